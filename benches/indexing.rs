@@ -0,0 +1,44 @@
+//! Indexing throughput on a synthetic corpus.
+//!
+//! The corpus size is `CORPUS=NxMxK` (projects × sessions × messages),
+//! 10x20x40 by default. Compare against a saved baseline with
+//! `cargo bench --bench indexing -- --baseline main`.
+
+use cc_sessions_viewer::corpus::{self, CorpusSpec};
+use cc_sessions_viewer::indexer;
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use tempfile::TempDir;
+
+fn spec() -> CorpusSpec {
+    let size = std::env::var("CORPUS").unwrap_or_else(|_| "10x20x40".to_string());
+    CorpusSpec::parse(&size, 42).expect("CORPUS must be NxMxK")
+}
+
+fn indexing(c: &mut Criterion) {
+    let spec = spec();
+    let tmp = TempDir::new().unwrap();
+    let projects_dir = tmp.path().join("projects");
+    let bytes = corpus::generate(&projects_dir, &spec).unwrap();
+
+    let mut group = c.benchmark_group("index");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(bytes));
+
+    group.bench_function("full", |b| {
+        b.iter_batched(
+            || TempDir::new().unwrap(),
+            |db| indexer::build_index(&db.path().join("index.db"), &projects_dir).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+
+    // 変更のないツリーの再索引は mtime の比較だけで済むはず
+    let db_path = tmp.path().join("index.db");
+    indexer::build_index(&db_path, &projects_dir).unwrap();
+    group.bench_function("unchanged", |b| b.iter(|| indexer::build_index(&db_path, &projects_dir).unwrap()));
+
+    group.finish();
+}
+
+criterion_group!(benches, indexing);
+criterion_main!(benches);
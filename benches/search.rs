@@ -0,0 +1,39 @@
+//! Index queries behind the session list and global search, on a
+//! synthetic corpus sized by `CORPUS=NxMxK` (10x20x40 by default).
+
+use cc_sessions_viewer::corpus::{self, CorpusSpec};
+use cc_sessions_viewer::index::SessionIndex;
+use cc_sessions_viewer::indexer;
+use criterion::{Criterion, criterion_group, criterion_main};
+use tempfile::TempDir;
+
+fn search(c: &mut Criterion) {
+    let size = std::env::var("CORPUS").unwrap_or_else(|_| "10x20x40".to_string());
+    let spec = CorpusSpec::parse(&size, 42).expect("CORPUS must be NxMxK");
+    let tmp = TempDir::new().unwrap();
+    let projects_dir = tmp.path().join("projects");
+    corpus::generate(&projects_dir, &spec).unwrap();
+    let db_path = tmp.path().join("index.db");
+    indexer::build_index(&db_path, &projects_dir).unwrap();
+    let index = SessionIndex::open(&db_path).unwrap();
+
+    let mut group = c.benchmark_group("search");
+    group.bench_function("all", |b| b.iter(|| index.search_all().unwrap()));
+    let project = corpus::project_dir_name(0);
+    group.bench_function("project", |b| b.iter(|| index.search_project(&project).unwrap()));
+    group.bench_function("rare_word", |b| {
+        b.iter(|| {
+            index
+                .search_all()
+                .unwrap()
+                .into_iter()
+                .filter(|s| s.prompts.iter().any(|p| p.contains(corpus::RARE_WORD)))
+                .count()
+        })
+    });
+    group.bench_function("file_history", |b| b.iter(|| index.sessions_touching("src/lib.rs", false).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
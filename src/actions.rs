@@ -0,0 +1,248 @@
+use crate::parser;
+use anyhow::Result;
+use serde_json::Value;
+use std::path::Path;
+
+/// What a session did, tallied from its tool calls, for the one-line summary
+/// at the top of the session detail.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionActions {
+    /// Files whose first change was a Write (new files).
+    pub files_created: usize,
+    /// Files changed by Edit/MultiEdit/NotebookEdit without being created first.
+    pub files_edited: usize,
+    /// Paths passed to `rm`/`git rm`/`unlink` in Bash commands.
+    pub files_deleted: usize,
+    pub commands: usize,
+    /// Bash commands that ran a test suite (a subset of `commands`).
+    pub test_runs: usize,
+    /// Distinct URLs fetched with WebFetch, in order of first fetch.
+    pub urls: Vec<String>,
+}
+
+/// Substrings of a shell command that mean it ran tests.
+const TEST_COMMANDS: &[&str] = &[
+    "cargo test",
+    "cargo nextest",
+    "npm test",
+    "npm run test",
+    "yarn test",
+    "pnpm test",
+    "bun test",
+    "deno test",
+    "pytest",
+    "go test",
+    "jest",
+    "vitest",
+    "rspec",
+    "mix test",
+    "make test",
+    "mvn test",
+    "gradle test",
+    "gradlew test",
+];
+
+impl SessionActions {
+    pub fn is_empty(&self) -> bool {
+        *self == SessionActions::default()
+    }
+
+    /// e.g. "2 created · 5 edited · 1 deleted · 12 commands (3 test runs) · 1 URL",
+    /// leaving out the kinds that did not happen.
+    pub fn summary(&self) -> String {
+        let plural = |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        let mut parts = Vec::new();
+        if self.files_created > 0 {
+            parts.push(format!("{} created", self.files_created));
+        }
+        if self.files_edited > 0 {
+            parts.push(format!("{} edited", self.files_edited));
+        }
+        if self.files_deleted > 0 {
+            parts.push(format!("{} deleted", self.files_deleted));
+        }
+        if self.commands > 0 {
+            let mut commands = plural(self.commands, "command", "commands");
+            if self.test_runs > 0 {
+                commands.push_str(&format!(" ({})", plural(self.test_runs, "test run", "test runs")));
+            }
+            parts.push(commands);
+        }
+        if !self.urls.is_empty() {
+            parts.push(plural(self.urls.len(), "URL", "URLs"));
+        }
+        parts.join(" · ")
+    }
+}
+
+pub fn is_test_command(command: &str) -> bool {
+    TEST_COMMANDS.iter().any(|t| command.contains(t))
+}
+
+/// Paths a shell command deletes: the non-flag arguments of every `rm`,
+/// `git rm` and `unlink` in it.
+pub fn deleted_paths(command: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    for part in command.split(['\n', ';', '|', '&']) {
+        let words: Vec<&str> = part.split_whitespace().collect();
+        let args = match words.as_slice() {
+            ["rm" | "unlink", rest @ ..] => rest,
+            ["git", "rm", rest @ ..] => rest,
+            _ => continue,
+        };
+        paths.extend(
+            args.iter()
+                .filter(|a| !a.starts_with('-'))
+                .map(|a| a.trim_matches(['"', '\'']).to_string()),
+        );
+    }
+    paths
+}
+
+/// Tally the tool calls in a session's JSONL content.
+pub fn summarize_actions(content: &str) -> SessionActions {
+    let mut actions = SessionActions::default();
+    let mut changed: Vec<String> = Vec::new();
+    let mut deleted: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let Ok(obj) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        if obj.get("type").and_then(Value::as_str) != Some("assistant") {
+            continue;
+        }
+        let Some(content) = obj.get("message").and_then(|m| m.get("content")) else {
+            continue;
+        };
+        for block in parser::extract_tool_blocks(content) {
+            let input = block.get("input");
+            let field = |key: &str| input.and_then(|i| i.get(key)).and_then(Value::as_str).unwrap_or("");
+            match block.get("name").and_then(Value::as_str).unwrap_or("") {
+                tool if parser::is_file_modifying_tool(tool) => {
+                    let path = match field("file_path") {
+                        "" => field("notebook_path"),
+                        path => path,
+                    };
+                    if path.is_empty() || changed.iter().any(|c| c == path) {
+                        continue;
+                    }
+                    changed.push(path.to_string());
+                    if tool == "Write" {
+                        actions.files_created += 1;
+                    } else {
+                        actions.files_edited += 1;
+                    }
+                }
+                "Bash" => {
+                    let command = field("command");
+                    actions.commands += 1;
+                    if is_test_command(command) {
+                        actions.test_runs += 1;
+                    }
+                    for path in deleted_paths(command) {
+                        if !deleted.contains(&path) {
+                            deleted.push(path);
+                        }
+                    }
+                }
+                "WebFetch" => {
+                    let url = field("url");
+                    if !url.is_empty() && !actions.urls.iter().any(|u| u == url) {
+                        actions.urls.push(url.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    actions.files_deleted = deleted.len();
+    actions
+}
+
+pub fn session_actions_in(project_name: &str, session_id: &str, projects_dir: &Path) -> Result<SessionActions> {
+    let jsonl_path = projects_dir
+        .join(project_name)
+        .join(format!("{}.jsonl", session_id));
+    if !jsonl_path.exists() {
+        return Ok(SessionActions::default());
+    }
+    Ok(summarize_actions(&parser::read_transcript(&jsonl_path)?.0))
+}
+
+pub fn session_actions(project_name: &str, session_id: &str) -> Result<SessionActions> {
+    match parser::session_root(project_name, session_id) {
+        Some(root) => session_actions_in(project_name, session_id, &root.projects_dir),
+        None => Ok(SessionActions::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn tool_line(name: &str, input: Value) -> String {
+        json!({
+            "type": "assistant",
+            "message": {"content": [{"type": "tool_use", "id": "t", "name": name, "input": input}]}
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn summarize_actions_counts_each_kind() {
+        let content = [
+            tool_line("Write", json!({"file_path": "/p/new.rs", "content": "x"})),
+            tool_line("Edit", json!({"file_path": "/p/new.rs", "old_string": "x", "new_string": "y"})),
+            tool_line("Edit", json!({"file_path": "/p/lib.rs", "old_string": "a", "new_string": "b"})),
+            tool_line("MultiEdit", json!({"file_path": "/p/lib.rs", "edits": []})),
+            tool_line("Read", json!({"file_path": "/p/main.rs"})),
+            tool_line("Bash", json!({"command": "cargo build && cargo test -q"})),
+            tool_line("Bash", json!({"command": "rm -f old.rs tmp.txt; git rm stale.rs"})),
+            tool_line("Bash", json!({"command": "rm old.rs"})),
+            tool_line("WebFetch", json!({"url": "https://docs.rs/x", "prompt": "?"})),
+            tool_line("WebFetch", json!({"url": "https://docs.rs/x", "prompt": "again"})),
+            "not json".to_string(),
+        ]
+        .join("\n");
+
+        let actions = summarize_actions(&content);
+        assert_eq!(
+            actions,
+            SessionActions {
+                files_created: 1,
+                files_edited: 1,
+                files_deleted: 3,
+                commands: 3,
+                test_runs: 1,
+                urls: vec!["https://docs.rs/x".to_string()],
+            }
+        );
+        assert_eq!(
+            actions.summary(),
+            "1 created · 1 edited · 3 deleted · 3 commands (1 test run) · 1 URL"
+        );
+        assert!(summarize_actions("").is_empty());
+    }
+
+    #[test]
+    fn deleted_paths_and_test_commands() {
+        assert_eq!(deleted_paths("cd src && rm -rf build 'dist'"), vec!["build", "dist"]);
+        assert!(deleted_paths("echo rm -rf /").is_empty());
+        assert!(deleted_paths("grep -r unlinked .").is_empty());
+        assert!(is_test_command("RUST_LOG=debug cargo test parser"));
+        assert!(is_test_command("npx vitest run"));
+        assert!(!is_test_command("cargo build --release"));
+    }
+
+    #[test]
+    fn session_actions_in_reads_the_session_file() {
+        let tmp = TempDir::new().unwrap();
+        fs::create_dir_all(tmp.path().join("-p")).unwrap();
+        fs::write(tmp.path().join("-p").join("s1.jsonl"), tool_line("Bash", json!({"command": "ls"}))).unwrap();
+        assert_eq!(session_actions_in("-p", "s1", tmp.path()).unwrap().commands, 1);
+        assert!(session_actions_in("-p", "missing", tmp.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,712 @@
+use crate::models::{LoopRegion, Message, MessageRole, Subagent, ThreadMark, Timeline, TimelineCall};
+use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::OnceLock;
+
+/// Number of consecutive near-identical tool calls that counts as a loop.
+pub const LOOP_MIN_REPEATS: usize = 4;
+
+/// Reduce a tool call summary to the parts that matter for repetition:
+/// case, digits (line numbers, counters, timestamps) and spacing are ignored.
+fn normalize_call(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last_space = false;
+    for ch in text.chars() {
+        if ch.is_ascii_digit() {
+            continue;
+        }
+        if ch.is_whitespace() {
+            if !last_space {
+                out.push(' ');
+            }
+            last_space = true;
+        } else {
+            out.extend(ch.to_lowercase());
+            last_space = false;
+        }
+    }
+    out.trim().to_string()
+}
+
+/// Find runs of near-identical tool calls, the classic stuck-agent pattern.
+///
+/// Only ToolUse messages are compared; assistant text and tool results in
+/// between do not break a run. A run of at least [`LOOP_MIN_REPEATS`] calls is
+/// reported as a region spanning the first to the last call.
+pub fn detect_loops(messages: &[Message]) -> Vec<LoopRegion> {
+    let mut regions = Vec::new();
+    // (first index, last index, normalized call, repeats)
+    let mut run: Option<(usize, usize, String, usize)> = None;
+
+    let close = |run: Option<(usize, usize, String, usize)>, regions: &mut Vec<LoopRegion>| {
+        if let Some((start, end, _, repeats)) = run
+            && repeats >= LOOP_MIN_REPEATS
+        {
+            regions.push(LoopRegion {
+                start,
+                end,
+                tool_name: messages[start].tool_name.clone().unwrap_or_default(),
+                repeats,
+            });
+        }
+    };
+
+    for (i, msg) in messages.iter().enumerate() {
+        if msg.role != MessageRole::ToolUse {
+            continue;
+        }
+        let key = normalize_call(&msg.text);
+        match &mut run {
+            Some((_, end, current, repeats)) if *current == key => {
+                *end = i;
+                *repeats += 1;
+            }
+            _ => {
+                close(run.take(), &mut regions);
+                run = Some((i, i, key, 1));
+            }
+        }
+    }
+    close(run, &mut regions);
+    regions
+}
+
+/// Fold resumed sessions into one entry per conversation.
+///
+/// `items` are (session id, parent session id or "") in display order. Each
+/// session is grouped under the root of its resume chain, following parents
+/// through the listed sessions; the first item of every group is kept.
+/// Returns the kept positions with how many later items were folded into each.
+pub fn collapse_continuations(items: &[(&str, &str)]) -> Vec<(usize, usize)> {
+    let parents: HashMap<&str, &str> = items.iter().copied().collect();
+    let mut kept: Vec<(usize, usize)> = Vec::new();
+    let mut group_of_root: HashMap<&str, usize> = HashMap::new();
+    for (i, (id, _)) in items.iter().enumerate() {
+        let root = chain_root(&parents, id);
+        match group_of_root.get(root) {
+            Some(&k) => kept[k].1 += 1,
+            None => {
+                group_of_root.insert(root, kept.len());
+                kept.push((i, 0));
+            }
+        }
+    }
+    kept
+}
+
+/// Follow parent ids from `id` to the start of its resume chain. The chain
+/// may end at a parent that is not listed; a cycle is rooted at its smallest id.
+fn chain_root<'a>(parents: &HashMap<&'a str, &'a str>, id: &'a str) -> &'a str {
+    let mut chain = vec![id];
+    let mut current = id;
+    while let Some(&parent) = parents.get(current).filter(|p| !p.is_empty()) {
+        if let Some(start) = chain.iter().position(|&c| c == parent) {
+            return chain[start..].iter().copied().min().unwrap_or(parent);
+        }
+        current = parent;
+        chain.push(current);
+    }
+    current
+}
+
+/// FNV-1a hash of `key`, the same across runs and builds (unlike `std`'s hasher).
+pub fn stable_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Stable slot in `0..slots` for `key`, so a project keeps its accent
+/// color across runs and builds.
+pub fn accent_slot(key: &str, slots: usize) -> usize {
+    (stable_hash(key) % slots.max(1) as u64) as usize
+}
+
+/// Distinct non-empty versions, newest first. Dotted versions compare
+/// numerically part by part ("2.0.10" is newer than "2.0.9"), and a
+/// release sorts before its pre-releases.
+pub fn distinct_versions<'a>(versions: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let key = |v: &str| -> Vec<u64> {
+        v.split('.')
+            .map(|part| {
+                let digits = part.find(|c: char| !c.is_ascii_digit()).unwrap_or(part.len());
+                part[..digits].parse().unwrap_or(0)
+            })
+            .collect()
+    };
+    let mut out: Vec<String> = Vec::new();
+    for v in versions {
+        if !v.is_empty() && !out.iter().any(|o| o == v) {
+            out.push(v.to_string());
+        }
+    }
+    out.sort_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.cmp(b)));
+    out
+}
+
+/// Squeeze a transcript of `total` rows into `height` minimap rows. Each
+/// `(start_row, label)` segment runs until the next one starts; a minimap row
+/// gets the label covering most of its share of the transcript (`None` for
+/// an empty transcript). Short transcripts are stretched to the full height.
+pub fn minimap<T: Clone + PartialEq>(segments: &[(usize, T)], total: usize, height: usize) -> Vec<Option<T>> {
+    let mut out = Vec::with_capacity(height);
+    let mut seg = 0;
+    for k in 0..height {
+        let lo = k * total / height;
+        if lo >= total {
+            out.push(None);
+            continue;
+        }
+        let hi = ((k + 1) * total / height).max(lo + 1);
+        // lo を含むセグメントまで進める
+        while seg + 1 < segments.len() && segments[seg + 1].0 <= lo {
+            seg += 1;
+        }
+        let mut weights: Vec<(T, usize)> = Vec::new();
+        let mut i = seg;
+        while i < segments.len() && segments[i].0 < hi {
+            let end = segments.get(i + 1).map_or(total, |s| s.0);
+            let overlap = end.min(hi).saturating_sub(segments[i].0.max(lo));
+            if overlap > 0 {
+                match weights.iter_mut().find(|(label, _)| *label == segments[i].1) {
+                    Some((_, w)) => *w += overlap,
+                    None => weights.push((segments[i].1.clone(), overlap)),
+                }
+            }
+            i += 1;
+        }
+        // 同じ重みなら先に現れたもの
+        let best = weights
+            .into_iter()
+            .fold(None, |best: Option<(T, usize)>, (label, w)| match best {
+                Some((_, bw)) if bw >= w => best,
+                _ => Some((label, w)),
+            });
+        out.push(best.map(|(label, _)| label));
+    }
+    out
+}
+
+/// Paths of the instruction and settings files (`CLAUDE.md`,
+/// `CLAUDE.local.md`, `.claude/settings.json`, `.claude/settings.local.json`)
+/// a session refers to, in order of first mention. They are taken from the
+/// context Claude Code injects (system messages and the reminders in user
+/// messages) and from tool calls that read or edited them.
+pub fn instruction_files(messages: &[Message]) -> Vec<String> {
+    static PATH: OnceLock<Regex> = OnceLock::new();
+    let path = PATH.get_or_init(|| {
+        Regex::new(r#"(?:~|/)[^\s"'`()<>\[\]{},;]*/(?:CLAUDE(?:\.local)?\.md|\.claude/settings(?:\.local)?\.json)\b"#)
+            .expect("valid regex")
+    });
+    let mut found: Vec<String> = Vec::new();
+    for msg in messages {
+        let text = match msg.role {
+            MessageRole::System | MessageRole::User => msg.text.as_str(),
+            MessageRole::ToolUse => match msg.tool_input.as_ref().and_then(|i| i.get("file_path")) {
+                Some(serde_json::Value::String(file)) => file.as_str(),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        for m in path.find_iter(text) {
+            if !found.iter().any(|f| f == m.as_str()) {
+                found.push(m.as_str().to_string());
+            }
+        }
+    }
+    found
+}
+
+/// The place of each message in the conversation tree: messages sharing a
+/// `uuid` are one entry, and an entry with more than one child entry is a
+/// fork whose branches are indented one level deeper. Transcripts are
+/// append-only, so a branch already follows the one it replaced in file
+/// order and the messages keep their order. Messages without a `uuid`
+/// continue the entry before them.
+pub fn thread_marks(messages: &[Message]) -> Vec<ThreadMark> {
+    // 子エントリの uuid（ファイル順、重複なし）
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut seen = HashSet::new();
+    for msg in messages {
+        let Some(uuid) = msg.uuid.as_deref() else { continue };
+        if !seen.insert(uuid) {
+            continue;
+        }
+        if let Some(parent) = msg.parent_uuid.as_deref() {
+            children.entry(parent).or_default().push(uuid);
+        }
+    }
+    let mut depths: HashMap<&str, usize> = HashMap::new();
+    let mut marks = Vec::with_capacity(messages.len());
+    let mut last = ThreadMark::default();
+    for msg in messages {
+        let Some(uuid) = msg.uuid.as_deref() else {
+            marks.push(ThreadMark { branch: None, ..last });
+            continue;
+        };
+        if let Some(&depth) = depths.get(uuid) {
+            last = ThreadMark { depth, branch: None };
+            marks.push(last);
+            continue;
+        }
+        let parent = msg.parent_uuid.as_deref();
+        let siblings = parent.and_then(|p| children.get(p)).map_or(&[][..], Vec::as_slice);
+        let parent_depth = parent.and_then(|p| depths.get(p)).copied().unwrap_or(0);
+        last = if siblings.len() > 1 {
+            let k = siblings.iter().position(|&s| s == uuid).unwrap_or(0);
+            ThreadMark { depth: parent_depth + 1, branch: Some((k + 1, siblings.len())) }
+        } else {
+            ThreadMark { depth: parent_depth, branch: None }
+        };
+        depths.insert(uuid, last.depth);
+        marks.push(last);
+    }
+    marks
+}
+
+/// Tools that run a subagent.
+const SUBAGENT_TOOLS: [&str; 2] = ["Task", "Agent"];
+
+/// Group sidechain messages into one conversation per root entry and match
+/// each to the Task/Agent call in `main` whose prompt it starts with. Those
+/// left over are matched to the remaining calls in order.
+pub fn subagents(main: &[Message], sidechain: Vec<Message>) -> Vec<Subagent> {
+    let ids: HashSet<&str> = sidechain.iter().filter_map(|m| m.uuid.as_deref()).collect();
+    let parents: HashMap<&str, &str> = sidechain
+        .iter()
+        .filter_map(|m| Some((m.uuid.as_deref()?, m.parent_uuid.as_deref()?)))
+        .collect();
+    let root_of = |uuid: &str| {
+        let mut root = uuid;
+        // 循環していても止まるよう、たどる回数はエントリの数まで
+        for _ in 0..ids.len() {
+            match parents.get(root) {
+                Some(parent) if ids.contains(parent) => root = parent,
+                _ => break,
+            }
+        }
+        root.to_string()
+    };
+    let mut groups: Vec<(String, Vec<Message>)> = Vec::new();
+    for msg in sidechain.iter() {
+        let root = match msg.uuid.as_deref() {
+            Some(uuid) => root_of(uuid),
+            None => groups.last().map(|(root, _)| root.clone()).unwrap_or_default(),
+        };
+        match groups.iter_mut().find(|(r, _)| *r == root) {
+            Some((_, messages)) => messages.push(msg.clone()),
+            None => groups.push((root, vec![msg.clone()])),
+        }
+    }
+
+    let calls: Vec<usize> = (0..main.len())
+        .filter(|&i| {
+            main[i].role == MessageRole::ToolUse
+                && main[i].tool_name.as_deref().is_some_and(|name| SUBAGENT_TOOLS.contains(&name))
+        })
+        .collect();
+    let input = |i: usize, key: &str| {
+        main[i].tool_input.as_ref().and_then(|input| input.get(key)).and_then(|v| v.as_str()).unwrap_or("").trim()
+    };
+    let mut taken = vec![false; calls.len()];
+    let mut agents: Vec<Subagent> = groups
+        .into_iter()
+        .map(|(_, messages)| {
+            let prompt = messages.iter().find(|m| m.role == MessageRole::User).map_or("", |m| m.text.trim());
+            let found = calls.iter().enumerate().position(|(k, &i)| !taken[k] && !prompt.is_empty() && input(i, "prompt") == prompt);
+            if let Some(k) = found {
+                taken[k] = true;
+            }
+            Subagent {
+                spawned_by: found.map(|k| calls[k]),
+                description: prompt.lines().next().unwrap_or("").to_string(),
+                messages,
+            }
+        })
+        .collect();
+    for agent in agents.iter_mut().filter(|a| a.spawned_by.is_none()) {
+        if let Some(k) = taken.iter().position(|t| !t) {
+            taken[k] = true;
+            agent.spawned_by = Some(calls[k]);
+        }
+    }
+    for agent in &mut agents {
+        if let Some(i) = agent.spawned_by
+            && !input(i, "description").is_empty()
+        {
+            agent.description = input(i, "description").to_string();
+        }
+    }
+    agents
+}
+
+/// For each tool result, the index of the call it answers. Results carry
+/// no tool name and, once parsed, no `tool_use_id`, but they come back in
+/// the order the calls were made, so they are paired first come first
+/// served with the calls still open. A user prompt drops calls that never
+/// got a result (an interrupted turn).
+pub fn result_calls(messages: &[Message]) -> Vec<Option<usize>> {
+    let mut open = VecDeque::new();
+    messages
+        .iter()
+        .enumerate()
+        .map(|(i, msg)| match msg.role {
+            MessageRole::ToolUse => {
+                open.push_back(i);
+                None
+            }
+            MessageRole::ToolResult => open.pop_front(),
+            MessageRole::User => {
+                open.clear();
+                None
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The prompts and tool calls of a session on a time axis, each call
+/// lasting until its result (see [`result_calls`]). None without timestamps.
+pub fn timeline(messages: &[Message], result_calls: &[Option<usize>]) -> Option<Timeline> {
+    let times = messages.iter().filter_map(|m| m.timestamp);
+    let (start, end) = (times.clone().min()?, times.max()?);
+    let mut ends: HashMap<usize, _> = HashMap::new();
+    for (i, call) in result_calls.iter().enumerate() {
+        if let (Some(call), Some(at)) = (call, messages[i].timestamp) {
+            ends.insert(*call, at);
+        }
+    }
+    let prompts = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.role == MessageRole::User)
+        .filter_map(|(i, m)| Some((i, m.timestamp?)))
+        .collect();
+    let calls = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.role == MessageRole::ToolUse)
+        .filter_map(|(i, m)| {
+            let at = m.timestamp?;
+            Some(TimelineCall {
+                message: i,
+                tool: m.tool_name.clone().unwrap_or_default(),
+                start: at,
+                end: ends.get(&i).copied().unwrap_or(at).max(at),
+            })
+        })
+        .collect();
+    Some(Timeline { start, end, prompts, calls })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(text: &str) -> Message {
+        Message {
+            role: MessageRole::ToolUse,
+            text: text.to_string(),
+            timestamp: None,
+            tool_name: text
+                .strip_prefix('[')
+                .and_then(|t| t.split(']').next())
+                .map(String::from),
+            tool_input: None,
+            model: None,
+            usage: None,
+            source_line: None,
+            uuid: None,
+            parent_uuid: None,
+            sidechain: false,
+        }
+    }
+
+    fn result() -> Message {
+        result_as(MessageRole::ToolResult)
+    }
+
+    fn result_as(role: MessageRole) -> Message {
+        Message {
+            role,
+            text: "error".to_string(),
+            timestamp: None,
+            tool_name: None,
+            tool_input: None,
+            model: None,
+            usage: None,
+            source_line: None,
+            uuid: None,
+            parent_uuid: None,
+            sidechain: false,
+        }
+    }
+
+    #[test]
+    fn normalize_call_ignores_digits_case_and_spacing() {
+        assert_eq!(
+            normalize_call("[Bash]  cargo test --test  it_42"),
+            normalize_call("[bash] cargo test --test it_7")
+        );
+    }
+
+    #[test]
+    fn detect_loops_finds_repeated_calls() {
+        let mut messages = Vec::new();
+        messages.push(tool("[Read] /src/lib.rs"));
+        messages.push(result());
+        for i in 0..5 {
+            messages.push(tool(&format!("[Bash] cargo test attempt {}", i)));
+            messages.push(result());
+        }
+        messages.push(tool("[Edit] /src/lib.rs"));
+
+        let regions = detect_loops(&messages);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].start, 2);
+        assert_eq!(regions[0].end, 10);
+        assert_eq!(regions[0].repeats, 5);
+        assert_eq!(regions[0].tool_name, "Bash");
+    }
+
+    #[test]
+    fn detect_loops_ignores_short_runs() {
+        let messages: Vec<Message> = (0..LOOP_MIN_REPEATS - 1)
+            .map(|_| tool("[Bash] ls"))
+            .chain(std::iter::once(tool("[Read] /a")))
+            .collect();
+        assert!(detect_loops(&messages).is_empty());
+    }
+
+    #[test]
+    fn detect_loops_empty() {
+        assert!(detect_loops(&[]).is_empty());
+    }
+
+    #[test]
+    fn collapse_continuations_keeps_newest_of_each_chain() {
+        // 新しい順: c は b の、b は a の再開。e は一覧にない x の再開
+        let items = [
+            ("c", "b"),
+            ("d", ""),
+            ("b", "a"),
+            ("e", "x"),
+            ("a", ""),
+            ("f", "x"),
+        ];
+        assert_eq!(collapse_continuations(&items), vec![(0, 2), (1, 0), (3, 1)]);
+    }
+
+    #[test]
+    fn collapse_continuations_survives_parent_cycles() {
+        let items = [("a", "b"), ("b", "a")];
+        assert_eq!(collapse_continuations(&items).len(), 1);
+        assert!(collapse_continuations(&[]).is_empty());
+    }
+
+    #[test]
+    fn accent_slot_is_stable_and_in_range() {
+        assert_eq!(accent_slot("/home/me/api", 11), accent_slot("/home/me/api", 11));
+        assert_eq!(accent_slot("", 11), 0xcbf2_9ce4_8422_2325u64 as usize % 11);
+        let slots: std::collections::HashSet<usize> =
+            (0..50).map(|i| accent_slot(&format!("/p/{}", i), 11)).collect();
+        assert!(slots.iter().all(|&s| s < 11));
+        assert!(slots.len() > 5);
+        assert_eq!(accent_slot("/p", 0), 0);
+    }
+
+    #[test]
+    fn distinct_versions_sorts_newest_first() {
+        let versions = ["2.0.9", "", "1.0.128", "2.0.10", "2.0.9", "2.0.10-beta"];
+        assert_eq!(
+            distinct_versions(versions),
+            vec!["2.0.10", "2.0.10-beta", "2.0.9", "1.0.128"]
+        );
+        assert!(distinct_versions([""]).is_empty());
+    }
+
+    #[test]
+    fn minimap_picks_dominant_label_per_row() {
+        // a: 0-5, b: 6-7, c: 8-19
+        let segments = [(0, 'a'), (6, 'b'), (8, 'c')];
+        assert_eq!(
+            minimap(&segments, 20, 5),
+            vec![Some('a'), Some('a'), Some('c'), Some('c'), Some('c')]
+        );
+        // 行数より高い地図は引き伸ばす
+        assert_eq!(minimap(&[(0, 'a'), (1, 'b')], 2, 3), vec![Some('a'), Some('a'), Some('b')]);
+        // 長さ0のセグメント（非表示のメッセージ）は無視する
+        assert_eq!(minimap(&[(0, 'x'), (0, 'a'), (4, 'b')], 8, 2), vec![Some('a'), Some('b')]);
+        assert_eq!(minimap::<char>(&[], 0, 2), vec![None, None]);
+    }
+
+    #[test]
+    fn thread_marks_indent_the_branches_of_a_fork() {
+        let entry = |role, uuid: &str, parent: Option<&str>| Message {
+            uuid: Some(uuid.to_string()),
+            parent_uuid: parent.map(String::from),
+            ..result_as(role)
+        };
+        // u1 → a1 → (u2 → a2) と、u2 を編集した (u3 → a3, a3 のツール呼び出し)
+        let messages = vec![
+            entry(MessageRole::User, "u1", None),
+            entry(MessageRole::Assistant, "a1", Some("u1")),
+            entry(MessageRole::User, "u2", Some("a1")),
+            entry(MessageRole::Assistant, "a2", Some("u2")),
+            entry(MessageRole::User, "u3", Some("a1")),
+            entry(MessageRole::Assistant, "a3", Some("u3")),
+            entry(MessageRole::ToolUse, "a3", Some("u3")),
+            Message { uuid: None, ..result() },
+        ];
+        let marks: Vec<(usize, Option<(usize, usize)>)> =
+            thread_marks(&messages).iter().map(|m| (m.depth, m.branch)).collect();
+        assert_eq!(
+            marks,
+            vec![
+                (0, None),
+                (0, None),
+                (1, Some((1, 2))),
+                (1, None),
+                (1, Some((2, 2))),
+                (1, None),
+                (1, None),
+                (1, None),
+            ]
+        );
+        // 分岐の無い会話は字下げしない
+        assert!(thread_marks(&messages[..4]).iter().all(|m| m.depth == 0 && m.branch.is_none()));
+    }
+
+    #[test]
+    fn result_calls_pair_results_with_open_calls_in_order() {
+        let call = |name: &str| Message { tool_name: Some(name.to_string()), ..result_as(MessageRole::ToolUse) };
+        let messages = vec![
+            result_as(MessageRole::User),
+            call("Read"),
+            call("Grep"),
+            result_as(MessageRole::ToolResult),
+            result_as(MessageRole::ToolResult),
+            result_as(MessageRole::Assistant),
+            call("Bash"),
+            // 結果の無いまま中断され、次のプロンプトに移った
+            result_as(MessageRole::User),
+            call("Edit"),
+            result_as(MessageRole::ToolResult),
+            result_as(MessageRole::ToolResult),
+        ];
+        assert_eq!(
+            result_calls(&messages),
+            vec![None, None, None, Some(1), Some(2), None, None, None, None, Some(8), None]
+        );
+    }
+
+    #[test]
+    fn timeline_spans_calls_until_their_results() {
+        let at = |secs: i64| Some(chrono::DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap());
+        let timed = |role, secs: i64| Message { timestamp: at(secs), ..result_as(role) };
+        let call = |name: &str, secs: i64| Message { tool_name: Some(name.to_string()), ..timed(MessageRole::ToolUse, secs) };
+        let messages = vec![
+            timed(MessageRole::User, 0),
+            call("Bash", 10),
+            timed(MessageRole::ToolResult, 70),
+            call("Read", 75),
+            timed(MessageRole::ToolResult, 76),
+            call("Bash", 80),
+            timed(MessageRole::User, 100),
+            timed(MessageRole::Assistant, 120),
+        ];
+        let timeline = timeline(&messages, &result_calls(&messages)).unwrap();
+        assert_eq!((timeline.secs(), timeline.prompts.len()), (120, 2));
+        let spans: Vec<(&str, i64)> = timeline.calls.iter().map(|c| (c.tool.as_str(), c.secs())).collect();
+        // 結果の無い呼び出しは長さ0
+        assert_eq!(spans, vec![("Bash", 60), ("Read", 1), ("Bash", 0)]);
+        assert_eq!(timeline.tool_totals(), vec![("Bash".to_string(), 60, 2), ("Read".to_string(), 1, 1)]);
+        assert_eq!(timeline.column(timeline.start, 61), 0);
+        assert_eq!(timeline.column(at(60).unwrap(), 61), 30);
+        assert_eq!(timeline.column(timeline.end, 61), 60);
+
+        assert!(super::timeline(&[result_as(MessageRole::User)], &[None]).is_none());
+    }
+
+    #[test]
+    fn subagents_are_grouped_by_root_and_matched_to_their_calls() {
+        let task = |prompt: &str, description: &str| Message {
+            tool_name: Some("Task".to_string()),
+            tool_input: Some(serde_json::json!({"prompt": prompt, "description": description})),
+            ..result_as(MessageRole::ToolUse)
+        };
+        let entry = |role, text: &str, uuid: &str, parent: Option<&str>| Message {
+            text: text.to_string(),
+            uuid: Some(uuid.to_string()),
+            parent_uuid: parent.map(String::from),
+            sidechain: true,
+            ..result_as(role)
+        };
+        let main = vec![
+            result_as(MessageRole::User),
+            task("find the callers", "Find callers"),
+            task("summarize the docs\nbriefly", ""),
+        ];
+        // 2つの会話が交互に書かれている。2つ目の指示文はどの呼び出しとも一致しない
+        let sidechain = vec![
+            entry(MessageRole::User, "something else", "x1", None),
+            entry(MessageRole::User, "find the callers", "f1", None),
+            entry(MessageRole::Assistant, "searching", "f2", Some("f1")),
+            entry(MessageRole::Assistant, "done else", "x2", Some("x1")),
+            entry(MessageRole::Assistant, "3 callers", "f3", Some("f2")),
+        ];
+        let agents = subagents(&main, sidechain);
+        assert_eq!(agents.len(), 2);
+        let texts = |k: usize| agents[k].messages.iter().map(|m| m.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts(0), vec!["something else", "done else"]);
+        assert_eq!(texts(1), vec!["find the callers", "searching", "3 callers"]);
+        // 指示文が一致した呼び出しに付け、残りは順に割り当てる
+        assert_eq!((agents[1].spawned_by, agents[1].description.as_str()), (Some(1), "Find callers"));
+        assert_eq!((agents[0].spawned_by, agents[0].description.as_str()), (Some(2), "something else"));
+
+        assert!(subagents(&main, Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn instruction_files_are_found_in_injected_context_and_tool_calls() {
+        let message = |role, text: &str| Message {
+            role,
+            text: text.to_string(),
+            timestamp: None,
+            tool_name: None,
+            tool_input: None,
+            model: None,
+            usage: None,
+            source_line: None,
+            uuid: None,
+            parent_uuid: None,
+            sidechain: false,
+        };
+        let messages = vec![
+            message(
+                MessageRole::User,
+                "<system-reminder>Contents of /home/me/app/CLAUDE.md (project instructions):\n...\n\
+                 Contents of ~/.claude/CLAUDE.md (user's private global instructions)</system-reminder>",
+            ),
+            message(MessageRole::Assistant, "I will follow /home/me/app/CLAUDE.local.md"),
+            message(MessageRole::System, "Loaded /home/me/app/.claude/settings.local.json; see /home/me/app/CLAUDE.md"),
+            Message {
+                tool_input: Some(serde_json::json!({"file_path": "/home/me/app/.claude/settings.json"})),
+                ..message(MessageRole::ToolUse, "[Edit] /home/me/app/.claude/settings.json")
+            },
+            Message {
+                tool_input: Some(serde_json::json!({"file_path": "/home/me/app/src/settings.json"})),
+                ..message(MessageRole::ToolUse, "[Read] /home/me/app/src/settings.json")
+            },
+        ];
+        assert_eq!(
+            instruction_files(&messages),
+            vec![
+                "/home/me/app/CLAUDE.md",
+                "~/.claude/CLAUDE.md",
+                "/home/me/app/.claude/settings.local.json",
+                "/home/me/app/.claude/settings.json",
+            ]
+        );
+    }
+}
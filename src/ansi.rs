@@ -0,0 +1,195 @@
+//! ANSI escape sequences in tool output (test runners, `git diff --color`),
+//! turned into styles for the detail view or stripped.
+//!
+//! Only SGR (`ESC [ ... m`) carries styling; other CSI and OSC sequences
+//! (cursor movement, window titles, hyperlinks) are dropped.
+
+use ratatui::style::{Color, Modifier, Style};
+
+/// Text and the style of each of its chars, one entry per line.
+pub type StyledLine = (String, Vec<Style>);
+
+/// Split `text` into lines with the escape sequences removed and their SGR
+/// styling applied over `base`. Styling carries over to the next line until
+/// it is reset, as in a terminal.
+pub fn styled_lines(text: &str, base: Style) -> Vec<StyledLine> {
+    let mut style = base;
+    text.lines()
+        .map(|line| {
+            let mut out = String::with_capacity(line.len());
+            let mut styles = Vec::with_capacity(line.len());
+            for piece in pieces(line) {
+                match piece {
+                    Piece::Text(s) => {
+                        out.push_str(s);
+                        styles.extend(std::iter::repeat_n(style, s.chars().count()));
+                    }
+                    Piece::Sgr(params) => style = apply_sgr(style, base, params),
+                    Piece::Other => {}
+                }
+            }
+            (out, styles)
+        })
+        .collect()
+}
+
+/// `text` without any escape sequences.
+pub fn strip(text: &str) -> String {
+    if !text.contains('\x1b') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        for piece in pieces(line) {
+            if let Piece::Text(s) = piece {
+                out.push_str(s);
+            }
+        }
+    }
+    out
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    /// Parameters of an SGR sequence, e.g. `1;31`.
+    Sgr(&'a str),
+    Other,
+}
+
+/// Cut `line` into plain text and escape sequences.
+fn pieces(line: &str) -> Vec<Piece<'_>> {
+    let mut out = Vec::new();
+    let mut rest = line;
+    while let Some(pos) = rest.find('\x1b') {
+        if pos > 0 {
+            out.push(Piece::Text(&rest[..pos]));
+        }
+        let after = &rest[pos + 1..];
+        let (piece, len) = if let Some(csi) = after.strip_prefix('[') {
+            // パラメータと中間バイトの後の終端バイト (0x40-0x7e) まで
+            match csi.find(|c: char| ('\x40'..='\x7e').contains(&c)) {
+                Some(end) if csi[end..].starts_with('m') => (Piece::Sgr(&csi[..end]), 1 + end + 1),
+                Some(end) => (Piece::Other, 1 + end + 1),
+                None => (Piece::Other, after.len()),
+            }
+        } else if let Some(osc) = after.strip_prefix(']') {
+            // BEL か ESC \ で終わる
+            match osc.find(['\x07', '\x1b']) {
+                Some(end) if osc[end..].starts_with('\x1b') => (Piece::Other, 1 + end + 2.min(osc.len() - end)),
+                Some(end) => (Piece::Other, 1 + end + 1),
+                None => (Piece::Other, after.len()),
+            }
+        } else {
+            // ESC の後の1文字だけの列 (ESC =, ESC 7 など)
+            (Piece::Other, after.chars().next().map_or(0, char::len_utf8))
+        };
+        out.push(piece);
+        rest = &after[len.min(after.len())..];
+    }
+    if !rest.is_empty() {
+        out.push(Piece::Text(rest));
+    }
+    out
+}
+
+fn apply_sgr(mut style: Style, base: Style, params: &str) -> Style {
+    let codes: Vec<u16> = params.split([';', ':']).map(|p| p.parse().unwrap_or(0)).collect();
+    let mut i = 0;
+    while i < codes.len() {
+        match codes[i] {
+            0 => style = base,
+            1 => style = style.add_modifier(Modifier::BOLD),
+            2 => style = style.add_modifier(Modifier::DIM),
+            3 => style = style.add_modifier(Modifier::ITALIC),
+            4 => style = style.add_modifier(Modifier::UNDERLINED),
+            7 => style = style.add_modifier(Modifier::REVERSED),
+            9 => style = style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style = style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style = style.remove_modifier(Modifier::ITALIC),
+            24 => style = style.remove_modifier(Modifier::UNDERLINED),
+            27 => style = style.remove_modifier(Modifier::REVERSED),
+            29 => style = style.remove_modifier(Modifier::CROSSED_OUT),
+            n @ 30..=37 => style = style.fg(basic_color(n - 30)),
+            n @ 90..=97 => style = style.fg(basic_color(n - 90 + 8)),
+            n @ 40..=47 => style = style.bg(basic_color(n - 40)),
+            n @ 100..=107 => style = style.bg(basic_color(n - 100 + 8)),
+            39 => style.fg = base.fg,
+            49 => style.bg = base.bg,
+            n @ (38 | 48) => {
+                let (color, used) = extended_color(&codes[i + 1..]);
+                if let Some(color) = color {
+                    style = if n == 38 { style.fg(color) } else { style.bg(color) };
+                }
+                i += used;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    style
+}
+
+/// `5;n` or `2;r;g;b` after a 38/48, and how many codes it took.
+fn extended_color(codes: &[u16]) -> (Option<Color>, usize) {
+    match codes {
+        [5, n, ..] => (Some(Color::Indexed(*n as u8)), 2),
+        [2, r, g, b, ..] => (Some(Color::Rgb(*r as u8, *g as u8, *b as u8)), 4),
+        _ => (None, codes.len()),
+    }
+}
+
+fn basic_color(n: u16) -> Color {
+    match n {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::Gray,
+        8 => Color::DarkGray,
+        9 => Color::LightRed,
+        10 => Color::LightGreen,
+        11 => Color::LightYellow,
+        12 => Color::LightBlue,
+        13 => Color::LightMagenta,
+        14 => Color::LightCyan,
+        _ => Color::White,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styled_lines_apply_sgr_and_carry_across_lines() {
+        let base = Style::default().fg(Color::DarkGray);
+        let lines = styled_lines("\x1b[1;32mok\x1b[0m done\n\x1b[31mFAIL\nstill red\x1b[39m", base);
+        let texts: Vec<&str> = lines.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(texts, vec!["ok done", "FAIL", "still red"]);
+        assert_eq!(lines[0].1[0], Style::default().fg(Color::Green).add_modifier(Modifier::BOLD));
+        assert_eq!(lines[0].1[2], base);
+        assert_eq!(lines[1].1[0].fg, Some(Color::Red));
+        assert_eq!(lines[2].1[0].fg, Some(Color::Red));
+    }
+
+    #[test]
+    fn extended_colors_and_non_sgr_sequences() {
+        let base = Style::default();
+        let lines = styled_lines("\x1b[38;5;208mA\x1b[48;2;1;2;3mB\x1b[2K\x1b]0;title\x07C", base);
+        assert_eq!(lines[0].0, "ABC");
+        assert_eq!(lines[0].1[0].fg, Some(Color::Indexed(208)));
+        assert_eq!(lines[0].1[1].bg, Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(lines[0].1[2], lines[0].1[1]);
+    }
+
+    #[test]
+    fn strip_removes_every_sequence() {
+        assert_eq!(strip("\x1b[32m✓\x1b[0m passed\n\x1b]8;;http://x\x1b\\link\x1b]8;;\x1b\\\n"), "✓ passed\nlink\n");
+        assert_eq!(strip("plain"), "plain");
+        // 途中で切れた列も残さない
+        assert_eq!(strip("cut \x1b[3"), "cut ");
+    }
+}
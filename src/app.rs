@@ -1,5 +1,22 @@
+use crate::actions::{self, SessionActions};
+use crate::analysis;
+use crate::anonymize::Anonymizer;
+use crate::audit;
+use crate::changes;
+use crate::cleanup::{self, Decision, Review};
+use crate::clipboard::{self, Clipboard, ClipboardKind, Environment, Osc52Clipboard};
+use crate::commands::{self, CommandContext};
+use crate::config::Config;
+use crate::digest;
+use crate::index::{Bookmark, IndexChange, JournalEntry, ProjectActivity, SearchableSession, SessionIndex};
+use crate::indexer::{self, IndexOptions, IndexProgress};
+use crate::list::ListState;
+use crate::markdown::{self, CodeBlock};
 use crate::models::*;
 use crate::parser;
+use crate::plugins::{self, Hook};
+use crate::scripting::ScriptSet;
+use crate::snapshots;
 use crate::ui;
 
 use anyhow::Result;
@@ -7,12 +24,21 @@ use chrono::Utc;
 use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton,
+        MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Screen {
@@ -20,6 +46,63 @@ pub enum Screen {
     SessionList,
     SessionDetail,
     GlobalSearch,
+    PermissionAudit,
+    SessionChanges,
+    FileSnapshots,
+    SnapshotView,
+    CleanupReview,
+    IndexActivity,
+}
+
+/// SessionDetail で同時にハイライトできる語の数（色の数）
+pub const MAX_HIGHLIGHT_TERMS: usize = 6;
+
+/// プロジェクトの活動パネルに出すファイルとコマンドの件数
+const PROJECT_ACTIVITY_TOP: usize = 8;
+
+/// 索引の変更履歴画面に出す件数
+const INDEX_ACTIVITY_LIMIT: usize = 500;
+/// ブックマークのメモの初期値にするメッセージの先頭の文字数
+const BOOKMARK_NOTE_PREFILL: usize = 60;
+
+/// apply-retention の記録は最新のこの件数だけ Index Activity に出す
+const JOURNAL_PANEL_ENTRIES: usize = 8;
+
+/// SessionDetail で 1-5 キーで表示を切り替えるロールと、パンくずに出す名前
+pub const ROLE_TOGGLES: [(&str, &[MessageRole]); 5] = [
+    ("user", &[MessageRole::User]),
+    ("assistant", &[MessageRole::Assistant]),
+    ("tool calls", &[MessageRole::ToolUse]),
+    ("tool results", &[MessageRole::ToolResult]),
+    ("system", &[MessageRole::System, MessageRole::Progress]),
+];
+
+/// バックグラウンドのインデックス構築スレッドからの通知
+pub enum IndexEvent {
+    /// 1プロジェクト分の索引が終わった（そのプロジェクトの検索対象を添える）
+    Progress(IndexProgress, Vec<SearchableSession>),
+    /// 構築完了。実行結果のまとめと索引済みのプロジェクト一覧を添える
+    Done(Result<(IndexProgress, Vec<ProjectInfo>), String>),
+}
+
+/// E で公開する前に確認しているもの
+pub struct PendingShare {
+    /// `12 prompts`、`3 lines` など
+    pub what: String,
+    pub text: String,
+    /// 匿名化してから送る
+    pub anonymized: bool,
+}
+
+/// サブエージェントの会話を開く前の本体の会話。閉じたらここに戻る
+pub struct MainConversation {
+    pub messages: Vec<Message>,
+    pub subagents: Vec<Subagent>,
+    pub bookmarked: HashSet<usize>,
+    pub selected_message: usize,
+    pub scroll_offset: usize,
+    /// 開いているサブエージェントの説明
+    pub agent: String,
 }
 
 pub struct App {
@@ -29,39 +112,425 @@ pub struct App {
     pub sessions: Vec<SessionInfo>,
     pub filtered_sessions: Vec<SessionInfo>,
     pub messages: Vec<Message>,
-    pub selected_project: usize,
-    pub selected_session: usize,
+    /// 上限を超える長いメッセージも全文表示する
+    pub show_full_messages: bool,
+    /// 展開したツール結果（メッセージの添字）。それ以外の複数行の結果は1行に畳む
+    pub expanded_results: HashSet<usize>,
+    /// 行数の上限を外して全行を表示するツール結果（メッセージの添字）
+    pub full_results: HashSet<usize>,
+    /// アシスタントの回答だけを記事のように続けて表示する（別のセッションを開いても維持）
+    pub answers_only: bool,
+    /// 詳細画面の右に選択中のメッセージの元の JSONL を並べる（別のセッションを開いても維持）
+    pub raw_pane: bool,
+    /// 詳細画面の下端に現在位置の代わりにキー操作の一覧を出す
+    pub show_detail_keys: bool,
+    /// SessionDetail で隠すロール（別のセッションを開いても維持）
+    pub hidden_roles: Vec<MessageRole>,
+    /// SessionDetail で見せるツールの名前。空なら全て（別のセッションを開いても維持）
+    pub shown_tools: Vec<String>,
+    /// アシスタントの Markdown を整形して表示する（別のセッションを開いても維持）
+    pub markdown: bool,
+    /// SessionDetail を1メッセージ1行の概観で表示する
+    pub overview: bool,
+    pub overview_list: ListState,
+    /// SessionDetail の折り返し済み行（メッセージや表示設定が変わったら破棄）
+    pub detail_cache: RefCell<ui::DetailLineCache>,
+    /// SessionDetail でハイライトする検索語（一覧の検索クエリを引き継ぐ）
+    pub detail_query: String,
+    /// `+` で追加した固定のハイライト語（それぞれ別の色）
+    pub detail_terms: Vec<String>,
+    pub detail_search_active: bool,
+    /// n/N で最後に移動した出現位置
+    pub detail_match: Option<usize>,
+    pub loop_regions: Vec<LoopRegion>,
+    /// 各メッセージの uuid/parentUuid の木での位置
+    pub thread_marks: Vec<ThreadMark>,
+    /// ツールの結果が答えている呼び出しの位置
+    pub result_calls: Vec<Option<usize>>,
+    /// 分岐（編集したプロンプトややり直した回答）を字下げして示す（別のセッションを開いても維持）
+    pub threaded: bool,
+    /// 開いているセッションのサブエージェントの会話（本体からは取り除いてある）
+    pub subagents: Vec<Subagent>,
+    /// サブエージェントの会話を表示中なら、戻り先の本体の会話
+    pub main_conversation: Option<MainConversation>,
+    /// 開いているセッションのツール呼び出しの集計（詳細画面の先頭に表示）
+    pub session_actions: SessionActions,
+    pub project_list: ListState,
+    pub session_list: ListState,
     pub scroll_offset: usize,
+    /// SessionDetail で選択中のメッセージ（j/k で移動し、画面内に保つ）
+    pub selected_message: usize,
     pub time_filter: TimeFilter,
+    /// セッション一覧の並び順（別のプロジェクトを開いても維持）
+    pub session_sort: SessionSort,
+    pub needs_input_only: bool,
+    pub expensive_only: bool,
+    /// スクリプトで定義したカラムとフィルタ
+    pub scripts: ScriptSet,
+    /// 適用中のスクリプトフィルタ（scripts.filter_names() の添字）
+    pub script_filter: Option<usize>,
+    /// この Claude Code バージョンで書かれたセッションだけを表示
+    pub version_filter: Option<String>,
+    /// 再開されたセッションを1つにまとめずに全部表示する
+    pub show_resumed: bool,
+    /// 一覧に残したセッションごとの、まとめた再開前セッションの数
+    pub session_resumes: HashMap<String, usize>,
+    /// Space で印を付けたセッション。書き出しや削除をまとめて行う
+    pub marked_sessions: HashSet<String>,
+    /// 印を付けたセッションの削除を一度頼まれた（もう一度 X で実行）
+    pub delete_marked_pending: bool,
+    /// 通常の一覧の代わりにアーカイブしたセッションを一覧する
+    pub show_archived: bool,
+    /// お気に入りにしたセッション（索引に保存）
+    pub favorites: HashSet<String>,
+    pub favorites_only: bool,
+    /// セッションごとのタグ（索引に保存、並べ替え済み）
+    pub session_tags: HashMap<String, Vec<String>>,
+    /// 入力中のタグ（Some の間は下端で入力中）
+    pub tag_input: Option<String>,
+    /// Cost at or above which a session counts as unusually expensive.
+    pub expensive_threshold: Option<f64>,
     pub current_project_name: String,
     pub should_quit: bool,
+    pub config: Config,
+    /// コピー先。システムのクリップボードには最初のコピーまで接続しない
+    pub clipboard: Box<dyn Clipboard>,
+    /// 複数の Claude ルートを見ているときだけ、どのルートのセッションかを表示する
+    pub show_root_column: bool,
     pub terminal_height: usize,
     pub search_active: bool,
     pub search_query: String,
     pub global_search_results: Vec<SearchResult>,
     pub global_search_filtered: Vec<SearchResult>,
     pub global_search_query: String,
-    pub global_search_selected: usize,
-    pub project_scroll_offset: usize,
-    pub session_scroll_offset: usize,
-    pub global_search_scroll_offset: usize,
+    pub global_search_list: ListState,
+    /// Session whose other matching prompts are listed under its row.
+    pub global_search_expanded: Option<String>,
+    /// 結果に残したセッションごとの、まとめた再開前セッションの数
+    pub global_search_resumes: HashMap<String, usize>,
+    pub index_progress: Option<IndexProgress>,
+    pub index_rx: Option<Receiver<IndexEvent>>,
+    /// Ctrl-R で手動更新を頼まれた（完了時にどの画面でも結果を知らせる）
+    pub index_requested: bool,
+    /// E で公開しようとしているもの（Some の間は送るかどうかの確認中）
+    pub share_pending: Option<PendingShare>,
+    /// E で公開中の share_command の結果: (何を公開したか, 出力)
+    pub share_rx: Option<Receiver<(String, Result<String>)>>,
+    /// 最後に索引を更新し終えた時刻（起動時は DB ファイルの更新時刻）
+    pub last_indexed: Option<chrono::DateTime<Utc>>,
+    pub current_session_id: String,
+    pub audit_entries: Vec<PermissionAuditEntry>,
+    pub audit_title: String,
+    pub audit_list: ListState,
+    pub audit_return_screen: Screen,
+    pub file_changes: Vec<FileChange>,
+    /// 差分の描画行。シンタックスハイライトは重いので開いたときに一度だけ作る
+    pub changes_cache: RefCell<Vec<ratatui::text::Line<'static>>>,
+    pub changes_scroll_offset: usize,
+    pub snapshots: Vec<FileSnapshot>,
+    pub snapshot_list: ListState,
+    pub snapshot_content: String,
+    pub snapshot_content_scroll: usize,
+    /// 削除候補のセッションを1件ずつ確認するレビューの残りと結果
+    pub cleanup_review: Review,
+    /// レビュー中のセッションの会話（プレビュー用）
+    pub cleanup_preview: Vec<Message>,
+    pub cleanup_scroll: usize,
+    /// 索引の最近の変更（新しい順）
+    pub index_changes: Vec<IndexChange>,
+    pub index_changes_list: ListState,
+    /// apply-retention が行ったアーカイブの記録（新しい順）
+    pub retention_journal: Vec<JournalEntry>,
+    /// 書き出し先の入力中パス（Some の間は入力モード）
+    pub export_prompt: Option<String>,
+    pub export_confirm_overwrite: bool,
+    /// 外部コマンドメニューの選択位置（Some の間はメニュー表示中）
+    pub command_menu: Option<usize>,
+    /// プロジェクト全体の活動集計（Some の間はパネル表示中）
+    pub project_activity: Option<ProjectActivity>,
+    /// 開いているセッションの時間軸（Some の間はタイムラインを表示中）
+    pub session_timeline: Option<Timeline>,
+    /// 選択中のメッセージの元の JSON を整形したもの（Some の間はポップアップ表示中）
+    pub raw_json: Option<String>,
+    pub raw_json_scroll: usize,
+    /// 選択中の回答から取り出したコードブロック
+    pub code_blocks: Vec<CodeBlock>,
+    /// プロンプトの目次の選択位置（Some の間は目次を表示中）
+    pub prompt_toc: Option<ListState>,
+    /// ツールの絞り込みの選択位置（Some の間は表示中）
+    pub tool_picker: Option<ListState>,
+    /// ビジュアル選択の（起点, カーソル）。どちらも詳細画面の描画行
+    pub visual: Option<(usize, usize)>,
+    /// コピーするコードブロックの選択位置（Some の間はピッカー表示中）
+    pub code_picker: Option<usize>,
+    /// 次のループで端末を一時停止して実行する (名前, 展開済みコマンド)
+    pub pending_command: Option<(String, String)>,
+    /// ページャに渡す（コマンドライン, 本文）。run_loop が端末を明け渡して実行する
+    pub pending_pager: Option<(String, String)>,
+    /// エディタで開くコマンドライン。run_loop が端末を明け渡して実行する
+    pub pending_editor: Option<String>,
+    /// 開いているセッションが参照した CLAUDE.md と設定ファイル
+    pub instruction_files: Vec<String>,
+    pub instruction_list: Option<ListState>,
+    /// フックで呼び出すプラグインのディレクトリ（None なら呼ばない）
+    pub plugins_dir: Option<PathBuf>,
+    /// ブックマークを保存する索引（テストでは None）
+    pub index_path: Option<PathBuf>,
+    /// 開いているセッションでブックマークしたメッセージ
+    pub bookmarked: HashSet<usize>,
+    /// 入力中のブックマークのメモ
+    pub bookmark_note: Option<String>,
+    /// 入力中の期間（Some の間は一覧の下端で入力中）
+    pub date_range_input: Option<String>,
+    /// 全セッションのブックマーク一覧（新しい順）
+    pub bookmarks: Vec<Bookmark>,
+    pub bookmark_list: Option<ListState>,
+    /// One-shot feedback shown in the help bar until the next key press.
+    pub status_message: Option<String>,
+}
+
+/// どの画面にもある本文以外の行: title(1) + help(1) + borders(2)
+const CHROME_ROWS: usize = 4;
+
+/// 目次などのポップアップは上下に余白を残す
+const POPUP_MARGIN_ROWS: usize = 4;
+
+/// Char indices of every occurrence of `token` in `lower` (already lowercased).
+fn token_char_indices(lower: &str, token: &str) -> Vec<usize> {
+    let char_len = token.chars().count();
+    lower
+        .match_indices(token)
+        .flat_map(|(byte_pos, _)| {
+            // byte position -> char index
+            let char_start = lower[..byte_pos].chars().count();
+            char_start..char_start + char_len
+        })
+        .collect()
+}
+
+/// AND-match whitespace-separated `tokens` against a session.
+///
+/// Every token must appear in at least one prompt, the summary, the project
+/// path or the branch name. The prompt (or summary) containing the most
+/// tokens becomes the snippet, with all of its matching tokens highlighted;
+/// prompts win ties against the summary.
+fn match_search_result(r: &SearchResult, tokens: &[String]) -> Option<SearchResult> {
+    let project_lower = r.project_path.to_lowercase();
+    let branch_lower = r.git_branch.to_lowercase();
+    let summary_lower = r.summary.to_lowercase();
+    let prompts_lower: Vec<String> = r.prompts.iter().map(|p| p.to_lowercase()).collect();
+
+    let all_found = tokens.iter().all(|t| {
+        prompts_lower.iter().any(|p| p.contains(t.as_str()))
+            || summary_lower.contains(t.as_str())
+            || project_lower.contains(t.as_str())
+            || branch_lower.contains(t.as_str())
+    });
+    if !all_found {
+        return None;
+    }
+
+    let highlight = |lower: &str| {
+        let mut indices: Vec<usize> = tokens
+            .iter()
+            .flat_map(|t| token_char_indices(lower, t))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    };
+
+    // (prompt index or None for the summary, tokens matched)
+    let mut best: Option<(Option<usize>, usize)> = None;
+    let mut matched_prompts = Vec::new();
+    for (i, lower) in prompts_lower.iter().enumerate() {
+        let hits = tokens.iter().filter(|t| lower.contains(t.as_str())).count();
+        if hits > 0 {
+            matched_prompts.push(i);
+            if best.is_none_or(|(_, h)| hits > h) {
+                best = Some((Some(i), hits));
+            }
+        }
+    }
+    let summary_hits = tokens
+        .iter()
+        .filter(|t| summary_lower.contains(t.as_str()))
+        .count();
+    if summary_hits > 0 && best.is_none_or(|(_, h)| summary_hits > h) {
+        best = Some((None, summary_hits));
+    }
+
+    let mut result = r.clone();
+    match best {
+        Some((Some(i), _)) => {
+            result.best_match_prompt = r.prompts[i].clone();
+            result.best_match_indices = highlight(&prompts_lower[i]);
+            result.best_match_is_summary = false;
+        }
+        Some((None, _)) => {
+            result.best_match_prompt = r.summary.clone();
+            result.best_match_indices = highlight(&summary_lower);
+            result.best_match_is_summary = true;
+        }
+        None => {
+            // プロジェクト名・ブランチ名だけでマッチした
+            result.best_match_prompt = r.prompts.first().cloned().unwrap_or_default();
+            result.best_match_indices = Vec::new();
+            result.best_match_is_summary = false;
+        }
+    }
+    let best_prompt = best.and_then(|(i, _)| i);
+    result.other_matches = matched_prompts
+        .into_iter()
+        .filter(|i| Some(*i) != best_prompt)
+        .map(|i| (r.prompts[i].clone(), highlight(&prompts_lower[i])))
+        .collect();
+    Some(result)
+}
+
+/// Minimum number of priced sessions before outliers are flagged.
+const EXPENSIVE_MIN_SESSIONS: usize = 10;
+
+/// Cost of the session at the 90th percentile, i.e. the cut-off for the most
+/// expensive 10% of sessions. Returns None when there is too little data.
+fn expensive_threshold(sessions: &[SessionInfo]) -> Option<f64> {
+    let mut costs: Vec<f64> = sessions
+        .iter()
+        .map(|s| s.cost_usd)
+        .filter(|c| *c > 0.0)
+        .collect();
+    if costs.len() < EXPENSIVE_MIN_SESSIONS {
+        return None;
+    }
+    costs.sort_by(f64::total_cmp);
+    let idx = (costs.len() * 9).div_ceil(10) - 1;
+    Some(costs[idx])
+}
+
+/// 索引済みのプロジェクト一覧（起動を速くするため）。索引がなければディレクトリを走査する
+fn load_projects() -> Vec<ProjectInfo> {
+    let indexed = indexer::default_db_path()
+        .filter(|p| p.exists())
+        .and_then(|p| SessionIndex::open(&p).ok())
+        .and_then(|index| index.list_projects().ok())
+        .unwrap_or_default();
+    if indexed.is_empty() {
+        parser::list_projects().unwrap_or_default()
+    } else {
+        indexed
+    }
+}
+
+/// `#refactor, incident-2026-01` のような入力からタグを取り出す。
+/// 先頭の `#` は外して小文字にし、重複を除いて並べる
+fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags: Vec<String> = input
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|t| t.trim_start_matches('#').to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// 検索語を `tag:名前` の絞り込み（小文字の名前だけ）とそれ以外の語に分ける
+fn split_tag_terms(query: &str) -> (Vec<String>, Vec<String>) {
+    let (tags, terms): (Vec<&str>, Vec<&str>) = query.split_whitespace().partition(|t| t.starts_with("tag:"));
+    let tags = tags
+        .into_iter()
+        .map(|t| t["tag:".len()..].trim_start_matches('#').to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    (tags, terms.into_iter().map(String::from).collect())
+}
+
+/// 期限切れ（Claude Code の cleanupPeriodDays で削除済み）のセッションを開こうとしたときの案内
+const EXPIRED_TRANSCRIPT_NOTE: &str =
+    "Transcript was deleted by Claude Code's cleanup (cleanupPeriodDays); only its prompts remain in the index";
+
+fn search_result_from(s: SearchableSession) -> SearchResult {
+    SearchResult {
+        session_id: s.session_id,
+        project_path: s.project_path,
+        dir_name: s.dir_name,
+        git_branch: s.git_branch,
+        summary: s.summary,
+        created_at: s.created_at,
+        last_activity: s.last_activity,
+        prompts: s.prompts,
+        best_match_prompt: String::new(),
+        best_match_indices: Vec::new(),
+        best_match_is_summary: false,
+        other_matches: Vec::new(),
+        parent_session_id: s.parent_session_id,
+        expired: s.expired,
+        source_root: s.source_root,
+    }
 }
 
-fn ensure_visible(selected: usize, scroll_offset: &mut usize, visible_height: usize) {
-    if visible_height == 0 {
-        return;
+/// 各行を残すなら Some(まとめた再開セッション数)、まとめられる側なら None
+fn folded_counts<'a>(items: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<Option<usize>> {
+    let items: Vec<(&str, &str)> = items.collect();
+    let mut counts = vec![None; items.len()];
+    for (i, folded) in analysis::collapse_continuations(&items) {
+        counts[i] = Some(folded);
     }
-    if selected < *scroll_offset {
-        *scroll_offset = selected;
-    } else if selected >= *scroll_offset + visible_height {
-        *scroll_offset = selected - visible_height + 1;
+    counts
+}
+
+/// インデックス構築を別スレッドで開始し、進捗を受け取るチャネルを返す
+fn spawn_indexer(options: IndexOptions, plugins_dir: Option<PathBuf>) -> Receiver<IndexEvent> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // 壊れた索引を作り直したら、退避した古いファイルではなく新しい方を読む
+        let mut reader: Option<(bool, SessionIndex)> = None;
+        let result = indexer::build_default_index_with_progress(&options, |progress| {
+            if reader.as_ref().is_none_or(|(recovered, _)| *recovered != progress.recovered) {
+                reader = indexer::default_db_path()
+                    .and_then(|p| SessionIndex::open(&p).ok())
+                    .map(|index| (progress.recovered, index));
+            }
+            let sessions = reader
+                .as_ref()
+                .and_then(|(_, index)| index.search_project(&progress.dir_name).ok())
+                .unwrap_or_default();
+            let _ = tx.send(IndexEvent::Progress(progress.clone(), sessions));
+        });
+        if let (Ok((db_path, report)), Some(dir)) = (&result, &plugins_dir) {
+            let payload = serde_json::json!({
+                "db_path": db_path,
+                "projects": report.projects_total,
+                "sessions": report.sessions_scanned,
+            });
+            plugins::run_hook_in(dir, Hook::PostIndex, &payload);
+        }
+        let result = result
+            .and_then(|(db_path, report)| Ok((report, SessionIndex::open(&db_path)?.list_projects()?)));
+        let _ = tx.send(IndexEvent::Done(result.map_err(|e| e.to_string())));
+    });
+    rx
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl App {
     pub fn new() -> App {
-        let projects = parser::list_projects().unwrap_or_default();
+        let projects = load_projects();
+        let (scripts, script_error) = match ScriptSet::load() {
+            Ok(scripts) => (scripts, None),
+            Err(e) => (ScriptSet::default(), Some(format!("Script error: {}", e))),
+        };
         let displayed_projects = projects.clone();
+        // 壊れた設定は既定値で動かしつつ、黙って捨てずに知らせる
+        let (config, config_error) = match Config::try_load() {
+            Ok(config) => (config, None),
+            Err(e) => (Config::default(), Some(format!("Config error, using defaults: {}", e))),
+        };
         App {
             screen: Screen::ProjectList,
             projects,
@@ -69,22 +538,116 @@ impl App {
             sessions: Vec::new(),
             filtered_sessions: Vec::new(),
             messages: Vec::new(),
-            selected_project: 0,
-            selected_session: 0,
+            loop_regions: Vec::new(),
+            thread_marks: Vec::new(),
+            result_calls: Vec::new(),
+            threaded: false,
+            subagents: Vec::new(),
+            main_conversation: None,
+            session_actions: SessionActions::default(),
+            show_full_messages: false,
+            expanded_results: HashSet::new(),
+            full_results: HashSet::new(),
+            answers_only: false,
+            raw_pane: false,
+            show_detail_keys: false,
+            hidden_roles: Vec::new(),
+            shown_tools: Vec::new(),
+            markdown: true,
+            overview: false,
+            overview_list: ListState::default(),
+            detail_cache: RefCell::new(ui::DetailLineCache::default()),
+            detail_query: String::new(),
+            detail_terms: Vec::new(),
+            detail_search_active: false,
+            detail_match: None,
+            project_list: ListState::default(),
+            session_list: ListState::default(),
             scroll_offset: 0,
+            selected_message: 0,
             time_filter: TimeFilter::All,
+            session_sort: SessionSort::default(),
+            needs_input_only: false,
+            expensive_only: false,
+            scripts,
+            script_filter: None,
+            version_filter: None,
+            show_resumed: false,
+            session_resumes: HashMap::new(),
+            marked_sessions: HashSet::new(),
+            delete_marked_pending: false,
+            show_archived: false,
+            favorites: HashSet::new(),
+            favorites_only: false,
+            session_tags: HashMap::new(),
+            tag_input: None,
+            expensive_threshold: None,
             current_project_name: String::new(),
             should_quit: false,
+            clipboard: clipboard::open(config.clipboard, config.clip_file().as_deref()),
+            config,
+            show_root_column: parser::claude_roots().len() > 1,
             terminal_height: 24,
             search_active: false,
             search_query: String::new(),
             global_search_results: Vec::new(),
             global_search_filtered: Vec::new(),
             global_search_query: String::new(),
-            global_search_selected: 0,
-            project_scroll_offset: 0,
-            session_scroll_offset: 0,
-            global_search_scroll_offset: 0,
+            global_search_list: ListState::default(),
+            global_search_expanded: None,
+            global_search_resumes: HashMap::new(),
+            index_progress: None,
+            index_rx: None,
+            index_requested: false,
+            share_pending: None,
+            share_rx: None,
+            current_session_id: String::new(),
+            audit_entries: Vec::new(),
+            audit_title: String::new(),
+            audit_list: ListState::default(),
+            audit_return_screen: Screen::ProjectList,
+            file_changes: Vec::new(),
+            changes_cache: RefCell::default(),
+            changes_scroll_offset: 0,
+            snapshots: Vec::new(),
+            snapshot_list: ListState::default(),
+            snapshot_content: String::new(),
+            snapshot_content_scroll: 0,
+            cleanup_review: Review::default(),
+            cleanup_preview: Vec::new(),
+            cleanup_scroll: 0,
+            index_changes: Vec::new(),
+            index_changes_list: ListState::default(),
+            retention_journal: Vec::new(),
+            export_prompt: None,
+            export_confirm_overwrite: false,
+            command_menu: None,
+            project_activity: None,
+            session_timeline: None,
+            raw_json: None,
+            raw_json_scroll: 0,
+            prompt_toc: None,
+            tool_picker: None,
+            visual: None,
+            code_blocks: Vec::new(),
+            code_picker: None,
+            pending_command: None,
+            pending_pager: None,
+            pending_editor: None,
+            instruction_files: Vec::new(),
+            instruction_list: None,
+            plugins_dir: plugins::plugins_dir(),
+            index_path: indexer::default_db_path(),
+            bookmarked: HashSet::new(),
+            bookmark_note: None,
+            date_range_input: None,
+            bookmarks: Vec::new(),
+            bookmark_list: None,
+            status_message: config_error.or(script_error),
+            last_indexed: indexer::default_db_path()
+                .and_then(|p| std::fs::metadata(p).ok())
+                .and_then(|m| m.modified().ok())
+                .map(chrono::DateTime::from),
         }
     }
 
@@ -98,49 +661,145 @@ impl App {
             sessions: Vec::new(),
             filtered_sessions: Vec::new(),
             messages: Vec::new(),
-            selected_project: 0,
-            selected_session: 0,
+            loop_regions: Vec::new(),
+            thread_marks: Vec::new(),
+            result_calls: Vec::new(),
+            threaded: false,
+            subagents: Vec::new(),
+            main_conversation: None,
+            session_actions: SessionActions::default(),
+            show_full_messages: false,
+            expanded_results: HashSet::new(),
+            full_results: HashSet::new(),
+            answers_only: false,
+            raw_pane: false,
+            show_detail_keys: false,
+            hidden_roles: Vec::new(),
+            shown_tools: Vec::new(),
+            markdown: true,
+            overview: false,
+            overview_list: ListState::default(),
+            detail_cache: RefCell::new(ui::DetailLineCache::default()),
+            detail_query: String::new(),
+            detail_terms: Vec::new(),
+            detail_search_active: false,
+            detail_match: None,
+            project_list: ListState::default(),
+            session_list: ListState::default(),
             scroll_offset: 0,
+            selected_message: 0,
             time_filter: TimeFilter::All,
+            session_sort: SessionSort::default(),
+            needs_input_only: false,
+            expensive_only: false,
+            scripts: ScriptSet::default(),
+            script_filter: None,
+            version_filter: None,
+            show_resumed: false,
+            session_resumes: HashMap::new(),
+            marked_sessions: HashSet::new(),
+            delete_marked_pending: false,
+            show_archived: false,
+            favorites: HashSet::new(),
+            favorites_only: false,
+            session_tags: HashMap::new(),
+            tag_input: None,
+            expensive_threshold: None,
             current_project_name: String::new(),
             should_quit: false,
+            config: Config::default(),
+            clipboard: Box::new(clipboard::SystemClipboard),
+            show_root_column: false,
             terminal_height: 24,
             search_active: false,
             search_query: String::new(),
             global_search_results: Vec::new(),
             global_search_filtered: Vec::new(),
             global_search_query: String::new(),
-            global_search_selected: 0,
-            project_scroll_offset: 0,
-            session_scroll_offset: 0,
-            global_search_scroll_offset: 0,
+            global_search_list: ListState::default(),
+            global_search_expanded: None,
+            global_search_resumes: HashMap::new(),
+            index_progress: None,
+            index_rx: None,
+            index_requested: false,
+            share_pending: None,
+            share_rx: None,
+            current_session_id: String::new(),
+            audit_entries: Vec::new(),
+            audit_title: String::new(),
+            audit_list: ListState::default(),
+            audit_return_screen: Screen::ProjectList,
+            file_changes: Vec::new(),
+            changes_cache: RefCell::default(),
+            changes_scroll_offset: 0,
+            snapshots: Vec::new(),
+            snapshot_list: ListState::default(),
+            snapshot_content: String::new(),
+            snapshot_content_scroll: 0,
+            cleanup_review: Review::default(),
+            cleanup_preview: Vec::new(),
+            cleanup_scroll: 0,
+            index_changes: Vec::new(),
+            index_changes_list: ListState::default(),
+            retention_journal: Vec::new(),
+            export_prompt: None,
+            export_confirm_overwrite: false,
+            command_menu: None,
+            project_activity: None,
+            session_timeline: None,
+            raw_json: None,
+            raw_json_scroll: 0,
+            prompt_toc: None,
+            tool_picker: None,
+            visual: None,
+            code_blocks: Vec::new(),
+            code_picker: None,
+            pending_command: None,
+            pending_pager: None,
+            pending_editor: None,
+            instruction_files: Vec::new(),
+            instruction_list: None,
+            plugins_dir: None,
+            index_path: None,
+            bookmarked: HashSet::new(),
+            bookmark_note: None,
+            date_range_input: None,
+            bookmarks: Vec::new(),
+            bookmark_list: None,
+            status_message: None,
+            last_indexed: None,
         }
     }
 
+    pub fn is_expensive(&self, session: &SessionInfo) -> bool {
+        self.expensive_threshold
+            .is_some_and(|t| session.cost_usd > 0.0 && session.cost_usd >= t)
+    }
+
     pub fn apply_filter(&mut self) {
+        self.expensive_threshold = expensive_threshold(&self.sessions);
         let now = Utc::now();
         let time_filtered: Vec<SessionInfo> = self
             .sessions
             .iter()
-            .filter(|s| match self.time_filter {
-                TimeFilter::All => true,
-                TimeFilter::Yesterday => s
-                    .timestamp
-                    .map(|t| now.signed_duration_since(t).num_hours() < 24)
-                    .unwrap_or(false),
-                TimeFilter::Week => s
-                    .timestamp
-                    .map(|t| now.signed_duration_since(t).num_days() < 7)
-                    .unwrap_or(false),
-                TimeFilter::Month => s
-                    .timestamp
-                    .map(|t| now.signed_duration_since(t).num_days() < 30)
-                    .unwrap_or(false),
+            .filter(|s| self.time_filter == TimeFilter::All || s.timestamp.is_some_and(|t| self.time_filter.contains(t, now)))
+            .filter(|s| !self.needs_input_only || s.needs_input)
+            .filter(|s| !self.favorites_only || self.favorites.contains(&s.session_id))
+            .filter(|s| !self.expensive_only || self.is_expensive(s))
+            .filter(|s| {
+                self.script_filter
+                    .is_none_or(|f| self.scripts.filter_matches(f, s))
             })
+            .filter(|s| self.version_filter.as_ref().is_none_or(|v| s.version == *v))
             .cloned()
             .collect();
 
-        if self.search_query.is_empty() {
+        // `tag:名前` はタグの絞り込み、残りをあいまい検索に使う
+        let (tags, terms) = split_tag_terms(&self.search_query);
+        let query = if tags.is_empty() { self.search_query.clone() } else { terms.join(" ") };
+        let time_filtered: Vec<SessionInfo> =
+            time_filtered.into_iter().filter(|s| self.has_tags(&s.session_id, &tags)).collect();
+        if query.is_empty() {
             self.filtered_sessions = time_filtered;
         } else {
             let matcher = SkimMatcherV2::default();
@@ -148,35 +807,346 @@ impl App {
                 .into_iter()
                 .filter(|s| {
                     matcher
-                        .fuzzy_match(&s.preview, &self.search_query)
+                        .fuzzy_match(&s.preview, &query)
                         .is_some()
                         || matcher
-                            .fuzzy_match(&s.summary, &self.search_query)
+                            .fuzzy_match(&s.summary, &query)
                             .is_some()
                         || matcher
-                            .fuzzy_match(&s.git_branch, &self.search_query)
+                            .fuzzy_match(&s.git_branch, &query)
                             .is_some()
                 })
                 .collect();
         }
+
+        self.session_resumes.clear();
+        if !self.show_resumed {
+            let sessions = std::mem::take(&mut self.filtered_sessions);
+            let folded = folded_counts(
+                sessions
+                    .iter()
+                    .map(|s| (s.session_id.as_str(), s.parent_session_id.as_str())),
+            );
+            for (session, folded) in sessions.into_iter().zip(folded) {
+                let Some(folded) = folded else { continue };
+                if folded > 0 {
+                    self.session_resumes.insert(session.session_id.clone(), folded);
+                }
+                self.filtered_sessions.push(session);
+            }
+        }
+
+        // 読み込んだ一覧は作成順（新しい順）に並んでいるので、それ以外の順のときだけ並べ替える
+        if self.session_sort != SessionSort::Created {
+            self.session_sort.apply(&mut self.filtered_sessions);
+        }
+        if self.expensive_only {
+            self.filtered_sessions
+                .sort_by(|a, b| b.cost_usd.total_cmp(&a.cost_usd));
+        }
     }
 
-    fn ensure_table_scroll(&mut self) {
-        let th = self.terminal_height;
-        match self.screen {
-            Screen::ProjectList => {
-                let vh = th.saturating_sub(5);
-                ensure_visible(self.selected_project, &mut self.project_scroll_offset, vh);
+    /// 一覧で選択中（詳細画面では表示中）のセッションをお気に入りにする・外す
+    pub fn toggle_favorite(&mut self) {
+        let session_id = match self.screen {
+            Screen::SessionList => match self.filtered_sessions.get(self.session_list.selected) {
+                Some(session) => session.session_id.clone(),
+                None => return,
+            },
+            Screen::SessionDetail if self.main_conversation.is_none() => self.current_session_id.clone(),
+            _ => return,
+        };
+        let adding = !self.favorites.contains(&session_id);
+        let saved = self.open_index().and_then(|index| {
+            if adding {
+                index.add_favorite(&session_id, &self.current_project_name, Utc::now().timestamp())
+            } else {
+                index.remove_favorite(&session_id)
             }
-            Screen::SessionList => {
-                let vh = th.saturating_sub(7);
-                ensure_visible(self.selected_session, &mut self.session_scroll_offset, vh);
+        });
+        match saved {
+            Ok(()) if adding => {
+                self.favorites.insert(session_id);
+                self.status_message = Some("Added to favorites".to_string());
+            }
+            Ok(()) => {
+                self.favorites.remove(&session_id);
+                self.status_message = Some("Removed from favorites".to_string());
+                // お気に入りだけの一覧からは外したものを消す
+                if self.favorites_only && self.screen == Screen::SessionList {
+                    self.apply_filter();
+                    let last = self.filtered_sessions.len().saturating_sub(1);
+                    let visible = self.visible_rows(&Screen::SessionList);
+                    self.session_list.select(self.session_list.selected.min(last), visible);
+                }
+            }
+            Err(e) => self.status_message = Some(format!("Could not save the favorite: {}", e)),
+        }
+    }
+
+    /// セッションに `tags` のそれぞれを含むタグが付いているか
+    fn has_tags(&self, session_id: &str, tags: &[String]) -> bool {
+        let own = self.session_tags.get(session_id).map(Vec::as_slice).unwrap_or_default();
+        tags.iter().all(|tag| own.iter().any(|t| t.contains(tag.as_str())))
+    }
+
+    /// タグの入力を始める。印を付けたセッションがあればそれらに足すタグを、
+    /// なければ選択中（詳細画面では表示中）のセッションのタグを書き換える
+    pub fn open_tag_input(&mut self) {
+        let session_id = match self.screen {
+            Screen::SessionList if !self.marked_in_list().is_empty() => {
+                self.tag_input = Some(String::new());
+                return;
+            }
+            Screen::SessionList => match self.filtered_sessions.get(self.session_list.selected) {
+                Some(session) => session.session_id.clone(),
+                None => return,
+            },
+            Screen::SessionDetail if self.main_conversation.is_none() => self.current_session_id.clone(),
+            _ => return,
+        };
+        let tags = self.session_tags.get(&session_id).cloned().unwrap_or_default();
+        self.tag_input = Some(tags.iter().map(|t| format!("#{} ", t)).collect());
+    }
+
+    pub fn tag_input_push(&mut self, ch: char) {
+        if let Some(input) = &mut self.tag_input {
+            input.push(ch);
+        }
+    }
+
+    pub fn tag_input_pop(&mut self) {
+        if let Some(input) = &mut self.tag_input {
+            input.pop();
+        }
+    }
+
+    pub fn cancel_tag_input(&mut self) {
+        self.tag_input = None;
+    }
+
+    /// 入力したタグを保存する。印を付けたセッションには足し、1件なら置き換える
+    pub fn confirm_tag_input(&mut self) {
+        let Some(input) = self.tag_input.take() else {
+            return;
+        };
+        let typed = parse_tags(&input);
+        let marked: Vec<String> = match self.screen {
+            Screen::SessionList => self.marked_in_list().iter().map(|s| s.session_id.clone()).collect(),
+            _ => Vec::new(),
+        };
+        let updates: Vec<(String, Vec<String>)> = if marked.is_empty() {
+            let session_id = match self.screen {
+                Screen::SessionList => match self.filtered_sessions.get(self.session_list.selected) {
+                    Some(session) => session.session_id.clone(),
+                    None => return,
+                },
+                _ => self.current_session_id.clone(),
+            };
+            vec![(session_id, typed.clone())]
+        } else {
+            if typed.is_empty() {
+                return;
+            }
+            marked
+                .into_iter()
+                .map(|id| {
+                    let mut tags = self.session_tags.get(&id).cloned().unwrap_or_default();
+                    tags.extend(typed.iter().cloned());
+                    tags.sort();
+                    tags.dedup();
+                    (id, tags)
+                })
+                .collect()
+        };
+        let saved = self.open_index().and_then(|index| {
+            for (session_id, tags) in &updates {
+                index.set_session_tags(session_id, &self.current_project_name, tags)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = saved {
+            self.status_message = Some(format!("Could not save the tags: {}", e));
+            return;
+        }
+        let count = updates.len();
+        for (session_id, tags) in updates {
+            if tags.is_empty() {
+                self.session_tags.remove(&session_id);
+            } else {
+                self.session_tags.insert(session_id, tags);
+            }
+        }
+        let shown: Vec<String> = typed.iter().map(|t| format!("#{}", t)).collect();
+        self.status_message = Some(match (count, shown.is_empty()) {
+            (1, true) => "Tags cleared".to_string(),
+            (1, false) => format!("Tagged {}", shown.join(" ")),
+            _ => format!("Tagged {} sessions {}", count, shown.join(" ")),
+        });
+        if self.screen == Screen::SessionList {
+            self.apply_filter();
+        }
+    }
+
+    pub fn toggle_favorites_filter(&mut self) {
+        self.favorites_only = !self.favorites_only;
+        self.apply_filter();
+        self.session_list.reset();
+    }
+
+    /// 一覧に見えているセッションのうち印の付いたもの（一覧の順）
+    pub fn marked_in_list(&self) -> Vec<&SessionInfo> {
+        self.filtered_sessions.iter().filter(|s| self.marked_sessions.contains(&s.session_id)).collect()
+    }
+
+    /// 選択中のセッションの印を付け外しして次の行へ進む
+    pub fn toggle_session_mark(&mut self) {
+        let Some(session) = self.filtered_sessions.get(self.session_list.selected) else {
+            return;
+        };
+        if !self.marked_sessions.remove(&session.session_id) {
+            self.marked_sessions.insert(session.session_id.clone());
+        }
+        self.navigate_down();
+    }
+
+    /// 印を付けたセッションのファイルを消す。1回目は確認を出し、続けてもう一度押すと実行する
+    pub fn delete_marked_sessions(&mut self) {
+        let marked: Vec<String> = self.marked_in_list().iter().map(|s| s.session_id.clone()).collect();
+        if marked.is_empty() {
+            self.status_message = Some("Mark sessions with Space first".to_string());
+            return;
+        }
+        if !self.delete_marked_pending {
+            self.delete_marked_pending = true;
+            self.status_message = Some(format!("Delete {} marked sessions? Press X again to delete", marked.len()));
+            return;
+        }
+        self.delete_marked_pending = false;
+        let mut deleted = 0;
+        let mut failures = Vec::new();
+        for session_id in marked {
+            let result = parser::session_file_path(&self.current_project_name, &session_id)
+                .ok_or_else(|| anyhow::anyhow!("transcript of {} not found", &session_id[..session_id.len().min(8)]))
+                .and_then(|path| cleanup::delete_session(&path));
+            match result {
+                Ok(()) => {
+                    self.forget_deleted_session(&session_id);
+                    self.sessions.retain(|s| s.session_id != session_id);
+                    self.marked_sessions.remove(&session_id);
+                    deleted += 1;
+                }
+                Err(e) => failures.push(e.to_string()),
             }
+        }
+        self.apply_filter();
+        let last = self.filtered_sessions.len().saturating_sub(1);
+        let visible = self.visible_rows(&Screen::SessionList);
+        self.session_list.select(self.session_list.selected.min(last), visible);
+        self.status_message = Some(match failures.first() {
+            None => format!("Deleted {} sessions", deleted),
+            Some(e) => format!("Deleted {} sessions, {} failed: {}", deleted, failures.len(), e),
+        });
+    }
+
+    /// 消したセッションを索引からも落とす（残すと次の索引で期限切れと誤認される）
+    fn forget_deleted_session(&self, session_id: &str) {
+        if let Ok(index) = self.open_index() {
+            let _ = index.forget_session(session_id, &self.current_project_name);
+        }
+    }
+
+    /// 一覧の並び順を切り替え、選択中のセッションを選んだままにする
+    pub fn cycle_session_sort(&mut self) {
+        let selected = self.filtered_sessions.get(self.session_list.selected).map(|s| s.session_id.clone());
+        self.session_sort = self.session_sort.next();
+        self.apply_filter();
+        self.session_list.reset();
+        if let Some(row) = selected.and_then(|id| self.filtered_sessions.iter().position(|s| s.session_id == id)) {
+            let visible = self.visible_rows(&Screen::SessionList);
+            self.session_list.select(row, visible);
+        }
+    }
+
+    /// 再開されたセッションをまとめるかどうかを切り替え
+    pub fn toggle_show_resumed(&mut self) {
+        self.show_resumed = !self.show_resumed;
+        self.apply_filter();
+        self.session_list.reset();
+    }
+
+    /// バージョンフィルタを新しい順に切り替え（最後の次は解除）
+    pub fn cycle_version_filter(&mut self) {
+        let versions = analysis::distinct_versions(self.sessions.iter().map(|s| s.version.as_str()));
+        if versions.is_empty() {
+            self.status_message = Some("No Claude Code versions recorded in these sessions".to_string());
+            return;
+        }
+        let next = match &self.version_filter {
+            None => 0,
+            Some(v) => versions.iter().position(|o| o == v).map_or(0, |i| i + 1),
+        };
+        self.version_filter = versions.get(next).cloned();
+        self.apply_filter();
+        self.session_list.reset();
+    }
+
+    /// 表示中の一覧画面の選択・スクロール位置と行数
+    fn active_list(&mut self) -> Option<(&mut ListState, usize)> {
+        let overview_len = if self.overview { self.overview_indices().len() } else { 0 };
+        match self.screen {
+            Screen::ProjectList => Some((&mut self.project_list, self.displayed_projects.len())),
+            Screen::SessionList => Some((&mut self.session_list, self.filtered_sessions.len())),
             Screen::GlobalSearch => {
-                let vh = th.saturating_sub(6);
-                ensure_visible(self.global_search_selected, &mut self.global_search_scroll_offset, vh);
+                Some((&mut self.global_search_list, self.global_search_filtered.len()))
+            }
+            Screen::PermissionAudit => Some((&mut self.audit_list, self.audit_entries.len())),
+            Screen::FileSnapshots => Some((&mut self.snapshot_list, self.snapshots.len())),
+            Screen::IndexActivity => Some((&mut self.index_changes_list, self.index_changes.len())),
+            Screen::SessionDetail if self.overview => Some((&mut self.overview_list, overview_len)),
+            Screen::SessionDetail | Screen::SessionChanges | Screen::SnapshotView | Screen::CleanupReview => None,
+        }
+    }
+
+    /// `screen` で本文以外に使う行数。高さの計算はすべてここを通す
+    fn chrome_rows(&self, screen: &Screen) -> usize {
+        CHROME_ROWS
+            + match screen {
+                // header
+                Screen::ProjectList => 1,
+                // header + breadcrumb + filter tabs
+                Screen::SessionList => 3,
+                // header + 検索入力行 / breadcrumb
+                Screen::GlobalSearch | Screen::PermissionAudit | Screen::FileSnapshots => 2,
+                // header + 下の apply-retention の記録
+                Screen::IndexActivity => 1 + self.journal_panel_rows(),
+                // breadcrumb + 要約行
+                Screen::SessionDetail => 1 + usize::from(!self.session_actions.is_empty()),
+                // breadcrumb
+                Screen::SessionChanges | Screen::SnapshotView => 1,
+                // 候補の説明
+                Screen::CleanupReview => 2,
             }
-            Screen::SessionDetail => {}
+    }
+
+    /// `screen` の本文（一覧ならテーブルの行）に使える行数
+    pub fn visible_rows(&self, screen: &Screen) -> usize {
+        self.terminal_height.saturating_sub(self.chrome_rows(screen))
+    }
+
+    /// Index Activity の下に出す apply-retention の記録の高さ（枠と見出し込み）
+    pub fn journal_panel_rows(&self) -> usize {
+        if self.retention_journal.is_empty() {
+            0
+        } else {
+            self.retention_journal.len().min(JOURNAL_PANEL_ENTRIES) + 3
+        }
+    }
+
+    fn ensure_table_scroll(&mut self) {
+        let visible = self.visible_rows(&self.screen);
+        if let Some((list, _)) = self.active_list() {
+            list.scroll_into_view(visible);
         }
     }
 
@@ -184,13 +1154,12 @@ impl App {
         if self.displayed_projects.is_empty() {
             return;
         }
-        let project = &self.displayed_projects[self.selected_project];
+        let project = &self.displayed_projects[self.project_list.selected];
         self.current_project_name = project.dir_name.clone();
         self.search_query.clear();
-        self.sessions = parser::list_sessions(&project.dir_name).unwrap_or_default();
-        self.apply_filter();
-        self.selected_session = 0;
-        self.session_scroll_offset = 0;
+        self.show_archived = false;
+        self.load_session_list();
+        self.session_list.reset();
         self.scroll_offset = 0;
         self.screen = Screen::SessionList;
     }
@@ -199,12 +1168,168 @@ impl App {
         if self.filtered_sessions.is_empty() {
             return;
         }
-        let session = &self.filtered_sessions[self.selected_session];
-        self.messages =
-            parser::load_session(&self.current_project_name, &session.session_id)
-                .unwrap_or_default();
-        self.scroll_offset = 0;
-        self.screen = Screen::SessionDetail;
+        let session = &self.filtered_sessions[self.session_list.selected];
+        self.current_session_id = session.session_id.clone();
+        let messages = match self.archived_dir_of(&session.session_id) {
+            Some(dir) if self.show_archived => parser::load_transcript_in(&dir, &session.session_id),
+            _ => parser::load_session(&self.current_project_name, &session.session_id),
+        }
+        .unwrap_or_default();
+        if !session.warning.is_empty() {
+            self.status_message = Some(format!("Transcript repaired: {}", session.warning));
+        }
+        let query = self.search_query.clone();
+        self.set_messages(messages);
+        self.session_actions = actions::session_actions(&self.current_project_name, &self.current_session_id)
+            .unwrap_or_default();
+        self.set_detail_query(&query);
+        self.fire_open_session_hook();
+    }
+
+    /// 開いているプロジェクトのセッションを読み込む。アーカイブしたものは
+    /// アーカイブの一覧でだけ出す（sessions-index.json に残っていても隠す）
+    fn load_session_list(&mut self) {
+        let archived = parser::list_archived_sessions(
+            &self.current_project_name,
+            &self.archive_dirs().into_iter().map(|(_, archive)| archive).collect::<Vec<_>>(),
+            &self.config.prices,
+        );
+        self.sessions = if self.show_archived {
+            archived
+        } else {
+            let mut sessions = parser::list_sessions(&self.current_project_name, &self.config.prices).unwrap_or_default();
+            sessions.retain(|s| !archived.iter().any(|a| a.session_id == s.session_id));
+            sessions
+        };
+        self.marked_sessions.clear();
+        self.favorites = self.open_index().and_then(|index| index.favorites()).unwrap_or_default();
+        self.session_tags = self.open_index().and_then(|index| index.session_tags()).unwrap_or_default();
+        self.apply_filter();
+    }
+
+    /// 各 Claude ルートでの（プロジェクトのディレクトリ, そのアーカイブ先）
+    fn archive_dirs(&self) -> Vec<(PathBuf, PathBuf)> {
+        let mut dirs: Vec<(PathBuf, PathBuf)> = Vec::new();
+        for root in parser::claude_roots() {
+            let project_dir = root.projects_dir.join(&self.current_project_name);
+            let archive = cleanup::archive_dir(&self.config.archive_dir, &project_dir);
+            if !dirs.iter().any(|(_, a)| *a == archive) {
+                dirs.push((project_dir, archive));
+            }
+        }
+        dirs
+    }
+
+    /// アーカイブしたセッションのファイルがあるアーカイブ先
+    fn archived_dir_of(&self, session_id: &str) -> Option<PathBuf> {
+        let file_name = format!("{}.jsonl", session_id);
+        self.archive_dirs().into_iter().map(|(_, archive)| archive).find(|dir| dir.join(&file_name).exists())
+    }
+
+    /// 通常の一覧とアーカイブの一覧を切り替える
+    pub fn toggle_archived_view(&mut self) {
+        self.show_archived = !self.show_archived;
+        self.load_session_list();
+        self.session_list.reset();
+        if self.show_archived && self.sessions.is_empty() {
+            self.status_message = Some("No archived sessions in this project".to_string());
+        }
+    }
+
+    /// 印を付けたセッション（なければ選択中のもの）をアーカイブへ移す。
+    /// アーカイブの一覧ではプロジェクトへ戻す
+    pub fn archive_sessions(&mut self) {
+        let marked: Vec<String> = self.marked_in_list().iter().map(|s| s.session_id.clone()).collect();
+        let targets = if marked.is_empty() {
+            self.filtered_sessions.get(self.session_list.selected).map(|s| s.session_id.clone()).into_iter().collect()
+        } else {
+            marked
+        };
+        if targets.is_empty() {
+            return;
+        }
+        let dirs = self.archive_dirs();
+        let mut moved = 0;
+        let mut failures = Vec::new();
+        for session_id in &targets {
+            let file_name = format!("{}.jsonl", session_id);
+            let result = if self.show_archived {
+                dirs.iter()
+                    .find(|(_, archive)| archive.join(&file_name).exists())
+                    .ok_or_else(|| anyhow::anyhow!("{} is not archived", file_name))
+                    .and_then(|(project_dir, archive)| cleanup::restore_session(&archive.join(&file_name), project_dir))
+            } else {
+                parser::session_file_path(&self.current_project_name, session_id)
+                    .filter(|path| path.exists())
+                    .ok_or_else(|| anyhow::anyhow!("transcript {} not found", file_name))
+                    .and_then(|path| {
+                        let project_dir = path.parent().unwrap_or(&path).to_path_buf();
+                        cleanup::archive_session(&path, &cleanup::archive_dir(&self.config.archive_dir, &project_dir))
+                    })
+            };
+            match result {
+                Ok(_) => moved += 1,
+                Err(e) => failures.push(e.to_string()),
+            }
+        }
+        self.load_session_list();
+        let last = self.filtered_sessions.len().saturating_sub(1);
+        let visible = self.visible_rows(&Screen::SessionList);
+        self.session_list.select(self.session_list.selected.min(last), visible);
+        let verb = if self.show_archived { "Restored" } else { "Archived" };
+        self.status_message = Some(match failures.first() {
+            None => format!("{} {} sessions", verb, moved),
+            Some(e) => format!("{} {} sessions, {} failed: {}", verb, moved, failures.len(), e),
+        });
+    }
+
+    /// セッション一覧の並び順で前後のセッションを開く。ロールの表示設定と
+    /// ハイライト中の検索語はそのまま引き継ぐ
+    pub fn switch_session(&mut self, forward: bool) {
+        let selected = self.session_list.selected;
+        let from_list = self
+            .filtered_sessions
+            .get(selected)
+            .is_some_and(|s| s.session_id == self.current_session_id);
+        if !from_list {
+            self.status_message = Some("Open the session from the session list to step through it".to_string());
+            return;
+        }
+        let next = if forward {
+            Some(selected + 1).filter(|&i| i < self.filtered_sessions.len())
+        } else {
+            selected.checked_sub(1)
+        };
+        let Some(next) = next else {
+            let edge = if forward { "last" } else { "first" };
+            self.status_message = Some(format!("Already at the {edge} session"));
+            return;
+        };
+        let query = self.detail_query.clone();
+        let terms = std::mem::take(&mut self.detail_terms);
+        self.session_list.selected = next;
+        let visible = self.visible_rows(&Screen::SessionList);
+        self.session_list.scroll_into_view(visible);
+        self.enter_session_detail();
+        self.detail_terms = terms;
+        self.set_detail_query(&query);
+        if self.status_message.is_none() {
+            self.status_message = Some(format!("Session {}/{}", next + 1, self.filtered_sessions.len()));
+        }
+    }
+
+    /// 開いたセッションを on-open-session フックのプラグインに知らせる
+    pub fn fire_open_session_hook(&self) {
+        if let Some(ctx) = self.command_context() {
+            let payload = serde_json::to_value(ctx).unwrap_or_default();
+            plugins::fire(self.plugins_dir.as_deref(), Hook::OnOpenSession, payload);
+        }
+    }
+
+    fn fire_export_hook(&self, kind: &str, path: &std::path::Path) {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let payload = serde_json::json!({ "kind": kind, "path": path });
+        plugins::fire(self.plugins_dir.as_deref(), Hook::OnExport, payload);
     }
 
     pub fn go_back(&mut self) {
@@ -215,13 +1340,15 @@ impl App {
             Screen::ProjectList => {
                 self.should_quit = true;
             }
+            // 印があればまず印を外す
+            Screen::SessionList if !self.marked_sessions.is_empty() => self.marked_sessions.clear(),
             Screen::SessionList => {
                 self.screen = Screen::ProjectList;
-                self.selected_session = 0;
-                self.session_scroll_offset = 0;
+                self.session_list.reset();
                 self.scroll_offset = 0;
                 self.displayed_projects = self.projects.clone(); // リセット
             }
+            Screen::SessionDetail if self.main_conversation.is_some() => self.close_subagent(),
             Screen::SessionDetail => {
                 self.screen = Screen::SessionList;
                 self.scroll_offset = 0;
@@ -229,108 +1356,117 @@ impl App {
             Screen::GlobalSearch => {
                 self.screen = Screen::ProjectList;
                 self.global_search_query.clear();
-                self.global_search_selected = 0;
-                self.global_search_scroll_offset = 0;
+                self.global_search_list.reset();
             }
-        }
-    }
-
-    pub fn navigate_up(&mut self) {
-        match self.screen {
-            Screen::ProjectList => {
-                if self.selected_project > 0 {
-                    self.selected_project -= 1;
-                }
+            Screen::PermissionAudit => {
+                self.screen = self.audit_return_screen.clone();
+                self.audit_entries.clear();
+                self.audit_list.reset();
             }
-            Screen::SessionList => {
-                if self.selected_session > 0 {
-                    self.selected_session -= 1;
-                }
+            Screen::SessionChanges => {
+                self.screen = Screen::SessionDetail;
+                self.file_changes.clear();
+                self.changes_cache.get_mut().clear();
+                self.changes_scroll_offset = 0;
             }
-            Screen::SessionDetail => {
-                if self.scroll_offset > 0 {
-                    self.scroll_offset -= 1;
-                }
+            Screen::FileSnapshots => {
+                self.screen = Screen::SessionDetail;
+                self.snapshots.clear();
+                self.snapshot_list.reset();
             }
-            Screen::GlobalSearch => {
-                if self.global_search_selected > 0 {
-                    self.global_search_selected -= 1;
-                }
+            Screen::SnapshotView => {
+                self.screen = Screen::FileSnapshots;
+                self.snapshot_content.clear();
+                self.snapshot_content_scroll = 0;
+            }
+            Screen::CleanupReview => self.finish_cleanup_review(),
+            Screen::IndexActivity => {
+                self.screen = Screen::ProjectList;
+                self.index_changes.clear();
+                self.index_changes_list.reset();
+                self.retention_journal.clear();
             }
         }
-        self.ensure_table_scroll();
+    }
+
+    pub fn navigate_up(&mut self) {
+        if self.screen == Screen::SessionDetail && !self.overview {
+            self.move_selection(false);
+        } else {
+            self.scroll_up(1);
+        }
     }
 
     pub fn navigate_down(&mut self) {
-        match self.screen {
-            Screen::ProjectList => {
-                if !self.displayed_projects.is_empty() && self.selected_project < self.displayed_projects.len() - 1 {
-                    self.selected_project += 1;
-                }
-            }
-            Screen::SessionList => {
-                if !self.filtered_sessions.is_empty()
-                    && self.selected_session < self.filtered_sessions.len() - 1
-                {
-                    self.selected_session += 1;
-                }
-            }
-            Screen::SessionDetail => {
-                self.scroll_offset += 1;
-            }
-            Screen::GlobalSearch => {
-                if !self.global_search_filtered.is_empty()
-                    && self.global_search_selected < self.global_search_filtered.len() - 1
-                {
-                    self.global_search_selected += 1;
-                }
-            }
+        if self.screen == Screen::SessionDetail && !self.overview {
+            self.move_selection(true);
+        } else {
+            self.scroll_down(1);
         }
-        self.ensure_table_scroll();
     }
 
     pub fn half_page_down(&mut self) {
-        let half = self.terminal_height / 2;
+        self.scroll_down(self.half_page());
+    }
+
+    pub fn half_page_up(&mut self) {
+        self.scroll_up(self.half_page());
+    }
+
+    /// 半ページの行数。詳細画面は本文の枠の内側の高さから
+    fn half_page(&self) -> usize {
         match self.screen {
-            Screen::ProjectList => {
-                if !self.displayed_projects.is_empty() {
-                    self.selected_project =
-                        (self.selected_project + half).min(self.displayed_projects.len() - 1);
-                }
+            Screen::SessionDetail if !self.overview => self.detail_visible_rows() / 2,
+            _ => self.terminal_height / 2,
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        match self.screen {
+            Screen::SessionDetail if !self.overview => {
+                // 末尾を越えた位置からでもすぐ動くように、描画と同じ上限で丸めてから
+                self.scroll_offset = self.scroll_offset.min(self.detail_max_scroll()).saturating_sub(n);
+                self.select_on_screen();
             }
-            Screen::SessionList => {
-                if !self.filtered_sessions.is_empty() {
-                    self.selected_session =
-                        (self.selected_session + half).min(self.filtered_sessions.len() - 1);
-                }
+            Screen::SessionChanges => {
+                self.changes_scroll_offset = self.changes_scroll_offset.saturating_sub(n);
             }
-            Screen::SessionDetail => {
-                self.scroll_offset += half;
+            Screen::SnapshotView => {
+                self.snapshot_content_scroll = self.snapshot_content_scroll.saturating_sub(n);
             }
-            Screen::GlobalSearch => {
-                if !self.global_search_filtered.is_empty() {
-                    self.global_search_selected = (self.global_search_selected + half)
-                        .min(self.global_search_filtered.len() - 1);
+            Screen::CleanupReview => {
+                self.cleanup_scroll = self.cleanup_scroll.saturating_sub(n);
+            }
+            _ => {
+                if let Some((list, _)) = self.active_list() {
+                    list.up(n);
                 }
             }
         }
         self.ensure_table_scroll();
     }
 
-    pub fn half_page_up(&mut self) {
-        let half = self.terminal_height / 2;
+    fn scroll_down(&mut self, n: usize) {
         match self.screen {
-            Screen::ProjectList => {
-                self.selected_project = self.selected_project.saturating_sub(half);
+            Screen::SessionDetail if !self.overview => {
+                self.scroll_offset = (self.scroll_offset + n).min(self.detail_max_scroll());
+                self.select_on_screen();
             }
-            Screen::SessionList => {
-                self.selected_session = self.selected_session.saturating_sub(half);
+            Screen::SessionChanges => {
+                self.changes_scroll_offset =
+                    (self.changes_scroll_offset + n).min(self.changes_max_scroll());
             }
-            Screen::SessionDetail => {
-                self.scroll_offset = self.scroll_offset.saturating_sub(half);
+            Screen::SnapshotView => {
+                self.snapshot_content_scroll =
+                    (self.snapshot_content_scroll + n).min(self.snapshot_max_scroll());
             }
-            Screen::GlobalSearch => {
-                self.global_search_selected = self.global_search_selected.saturating_sub(half);
+            Screen::CleanupReview => {
+                self.cleanup_scroll = (self.cleanup_scroll + n).min(self.cleanup_max_scroll());
+            }
+            _ => {
+                if let Some((list, len)) = self.active_list() {
+                    list.down(n, len);
+                }
             }
         }
         self.ensure_table_scroll();
@@ -339,71 +1475,163 @@ impl App {
     pub fn cycle_filter_next(&mut self) {
         self.time_filter = self.time_filter.next();
         self.apply_filter();
-        self.selected_session = 0;
-        self.session_scroll_offset = 0;
+        self.session_list.reset();
     }
 
     pub fn cycle_filter_prev(&mut self) {
         self.time_filter = self.time_filter.prev();
         self.apply_filter();
-        self.selected_session = 0;
-        self.session_scroll_offset = 0;
+        self.session_list.reset();
     }
 
-    pub fn go_to_top(&mut self) {
-        match self.screen {
-            Screen::ProjectList => {
-                self.selected_project = 0;
-                self.project_scroll_offset = 0;
-            }
-            Screen::SessionList => {
-                self.selected_session = 0;
-                self.session_scroll_offset = 0;
+    /// 期間の入力を始める。今の期間（プリセットならその日付の範囲）を入れておく
+    pub fn open_date_range_input(&mut self) {
+        if self.screen != Screen::SessionList {
+            return;
+        }
+        self.date_range_input = Some(match self.time_filter {
+            TimeFilter::All => String::new(),
+            filter => filter.as_range(Utc::now()).label(),
+        });
+    }
+
+    pub fn date_range_push(&mut self, ch: char) {
+        if let Some(input) = &mut self.date_range_input {
+            input.push(ch);
+        }
+    }
+
+    pub fn date_range_pop(&mut self) {
+        if let Some(input) = &mut self.date_range_input {
+            input.pop();
+        }
+    }
+
+    pub fn cancel_date_range_input(&mut self) {
+        self.date_range_input = None;
+    }
+
+    /// 入力した期間で絞り込む。空なら期間の絞り込みを外す
+    pub fn confirm_date_range_input(&mut self) {
+        let Some(input) = self.date_range_input.take() else {
+            return;
+        };
+        let filter = if input.trim().is_empty() {
+            TimeFilter::All
+        } else {
+            match TimeFilter::parse_range(&input) {
+                Some(range) => range,
+                None => {
+                    self.status_message =
+                        Some(format!("Not a date range: {} (write it like 2026-01-01..2026-01-15)", input.trim()));
+                    return;
+                }
             }
-            Screen::SessionDetail => {
+        };
+        self.time_filter = filter;
+        self.apply_filter();
+        self.session_list.reset();
+    }
+
+    pub fn toggle_needs_input_filter(&mut self) {
+        self.needs_input_only = !self.needs_input_only;
+        self.apply_filter();
+        self.session_list.reset();
+    }
+
+    /// 高コストセッションのみ表示（コスト降順）を切り替え
+    pub fn toggle_expensive_filter(&mut self) {
+        self.expensive_only = !self.expensive_only;
+        self.apply_filter();
+        self.session_list.reset();
+    }
+
+    /// スクリプトフィルタを順に切り替え（最後の次は解除）
+    pub fn cycle_script_filter(&mut self) {
+        let count = self.scripts.filter_names().len();
+        if count == 0 {
+            self.status_message = Some("No script filters defined".to_string());
+            return;
+        }
+        self.script_filter = match self.script_filter {
+            None => Some(0),
+            Some(i) if i + 1 < count => Some(i + 1),
+            Some(_) => None,
+        };
+        self.apply_filter();
+        self.session_list.reset();
+    }
+
+    pub fn go_to_top(&mut self) {
+        match self.screen {
+            Screen::SessionDetail if !self.overview => {
                 self.scroll_offset = 0;
+                self.selected_message = self.first_shown_message();
             }
-            Screen::GlobalSearch => {
-                self.global_search_selected = 0;
-                self.global_search_scroll_offset = 0;
+            Screen::SessionChanges => {
+                self.changes_scroll_offset = 0;
+            }
+            Screen::SnapshotView => {
+                self.snapshot_content_scroll = 0;
+            }
+            _ => {
+                if let Some((list, _)) = self.active_list() {
+                    list.reset();
+                }
             }
         }
     }
 
     pub fn set_sessions(&mut self, sessions: Vec<SessionInfo>) {
         self.sessions = sessions;
+        self.marked_sessions.clear();
         self.apply_filter();
-        self.selected_session = 0;
-        self.session_scroll_offset = 0;
+        self.session_list.reset();
         self.scroll_offset = 0;
         self.screen = Screen::SessionList;
     }
 
     pub fn set_messages(&mut self, messages: Vec<Message>) {
+        let (messages, sidechain): (Vec<Message>, Vec<Message>) = messages.into_iter().partition(|m| !m.sidechain);
+        self.subagents = analysis::subagents(&messages, sidechain);
+        self.main_conversation = None;
+        self.loop_regions = analysis::detect_loops(&messages);
+        self.thread_marks = analysis::thread_marks(&messages);
+        self.result_calls = analysis::result_calls(&messages);
         self.messages = messages;
+        self.session_actions = SessionActions::default();
         self.scroll_offset = 0;
+        self.selected_message = self.first_shown_message();
+        self.show_full_messages = false;
+        self.expanded_results.clear();
+        self.full_results.clear();
+        self.overview = false;
+        self.overview_list.reset();
+        self.detail_query.clear();
+        self.detail_terms.clear();
+        self.detail_search_active = false;
+        self.detail_match = None;
+        self.detail_cache.get_mut().clear();
         self.screen = Screen::SessionDetail;
+        self.load_bookmarks();
     }
-
     pub fn go_to_bottom(&mut self) {
         match self.screen {
-            Screen::ProjectList => {
-                if !self.displayed_projects.is_empty() {
-                    self.selected_project = self.displayed_projects.len() - 1;
-                }
+            Screen::SessionDetail if !self.overview => {
+                self.scroll_offset = self.detail_max_scroll();
+                self.selected_message = (0..self.messages.len())
+                    .rfind(|&i| self.shows_message_at(i))
+                    .unwrap_or(0);
             }
-            Screen::SessionList => {
-                if !self.filtered_sessions.is_empty() {
-                    self.selected_session = self.filtered_sessions.len() - 1;
-                }
+            Screen::SessionChanges => {
+                self.changes_scroll_offset = self.changes_max_scroll();
             }
-            Screen::SessionDetail => {
-                // Scroll to a large value; the UI will clamp it
-                self.scroll_offset = usize::MAX / 2;
+            Screen::SnapshotView => {
+                self.snapshot_content_scroll = self.snapshot_max_scroll();
             }
-            Screen::GlobalSearch => {
-                if !self.global_search_filtered.is_empty() {
-                    self.global_search_selected = self.global_search_filtered.len() - 1;
+            _ => {
+                if let Some((list, len)) = self.active_list() {
+                    list.bottom(len);
                 }
             }
         }
@@ -412,7 +1640,7 @@ impl App {
 
     /// 検索モードを開始（ProjectList/SessionListのみ）
     pub fn start_search(&mut self) {
-        if self.screen == Screen::SessionDetail {
+        if !matches!(self.screen, Screen::ProjectList | Screen::SessionList) {
             return;
         }
         self.search_active = true;
@@ -461,22 +1689,160 @@ impl App {
                 .cloned()
                 .collect();
         }
-        self.selected_project = 0;
-        self.project_scroll_offset = 0;
+        self.project_list.reset();
 
         // SessionListの場合はfiltered_sessionsも再フィルタ
         if self.screen == Screen::SessionList {
             self.apply_filter();
-            self.session_scroll_offset = 0;
+            self.session_list.offset = 0;
+        }
+    }
+
+    /// 既存のインデックスで GlobalSearch を開き、裏でインデックスを更新する
+    pub fn start_global_search(&mut self) {
+        if self.screen != Screen::ProjectList {
+            return;
+        }
+        let results = indexer::default_db_path()
+            .filter(|p| p.exists())
+            .and_then(|p| SessionIndex::open(&p).ok())
+            .and_then(|index| index.search_all().ok())
+            .unwrap_or_default();
+        self.enter_global_search(results.into_iter().map(search_result_from).collect());
+        self.start_indexing();
+    }
+
+    /// インデックスの更新をバックグラウンドで始める（実行中なら何もしない）
+    pub fn start_indexing(&mut self) {
+        if self.index_rx.is_none() {
+            let options = IndexOptions::from_config(&self.config);
+            self.index_rx = Some(spawn_indexer(options, self.plugins_dir.clone()));
+            self.index_progress = Some(IndexProgress::default());
+        }
+    }
+
+    /// Ctrl-R: 変更のあったセッションを索引し直し、終わったら件数を知らせる
+    pub fn reindex(&mut self) {
+        if !matches!(self.screen, Screen::ProjectList | Screen::GlobalSearch | Screen::IndexActivity) {
+            return;
+        }
+        self.index_requested = true;
+        self.start_indexing();
+    }
+
+    /// インデックス構築スレッドからの通知をすべて取り込む
+    pub fn poll_indexing(&mut self) {
+        loop {
+            let Some(rx) = &self.index_rx else {
+                return;
+            };
+            match rx.try_recv() {
+                Ok(event) => self.apply_index_event(event),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.index_rx = None;
+                    self.index_progress = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn apply_index_event(&mut self, event: IndexEvent) {
+        match event {
+            IndexEvent::Progress(progress, sessions) => {
+                self.index_progress = Some(progress);
+                self.merge_global_search_results(sessions);
+            }
+            IndexEvent::Done(result) => {
+                self.index_progress = None;
+                self.index_rx = None;
+                let requested = std::mem::take(&mut self.index_requested);
+                match result {
+                    Ok((report, projects)) => {
+                        self.last_indexed = Some(Utc::now());
+                        if report.recovered {
+                            let backup = report.backup.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+                            self.status_message = Some(format!(
+                                "The search index was damaged and has been rebuilt ({} sessions, {} bookmarks/favorites/tags/snapshots kept); old file moved to {}",
+                                report.sessions_scanned, report.salvaged_rows, backup
+                            ));
+                        } else if requested || report.has_problems() || self.screen == Screen::GlobalSearch {
+                            // 読めなかったファイルや壊れた行は頼まれていなくても知らせる
+                            self.status_message = Some(report.summary());
+                        }
+                        if !projects.is_empty() {
+                            self.refresh_projects(projects);
+                        }
+                        if self.screen == Screen::IndexActivity {
+                            self.open_index_activity();
+                        }
+                    }
+                    Err(e) => self.status_message = Some(format!("Indexing failed: {}", e)),
+                }
+            }
+        }
+    }
+
+    /// 最新のプロジェクト一覧に差し替える（絞り込み中でなければ表示も更新し、選択は維持）
+    fn refresh_projects(&mut self, projects: Vec<ProjectInfo>) {
+        self.projects = projects;
+        if self.screen != Screen::ProjectList || !self.search_query.is_empty() {
+            return;
+        }
+        let selected = self
+            .displayed_projects
+            .get(self.project_list.selected)
+            .map(|p| p.dir_name.clone());
+        self.displayed_projects = self.projects.clone();
+        let selected = selected
+            .and_then(|dir| self.displayed_projects.iter().position(|p| p.dir_name == dir))
+            .unwrap_or(0);
+        let visible = self.visible_rows(&Screen::ProjectList);
+        self.project_list.select(selected, visible);
+    }
+
+    /// 新しく索引されたセッションを結果に反映（選択中の行は維持）
+    fn merge_global_search_results(&mut self, sessions: Vec<SearchableSession>) {
+        if sessions.is_empty() {
+            return;
+        }
+        let selected_id = self
+            .global_search_filtered
+            .get(self.global_search_list.selected)
+            .map(|r| r.session_id.clone());
+        let expanded = self.global_search_expanded.take();
+        for session in sessions {
+            let result = search_result_from(session);
+            match self
+                .global_search_results
+                .iter()
+                .position(|r| r.session_id == result.session_id)
+            {
+                Some(i) => self.global_search_results[i] = result,
+                None => self.global_search_results.push(result),
+            }
+        }
+        self.global_search_results
+            .sort_by(|a, b| b.last_activity.cmp(&a.last_activity).then_with(|| a.session_id.cmp(&b.session_id)));
+        self.apply_global_search();
+        self.global_search_expanded = expanded;
+        if let Some(id) = selected_id
+            && let Some(i) = self
+                .global_search_filtered
+                .iter()
+                .position(|r| r.session_id == id)
+        {
+            let visible = self.visible_rows(&Screen::GlobalSearch);
+            self.global_search_list.select(i, visible);
         }
     }
 
     pub fn enter_global_search(&mut self, results: Vec<SearchResult>) {
-        self.global_search_results = results.clone();
-        self.global_search_filtered = results;
+        self.global_search_results = results;
+        self.session_tags = self.open_index().and_then(|index| index.session_tags()).unwrap_or_default();
         self.global_search_query.clear();
-        self.global_search_selected = 0;
-        self.global_search_scroll_offset = 0;
+        self.apply_global_search();
         self.screen = Screen::GlobalSearch;
     }
 
@@ -491,904 +1857,5638 @@ impl App {
     }
 
     fn apply_global_search(&mut self) {
-        if self.global_search_query.is_empty() {
-            self.global_search_filtered = self.global_search_results.clone();
+        // `root:名前` と `tag:名前` は絞り込みとして扱い、検索語には含めない
+        let mut roots = Vec::new();
+        let (tags, tokens) = split_tag_terms(&self.global_search_query);
+        let tokens: Vec<String> = tokens
+            .iter()
+            .map(|t| t.to_lowercase())
+            .filter(|t| match t.strip_prefix("root:") {
+                Some(root) => {
+                    roots.push(root.to_string());
+                    false
+                }
+                None => true,
+            })
+            .collect();
+        let candidates = self
+            .global_search_results
+            .iter()
+            .filter(|r| roots.iter().all(|root| r.source_root.to_lowercase().contains(root.as_str())))
+            .filter(|r| self.has_tags(&r.session_id, &tags));
+        if tokens.is_empty() {
+            self.global_search_filtered = candidates.cloned().collect();
         } else {
-            let query = self.global_search_query.to_lowercase();
-            self.global_search_filtered = self
-                .global_search_results
+            self.global_search_filtered = candidates.filter_map(|r| match_search_result(r, &tokens)).collect();
+        }
+        // 同じ会話の再開セッションは最新の1件にまとめる
+        let results = std::mem::take(&mut self.global_search_filtered);
+        let folded = folded_counts(
+            results
                 .iter()
-                .filter_map(|r| {
-                    let mut best_prompt = String::new();
-                    let mut best_indices: Vec<usize> = Vec::new();
-                    let mut found = false;
-                    for prompt in &r.prompts {
-                        let lower = prompt.to_lowercase();
-                        if let Some(byte_pos) = lower.find(&query) {
-                            // byte position -> char index
-                            let char_start = lower[..byte_pos].chars().count();
-                            let char_len = query.chars().count();
-                            best_prompt = prompt.clone();
-                            best_indices = (char_start..char_start + char_len).collect();
-                            found = true;
-                            break;
-                        }
-                    }
-                    if !found {
-                        // プロジェクト名・ブランチ名でもマッチを試す
-                        if r.project_path.to_lowercase().contains(&query)
-                            || r.git_branch.to_lowercase().contains(&query)
-                        {
-                            best_prompt = r.prompts.first().cloned().unwrap_or_default();
-                            found = true;
-                        }
-                    }
-                    if found {
-                        let mut result = r.clone();
-                        result.best_match_prompt = best_prompt;
-                        result.best_match_indices = best_indices;
-                        Some(result)
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+                .map(|r| (r.session_id.as_str(), r.parent_session_id.as_str())),
+        );
+        self.global_search_resumes.clear();
+        for (result, folded) in results.into_iter().zip(folded) {
+            let Some(folded) = folded else { continue };
+            if folded > 0 {
+                self.global_search_resumes.insert(result.session_id.clone(), folded);
+            }
+            self.global_search_filtered.push(result);
         }
-        self.global_search_selected = 0;
-        self.global_search_scroll_offset = 0;
+        self.global_search_list.reset();
+        self.global_search_expanded = None;
     }
 
-    pub fn get_resume_command(&self) -> Option<String> {
-        self.global_search_filtered
-            .get(self.global_search_selected)
-            .map(|r| format!("claude --resume {}", r.session_id))
+    /// 選択中の結果の他のマッチしたプロンプトを展開/折りたたみ
+    pub fn toggle_global_search_expand(&mut self) {
+        let Some(result) = self.global_search_filtered.get(self.global_search_list.selected) else {
+            return;
+        };
+        if self.global_search_expanded.as_deref() == Some(result.session_id.as_str()) {
+            self.global_search_expanded = None;
+        } else {
+            self.global_search_expanded = Some(result.session_id.clone());
+        }
     }
-}
 
-fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) {
-    let _ = disable_raw_mode();
-    let _ = execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    );
-    let _ = terminal.show_cursor();
-}
+    pub fn enter_permission_audit(&mut self, title: String, entries: Vec<PermissionAuditEntry>) {
+        if self.screen != Screen::PermissionAudit {
+            self.audit_return_screen = self.screen.clone();
+        }
+        self.audit_title = title;
+        self.audit_entries = entries;
+        self.audit_list.reset();
+        self.screen = Screen::PermissionAudit;
+    }
 
-pub fn run() -> Result<()> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    /// SessionList ではプロジェクト全体、SessionDetail では表示中のセッションを監査
+    pub fn open_permission_audit(&mut self) {
+        match self.screen {
+            Screen::SessionList => {
+                let entries =
+                    audit::audit_project(&self.current_project_name).unwrap_or_default();
+                let title = format!("Project: {}", self.current_project_name);
+                self.enter_permission_audit(title, entries);
+            }
+            Screen::SessionDetail => {
+                let entries =
+                    audit::audit_session(&self.current_project_name, &self.current_session_id)
+                        .unwrap_or_default();
+                let title = format!("Session: {}", self.current_session_id);
+                self.enter_permission_audit(title, entries);
+            }
+            _ => {}
+        }
+    }
 
-    // Restore terminal on panic
-    let default_panic = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |info| {
-        let _ = disable_raw_mode();
-        let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture);
-        default_panic(info);
-    }));
+    /// 期間フィルタの範囲でプロジェクトの活動を索引から集計してパネルに出す
+    pub fn open_project_activity(&mut self) {
+        let since = self.time_filter.cutoff(Utc::now()).map(|t| t.timestamp());
+        let until = self.time_filter.until().map(|t| t.timestamp());
+        let activity = indexer::default_db_path()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine cache directory"))
+            .and_then(|p| SessionIndex::open(&p))
+            .and_then(|index| index.project_activity(&self.current_project_name, since, until, PROJECT_ACTIVITY_TOP));
+        match activity {
+            Ok(activity) => self.project_activity = Some(activity),
+            Err(e) => self.status_message = Some(format!("Could not read the index: {}", e)),
+        }
+    }
 
-    let mut app = App::new();
+    /// 索引に記録した最近の変更（追加・更新・期限切れ）の一覧を、
+    /// apply-retention の記録と合わせて開く
+    pub fn open_index_activity(&mut self) {
+        let changes = indexer::default_db_path()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine cache directory"))
+            .and_then(|p| SessionIndex::open(&p))
+            .and_then(|index| {
+                Ok((index.recent_changes(INDEX_ACTIVITY_LIMIT)?, index.retention_journal(INDEX_ACTIVITY_LIMIT)?))
+            });
+        match changes {
+            Ok((changes, journal)) => {
+                self.set_index_changes(changes);
+                self.retention_journal = journal;
+            }
+            Err(e) => self.status_message = Some(format!("Could not read the index: {}", e)),
+        }
+    }
 
-    let result = run_loop(&mut terminal, &mut app);
+    pub fn set_index_changes(&mut self, changes: Vec<IndexChange>) {
+        self.index_changes = changes;
+        self.index_changes_list.reset();
+        self.screen = Screen::IndexActivity;
+    }
 
-    restore_terminal(&mut terminal);
+    pub fn close_project_activity(&mut self) {
+        self.project_activity = None;
+    }
 
-    result
-}
+    /// プロンプトとツール呼び出しを時間軸に並べたタイムラインを開く
+    pub fn open_timeline(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
+        }
+        match analysis::timeline(&self.messages, &self.result_calls) {
+            Some(timeline) => self.session_timeline = Some(timeline),
+            None => self.status_message = Some("No timestamps in this session".to_string()),
+        }
+    }
 
-fn run_loop(
-    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-    app: &mut App,
-) -> Result<()> {
-    loop {
-        terminal.draw(|frame| {
-            app.terminal_height = frame.area().height as usize;
-            ui::draw(frame, app);
-        })?;
+    pub fn close_timeline(&mut self) {
+        self.session_timeline = None;
+    }
 
-        if let Event::Key(key) = event::read()? {
-            if app.screen == Screen::GlobalSearch {
-                match key.code {
-                    KeyCode::Esc => app.go_back(),
-                    KeyCode::Enter => {
-                        if let Some(result) =
-                            app.global_search_filtered.get(app.global_search_selected)
-                        {
-                            let dir_name = result.dir_name.clone();
-                            let session_id = result.session_id.clone();
-                            app.current_project_name = dir_name;
-                            if let Ok(msgs) =
-                                parser::load_session(&app.current_project_name, &session_id)
-                            {
-                                app.messages = msgs;
-                                app.scroll_offset = 0;
-                                app.screen = Screen::SessionDetail;
-                            }
-                        }
-                    }
-                    KeyCode::Char('y') => {
-                        if let Some(cmd) = app.get_resume_command() {
-                            let _ = cli_clipboard::set_contents(cmd);
-                        }
-                    }
-                    KeyCode::Char('j') | KeyCode::Down => app.navigate_down(),
-                    KeyCode::Char('k') | KeyCode::Up => app.navigate_up(),
-                    KeyCode::Char('d') => app.half_page_down(),
-                    KeyCode::Char('u') => app.half_page_up(),
-                    KeyCode::Char('g') => app.go_to_top(),
-                    KeyCode::Char('G') => app.go_to_bottom(),
-                    KeyCode::Backspace => app.global_search_pop(),
-                    KeyCode::Char(c) => app.global_search_push(c),
-                    _ => {}
-                }
-            } else if app.search_active {
-                match key.code {
-                    KeyCode::Esc => app.cancel_search(),
-                    KeyCode::Enter => app.confirm_search(),
-                    KeyCode::Backspace => app.search_pop(),
-                    KeyCode::Down => app.navigate_down(),
-                    KeyCode::Up => app.navigate_up(),
-                    KeyCode::Char(c) => app.search_push(c),
-                    _ => {}
-                }
-            } else {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Esc => {
-                        app.go_back();
-                    }
-                    KeyCode::Char('/') => {
-                        app.start_search();
-                    }
-                    KeyCode::Char('s') => {
-                        if app.screen == Screen::ProjectList {
-                            if let Ok(db_path) = crate::indexer::build_default_index() {
-                                if let Ok(index) =
-                                    crate::index::SessionIndex::open(&db_path)
-                                {
-                                    if let Ok(sessions) = index.search_all() {
-                                        let results: Vec<SearchResult> = sessions
-                                            .into_iter()
-                                            .map(|s| SearchResult {
-                                                session_id: s.session_id,
-                                                project_path: s.project_path,
-                                                dir_name: s.dir_name,
-                                                git_branch: s.git_branch,
-                                                created_at: s.created_at,
-                                                prompts: s.prompts,
-                                                best_match_prompt: String::new(),
-                                                best_match_indices: Vec::new(),
-                                            })
-                                            .collect();
-                                        app.enter_global_search(results);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    KeyCode::Enter => match app.screen {
-                        Screen::ProjectList => app.enter_session_list(),
-                        Screen::SessionList => app.enter_session_detail(),
-                        Screen::SessionDetail => {}
-                        Screen::GlobalSearch => {}
-                    },
-                    KeyCode::Char('j') | KeyCode::Down => {
-                        app.navigate_down();
-                    }
-                    KeyCode::Char('k') | KeyCode::Up => {
-                        app.navigate_up();
-                    }
-                    KeyCode::Tab => {
-                        if app.screen == Screen::SessionList {
-                            app.cycle_filter_next();
-                        }
-                    }
-                    KeyCode::BackTab => {
-                        if app.screen == Screen::SessionList {
-                            app.cycle_filter_prev();
-                        }
-                    }
-                    KeyCode::Char('d') => {
-                        app.half_page_down();
-                    }
-                    KeyCode::Char('u') => {
-                        app.half_page_up();
-                    }
-                    KeyCode::Char('g') => {
-                        app.go_to_top();
-                    }
-                    KeyCode::Char('G') => {
-                        app.go_to_bottom();
-                    }
-                    _ => {}
-                }
-            }
+    /// 選択中のメッセージの元になった JSONL の1行を整形して表示する
+    pub fn open_raw_json(&mut self) {
+        if self.screen != Screen::SessionDetail {
+            return;
         }
+        let Some(pretty) = self.messages.get(self.selected_message).and_then(Message::pretty_source_line) else {
+            self.status_message = Some("No raw entry for the selected message".to_string());
+            return;
+        };
+        self.raw_json = Some(pretty);
+        self.raw_json_scroll = 0;
+    }
 
-        if app.should_quit {
-            break;
-        }
+    /// 目次に並べる自分のプロンプト（メッセージの添字）
+    pub fn toc_prompts(&self) -> Vec<usize> {
+        (0..self.messages.len())
+            .filter(|&i| self.messages[i].role == MessageRole::User && !self.messages[i].text.trim().is_empty())
+            .collect()
     }
 
-    Ok(())
-}
+    /// 目次のポップアップに収まる行数
+    pub fn toc_visible_rows(&self) -> usize {
+        self.terminal_height.saturating_sub(CHROME_ROWS + POPUP_MARGIN_ROWS).max(1)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// プロンプトの目次を開く。今いる位置のプロンプトを選んでおく
+    pub fn open_prompt_toc(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
+        }
+        let prompts = self.toc_prompts();
+        if prompts.is_empty() {
+            self.status_message = Some("No prompts in this session".to_string());
+            return;
+        }
+        let current = prompts.iter().rposition(|&i| i <= self.selected_message).unwrap_or(0);
+        let mut list = ListState::default();
+        list.select(current, self.toc_visible_rows());
+        self.prompt_toc = Some(list);
+    }
 
-    fn make_project(name: &str) -> ProjectInfo {
-        ProjectInfo {
-            dir_name: name.to_string(),
-            original_path: format!("/path/{}", name),
-            session_count: 0,
+    pub fn close_prompt_toc(&mut self) {
+        self.prompt_toc = None;
+    }
+
+    pub fn prompt_toc_move(&mut self, down: bool, n: usize) {
+        let len = self.toc_prompts().len();
+        let visible = self.toc_visible_rows();
+        if let Some(list) = self.prompt_toc.as_mut() {
+            if down {
+                list.down(n.min(len), len);
+            } else {
+                list.up(n);
+            }
+            list.scroll_into_view(visible);
         }
     }
 
-    fn make_session(id: &str) -> SessionInfo {
-        SessionInfo {
-            session_id: id.to_string(),
-            project_name: "test".to_string(),
-            preview: format!("Preview {}", id),
-            timestamp: Some(chrono::Utc::now()),
-            message_count: 0,
-            git_branch: String::new(),
-            summary: String::new(),
+    /// 目次で選んだプロンプトを画面の先頭に出す。隠れていれば次に見えるメッセージへ
+    pub fn confirm_prompt_toc(&mut self) {
+        let Some(list) = self.prompt_toc.take() else {
+            return;
+        };
+        let Some(&prompt) = self.toc_prompts().get(list.selected) else {
+            return;
+        };
+        let Some(target) = (prompt..self.messages.len()).find(|&i| self.shows_message_at(i)) else {
+            self.status_message = Some("Nothing shown after that prompt".to_string());
+            return;
+        };
+        self.selected_message = target;
+        let mut cache = self.detail_cache.borrow_mut();
+        let width = cache.width();
+        cache.sync(self, width);
+        self.scroll_offset = cache.message_start(target).unwrap_or(0);
+    }
+
+    /// 描画行の範囲選択を始める。カーソルは選択中のメッセージの先頭（画面外なら画面の端）
+    pub fn start_visual(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
         }
+        let visible = self.detail_visible_rows().max(1);
+        let mut cache = self.detail_cache.borrow_mut();
+        let width = cache.width();
+        cache.sync(self, width);
+        let total = cache.total_rows();
+        if total == 0 {
+            return;
+        }
+        let top = self.scroll_offset.min(total.saturating_sub(visible));
+        let row = cache
+            .message_rows(self.selected_message)
+            .start
+            .clamp(top, (top + visible).min(total) - 1);
+        self.visual = Some((row, row));
     }
 
-    fn make_message(role: MessageRole, text: &str) -> Message {
-        Message {
-            role,
-            text: text.to_string(),
-            timestamp: None,
-            tool_name: None,
+    pub fn cancel_visual(&mut self) {
+        self.visual = None;
+    }
+
+    /// カーソルを動かして選択を広げ、カーソル行が見えるようにスクロールする
+    pub fn visual_move(&mut self, down: bool, n: usize) {
+        let total = self.detail_cache.borrow().total_rows();
+        let visible = self.detail_visible_rows().max(1);
+        let Some((_, cursor)) = self.visual.as_mut() else {
+            return;
+        };
+        *cursor = if down {
+            cursor.saturating_add(n).min(total.saturating_sub(1))
+        } else {
+            cursor.saturating_sub(n)
+        };
+        let cursor = *cursor;
+        let top = self.scroll_offset.min(total.saturating_sub(visible));
+        if cursor < top {
+            self.scroll_offset = cursor;
+        } else if cursor >= top + visible {
+            self.scroll_offset = cursor + 1 - visible;
+        } else {
+            self.scroll_offset = top;
         }
     }
 
-    // ===== ナビゲーションテスト =====
+    /// 選択中の描画行（両端を含む）
+    pub fn visual_range(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        self.visual.map(|(anchor, cursor)| anchor.min(cursor)..=anchor.max(cursor))
+    }
 
-    #[test]
-    fn navigate_down_project_list() {
-        let mut app = App::with_projects(vec![
-            make_project("a"),
-            make_project("b"),
-            make_project("c"),
-        ]);
-        assert_eq!(app.selected_project, 0);
-        app.navigate_down();
-        assert_eq!(app.selected_project, 1);
-        app.navigate_down();
-        assert_eq!(app.selected_project, 2);
+    /// 選択した行の文字を取り出して選択を終える
+    pub fn yank_visual(&mut self) -> Option<String> {
+        let range = self.visual_range()?;
+        self.visual = None;
+        let lines = range.end() - range.start() + 1;
+        let text = self.detail_cache.borrow().rows_text(range);
+        self.status_message = Some(format!("Copied {} line{}", lines, if lines == 1 { "" } else { "s" }));
+        Some(text)
+    }
+
+    fn open_index(&self) -> Result<SessionIndex> {
+        let path = self.index_path.as_ref().ok_or_else(|| anyhow::anyhow!("Could not determine cache directory"))?;
+        SessionIndex::open(path)
+    }
+
+    /// 開いたセッションのブックマークを読み、今のメッセージの位置に合わせる
+    fn load_bookmarks(&mut self) {
+        let bookmarks = self
+            .open_index()
+            .and_then(|index| index.session_bookmarks(&self.current_session_id))
+            .unwrap_or_default();
+        self.bookmarked = bookmarks.iter().map(|b| self.locate_bookmark(b)).collect();
+    }
+
+    /// ブックマークしたメッセージの位置。uuid が同じ位置に無ければ uuid で探し直す
+    fn locate_bookmark(&self, bookmark: &Bookmark) -> usize {
+        let uuid_at = |i: usize| self.messages.get(i).and_then(Message::source_uuid);
+        if bookmark.message_uuid.is_empty() || uuid_at(bookmark.message_index).as_deref() == Some(&bookmark.message_uuid) {
+            return bookmark.message_index;
+        }
+        (0..self.messages.len())
+            .find(|&i| uuid_at(i).as_deref() == Some(&bookmark.message_uuid))
+            .unwrap_or(bookmark.message_index)
+    }
+
+    /// 選択中のメッセージのブックマークを外すか、メモの入力を始める
+    pub fn toggle_bookmark(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
+        }
+        if self.main_conversation.is_some() {
+            self.status_message = Some("Bookmarks are not available in a subagent's conversation".to_string());
+            return;
+        }
+        let Some(msg) = self.messages.get(self.selected_message) else {
+            return;
+        };
+        if !self.bookmarked.contains(&self.selected_message) {
+            let first = msg.text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+            self.bookmark_note = Some(first.chars().take(BOOKMARK_NOTE_PREFILL).collect());
+            return;
+        }
+        let removed = self
+            .open_index()
+            .and_then(|index| index.remove_bookmark(&self.current_session_id, self.selected_message));
+        match removed {
+            Ok(()) => {
+                self.bookmarked.remove(&self.selected_message);
+                self.status_message = Some("Bookmark removed".to_string());
+            }
+            Err(e) => self.status_message = Some(format!("Could not remove the bookmark: {}", e)),
+        }
+    }
+
+    pub fn bookmark_note_push(&mut self, ch: char) {
+        if let Some(note) = &mut self.bookmark_note {
+            note.push(ch);
+        }
+    }
+
+    pub fn bookmark_note_pop(&mut self) {
+        if let Some(note) = &mut self.bookmark_note {
+            note.pop();
+        }
+    }
+
+    pub fn cancel_bookmark_note(&mut self) {
+        self.bookmark_note = None;
+    }
+
+    /// 入力したメモで選択中のメッセージをブックマークする
+    pub fn confirm_bookmark_note(&mut self) {
+        let Some(note) = self.bookmark_note.take() else {
+            return;
+        };
+        let bookmark = Bookmark {
+            session_id: self.current_session_id.clone(),
+            dir_name: self.current_project_name.clone(),
+            message_index: self.selected_message,
+            message_uuid: self
+                .messages
+                .get(self.selected_message)
+                .and_then(Message::source_uuid)
+                .unwrap_or_default(),
+            note: note.trim().to_string(),
+            created_at: Utc::now().timestamp(),
+        };
+        match self.open_index().and_then(|index| index.add_bookmark(&bookmark)) {
+            Ok(()) => {
+                self.bookmarked.insert(self.selected_message);
+                self.status_message = Some("Bookmarked".to_string());
+            }
+            Err(e) => self.status_message = Some(format!("Could not save the bookmark: {}", e)),
+        }
+    }
+
+    /// 全セッションのブックマーク一覧を開く
+    pub fn open_bookmarks(&mut self) {
+        match self.open_index().and_then(|index| index.bookmarks()) {
+            Ok(bookmarks) if bookmarks.is_empty() => {
+                self.status_message = Some("No bookmarks yet (m marks a message in a session)".to_string());
+            }
+            Ok(bookmarks) => {
+                self.bookmarks = bookmarks;
+                self.bookmark_list = Some(ListState::default());
+            }
+            Err(e) => self.status_message = Some(format!("Could not read the index: {}", e)),
+        }
+    }
+
+    pub fn close_bookmarks(&mut self) {
+        self.bookmark_list = None;
+    }
+
+    pub fn bookmark_list_move(&mut self, down: bool, n: usize) {
+        let len = self.bookmarks.len();
+        let visible = self.toc_visible_rows();
+        if let Some(list) = self.bookmark_list.as_mut() {
+            if down {
+                list.down(n.min(len), len);
+            } else {
+                list.up(n);
+            }
+            list.scroll_into_view(visible);
+        }
+    }
+
+    /// 一覧で選んだブックマークを消す。最後の1つなら一覧を閉じる
+    pub fn delete_selected_bookmark(&mut self) {
+        let Some(selected) = self.bookmark_list.map(|l| l.selected) else {
+            return;
+        };
+        let Some(bookmark) = self.bookmarks.get(selected).cloned() else {
+            return;
+        };
+        if let Err(e) = self
+            .open_index()
+            .and_then(|index| index.remove_bookmark(&bookmark.session_id, bookmark.message_index))
+        {
+            self.status_message = Some(format!("Could not remove the bookmark: {}", e));
+            return;
+        }
+        self.bookmarks.remove(selected);
+        if bookmark.session_id == self.current_session_id {
+            self.bookmarked.remove(&self.locate_bookmark(&bookmark));
+        }
+        let visible = self.toc_visible_rows();
+        if self.bookmarks.is_empty() {
+            self.bookmark_list = None;
+        } else if let Some(list) = self.bookmark_list.as_mut() {
+            list.selected = selected.min(self.bookmarks.len() - 1);
+            list.scroll_into_view(visible);
+        }
+    }
+
+    /// 一覧で選んだブックマークのセッションを開き、そのメッセージを画面の先頭に出す
+    pub fn confirm_bookmark(&mut self) {
+        let Some(list) = self.bookmark_list.take() else {
+            return;
+        };
+        let Some(bookmark) = self.bookmarks.get(list.selected).cloned() else {
+            return;
+        };
+        let messages = match parser::load_session(&bookmark.dir_name, &bookmark.session_id) {
+            Ok(messages) => messages,
+            Err(e) => {
+                self.status_message = Some(format!("Could not open the session: {}", e));
+                return;
+            }
+        };
+        // 戻った先と Ctrl-n/p がそのプロジェクトのセッション一覧になるように
+        if self.current_project_name != bookmark.dir_name || self.sessions.is_empty() {
+            self.current_project_name = bookmark.dir_name.clone();
+            self.search_query.clear();
+            self.show_archived = false;
+            self.load_session_list();
+        }
+        if let Some(pos) = self.filtered_sessions.iter().position(|s| s.session_id == bookmark.session_id) {
+            self.session_list.selected = pos;
+            let visible = self.visible_rows(&Screen::SessionList);
+            self.session_list.scroll_into_view(visible);
+        }
+        self.current_session_id = bookmark.session_id.clone();
+        self.set_messages(messages);
+        self.session_actions = actions::session_actions(&self.current_project_name, &self.current_session_id)
+            .unwrap_or_default();
+        self.fire_open_session_hook();
+        self.jump_to_message(self.locate_bookmark(&bookmark));
+    }
+
+    /// メッセージ `i`（隠れていれば次に見えるもの）を選び、画面の先頭に出す
+    fn jump_to_message(&mut self, i: usize) {
+        let Some(target) = (i..self.messages.len()).find(|&i| self.shows_message_at(i)) else {
+            return;
+        };
+        self.selected_message = target;
+        let mut cache = self.detail_cache.borrow_mut();
+        let width = cache.width();
+        cache.sync(self, width);
+        self.scroll_offset = cache.message_start(target).unwrap_or(0);
+    }
+
+    /// 選択中の回答のコードブロックを取り出す。1つだけならそのまま返し、
+    /// 複数あれば番号付きのピッカーを開く
+    pub fn open_code_blocks(&mut self) -> Option<String> {
+        if self.screen != Screen::SessionDetail {
+            return None;
+        }
+        let msg = self.messages.get(self.selected_message)?;
+        if msg.role != MessageRole::Assistant {
+            self.status_message = Some("The selected message is not an answer".to_string());
+            return None;
+        }
+        self.code_blocks = markdown::code_blocks(&msg.text);
+        match self.code_blocks.len() {
+            0 => {
+                self.status_message = Some("No code blocks in the selected answer".to_string());
+                None
+            }
+            1 => self.take_code_block(0),
+            _ => {
+                self.code_picker = Some(0);
+                None
+            }
+        }
+    }
+
+    pub fn close_code_picker(&mut self) {
+        self.code_picker = None;
+        self.code_blocks.clear();
+    }
+
+    pub fn code_picker_down(&mut self) {
+        if let Some(i) = self.code_picker.as_mut()
+            && *i + 1 < self.code_blocks.len()
+        {
+            *i += 1;
+        }
+    }
+
+    pub fn code_picker_up(&mut self) {
+        if let Some(i) = self.code_picker.as_mut() {
+            *i = i.saturating_sub(1);
+        }
+    }
+
+    /// ピッカーで選んだコードブロックを返す（`n` は 0 始まり、None なら選択位置）
+    pub fn confirm_code_block(&mut self, n: Option<usize>) -> Option<String> {
+        let i = n.or(self.code_picker)?;
+        if i >= self.code_blocks.len() {
+            return None;
+        }
+        self.take_code_block(i)
+    }
+
+    /// コピーする内容を返してピッカーを閉じる。ステータスにはコピーしたものを出す
+    fn take_code_block(&mut self, i: usize) -> Option<String> {
+        let block = self.code_blocks.get(i)?.clone();
+        let lang = if block.lang.is_empty() { String::new() } else { format!("{}, ", block.lang) };
+        self.status_message = Some(format!(
+            "Copied code block {}/{} ({}{} lines)",
+            i + 1,
+            self.code_blocks.len(),
+            lang,
+            block.code.lines().count()
+        ));
+        self.close_code_picker();
+        Some(block.code)
+    }
+
+    pub fn close_raw_json(&mut self) {
+        self.raw_json = None;
+        self.raw_json_scroll = 0;
+    }
+
+    pub fn scroll_raw_json(&mut self, down: bool, n: usize) {
+        self.raw_json_scroll = if down {
+            self.raw_json_scroll.saturating_add(n).min(self.raw_json_max_scroll())
+        } else {
+            self.raw_json_scroll.saturating_sub(n)
+        };
+    }
+
+    fn raw_json_max_scroll(&self) -> usize {
+        // 画面の本文いっぱいに重ねる
+        let visible = self.terminal_height.saturating_sub(CHROME_ROWS);
+        self.raw_json.as_ref().map_or(0, |j| j.lines().count().saturating_sub(visible))
+    }
+
+    /// 監査レポートをカレントディレクトリに TSV で書き出す
+    pub fn export_permission_audit(&mut self) {
+        let slug: String = self
+            .audit_title
+            .split_once(": ")
+            .map(|(_, name)| name)
+            .unwrap_or("report")
+            .trim_start_matches('-')
+            .chars()
+            .take(40)
+            .collect();
+        let file_name = format!("permission-audit-{}.tsv", slug);
+        let report = audit::format_report_tsv(&self.audit_entries);
+        self.status_message = Some(match std::fs::write(&file_name, report) {
+            Ok(()) => {
+                self.fire_export_hook("permission-audit", std::path::Path::new(&file_name));
+                format!("Exported {} entries to {}", self.audit_entries.len(), file_name)
+            }
+            Err(e) => format!("Export failed: {}", e),
+        });
+    }
+
+    /// プロンプトだけのダイジェストの見出しと対象セッション (id, タイトル)
+    /// SessionList では絞り込み後の一覧（期間フィルタ込み）、SessionDetail では表示中のセッション
+    fn prompt_digest_target(&self) -> Option<(String, Vec<(String, String)>)> {
+        let title_of = |s: &SessionInfo| {
+            if s.summary.is_empty() { s.preview.clone() } else { s.summary.clone() }
+        };
+        match self.screen {
+            Screen::SessionList => {
+                let marked = self.marked_in_list();
+                let heading = if marked.is_empty() {
+                    format!("Prompts: {} ({})", self.current_project_name, self.time_filter.label())
+                } else {
+                    format!("Prompts: {} ({} marked)", self.current_project_name, marked.len())
+                };
+                let targets = if marked.is_empty() { self.filtered_sessions.iter().collect() } else { marked }
+                    .into_iter()
+                    .map(|s| (s.session_id.clone(), title_of(s)))
+                    .collect();
+                Some((heading, targets))
+            }
+            Screen::SessionDetail => {
+                let title = self
+                    .sessions
+                    .iter()
+                    .find(|s| s.session_id == self.current_session_id)
+                    .map(title_of)
+                    .unwrap_or_default();
+                let heading = format!("Prompts: {}", self.current_session_id);
+                Some((heading, vec![(self.current_session_id.clone(), title)]))
+            }
+            _ => None,
+        }
+    }
+
+    /// プロンプトのダイジェストを作る: (ファイル名, Markdown, プロンプト数)
+    fn build_prompt_digest(&self) -> Option<(String, String, usize)> {
+        let (heading, targets) = self.prompt_digest_target()?;
+        let sessions: Vec<digest::DigestSession> = targets
+            .iter()
+            .filter_map(|(id, title)| digest::digest_session(&self.current_project_name, id, title).ok())
+            .collect();
+        let prompt_count: usize = sessions.iter().map(|s| s.prompts.len()).sum();
+        let name = if self.screen == Screen::SessionDetail {
+            &self.current_session_id
+        } else {
+            &self.current_project_name
+        };
+        let slug: String = name
+            .trim_start_matches('-')
+            .chars()
+            .take(40)
+            .collect();
+        let file_name = format!("prompts-{}.md", slug);
+        let report = digest::format_digest_markdown(&heading, &sessions);
+        Some((file_name, report, prompt_count))
+    }
+
+    /// 自分のプロンプトだけを時刻付きの箇条書きにしてカレントディレクトリに書き出す
+    pub fn export_prompt_digest(&mut self) {
+        let Some((file_name, report, prompt_count)) = self.build_prompt_digest() else {
+            return;
+        };
+        self.status_message = Some(match std::fs::write(&file_name, report) {
+            Ok(()) => {
+                self.fire_export_hook("prompts", std::path::Path::new(&file_name));
+                format!("Exported {} prompts to {}", prompt_count, file_name)
+            }
+            Err(e) => format!("Export failed: {}", e),
+        });
+    }
+
+    /// いま書き出せるもの（行選択中なら選択行、そうでなければプロンプトのダイジェスト）を
+    /// share_command で公開する前に、何を何行送るかを見せて確認する
+    pub fn share_export(&mut self) {
+        if self.config.share_command.is_empty() {
+            self.status_message = Some("No share_command configured in config.toml".to_string());
+            return;
+        }
+        if self.share_rx.is_some() {
+            self.status_message = Some("Still sharing the previous export".to_string());
+            return;
+        }
+        let (what, text) = match self.visual_range() {
+            Some(range) => {
+                self.visual = None;
+                let lines = range.end() - range.start() + 1;
+                let text = self.detail_cache.borrow().rows_text(range);
+                (format!("{} line{}", lines, if lines == 1 { "" } else { "s" }), text)
+            }
+            None => {
+                let Some((_, report, prompt_count)) = self.build_prompt_digest() else {
+                    return;
+                };
+                (format!("{} prompts", prompt_count), report)
+            }
+        };
+        self.share_pending = Some(PendingShare { what, text, anonymized: false });
+    }
+
+    /// 確認中の内容からプロジェクトのパス・ユーザー名・秘密情報を除く
+    pub fn anonymize_pending_share(&mut self) {
+        let anonymizer = match self.open_index().and_then(|index| Anonymizer::for_index(&index)) {
+            Ok(anonymizer) => anonymizer,
+            Err(e) => {
+                // 匿名化できないまま送らないよう、確認ごと取りやめる
+                self.share_pending = None;
+                self.status_message = Some(format!("Could not anonymize, nothing shared: {}", e));
+                return;
+            }
+        };
+        if let Some(pending) = &mut self.share_pending {
+            pending.text = anonymizer.text(&pending.text);
+            pending.anonymized = true;
+        }
+    }
+
+    pub fn cancel_share(&mut self) {
+        self.share_pending = None;
+    }
+
+    /// 確認した内容を share_command に渡す。コマンドは別スレッドで走らせ、結果は `poll_share` で受け取る
+    pub fn confirm_share(&mut self) {
+        let Some(PendingShare { what, text, .. }) = self.share_pending.take() else {
+            return;
+        };
+        let (tx, rx) = mpsc::channel();
+        let command_line = self.config.share_command.clone();
+        thread::spawn(move || {
+            let _ = tx.send((what, commands::run_piped(&command_line, &text)));
+        });
+        self.share_rx = Some(rx);
+        self.status_message = Some("Sharing…".to_string());
+    }
+
+    /// 公開が終わっていれば結果を知らせ、URL をクリップボードに写す
+    pub fn poll_share(&mut self) {
+        let Some(rx) = &self.share_rx else {
+            return;
+        };
+        let (what, result) = match rx.try_recv() {
+            Ok(outcome) => outcome,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                self.share_rx = None;
+                return;
+            }
+        };
+        self.share_rx = None;
+        match result.as_deref().map(commands::first_url) {
+            Ok(Some(url)) => {
+                let url = url.to_string();
+                self.status_message = Some(format!("Shared {}: {}, copied", what, url));
+                copy_to_clipboard(self, url);
+            }
+            Ok(None) => self.status_message = Some("Share command printed no URL".to_string()),
+            Err(e) => self.status_message = Some(format!("Share failed: {}", e)),
+        }
+    }
+
+    /// 表示中のセッションがファイルに加えた変更を再構成して表示
+    pub fn open_session_changes(&mut self) {
+        if self.screen != Screen::SessionDetail {
+            return;
+        }
+        let changes = changes::session_changes(&self.current_project_name, &self.current_session_id)
+            .unwrap_or_default();
+        self.set_file_changes(changes);
+    }
+
+    pub fn set_file_changes(&mut self, changes: Vec<FileChange>) {
+        self.file_changes = changes;
+        self.changes_cache.get_mut().clear();
+        self.changes_scroll_offset = 0;
+        self.screen = Screen::SessionChanges;
+    }
+
+    /// Total rendered lines of the changes view: a header, the diff and a
+    /// blank separator per file.
+    pub fn changes_line_count(&self) -> usize {
+        self.file_changes
+            .iter()
+            .map(|c| c.diff.lines().count() + 2)
+            .sum()
+    }
+
+    fn changes_max_scroll(&self) -> usize {
+        let visible = self.visible_rows(&Screen::SessionChanges);
+        self.changes_line_count().saturating_sub(visible)
+    }
+
+    /// ハイライトする検索語を設定（描画行は作り直す）
+    pub fn set_detail_query(&mut self, query: &str) {
+        self.detail_query = query.trim().to_string();
+        self.highlights_changed();
+    }
+
+    fn highlights_changed(&mut self) {
+        self.detail_match = None;
+        self.detail_cache.get_mut().clear();
+    }
+
+    /// 色分けして表示するクエリ（固定した語、入力中の語の順）
+    pub fn highlight_queries(&self) -> Vec<&str> {
+        let mut queries: Vec<&str> = self.detail_terms.iter().map(String::as_str).collect();
+        let current = self.detail_query.trim();
+        if !current.is_empty() {
+            queries.push(current);
+        }
+        queries
+    }
+
+    pub fn start_detail_search(&mut self) {
+        if self.screen == Screen::SessionDetail {
+            self.detail_search_active = true;
+        }
+    }
+
+    /// `+` は入力中の語を固定して次の語の入力に移る
+    pub fn detail_search_push(&mut self, ch: char) {
+        if ch == '+' {
+            self.pin_detail_query();
+            return;
+        }
+        self.detail_query.push(ch);
+        self.highlights_changed();
+    }
+
+    /// 入力が空なら直前に固定した語を編集に戻す
+    pub fn detail_search_pop(&mut self) {
+        if self.detail_query.is_empty() {
+            if let Some(term) = self.detail_terms.pop() {
+                self.detail_query = term;
+            }
+        } else {
+            self.detail_query.pop();
+        }
+        self.highlights_changed();
+    }
+
+    fn pin_detail_query(&mut self) {
+        let term = self.detail_query.trim().to_string();
+        if term.is_empty() {
+            return;
+        }
+        if self.detail_terms.len() + 1 >= MAX_HIGHLIGHT_TERMS {
+            self.status_message = Some(format!("At most {} highlight terms", MAX_HIGHLIGHT_TERMS));
+            return;
+        }
+        self.detail_terms.push(term);
+        self.detail_query.clear();
+        self.highlights_changed();
+    }
+
+    pub fn confirm_detail_search(&mut self) {
+        self.detail_search_active = false;
+        if !self.highlight_queries().is_empty() {
+            self.jump_to_match(true);
+        }
+    }
+
+    /// 入力中の語だけを取り消す（固定した語は残す）
+    pub fn cancel_detail_search(&mut self) {
+        self.detail_search_active = false;
+        self.detail_query.clear();
+        self.highlights_changed();
+    }
+
+    /// 次（forward）/前の検索語の出現位置へスクロール
+    pub fn jump_to_match(&mut self, forward: bool) {
+        let queries = self.highlight_queries();
+        if self.screen != Screen::SessionDetail || queries.is_empty() {
+            return;
+        }
+        let label = queries.join("\", \"");
+        let rows = {
+            let mut cache = self.detail_cache.borrow_mut();
+            let width = cache.width();
+            cache.sync(self, width);
+            cache.match_rows.clone()
+        };
+        if rows.is_empty() {
+            self.status_message = Some(format!("No matches for \"{}\"", label));
+            return;
+        }
+        let idx = match (self.detail_match, forward) {
+            (Some(i), true) => (i + 1) % rows.len(),
+            (Some(i), false) => (i + rows.len() - 1) % rows.len(),
+            (None, true) => rows.iter().position(|&r| r >= self.scroll_offset).unwrap_or(0),
+            (None, false) => rows
+                .iter()
+                .rposition(|&r| r < self.scroll_offset)
+                .unwrap_or(rows.len() - 1),
+        };
+        self.detail_match = Some(idx);
+        // 少し上の文脈も見えるように
+        self.scroll_offset = rows[idx].saturating_sub(2);
+        if let Some(i) = self.detail_cache.borrow().message_at_row(rows[idx]) {
+            self.selected_message = i;
+        }
+    }
+
+    /// 長いメッセージの全文表示を切り替え
+    pub fn toggle_full_messages(&mut self) {
+        if self.screen == Screen::SessionDetail {
+            self.show_full_messages = !self.show_full_messages;
+            self.detail_cache.get_mut().clear();
+        }
+    }
+
+    /// 元の JSONL を並べる分割表示を切り替え。幅が変わるので行は描画時に組み直す
+    pub fn toggle_raw_pane(&mut self) {
+        if self.screen == Screen::SessionDetail {
+            self.raw_pane = !self.raw_pane;
+            self.detail_cache.get_mut().clear();
+        }
+    }
+
+    /// 回答だけの読書モードを切り替え。行の位置が変わるので先頭に戻る
+    pub fn toggle_answers_only(&mut self) {
+        if self.screen == Screen::SessionDetail {
+            self.answers_only = !self.answers_only;
+            self.detail_cache.get_mut().clear();
+            self.overview_list.reset();
+            self.scroll_offset = 0;
+            self.selected_message = self.first_shown_message();
+            self.detail_match = None;
+        }
+    }
+
+    /// 概観の各行に対応するメッセージの位置
+    pub fn overview_indices(&self) -> Vec<usize> {
+        (0..self.messages.len())
+            .filter(|&i| self.shows_message_at(i))
+            .collect()
+    }
+
+    /// 1メッセージ1行の概観に切り替える。選択中のメッセージを選んだ状態で開く
+    pub fn open_overview(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
+        }
+        let selected = self
+            .overview_indices()
+            .iter()
+            .position(|&i| i >= self.selected_message)
+            .unwrap_or(0);
+        self.overview = true;
+        self.overview_list.reset();
+        let visible = self.visible_rows(&Screen::SessionDetail);
+        self.overview_list.select(selected, visible);
+    }
+
+    /// 概観で選んだメッセージを先頭にして通常表示に戻る
+    pub fn expand_overview(&mut self) {
+        if !self.overview {
+            return;
+        }
+        self.overview = false;
+        let Some(&message) = self.overview_indices().get(self.overview_list.selected) else {
+            return;
+        };
+        self.selected_message = message;
+        let mut cache = self.detail_cache.borrow_mut();
+        let width = cache.width();
+        cache.sync(self, width);
+        self.scroll_offset = cache.message_start(message).unwrap_or(0);
+    }
+
+    /// ミニマップのクリック位置に対応する行へスクロールする
+    pub fn click_minimap(&mut self, column: u16, row: u16) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
+        }
+        let target = self.detail_cache.borrow().minimap_target(column, row);
+        if let Some(target) = target {
+            self.scroll_offset = target;
+            self.select_on_screen();
+        }
+    }
+
+    /// 詳細画面の本文の高さ
+    pub fn detail_visible_rows(&self) -> usize {
+        self.visible_rows(&Screen::SessionDetail)
+    }
+
+    /// 今の幅で折り返した行数から求めた、詳細画面の最も下のスクロール位置
+    fn detail_max_scroll(&self) -> usize {
+        let visible = self.detail_visible_rows();
+        let mut cache = self.detail_cache.borrow_mut();
+        let width = cache.width();
+        cache.sync(self, width);
+        cache.total_rows().saturating_sub(visible)
+    }
+
+    /// 最初に表示されるメッセージ（読書モードでは最初の回答）
+    fn first_shown_message(&self) -> usize {
+        (0..self.messages.len())
+            .find(|&i| self.shows_message_at(i))
+            .unwrap_or(0)
+    }
+
+    /// 前後の表示されるメッセージへ選択を移し、それが見えるようにスクロールする
+    pub fn move_selection(&mut self, forward: bool) {
+        let next = if forward {
+            (self.selected_message + 1..self.messages.len()).find(|&i| self.shows_message_at(i))
+        } else {
+            (0..self.selected_message).rfind(|&i| self.shows_message_at(i))
+        };
+        if let Some(i) = next {
+            self.selected_message = i;
+        }
+        self.scroll_to_selected();
+    }
+
+    /// 前後のユーザーのプロンプトへ選択を移し、それを画面の先頭に合わせる。
+    /// 概観では選択行だけを動かす
+    pub fn jump_to_prompt(&mut self, forward: bool) {
+        if self.screen != Screen::SessionDetail {
+            return;
+        }
+        let is_prompt = |app: &App, i: usize| app.messages[i].role == MessageRole::User && app.shows_message_at(i);
+        let not_found = if forward { "No later prompt" } else { "No earlier prompt" };
+        if self.overview {
+            let indices = self.overview_indices();
+            let current = self.overview_list.selected;
+            let target = if forward {
+                (current + 1..indices.len()).find(|&row| is_prompt(self, indices[row]))
+            } else {
+                (0..current.min(indices.len())).rfind(|&row| is_prompt(self, indices[row]))
+            };
+            match target {
+                Some(row) => {
+                    let visible = self.visible_rows(&Screen::SessionDetail);
+                    self.overview_list.select(row, visible);
+                }
+                None => self.status_message = Some(not_found.to_string()),
+            }
+            return;
+        }
+        let target = if forward {
+            (self.selected_message + 1..self.messages.len()).find(|&i| is_prompt(self, i))
+        } else {
+            (0..self.selected_message).rfind(|&i| is_prompt(self, i))
+        };
+        let Some(i) = target else {
+            self.status_message = Some(not_found.to_string());
+            return;
+        };
+        self.selected_message = i;
+        let mut cache = self.detail_cache.borrow_mut();
+        let width = cache.width();
+        cache.sync(self, width);
+        self.scroll_offset = cache.message_rows(i).start;
+    }
+
+    /// 選択中のメッセージが画面に入るようにスクロールする。画面より長いメッセージは先頭を合わせる
+    fn scroll_to_selected(&mut self) {
+        let (rows, total) = {
+            let mut cache = self.detail_cache.borrow_mut();
+            let width = cache.width();
+            cache.sync(self, width);
+            (cache.message_rows(self.selected_message), cache.total_rows())
+        };
+        let visible = self.detail_visible_rows();
+        let top = self.scroll_offset.min(total.saturating_sub(visible));
+        if rows.start < top {
+            self.scroll_offset = rows.start;
+        } else if rows.end > top + visible {
+            self.scroll_offset = rows.end.saturating_sub(visible).min(rows.start);
+        }
+    }
+
+    /// スクロールで選択中のメッセージが画面から外れたら、画面上端のメッセージを選び直す
+    fn select_on_screen(&mut self) {
+        let mut cache = self.detail_cache.borrow_mut();
+        let width = cache.width();
+        cache.sync(self, width);
+        let visible = self.detail_visible_rows().max(1);
+        let top = self.scroll_offset.min(cache.total_rows().saturating_sub(visible));
+        let selected = cache.message_rows(self.selected_message);
+        if selected.start < top + visible && selected.end > top {
+            return;
+        }
+        let first = cache.message_at_row(top).unwrap_or(0);
+        if let Some(i) = (first..self.messages.len()).find(|&i| {
+            let rows = cache.message_rows(i);
+            !rows.is_empty() && rows.end > top
+        }) {
+            self.selected_message = i;
+        }
+    }
+
+    /// 選択中の複数行のツール結果を展開・折りたたむ
+    pub fn toggle_tool_result(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
+        }
+        let i = self.selected_message;
+        if !self.messages.get(i).is_some_and(ui::is_collapsible) {
+            self.status_message = Some("The selected message is not a long tool result, an edit or a write".to_string());
+            return;
+        }
+        if !self.expanded_results.remove(&i) {
+            self.expanded_results.insert(i);
+        }
+        self.detail_cache.get_mut().clear();
+        self.scroll_to_selected();
+    }
+
+    /// 選択中のツール結果の、上限を超えて隠した行を表示する・再び隠す
+    pub fn toggle_result_lines(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
+        }
+        let i = self.selected_message;
+        let cap = self.config.max_result_lines;
+        let long = self.messages.get(i).is_some_and(|m| {
+            m.role == MessageRole::ToolResult && cap > 0 && m.text.trim_end().lines().nth(cap).is_some()
+        });
+        if !long {
+            self.status_message = Some("The selected message is not a tool result with hidden lines".to_string());
+            return;
+        }
+        if !self.full_results.remove(&i) {
+            self.full_results.insert(i);
+            self.expanded_results.insert(i);
+        }
+        self.detail_cache.get_mut().clear();
+        self.scroll_to_selected();
+    }
+
+    /// SessionDetail でツール結果に表示する行数の上限（0 = 無制限）
+    pub fn result_line_cap(&self, i: usize) -> usize {
+        if self.full_results.contains(&i) { 0 } else { self.config.max_result_lines }
+    }
+
+    /// Markdown の整形と原文表示を切り替える。先頭に見えていたメッセージの位置は保つ
+    pub fn toggle_markdown(&mut self) {
+        if self.screen != Screen::SessionDetail {
+            return;
+        }
+        let mut cache = self.detail_cache.borrow_mut();
+        let top = cache.message_at_row(self.scroll_offset);
+        let width = cache.width();
+        self.markdown = !self.markdown;
+        cache.clear();
+        cache.sync(self, width);
+        self.scroll_offset = top.and_then(|i| cache.message_start(i)).unwrap_or(0);
+        drop(cache);
+        self.scroll_to_selected();
+    }
+
+    /// 選択中のメッセージが表示中のメッセージの何番目か（1始まり）と表示中の件数
+    pub fn selected_message_position(&self) -> (usize, usize) {
+        let shown = |end: usize| (0..end).filter(|&i| self.shows_message_at(i)).count();
+        let end = (self.selected_message + 1).min(self.messages.len());
+        (shown(end), shown(self.messages.len()))
+    }
+
+    pub fn toggle_detail_keys(&mut self) {
+        self.show_detail_keys = !self.show_detail_keys;
+    }
+
+    /// 選択中の Task/Agent 呼び出しが走らせたサブエージェントの会話を開く
+    pub fn open_subagent(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview || self.main_conversation.is_some() {
+            return;
+        }
+        let Some(k) = self.subagents.iter().position(|a| a.spawned_by == Some(self.selected_message)) else {
+            self.status_message = Some(match self.subagents.len() {
+                0 => "This session ran no subagents".to_string(),
+                n => format!("Select a Task/Agent call to open its subagent ({} in this session)", n),
+            });
+            return;
+        };
+        let messages = self.subagents[k].messages.iter().map(|m| Message { sidechain: false, ..m.clone() }).collect();
+        let main = MainConversation {
+            agent: self.subagents[k].description.clone(),
+            messages: std::mem::take(&mut self.messages),
+            subagents: std::mem::take(&mut self.subagents),
+            // ブックマークは本体の会話のメッセージの位置で持っているので、ここでは出さない
+            bookmarked: std::mem::take(&mut self.bookmarked),
+            selected_message: self.selected_message,
+            scroll_offset: self.scroll_offset,
+        };
+        self.set_messages(messages);
+        self.main_conversation = Some(main);
+    }
+
+    /// サブエージェントの会話を閉じ、開く前の本体の会話の位置に戻る
+    pub fn close_subagent(&mut self) {
+        let Some(main) = self.main_conversation.take() else {
+            return;
+        };
+        self.set_messages(main.messages);
+        self.subagents = main.subagents;
+        self.bookmarked = main.bookmarked;
+        self.selected_message = main.selected_message;
+        self.scroll_offset = main.scroll_offset;
+    }
+
+    /// 分岐の字下げ表示を切り替え、見ていたメッセージの位置を保つ
+    pub fn toggle_threaded(&mut self) {
+        if self.screen != Screen::SessionDetail {
+            return;
+        }
+        let mut cache = self.detail_cache.borrow_mut();
+        let top = cache.message_at_row(self.scroll_offset);
+        let width = cache.width();
+        self.threaded = !self.threaded;
+        cache.clear();
+        cache.sync(self, width);
+        self.scroll_offset = top.and_then(|i| cache.message_start(i)).unwrap_or(0);
+        drop(cache);
+        self.scroll_to_selected();
+    }
+
+    /// 分岐の数（枝が2本以上に分かれたエントリの数）
+    pub fn fork_count(&self) -> usize {
+        self.thread_marks.iter().filter(|m| m.branch.is_some_and(|(k, _)| k == 1)).count()
+    }
+
+    /// SessionDetail に表示するメッセージか（隠したロールは除き、読書モードではアシスタントの本文のみ）
+    pub fn shows_message(&self, msg: &Message) -> bool {
+        !self.hidden_roles.contains(&msg.role)
+            && (!self.answers_only || (msg.role == MessageRole::Assistant && !msg.text.trim().is_empty()))
+    }
+
+    /// `i` 番目のメッセージを表示するか。ツールを絞り込んでいれば、ほかのツールの呼び出しと結果も隠す
+    pub fn shows_message_at(&self, i: usize) -> bool {
+        self.messages.get(i).is_some_and(|msg| self.shows_message(msg))
+            && (self.shown_tools.is_empty()
+                || !matches!(self.messages[i].role, MessageRole::ToolUse | MessageRole::ToolResult)
+                || self.message_tool(i).is_some_and(|name| self.shown_tools.iter().any(|t| t == name)))
+    }
+
+    /// ツールの呼び出しならその名前、結果なら答えている呼び出しの名前
+    pub fn message_tool(&self, i: usize) -> Option<&str> {
+        let call = match self.messages.get(i)?.role {
+            MessageRole::ToolUse => i,
+            MessageRole::ToolResult => (*self.result_calls.get(i)?)?,
+            _ => return None,
+        };
+        self.messages[call].tool_name.as_deref()
+    }
+
+    /// 開いているセッションで使われたツールと呼び出し回数（多い順）
+    pub fn session_tools(&self) -> Vec<(String, usize)> {
+        let mut tools: Vec<(String, usize)> = Vec::new();
+        for msg in self.messages.iter().filter(|m| m.role == MessageRole::ToolUse) {
+            let name = msg.tool_name.as_deref().unwrap_or("");
+            match tools.iter_mut().find(|(t, _)| t == name) {
+                Some((_, n)) => *n += 1,
+                None => tools.push((name.to_string(), 1)),
+            }
+        }
+        tools.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        tools
+    }
+
+    /// ツールの絞り込みを開く
+    pub fn open_tool_picker(&mut self) {
+        if self.screen != Screen::SessionDetail || self.overview {
+            return;
+        }
+        if self.session_tools().is_empty() && self.shown_tools.is_empty() {
+            self.status_message = Some("No tool calls in this session".to_string());
+            return;
+        }
+        self.tool_picker = Some(ListState::default());
+    }
+
+    pub fn close_tool_picker(&mut self) {
+        self.tool_picker = None;
+    }
+
+    pub fn tool_picker_move(&mut self, down: bool, n: usize) {
+        let len = self.session_tools().len();
+        let visible = self.toc_visible_rows();
+        if let Some(list) = self.tool_picker.as_mut() {
+            if down {
+                list.down(n.min(len), len);
+            } else {
+                list.up(n);
+            }
+            list.scroll_into_view(visible);
+        }
+    }
+
+    /// 絞り込みで選択中のツールを見せる・見せないを切り替える
+    pub fn toggle_picked_tool(&mut self) {
+        let Some(list) = &self.tool_picker else {
+            return;
+        };
+        let Some((name, _)) = self.session_tools().into_iter().nth(list.selected) else {
+            return;
+        };
+        match self.shown_tools.iter().position(|t| *t == name) {
+            Some(k) => {
+                self.shown_tools.remove(k);
+            }
+            None => self.shown_tools.push(name),
+        }
+        self.refilter_messages();
+    }
+
+    /// ツールの絞り込みを解いて全てのツールを見せる
+    pub fn show_all_tools(&mut self) {
+        self.shown_tools.clear();
+        self.refilter_messages();
+    }
+
+    /// `ROLE_TOGGLES` の `slot` 番目のロールの表示を切り替える
+    pub fn toggle_role(&mut self, slot: usize) {
+        if let Some(&(_, roles)) = ROLE_TOGGLES.get(slot) {
+            self.toggle_roles(roles);
+        }
+    }
+
+    /// ツールの呼び出しと結果をまとめて隠す（片方でも見えていれば両方隠す）
+    pub fn toggle_tool_roles(&mut self) {
+        self.toggle_roles(&[MessageRole::ToolUse, MessageRole::ToolResult]);
+    }
+
+    fn toggle_roles(&mut self, roles: &[MessageRole]) {
+        if self.screen != Screen::SessionDetail {
+            return;
+        }
+        if self.overview
+            && let Some(&i) = self.overview_indices().get(self.overview_list.selected)
+        {
+            self.selected_message = i;
+        }
+        let hide = !roles.iter().all(|r| self.hidden_roles.contains(r));
+        self.hidden_roles.retain(|r| !roles.contains(r));
+        if hide {
+            self.hidden_roles.extend(roles.iter().cloned());
+        }
+        self.refilter_messages();
+    }
+
+    /// 表示するメッセージが変わった後、選択とスクロールを見えるメッセージに合わせ直す
+    fn refilter_messages(&mut self) {
+        self.detail_cache.get_mut().clear();
+        self.detail_match = None;
+        // 選択中のメッセージが隠れたら、後ろ（無ければ前）の表示されるメッセージを選ぶ
+        let selected = self.selected_message.min(self.messages.len().saturating_sub(1));
+        self.selected_message = (selected..self.messages.len())
+            .find(|&i| self.shows_message_at(i))
+            .or_else(|| (0..selected).rfind(|&i| self.shows_message_at(i)))
+            .unwrap_or(0);
+        if self.overview {
+            let row = self
+                .overview_indices()
+                .iter()
+                .position(|&i| i >= self.selected_message)
+                .unwrap_or(0);
+            self.overview_list.reset();
+            let visible = self.visible_rows(&Screen::SessionDetail);
+            self.overview_list.select(row, visible);
+            return;
+        }
+        let mut cache = self.detail_cache.borrow_mut();
+        let width = cache.width();
+        cache.sync(self, width);
+        self.scroll_offset = cache.message_rows(self.selected_message).start;
+    }
+
+    /// パンくずに出す、隠しているロールの名前
+    pub fn hidden_role_labels(&self) -> Vec<&'static str> {
+        ROLE_TOGGLES
+            .iter()
+            .filter(|(_, roles)| roles.iter().all(|r| self.hidden_roles.contains(r)))
+            .map(|&(label, _)| label)
+            .collect()
+    }
+
+    /// SessionDetail で適用する1メッセージあたりの表示文字数上限（0 = 無制限）
+    pub fn message_char_cap(&self) -> usize {
+        if self.show_full_messages {
+            0
+        } else {
+            self.config.max_message_chars
+        }
+    }
+
+    /// 表示中のセッションの file-history-snapshot 一覧を開く
+    pub fn open_file_snapshots(&mut self) {
+        if self.screen != Screen::SessionDetail {
+            return;
+        }
+        let snapshots =
+            snapshots::session_snapshots(&self.current_project_name, &self.current_session_id)
+                .unwrap_or_default();
+        self.set_snapshots(snapshots);
+    }
+
+    pub fn set_snapshots(&mut self, snapshots: Vec<FileSnapshot>) {
+        self.snapshots = snapshots;
+        self.snapshot_list.reset();
+        self.screen = Screen::FileSnapshots;
+    }
+
+    /// 選択中のスナップショットの保存内容を表示
+    pub fn open_selected_snapshot(&mut self) {
+        let Some(snapshot) = self.snapshots.get(self.snapshot_list.selected) else {
+            return;
+        };
+        let content = match snapshots::read_snapshot(&self.current_session_id, snapshot) {
+            Ok(_) if snapshot.backup_file.is_none() => {
+                "(file did not exist yet at this point)".to_string()
+            }
+            Ok(c) => c,
+            Err(e) => format!("(backup not available: {})", e),
+        };
+        self.set_snapshot_content(content);
+    }
+
+    pub fn set_snapshot_content(&mut self, content: String) {
+        self.snapshot_content = content;
+        self.snapshot_content_scroll = 0;
+        self.screen = Screen::SnapshotView;
+    }
+
+    /// 選択中のスナップショットの書き出し先入力を開始
+    pub fn start_snapshot_export(&mut self) {
+        if !matches!(self.screen, Screen::FileSnapshots | Screen::SnapshotView) {
+            return;
+        }
+        let Some(snapshot) = self.snapshots.get(self.snapshot_list.selected) else {
+            return;
+        };
+        if snapshot.backup_file.is_none() {
+            self.status_message = Some(format!("{} did not exist at this point", snapshot.path));
+            return;
+        }
+        self.export_prompt = Some(format!("{}.snapshot-v{}", snapshot.path, snapshot.version));
+        self.export_confirm_overwrite = false;
+    }
+
+    pub fn cancel_snapshot_export(&mut self) {
+        self.export_prompt = None;
+        self.export_confirm_overwrite = false;
+    }
+
+    pub fn export_prompt_push(&mut self, ch: char) {
+        if let Some(dest) = &mut self.export_prompt {
+            dest.push(ch);
+            self.export_confirm_overwrite = false;
+        }
+    }
+
+    pub fn export_prompt_pop(&mut self) {
+        if let Some(dest) = &mut self.export_prompt {
+            dest.pop();
+            self.export_confirm_overwrite = false;
+        }
+    }
+
+    /// 入力された書き出し先へ保存。既存ファイルは2回目の Enter で上書き
+    pub fn confirm_snapshot_export(&mut self) {
+        let Some(input) = self.export_prompt.clone() else {
+            return;
+        };
+        let Some(snapshot) = self.snapshots.get(self.snapshot_list.selected) else {
+            self.cancel_snapshot_export();
+            return;
+        };
+        if input.trim().is_empty() {
+            return;
+        }
+        let dest = snapshots::expand_destination(input.trim());
+        if dest.exists() && !self.export_confirm_overwrite {
+            self.export_confirm_overwrite = true;
+            return;
+        }
+        let result = snapshots::export_snapshot(&self.current_session_id, snapshot, &dest, true);
+        self.status_message = Some(match result {
+            Ok(bytes) => {
+                self.fire_export_hook("snapshot", &dest);
+                format!("Wrote {} bytes to {}", bytes, dest.display())
+            }
+            Err(e) => format!("Export failed: {}", e),
+        });
+        self.cancel_snapshot_export();
+    }
+
+    fn snapshot_max_scroll(&self) -> usize {
+        let visible = self.visible_rows(&Screen::SnapshotView);
+        self.snapshot_content.lines().count().saturating_sub(visible)
+    }
+
+    /// 一覧に残っているセッションのうち、消してよさそうなものを1件ずつ確認する
+    pub fn open_cleanup_review(&mut self) {
+        if self.screen != Screen::SessionList {
+            return;
+        }
+        let review = Review::new(&self.filtered_sessions, self.config.cleanup_after_days, Utc::now());
+        if review.total == 0 {
+            self.status_message = Some("No sessions in this list look deletable".to_string());
+            return;
+        }
+        self.set_cleanup_review(review);
+    }
+
+    pub fn set_cleanup_review(&mut self, review: Review) {
+        self.cleanup_review = review;
+        self.screen = Screen::CleanupReview;
+        self.load_cleanup_preview();
+    }
+
+    fn load_cleanup_preview(&mut self) {
+        self.cleanup_scroll = 0;
+        self.cleanup_preview = self
+            .cleanup_review
+            .current()
+            .and_then(|c| parser::load_session(&self.current_project_name, &c.session.session_id).ok())
+            .unwrap_or_default();
+    }
+
+    /// レビュー中のセッションに決定を実行して次へ進む。失敗したら同じセッションに留まる
+    pub fn decide_cleanup(&mut self, decision: Decision) {
+        let Some(candidate) = self.cleanup_review.current() else {
+            return;
+        };
+        let session_id = candidate.session.session_id.clone();
+        let short_id = &session_id[..session_id.len().min(8)];
+        let path = parser::session_file_path(&self.current_project_name, &session_id);
+        let done = match (decision, path) {
+            (Decision::Keep | Decision::Skip, _) => Ok(None),
+            (_, None) => Err(anyhow::anyhow!("transcript of {} not found", short_id)),
+            (Decision::Delete, Some(path)) => {
+                cleanup::delete_session(&path).map(|_| Some(format!("Deleted {}", short_id)))
+            }
+            (Decision::Archive, Some(path)) => {
+                let project_dir = path.parent().unwrap_or(&path).to_path_buf();
+                cleanup::archive_session(&path, &cleanup::archive_dir(&self.config.archive_dir, &project_dir))
+                    .map(|dest| Some(format!("Archived {} to {}", short_id, dest.display())))
+            }
+        };
+        match done {
+            Ok(message) => self.status_message = message,
+            Err(e) => {
+                self.status_message = Some(format!("Cleanup failed: {}", e));
+                return;
+            }
+        }
+        if decision == Decision::Delete {
+            self.forget_deleted_session(&session_id);
+        }
+        if matches!(decision, Decision::Delete | Decision::Archive) {
+            self.sessions.retain(|s| s.session_id != session_id);
+        }
+        self.cleanup_review.resolve(decision);
+        if self.cleanup_review.current().is_some() {
+            self.load_cleanup_preview();
+        } else {
+            self.finish_cleanup_review();
+        }
+    }
+
+    /// レビューを終えて（途中でも）セッション一覧に戻り、結果を知らせる
+    pub fn finish_cleanup_review(&mut self) {
+        self.status_message = Some(format!("Cleanup review: {}", self.cleanup_review.summary()));
+        self.cleanup_review = Review::default();
+        self.cleanup_preview.clear();
+        self.cleanup_scroll = 0;
+        self.apply_filter();
+        self.session_list.reset();
+        self.screen = Screen::SessionList;
+    }
+
+    /// Rendered lines of the cleanup preview: a header, the text and a blank
+    /// separator per message.
+    pub fn cleanup_preview_line_count(&self) -> usize {
+        self.cleanup_preview.iter().map(|m| m.text.lines().count() + 2).sum()
+    }
+
+    fn cleanup_max_scroll(&self) -> usize {
+        let visible = self.visible_rows(&Screen::CleanupReview);
+        self.cleanup_preview_line_count().saturating_sub(visible)
+    }
+
+    /// タイトルバーに出す、いま効いている絞り込みや表示モード
+    pub fn title_filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+        match self.screen {
+            Screen::ProjectList | Screen::SessionList if !self.search_query.is_empty() => {
+                filters.push(format!("/{}", self.search_query));
+            }
+            _ => {}
+        }
+        match self.screen {
+            Screen::SessionList => {
+                if self.time_filter != TimeFilter::All {
+                    filters.push(self.time_filter.label());
+                }
+                if self.needs_input_only {
+                    filters.push("needs input".to_string());
+                }
+                if self.favorites_only {
+                    filters.push("favorites".to_string());
+                }
+                if self.expensive_only {
+                    filters.push("expensive".to_string());
+                }
+                if let Some(name) = self.script_filter.and_then(|i| self.scripts.filter_names().get(i).copied()) {
+                    filters.push(name.to_string());
+                }
+                if let Some(version) = &self.version_filter {
+                    filters.push(format!("v{}", version));
+                }
+                if self.show_resumed {
+                    filters.push("all parts".to_string());
+                }
+                if self.show_archived {
+                    filters.push("archived".to_string());
+                }
+            }
+            Screen::SessionDetail => {
+                if self.answers_only {
+                    filters.push("answers only".to_string());
+                }
+                if !self.hidden_role_labels().is_empty() {
+                    filters.push(format!("hiding {}", self.hidden_role_labels().join(", ")));
+                }
+                if !self.shown_tools.is_empty() {
+                    filters.push(format!("tools: {}", self.shown_tools.join(", ")));
+                }
+                if self.threaded {
+                    filters.push("threaded".to_string());
+                }
+                if self.overview {
+                    filters.push("overview".to_string());
+                }
+            }
+            _ => {}
+        }
+        filters
+    }
+
+    /// タイトルバーに出す件数（絞り込み中は「表示中/全体」）
+    pub fn title_counts(&self) -> String {
+        let counts = |shown: usize, total: usize, noun: &str| {
+            if shown == total {
+                format!("{} {}", total, noun)
+            } else {
+                format!("{}/{} {}", shown, total, noun)
+            }
+        };
+        match self.screen {
+            Screen::ProjectList => counts(self.displayed_projects.len(), self.projects.len(), "projects"),
+            Screen::SessionList => counts(self.filtered_sessions.len(), self.sessions.len(), "sessions"),
+            Screen::SessionDetail => {
+                let shown = (0..self.messages.len()).filter(|&i| self.shows_message_at(i)).count();
+                counts(shown, self.messages.len(), "messages")
+            }
+            Screen::GlobalSearch => {
+                counts(self.global_search_filtered.len(), self.global_search_results.len(), "sessions")
+            }
+            Screen::PermissionAudit => format!("{} calls", self.audit_entries.len()),
+            Screen::SessionChanges => format!("{} files", self.file_changes.len()),
+            Screen::FileSnapshots => format!("{} snapshots", self.snapshots.len()),
+            Screen::SnapshotView => format!("{} lines", self.snapshot_content.lines().count()),
+            Screen::CleanupReview => {
+                format!("{}/{} reviewed", self.cleanup_review.done(), self.cleanup_review.total)
+            }
+            Screen::IndexActivity => format!("{} changes", self.index_changes.len()),
+        }
+    }
+
+    /// 開いているプロジェクトの元のパス（一覧に無ければディレクトリ名から復元）
+    pub fn current_project_path(&self) -> String {
+        self.projects
+            .iter()
+            .find(|p| p.dir_name == self.current_project_name)
+            .map(|p| p.original_path.clone())
+            .unwrap_or_else(|| parser::decode_project_path(&self.current_project_name))
+    }
+
+    /// セッションが参照した CLAUDE.md と設定ファイルの一覧を開く
+    pub fn open_instruction_files(&mut self) {
+        if self.screen != Screen::SessionDetail {
+            return;
+        }
+        self.instruction_files = analysis::instruction_files(&self.messages);
+        if self.instruction_files.is_empty() {
+            self.status_message = Some("No CLAUDE.md or settings files referenced in this session".to_string());
+            return;
+        }
+        self.instruction_list = Some(ListState::default());
+    }
+
+    pub fn close_instruction_files(&mut self) {
+        self.instruction_list = None;
+    }
+
+    pub fn instruction_list_move(&mut self, down: bool) {
+        let len = self.instruction_files.len();
+        if let Some(list) = self.instruction_list.as_mut() {
+            if down {
+                list.down(1, len);
+            } else {
+                list.up(1);
+            }
+        }
+    }
+
+    /// 選んだファイルを $VISUAL / $EDITOR で開く（無いファイルは開かない）
+    pub fn edit_instruction_file(&mut self) {
+        let Some(list) = self.instruction_list else {
+            return;
+        };
+        let Some(file) = self.instruction_files.get(list.selected) else {
+            return;
+        };
+        let path = expand_home(file);
+        if !path.exists() {
+            self.status_message = Some(format!("{} no longer exists", file));
+            return;
+        }
+        self.instruction_list = None;
+        self.pending_editor = Some(format!(
+            "${{VISUAL:-${{EDITOR:-vi}}}} {}",
+            commands::shell_quote(&path.to_string_lossy())
+        ));
+    }
+
+    /// 選んだファイルの、セッションの最後の時点で索引に残っていた版をページャで開く
+    pub fn page_instruction_snapshot(&mut self) {
+        let Some(file) = self.instruction_list.and_then(|l| self.instruction_files.get(l.selected)) else {
+            return;
+        };
+        let path = expand_home(file).to_string_lossy().to_string();
+        let at = self
+            .messages
+            .iter()
+            .rev()
+            .find_map(|m| m.timestamp)
+            .map_or_else(|| Utc::now().timestamp(), |t| t.timestamp());
+        match self.open_index().and_then(|index| index.instruction_file_at(&path, at)) {
+            Ok(Some(snapshot)) => {
+                self.instruction_list = None;
+                self.pending_pager = Some((self.pager_command(false), snapshot.content));
+            }
+            Ok(None) if !self.config.snapshot_instructions => {
+                self.status_message = Some("No snapshots recorded; set snapshot_instructions = true".to_string());
+            }
+            Ok(None) => {
+                self.status_message = Some(format!("No snapshot of {} from before this session", file));
+            }
+            Err(e) => self.status_message = Some(format!("Could not read the index: {}", e)),
+        }
+    }
+
+    /// 選択中のメッセージの全文を外部ページャで開く。Edit/MultiEdit は差分を
+    /// diff_pager に、Write は書いた内容を、ほかのツール呼び出しは入力の JSON を渡す
+    pub fn page_selected_message(&mut self) {
+        let Some(msg) = self.messages.get(self.selected_message) else {
+            return;
+        };
+        let diff = msg
+            .tool_name
+            .as_deref()
+            .zip(msg.tool_input.as_ref())
+            .and_then(|(name, input)| changes::tool_call_diff(name, input));
+        let text = match (&diff, &msg.tool_input) {
+            (Some(diff), _) => diff.clone(),
+            (None, Some(input)) if msg.role == MessageRole::ToolUse => match input.get("content") {
+                Some(serde_json::Value::String(content)) if msg.tool_name.as_deref() == Some("Write") => {
+                    content.clone()
+                }
+                _ => serde_json::to_string_pretty(input).unwrap_or_default(),
+            },
+            _ => msg.text.clone(),
+        };
+        let pager = self.pager_command(diff.is_some());
+        self.pending_pager = Some((pager, text));
+    }
+
+    /// 設定、$PAGER、less の順で決まるページャ
+    fn pager_command(&self, diff: bool) -> String {
+        [&self.config.diff_pager, &self.config.pager]
+            .into_iter()
+            .skip(if diff { 0 } else { 1 })
+            .find(|p| !p.trim().is_empty())
+            .cloned()
+            .or_else(|| std::env::var("PAGER").ok().filter(|p| !p.trim().is_empty()))
+            .unwrap_or_else(|| "less -R".to_string())
+    }
+
+    /// カーソル位置のプロンプトをプロジェクトのディレクトリで新しい claude に渡す。
+    /// 実行は run_loop が端末を明け渡してから行う
+    pub fn reask_prompt(&mut self) {
+        let target = match self.screen {
+            Screen::GlobalSearch => {
+                let Some(prompt) = self.full_matched_prompt() else {
+                    return;
+                };
+                self.global_search_filtered
+                    .get(self.global_search_list.selected)
+                    .map(|r| (r.project_path.clone(), prompt))
+            }
+            Screen::SessionDetail => self
+                .messages
+                .get(self.selected_message)
+                .filter(|m| m.role == MessageRole::User)
+                .map(|m| (self.current_project_path(), m.text.clone())),
+            _ => return,
+        };
+        let Some((project_path, prompt)) = target.filter(|(_, p)| !p.trim().is_empty()) else {
+            self.status_message = Some("The selection is not one of your prompts".to_string());
+            return;
+        };
+        let command_line = format!(
+            "cd {} && claude -- {}",
+            commands::shell_quote(&project_path),
+            commands::shell_quote(&prompt)
+        );
+        self.pending_command = Some(("claude".to_string(), command_line));
+    }
+
+    /// 選択中（SessionList）または表示中（SessionDetail）のセッションを外部コマンドに渡す値
+    pub fn command_context(&self) -> Option<CommandContext> {
+        let session_id = match self.screen {
+            Screen::SessionList => self.filtered_sessions.get(self.session_list.selected)?.session_id.clone(),
+            Screen::SessionDetail => self.current_session_id.clone(),
+            _ => return None,
+        };
+        let project_dir = self.current_project_name.clone();
+        let session_path = parser::session_file_path(&project_dir, &session_id)
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+        let project_path = self.current_project_path();
+        Some(CommandContext {
+            session_id,
+            session_path,
+            project_path,
+            project_dir,
+        })
+    }
+
+    /// 設定ファイルに登録した外部コマンドのメニューを開く
+    pub fn open_command_menu(&mut self) {
+        if self.command_context().is_none() {
+            return;
+        }
+        if self.config.commands.is_empty() {
+            self.status_message = Some("No commands configured in config.toml".to_string());
+            return;
+        }
+        self.command_menu = Some(0);
+    }
+
+    pub fn close_command_menu(&mut self) {
+        self.command_menu = None;
+    }
+
+    pub fn command_menu_down(&mut self) {
+        if let Some(i) = self.command_menu.as_mut()
+            && *i + 1 < self.config.commands.len()
+        {
+            *i += 1;
+        }
+    }
+
+    pub fn command_menu_up(&mut self) {
+        if let Some(i) = self.command_menu.as_mut() {
+            *i = i.saturating_sub(1);
+        }
+    }
+
+    /// 選択したコマンドを展開し、run_loop に実行を任せる
+    pub fn confirm_command(&mut self) {
+        let Some(i) = self.command_menu.take() else {
+            return;
+        };
+        let (Some(cmd), Some(ctx)) = (self.config.commands.get(i), self.command_context()) else {
+            return;
+        };
+        self.pending_command = Some((cmd.name.clone(), commands::expand(&cmd.command, &ctx)));
+    }
+
+    pub fn get_resume_command(&self) -> Option<String> {
+        self.global_search_filtered
+            .get(self.global_search_list.selected)
+            .map(|r| format!("claude --resume {}", r.session_id))
+    }
+
+    /// 選択中の結果で一致したプロンプトの全文。要約で一致したときや検索語が
+    /// 無いときは、ほかに一致したプロンプトか最初のプロンプト
+    pub fn get_matched_prompt(&self) -> Option<String> {
+        let r = self.global_search_filtered.get(self.global_search_list.selected)?;
+        if !r.best_match_is_summary && !r.best_match_prompt.is_empty() {
+            return Some(r.best_match_prompt.clone());
+        }
+        r.other_matches.first().map(|(p, _)| p).or(r.prompts.first()).cloned()
+    }
+
+    /// 選択中の結果のプロンプトの全文。索引のプロンプトは `max_prompt_chars`
+    /// で切り詰められていることがあるので、トランスクリプトから読み直す。
+    /// 読めずに切り詰められたものしか無いときはステータスに出して None
+    pub fn full_matched_prompt(&mut self) -> Option<String> {
+        let prompt = self.get_matched_prompt().filter(|p| !p.trim().is_empty());
+        let Some(prompt) = prompt else {
+            self.status_message = Some("The selection is not one of your prompts".to_string());
+            return None;
+        };
+        let r = &self.global_search_filtered[self.global_search_list.selected];
+        let full = self
+            .transcript_of(&r.dir_name, &r.session_id)
+            .and_then(|messages| full_prompt_text(&messages, &prompt));
+        match full {
+            Some(full) => Some(full),
+            None if self.config.max_prompt_chars > 0 && prompt.chars().count() >= self.config.max_prompt_chars => {
+                self.status_message = Some(format!(
+                    "The index keeps only the first {} characters of this prompt and its transcript is gone",
+                    self.config.max_prompt_chars
+                ));
+                None
+            }
+            None => Some(prompt),
+        }
+    }
+
+    /// プロジェクト `dir_name` のセッションの会話。アーカイブ先も探す
+    fn transcript_of(&self, dir_name: &str, session_id: &str) -> Option<Vec<Message>> {
+        let file_name = format!("{}.jsonl", session_id);
+        parser::claude_roots()
+            .iter()
+            .map(|root| root.projects_dir.join(dir_name))
+            .flat_map(|project_dir| {
+                let archive = cleanup::archive_dir(&self.config.archive_dir, &project_dir);
+                [project_dir, archive]
+            })
+            .find(|dir| dir.join(&file_name).is_file())
+            .and_then(|dir| parser::load_transcript_in(&dir, session_id).ok())
+    }
+}
+
+/// 索引に残った（切り詰められているかもしれない）`indexed` に当たる
+/// ユーザーのメッセージの全文
+fn full_prompt_text(messages: &[Message], indexed: &str) -> Option<String> {
+    let prompts = || messages.iter().filter(|m| m.role == MessageRole::User && !m.sidechain);
+    prompts()
+        .find(|m| m.text == indexed)
+        .or_else(|| prompts().find(|m| m.text.starts_with(indexed)))
+        .map(|m| m.text.clone())
+}
+
+fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) {
+    let _ = disable_raw_mode();
+    let _ = execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    );
+    let _ = terminal.show_cursor();
+}
+
+/// 先頭の `~/` をホームディレクトリにする
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// クリップボードにコピーし、コピー先をステータスに添える。
+/// システムのクリップボードが使えなければ、以後は OSC 52 でコピーする
+fn copy_to_clipboard(app: &mut App, text: String) {
+    let copied = app.status_message.take().unwrap_or_else(|| "Copied".to_string());
+    app.status_message = Some(match app.clipboard.set_contents(&text) {
+        Ok(()) => format!("{} via {}", copied, app.clipboard.name()),
+        Err(e) if app.clipboard.kind() == ClipboardKind::System => {
+            let failed = app.clipboard.name();
+            app.clipboard = Box::new(Osc52Clipboard { tmux: Environment::current().tmux });
+            match app.clipboard.set_contents(&text) {
+                Ok(()) => format!("{} via OSC 52 ({} unavailable: {})", copied, failed, e),
+                Err(e) => format!("Could not copy to the clipboard: {}", e),
+            }
+        }
+        Err(e) => format!("Could not copy via {}: {}", app.clipboard.name(), e),
+    });
+}
+
+/// 端末を通常モードに戻して外部コマンドを実行し、Enter で TUI に戻る
+fn run_external_command(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    name: &str,
+    command_line: &str,
+) -> Result<String> {
+    restore_terminal(terminal);
+    println!("$ {}\n", command_line);
+    let status = match commands::run(command_line) {
+        Ok(status) if status.success() => format!("{} finished", name),
+        Ok(status) => format!("{} failed ({})", name, status),
+        Err(e) => format!("{} could not be started: {}", name, e),
+    };
+    println!("\n[{}] Press Enter to return", status);
+    let mut line = String::new();
+    let _ = io::stdin().read_line(&mut line);
+
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen, EnableMouseCapture)?;
+    terminal.clear()?;
+    Ok(status)
+}
+
+/// ページャやエディタに終わるまで端末を明け渡す（`input` があれば標準入力に渡す）。
+/// 失敗したときだけ状態を返す
+fn run_interactive(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    command_line: &str,
+    input: Option<&str>,
+) -> Result<Option<String>> {
+    restore_terminal(terminal);
+    let result = match input {
+        Some(text) => commands::run_with_input(command_line, text),
+        None => commands::run(command_line),
+    };
+    let status = match result {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!("{} failed ({})", command_line, status)),
+        Err(e) => Some(format!("{} could not be started: {}", command_line, e)),
+    };
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen, EnableMouseCapture)?;
+    terminal.clear()?;
+    Ok(status)
+}
+
+/// TUI を起動する。`clip_file` があれば設定に関わらずコピーをそのファイルに書く
+pub fn run(clip_file: Option<PathBuf>) -> Result<()> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    // Restore terminal on panic
+    let default_panic = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture);
+        default_panic(info);
+    }));
+
+    let mut app = App::new();
+    if let Some(path) = clip_file {
+        app.clipboard = Box::new(clipboard::FileClipboard { path });
+    }
+    // 索引から読んだプロジェクト一覧を裏で最新化する
+    app.start_indexing();
+
+    let result = run_loop(&mut terminal, &mut app);
+
+    restore_terminal(&mut terminal);
+
+    result
+}
+
+/// キー入力を1つ処理する。端末を明け渡す外部コマンドやページャは
+/// `pending_*` に積むだけで、実行は呼び出し側（`run_loop`）が行う
+pub fn handle_key(app: &mut App, key: KeyEvent) {
+    app.status_message = None;
+    // 削除の確認は続けて X を押したときだけ有効
+    if key.code != KeyCode::Char('X') {
+        app.delete_marked_pending = false;
+    }
+    if app.screen == Screen::GlobalSearch {
+        match key.code {
+            KeyCode::Esc => app.go_back(),
+            KeyCode::Enter => {
+                if let Some(result) =
+                    app.global_search_filtered.get(app.global_search_list.selected)
+                {
+                    if result.expired {
+                        app.status_message = Some(EXPIRED_TRANSCRIPT_NOTE.to_string());
+                    } else {
+                        let dir_name = result.dir_name.clone();
+                        let session_id = result.session_id.clone();
+                        app.current_project_name = dir_name;
+                        app.current_session_id = session_id.clone();
+                        // アーカイブしたセッションはアーカイブ先から開く
+                        let loaded = match app.archived_dir_of(&session_id) {
+                            Some(dir) => parser::load_transcript_in(&dir, &session_id),
+                            None => parser::load_session(&app.current_project_name, &session_id),
+                        };
+                        if let Ok(msgs) = loaded {
+                            let query = app.global_search_query.clone();
+                            app.set_messages(msgs);
+                            app.session_actions =
+                                actions::session_actions(&app.current_project_name, &session_id)
+                                    .unwrap_or_default();
+                            app.set_detail_query(&query);
+                            app.fire_open_session_hook();
+                        }
+                    }
+                }
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.reindex();
+            }
+            KeyCode::Tab => app.toggle_global_search_expand(),
+            KeyCode::Char('y') => {
+                if let Some(cmd) = app.get_resume_command() {
+                    app.status_message = Some("Copied resume command".to_string());
+                    copy_to_clipboard(app, cmd);
+                }
+            }
+            KeyCode::Char('R') => app.reask_prompt(),
+            KeyCode::Char('Y') => {
+                if let Some(prompt) = app.full_matched_prompt() {
+                    app.status_message = Some(format!("Copied prompt ({} chars)", prompt.chars().count()));
+                    copy_to_clipboard(app, prompt);
+                }
+            }
+            KeyCode::Char('j') | KeyCode::Down => app.navigate_down(),
+            KeyCode::Char('k') | KeyCode::Up => app.navigate_up(),
+            KeyCode::Char('d') => app.half_page_down(),
+            KeyCode::Char('u') => app.half_page_up(),
+            KeyCode::Char('g') => app.go_to_top(),
+            KeyCode::Char('G') => app.go_to_bottom(),
+            KeyCode::Backspace => app.global_search_pop(),
+            KeyCode::Char(c) => app.global_search_push(c),
+            _ => {}
+        }
+    } else if app.screen == Screen::CleanupReview {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.finish_cleanup_review(),
+            KeyCode::Char('k') => app.decide_cleanup(Decision::Keep),
+            KeyCode::Char('d') => app.decide_cleanup(Decision::Delete),
+            KeyCode::Char('a') => app.decide_cleanup(Decision::Archive),
+            KeyCode::Char('s') => app.decide_cleanup(Decision::Skip),
+            KeyCode::Down => app.navigate_down(),
+            KeyCode::Up => app.navigate_up(),
+            KeyCode::PageDown => app.half_page_down(),
+            KeyCode::PageUp => app.half_page_up(),
+            _ => {}
+        }
+    } else if app.share_pending.is_some() {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.cancel_share(),
+            KeyCode::Enter | KeyCode::Char('E') => app.confirm_share(),
+            KeyCode::Char('a') => app.anonymize_pending_share(),
+            _ => {}
+        }
+    } else if app.project_activity.is_some() {
+        if matches!(key.code, KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('P')) {
+            app.close_project_activity();
+        }
+    } else if app.session_timeline.is_some() {
+        if matches!(key.code, KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('L')) {
+            app.close_timeline();
+        }
+    } else if app.raw_json.is_some() {
+        let half = app.terminal_height / 2;
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('J') => app.close_raw_json(),
+            KeyCode::Char('j') | KeyCode::Down => app.scroll_raw_json(true, 1),
+            KeyCode::Char('k') | KeyCode::Up => app.scroll_raw_json(false, 1),
+            KeyCode::Char('d') => app.scroll_raw_json(true, half),
+            KeyCode::Char('u') => app.scroll_raw_json(false, half),
+            KeyCode::Char('g') => app.scroll_raw_json(false, usize::MAX),
+            KeyCode::Char('G') => app.scroll_raw_json(true, usize::MAX),
+            _ => {}
+        }
+    } else if app.prompt_toc.is_some() {
+        let half = app.toc_visible_rows() / 2;
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('T') => app.close_prompt_toc(),
+            KeyCode::Enter => app.confirm_prompt_toc(),
+            KeyCode::Char('j') | KeyCode::Down => app.prompt_toc_move(true, 1),
+            KeyCode::Char('k') | KeyCode::Up => app.prompt_toc_move(false, 1),
+            KeyCode::Char('d') | KeyCode::PageDown => app.prompt_toc_move(true, half),
+            KeyCode::Char('u') | KeyCode::PageUp => app.prompt_toc_move(false, half),
+            KeyCode::Char('g') => app.prompt_toc_move(false, usize::MAX),
+            KeyCode::Char('G') => app.prompt_toc_move(true, usize::MAX),
+            _ => {}
+        }
+    } else if app.tool_picker.is_some() {
+        let half = app.toc_visible_rows() / 2;
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') | KeyCode::Char('F') => app.close_tool_picker(),
+            KeyCode::Char(' ') => app.toggle_picked_tool(),
+            KeyCode::Char('a') => app.show_all_tools(),
+            KeyCode::Char('j') | KeyCode::Down => app.tool_picker_move(true, 1),
+            KeyCode::Char('k') | KeyCode::Up => app.tool_picker_move(false, 1),
+            KeyCode::Char('d') | KeyCode::PageDown => app.tool_picker_move(true, half),
+            KeyCode::Char('u') | KeyCode::PageUp => app.tool_picker_move(false, half),
+            KeyCode::Char('g') => app.tool_picker_move(false, usize::MAX),
+            KeyCode::Char('G') => app.tool_picker_move(true, usize::MAX),
+            _ => {}
+        }
+    } else if app.bookmark_list.is_some() {
+        let half = app.toc_visible_rows() / 2;
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('B') => app.close_bookmarks(),
+            KeyCode::Enter => app.confirm_bookmark(),
+            KeyCode::Char('x') | KeyCode::Delete => app.delete_selected_bookmark(),
+            KeyCode::Char('j') | KeyCode::Down => app.bookmark_list_move(true, 1),
+            KeyCode::Char('k') | KeyCode::Up => app.bookmark_list_move(false, 1),
+            KeyCode::Char('d') | KeyCode::PageDown => app.bookmark_list_move(true, half),
+            KeyCode::Char('u') | KeyCode::PageUp => app.bookmark_list_move(false, half),
+            KeyCode::Char('g') => app.bookmark_list_move(false, usize::MAX),
+            KeyCode::Char('G') => app.bookmark_list_move(true, usize::MAX),
+            _ => {}
+        }
+    } else if app.instruction_list.is_some() {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('I') => app.close_instruction_files(),
+            KeyCode::Enter | KeyCode::Char('e') => app.edit_instruction_file(),
+            KeyCode::Char('s') => app.page_instruction_snapshot(),
+            KeyCode::Char('j') | KeyCode::Down => app.instruction_list_move(true),
+            KeyCode::Char('k') | KeyCode::Up => app.instruction_list_move(false),
+            _ => {}
+        }
+    } else if app.code_picker.is_some() {
+        let code = match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('y') => {
+                app.close_code_picker();
+                None
+            }
+            KeyCode::Enter => app.confirm_code_block(None),
+            KeyCode::Char(c @ '1'..='9') => app.confirm_code_block(Some(c as usize - '1' as usize)),
+            KeyCode::Char('j') | KeyCode::Down => {
+                app.code_picker_down();
+                None
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                app.code_picker_up();
+                None
+            }
+            _ => None,
+        };
+        if let Some(code) = code {
+            copy_to_clipboard(app, code);
+        }
+    } else if app.visual.is_some() {
+        let half = app.detail_visible_rows() / 2;
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('V') => app.cancel_visual(),
+            KeyCode::Char('y') | KeyCode::Enter => {
+                if let Some(text) = app.yank_visual() {
+                    copy_to_clipboard(app, text);
+                }
+            }
+            KeyCode::Char('E') => app.share_export(),
+            KeyCode::Char('j') | KeyCode::Down => app.visual_move(true, 1),
+            KeyCode::Char('k') | KeyCode::Up => app.visual_move(false, 1),
+            KeyCode::Char('d') | KeyCode::PageDown => app.visual_move(true, half),
+            KeyCode::Char('u') | KeyCode::PageUp => app.visual_move(false, half),
+            KeyCode::Char('g') => app.visual_move(false, usize::MAX),
+            KeyCode::Char('G') => app.visual_move(true, usize::MAX),
+            _ => {}
+        }
+    } else if app.command_menu.is_some() {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.close_command_menu(),
+            KeyCode::Enter => app.confirm_command(),
+            KeyCode::Char('j') | KeyCode::Down => app.command_menu_down(),
+            KeyCode::Char('k') | KeyCode::Up => app.command_menu_up(),
+            _ => {}
+        }
+    } else if app.bookmark_note.is_some() {
+        match key.code {
+            KeyCode::Esc => app.cancel_bookmark_note(),
+            KeyCode::Enter => app.confirm_bookmark_note(),
+            KeyCode::Backspace => app.bookmark_note_pop(),
+            KeyCode::Char(c) => app.bookmark_note_push(c),
+            _ => {}
+        }
+    } else if app.tag_input.is_some() {
+        match key.code {
+            KeyCode::Esc => app.cancel_tag_input(),
+            KeyCode::Enter => app.confirm_tag_input(),
+            KeyCode::Backspace => app.tag_input_pop(),
+            KeyCode::Char(c) => app.tag_input_push(c),
+            _ => {}
+        }
+    } else if app.date_range_input.is_some() {
+        match key.code {
+            KeyCode::Esc => app.cancel_date_range_input(),
+            KeyCode::Enter => app.confirm_date_range_input(),
+            KeyCode::Backspace => app.date_range_pop(),
+            KeyCode::Char(c) => app.date_range_push(c),
+            _ => {}
+        }
+    } else if app.export_prompt.is_some() {
+        match key.code {
+            KeyCode::Esc => app.cancel_snapshot_export(),
+            KeyCode::Enter => app.confirm_snapshot_export(),
+            KeyCode::Backspace => app.export_prompt_pop(),
+            KeyCode::Char(c) => app.export_prompt_push(c),
+            _ => {}
+        }
+    } else if app.detail_search_active {
+        match key.code {
+            KeyCode::Esc => app.cancel_detail_search(),
+            KeyCode::Enter => app.confirm_detail_search(),
+            KeyCode::Backspace => app.detail_search_pop(),
+            KeyCode::Char(c) => app.detail_search_push(c),
+            _ => {}
+        }
+    } else if app.search_active {
+        match key.code {
+            KeyCode::Esc => app.cancel_search(),
+            KeyCode::Enter => app.confirm_search(),
+            KeyCode::Backspace => app.search_pop(),
+            KeyCode::Down => app.navigate_down(),
+            KeyCode::Up => app.navigate_up(),
+            KeyCode::Char(c) => app.search_push(c),
+            _ => {}
+        }
+    } else {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                app.go_back();
+            }
+            KeyCode::Char('/') if app.screen == Screen::SessionDetail => {
+                app.start_detail_search();
+            }
+            KeyCode::Char('/') => {
+                app.start_search();
+            }
+            KeyCode::Char('s') => {
+                app.start_global_search();
+            }
+            KeyCode::Char('i') if app.screen == Screen::ProjectList => {
+                app.open_index_activity();
+            }
+            KeyCode::Enter => match app.screen {
+                Screen::ProjectList => app.enter_session_list(),
+                Screen::SessionList => app.enter_session_detail(),
+                Screen::SessionDetail if app.overview => app.expand_overview(),
+                Screen::SessionDetail => app.toggle_tool_result(),
+                Screen::GlobalSearch => {}
+                Screen::FileSnapshots => app.open_selected_snapshot(),
+                Screen::PermissionAudit => {}
+                Screen::SessionChanges => {}
+                Screen::SnapshotView => {}
+                Screen::CleanupReview => {}
+                Screen::IndexActivity => {}
+            },
+            KeyCode::Char('j') | KeyCode::Down => {
+                app.navigate_down();
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                app.navigate_up();
+            }
+            KeyCode::Tab if app.screen == Screen::SessionList => {
+                app.cycle_filter_next();
+            }
+            KeyCode::BackTab if app.screen == Screen::SessionList => {
+                app.cycle_filter_prev();
+            }
+            KeyCode::Char('n') if app.screen == Screen::SessionList => {
+                app.toggle_needs_input_filter();
+            }
+            KeyCode::Char('x') if app.screen == Screen::SessionList => {
+                app.toggle_expensive_filter();
+            }
+            KeyCode::Char('x') if app.screen == Screen::SessionDetail => {
+                app.toggle_result_lines();
+            }
+            KeyCode::Char('F') if app.screen == Screen::SessionList => {
+                app.cycle_script_filter();
+            }
+            KeyCode::Char('D') if app.screen == Screen::SessionList => {
+                app.open_date_range_input();
+            }
+            KeyCode::Char('o') if app.screen == Screen::SessionList => {
+                app.cycle_session_sort();
+            }
+            KeyCode::Char('V') if app.screen == Screen::SessionList => {
+                app.cycle_version_filter();
+            }
+            KeyCode::Char('P') if app.screen == Screen::SessionList => {
+                app.open_project_activity();
+            }
+            KeyCode::Char('C') if app.screen == Screen::SessionList => {
+                app.open_cleanup_review();
+            }
+            KeyCode::Char(' ') if app.screen == Screen::SessionList => {
+                app.toggle_session_mark();
+            }
+            KeyCode::Char('X') if app.screen == Screen::SessionList => {
+                app.delete_marked_sessions();
+            }
+            KeyCode::Char('Z') if app.screen == Screen::SessionList => {
+                app.archive_sessions();
+            }
+            KeyCode::Char('A') if app.screen == Screen::SessionList => {
+                app.toggle_archived_view();
+            }
+            KeyCode::Char('*') if matches!(app.screen, Screen::SessionList | Screen::SessionDetail) => {
+                app.toggle_favorite();
+            }
+            KeyCode::Char('f') if app.screen == Screen::SessionList => {
+                app.toggle_favorites_filter();
+            }
+            KeyCode::Char('#') if matches!(app.screen, Screen::SessionList | Screen::SessionDetail) => {
+                app.open_tag_input();
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.reindex();
+            }
+            KeyCode::Char('r') if app.screen == Screen::SessionList => {
+                app.toggle_show_resumed();
+            }
+            KeyCode::Char('a') => {
+                app.open_permission_audit();
+            }
+            KeyCode::Char('c') if app.screen == Screen::SessionDetail => {
+                app.open_session_changes();
+            }
+            KeyCode::Char(c @ '1'..='5') if app.screen == Screen::SessionDetail => {
+                app.toggle_role(c as usize - '1' as usize);
+            }
+            KeyCode::Char('t') if app.screen == Screen::SessionDetail => {
+                app.toggle_tool_roles();
+            }
+            KeyCode::Char(']') if app.screen == Screen::SessionDetail => {
+                app.jump_to_prompt(true);
+            }
+            KeyCode::Char('[') if app.screen == Screen::SessionDetail => {
+                app.jump_to_prompt(false);
+            }
+            KeyCode::Char('n') | KeyCode::Char('p')
+                if app.screen == Screen::SessionDetail && key.modifiers.contains(KeyModifiers::CONTROL) =>
+            {
+                app.switch_session(key.code == KeyCode::Char('n'));
+            }
+            KeyCode::Char('n') if app.screen == Screen::SessionDetail => {
+                app.jump_to_match(true);
+            }
+            KeyCode::Char('N') if app.screen == Screen::SessionDetail => {
+                app.jump_to_match(false);
+            }
+            KeyCode::Char('o') if app.screen == Screen::SessionDetail => {
+                app.toggle_full_messages();
+            }
+            KeyCode::Char('A') if app.screen == Screen::SessionDetail => {
+                app.toggle_answers_only();
+            }
+            KeyCode::Char(' ') if app.screen == Screen::SessionDetail => {
+                app.toggle_tool_result();
+            }
+            KeyCode::Char('M') if app.screen == Screen::SessionDetail => {
+                app.toggle_markdown();
+            }
+            KeyCode::Char('J') if app.screen == Screen::SessionDetail => {
+                app.open_raw_json();
+            }
+            KeyCode::Char('D') if app.screen == Screen::SessionDetail => {
+                app.toggle_raw_pane();
+            }
+            KeyCode::Char('T') if app.screen == Screen::SessionDetail => {
+                app.open_prompt_toc();
+            }
+            KeyCode::Char('L') if app.screen == Screen::SessionDetail => {
+                app.open_timeline();
+            }
+            KeyCode::Char('F') if app.screen == Screen::SessionDetail => {
+                app.open_tool_picker();
+            }
+            KeyCode::Char('S') if app.screen == Screen::SessionDetail => {
+                app.open_subagent();
+            }
+            KeyCode::Char('b') if app.screen == Screen::SessionDetail => {
+                app.toggle_threaded();
+            }
+            KeyCode::Char('?') if app.screen == Screen::SessionDetail => {
+                app.toggle_detail_keys();
+            }
+            KeyCode::Char('I') if app.screen == Screen::SessionDetail => {
+                app.open_instruction_files();
+            }
+            KeyCode::Char('m') if app.screen == Screen::SessionDetail => {
+                app.toggle_bookmark();
+            }
+            KeyCode::Char('B')
+                if matches!(app.screen, Screen::ProjectList | Screen::SessionList | Screen::SessionDetail) =>
+            {
+                app.open_bookmarks();
+            }
+            KeyCode::Char('V') if app.screen == Screen::SessionDetail => {
+                app.start_visual();
+            }
+            KeyCode::Char('P') if app.screen == Screen::SessionDetail => {
+                app.page_selected_message();
+            }
+            KeyCode::Char('R') if app.screen == Screen::SessionDetail => {
+                app.reask_prompt();
+            }
+            KeyCode::Char('y') if app.screen == Screen::SessionDetail => {
+                if let Some(code) = app.open_code_blocks() {
+                    copy_to_clipboard(app, code);
+                }
+            }
+            KeyCode::Char('v') if app.screen == Screen::SessionDetail => {
+                if app.overview {
+                    app.expand_overview();
+                } else {
+                    app.open_overview();
+                }
+            }
+            KeyCode::Char('f') if app.screen == Screen::SessionDetail => {
+                app.open_file_snapshots();
+            }
+            KeyCode::Char('w')
+                if matches!(app.screen, Screen::FileSnapshots | Screen::SnapshotView) =>
+            {
+                app.start_snapshot_export();
+            }
+            KeyCode::Char('!')
+                if matches!(app.screen, Screen::SessionList | Screen::SessionDetail) =>
+            {
+                app.open_command_menu();
+            }
+            KeyCode::Char('E')
+                if matches!(app.screen, Screen::SessionList | Screen::SessionDetail) =>
+            {
+                app.share_export();
+            }
+            KeyCode::Char('e') if app.screen == Screen::PermissionAudit => {
+                app.export_permission_audit();
+            }
+            KeyCode::Char('e')
+                if matches!(app.screen, Screen::SessionList | Screen::SessionDetail) =>
+            {
+                app.export_prompt_digest();
+            }
+            KeyCode::Char('d') => {
+                app.half_page_down();
+            }
+            KeyCode::Char('u') => {
+                app.half_page_up();
+            }
+            KeyCode::Char('g') => {
+                app.go_to_top();
+            }
+            KeyCode::Char('G') => {
+                app.go_to_bottom();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn run_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+) -> Result<()> {
+    loop {
+        terminal.draw(|frame| {
+            app.terminal_height = frame.area().height as usize;
+            ui::draw(frame, app);
+        })?;
+
+        app.poll_indexing();
+        app.poll_share();
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+
+        let event = event::read()?;
+        if let Event::Mouse(mouse) = event {
+            if mouse.kind == MouseEventKind::Down(MouseButton::Left) {
+                app.click_minimap(mouse.column, mouse.row);
+            }
+            continue;
+        }
+        if let Event::Key(key) = event {
+            handle_key(app, key);
+        }
+
+        if let Some((name, command_line)) = app.pending_command.take() {
+            app.status_message = Some(run_external_command(terminal, &name, &command_line)?);
+        }
+        if let Some((command_line, text)) = app.pending_pager.take() {
+            app.status_message = run_interactive(terminal, &command_line, Some(&text))?;
+        }
+        if let Some(command_line) = app.pending_editor.take() {
+            app.status_message = run_interactive(terminal, &command_line, None)?;
+        }
+
+        if app.should_quit {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_MAX_MESSAGE_CHARS;
+
+    fn make_project(name: &str) -> ProjectInfo {
+        ProjectInfo {
+            dir_name: name.to_string(),
+            original_path: format!("/path/{}", name),
+            session_count: 0,
+        }
+    }
+
+    fn make_session(id: &str) -> SessionInfo {
+        SessionInfo {
+            session_id: id.to_string(),
+            project_name: "test".to_string(),
+            preview: format!("Preview {}", id),
+            timestamp: Some(chrono::Utc::now()),
+            modified: None,
+            message_count: 0,
+            git_branch: String::new(),
+            summary: String::new(),
+            needs_input: false,
+            usage: TokenUsage::default(),
+            cost_usd: 0.0,
+            has_loops: false,
+            parent_session_id: String::new(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+            warning: String::new(),
+        }
+    }
+
+    fn make_message(role: MessageRole, text: &str) -> Message {
+        Message {
+            role,
+            text: text.to_string(),
+            timestamp: None,
+            tool_name: None,
+            tool_input: None,
+            model: None,
+            usage: None,
+            source_line: None,
+            uuid: None,
+            parent_uuid: None,
+            sidechain: false,
+        }
+    }
+
+    // ===== ナビゲーションテスト =====
+
+    #[test]
+    fn navigate_down_project_list() {
+        let mut app = App::with_projects(vec![
+            make_project("a"),
+            make_project("b"),
+            make_project("c"),
+        ]);
+        assert_eq!(app.project_list.selected, 0);
+        app.navigate_down();
+        assert_eq!(app.project_list.selected, 1);
+        app.navigate_down();
+        assert_eq!(app.project_list.selected, 2);
+    }
+
+    #[test]
+    fn navigate_up_project_list() {
+        let mut app = App::with_projects(vec![
+            make_project("a"),
+            make_project("b"),
+            make_project("c"),
+        ]);
+        app.project_list.selected = 2;
+        app.navigate_up();
+        assert_eq!(app.project_list.selected, 1);
+        app.navigate_up();
+        assert_eq!(app.project_list.selected, 0);
+    }
+
+    #[test]
+    fn navigate_down_session_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![
+            make_session("s1"),
+            make_session("s2"),
+            make_session("s3"),
+        ]);
+        assert_eq!(app.session_list.selected, 0);
+        app.navigate_down();
+        assert_eq!(app.session_list.selected, 1);
+        app.navigate_down();
+        assert_eq!(app.session_list.selected, 2);
+    }
+
+    #[test]
+    fn navigate_up_session_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![
+            make_session("s1"),
+            make_session("s2"),
+            make_session("s3"),
+        ]);
+        app.session_list.selected = 2;
+        app.navigate_up();
+        assert_eq!(app.session_list.selected, 1);
+        app.navigate_up();
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    #[test]
+    fn navigate_down_session_detail() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![
+            make_message(MessageRole::User, "hello"),
+            make_message(MessageRole::Assistant, "hi"),
+        ]);
+        assert_eq!(app.selected_message, 0);
+        app.navigate_down();
+        assert_eq!(app.selected_message, 1);
+        // 最後のメッセージで止まる
+        app.navigate_down();
+        assert_eq!(app.selected_message, 1);
+    }
+
+    #[test]
+    fn selected_message_stays_on_screen_and_is_marked() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.terminal_height = 20;
+        app.set_messages((0..30).map(|i| make_message(MessageRole::User, &format!("prompt {}", i))).collect());
+        let draw = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(40, 20)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().clone()
+        };
+        // 本文の枠の左端: 選択中のメッセージの行だけ太線になる
+        let marked_rows = |app: &App| {
+            let buffer = draw(app);
+            (0..20).filter(|&y| buffer[(0, y)].symbol() == "┃").count()
+        };
+        assert_eq!(marked_rows(&app), 2);
+
+        for _ in 0..10 {
+            app.navigate_down();
+        }
+        assert_eq!(app.selected_message, 10);
+        let rows = app.detail_cache.borrow().message_rows(10);
+        assert!(rows.start >= app.scroll_offset && rows.end <= app.scroll_offset + 15);
+        assert_eq!(marked_rows(&app), 2);
+
+        // 行単位のスクロールで画面外に出たら、上端のメッセージを選び直す
+        for _ in 0..3 {
+            app.half_page_down();
+        }
+        let rows = app.detail_cache.borrow().message_rows(app.selected_message);
+        assert!(app.selected_message > 10);
+        assert!(rows.end > app.scroll_offset && rows.start < app.scroll_offset + 15);
+
+        app.go_to_top();
+        assert_eq!((app.selected_message, app.scroll_offset), (0, 0));
+    }
+
+    #[test]
+    fn brackets_jump_between_prompts() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![
+            make_message(MessageRole::User, "first"),
+            make_message(MessageRole::Assistant, "a\nb\nc"),
+            make_message(MessageRole::ToolUse, "Bash"),
+            make_message(MessageRole::ToolResult, "ok"),
+            make_message(MessageRole::User, "second"),
+            make_message(MessageRole::Assistant, "done"),
+        ]);
+        // 次のプロンプトが画面の先頭に来る
+        app.jump_to_prompt(true);
+        assert_eq!(app.selected_message, 4);
+        assert_eq!(app.scroll_offset, app.detail_cache.borrow().message_rows(4).start);
+        assert!(app.scroll_offset > 0);
+        app.jump_to_prompt(true);
+        assert_eq!(app.selected_message, 4);
+        assert_eq!(app.status_message.as_deref(), Some("No later prompt"));
+        app.jump_to_prompt(false);
+        assert_eq!((app.selected_message, app.scroll_offset), (0, 0));
+
+        // 概観では選択行だけが動く
+        app.open_overview();
+        app.jump_to_prompt(true);
+        assert_eq!(app.overview_list.selected, 4);
+        app.jump_to_prompt(false);
+        assert_eq!(app.overview_list.selected, 0);
+    }
+
+    #[test]
+    fn navigate_up_session_detail() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![
+            make_message(MessageRole::User, "hello"),
+            make_message(MessageRole::Assistant, "hi"),
+        ]);
+        app.go_to_bottom();
+        assert_eq!(app.selected_message, 1);
+        app.navigate_up();
+        assert_eq!(app.selected_message, 0);
+        app.navigate_up();
+        assert_eq!(app.selected_message, 0);
+    }
+
+    #[test]
+    fn navigate_down_empty_project_list_no_panic() {
+        let mut app = App::with_projects(vec![]);
+        app.navigate_down(); // should not panic
+        assert_eq!(app.project_list.selected, 0);
+    }
+
+    #[test]
+    fn navigate_down_empty_session_list_no_panic() {
+        let mut app = App::with_projects(vec![]);
+        app.set_sessions(vec![]);
+        app.navigate_down(); // should not panic
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    #[test]
+    fn navigate_up_at_top_stays_zero() {
+        let mut app = App::with_projects(vec![
+            make_project("a"),
+            make_project("b"),
+        ]);
+        assert_eq!(app.project_list.selected, 0);
+        app.navigate_up();
+        assert_eq!(app.project_list.selected, 0);
+    }
+
+    #[test]
+    fn navigate_down_at_bottom_stays_max() {
+        let mut app = App::with_projects(vec![
+            make_project("a"),
+            make_project("b"),
+            make_project("c"),
+        ]);
+        app.project_list.selected = 2;
+        app.navigate_down();
+        assert_eq!(app.project_list.selected, 2);
+    }
+
+    #[test]
+    fn navigate_up_session_list_at_top_stays_zero() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2")]);
+        assert_eq!(app.session_list.selected, 0);
+        app.navigate_up();
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    #[test]
+    fn navigate_down_session_list_at_bottom_stays_max() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2")]);
+        app.session_list.selected = 1;
+        app.navigate_down();
+        assert_eq!(app.session_list.selected, 1);
+    }
+
+    #[test]
+    fn navigate_up_session_detail_at_zero_stays_zero() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        assert_eq!(app.scroll_offset, 0);
+        app.navigate_up();
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    // ===== ハーフページテスト =====
+
+    #[test]
+    fn half_page_down_project_list() {
+        let projects: Vec<_> = (0..20).map(|i| make_project(&format!("p{}", i))).collect();
+        let mut app = App::with_projects(projects);
+        app.terminal_height = 24;
+        assert_eq!(app.project_list.selected, 0);
+        app.half_page_down();
+        assert_eq!(app.project_list.selected, 12); // 24/2 = 12
+    }
+
+    #[test]
+    fn half_page_up_project_list() {
+        let projects: Vec<_> = (0..20).map(|i| make_project(&format!("p{}", i))).collect();
+        let mut app = App::with_projects(projects);
+        app.terminal_height = 24;
+        app.project_list.selected = 15;
+        app.half_page_up();
+        assert_eq!(app.project_list.selected, 3); // 15 - 12 = 3
+    }
+
+    #[test]
+    fn half_page_down_session_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let sessions: Vec<_> = (0..20).map(|i| make_session(&format!("s{}", i))).collect();
+        app.set_sessions(sessions);
+        app.terminal_height = 24;
+        assert_eq!(app.session_list.selected, 0);
+        app.half_page_down();
+        assert_eq!(app.session_list.selected, 12);
+    }
+
+    #[test]
+    fn half_page_up_session_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let sessions: Vec<_> = (0..20).map(|i| make_session(&format!("s{}", i))).collect();
+        app.set_sessions(sessions);
+        app.terminal_height = 24;
+        app.session_list.selected = 15;
+        app.half_page_up();
+        assert_eq!(app.session_list.selected, 3);
+    }
+
+    #[test]
+    fn half_page_down_session_detail() {
+        let mut app = long_detail(24);
+        assert_eq!(app.scroll_offset, 0);
+        app.half_page_down();
+        // 本文の高さ 24 - 5 = 19 の半分
+        assert_eq!(app.scroll_offset, 9);
+    }
+
+    #[test]
+    fn half_page_up_session_detail() {
+        let mut app = long_detail(24);
+        app.scroll_offset = 20;
+        app.half_page_up();
+        assert_eq!(app.scroll_offset, 11);
+    }
+
+    #[test]
+    fn half_page_down_stops_at_the_last_screen() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        app.terminal_height = 24;
+        app.half_page_down();
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn half_page_down_clamps_project_list() {
+        let mut app = App::with_projects(vec![
+            make_project("a"),
+            make_project("b"),
+            make_project("c"),
+        ]);
+        app.terminal_height = 24; // half = 12, but only 3 items
+        app.half_page_down();
+        assert_eq!(app.project_list.selected, 2); // clamped to max index
+    }
+
+    #[test]
+    fn half_page_down_clamps_session_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
+        app.terminal_height = 24;
+        app.half_page_down();
+        assert_eq!(app.session_list.selected, 2); // clamped to max index
+    }
+
+    #[test]
+    fn half_page_up_clamps_at_zero() {
+        let mut app = App::with_projects(vec![
+            make_project("a"),
+            make_project("b"),
+        ]);
+        app.terminal_height = 24;
+        app.project_list.selected = 3; // even if beyond, saturating_sub handles it
+        app.half_page_up();
+        assert_eq!(app.project_list.selected, 0);
+    }
+
+    // ===== go_to_top / go_to_bottom テスト =====
+
+    #[test]
+    fn go_to_top_project_list() {
+        let mut app = App::with_projects(vec![
+            make_project("a"),
+            make_project("b"),
+            make_project("c"),
+        ]);
+        app.project_list.selected = 2;
+        app.go_to_top();
+        assert_eq!(app.project_list.selected, 0);
+    }
+
+    #[test]
+    fn go_to_top_session_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
+        app.session_list.selected = 2;
+        app.go_to_top();
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    #[test]
+    fn go_to_top_session_detail() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        app.scroll_offset = 100;
+        app.go_to_top();
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn go_to_bottom_project_list() {
+        let mut app = App::with_projects(vec![
+            make_project("a"),
+            make_project("b"),
+            make_project("c"),
+        ]);
+        app.go_to_bottom();
+        assert_eq!(app.project_list.selected, 2);
+    }
+
+    #[test]
+    fn go_to_bottom_session_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
+        app.go_to_bottom();
+        assert_eq!(app.session_list.selected, 2);
+    }
+
+    #[test]
+    fn go_to_bottom_session_detail() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = long_detail(24);
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        assert!(screen(&app).contains("Top"));
+        app.go_to_bottom();
+        let total = app.detail_cache.borrow().total_rows();
+        assert_eq!(app.scroll_offset, total - 19);
+        assert!(screen(&app).contains("Bot"));
+        // 下端からはすぐに戻れる
+        app.half_page_up();
+        assert_eq!(app.scroll_offset, total - 19 - 9);
+        assert!(screen(&app).contains('%'));
+    }
+
+    #[test]
+    fn detail_bottom_bar_shows_position_until_keys_are_asked_for() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = long_detail(24);
+        let bottom = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..80).map(|x| buffer[(x, 23)].symbol()).collect::<String>()
+        };
+        let total = app.detail_cache.borrow().total_rows();
+        assert!(bottom(&app).starts_with(&format!(" message 1/30 · line 1/{} · Top", total)));
+
+        app.go_to_bottom();
+        app.toggle_role(0);
+        assert_eq!(app.selected_message_position(), (30, 30));
+        let bar = bottom(&app);
+        assert!(bar.contains(" · Bot"), "{}", bar);
+        assert!(bar.contains("[hidden: "), "{}", bar);
+
+        app.toggle_detail_keys();
+        assert!(bottom(&app).starts_with("?: Position  Esc: Back"));
+    }
+
+    /// 80x24 で一度描画した、画面に収まらない長さの詳細画面
+    fn long_detail(height: u16) -> App {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages((0..30).map(|i| make_message(MessageRole::Assistant, &format!("answer {}", i))).collect());
+        app.terminal_height = height as usize;
+        let mut terminal = Terminal::new(TestBackend::new(80, height)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        app
+    }
+
+    #[test]
+    fn go_to_top_empty_project_list_no_panic() {
+        let mut app = App::with_projects(vec![]);
+        app.go_to_top(); // should not panic
+        assert_eq!(app.project_list.selected, 0);
+    }
+
+    #[test]
+    fn go_to_bottom_empty_project_list_no_panic() {
+        let mut app = App::with_projects(vec![]);
+        app.go_to_bottom(); // should not panic
+        assert_eq!(app.project_list.selected, 0);
+    }
+
+    #[test]
+    fn go_to_top_empty_session_list_no_panic() {
+        let mut app = App::with_projects(vec![]);
+        app.set_sessions(vec![]);
+        app.go_to_top();
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    #[test]
+    fn go_to_bottom_empty_session_list_no_panic() {
+        let mut app = App::with_projects(vec![]);
+        app.set_sessions(vec![]);
+        app.go_to_bottom();
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    // ===== go_back テスト =====
+
+    #[test]
+    fn go_back_from_project_list_sets_should_quit() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        assert_eq!(app.screen, Screen::ProjectList);
+        app.go_back();
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn go_back_from_session_list_to_project_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1")]);
+        assert_eq!(app.screen, Screen::SessionList);
+        app.session_list.selected = 1; // some value
+        app.go_back();
+        assert_eq!(app.screen, Screen::ProjectList);
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    #[test]
+    fn go_back_from_session_detail_to_session_list() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1")]);
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        assert_eq!(app.screen, Screen::SessionDetail);
+        app.go_back();
+        assert_eq!(app.screen, Screen::SessionList);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn switch_session_steps_through_the_list_keeping_filters_and_highlights() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
+        app.enter_session_detail();
+        assert_eq!(app.current_session_id, "s1");
+        app.set_detail_query("cargo");
+        app.toggle_role(0);
+        let hidden = app.hidden_roles.clone();
+
+        app.switch_session(true);
+        assert_eq!(app.current_session_id, "s2");
+        assert_eq!(app.session_list.selected, 1);
+        assert_eq!(app.screen, Screen::SessionDetail);
+        assert_eq!(app.detail_query, "cargo");
+        assert_eq!(app.hidden_roles, hidden);
+        assert_eq!(app.status_message.as_deref(), Some("Session 2/3"));
+
+        app.switch_session(false);
+        app.switch_session(false);
+        assert_eq!(app.current_session_id, "s1");
+        assert_eq!(app.status_message.as_deref(), Some("Already at the first session"));
+
+        // 一覧にないセッション（Global Search から開いたもの）では動かない
+        app.current_session_id = "elsewhere".to_string();
+        app.switch_session(true);
+        assert_eq!(app.current_session_id, "elsewhere");
+    }
+
+    // ===== フィルタテスト =====
+
+    #[test]
+    fn cycle_filter_next_order() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1")]);
+        assert_eq!(app.time_filter, TimeFilter::All);
+        app.cycle_filter_next();
+        assert_eq!(app.time_filter, TimeFilter::Yesterday);
+        app.cycle_filter_next();
+        assert_eq!(app.time_filter, TimeFilter::Week);
+        app.cycle_filter_next();
+        assert_eq!(app.time_filter, TimeFilter::Month);
+        app.cycle_filter_next();
+        assert_eq!(app.time_filter, TimeFilter::All);
+    }
+
+    #[test]
+    fn cycle_filter_prev_order() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1")]);
+        assert_eq!(app.time_filter, TimeFilter::All);
+        app.cycle_filter_prev();
+        assert_eq!(app.time_filter, TimeFilter::Month);
+        app.cycle_filter_prev();
+        assert_eq!(app.time_filter, TimeFilter::Week);
+        app.cycle_filter_prev();
+        assert_eq!(app.time_filter, TimeFilter::Yesterday);
+        app.cycle_filter_prev();
+        assert_eq!(app.time_filter, TimeFilter::All);
+    }
+
+    #[test]
+    fn cycle_filter_resets_selected_session() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
+        app.session_list.selected = 2;
+        app.cycle_filter_next();
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    #[test]
+    fn cycle_filter_prev_resets_selected_session() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
+        app.session_list.selected = 2;
+        app.cycle_filter_prev();
+        assert_eq!(app.session_list.selected, 0);
+    }
+
+    #[test]
+    fn toggle_needs_input_filter_keeps_waiting_sessions() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let mut waiting = make_session("waiting");
+        waiting.needs_input = true;
+        app.set_sessions(vec![make_session("done"), waiting]);
+        app.session_list.selected = 1;
+        app.toggle_needs_input_filter();
+        assert!(app.needs_input_only);
+        assert_eq!(app.filtered_sessions.len(), 1);
+        assert_eq!(app.filtered_sessions[0].session_id, "waiting");
+        assert_eq!(app.session_list.selected, 0);
+        app.toggle_needs_input_filter();
+        assert_eq!(app.filtered_sessions.len(), 2);
+    }
+
+    #[test]
+    fn title_shows_active_filters_and_counts() {
+        let mut app = App::with_projects(vec![make_project("a"), make_project("b")]);
+        assert!(app.title_filters().is_empty());
+        assert_eq!(app.title_counts(), "2 projects");
+
+        let mut waiting = make_session("waiting");
+        waiting.needs_input = true;
+        app.time_filter = TimeFilter::All;
+        app.set_sessions(vec![make_session("done"), waiting]);
+        assert_eq!(app.title_counts(), "2 sessions");
+        app.toggle_needs_input_filter();
+        app.time_filter = TimeFilter::Week;
+        assert_eq!(app.title_filters(), vec!["Week", "needs input"]);
+        assert_eq!(app.title_counts(), "1/2 sessions");
+
+        app.set_messages(vec![
+            make_message(MessageRole::User, "hi"),
+            make_message(MessageRole::Assistant, "hello"),
+        ]);
+        app.screen = Screen::SessionDetail;
+        app.toggle_answers_only();
+        assert_eq!(app.title_filters(), vec!["answers only"]);
+        assert_eq!(app.title_counts(), "1/2 messages");
+    }
+
+    fn make_priced_sessions() -> Vec<SessionInfo> {
+        (1..=20)
+            .map(|i| {
+                let mut s = make_session(&format!("s{}", i));
+                s.cost_usd = i as f64;
+                s
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expensive_threshold_is_top_decile() {
+        assert_eq!(expensive_threshold(&make_priced_sessions()), Some(18.0));
+        assert_eq!(expensive_threshold(&make_priced_sessions()[..5]), None);
+    }
+
+    #[test]
+    fn session_list_folds_resumed_sessions_until_toggled() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let mut latest = make_session("s3");
+        latest.parent_session_id = "s2".to_string();
+        let mut middle = make_session("s2");
+        middle.parent_session_id = "s1".to_string();
+        app.set_sessions(vec![latest, make_session("other"), middle, make_session("s1")]);
+        let ids: Vec<&str> = app.filtered_sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s3", "other"]);
+        assert_eq!(app.session_resumes.get("s3"), Some(&2));
+
+        app.toggle_show_resumed();
+        assert_eq!(app.filtered_sessions.len(), 4);
+        assert!(app.session_resumes.is_empty());
+    }
+
+    #[test]
+    fn cycle_script_filter_without_scripts_keeps_all_sessions() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2")]);
+        app.cycle_script_filter();
+        assert!(app.script_filter.is_none());
+        assert!(app.status_message.is_some());
+        assert_eq!(app.filtered_sessions.len(), 2);
+    }
+
+    #[test]
+    fn cycle_version_filter_steps_through_versions_newest_first() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let with_version = |id: &str, version: &str| SessionInfo { version: version.to_string(), ..make_session(id) };
+        app.set_sessions(vec![
+            with_version("s1", "2.0.9"),
+            with_version("s2", "2.0.10"),
+            with_version("s3", ""),
+            with_version("s4", "2.0.9"),
+        ]);
+        let ids = |app: &App| app.filtered_sessions.iter().map(|s| s.session_id.clone()).collect::<Vec<_>>();
+
+        app.cycle_version_filter();
+        assert_eq!(app.version_filter.as_deref(), Some("2.0.10"));
+        assert_eq!(ids(&app), vec!["s2"]);
+        assert!(app.title_filters().contains(&"v2.0.10".to_string()));
+        app.cycle_version_filter();
+        assert_eq!(ids(&app), vec!["s1", "s4"]);
+        app.cycle_version_filter();
+        assert!(app.version_filter.is_none());
+        assert_eq!(app.filtered_sessions.len(), 4);
+
+        app.set_sessions(vec![make_session("s5")]);
+        app.cycle_version_filter();
+        assert!(app.version_filter.is_none());
+        assert!(app.status_message.is_some());
+    }
+
+    #[test]
+    fn cycle_session_sort_reorders_and_keeps_the_selection() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let session = |id: &str, messages: usize, branch: &str| SessionInfo {
+            message_count: messages,
+            git_branch: branch.to_string(),
+            ..make_session(id)
+        };
+        app.set_sessions(vec![session("s1", 3, "main"), session("s2", 40, ""), session("s3", 12, "feat/a")]);
+        let ids = |app: &App| app.filtered_sessions.iter().map(|s| s.session_id.clone()).collect::<Vec<_>>();
+        app.navigate_down();
+
+        app.cycle_session_sort();
+        assert_eq!(app.session_sort, SessionSort::Modified);
+        app.cycle_session_sort();
+        assert_eq!(ids(&app), vec!["s2", "s3", "s1"]);
+        assert_eq!(app.session_list.selected, 0);
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains(" Sessions [sort: messages] "));
+
+        app.cycle_session_sort();
+        assert_eq!(ids(&app), vec!["s3", "s1", "s2"]);
+        assert_eq!(app.session_list.selected, 2);
+        // 一覧を読み込み直しても並び順は維持する
+        app.set_sessions(vec![session("s4", 1, "zz"), session("s5", 1, "aa")]);
+        assert_eq!(ids(&app), vec!["s5", "s4"]);
+
+        // トークン順ではトークン数、費用順では推定費用を列に出す
+        let spent = |id: &str, tokens: u64, cost: f64| SessionInfo {
+            usage: TokenUsage { output_tokens: tokens, ..TokenUsage::default() },
+            cost_usd: cost,
+            ..make_session(id)
+        };
+        app.set_sessions(vec![spent("s6", 900, 0.05), spent("s7", 1_500_000, 4.5)]);
+        app.cycle_session_sort();
+        assert_eq!(app.session_sort, SessionSort::Tokens);
+        assert_eq!(ids(&app), vec!["s7", "s6"]);
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Tokens") && screen.contains("1.5M") && screen.contains("900"));
+        app.cycle_session_sort();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Cost") && screen.contains("$4.50") && screen.contains("$0.05"));
+        app.cycle_session_sort();
+        assert_eq!(app.session_sort, SessionSort::Created);
+    }
+
+    #[test]
+    fn date_range_input_filters_by_whole_days() {
+        use chrono::TimeZone;
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let on = |id: &str, day: u32, hour: u32| SessionInfo {
+            timestamp: Some(Utc.with_ymd_and_hms(2026, 1, day, hour, 0, 0).unwrap()),
+            ..make_session(id)
+        };
+        app.set_sessions(vec![on("s1", 16, 0), on("s2", 15, 23), on("s3", 8, 9), on("s4", 1, 0)]);
+        let ids = |app: &App| app.filtered_sessions.iter().map(|s| s.session_id.clone()).collect::<Vec<_>>();
+
+        app.open_date_range_input();
+        assert_eq!(app.date_range_input.as_deref(), Some(""));
+        "2026-01-02..2026-01-15".chars().for_each(|c| app.date_range_push(c));
+        app.confirm_date_range_input();
+        assert_eq!(ids(&app), vec!["s2", "s3"]);
+        assert_eq!(app.title_filters(), vec!["2026-01-02..2026-01-15"]);
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("All │ 2026-01-02..2026-01-15"));
+
+        // 今の期間を書き換えて開いた端を作る
+        app.open_date_range_input();
+        assert_eq!(app.date_range_input.as_deref(), Some("2026-01-02..2026-01-15"));
+        (0..10).for_each(|_| app.date_range_pop());
+        app.confirm_date_range_input();
+        assert_eq!(ids(&app), vec!["s1", "s2", "s3"]);
+
+        // 読めない期間は今の絞り込みを変えない
+        app.open_date_range_input();
+        "x".chars().for_each(|c| app.date_range_push(c));
+        app.confirm_date_range_input();
+        assert!(app.status_message.as_deref().unwrap().starts_with("Not a date range"));
+        assert_eq!(ids(&app).len(), 3);
+
+        app.cycle_filter_prev();
+        assert_eq!(app.time_filter, TimeFilter::All);
+        assert_eq!(ids(&app).len(), 4);
+    }
+
+    #[test]
+    fn toggle_expensive_filter_sorts_by_cost() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(make_priced_sessions());
+        assert!(app.is_expensive(&app.sessions[19]));
+        assert!(!app.is_expensive(&app.sessions[0]));
+        app.toggle_expensive_filter();
+        let ids: Vec<&str> = app
+            .filtered_sessions
+            .iter()
+            .map(|s| s.session_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["s20", "s19", "s18"]);
+        app.toggle_expensive_filter();
+        assert_eq!(app.filtered_sessions.len(), 20);
+    }
+
+    // ===== set_sessions / set_messages テスト =====
+
+    #[test]
+    fn set_sessions_updates_state() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        assert_eq!(app.screen, Screen::ProjectList);
+        let sessions = vec![make_session("s1"), make_session("s2")];
+        app.set_sessions(sessions);
+        assert_eq!(app.screen, Screen::SessionList);
+        assert_eq!(app.sessions.len(), 2);
+        assert_eq!(app.filtered_sessions.len(), 2);
+        assert_eq!(app.session_list.selected, 0);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn set_sessions_applies_filter() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        // Set filter to Yesterday; sessions with old timestamps should be filtered out
+        app.time_filter = TimeFilter::Yesterday;
+        let mut old_session = make_session("old");
+        old_session.timestamp = Some(chrono::Utc::now() - chrono::Duration::days(10));
+        let recent_session = make_session("recent");
+        app.set_sessions(vec![old_session, recent_session]);
+        assert_eq!(app.sessions.len(), 2);
+        assert_eq!(app.filtered_sessions.len(), 1);
+        assert_eq!(app.filtered_sessions[0].session_id, "recent");
+    }
+
+    #[test]
+    fn prompt_digest_target_follows_screen_and_filters() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        assert!(app.prompt_digest_target().is_none());
+
+        app.current_project_name = "-proj".to_string();
+        app.time_filter = TimeFilter::Week;
+        let mut old_session = make_session("old");
+        old_session.timestamp = Some(chrono::Utc::now() - chrono::Duration::days(30));
+        let mut summarized = make_session("s2");
+        summarized.summary = "Fix login".to_string();
+        app.set_sessions(vec![old_session, make_session("s1"), summarized]);
+        let (heading, targets) = app.prompt_digest_target().unwrap();
+        assert_eq!(heading, "Prompts: -proj (Week)");
+        assert_eq!(
+            targets,
+            vec![
+                ("s1".to_string(), "Preview s1".to_string()),
+                ("s2".to_string(), "Fix login".to_string()),
+            ]
+        );
+
+        app.current_session_id = "s2".to_string();
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        let (heading, targets) = app.prompt_digest_target().unwrap();
+        assert_eq!(heading, "Prompts: s2");
+        assert_eq!(targets, vec![("s2".to_string(), "Fix login".to_string())]);
+    }
+
+    #[test]
+    fn marked_sessions_are_exported_and_deleted_together() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.current_project_name = "-proj-without-transcripts".to_string();
+        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
+        app.toggle_session_mark();
+        app.navigate_down();
+        app.toggle_session_mark();
+        assert_eq!(app.session_list.selected, 2);
+        let ids: Vec<&str> = app.marked_in_list().iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s1", "s3"]);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains(" Sessions [2 marked] "));
+        assert!(screen.contains("● Preview s1"));
+
+        // 書き出しは印を付けたものだけ
+        let (heading, targets) = app.prompt_digest_target().unwrap();
+        assert_eq!(heading, "Prompts: -proj-without-transcripts (2 marked)");
+        assert_eq!(targets.len(), 2);
+
+        // 削除は続けてもう一度押したときだけ
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('X'), KeyModifiers::NONE));
+        assert_eq!(app.status_message.as_deref(), Some("Delete 2 marked sessions? Press X again to delete"));
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE));
+        assert!(!app.delete_marked_pending);
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('X'), KeyModifiers::NONE));
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('X'), KeyModifiers::NONE));
+        assert!(app.status_message.as_deref().unwrap().starts_with("Deleted 0 sessions, 2 failed: "));
+        assert_eq!(app.filtered_sessions.len(), 3);
+
+        // Esc はまず印を外す
+        app.go_back();
+        assert!(app.marked_sessions.is_empty());
+        assert_eq!(app.screen, Screen::SessionList);
+        app.delete_marked_sessions();
+        assert_eq!(app.status_message.as_deref(), Some("Mark sessions with Space first"));
+    }
+
+    /// 公開コマンドの終了を待って結果を受け取る
+    fn wait_for_share(app: &mut App) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while app.share_rx.is_some() && std::time::Instant::now() < deadline {
+            app.poll_share();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(app.share_rx.is_none(), "share command did not finish");
+    }
+
+    #[test]
+    fn share_export_pipes_markdown_off_the_ui_thread_and_copies_url() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let out = tmp.path().join("shared.md");
+        let clip = tmp.path().join("clip.txt");
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.clipboard = Box::new(clipboard::FileClipboard { path: clip.clone() });
+        app.current_project_name = "-proj".to_string();
+        app.set_sessions(vec![make_session("s1")]);
+        app.share_export();
+        assert!(app.share_rx.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("No share_command configured in config.toml"));
+
+        app.config.share_command = format!("cat > '{}'; echo 'Created gist https://gist.example/abc'", out.display());
+        app.share_export();
+        // 1回目は何を送るかを見せるだけ
+        assert_eq!(app.share_pending.as_ref().map(|s| s.what.as_str()), Some("0 prompts"));
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(120, 10)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains(" Share 0 prompts (1 line)?  E/Enter: Send"), "{}", screen);
+        assert!(app.share_rx.is_none() && !out.exists());
+        app.confirm_share();
+        assert!(app.share_pending.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("Sharing…"));
+        wait_for_share(&mut app);
+        assert!(std::fs::read_to_string(&out).unwrap().starts_with("# Prompts: -proj"));
+        assert!(app.status_message.as_deref().unwrap().starts_with("Shared 0 prompts: https://gist.example/abc, copied via "));
+        assert_eq!(std::fs::read_to_string(&clip).unwrap(), "https://gist.example/abc");
+
+        app.config.share_command = "cat > /dev/null; echo 401 Unauthorized >&2; exit 1".to_string();
+        app.share_export();
+        app.confirm_share();
+        wait_for_share(&mut app);
+        assert!(app.status_message.as_deref().unwrap().starts_with("Share failed: 401 Unauthorized"));
+    }
+
+    #[test]
+    fn share_export_sends_the_visual_selection() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let out = tmp.path().join("shared.txt");
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.clipboard = Box::new(clipboard::FileClipboard { path: tmp.path().join("clip.txt") });
+        app.config.share_command = format!("cat > '{}'; echo https://paste.example/1", out.display());
+        app.screen = Screen::SessionDetail;
+        app.terminal_height = 40;
+        app.set_messages(vec![make_message(MessageRole::User, "first line"), make_message(MessageRole::Assistant, "second")]);
+        app.start_visual();
+        app.visual_move(true, 1);
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('E'), KeyModifiers::NONE));
+        assert!(app.visual.is_none());
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('E'), KeyModifiers::NONE));
+        wait_for_share(&mut app);
+        let shared = std::fs::read_to_string(&out).unwrap();
+        assert!(shared.contains("first line"));
+        assert!(!shared.contains("second"));
+        assert!(app.status_message.as_deref().unwrap().starts_with("Shared 2 lines: https://paste.example/1"));
+    }
+
+    #[test]
+    fn share_export_asks_first_and_can_anonymize() {
+        use ratatui::backend::TestBackend;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let out = tmp.path().join("shared.txt");
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.clipboard = Box::new(clipboard::FileClipboard { path: tmp.path().join("clip.txt") });
+        app.config.share_command = format!("cat > '{}'; echo https://paste.example/1", out.display());
+        app.screen = Screen::SessionDetail;
+        app.terminal_height = 40;
+        app.set_messages(vec![make_message(MessageRole::User, "see /home/alice/notes.txt")]);
+        app.start_visual();
+        app.visual_move(true, 1);
+
+        let e = KeyEvent::new(KeyCode::Char('E'), KeyModifiers::NONE);
+        handle_key(&mut app, e);
+        let help_bar = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(120, 10)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..120).map(|x| buffer[(x, 9)].symbol()).collect::<String>()
+        };
+        assert!(
+            help_bar(&app).starts_with(" Share 2 lines?  E/Enter: Send  a: Anonymize first  Esc: Cancel  → cat > "),
+            "{}",
+            help_bar(&app)
+        );
+
+        // Esc で何も送らずにやめる
+        handle_key(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(app.share_pending.is_none() && app.share_rx.is_none());
+
+        // 索引が開けなければ匿名化せずに送ることはしない
+        app.start_visual();
+        app.visual_move(true, 1);
+        handle_key(&mut app, e);
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+        assert!(app.share_pending.is_none() && app.share_rx.is_none());
+        assert!(app.status_message.as_deref().unwrap().starts_with("Could not anonymize, nothing shared"));
+
+        app.index_path = Some(tmp.path().join("index.db"));
+        app.start_visual();
+        app.visual_move(true, 1);
+        handle_key(&mut app, e);
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+        assert!(app.share_pending.as_ref().unwrap().anonymized);
+        assert!(help_bar(&app).starts_with(" Share 2 lines anonymized?  E/Enter: Send  Esc: Cancel"));
+        handle_key(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        wait_for_share(&mut app);
+        let shared = std::fs::read_to_string(&out).unwrap();
+        assert!(shared.contains("/home/user/notes.txt") && !shared.contains("alice"), "{}", shared);
+    }
+
+    #[test]
+    fn set_messages_updates_state() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        assert_eq!(app.screen, Screen::ProjectList);
+        app.scroll_offset = 10; // set some offset
+        let messages = vec![
+            make_message(MessageRole::User, "hello"),
+            make_message(MessageRole::Assistant, "world"),
+        ];
+        app.set_messages(messages);
+        assert_eq!(app.screen, Screen::SessionDetail);
+        assert_eq!(app.messages.len(), 2);
+        assert_eq!(app.scroll_offset, 0); // reset to 0
+    }
+
+    #[test]
+    fn set_messages_detects_loops() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let mut messages = vec![make_message(MessageRole::User, "fix the test")];
+        for _ in 0..analysis::LOOP_MIN_REPEATS {
+            messages.push(make_message(MessageRole::ToolUse, "[Bash] cargo test"));
+            messages.push(make_message(MessageRole::ToolResult, "failed"));
+        }
+        app.set_messages(messages);
+        assert_eq!(app.loop_regions.len(), 1);
+        assert_eq!(app.loop_regions[0].start, 1);
+
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        assert!(app.loop_regions.is_empty());
+    }
+
+    // ===== 空リスト安全性テスト =====
+
+    #[test]
+    fn empty_projects_all_operations_safe() {
+        let mut app = App::with_projects(vec![]);
+        // navigate
+        app.navigate_down();
+        app.navigate_up();
+        // half page
+        app.half_page_down();
+        app.half_page_up();
+        // go_to
+        app.go_to_top();
+        app.go_to_bottom();
+        // go_back
+        app.go_back();
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn empty_sessions_all_operations_safe() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![]);
+        // navigate
+        app.navigate_down();
+        app.navigate_up();
+        // half page
+        app.half_page_down();
+        app.half_page_up();
+        // go_to
+        app.go_to_top();
+        app.go_to_bottom();
+        // filter
+        app.cycle_filter_next();
+        app.cycle_filter_prev();
+        // go_back
+        app.go_back();
+        assert_eq!(app.screen, Screen::ProjectList);
+    }
+
+    #[test]
+    fn empty_messages_all_operations_safe() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![]);
+        // navigate
+        app.navigate_down();
+        app.navigate_up();
+        // half page
+        app.half_page_down();
+        app.half_page_up();
+        // go_to
+        app.go_to_top();
+        app.go_to_bottom();
+        // go_back
+        app.go_back();
+        assert_eq!(app.screen, Screen::SessionList);
+    }
+
+    // ===== 検索テスト =====
+
+    #[test]
+    fn start_search_activates() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        assert!(!app.search_active);
+        app.start_search();
+        assert!(app.search_active);
+        assert!(app.search_query.is_empty());
+    }
+
+    #[test]
+    fn cancel_search_restores_all() {
+        let mut app = App::with_projects(vec![
+            make_project("alpha"),
+            make_project("beta"),
+            make_project("gamma"),
+        ]);
+        app.start_search();
+        app.search_push('z'); // フィルタで全て消える可能性あり
+        app.cancel_search();
+        assert!(!app.search_active);
+        assert!(app.search_query.is_empty());
+        assert_eq!(app.displayed_projects.len(), 3);
+    }
+
+    #[test]
+    fn confirm_search_keeps_filter() {
+        let mut app = App::with_projects(vec![
+            make_project("alpha"),
+            make_project("beta"),
+            make_project("gamma"),
+        ]);
+        app.start_search();
+        app.search_push('a'); // "alpha" と "gamma" にマッチ
+        let filtered_count = app.displayed_projects.len();
+        app.confirm_search();
+        assert!(!app.search_active);
+        assert_eq!(app.displayed_projects.len(), filtered_count);
+    }
+
+    #[test]
+    fn search_push_filters_projects() {
+        let mut app = App::with_projects(vec![
+            make_project("alpha"),
+            make_project("beta"),
+            make_project("gamma"),
+        ]);
+        app.start_search();
+        app.search_push('b');
+        app.search_push('e');
+        app.search_push('t');
+        app.search_push('a');
+        // "beta" にマッチするはず
+        assert!(app.displayed_projects.len() <= 3);
+        let has_beta = app
+            .displayed_projects
+            .iter()
+            .any(|p| p.dir_name == "beta");
+        assert!(has_beta);
+    }
+
+    #[test]
+    fn search_pop_expands_results() {
+        let mut app = App::with_projects(vec![
+            make_project("alpha"),
+            make_project("beta"),
+            make_project("gamma"),
+        ]);
+        app.start_search();
+        app.search_push('b');
+        app.search_push('e');
+        app.search_push('t');
+        app.search_push('a');
+        let narrow_count = app.displayed_projects.len();
+        app.search_pop(); // "bet" に緩和
+        let wider_count = app.displayed_projects.len();
+        assert!(wider_count >= narrow_count);
     }
 
     #[test]
-    fn navigate_up_project_list() {
+    fn search_on_session_detail_does_nothing() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        assert_eq!(app.screen, Screen::SessionDetail);
+        app.start_search();
+        assert!(!app.search_active);
+    }
+
+    #[test]
+    fn search_resets_selected_project() {
         let mut app = App::with_projects(vec![
-            make_project("a"),
-            make_project("b"),
-            make_project("c"),
+            make_project("alpha"),
+            make_project("beta"),
+            make_project("gamma"),
         ]);
-        app.selected_project = 2;
-        app.navigate_up();
-        assert_eq!(app.selected_project, 1);
-        app.navigate_up();
-        assert_eq!(app.selected_project, 0);
+        app.project_list.selected = 2;
+        app.start_search();
+        app.search_push('a');
+        assert_eq!(app.project_list.selected, 0);
     }
 
     #[test]
-    fn navigate_down_session_list() {
-        let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![
-            make_session("s1"),
-            make_session("s2"),
-            make_session("s3"),
+    fn navigate_with_search_uses_displayed_projects() {
+        let mut app = App::with_projects(vec![
+            make_project("alpha"),
+            make_project("beta"),
+            make_project("gamma"),
         ]);
-        assert_eq!(app.selected_session, 0);
+        app.start_search();
+        app.search_push('a'); // "alpha" と "gamma" にマッチ (original_path: /path/alpha, /path/gamma)
+        let count = app.displayed_projects.len();
+        // 最下端までナビゲート
+        for _ in 0..count + 5 {
+            app.navigate_down();
+        }
+        // displayed_projects のサイズを超えないこと
+        assert!(app.project_list.selected < count);
+    }
+
+    // ===== PermissionAudit テスト =====
+
+    fn make_audit_entry(tool: &str, decision: PermissionDecision) -> PermissionAuditEntry {
+        PermissionAuditEntry {
+            session_id: "s1".to_string(),
+            timestamp: None,
+            tool_name: tool.to_string(),
+            summary: format!("[{}]", tool),
+            permission_mode: "default".to_string(),
+            decision,
+        }
+    }
+
+    #[test]
+    fn permission_audit_returns_to_previous_screen() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_sessions(vec![make_session("s1")]);
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        app.enter_permission_audit(
+            "Session: s1".to_string(),
+            vec![
+                make_audit_entry("Bash", PermissionDecision::Denied),
+                make_audit_entry("Edit", PermissionDecision::Approved),
+            ],
+        );
+        assert_eq!(app.screen, Screen::PermissionAudit);
         app.navigate_down();
-        assert_eq!(app.selected_session, 1);
+        assert_eq!(app.audit_list.selected, 1);
         app.navigate_down();
-        assert_eq!(app.selected_session, 2);
+        assert_eq!(app.audit_list.selected, 1);
+        app.go_back();
+        assert_eq!(app.screen, Screen::SessionDetail);
+        assert!(app.audit_entries.is_empty());
+    }
+
+    // ===== SessionChanges テスト =====
+
+    fn make_file_change(path: &str, diff_lines: usize) -> FileChange {
+        FileChange {
+            path: path.to_string(),
+            operations: 1,
+            created: false,
+            exact: true,
+            diff: "+line\n".repeat(diff_lines),
+        }
     }
 
     #[test]
-    fn navigate_up_session_list() {
+    fn session_changes_scroll_is_clamped() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![
-            make_session("s1"),
-            make_session("s2"),
-            make_session("s3"),
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        app.terminal_height = 24;
+        app.set_file_changes(vec![make_file_change("/a", 20), make_file_change("/b", 18)]);
+        assert_eq!(app.screen, Screen::SessionChanges);
+        assert_eq!(app.changes_line_count(), 42);
+        app.go_to_bottom();
+        assert_eq!(app.changes_scroll_offset, 42 - 19);
+        app.navigate_down();
+        assert_eq!(app.changes_scroll_offset, 42 - 19);
+        app.go_back();
+        assert_eq!(app.screen, Screen::SessionDetail);
+        assert!(app.file_changes.is_empty());
+    }
+
+    #[test]
+    fn session_changes_highlight_code_by_file_extension() {
+        use ratatui::backend::TestBackend;
+        use ratatui::style::Color;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        let change = FileChange { diff: "@@ -0,0 +1 @@\n+fn main() {}\n".to_string(), ..make_file_change("/src/main.rs", 0) };
+        let cell_fg = |app: &App, x: u16| {
+            let mut terminal = Terminal::new(TestBackend::new(40, 12)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            // title(1) + breadcrumb(1) + 枠(1) + 見出し(1) + @@(1) の次の行
+            terminal.backend().buffer()[(x, 5)].fg
+        };
+        app.set_file_changes(vec![change.clone()]);
+        assert_eq!(cell_fg(&app, 1), Color::Green);
+        assert!(matches!(cell_fg(&app, 2), Color::Rgb(..)));
+
+        app.config.syntax_highlighting = false;
+        app.set_file_changes(vec![change]);
+        assert_eq!(cell_fg(&app, 2), Color::Green);
+    }
+
+    #[test]
+    fn toggle_full_messages_only_in_detail_and_resets_on_open() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.toggle_full_messages();
+        assert!(!app.show_full_messages);
+
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        assert_eq!(app.message_char_cap(), DEFAULT_MAX_MESSAGE_CHARS);
+        app.toggle_full_messages();
+        assert_eq!(app.message_char_cap(), 0);
+
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        assert!(!app.show_full_messages);
+    }
+
+    #[test]
+    fn answers_only_shows_assistant_text_and_persists() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.toggle_answers_only();
+        assert!(!app.answers_only);
+
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        app.scroll_offset = 5;
+        app.toggle_answers_only();
+        assert!(app.answers_only);
+        assert_eq!(app.scroll_offset, 0);
+        assert!(app.shows_message(&make_message(MessageRole::Assistant, "answer")));
+        assert!(!app.shows_message(&make_message(MessageRole::Assistant, "  ")));
+        assert!(!app.shows_message(&make_message(MessageRole::User, "hi")));
+        assert!(!app.shows_message(&make_message(MessageRole::ToolResult, "ok")));
+
+        // 別のセッションを開いても読書モードのまま
+        app.set_messages(vec![make_message(MessageRole::User, "next")]);
+        assert!(app.answers_only);
+        app.toggle_answers_only();
+        assert!(app.shows_message(&make_message(MessageRole::ToolResult, "ok")));
+    }
+
+    #[test]
+    fn timeline_draws_prompts_and_tool_lanes() {
+        use ratatui::backend::TestBackend;
+
+        let at = |secs: i64| Some(chrono::DateTime::from_timestamp(1_768_471_200 + secs, 0).unwrap());
+        let timed = |role, text: &str, secs: i64| Message { timestamp: at(secs), ..make_message(role, text) };
+        let call = |name: &str, text: &str, secs: i64| Message {
+            tool_name: Some(name.to_string()),
+            ..timed(MessageRole::ToolUse, text, secs)
+        };
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.screen = Screen::SessionDetail;
+        app.set_messages(vec![make_message(MessageRole::User, "no time")]);
+        app.open_timeline();
+        assert!(app.session_timeline.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("No timestamps in this session"));
+
+        app.set_messages(vec![
+            timed(MessageRole::User, "run the tests", 0),
+            call("Bash", "$ cargo test", 10),
+            timed(MessageRole::ToolResult, "ok", 250),
+            call("Read", "src/main.rs", 260),
+            timed(MessageRole::ToolResult, "fn main", 262),
+            timed(MessageRole::User, "thanks", 300),
         ]);
-        app.selected_session = 2;
-        app.navigate_up();
-        assert_eq!(app.selected_session, 1);
-        app.navigate_up();
-        assert_eq!(app.selected_session, 0);
+        app.open_timeline();
+        let mut terminal = Terminal::new(TestBackend::new(100, 24)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..24).map(|y| (0..100).map(|x| buffer[(x, y)].symbol()).collect()).collect();
+        let row = |text: &str| rows.iter().find(|r| r.contains(text)).cloned().unwrap_or_default();
+        assert!(row("Timeline").contains("┌ Timeline"));
+        assert!(row("2026-01-15 10:00:00 – 10:05:00").contains("5m 00s · 2 prompts · 2 tool calls"));
+        assert_eq!(row("│ prompts").matches('▼').count(), 2);
+        // Bash が4分、Read が2秒で、長い方が上の段
+        let (bash, read) = (row("│ Bash"), row("│ Read"));
+        assert!(bash.contains("4m 00s ×1") && bash.matches('█').count() > 40);
+        assert!(read.contains("2s ×1") && read.matches('█').count() < 3);
+        assert!(rows.iter().position(|r| *r == bash) < rows.iter().position(|r| *r == read));
+        assert!(row("Longest calls").contains("Longest calls"));
+        assert!(row("$ cargo test").contains("4m 00s  Bash"));
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('L'), KeyModifiers::NONE));
+        assert!(app.session_timeline.is_none());
     }
 
     #[test]
-    fn navigate_down_session_detail() {
+    fn tool_filter_shows_only_the_picked_tools_and_their_results() {
+        use ratatui::backend::TestBackend;
+
+        let call = |name: &str| Message { tool_name: Some(name.to_string()), ..make_message(MessageRole::ToolUse, name) };
         let mut app = App::with_projects(vec![make_project("a")]);
+        app.screen = Screen::SessionDetail;
+        app.terminal_height = 20;
         app.set_messages(vec![
-            make_message(MessageRole::User, "hello"),
-            make_message(MessageRole::Assistant, "hi"),
+            make_message(MessageRole::User, "run the tests"),
+            call("Read"),
+            call("Bash"),
+            make_message(MessageRole::ToolResult, "fn main"),
+            make_message(MessageRole::ToolResult, "3 passed"),
+            call("Bash"),
+            make_message(MessageRole::ToolResult, "ok"),
+            make_message(MessageRole::Assistant, "all green"),
         ]);
-        assert_eq!(app.scroll_offset, 0);
+        assert_eq!(app.session_tools(), vec![("Bash".to_string(), 2), ("Read".to_string(), 1)]);
+        assert_eq!(app.message_tool(4), Some("Bash"));
+
         app.navigate_down();
-        assert_eq!(app.scroll_offset, 1);
+        app.open_tool_picker();
+        app.toggle_picked_tool();
+        assert_eq!(app.shown_tools, vec!["Bash"]);
+        let shown: Vec<usize> = (0..app.messages.len()).filter(|&i| app.shows_message_at(i)).collect();
+        assert_eq!(shown, vec![0, 2, 4, 5, 6, 7]);
+        // 選択中の Read が隠れたので次に見えるメッセージを選ぶ
+        assert_eq!(app.selected_message, 2);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains(" [x] Bash"));
+        assert!(screen.contains(" [ ] Read"));
+        app.close_tool_picker();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("[tools: Bash]"));
+        assert!(!screen.contains("fn main"));
+        assert_eq!(app.title_filters(), vec!["tools: Bash"]);
+
+        // 別のセッションを開いても維持し、a で全て戻す
+        app.set_messages(vec![call("Grep"), make_message(MessageRole::ToolResult, "hit")]);
+        assert!(!app.shows_message_at(0) && !app.shows_message_at(1));
+        app.open_tool_picker();
+        app.show_all_tools();
+        assert!(app.shows_message_at(0) && app.shows_message_at(1));
+    }
+
+    #[test]
+    fn role_toggles_hide_messages_and_show_in_breadcrumb() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages(vec![
+            make_message(MessageRole::User, "question"),
+            make_message(MessageRole::ToolUse, "Bash"),
+            make_message(MessageRole::ToolResult, "ok"),
+            make_message(MessageRole::Assistant, "answer"),
+        ]);
         app.navigate_down();
-        assert_eq!(app.scroll_offset, 2);
+        assert_eq!(app.selected_message, 1);
+
+        // 選択中のツール呼び出しが隠れたら、次に見えるメッセージを選ぶ
+        app.toggle_tool_roles();
+        assert!(!app.shows_message(&make_message(MessageRole::ToolUse, "Bash")));
+        assert!(!app.shows_message(&make_message(MessageRole::ToolResult, "ok")));
+        assert_eq!(app.selected_message, 3);
+        assert_eq!(app.hidden_role_labels(), vec!["tool calls", "tool results"]);
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("[hidden: tool calls, tool results]"));
+        assert!(!screen.contains("Bash"));
+
+        // 片方だけ見えている状態で t を押すと両方隠す
+        app.toggle_role(3);
+        assert_eq!(app.hidden_role_labels(), vec!["tool calls"]);
+        app.toggle_tool_roles();
+        assert_eq!(app.hidden_role_labels(), vec!["tool calls", "tool results"]);
+        app.toggle_role(0);
+        assert_eq!(app.hidden_role_labels(), vec!["user", "tool calls", "tool results"]);
+
+        // 別のセッションを開いても維持し、もう一度押すと戻る
+        app.set_messages(vec![make_message(MessageRole::User, "next")]);
+        assert!(!app.shows_message(&app.messages[0]));
+        app.toggle_role(0);
+        app.toggle_tool_roles();
+        assert!(app.hidden_roles.is_empty());
     }
 
     #[test]
-    fn navigate_up_session_detail() {
+    fn overview_opens_at_selected_message_and_expands_at_selection() {
         let mut app = App::with_projects(vec![make_project("a")]);
         app.set_messages(vec![
-            make_message(MessageRole::User, "hello"),
-            make_message(MessageRole::Assistant, "hi"),
+            make_message(MessageRole::User, "hi"),
+            make_message(MessageRole::Assistant, "a\nb\nc"),
+            make_message(MessageRole::ToolUse, "[Bash] ls"),
         ]);
-        app.scroll_offset = 5;
-        app.navigate_up();
-        assert_eq!(app.scroll_offset, 4);
-        app.navigate_up();
+        // 幅0では折り返さないので 行: hi=0-1, 回答=2-6（空行・見出し・3行）, ツール=7-9
+        app.terminal_height = 8;
+        app.navigate_down();
+        assert_eq!(app.selected_message, 1);
+        // 本文は3行分しかないので、回答（4行）の見出しを上端に合わせる
+        assert_eq!(app.scroll_offset, 3);
+        app.open_overview();
+        assert!(app.overview);
+        assert_eq!(app.overview_list.selected, 1);
+
+        app.navigate_down();
+        app.navigate_down();
+        assert_eq!(app.overview_list.selected, 2);
         assert_eq!(app.scroll_offset, 3);
+
+        app.expand_overview();
+        assert!(!app.overview);
+        assert_eq!(app.scroll_offset, 7);
+        assert_eq!(app.selected_message, 2);
+
+        // 新しいセッションは通常表示で開く
+        app.open_overview();
+        app.set_messages(vec![make_message(MessageRole::User, "next")]);
+        assert!(!app.overview);
     }
 
     #[test]
-    fn navigate_down_empty_project_list_no_panic() {
-        let mut app = App::with_projects(vec![]);
-        app.navigate_down(); // should not panic
-        assert_eq!(app.selected_project, 0);
+    fn overview_bottom_bar_shows_the_selected_row() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages((0..40).map(|i| make_message(MessageRole::User, &format!("prompt {}", i))).collect());
+        app.terminal_height = 20;
+        app.open_overview();
+        for _ in 0..20 {
+            app.navigate_down();
+        }
+        let bottom = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..80).map(|x| buffer[(x, 19)].symbol()).collect::<String>()
+        };
+        // 15行見えるので、21行目を選ぶと6行スクロールしている（最大25行）
+        assert!(bottom(&app).starts_with(" message 21/40 · overview · 24%  ?: Keys"));
+        app.toggle_detail_keys();
+        assert!(bottom(&app).starts_with("?: Position  Enter/v: Expand at message"));
     }
 
     #[test]
-    fn navigate_down_empty_session_list_no_panic() {
-        let mut app = App::with_projects(vec![]);
-        app.set_sessions(vec![]);
-        app.navigate_down(); // should not panic
-        assert_eq!(app.selected_session, 0);
+    fn overview_keeps_the_selection_visible_under_the_action_summary() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.set_messages((0..40).map(|i| make_message(MessageRole::User, &format!("prompt {}", i))).collect());
+        app.session_actions = SessionActions { files_edited: 1, ..SessionActions::default() };
+        app.terminal_height = 20;
+        assert_eq!(app.visible_rows(&Screen::SessionDetail), 14);
+        app.open_overview();
+        for _ in 0..20 {
+            app.navigate_down();
+        }
+        // 要約行の分だけ本文が狭いので、選んだ行は最下行に来る
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..80).map(|x| buffer[(x, y)].symbol()).collect::<String>();
+        assert!(row(17).contains("prompt 20"), "{}", row(17));
+        assert!(row(4).contains("prompt 7"), "{}", row(4));
     }
 
-    #[test]
-    fn navigate_up_at_top_stays_zero() {
-        let mut app = App::with_projects(vec![
-            make_project("a"),
-            make_project("b"),
-        ]);
-        assert_eq!(app.selected_project, 0);
-        app.navigate_up();
-        assert_eq!(app.selected_project, 0);
+    // ===== バックグラウンドインデックス テスト =====
+
+    fn make_searchable(id: &str, last_activity: i64, prompt: &str) -> SearchableSession {
+        SearchableSession {
+            session_id: id.to_string(),
+            project_path: "/p".to_string(),
+            dir_name: "-p".to_string(),
+            git_branch: String::new(),
+            summary: String::new(),
+            created_at: String::new(),
+            last_activity,
+            prompts: vec![prompt.to_string()],
+            parent_session_id: String::new(),
+            expired: false,
+            source_root: String::new(),
+        }
     }
 
     #[test]
-    fn navigate_down_at_bottom_stays_max() {
-        let mut app = App::with_projects(vec![
-            make_project("a"),
-            make_project("b"),
-            make_project("c"),
-        ]);
-        app.selected_project = 2;
-        app.navigate_down();
-        assert_eq!(app.selected_project, 2);
+    fn index_progress_merges_results_and_keeps_selection() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.enter_global_search(vec![search_result_from(make_searchable(
+            "old", 1_767_225_600, "fix bug",
+        ))]);
+        app.global_search_push('b');
+
+        let progress = IndexProgress {
+            projects_done: 1,
+            projects_total: 2,
+            sessions_scanned: 2,
+            sessions_updated: 1,
+            dir_name: "-p".to_string(),
+            ..IndexProgress::default()
+        };
+        app.apply_index_event(IndexEvent::Progress(
+            progress.clone(),
+            vec![
+                make_searchable("new", 1_769_904_000, "add bar"),
+                make_searchable("other", 1_769_990_400, "unrelated"),
+            ],
+        ));
+
+        assert_eq!(app.index_progress, Some(progress.clone()));
+        let ids: Vec<&str> = app
+            .global_search_filtered
+            .iter()
+            .map(|r| r.session_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["new", "old"]);
+        // 絞り込みは維持、選択していた "old" を追従
+        assert_eq!(app.global_search_list.selected, 1);
+
+        app.apply_index_event(IndexEvent::Done(Ok((progress, Vec::new()))));
+        assert!(app.index_progress.is_none());
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Indexed 2 sessions (0 new, 1 updated, 1 unchanged)")
+        );
     }
 
     #[test]
-    fn navigate_up_session_list_at_top_stays_zero() {
+    fn manual_reindex_reports_result_on_project_list() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1"), make_session("s2")]);
-        assert_eq!(app.selected_session, 0);
-        app.navigate_up();
-        assert_eq!(app.selected_session, 0);
+        app.set_sessions(vec![make_session("s1")]);
+        app.reindex();
+        assert!(!app.index_requested);
+        app.go_back();
+
+        // 起動時の自動更新はプロジェクト一覧では知らせない
+        app.apply_index_event(IndexEvent::Done(Ok((IndexProgress::default(), Vec::new()))));
+        assert!(app.status_message.is_none());
+
+        app.index_requested = true;
+        app.apply_index_event(IndexEvent::Done(Ok((IndexProgress { sessions_scanned: 5, sessions_updated: 2, ..IndexProgress::default() }, Vec::new()))));
+        assert_eq!(app.status_message.as_deref(), Some("Indexed 5 sessions (0 new, 2 updated, 3 unchanged)"));
+        assert!(!app.index_requested);
+
+        app.index_requested = true;
+        app.apply_index_event(IndexEvent::Done(Ok((IndexProgress { sessions_scanned: 5, prompt_bytes_saved: 5000, ..IndexProgress::default() }, Vec::new()))));
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Indexed 5 sessions (0 new, 0 updated, 5 unchanged), 5 KiB of long prompts not stored")
+        );
+
+        // 読めなかったファイルがあれば頼まれていなくても知らせる
+        let report = IndexProgress { sessions_scanned: 5, bad_lines: 1, ..IndexProgress::default() };
+        app.apply_index_event(IndexEvent::Done(Ok((report.clone(), Vec::new()))));
+        assert_eq!(app.status_message, Some(report.summary()));
+
+        // 壊れた索引を作り直したときは頼まれていなくても知らせる
+        let report = IndexProgress {
+            sessions_scanned: 5,
+            recovered: true,
+            backup: Some(PathBuf::from("/cache/index.db.corrupt-20260101-120000")),
+            salvaged_rows: 3,
+            ..IndexProgress::default()
+        };
+        app.apply_index_event(IndexEvent::Done(Ok((report, Vec::new()))));
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some(
+                "The search index was damaged and has been rebuilt (5 sessions, 3 bookmarks/favorites/tags/snapshots kept); old file moved to /cache/index.db.corrupt-20260101-120000"
+            )
+        );
     }
 
     #[test]
-    fn navigate_down_session_list_at_bottom_stays_max() {
+    fn session_detail_renders_only_visible_window() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1"), make_session("s2")]);
-        app.selected_session = 1;
-        app.navigate_down();
-        assert_eq!(app.selected_session, 1);
+        let messages = (0..200)
+            .map(|i| make_message(MessageRole::User, &format!("message number {}", i)))
+            .collect();
+        app.set_messages(messages);
+        app.scroll_offset = usize::MAX / 2;
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 12)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .collect();
+        // 末尾を超えたスクロール位置は最終ページに丸められる
+        assert!(screen.contains("message number 199"));
+        assert!(!screen.contains("message number 0 "));
+
+        // 幅が変わったら折り返しを作り直す
+        let mut narrow = Terminal::new(TestBackend::new(12, 12)).unwrap();
+        narrow.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let cache = app.detail_cache.borrow();
+        assert_eq!(cache.width(), 9);
     }
 
     #[test]
-    fn navigate_up_session_detail_at_zero_stays_zero() {
+    fn tool_results_are_collapsed_until_toggled() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
+        app.terminal_height = 20;
+        app.set_messages(vec![
+            make_message(MessageRole::User, "run it"),
+            make_message(MessageRole::ToolResult, "ok"),
+            make_message(MessageRole::ToolResult, "line one\nline two\nsecret three"),
+        ]);
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        let collapsed = screen(&app);
+        assert!(collapsed.contains("▸ 3 lines, 30 B: line one"));
+        assert!(!collapsed.contains("secret three"));
+        // 1行だけの結果はそのまま
+        assert!(collapsed.contains("ok"));
+
+        // 畳んだ中の検索語も一致として数える
+        app.set_detail_query("secret");
+        app.jump_to_match(true);
+        assert_eq!(app.detail_match, Some(0));
+
+        app.toggle_tool_result();
+        assert!(app.expanded_results.contains(&2));
+        assert!(screen(&app).contains("secret three"));
+        app.toggle_tool_result();
+        assert!(app.expanded_results.is_empty());
+
         app.set_messages(vec![make_message(MessageRole::User, "hi")]);
-        assert_eq!(app.scroll_offset, 0);
-        app.navigate_up();
-        assert_eq!(app.scroll_offset, 0);
+        app.toggle_tool_result();
+        assert_eq!(app.status_message.as_deref(), Some("The selected message is not a long tool result, an edit or a write"));
     }
 
-    // ===== ハーフページテスト =====
+    #[test]
+    fn tool_output_keeps_ansi_colors_or_strips_them() {
+        use ratatui::backend::TestBackend;
+        use ratatui::style::Color;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.terminal_height = 12;
+        app.set_messages(vec![make_message(MessageRole::ToolResult, "\x1b[32mok\x1b[0m 3 passed")]);
+        let render = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().clone()
+        };
+        let buffer = render(&app);
+        let screen: String = buffer.content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("ok 3 passed") && !screen.contains("[32m"));
+        let ok = buffer.content().windows(2).position(|w| w[0].symbol() == "o" && w[1].symbol() == "k").unwrap();
+        assert_eq!(buffer.content()[ok].fg, Color::Green);
+
+        app.config.ansi_colors = false;
+        app.detail_cache.get_mut().clear();
+        let buffer = render(&app);
+        let ok = buffer.content().windows(2).position(|w| w[0].symbol() == "o" && w[1].symbol() == "k").unwrap();
+        assert_eq!(buffer.content()[ok].fg, Color::DarkGray);
+    }
 
     #[test]
-    fn half_page_down_project_list() {
-        let projects: Vec<_> = (0..20).map(|i| make_project(&format!("p{}", i))).collect();
-        let mut app = App::with_projects(projects);
-        app.terminal_height = 24;
-        assert_eq!(app.selected_project, 0);
-        app.half_page_down();
-        assert_eq!(app.selected_project, 12); // 24/2 = 12
+    fn huge_tool_results_show_first_lines_until_x() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.config.max_result_lines = 3;
+        app.terminal_height = 20;
+        let output: Vec<String> = (1..=8).map(|n| format!("out {}", n)).collect();
+        app.set_messages(vec![
+            make_message(MessageRole::User, "run it"),
+            make_message(MessageRole::ToolResult, &output.join("\n")),
+        ]);
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        app.selected_message = 1;
+        app.toggle_tool_result();
+        let capped = screen(&app);
+        assert!(capped.contains("out 3") && !capped.contains("out 4"));
+        assert!(capped.contains("… 5 more lines (press x to expand)"));
+
+        app.toggle_result_lines();
+        let full = screen(&app);
+        assert!(full.contains("out 8") && !full.contains("more lines"));
+        app.toggle_result_lines();
+        assert!(screen(&app).contains("… 5 more lines"));
+
+        // 上限 0 なら全行表示で、x は効かない
+        app.config.max_result_lines = 0;
+        app.detail_cache.get_mut().clear();
+        assert!(screen(&app).contains("out 8"));
+        app.toggle_result_lines();
+        assert_eq!(app.status_message.as_deref(), Some("The selected message is not a tool result with hidden lines"));
     }
 
     #[test]
-    fn half_page_up_project_list() {
-        let projects: Vec<_> = (0..20).map(|i| make_project(&format!("p{}", i))).collect();
-        let mut app = App::with_projects(projects);
+    fn assistant_headers_show_model_and_usage() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.terminal_height = 20;
+        let answer = Message {
+            model: Some("claude-sonnet-4-5-20250929".to_string()),
+            usage: Some(TokenUsage { input_tokens: 200, cache_read_tokens: 1000, output_tokens: 3400, ..Default::default() }),
+            ..make_message(MessageRole::Assistant, "done")
+        };
+        app.set_messages(vec![make_message(MessageRole::User, "go"), answer]);
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("ASSISTANT (claude-sonnet-4-5) 1.2k→3.4k tok"));
+        assert!(!screen.contains("USER ("));
+    }
+
+    #[test]
+    fn headers_show_the_gap_since_the_previous_message() {
+        use ratatui::backend::TestBackend;
+        use ratatui::style::Color;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
         app.terminal_height = 24;
-        app.selected_project = 15;
-        app.half_page_up();
-        assert_eq!(app.selected_project, 3); // 15 - 12 = 3
+        let start: chrono::DateTime<Utc> = "2026-01-15T10:00:00Z".parse().unwrap();
+        let at = |role, text: &str, secs: i64| Message {
+            timestamp: Some(start + chrono::Duration::seconds(secs)),
+            ..make_message(role, text)
+        };
+        app.set_messages(vec![
+            at(MessageRole::User, "go", 0),
+            at(MessageRole::Assistant, "thinking done", 133),
+            // 同じ行から出たツール呼び出しは同時刻なので出さない
+            at(MessageRole::ToolUse, "[Bash] make", 133),
+            make_message(MessageRole::System, "no time"),
+            at(MessageRole::User, "back", 133 + 3900),
+        ]);
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        let screen: String = buffer.content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("10:02:13  +2m 13s"));
+        assert_eq!(screen.matches("  +").count(), 2);
+        assert!(screen.contains("+1h 05m"));
+        let long = buffer.content().windows(2).position(|w| w[0].symbol() == "+" && w[1].symbol() == "1").unwrap();
+        assert_eq!(buffer.content()[long].fg, Color::Yellow);
+    }
+
+    #[test]
+    fn pager_gets_the_full_text_and_diffs_go_to_the_diff_pager() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.config.pager = "most".to_string();
+        app.config.diff_pager = "delta".to_string();
+        let edit = Message {
+            tool_name: Some("Edit".to_string()),
+            tool_input: Some(serde_json::json!({"file_path": "/w/a.txt", "old_string": "old", "new_string": "new"})),
+            ..make_message(MessageRole::ToolUse, "[Edit] /w/a.txt")
+        };
+        let bash = Message {
+            tool_name: Some("Bash".to_string()),
+            tool_input: Some(serde_json::json!({"command": "ls"})),
+            ..make_message(MessageRole::ToolUse, "[Bash] ls")
+        };
+        app.set_messages(vec![make_message(MessageRole::ToolResult, "line 1\nline 2"), edit, bash]);
+
+        app.page_selected_message();
+        assert_eq!(app.pending_pager.take(), Some(("most".to_string(), "line 1\nline 2".to_string())));
+
+        app.selected_message = 1;
+        app.page_selected_message();
+        let (pager, text) = app.pending_pager.take().unwrap();
+        assert_eq!(pager, "delta");
+        assert!(text.contains("-old") && text.contains("+new"), "{text}");
+
+        app.selected_message = 2;
+        app.config.pager.clear();
+        app.page_selected_message();
+        let (pager, text) = app.pending_pager.take().unwrap();
+        assert_ne!(pager, "delta");
+        assert_eq!(text, "{\n  \"command\": \"ls\"\n}");
+    }
+
+    #[test]
+    fn edit_calls_expand_into_a_diff() {
+        use ratatui::backend::TestBackend;
+        use ratatui::style::Color;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.terminal_height = 20;
+        let edit = Message {
+            tool_name: Some("Edit".to_string()),
+            tool_input: Some(serde_json::json!({
+                "file_path": "/w/notes.txt",
+                "old_string": "keep\nold line",
+                "new_string": "keep\nnew line\nextra",
+            })),
+            ..make_message(MessageRole::ToolUse, "[Edit] /w/notes.txt")
+        };
+        app.set_messages(vec![make_message(MessageRole::User, "fix it"), edit]);
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().clone()
+        };
+        let text = |buffer: &ratatui::buffer::Buffer| buffer.content().iter().map(|c| c.symbol()).collect::<String>();
+        let collapsed = text(&screen(&app));
+        assert!(collapsed.contains("[Edit] /w/notes.txt"));
+        assert!(collapsed.contains("▸ diff +2 −1 lines"));
+        assert!(!collapsed.contains("-old line"));
+
+        app.selected_message = 1;
+        app.toggle_tool_result();
+        let buffer = screen(&app);
+        let expanded = text(&buffer);
+        assert!(expanded.contains("-old line") && expanded.contains("+new line") && expanded.contains(" keep"));
+        // 削除は赤、追加は緑
+        let cell_of = |needle: &str| {
+            let width = buffer.area.width as usize;
+            let at = expanded.find(needle).unwrap();
+            let index = expanded[..at].chars().count();
+            buffer.cell(((index % width) as u16, (index / width) as u16)).unwrap().fg
+        };
+        assert_eq!(cell_of("-old line"), Color::Red);
+        assert_eq!(cell_of("+new line"), Color::Green);
+    }
+
+    #[test]
+    fn write_calls_expand_into_their_content() {
+        use ratatui::backend::TestBackend;
+        use ratatui::style::Color;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.terminal_height = 20;
+        let write = Message {
+            tool_name: Some("Write".to_string()),
+            tool_input: Some(serde_json::json!({
+                "file_path": "/w/main.rs",
+                "content": "fn main() {\n    let x = 1;\n}\n",
+            })),
+            ..make_message(MessageRole::ToolUse, "[Write] /w/main.rs")
+        };
+        app.set_messages(vec![make_message(MessageRole::User, "write it"), write]);
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().clone()
+        };
+        let text = |buffer: &ratatui::buffer::Buffer| buffer.content().iter().map(|c| c.symbol()).collect::<String>();
+        let collapsed = text(&screen(&app));
+        assert!(collapsed.contains("[Write] /w/main.rs"));
+        assert!(collapsed.contains("▸ content 3 lines, 29 B"));
+        assert!(!collapsed.contains("let x = 1;"));
+
+        app.selected_message = 1;
+        app.toggle_tool_result();
+        let buffer = screen(&app);
+        let expanded = text(&buffer);
+        assert!(expanded.contains("fn main() {") && expanded.contains("let x = 1;"));
+        // 拡張子 .rs のハイライトが付く
+        let width = buffer.area.width as usize;
+        let index = expanded[..expanded.find("fn main").unwrap()].chars().count();
+        let fg = buffer.cell(((index % width) as u16, (index / width) as u16)).unwrap().fg;
+        assert!(matches!(fg, Color::Rgb(..)));
     }
 
     #[test]
-    fn half_page_down_session_list() {
+    fn session_actions_line_shows_only_for_sessions_with_tool_calls() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        let sessions: Vec<_> = (0..20).map(|i| make_session(&format!("s{}", i))).collect();
-        app.set_sessions(sessions);
-        app.terminal_height = 24;
-        assert_eq!(app.selected_session, 0);
-        app.half_page_down();
-        assert_eq!(app.selected_session, 12);
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        app.set_messages(vec![make_message(MessageRole::User, "hello")]);
+        assert!(!screen(&app).contains("Did:"));
+
+        app.session_actions = SessionActions { files_edited: 2, commands: 1, ..SessionActions::default() };
+        assert!(screen(&app).contains("Did: 2 edited · 1 command"));
+
+        // 別のセッションを開いたら前の集計は消える
+        app.set_messages(vec![make_message(MessageRole::User, "hello")]);
+        assert!(app.session_actions.is_empty());
     }
 
     #[test]
-    fn half_page_up_session_list() {
+    fn index_activity_lists_recent_changes() {
+        use crate::index::ChangeKind;
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        let sessions: Vec<_> = (0..20).map(|i| make_session(&format!("s{}", i))).collect();
-        app.set_sessions(sessions);
-        app.terminal_height = 24;
-        app.selected_session = 15;
-        app.half_page_up();
-        assert_eq!(app.selected_session, 3);
+        let now = Utc::now().timestamp();
+        let change = |kind, id: &str, ago: i64| IndexChange {
+            at: now - ago,
+            kind,
+            session_id: id.to_string(),
+            dir_name: "a".to_string(),
+        };
+        app.set_index_changes(vec![change(ChangeKind::Updated, "s-new", 120), change(ChangeKind::Expired, "s-gone", 7200)]);
+        assert_eq!(app.screen, Screen::IndexActivity);
+        app.navigate_down();
+        assert_eq!(app.index_changes_list.selected, 1);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 10)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Index Activity (2)"));
+        assert!(screen.contains("2m ago") && screen.contains("updated") && screen.contains("s-new"));
+        assert!(screen.contains("2h ago") && screen.contains("expired") && screen.contains("/path/a"));
+
+        // apply-retention の記録は下に別の表で出す
+        app.retention_journal = vec![JournalEntry {
+            at: now,
+            action: "archived".to_string(),
+            session_id: "s-old-session".to_string(),
+            dir_name: "a".to_string(),
+            detail: "/backup/a/s-old-session.jsonl".to_string(),
+        }];
+        let mut terminal = Terminal::new(TestBackend::new(100, 16)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Index Activity (2)") && screen.contains("Retention Journal (1)"));
+        assert!(screen.contains("archived") && screen.contains("/backup/a/s-old-session.jsonl"));
+
+        app.go_back();
+        assert_eq!(app.screen, Screen::ProjectList);
+        assert!(app.index_changes.is_empty() && app.retention_journal.is_empty());
     }
 
     #[test]
-    fn half_page_down_session_detail() {
+    fn cleanup_review_walks_candidates_one_at_a_time() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
-        app.terminal_height = 24;
-        assert_eq!(app.scroll_offset, 0);
-        app.half_page_down();
-        assert_eq!(app.scroll_offset, 12);
+        let session = |id: &str, count: usize| SessionInfo { message_count: count, ..make_session(id) };
+        app.set_sessions(vec![session("busy", 30)]);
+        app.open_cleanup_review();
+        assert_eq!(app.screen, Screen::SessionList);
+        assert_eq!(app.status_message.as_deref(), Some("No sessions in this list look deletable"));
+
+        app.set_sessions(vec![session("tiny-1", 1), session("busy", 30), session("tiny-2", 2)]);
+        app.open_cleanup_review();
+        assert_eq!(app.screen, Screen::CleanupReview);
+        assert_eq!(app.cleanup_review.total, 2);
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("tiny-1") && screen.contains("Candidate: only 1 messages"));
+        assert!(screen.contains("Review 1/2"));
+
+        // 保留したものは最後にもう一度聞かれる
+        app.decide_cleanup(Decision::Skip);
+        assert_eq!(app.cleanup_review.current().unwrap().session.session_id, "tiny-2");
+        app.decide_cleanup(Decision::Keep);
+        assert_eq!(app.cleanup_review.current().unwrap().session.session_id, "tiny-1");
+        app.go_back();
+        assert_eq!(app.screen, Screen::SessionList);
+        assert_eq!(app.status_message.as_deref(), Some("Cleanup review: 0 deleted, 0 archived, 1 kept, 1 left"));
+        assert_eq!(app.filtered_sessions.len(), 3);
     }
 
     #[test]
-    fn half_page_up_session_detail() {
+    fn project_activity_panel_lists_top_files_and_commands() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
-        app.terminal_height = 24;
-        app.scroll_offset = 20;
-        app.half_page_up();
-        assert_eq!(app.scroll_offset, 8); // 20 - 12 = 8
-    }
+        app.current_project_name = "a".to_string();
+        app.set_sessions(vec![make_session("s1")]);
+        app.time_filter = TimeFilter::Week;
+        app.project_activity = Some(ProjectActivity {
+            sessions: 3,
+            tool_calls: 42,
+            commands_run: 7,
+            files_changed: 2,
+            top_files: vec![("/path/a/src/app.rs".to_string(), 2)],
+            top_commands: vec![("cargo test".to_string(), 5)],
+            tools: vec![("Bash".to_string(), 7), ("Edit".to_string(), 4)],
+        });
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Activity (Week)"));
+        assert!(screen.contains("3 sessions · 42 tool calls · 7 commands · 2 files changed"));
+        // プロジェクト内のパスは相対で出す
+        assert!(screen.contains("2  src/app.rs"));
+        assert!(screen.contains("5  cargo test"));
+        assert!(screen.contains("Bash 7 · Edit 4"));
 
-    #[test]
-    fn half_page_down_clamps_project_list() {
-        let mut app = App::with_projects(vec![
-            make_project("a"),
-            make_project("b"),
-            make_project("c"),
-        ]);
-        app.terminal_height = 24; // half = 12, but only 3 items
-        app.half_page_down();
-        assert_eq!(app.selected_project, 2); // clamped to max index
+        app.close_project_activity();
+        assert!(app.project_activity.is_none());
     }
 
     #[test]
-    fn half_page_down_clamps_session_list() {
+    fn raw_json_shows_the_selected_messages_source_line() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
-        app.terminal_height = 24;
-        app.half_page_down();
-        assert_eq!(app.selected_session, 2); // clamped to max index
+        app.terminal_height = 8;
+        let answer = Message {
+            source_line: Some(r#"{"type":"assistant","uuid":"u-1","message":{"content":"done"}}"#.into()),
+            ..make_message(MessageRole::Assistant, "done")
+        };
+        app.set_messages(vec![make_message(MessageRole::User, "go"), answer]);
+        app.open_raw_json();
+        assert_eq!(app.raw_json, None);
+        assert_eq!(app.status_message.as_deref(), Some("No raw entry for the selected message"));
+
+        app.selected_message = 1;
+        app.open_raw_json();
+        let json = app.raw_json.clone().unwrap();
+        assert!(json.contains("\n  \"uuid\": \"u-1\""));
+        let mut terminal = Terminal::new(TestBackend::new(60, 8)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Raw JSON") && screen.contains("\"message\": {"));
+
+        app.scroll_raw_json(true, usize::MAX);
+        assert_eq!(app.raw_json_scroll, json.lines().count() - 4);
+        app.close_raw_json();
+        assert_eq!(app.raw_json, None);
     }
 
     #[test]
-    fn half_page_up_clamps_at_zero() {
-        let mut app = App::with_projects(vec![
-            make_project("a"),
-            make_project("b"),
+    fn raw_pane_follows_the_message_cursor() {
+        use ratatui::backend::TestBackend;
+
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.terminal_height = 16;
+        let line: std::sync::Arc<str> =
+            r#"{"type":"assistant","message":{"content":[{"type":"text"},{"type":"tool_use"}]}}"#.into();
+        app.set_messages(vec![
+            Message { source_line: Some(r#"{"type":"user","uuid":"u-1"}"#.into()), ..make_message(MessageRole::User, "go") },
+            Message { source_line: Some(line.clone()), ..make_message(MessageRole::Assistant, "on it") },
+            Message { source_line: Some(line), ..make_message(MessageRole::ToolUse, "Bash") },
         ]);
-        app.terminal_height = 24;
-        app.selected_project = 3; // even if beyond, saturating_sub handles it
-        app.half_page_up();
-        assert_eq!(app.selected_project, 0);
-    }
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(100, 16)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        assert!(!screen(&app).contains("Raw JSONL"));
 
-    // ===== go_to_top / go_to_bottom テスト =====
+        app.toggle_raw_pane();
+        let first = screen(&app);
+        assert!(first.contains("Raw JSONL: message 1 ") && first.contains("\"uuid\": \"u-1\""));
+        assert!(first.contains("on it"));
 
-    #[test]
-    fn go_to_top_project_list() {
-        let mut app = App::with_projects(vec![
-            make_project("a"),
-            make_project("b"),
-            make_project("c"),
-        ]);
-        app.selected_project = 2;
-        app.go_to_top();
-        assert_eq!(app.selected_project, 0);
+        app.selected_message = 2;
+        let third = screen(&app);
+        assert!(third.contains("Raw JSONL: message 3 (line shared by 2)"));
+        assert!(third.contains("\"type\": \"tool_use\"") && !third.contains("u-1"));
+
+        app.toggle_raw_pane();
+        assert!(!app.raw_pane);
     }
 
     #[test]
-    fn go_to_top_session_list() {
+    fn prompt_toc_jumps_to_the_chosen_prompt() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
-        app.selected_session = 2;
-        app.go_to_top();
-        assert_eq!(app.selected_session, 0);
+        app.terminal_height = 12;
+        let mut messages = Vec::new();
+        for n in 1..=3 {
+            messages.push(make_message(MessageRole::User, &format!("prompt {}\nwith details", n)));
+            messages.extend((0..10).map(|k| make_message(MessageRole::Assistant, &format!("answer {}.{}", n, k))));
+        }
+        app.set_messages(messages);
+        {
+            let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        }
+        app.selected_message = 15;
+        app.open_prompt_toc();
+        // 今いる位置のプロンプトを選んでおく
+        assert_eq!(app.prompt_toc.map(|l| l.selected), Some(1));
+        let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Prompts (3)") && screen.contains(" 3. ") && screen.contains("prompt 3…"));
+
+        app.prompt_toc_move(true, usize::MAX);
+        app.confirm_prompt_toc();
+        assert!(app.prompt_toc.is_none());
+        assert_eq!(app.selected_message, 22);
+        assert_eq!(app.scroll_offset, app.detail_cache.borrow().message_start(22).unwrap());
+
+        // プロンプトを隠していれば、その後の最初の回答へ
+        app.toggle_answers_only();
+        app.open_prompt_toc();
+        app.prompt_toc_move(false, usize::MAX);
+        app.confirm_prompt_toc();
+        assert_eq!(app.selected_message, 1);
+
+        app.set_messages(vec![make_message(MessageRole::Assistant, "hi")]);
+        app.open_prompt_toc();
+        assert!(app.prompt_toc.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("No prompts in this session"));
     }
 
     #[test]
-    fn go_to_top_session_detail() {
+    fn instruction_files_open_in_the_editor() {
+        use ratatui::backend::TestBackend;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let claude_md = tmp.path().join("CLAUDE.md");
+        std::fs::write(&claude_md, "Use tabs").unwrap();
+        let gone = "/nowhere/app/.claude/settings.json";
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
-        app.scroll_offset = 100;
-        app.go_to_top();
-        assert_eq!(app.scroll_offset, 0);
+        app.set_messages(vec![make_message(MessageRole::Assistant, "hi")]);
+        app.open_instruction_files();
+        assert!(app.instruction_list.is_none());
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("No CLAUDE.md or settings files referenced in this session")
+        );
+
+        app.set_messages(vec![make_message(
+            MessageRole::User,
+            &format!("<system-reminder>Contents of {} and {}</system-reminder>", claude_md.display(), gone),
+        )]);
+        app.open_instruction_files();
+        assert_eq!(app.instruction_files.len(), 2);
+        let mut terminal = Terminal::new(TestBackend::new(100, 12)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Instruction files") && screen.contains("(missing)"));
+
+        app.instruction_list_move(true);
+        app.edit_instruction_file();
+        assert_eq!(app.status_message.as_deref(), Some(&*format!("{} no longer exists", gone)));
+        assert!(app.pending_editor.is_none());
+        app.instruction_list_move(false);
+        app.edit_instruction_file();
+        assert!(app.instruction_list.is_none());
+        assert_eq!(
+            app.pending_editor,
+            Some(format!("${{VISUAL:-${{EDITOR:-vi}}}} '{}'", claude_md.display()))
+        );
     }
 
     #[test]
-    fn go_to_bottom_project_list() {
-        let mut app = App::with_projects(vec![
-            make_project("a"),
-            make_project("b"),
-            make_project("c"),
-        ]);
-        app.go_to_bottom();
-        assert_eq!(app.selected_project, 2);
+    fn instruction_snapshot_as_of_the_session_opens_in_the_pager() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.index_path = Some(tmp.path().join("index.db"));
+        app.config.pager = "less".to_string();
+        app.config.snapshot_instructions = true;
+        let index = SessionIndex::open(app.index_path.as_ref().unwrap()).unwrap();
+        let prompt = Message {
+            timestamp: "2026-01-15T10:00:00Z".parse().ok(),
+            ..make_message(MessageRole::User, "Contents of /w/CLAUDE.md and /w/.claude/settings.json")
+        };
+        let session_time = prompt.timestamp.unwrap().timestamp();
+        index.record_instruction_file("/w/CLAUDE.md", "Use tabs", session_time - 60).unwrap();
+        index.record_instruction_file("/w/CLAUDE.md", "Use spaces", session_time + 60).unwrap();
+
+        app.set_messages(vec![prompt]);
+        app.open_instruction_files();
+        app.page_instruction_snapshot();
+        assert_eq!(app.pending_pager, Some(("less".to_string(), "Use tabs".to_string())));
+        assert!(app.instruction_list.is_none());
+
+        app.open_instruction_files();
+        app.instruction_list_move(true);
+        app.page_instruction_snapshot();
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("No snapshot of /w/.claude/settings.json from before this session")
+        );
     }
 
     #[test]
-    fn go_to_bottom_session_list() {
+    fn favorites_are_saved_in_the_index_and_filter_the_list() {
+        use ratatui::backend::TestBackend;
+
+        let tmp = tempfile::TempDir::new().unwrap();
         let mut app = App::with_projects(vec![make_project("a")]);
+        app.index_path = Some(tmp.path().join("index.db"));
+        app.current_project_name = "-a".to_string();
         app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
-        app.go_to_bottom();
-        assert_eq!(app.selected_session, 2);
+        app.navigate_down();
+        app.toggle_favorite();
+        assert_eq!(app.status_message.as_deref(), Some("Added to favorites"));
+        let index = SessionIndex::open(app.index_path.as_ref().unwrap()).unwrap();
+        assert_eq!(index.favorites().unwrap(), HashSet::from(["s2".to_string()]));
+
+        app.toggle_favorites_filter();
+        let ids: Vec<&str> = app.filtered_sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s2"]);
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("[favorites]"));
+        assert!(screen.contains("★ Preview s2"));
+
+        // 外すとお気に入りだけの一覧からも消える
+        app.toggle_favorite();
+        assert!(app.filtered_sessions.is_empty());
+        assert!(index.favorites().unwrap().is_empty());
     }
 
     #[test]
-    fn go_to_bottom_session_detail() {
+    fn tags_are_typed_per_session_or_added_to_marked_ones_and_searched() {
+        use ratatui::backend::TestBackend;
+
+        assert_eq!(parse_tags("#Refactor, incident-2026-01  #refactor #"), vec!["incident-2026-01", "refactor"]);
+        let tmp = tempfile::TempDir::new().unwrap();
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
-        app.go_to_bottom();
-        assert!(app.scroll_offset > 0);
-    }
+        app.index_path = Some(tmp.path().join("index.db"));
+        app.current_project_name = "-a".to_string();
+        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
+        let type_tags = |app: &mut App, text: &str| {
+            app.open_tag_input();
+            for c in text.chars() {
+                app.tag_input_push(c);
+            }
+            app.confirm_tag_input();
+        };
 
-    #[test]
-    fn go_to_top_empty_project_list_no_panic() {
-        let mut app = App::with_projects(vec![]);
-        app.go_to_top(); // should not panic
-        assert_eq!(app.selected_project, 0);
-    }
+        type_tags(&mut app, "#incident-2026-01 #auth");
+        assert_eq!(app.status_message.as_deref(), Some("Tagged #auth #incident-2026-01"));
+        // 付いているタグを入れた状態で開き、書き換えられる
+        app.open_tag_input();
+        assert_eq!(app.tag_input.as_deref(), Some("#auth #incident-2026-01 "));
+        app.cancel_tag_input();
 
-    #[test]
-    fn go_to_bottom_empty_project_list_no_panic() {
-        let mut app = App::with_projects(vec![]);
-        app.go_to_bottom(); // should not panic
-        assert_eq!(app.selected_project, 0);
+        app.navigate_down();
+        app.toggle_session_mark();
+        app.toggle_session_mark();
+        type_tags(&mut app, "refactor");
+        assert_eq!(app.status_message.as_deref(), Some("Tagged 2 sessions #refactor"));
+        let index = SessionIndex::open(app.index_path.as_ref().unwrap()).unwrap();
+        assert_eq!(index.session_tags().unwrap(), app.session_tags);
+        assert_eq!(app.session_tags["s2"], vec!["refactor"]);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Tags"));
+        assert!(screen.contains("#auth #incid") && screen.contains("#refactor"));
+
+        app.search_query = "tag:incident".to_string();
+        app.apply_filter();
+        let ids: Vec<&str> = app.filtered_sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s1"]);
+        app.search_query = "tag:refactor s3".to_string();
+        app.apply_filter();
+        let ids: Vec<&str> = app.filtered_sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s3"]);
+
+        // 全体検索でも tag: で絞り込める（タグは索引から読み直す）
+        app.enter_global_search(vec![make_search_result("s1", vec!["fix"]), make_search_result("s2", vec!["fix"])]);
+        for c in "tag:#refactor".chars() {
+            app.global_search_push(c);
+        }
+        let ids: Vec<&str> = app.global_search_filtered.iter().map(|r| r.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s2"]);
     }
 
     #[test]
-    fn go_to_top_empty_session_list_no_panic() {
-        let mut app = App::with_projects(vec![]);
-        app.set_sessions(vec![]);
-        app.go_to_top();
-        assert_eq!(app.selected_session, 0);
+    fn bookmarks_are_saved_with_a_note_and_follow_their_message() {
+        use ratatui::backend::TestBackend;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.index_path = Some(tmp.path().join("index.db"));
+        app.current_project_name = "a".to_string();
+        app.current_session_id = "s1".to_string();
+        let with_uuid = |role, text: &str, uuid: &str| Message {
+            source_line: Some(format!(r#"{{"uuid":"{}"}}"#, uuid).into()),
+            ..make_message(role, text)
+        };
+        let messages = vec![
+            with_uuid(MessageRole::User, "fix the parser", "u1"),
+            with_uuid(MessageRole::Assistant, "\nFound it: the offset was off by one", "u2"),
+        ];
+        app.set_messages(messages.clone());
+        app.selected_message = 1;
+        app.toggle_bookmark();
+        assert_eq!(app.bookmark_note.as_deref(), Some("Found it: the offset was off by one"));
+        for _ in 0.." was off by one".len() {
+            app.bookmark_note_pop();
+        }
+        "!".chars().for_each(|c| app.bookmark_note_push(c));
+        app.confirm_bookmark_note();
+        assert_eq!(app.status_message.as_deref(), Some("Bookmarked"));
+        assert_eq!(app.bookmarked, HashSet::from([1]));
+
+        let draw = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(70, 14)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        assert!(draw(&app).contains('◆'));
+
+        // 前にメッセージが増えて位置がずれても uuid で付け直す
+        let mut shifted = vec![with_uuid(MessageRole::System, "resumed", "u0")];
+        shifted.extend(messages);
+        app.set_messages(shifted);
+        assert_eq!(app.bookmarked, HashSet::from([2]));
+
+        app.open_bookmarks();
+        let screen = draw(&app);
+        assert!(screen.contains("Bookmarks (1)") && screen.contains("Found it: the offset!"), "{screen}");
+        app.delete_selected_bookmark();
+        assert!(app.bookmark_list.is_none());
+        assert!(app.bookmarked.is_empty());
+        app.open_bookmarks();
+        assert_eq!(app.status_message.as_deref(), Some("No bookmarks yet (m marks a message in a session)"));
+
+        app.selected_message = 0;
+        app.toggle_bookmark();
+        app.confirm_bookmark_note();
+        app.toggle_bookmark();
+        assert_eq!(app.status_message.as_deref(), Some("Bookmark removed"));
+        assert!(app.bookmarked.is_empty());
     }
 
     #[test]
-    fn go_to_bottom_empty_session_list_no_panic() {
-        let mut app = App::with_projects(vec![]);
-        app.set_sessions(vec![]);
-        app.go_to_bottom();
-        assert_eq!(app.selected_session, 0);
-    }
+    fn visual_selection_copies_rendered_lines() {
+        use ratatui::backend::TestBackend;
 
-    // ===== go_back テスト =====
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.terminal_height = 12;
+        let mut messages = vec![make_message(MessageRole::User, "prompt\nwith details")];
+        messages.extend((0..10).map(|k| make_message(MessageRole::Assistant, &format!("answer {}", k))));
+        app.set_messages(messages);
+        let draw = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        draw(&app);
+
+        app.start_visual();
+        assert_eq!(app.visual, Some((0, 0)));
+        app.visual_move(true, 2);
+        assert!(draw(&app).contains("VISUAL 3 lines"));
+        let text = app.yank_visual().unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert!(text.contains("prompt") && text.ends_with("with details"), "{text}");
+        assert_eq!(app.status_message.as_deref(), Some("Copied 3 lines"));
+        assert!(app.visual.is_none());
+
+        // カーソルが画面の外に出るとスクロールが追う
+        app.start_visual();
+        app.visual_move(true, usize::MAX);
+        let total = app.detail_cache.borrow().total_rows();
+        assert_eq!(app.visual, Some((0, total - 1)));
+        assert_eq!(app.scroll_offset, total - app.detail_visible_rows());
+        assert!(draw(&app).contains("answer 9"));
+    }
 
     #[test]
-    fn go_back_from_project_list_sets_should_quit() {
+    fn code_blocks_copy_directly_or_through_the_picker() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        assert_eq!(app.screen, Screen::ProjectList);
-        app.go_back();
-        assert!(app.should_quit);
+        app.set_messages(vec![
+            make_message(MessageRole::User, "```sh\nls\n```"),
+            make_message(MessageRole::Assistant, "Run:\n```sh\ncargo test\n```"),
+            make_message(MessageRole::Assistant, "```rust\nfn a() {}\n```\nthen\n```\nplain\ntext\n```"),
+            make_message(MessageRole::Assistant, "no code"),
+        ]);
+        assert_eq!(app.open_code_blocks(), None);
+        assert_eq!(app.status_message.as_deref(), Some("The selected message is not an answer"));
+
+        // 1つだけならピッカーを出さずにそのまま返す
+        app.selected_message = 1;
+        assert_eq!(app.open_code_blocks().as_deref(), Some("cargo test"));
+        assert_eq!(app.code_picker, None);
+        assert_eq!(app.status_message.as_deref(), Some("Copied code block 1/1 (sh, 1 lines)"));
+
+        app.selected_message = 3;
+        assert_eq!(app.open_code_blocks(), None);
+        assert_eq!(app.status_message.as_deref(), Some("No code blocks in the selected answer"));
+
+        app.selected_message = 2;
+        assert_eq!(app.open_code_blocks(), None);
+        assert_eq!(app.code_picker, Some(0));
+        let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        assert!(screen.contains("Copy code block") && screen.contains(" 1 rust") && screen.contains(" 2 code"));
+        assert!(screen.contains("fn a() {}"));
+
+        app.code_picker_down();
+        app.code_picker_down();
+        assert_eq!(app.confirm_code_block(None).as_deref(), Some("plain\ntext"));
+        assert_eq!(app.code_picker, None);
+
+        app.open_code_blocks();
+        assert_eq!(app.confirm_code_block(Some(5)), None);
+        assert_eq!(app.confirm_code_block(Some(0)).as_deref(), Some("fn a() {}"));
+        assert!(app.code_blocks.is_empty());
     }
 
     #[test]
-    fn go_back_from_session_list_to_project_list() {
+    fn threaded_view_indents_edited_prompts() {
+        use ratatui::backend::TestBackend;
+
+        let entry = |role, text: &str, uuid: &str, parent: Option<&str>| Message {
+            uuid: Some(uuid.to_string()),
+            parent_uuid: parent.map(String::from),
+            ..make_message(role, text)
+        };
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1")]);
-        assert_eq!(app.screen, Screen::SessionList);
-        app.selected_session = 1; // some value
-        app.go_back();
-        assert_eq!(app.screen, Screen::ProjectList);
-        assert_eq!(app.selected_session, 0);
+        app.terminal_height = 20;
+        app.set_messages(vec![
+            entry(MessageRole::User, "first try", "u1", None),
+            entry(MessageRole::Assistant, "answer one", "a1", Some("u1")),
+            entry(MessageRole::User, "edited try", "u2", None),
+            entry(MessageRole::User, "retry", "u3", Some("a1")),
+            entry(MessageRole::User, "other retry", "u4", Some("a1")),
+        ]);
+        assert_eq!(app.fork_count(), 1);
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..20).map(|y| (0..60).map(|x| buffer[(x, y)].symbol()).collect::<String>()).collect::<Vec<_>>()
+        };
+        let flat = screen(&app);
+        assert!(flat.iter().any(|row| row.contains("⎇ 1 fork(s), b: threaded")));
+        assert!(!flat.iter().any(|row| row.contains("branch")));
+
+        app.toggle_threaded();
+        let threaded = screen(&app);
+        let row = |text: &str| threaded.iter().find(|row| row.contains(text)).unwrap().clone();
+        // 枠の内側に字下げの罫線が入る。親の無い「edited try」は根なので字下げしない
+        assert!(row("edited try").starts_with("│edited try"));
+        assert!(row("│ retry").starts_with("││ retry"));
+        assert!(row("other retry").starts_with("││ other retry"));
+        let branches: Vec<String> = threaded.iter().filter(|row| row.contains("⎇ branch")).cloned().collect();
+        assert_eq!(branches.len(), 2);
+        assert!(branches[0].starts_with("││ USER") && branches[0].contains("⎇ branch 1/2"));
+        assert!(branches[1].contains("⎇ branch 2/2"));
+        assert_eq!(app.title_filters(), vec!["threaded"]);
     }
 
     #[test]
-    fn go_back_from_session_detail_to_session_list() {
+    fn subagent_opens_from_its_task_call_and_esc_returns_to_it() {
+        let task = Message {
+            tool_name: Some("Task".to_string()),
+            tool_input: Some(serde_json::json!({"prompt": "list the callers", "description": "Find callers"})),
+            ..make_message(MessageRole::ToolUse, "")
+        };
+        let agent = |role, text: &str| Message { sidechain: true, ..make_message(role, text) };
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1")]);
-        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        app.screen = Screen::SessionDetail;
+        app.terminal_height = 20;
+        app.set_messages(vec![
+            make_message(MessageRole::User, "refactor it"),
+            task,
+            agent(MessageRole::User, "list the callers"),
+            agent(MessageRole::Assistant, "3 callers"),
+            make_message(MessageRole::Assistant, "done"),
+        ]);
+        // 本体の会話からはサブエージェントのメッセージを除く
+        assert_eq!(app.messages.len(), 3);
+        assert_eq!(app.subagents.len(), 1);
+        app.bookmarked.insert(2);
+
+        app.open_subagent();
+        assert!(app.main_conversation.is_none());
+        assert!(app.status_message.as_deref().unwrap().contains("1 in this session"));
+
+        app.selected_message = 1;
+        app.open_subagent();
+        assert_eq!(app.main_conversation.as_ref().map(|m| m.agent.as_str()), Some("Find callers"));
+        let texts: Vec<&str> = app.messages.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["list the callers", "3 callers"]);
+        assert_eq!(app.selected_message, 0);
+        assert!(app.bookmarked.is_empty());
+
+        app.go_back();
         assert_eq!(app.screen, Screen::SessionDetail);
+        assert!(app.main_conversation.is_none());
+        assert_eq!((app.messages.len(), app.selected_message), (3, 1));
+        assert_eq!(app.bookmarked, HashSet::from([2]));
         app.go_back();
         assert_eq!(app.screen, Screen::SessionList);
-        assert_eq!(app.scroll_offset, 0);
     }
 
-    // ===== フィルタテスト =====
-
     #[test]
-    fn cycle_filter_next_order() {
+    fn toggle_markdown_switches_between_rendered_and_raw_text() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1")]);
-        assert_eq!(app.time_filter, TimeFilter::All);
-        app.cycle_filter_next();
-        assert_eq!(app.time_filter, TimeFilter::Yesterday);
-        app.cycle_filter_next();
-        assert_eq!(app.time_filter, TimeFilter::Week);
-        app.cycle_filter_next();
-        assert_eq!(app.time_filter, TimeFilter::Month);
-        app.cycle_filter_next();
-        assert_eq!(app.time_filter, TimeFilter::All);
+        let mut messages: Vec<Message> = (0..30)
+            .map(|i| make_message(MessageRole::User, &format!("prompt {}", i)))
+            .collect();
+        messages.push(make_message(MessageRole::Assistant, "## Result\n\n- **done** with `cargo test`"));
+        messages.push(make_message(MessageRole::User, "**not markdown**"));
+        app.terminal_height = 20;
+        app.set_messages(messages);
+        app.go_to_bottom();
+
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(50, 20)).unwrap();
+            terminal.draw(|frame| ui::draw(frame, app)).unwrap();
+            terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        let rendered = screen(&app);
+        assert!(rendered.contains("• done with cargo test"));
+        assert!(!rendered.contains("## Result"));
+        assert!(rendered.contains("**not markdown**"));
+
+        app.scroll_offset = app.detail_cache.borrow().message_start(30).unwrap();
+        app.toggle_markdown();
+        assert!(!app.markdown);
+        assert_eq!(app.scroll_offset, app.detail_cache.borrow().message_start(30).unwrap());
+        let raw = screen(&app);
+        assert!(raw.contains("## Result"));
+        assert!(raw.contains("- **done** with `cargo test`"));
+
+        // 別のセッションを開いても設定は残る
+        app.set_messages(vec![make_message(MessageRole::Assistant, "*x*")]);
+        assert!(!app.markdown);
     }
 
     #[test]
-    fn cycle_filter_prev_order() {
+    fn clicking_the_minimap_scrolls_the_detail() {
+        use ratatui::backend::TestBackend;
+
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1")]);
-        assert_eq!(app.time_filter, TimeFilter::All);
-        app.cycle_filter_prev();
-        assert_eq!(app.time_filter, TimeFilter::Month);
-        app.cycle_filter_prev();
-        assert_eq!(app.time_filter, TimeFilter::Week);
-        app.cycle_filter_prev();
-        assert_eq!(app.time_filter, TimeFilter::Yesterday);
-        app.cycle_filter_prev();
-        assert_eq!(app.time_filter, TimeFilter::All);
+        let messages = (0..100)
+            .map(|i| make_message(MessageRole::User, &format!("message number {}", i)))
+            .collect();
+        app.set_messages(messages);
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 24)).unwrap();
+        terminal.draw(|frame| ui::draw(frame, &app)).unwrap();
+        let area = app.detail_cache.borrow().minimap_area;
+        assert_eq!(area.x, 39);
+
+        // 枠の外や本文のクリックは無視する
+        app.click_minimap(10, area.y + 3);
+        assert_eq!(app.scroll_offset, 0);
+
+        let bottom = area.y + area.height - 1;
+        app.click_minimap(area.x, bottom);
+        let near_end = app.scroll_offset;
+        assert!(near_end > 0);
+        // 最下行は末尾近くのメッセージに対応する
+        assert!(app.detail_cache.borrow().message_at_row(near_end) > Some(90));
+
+        app.click_minimap(area.x, area.y);
+        assert_eq!(app.scroll_offset, 0);
+
+        app.open_overview();
+        app.click_minimap(area.x, bottom);
+        assert_eq!(app.scroll_offset, 0);
     }
 
     #[test]
-    fn cycle_filter_resets_selected_session() {
+    fn detail_query_resets_when_another_session_opens() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
-        app.selected_session = 2;
-        app.cycle_filter_next();
-        assert_eq!(app.selected_session, 0);
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        app.set_detail_query(" auth ");
+        assert_eq!(app.detail_query, "auth");
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        assert!(app.detail_query.is_empty());
     }
 
     #[test]
-    fn cycle_filter_prev_resets_selected_session() {
+    fn jump_to_match_cycles_through_occurrences() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![make_session("s1"), make_session("s2"), make_session("s3")]);
-        app.selected_session = 2;
-        app.cycle_filter_prev();
-        assert_eq!(app.selected_session, 0);
-    }
+        app.set_messages(vec![
+            make_message(MessageRole::User, "Fix the JWT bug"),
+            make_message(MessageRole::Assistant, "no match here"),
+            make_message(MessageRole::Assistant, "jwt again\nand jwt twice"),
+        ]);
+        app.set_detail_query("jwt");
 
-    // ===== set_sessions / set_messages テスト =====
+        // 行: 0 USER, 1 本文, 2 空行, 3 ASSISTANT, 4 本文, 5 空行, 6 ASSISTANT, 7 本文, 8 本文
+        app.jump_to_match(true);
+        assert_eq!(app.detail_match, Some(0));
+        assert_eq!(app.scroll_offset, 0);
+        app.jump_to_match(true);
+        assert_eq!(app.scroll_offset, 5);
+        app.jump_to_match(true);
+        assert_eq!(app.detail_match, Some(2));
+        assert_eq!(app.scroll_offset, 6);
+        app.jump_to_match(true);
+        assert_eq!(app.detail_match, Some(0));
+        app.jump_to_match(false);
+        assert_eq!(app.detail_match, Some(2));
+
+        app.set_detail_query("nothing");
+        app.jump_to_match(true);
+        assert!(app.detail_match.is_none());
+        assert!(app.status_message.is_some());
+    }
 
     #[test]
-    fn set_sessions_updates_state() {
+    fn detail_search_plus_pins_terms_in_order() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        assert_eq!(app.screen, Screen::ProjectList);
-        let sessions = vec![make_session("s1"), make_session("s2")];
-        app.set_sessions(sessions);
-        assert_eq!(app.screen, Screen::SessionList);
-        assert_eq!(app.sessions.len(), 2);
-        assert_eq!(app.filtered_sessions.len(), 2);
-        assert_eq!(app.selected_session, 0);
-        assert_eq!(app.scroll_offset, 0);
+        app.set_messages(vec![make_message(MessageRole::User, "foo calls bar")]);
+        app.start_detail_search();
+        assert!(app.detail_search_active);
+        for c in "foo+bar".chars() {
+            app.detail_search_push(c);
+        }
+        assert_eq!(app.detail_terms, vec!["foo"]);
+        assert_eq!(app.highlight_queries(), vec!["foo", "bar"]);
+
+        // 空の入力で Backspace すると固定した語を編集に戻す
+        for _ in 0..3 {
+            app.detail_search_pop();
+        }
+        app.detail_search_pop();
+        assert!(app.detail_terms.is_empty());
+        assert_eq!(app.detail_query, "foo");
+
+        app.detail_search_push('+');
+        app.cancel_detail_search();
+        assert!(!app.detail_search_active);
+        assert_eq!(app.highlight_queries(), vec!["foo"]);
     }
 
     #[test]
-    fn set_sessions_applies_filter() {
+    fn detail_search_limits_pinned_terms() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        // Set filter to Yesterday; sessions with old timestamps should be filtered out
-        app.time_filter = TimeFilter::Yesterday;
-        let mut old_session = make_session("old");
-        old_session.timestamp = Some(chrono::Utc::now() - chrono::Duration::days(10));
-        let recent_session = make_session("recent");
-        app.set_sessions(vec![old_session, recent_session]);
-        assert_eq!(app.sessions.len(), 2);
-        assert_eq!(app.filtered_sessions.len(), 1);
-        assert_eq!(app.filtered_sessions[0].session_id, "recent");
+        app.set_messages(vec![make_message(MessageRole::User, "x")]);
+        app.start_detail_search();
+        for i in 0..MAX_HIGHLIGHT_TERMS + 2 {
+            app.detail_search_push(char::from(b'a' + i as u8));
+            app.detail_search_push('+');
+        }
+        assert_eq!(app.detail_terms.len(), MAX_HIGHLIGHT_TERMS - 1);
+        assert_eq!(app.highlight_queries().len(), MAX_HIGHLIGHT_TERMS);
+        assert!(app.status_message.is_some());
     }
 
     #[test]
-    fn set_messages_updates_state() {
+    fn jump_to_match_covers_all_terms() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        assert_eq!(app.screen, Screen::ProjectList);
-        app.scroll_offset = 10; // set some offset
-        let messages = vec![
-            make_message(MessageRole::User, "hello"),
-            make_message(MessageRole::Assistant, "world"),
-        ];
-        app.set_messages(messages);
-        assert_eq!(app.screen, Screen::SessionDetail);
-        assert_eq!(app.messages.len(), 2);
-        assert_eq!(app.scroll_offset, 0); // reset to 0
+        app.set_messages(vec![
+            make_message(MessageRole::User, "alpha"),
+            make_message(MessageRole::User, "beta"),
+        ]);
+        app.start_detail_search();
+        for c in "alpha+beta".chars() {
+            app.detail_search_push(c);
+        }
+        app.confirm_detail_search();
+        assert_eq!(app.detail_match, Some(0));
+        app.jump_to_match(true);
+        // 行: 0 USER, 1 alpha, 2 空行, 3 USER, 4 beta
+        assert_eq!(app.scroll_offset, 2);
+        assert_eq!(app.detail_cache.borrow().match_rows, vec![1, 4]);
     }
 
-    // ===== 空リスト安全性テスト =====
-
     #[test]
-    fn empty_projects_all_operations_safe() {
-        let mut app = App::with_projects(vec![]);
-        // navigate
+    fn index_done_refreshes_project_list_keeping_selection() {
+        let mut app = App::with_projects(vec![make_project("b"), make_project("c")]);
         app.navigate_down();
-        app.navigate_up();
-        // half page
-        app.half_page_down();
-        app.half_page_up();
-        // go_to
-        app.go_to_top();
-        app.go_to_bottom();
-        // go_back
-        app.go_back();
-        assert!(app.should_quit);
+        app.apply_index_event(IndexEvent::Done(Ok((IndexProgress::default(), vec![
+            make_project("a"),
+            make_project("b"),
+            make_project("c"),
+        ]))));
+        assert_eq!(app.displayed_projects.len(), 3);
+        assert_eq!(app.displayed_projects[app.project_list.selected].dir_name, "c");
+        // GlobalSearch 以外では完了を通知しない
+        assert!(app.status_message.is_none());
+
+        // 絞り込み中は表示を変えない
+        app.search_query = "c".to_string();
+        app.displayed_projects = vec![make_project("c")];
+        app.project_list.selected = 0;
+        app.apply_index_event(IndexEvent::Done(Ok((IndexProgress::default(), vec![make_project("c"), make_project("d")]))));
+        assert_eq!(app.projects.len(), 2);
+        assert_eq!(app.displayed_projects.len(), 1);
+    }
+
+    // ===== FileSnapshots テスト =====
+
+    fn make_snapshot(path: &str) -> FileSnapshot {
+        FileSnapshot {
+            message_id: "m1".to_string(),
+            timestamp: None,
+            path: path.to_string(),
+            backup_file: None,
+            version: 1,
+            backup_time: None,
+        }
     }
 
     #[test]
-    fn empty_sessions_all_operations_safe() {
+    fn file_snapshots_navigation_and_back() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_sessions(vec![]);
-        // navigate
-        app.navigate_down();
-        app.navigate_up();
-        // half page
-        app.half_page_down();
-        app.half_page_up();
-        // go_to
-        app.go_to_top();
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        app.set_snapshots(vec![make_snapshot("a.rs"), make_snapshot("b.rs")]);
+        assert_eq!(app.screen, Screen::FileSnapshots);
         app.go_to_bottom();
-        // filter
-        app.cycle_filter_next();
-        app.cycle_filter_prev();
-        // go_back
+        assert_eq!(app.snapshot_list.selected, 1);
+
+        app.terminal_height = 10;
+        app.set_snapshot_content("line\n".repeat(20));
+        assert_eq!(app.screen, Screen::SnapshotView);
+        app.go_to_bottom();
+        assert_eq!(app.snapshot_content_scroll, 15);
         app.go_back();
-        assert_eq!(app.screen, Screen::ProjectList);
+        assert_eq!(app.screen, Screen::FileSnapshots);
+        assert_eq!(app.snapshot_list.selected, 1);
+        app.go_back();
+        assert_eq!(app.screen, Screen::SessionDetail);
     }
 
     #[test]
-    fn empty_messages_all_operations_safe() {
+    fn snapshot_export_prompt_prefills_and_cancels() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_messages(vec![]);
-        // navigate
+        let mut backed_up = make_snapshot("src/main.rs");
+        backed_up.backup_file = Some("abc@v2".to_string());
+        backed_up.version = 2;
+        app.set_snapshots(vec![make_snapshot("new.rs"), backed_up]);
+
+        // バックアップのないファイルは書き出し不可
+        app.start_snapshot_export();
+        assert!(app.export_prompt.is_none());
+        assert!(app.status_message.is_some());
+
         app.navigate_down();
-        app.navigate_up();
-        // half page
-        app.half_page_down();
-        app.half_page_up();
-        // go_to
-        app.go_to_top();
-        app.go_to_bottom();
-        // go_back
-        app.go_back();
-        assert_eq!(app.screen, Screen::SessionList);
+        app.start_snapshot_export();
+        assert_eq!(app.export_prompt.as_deref(), Some("src/main.rs.snapshot-v2"));
+        app.export_prompt_pop();
+        app.export_prompt_push('3');
+        assert_eq!(app.export_prompt.as_deref(), Some("src/main.rs.snapshot-v3"));
+        app.cancel_snapshot_export();
+        assert!(app.export_prompt.is_none());
+        assert_eq!(app.screen, Screen::FileSnapshots);
     }
 
-    // ===== 検索テスト =====
+    // ===== 外部コマンド テスト =====
 
-    #[test]
-    fn start_search_activates() {
+    fn with_commands(names: &[&str]) -> App {
         let mut app = App::with_projects(vec![make_project("a")]);
-        assert!(!app.search_active);
-        app.start_search();
-        assert!(app.search_active);
-        assert!(app.search_query.is_empty());
+        app.config.commands = names
+            .iter()
+            .map(|n| crate::commands::ExternalCommand {
+                name: n.to_string(),
+                command: format!("{} {{session_id}} {{project_path}}", n),
+            })
+            .collect();
+        app.current_project_name = "a".to_string();
+        app
     }
 
     #[test]
-    fn cancel_search_restores_all() {
-        let mut app = App::with_projects(vec![
-            make_project("alpha"),
-            make_project("beta"),
-            make_project("gamma"),
-        ]);
-        app.start_search();
-        app.search_push('z'); // フィルタで全て消える可能性あり
-        app.cancel_search();
-        assert!(!app.search_active);
-        assert!(app.search_query.is_empty());
-        assert_eq!(app.displayed_projects.len(), 3);
-    }
+    fn command_menu_requires_configured_commands() {
+        let mut app = with_commands(&[]);
+        app.set_sessions(vec![make_session("s1")]);
+        app.open_command_menu();
+        assert!(app.command_menu.is_none());
+        assert!(app.status_message.is_some());
 
-    #[test]
-    fn confirm_search_keeps_filter() {
-        let mut app = App::with_projects(vec![
-            make_project("alpha"),
-            make_project("beta"),
-            make_project("gamma"),
-        ]);
-        app.start_search();
-        app.search_push('a'); // "alpha" と "gamma" にマッチ
-        let filtered_count = app.displayed_projects.len();
-        app.confirm_search();
-        assert!(!app.search_active);
-        assert_eq!(app.displayed_projects.len(), filtered_count);
+        // ProjectList ではセッションが決まらないので開かない
+        let mut app = with_commands(&["sum"]);
+        app.open_command_menu();
+        assert!(app.command_menu.is_none());
     }
 
     #[test]
-    fn search_push_filters_projects() {
-        let mut app = App::with_projects(vec![
-            make_project("alpha"),
-            make_project("beta"),
-            make_project("gamma"),
-        ]);
-        app.start_search();
-        app.search_push('b');
-        app.search_push('e');
-        app.search_push('t');
-        app.search_push('a');
-        // "beta" にマッチするはず
-        assert!(app.displayed_projects.len() <= 3);
-        let has_beta = app
-            .displayed_projects
-            .iter()
-            .any(|p| p.dir_name == "beta");
-        assert!(has_beta);
+    fn command_menu_expands_selected_session() {
+        let mut app = with_commands(&["sum", "notes"]);
+        app.set_sessions(vec![make_session("s1"), make_session("s2")]);
+        app.navigate_down();
+        app.open_command_menu();
+        assert_eq!(app.command_menu, Some(0));
+        app.command_menu_down();
+        app.command_menu_down();
+        assert_eq!(app.command_menu, Some(1));
+        app.confirm_command();
+        assert!(app.command_menu.is_none());
+        assert_eq!(
+            app.pending_command,
+            Some(("notes".to_string(), "notes 's2' '/path/a'".to_string()))
+        );
     }
 
     #[test]
-    fn search_pop_expands_results() {
-        let mut app = App::with_projects(vec![
-            make_project("alpha"),
-            make_project("beta"),
-            make_project("gamma"),
-        ]);
-        app.start_search();
-        app.search_push('b');
-        app.search_push('e');
-        app.search_push('t');
-        app.search_push('a');
-        let narrow_count = app.displayed_projects.len();
-        app.search_pop(); // "bet" に緩和
-        let wider_count = app.displayed_projects.len();
-        assert!(wider_count >= narrow_count);
+    fn reask_runs_claude_with_the_prompt_in_the_project_dir() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.enter_global_search(vec![make_search_result("s1", vec!["fix Bob's bug"])]);
+        app.reask_prompt();
+        assert_eq!(
+            app.pending_command,
+            Some(("claude".to_string(), r"cd '/path/s1' && claude -- 'fix Bob'\''s bug'".to_string()))
+        );
+
+        app.pending_command = None;
+        app.current_project_name = "a".to_string();
+        app.set_messages(vec![make_message(MessageRole::User, "add tests"), make_message(MessageRole::Assistant, "ok")]);
+        app.reask_prompt();
+        assert_eq!(
+            app.pending_command.take().map(|(_, cmd)| cmd).as_deref(),
+            Some("cd '/path/a' && claude -- 'add tests'")
+        );
+        app.selected_message = 1;
+        app.reask_prompt();
+        assert!(app.pending_command.is_none());
+        assert_eq!(app.status_message.as_deref(), Some("The selection is not one of your prompts"));
+
+        // ダッシュで始まるプロンプトもオプションと取られない
+        app.set_messages(vec![make_message(MessageRole::User, "--help me")]);
+        app.reask_prompt();
+        assert_eq!(
+            app.pending_command.take().map(|(_, cmd)| cmd).as_deref(),
+            Some("cd '/path/a' && claude -- '--help me'")
+        );
     }
 
     #[test]
-    fn search_on_session_detail_does_nothing() {
+    fn truncated_prompts_without_a_transcript_are_refused() {
         let mut app = App::with_projects(vec![make_project("a")]);
-        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
-        assert_eq!(app.screen, Screen::SessionDetail);
-        app.start_search();
-        assert!(!app.search_active);
+        app.config.max_prompt_chars = 8;
+        app.enter_global_search(vec![make_search_result("no-such-session", vec!["cut to 8", "short"])]);
+        app.reask_prompt();
+        assert!(app.pending_command.is_none());
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("The index keeps only the first 8 characters of this prompt and its transcript is gone")
+        );
+        assert_eq!(app.full_matched_prompt(), None);
+
+        // Y も切れたものはコピーしない
+        let tmp = tempfile::TempDir::new().unwrap();
+        let clip = tmp.path().join("clip.txt");
+        app.clipboard = Box::new(clipboard::FileClipboard { path: clip.clone() });
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('Y'), KeyModifiers::NONE));
+        assert!(!clip.exists());
+        assert!(app.status_message.as_deref().unwrap().starts_with("The index keeps only the first 8 characters"));
+
+        // 上限より短ければ全文のはず
+        app.global_search_filtered[0].prompts = vec!["short".to_string()];
+        assert_eq!(app.full_matched_prompt().as_deref(), Some("short"));
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('Y'), KeyModifiers::NONE));
+        assert_eq!(std::fs::read_to_string(&clip).unwrap(), "short");
     }
 
     #[test]
-    fn search_resets_selected_project() {
-        let mut app = App::with_projects(vec![
-            make_project("alpha"),
-            make_project("beta"),
-            make_project("gamma"),
-        ]);
-        app.selected_project = 2;
-        app.start_search();
-        app.search_push('a');
-        assert_eq!(app.selected_project, 0);
+    fn full_prompt_text_finds_the_untruncated_prompt() {
+        let mut sub = make_message(MessageRole::User, "cut to eight and more from a subagent");
+        sub.sidechain = true;
+        let messages = vec![
+            sub,
+            make_message(MessageRole::User, "cut to eight characters, then the rest"),
+            make_message(MessageRole::Assistant, "cut to eight"),
+            make_message(MessageRole::User, "cut to e"),
+        ];
+        assert_eq!(full_prompt_text(&messages, "cut to e").as_deref(), Some("cut to e"));
+        assert_eq!(full_prompt_text(&messages, "cut to eight").as_deref(), Some("cut to eight characters, then the rest"));
+        assert_eq!(full_prompt_text(&messages, "missing"), None);
     }
 
     #[test]
-    fn navigate_with_search_uses_displayed_projects() {
-        let mut app = App::with_projects(vec![
-            make_project("alpha"),
-            make_project("beta"),
-            make_project("gamma"),
-        ]);
-        app.start_search();
-        app.search_push('a'); // "alpha" と "gamma" にマッチ (original_path: /path/alpha, /path/gamma)
-        let count = app.displayed_projects.len();
-        // 最下端までナビゲート
-        for _ in 0..count + 5 {
-            app.navigate_down();
-        }
-        // displayed_projects のサイズを超えないこと
-        assert!(app.selected_project < count);
+    fn command_menu_in_detail_uses_current_session() {
+        let mut app = with_commands(&["sum"]);
+        app.current_session_id = "open-one".to_string();
+        app.set_messages(vec![make_message(MessageRole::User, "hi")]);
+        let ctx = app.command_context().unwrap();
+        assert_eq!(ctx.session_id, "open-one");
+        assert_eq!(ctx.project_dir, "a");
+        assert!(ctx.session_path.ends_with("a/open-one.jsonl"));
+
+        app.open_command_menu();
+        app.close_command_menu();
+        assert!(app.command_menu.is_none());
+        assert!(app.pending_command.is_none());
     }
 
     // ===== GlobalSearch テスト =====
@@ -1399,11 +7499,63 @@ mod tests {
             project_path: format!("/path/{}", id),
             dir_name: format!("dir-{}", id),
             git_branch: "main".to_string(),
+            summary: String::new(),
             created_at: "2026-01-15T10:00:00Z".to_string(),
+            last_activity: 1_768_471_200,
             prompts: prompts.into_iter().map(String::from).collect(),
             best_match_prompt: String::new(),
             best_match_indices: Vec::new(),
+            best_match_is_summary: false,
+            other_matches: Vec::new(),
+            parent_session_id: String::new(),
+            expired: false,
+            source_root: String::new(),
+        }
+    }
+
+    #[test]
+    fn global_search_root_facet_filters_by_source_root() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let in_root = |id: &str, root: &str| SearchResult {
+            source_root: root.to_string(),
+            ..make_search_result(id, vec!["fix the build"])
+        };
+        app.enter_global_search(vec![in_root("s1", "claude"), in_root("s2", "claude-work"), in_root("s3", "claude-work")]);
+        for c in "root:work".chars() {
+            app.global_search_push(c);
+        }
+        let ids: Vec<&str> = app.global_search_filtered.iter().map(|r| r.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s2", "s3"]);
+
+        // 検索語と組み合わせられ、ルート名は検索語として扱わない
+        for c in " build".chars() {
+            app.global_search_push(c);
         }
+        assert_eq!(app.global_search_filtered.len(), 2);
+        assert!(app.global_search_filtered[0].best_match_indices.len() == "build".len());
+    }
+
+    #[test]
+    fn global_search_folds_resumed_sessions_into_newest() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let mut resumed = make_search_result("s2", vec!["fix login", "and tests"]);
+        resumed.parent_session_id = "s1".to_string();
+        app.enter_global_search(vec![
+            resumed,
+            make_search_result("s1", vec!["fix login"]),
+            make_search_result("s3", vec!["other"]),
+        ]);
+        let ids: Vec<&str> = app.global_search_filtered.iter().map(|r| r.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s2", "s3"]);
+        assert_eq!(app.global_search_resumes.get("s2"), Some(&1));
+
+        // 再開元だけがマッチした場合はそれを表示する
+        app.global_search_push('o');
+        app.global_search_push('t');
+        app.global_search_push('h');
+        let ids: Vec<&str> = app.global_search_filtered.iter().map(|r| r.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["s3"]);
+        assert!(app.global_search_resumes.is_empty());
     }
 
     #[test]
@@ -1436,6 +7588,102 @@ mod tests {
         assert!(app.global_search_filtered.iter().any(|r| r.session_id == "s1"));
     }
 
+    #[test]
+    fn global_search_requires_all_tokens() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let searchable = vec![
+            make_search_result("s1", vec!["add JWT auth", "now the refresh token"]),
+            make_search_result("s2", vec!["JWT only"]),
+        ];
+        app.enter_global_search(searchable);
+        for c in "jwt refresh".chars() {
+            app.global_search_push(c);
+        }
+        assert_eq!(app.global_search_filtered.len(), 1);
+        assert_eq!(app.global_search_filtered[0].session_id, "s1");
+    }
+
+    #[test]
+    fn global_search_highlights_each_token() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let searchable = vec![
+            make_search_result("s1", vec!["JWT only", "jwt refresh token"]),
+        ];
+        app.enter_global_search(searchable);
+        for c in "token jwt".chars() {
+            app.global_search_push(c);
+        }
+        let result = &app.global_search_filtered[0];
+        assert_eq!(result.best_match_prompt, "jwt refresh token");
+        assert_eq!(
+            result.best_match_indices,
+            vec![0, 1, 2, 12, 13, 14, 15, 16]
+        );
+    }
+
+    #[test]
+    fn global_search_matches_summary() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let mut with_summary = make_search_result("s1", vec!["fix it"]);
+        with_summary.summary = "OAuth callback regression".to_string();
+        app.enter_global_search(vec![with_summary, make_search_result("s2", vec!["fix it"])]);
+        for c in "oauth".chars() {
+            app.global_search_push(c);
+        }
+        assert_eq!(app.global_search_filtered.len(), 1);
+        let result = &app.global_search_filtered[0];
+        assert!(result.best_match_is_summary);
+        assert_eq!(result.best_match_prompt, "OAuth callback regression");
+        assert_eq!(result.best_match_indices, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn global_search_prefers_prompt_over_summary_on_tie() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let mut r = make_search_result("s1", vec!["oauth fix"]);
+        r.summary = "OAuth work".to_string();
+        app.enter_global_search(vec![r]);
+        for c in "oauth".chars() {
+            app.global_search_push(c);
+        }
+        assert!(!app.global_search_filtered[0].best_match_is_summary);
+        assert_eq!(app.global_search_filtered[0].best_match_prompt, "oauth fix");
+    }
+
+    #[test]
+    fn global_search_counts_other_matches() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let searchable = vec![make_search_result(
+            "s1",
+            vec!["fix auth", "unrelated", "auth tests", "auth docs and fix"],
+        )];
+        app.enter_global_search(searchable);
+        for c in "auth fix".chars() {
+            app.global_search_push(c);
+        }
+        let result = &app.global_search_filtered[0];
+        assert_eq!(result.best_match_prompt, "fix auth");
+        let others: Vec<&str> = result.other_matches.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(others, vec!["auth tests", "auth docs and fix"]);
+        assert_eq!(result.other_matches[0].1, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn toggle_global_search_expand_follows_selection() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        app.enter_global_search(vec![
+            make_search_result("s1", vec!["a"]),
+            make_search_result("s2", vec!["b"]),
+        ]);
+        app.toggle_global_search_expand();
+        assert_eq!(app.global_search_expanded.as_deref(), Some("s1"));
+        app.toggle_global_search_expand();
+        assert_eq!(app.global_search_expanded, None);
+        app.toggle_global_search_expand();
+        app.global_search_push('b');
+        assert_eq!(app.global_search_expanded, None);
+    }
+
     #[test]
     fn global_search_navigate() {
         let mut app = App::with_projects(vec![make_project("a")]);
@@ -1444,11 +7692,11 @@ mod tests {
             make_search_result("s2", vec!["b"]),
         ];
         app.enter_global_search(searchable);
-        assert_eq!(app.global_search_selected, 0);
+        assert_eq!(app.global_search_list.selected, 0);
         app.navigate_down();
-        assert_eq!(app.global_search_selected, 1);
+        assert_eq!(app.global_search_list.selected, 1);
         app.navigate_up();
-        assert_eq!(app.global_search_selected, 0);
+        assert_eq!(app.global_search_list.selected, 0);
     }
 
     #[test]
@@ -1462,6 +7710,28 @@ mod tests {
         assert_eq!(cmd, Some("claude --resume abc-123-def".to_string()));
     }
 
+    #[test]
+    fn global_search_copies_the_matched_prompt() {
+        let mut app = App::with_projects(vec![make_project("a")]);
+        let mut summarized = make_search_result("s2", vec!["first ask", "deploy the api\nto staging"]);
+        summarized.summary = "Deploy work".to_string();
+        app.enter_global_search(vec![make_search_result("s1", vec!["fix the login bug", "add tests"]), summarized]);
+        // 検索語が無ければ最初のプロンプト
+        assert_eq!(app.get_matched_prompt().as_deref(), Some("fix the login bug"));
+
+        for c in "tests".chars() {
+            app.global_search_push(c);
+        }
+        assert_eq!(app.get_matched_prompt().as_deref(), Some("add tests"));
+
+        app.global_search_query.clear();
+        for c in "deploy".chars() {
+            app.global_search_push(c);
+        }
+        assert_eq!(app.global_search_filtered[0].session_id, "s2");
+        assert_eq!(app.get_matched_prompt().as_deref(), Some("deploy the api\nto staging"));
+    }
+
     #[test]
     fn search_filters_sessions_by_preview() {
         let mut app = App::with_projects(vec![make_project("a")]);
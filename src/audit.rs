@@ -0,0 +1,233 @@
+use crate::models::{PermissionAuditEntry, PermissionDecision};
+use crate::parser;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Prefix Claude Code writes into a tool_result when the user rejects a tool call.
+const REJECTION_PREFIX: &str = "The user doesn't want to proceed with this tool use";
+
+/// Tools that go through Claude Code's permission prompt. Read-only tools
+/// (Read, Glob, Grep, ...) never ask, so they are left out of the audit.
+fn requires_permission(tool_name: &str) -> bool {
+    matches!(
+        tool_name,
+        "Bash" | "Edit" | "MultiEdit" | "Write" | "NotebookEdit" | "WebFetch" | "WebSearch"
+    ) || tool_name.starts_with("mcp__")
+}
+
+fn decide(tool_name: &str, mode: &str, result: Option<&Value>) -> PermissionDecision {
+    let Some(result) = result else {
+        return PermissionDecision::Pending;
+    };
+    let text = match result.get("content") {
+        Some(Value::String(s)) => s.clone(),
+        Some(c @ Value::Array(_)) => parser::extract_text_from_content(c),
+        _ => String::new(),
+    };
+    if text.starts_with(REJECTION_PREFIX) {
+        return PermissionDecision::Denied;
+    }
+    if mode == "bypassPermissions" || (mode == "acceptEdits" && parser::is_file_modifying_tool(tool_name)) {
+        PermissionDecision::AutoApproved
+    } else {
+        PermissionDecision::Approved
+    }
+}
+
+/// Build the permission audit for one session's JSONL content.
+///
+/// Each permission-gated tool_use is paired with its tool_result by id. The
+/// decision comes from the rejection marker in the result and the
+/// `permissionMode` in effect when the call was made.
+pub fn audit_session_content(session_id: &str, content: &str) -> Vec<PermissionAuditEntry> {
+    let mut mode = String::from("default");
+    let mut calls: Vec<(String, PermissionAuditEntry)> = Vec::new();
+    let mut results: HashMap<String, Value> = HashMap::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let obj: Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if let Some(m) = obj.get("permissionMode").and_then(Value::as_str) {
+            mode = m.to_string();
+        }
+        let blocks = match obj.get("message").and_then(|m| m.get("content")) {
+            Some(Value::Array(arr)) => arr,
+            _ => continue,
+        };
+        let timestamp = parser::parse_timestamp(obj.get("timestamp").and_then(Value::as_str));
+        for block in blocks {
+            match block.get("type").and_then(Value::as_str) {
+                Some("tool_use") => {
+                    let tool_name = block.get("name").and_then(Value::as_str).unwrap_or("");
+                    if !requires_permission(tool_name) {
+                        continue;
+                    }
+                    let id = block.get("id").and_then(Value::as_str).unwrap_or("");
+                    let input = block.get("input").cloned().unwrap_or(Value::Null);
+                    calls.push((
+                        id.to_string(),
+                        PermissionAuditEntry {
+                            session_id: session_id.to_string(),
+                            timestamp,
+                            tool_name: tool_name.to_string(),
+                            summary: parser::summarize_tool_use(tool_name, &input),
+                            permission_mode: mode.clone(),
+                            decision: PermissionDecision::Pending,
+                        },
+                    ));
+                }
+                Some("tool_result") => {
+                    if let Some(id) = block.get("tool_use_id").and_then(Value::as_str) {
+                        results.insert(id.to_string(), block.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    calls
+        .into_iter()
+        .map(|(id, mut entry)| {
+            entry.decision = decide(&entry.tool_name, &entry.permission_mode, results.get(&id));
+            entry
+        })
+        .collect()
+}
+
+pub fn audit_session_in(
+    project_name: &str,
+    session_id: &str,
+    projects_dir: &Path,
+) -> Result<Vec<PermissionAuditEntry>> {
+    let jsonl_path = projects_dir
+        .join(project_name)
+        .join(format!("{}.jsonl", session_id));
+    if !jsonl_path.exists() {
+        return Ok(Vec::new());
+    }
+    let (content, _) = parser::read_transcript(&jsonl_path)?;
+    Ok(audit_session_content(session_id, &content))
+}
+
+/// Audit every session in a project, oldest call first.
+pub fn audit_project_in(project_name: &str, projects_dir: &Path) -> Result<Vec<PermissionAuditEntry>> {
+    let project_dir = projects_dir.join(project_name);
+    if !project_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&project_dir)?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().map(|e| e == "jsonl").unwrap_or(false) {
+            let session_id = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if let Ok((content, _)) = parser::read_transcript(&path) {
+                entries.extend(audit_session_content(&session_id, &content));
+            }
+        }
+    }
+    entries.sort_by_key(|e| e.timestamp);
+    Ok(entries)
+}
+
+pub fn audit_session(project_name: &str, session_id: &str) -> Result<Vec<PermissionAuditEntry>> {
+    match parser::session_root(project_name, session_id) {
+        Some(root) => audit_session_in(project_name, session_id, &root.projects_dir),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub fn audit_project(project_name: &str) -> Result<Vec<PermissionAuditEntry>> {
+    let mut entries = Vec::new();
+    for root in parser::claude_roots() {
+        entries.extend(audit_project_in(project_name, &root.projects_dir)?);
+    }
+    entries.sort_by_key(|e| e.timestamp);
+    Ok(entries)
+}
+
+/// Render the audit as tab-separated values with a header row.
+pub fn format_report_tsv(entries: &[PermissionAuditEntry]) -> String {
+    let mut out = String::from("session_id\ttimestamp\ttool\tmode\tdecision\tsummary\n");
+    for e in entries {
+        out.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            e.session_id,
+            e.timestamp_str(),
+            e.tool_name,
+            e.permission_mode,
+            e.decision.label(),
+            e.summary.replace(['\t', '\n'], " "),
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const SESSION: &str = r#"{"type":"user","permissionMode":"default","timestamp":"2026-01-15T10:00:00Z","message":{"content":"clean up"}}
+{"type":"assistant","timestamp":"2026-01-15T10:00:01Z","message":{"content":[{"type":"tool_use","id":"t1","name":"Read","input":{"file_path":"/a"}},{"type":"tool_use","id":"t2","name":"Bash","input":{"command":"rm -rf tmp"}}]}}
+{"type":"user","timestamp":"2026-01-15T10:00:02Z","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"},{"type":"tool_result","tool_use_id":"t2","is_error":true,"content":"The user doesn't want to proceed with this tool use. The tool use was rejected."}]}}
+{"type":"user","permissionMode":"acceptEdits","timestamp":"2026-01-15T10:01:00Z","message":{"content":"just edit it"}}
+{"type":"assistant","timestamp":"2026-01-15T10:01:01Z","message":{"content":[{"type":"tool_use","id":"t3","name":"Edit","input":{"file_path":"/b"}},{"type":"tool_use","id":"t4","name":"Bash","input":{"command":"cargo test"}}]}}
+{"type":"user","timestamp":"2026-01-15T10:01:02Z","message":{"content":[{"type":"tool_result","tool_use_id":"t3","content":"edited"},{"type":"tool_result","tool_use_id":"t4","content":"ok"}]}}
+{"type":"assistant","timestamp":"2026-01-15T10:02:00Z","message":{"content":[{"type":"tool_use","id":"t5","name":"Write","input":{"file_path":"/c"}}]}}"#;
+
+    #[test]
+    fn audit_session_content_decisions() {
+        let entries = audit_session_content("sess-1", SESSION);
+        let decisions: Vec<_> = entries
+            .iter()
+            .map(|e| (e.tool_name.as_str(), e.decision))
+            .collect();
+        assert_eq!(
+            decisions,
+            vec![
+                ("Bash", PermissionDecision::Denied),
+                ("Edit", PermissionDecision::AutoApproved),
+                ("Bash", PermissionDecision::Approved),
+                ("Write", PermissionDecision::Pending),
+            ]
+        );
+        assert_eq!(entries[1].permission_mode, "acceptEdits");
+    }
+
+    #[test]
+    fn audit_project_in_collects_all_sessions() {
+        let tmp = TempDir::new().unwrap();
+        let project_dir = tmp.path().join("my-project");
+        fs::create_dir(&project_dir).unwrap();
+        fs::write(project_dir.join("sess-1.jsonl"), SESSION).unwrap();
+        fs::write(project_dir.join("sess-2.jsonl"), "").unwrap();
+
+        let entries = audit_project_in("my-project", tmp.path()).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|e| e.session_id == "sess-1"));
+    }
+
+    #[test]
+    fn format_report_tsv_has_header_and_rows() {
+        let entries = audit_session_content("sess-1", SESSION);
+        let tsv = format_report_tsv(&entries);
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("session_id\t"));
+        assert!(lines[1].contains("\tBash\tdefault\tDenied\t"));
+    }
+}
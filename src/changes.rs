@@ -0,0 +1,397 @@
+use crate::models::FileChange;
+use crate::parser;
+use anyhow::Result;
+use serde_json::Value;
+use similar::TextDiff;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// A single file mutation taken from a Write/Edit/MultiEdit tool input.
+#[derive(Debug, Clone)]
+enum FileOp {
+    Write(String),
+    Edit {
+        old: String,
+        new: String,
+        replace_all: bool,
+    },
+}
+
+fn edit_from(input: &Value) -> FileOp {
+    let get = |key: &str| input.get(key).and_then(Value::as_str).unwrap_or("").to_string();
+    FileOp::Edit {
+        old: get("old_string"),
+        new: get("new_string"),
+        replace_all: input
+            .get("replace_all")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    }
+}
+
+/// Collect file operations per path, in the order Claude issued them, with
+/// whether the file was read before its first operation (so it existed).
+fn collect_ops(content: &str) -> Vec<(String, bool, Vec<FileOp>)> {
+    let mut order: Vec<(String, bool)> = Vec::new();
+    let mut ops: HashMap<String, Vec<FileOp>> = HashMap::new();
+    let mut read: HashSet<String> = HashSet::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let obj: Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if obj.get("type").and_then(Value::as_str) != Some("assistant") {
+            continue;
+        }
+        let Some(Value::Array(blocks)) = obj.get("message").and_then(|m| m.get("content")) else {
+            continue;
+        };
+        for block in blocks {
+            if block.get("type").and_then(Value::as_str) != Some("tool_use") {
+                continue;
+            }
+            let input = block.get("input").cloned().unwrap_or(Value::Null);
+            let Some(path) = input.get("file_path").and_then(Value::as_str) else {
+                continue;
+            };
+            let new_ops = match block.get("name").and_then(Value::as_str).unwrap_or("") {
+                "Write" => vec![FileOp::Write(
+                    input
+                        .get("content")
+                        .and_then(Value::as_str)
+                        .unwrap_or("")
+                        .to_string(),
+                )],
+                "Edit" => vec![edit_from(&input)],
+                "MultiEdit" => input
+                    .get("edits")
+                    .and_then(Value::as_array)
+                    .map(|edits| edits.iter().map(edit_from).collect())
+                    .unwrap_or_default(),
+                "Read" => {
+                    read.insert(path.to_string());
+                    continue;
+                }
+                _ => continue,
+            };
+            if !ops.contains_key(path) {
+                order.push((path.to_string(), read.contains(path)));
+            }
+            ops.entry(path.to_string()).or_default().extend(new_ops);
+        }
+    }
+
+    order
+        .into_iter()
+        .map(|(path, was_read)| {
+            let file_ops = ops.remove(&path).unwrap_or_default();
+            (path, was_read, file_ops)
+        })
+        .collect()
+}
+
+fn apply_edit(text: &str, old: &str, new: &str, replace_all: bool) -> Option<String> {
+    if old.is_empty() || !text.contains(old) {
+        return None;
+    }
+    Some(if replace_all {
+        text.replace(old, new)
+    } else {
+        text.replacen(old, new, 1)
+    })
+}
+
+/// Recover the file content from before the session by undoing its edits on
+/// the file's current state. Fails if the file has drifted since, or when an
+/// edit cannot be undone for sure: an empty `new_string` leaves nothing to
+/// find, and one that occurs more than once could be any of them.
+fn undo_edits(final_text: &str, ops: &[FileOp]) -> Option<String> {
+    let mut text = final_text.to_string();
+    for op in ops.iter().rev() {
+        match op {
+            FileOp::Edit {
+                old,
+                new,
+                replace_all,
+            } => {
+                if new.is_empty() || (!replace_all && text.matches(new.as_str()).nth(1).is_some()) {
+                    return None;
+                }
+                text = apply_edit(&text, new, old, *replace_all)?;
+            }
+            FileOp::Write(_) => return None,
+        }
+    }
+    Some(text)
+}
+
+/// Replay `ops` from `base`; None if an edit no longer applies.
+fn replay(base: &str, ops: &[FileOp]) -> Option<String> {
+    let mut text = base.to_string();
+    for op in ops {
+        text = match op {
+            FileOp::Write(content) => content.clone(),
+            FileOp::Edit {
+                old,
+                new,
+                replace_all,
+            } => apply_edit(&text, old, new, *replace_all)?,
+        };
+    }
+    Some(text)
+}
+
+/// Fallback when no full-file base can be established: line up each edit's
+/// before/after fragments so they still diff side by side.
+fn fragments(ops: &[FileOp]) -> (String, String) {
+    let mut before = String::new();
+    let mut after = String::new();
+    for (i, op) in ops.iter().enumerate() {
+        if i > 0 {
+            before.push_str("…\n");
+            after.push_str("…\n");
+        }
+        let (old, new) = match op {
+            FileOp::Write(content) => ("", content.as_str()),
+            FileOp::Edit { old, new, .. } => (old.as_str(), new.as_str()),
+        };
+        for (buf, part) in [(&mut before, old), (&mut after, new)] {
+            buf.push_str(part);
+            if !part.is_empty() && !part.ends_with('\n') {
+                buf.push('\n');
+            }
+        }
+    }
+    (before, after)
+}
+
+/// A file counts as created only when a Write is the first thing the session
+/// did to it; a Write over a file it had read first replaces existing content,
+/// which is unknown, so that change is inexact.
+fn build_change(path: &str, was_read: bool, ops: Vec<FileOp>, current: Option<&str>) -> FileChange {
+    let created = !was_read && matches!(ops.first(), Some(FileOp::Write(_)));
+    let base = if created {
+        Some(String::new())
+    } else {
+        current.and_then(|text| undo_edits(text, &ops))
+    };
+    let (before, after, exact) = match base.and_then(|b| replay(&b, &ops).map(|a| (b, a))) {
+        Some((before, after)) => (before, after, true),
+        None => {
+            let (before, after) = fragments(&ops);
+            (before, after, false)
+        }
+    };
+    FileChange {
+        path: path.to_string(),
+        operations: ops.len(),
+        created,
+        exact,
+        diff: unified_diff(&before, &after),
+    }
+}
+
+fn unified_diff(before: &str, after: &str) -> String {
+    TextDiff::from_lines(before, after)
+        .unified_diff()
+        .context_radius(3)
+        .to_string()
+}
+
+/// Unified diff of the `old_string` → `new_string` of an Edit call, or of
+/// each edit of a MultiEdit call in turn; None for other tools. Line numbers
+/// in the hunks count from the start of each fragment, not of the file.
+pub fn tool_call_diff(tool_name: &str, input: &Value) -> Option<String> {
+    let edits = match tool_name {
+        "Edit" => vec![edit_from(input)],
+        "MultiEdit" => input.get("edits")?.as_array()?.iter().map(edit_from).collect(),
+        _ => return None,
+    };
+    let mut diff = String::new();
+    for edit in &edits {
+        let (before, after) = fragments(std::slice::from_ref(edit));
+        diff.push_str(&unified_diff(&before, &after));
+    }
+    Some(diff)
+}
+
+/// Reconstruct what a session did to each file it wrote or edited.
+///
+/// Files created by a Write (one not read earlier) diff against empty. For
+/// edited files the pre-session content is recovered by undoing the edits on
+/// the file as it is on disk now (its final state); when that fails the edits
+/// are shown as fragments and the change is marked inexact.
+pub fn reconstruct_changes(content: &str) -> Vec<FileChange> {
+    collect_ops(content)
+        .into_iter()
+        .map(|(path, was_read, ops)| {
+            let current = fs::read_to_string(&path).ok();
+            build_change(&path, was_read, ops, current.as_deref())
+        })
+        .collect()
+}
+
+pub fn session_changes_in(
+    project_name: &str,
+    session_id: &str,
+    projects_dir: &Path,
+) -> Result<Vec<FileChange>> {
+    let jsonl_path = projects_dir
+        .join(project_name)
+        .join(format!("{}.jsonl", session_id));
+    if !jsonl_path.exists() {
+        return Ok(Vec::new());
+    }
+    let (content, _) = parser::read_transcript(&jsonl_path)?;
+    Ok(reconstruct_changes(&content))
+}
+
+pub fn session_changes(project_name: &str, session_id: &str) -> Result<Vec<FileChange>> {
+    match parser::session_root(project_name, session_id) {
+        Some(root) => session_changes_in(project_name, session_id, &root.projects_dir),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn tool_line(name: &str, input: Value) -> String {
+        json!({
+            "type": "assistant",
+            "message": {"content": [{"type": "tool_use", "name": name, "input": input}]}
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn created_file_diffs_against_empty() {
+        let content = [
+            tool_line("Write", json!({"file_path": "/nope/new.rs", "content": "fn a() {}\n"})),
+            tool_line(
+                "Edit",
+                json!({"file_path": "/nope/new.rs", "old_string": "a()", "new_string": "b()"}),
+            ),
+        ]
+        .join("\n");
+        let changes = reconstruct_changes(&content);
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].created);
+        assert!(changes[0].exact);
+        assert_eq!(changes[0].operations, 2);
+        assert!(changes[0].diff.contains("+fn b() {}"));
+        assert!(!changes[0].diff.contains("-fn"));
+    }
+
+    #[test]
+    fn write_over_a_file_read_or_edited_first_is_not_created() {
+        let content = [
+            tool_line("Read", json!({"file_path": "/nope/old.rs"})),
+            tool_line("Write", json!({"file_path": "/nope/old.rs", "content": "new\n"})),
+            tool_line("Edit", json!({"file_path": "/nope/other.rs", "old_string": "a", "new_string": "b"})),
+            tool_line("Write", json!({"file_path": "/nope/other.rs", "content": "c\n"})),
+            tool_line("Write", json!({"file_path": "/nope/fresh.rs", "content": "d\n"})),
+            tool_line("Read", json!({"file_path": "/nope/fresh.rs"})),
+            tool_line("Write", json!({"file_path": "/nope/fresh.rs", "content": "e\n"})),
+        ]
+        .join("\n");
+        let changes = reconstruct_changes(&content);
+        let created: Vec<(&str, bool, bool)> =
+            changes.iter().map(|c| (c.path.as_str(), c.created, c.exact)).collect();
+        assert_eq!(
+            created,
+            vec![("/nope/old.rs", false, false), ("/nope/other.rs", false, false), ("/nope/fresh.rs", true, true)]
+        );
+    }
+
+    #[test]
+    fn edited_file_recovers_base_from_final_state() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("lib.rs");
+        fs::write(&path, "one\nTWO\nthree\nFOUR\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+        let content = [
+            tool_line(
+                "Edit",
+                json!({"file_path": path, "old_string": "two", "new_string": "TWO"}),
+            ),
+            tool_line(
+                "MultiEdit",
+                json!({"file_path": path, "edits": [{"old_string": "four", "new_string": "FOUR"}]}),
+            ),
+        ]
+        .join("\n");
+        let changes = reconstruct_changes(&content);
+        assert_eq!(changes.len(), 1);
+        assert!(!changes[0].created);
+        assert!(changes[0].exact);
+        assert!(changes[0].diff.contains("-two\n+TWO"));
+        assert!(changes[0].diff.contains("-four\n+FOUR"));
+    }
+
+    #[test]
+    fn ambiguous_edits_are_not_undone() {
+        let edit = |old: &str, new: &str| FileOp::Edit {
+            old: old.to_string(),
+            new: new.to_string(),
+            replace_all: false,
+        };
+        assert_eq!(undo_edits("b c", &[edit("a", "b")]).as_deref(), Some("a c"));
+        // 削除した行は元の位置がわからない
+        assert_eq!(undo_edits("one\nthree\n", &[edit("two\n", "")]), None);
+        // 置き換え後の文字列が複数あると、どれを戻すかわからない
+        assert_eq!(undo_edits("b b", &[edit("a", "b")]), None);
+
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("lib.rs");
+        fs::write(&path, "TWO\nTWO\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+        let content = tool_line("Edit", json!({"file_path": path, "old_string": "two", "new_string": "TWO"}));
+        let changes = reconstruct_changes(&content);
+        assert!(!changes[0].exact);
+        assert!(changes[0].diff.contains("-two\n+TWO"));
+    }
+
+    #[test]
+    fn drifted_file_falls_back_to_fragments() {
+        let content = tool_line(
+            "Edit",
+            json!({"file_path": "/nope/missing.rs", "old_string": "x", "new_string": "y"}),
+        );
+        let changes = reconstruct_changes(&content);
+        assert!(!changes[0].exact);
+        assert!(changes[0].diff.contains("-x\n+y"));
+    }
+
+    #[test]
+    fn non_file_tools_are_ignored() {
+        let content = tool_line("Read", json!({"file_path": "/a"}));
+        assert!(reconstruct_changes(&content).is_empty());
+    }
+
+    #[test]
+    fn tool_call_diff_shows_each_edit() {
+        let edit = json!({"file_path": "/a.rs", "old_string": "let a = 1;\nkeep();", "new_string": "let a = 2;\nkeep();"});
+        let diff = tool_call_diff("Edit", &edit).unwrap();
+        assert!(diff.contains("-let a = 1;\n+let a = 2;\n keep();"));
+
+        let multi = json!({"file_path": "/a.rs", "edits": [
+            {"old_string": "one", "new_string": "ONE"},
+            {"old_string": "two", "new_string": ""},
+        ]});
+        let diff = tool_call_diff("MultiEdit", &multi).unwrap();
+        assert_eq!(diff.matches("@@").count(), 4);
+        assert!(diff.contains("-one\n+ONE") && diff.contains("-two"));
+        assert_eq!(tool_call_diff("Write", &json!({"content": "x"})), None);
+    }
+}
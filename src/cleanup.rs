@@ -0,0 +1,361 @@
+use crate::index::SessionIndex;
+use crate::models::SessionInfo;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Sessions with fewer messages than this are offered for cleanup.
+pub const MIN_MESSAGES: usize = 4;
+
+/// Where the archived transcripts of the project in `project_dir` go: an
+/// `archive` folder inside it, which Claude Code and the session list leave
+/// alone, or `<configured>/<project dir name>/` when `archive_dir` is set.
+pub fn archive_dir(configured: &str, project_dir: &Path) -> PathBuf {
+    if configured.is_empty() {
+        return project_dir.join("archive");
+    }
+    let project = project_dir.file_name().unwrap_or_default();
+    crate::snapshots::expand_destination(configured).join(project)
+}
+
+/// Why `session` looks safe to delete, or None to leave it out of the
+/// review. Sessions without a prompt or with very few messages qualify, and
+/// so do those last active `older_than_days` or more ago (0 = never by age).
+pub fn candidate_reason(session: &SessionInfo, older_than_days: u64, now: DateTime<Utc>) -> Option<String> {
+    if session.preview.trim().is_empty() && session.summary.is_empty() {
+        return Some("no prompt".to_string());
+    }
+    if session.message_count < MIN_MESSAGES {
+        return Some(format!("only {} messages", session.message_count));
+    }
+    if older_than_days > 0
+        && let Some(ts) = session.timestamp
+    {
+        let days = (now - ts).num_days();
+        if days >= older_than_days as i64 {
+            return Some(format!("{} days old", days));
+        }
+    }
+    None
+}
+
+/// What to do with the session under review.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    /// Leave it and do not ask again in this review.
+    Keep,
+    Delete,
+    Archive,
+    /// Undecided: ask again after the rest.
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub session: SessionInfo,
+    pub reason: String,
+}
+
+/// Candidates still to decide on, one at a time, and what was decided so far.
+#[derive(Debug, Clone, Default)]
+pub struct Review {
+    pub queue: VecDeque<Candidate>,
+    pub total: usize,
+    pub kept: usize,
+    pub deleted: usize,
+    pub archived: usize,
+}
+
+impl Review {
+    /// Review the candidates among `sessions`, in the given order.
+    pub fn new(sessions: &[SessionInfo], older_than_days: u64, now: DateTime<Utc>) -> Review {
+        let queue: VecDeque<Candidate> = sessions
+            .iter()
+            .filter_map(|s| {
+                let reason = candidate_reason(s, older_than_days, now)?;
+                Some(Candidate { session: s.clone(), reason })
+            })
+            .collect();
+        Review { total: queue.len(), queue, ..Review::default() }
+    }
+
+    pub fn current(&self) -> Option<&Candidate> {
+        self.queue.front()
+    }
+
+    /// How many candidates have been decided on, for a `3/12` counter.
+    pub fn done(&self) -> usize {
+        self.kept + self.deleted + self.archived
+    }
+
+    /// Record `decision` for the current candidate once it has been carried
+    /// out, and move on. A skipped candidate goes to the back of the queue.
+    pub fn resolve(&mut self, decision: Decision) {
+        let Some(candidate) = self.queue.pop_front() else {
+            return;
+        };
+        match decision {
+            Decision::Keep => self.kept += 1,
+            Decision::Delete => self.deleted += 1,
+            Decision::Archive => self.archived += 1,
+            Decision::Skip => self.queue.push_back(candidate),
+        }
+    }
+
+    /// `2 deleted, 1 archived, 3 kept, 1 left`
+    pub fn summary(&self) -> String {
+        let mut parts = vec![
+            format!("{} deleted", self.deleted),
+            format!("{} archived", self.archived),
+            format!("{} kept", self.kept),
+        ];
+        if !self.queue.is_empty() {
+            parts.push(format!("{} left", self.queue.len()));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Delete the transcript at `path` and the folder of subagent transcripts
+/// beside it.
+pub fn delete_session(path: &Path) -> Result<()> {
+    fs::remove_file(path).with_context(|| format!("cannot delete {}", path.display()))?;
+    let folder = path.with_extension("");
+    if folder.is_dir() {
+        fs::remove_dir_all(&folder).with_context(|| format!("cannot delete {}", folder.display()))?;
+    }
+    Ok(())
+}
+
+/// Move the transcript at `path` into `dest_dir` (see [`archive_dir`]),
+/// keeping its file name, and return where it went.
+pub fn archive_session(path: &Path, dest_dir: &Path) -> Result<PathBuf> {
+    move_transcript(path, dest_dir, "already archived")
+}
+
+/// Move an archived transcript back into `project_dir`.
+pub fn restore_session(path: &Path, project_dir: &Path) -> Result<PathBuf> {
+    move_transcript(path, project_dir, "already in the project")
+}
+
+/// Move the transcript at `path` into `dest_dir`, never over an existing
+/// file. Falls back to copy and delete when `dest_dir` is on another file
+/// system. The folder of subagent transcripts beside it moves along when it
+/// can be renamed, and stays where it is otherwise.
+fn move_transcript(path: &Path, dest_dir: &Path, exists: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dest_dir).with_context(|| format!("cannot create {}", dest_dir.display()))?;
+    let dest = dest_dir.join(path.file_name().context("transcript path has no file name")?);
+    if dest.exists() {
+        anyhow::bail!("{} is {}", dest.display(), exists);
+    }
+    if fs::rename(path, &dest).is_err() {
+        fs::copy(path, &dest).with_context(|| format!("cannot copy {} to {}", path.display(), dest.display()))?;
+        fs::remove_file(path).with_context(|| format!("cannot remove {}", path.display()))?;
+    }
+    let folder = path.with_extension("");
+    if folder.is_dir() {
+        let _ = fs::rename(&folder, dest.with_extension(""));
+    }
+    Ok(dest)
+}
+
+/// What `apply_retention` did, for the closing summary.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionReport {
+    pub archived: usize,
+    /// Old enough, but a favorite or tagged.
+    pub protected: usize,
+    pub failed: usize,
+}
+
+/// Archive every transcript in `projects_dirs` last written `days` or more
+/// before `now` into its project's [`archive_dir`], except favorites and
+/// tagged sessions, and record each move (or failure) in the index's
+/// retention journal. Transcripts are moved as-is, not compressed, so the
+/// archived view can open and restore them.
+pub fn apply_retention(
+    index: &SessionIndex,
+    projects_dirs: &[PathBuf],
+    archive_setting: &str,
+    days: u64,
+    now: DateTime<Utc>,
+) -> Result<RetentionReport> {
+    let cutoff = now - chrono::Duration::days(days as i64);
+    let favorites = index.favorites()?;
+    let tags = index.session_tags()?;
+    let mut report = RetentionReport::default();
+    for projects_dir in projects_dirs {
+        let Ok(project_dirs) = fs::read_dir(projects_dir) else {
+            continue;
+        };
+        for project in project_dirs.flatten() {
+            let dir_name = project.file_name().to_string_lossy().to_string();
+            let Ok(files) = fs::read_dir(project.path()) else {
+                continue;
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+                    continue;
+                }
+                let Ok(modified) = file.metadata().and_then(|m| m.modified()) else {
+                    continue;
+                };
+                if DateTime::<Utc>::from(modified) > cutoff {
+                    continue;
+                }
+                let session_id = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                if favorites.contains(&session_id) || tags.contains_key(&session_id) {
+                    report.protected += 1;
+                    continue;
+                }
+                match archive_session(&path, &archive_dir(archive_setting, &project.path())) {
+                    Ok(dest) => {
+                        index.log_retention("archived", &session_id, &dir_name, &dest.display().to_string())?;
+                        report.archived += 1;
+                    }
+                    Err(e) => {
+                        index.log_retention("failed", &session_id, &dir_name, &format!("{:#}", e))?;
+                        report.failed += 1;
+                    }
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenUsage;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    fn now() -> DateTime<Utc> {
+        "2026-10-01T00:00:00Z".parse().unwrap()
+    }
+
+    fn session(id: &str, preview: &str, messages: usize, age_days: i64) -> SessionInfo {
+        SessionInfo {
+            session_id: id.to_string(),
+            project_name: "p".to_string(),
+            preview: preview.to_string(),
+            timestamp: Some(now() - Duration::days(age_days)),
+            modified: None,
+            message_count: messages,
+            git_branch: String::new(),
+            summary: String::new(),
+            needs_input: false,
+            usage: TokenUsage::default(),
+            cost_usd: 0.0,
+            has_loops: false,
+            parent_session_id: String::new(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+            warning: String::new(),
+        }
+    }
+
+    #[test]
+    fn candidates_are_empty_tiny_or_old_sessions() {
+        let now = now();
+        assert_eq!(candidate_reason(&session("a", "", 10, 0), 0, now).as_deref(), Some("no prompt"));
+        assert_eq!(candidate_reason(&session("b", "hi", 2, 0), 0, now).as_deref(), Some("only 2 messages"));
+        assert_eq!(candidate_reason(&session("c", "real work", 40, 100), 0, now), None);
+        assert_eq!(candidate_reason(&session("c", "real work", 40, 100), 90, now).as_deref(), Some("100 days old"));
+        assert_eq!(candidate_reason(&session("d", "real work", 40, 10), 90, now), None);
+    }
+
+    #[test]
+    fn review_walks_candidates_and_requeues_skips() {
+        let sessions = vec![session("a", "", 1, 0), session("b", "keep me", 20, 0), session("c", "hi", 2, 0)];
+        let mut review = Review::new(&sessions, 0, now());
+        assert_eq!(review.total, 2);
+        assert_eq!(review.current().unwrap().session.session_id, "a");
+
+        review.resolve(Decision::Skip);
+        assert_eq!(review.current().unwrap().session.session_id, "c");
+        review.resolve(Decision::Delete);
+        assert_eq!(review.current().unwrap().session.session_id, "a");
+        assert_eq!(review.summary(), "1 deleted, 0 archived, 0 kept, 1 left");
+        review.resolve(Decision::Archive);
+        assert!(review.current().is_none());
+        assert_eq!((review.done(), review.summary().as_str()), (2, "1 deleted, 1 archived, 0 kept"));
+    }
+
+    #[test]
+    fn archive_dir_is_inside_the_project_unless_configured() {
+        let project = Path::new("/home/me/.claude/projects/-w");
+        assert_eq!(archive_dir("", project), project.join("archive"));
+        assert_eq!(archive_dir("/backup/claude", project), Path::new("/backup/claude/-w"));
+    }
+
+    #[test]
+    fn archive_moves_transcript_and_subagents_and_restores_them() {
+        let tmp = TempDir::new().unwrap();
+        let project = tmp.path().join("projects").join("-w");
+        let transcript = project.join("s1.jsonl");
+        fs::create_dir_all(project.join("s1").join("subagents")).unwrap();
+        fs::write(&transcript, "{}\n").unwrap();
+        let archive = archive_dir("", &project);
+
+        let dest = archive_session(&transcript, &archive).unwrap();
+        assert_eq!(dest, project.join("archive").join("s1.jsonl"));
+        assert!(!transcript.exists());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "{}\n");
+        assert!(archive.join("s1").join("subagents").is_dir());
+        assert!(!project.join("s1").exists());
+
+        // 同じ名前がすでに退避済みなら上書きしない
+        fs::write(&transcript, "new\n").unwrap();
+        assert!(archive_session(&transcript, &archive).is_err());
+        assert!(transcript.exists());
+        assert!(restore_session(&dest, &project).is_err());
+        delete_session(&transcript).unwrap();
+
+        assert_eq!(restore_session(&dest, &project).unwrap(), transcript);
+        assert_eq!(fs::read_to_string(&transcript).unwrap(), "{}\n");
+        assert!(project.join("s1").join("subagents").is_dir());
+
+        delete_session(&transcript).unwrap();
+        assert!(!transcript.exists());
+        assert!(!project.join("s1").exists());
+    }
+
+    #[test]
+    fn retention_archives_old_transcripts_except_favorites_and_tagged() {
+        let tmp = TempDir::new().unwrap();
+        let projects = tmp.path().join("projects");
+        let project = projects.join("-w");
+        fs::create_dir_all(&project).unwrap();
+        // 最後に書かれたのが何日前かをファイルの更新時刻で決める
+        for (id, age_days) in [("old", 200), ("fav", 200), ("tagged", 200), ("recent", 10)] {
+            let path = project.join(format!("{}.jsonl", id));
+            fs::write(&path, "{}\n").unwrap();
+            let modified: std::time::SystemTime = (now() - Duration::days(age_days)).into();
+            fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        }
+
+        let index = SessionIndex::open(&tmp.path().join("index.db")).unwrap();
+        index.add_favorite("fav", "-w", 0).unwrap();
+        index.set_session_tags("tagged", "-w", &["keep".to_string()]).unwrap();
+        let report = apply_retention(&index, &[projects], "", 180, now()).unwrap();
+
+        assert_eq!(report, RetentionReport { archived: 1, protected: 2, failed: 0 });
+        assert!(!project.join("old.jsonl").exists());
+        for id in ["fav", "tagged", "recent"] {
+            assert!(project.join(format!("{}.jsonl", id)).exists(), "{id}");
+        }
+        // 圧縮せずそのまま移す
+        assert_eq!(fs::read_to_string(project.join("archive").join("old.jsonl")).unwrap(), "{}\n");
+
+        let journal = index.retention_journal(10).unwrap();
+        assert_eq!(journal.len(), 1);
+        assert_eq!((journal[0].action.as_str(), journal[0].session_id.as_str()), ("archived", "old"));
+        assert_eq!(journal[0].dir_name, "-w");
+    }
+}
@@ -0,0 +1,223 @@
+//! Clipboard backends: the system clipboard, an OSC 52 escape sequence that
+//! the terminal (also over SSH) turns into a copy, and a plain file for
+//! setups where neither works (`--clip-file`).
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a copy to the system clipboard may take before it is given up
+/// on; some headless and Wayland setups block forever.
+const SYSTEM_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The `clipboard` setting of the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardKind {
+    /// Pick from the environment, see [`detect`].
+    #[default]
+    Auto,
+    System,
+    Osc52,
+    File,
+}
+
+/// Somewhere copied text can go.
+pub trait Clipboard {
+    /// Shown after "Copied … via" and by `doctor`.
+    fn name(&self) -> String;
+    fn kind(&self) -> ClipboardKind;
+    fn set_contents(&mut self, text: &str) -> Result<()>;
+}
+
+/// The desktop clipboard through `cli_clipboard`. Nothing is connected
+/// until the first copy, so a broken setup costs nothing at startup.
+pub struct SystemClipboard;
+
+impl Clipboard for SystemClipboard {
+    fn name(&self) -> String {
+        "system clipboard".to_string()
+    }
+
+    fn kind(&self) -> ClipboardKind {
+        ClipboardKind::System
+    }
+
+    fn set_contents(&mut self, text: &str) -> Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let text = text.to_string();
+        thread::spawn(move || {
+            let _ = tx.send(cli_clipboard::set_contents(text).map_err(|e| e.to_string()));
+        });
+        match rx.recv_timeout(SYSTEM_TIMEOUT) {
+            Ok(result) => result.map_err(|e| anyhow!(e)),
+            Err(_) => Err(anyhow!("no answer within {}s", SYSTEM_TIMEOUT.as_secs())),
+        }
+    }
+}
+
+/// Asks the terminal to copy with an OSC 52 sequence written to stdout,
+/// wrapped for tmux to pass it through.
+pub struct Osc52Clipboard {
+    pub tmux: bool,
+}
+
+impl Clipboard for Osc52Clipboard {
+    fn name(&self) -> String {
+        "OSC 52".to_string()
+    }
+
+    fn kind(&self) -> ClipboardKind {
+        ClipboardKind::Osc52
+    }
+
+    fn set_contents(&mut self, text: &str) -> Result<()> {
+        let mut stdout = io::stdout();
+        stdout.write_all(osc52_sequence(text, self.tmux).as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Overwrites a file with each copy, for a watcher or editor to pick up.
+pub struct FileClipboard {
+    pub path: PathBuf,
+}
+
+impl Clipboard for FileClipboard {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn kind(&self) -> ClipboardKind {
+        ClipboardKind::File
+    }
+
+    fn set_contents(&mut self, text: &str) -> Result<()> {
+        fs::write(&self.path, text).map_err(|e| anyhow!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// The environment variables that decide the automatic backend.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    pub ssh: bool,
+    pub display: bool,
+    pub tmux: bool,
+}
+
+impl Environment {
+    pub fn current() -> Environment {
+        let set = |name: &str| std::env::var_os(name).is_some_and(|v| !v.is_empty());
+        Environment {
+            ssh: set("SSH_TTY") || set("SSH_CONNECTION"),
+            display: !cfg!(target_os = "linux") || set("DISPLAY") || set("WAYLAND_DISPLAY"),
+            tmux: set("TMUX"),
+        }
+    }
+}
+
+/// The backend `kind` stands for, with the reason it was chosen. A clip file
+/// wins over any setting; `Auto` uses OSC 52 over SSH and where there is no
+/// display server, and the system clipboard otherwise.
+pub fn detect(kind: ClipboardKind, clip_file: Option<&Path>, env: &Environment) -> (ClipboardKind, &'static str) {
+    match (kind, clip_file) {
+        (_, Some(_)) => (ClipboardKind::File, "clip file given"),
+        (ClipboardKind::File, None) => (ClipboardKind::Osc52, "clipboard = \"file\" without clip_file"),
+        (ClipboardKind::Auto, None) if env.ssh => (ClipboardKind::Osc52, "over SSH"),
+        (ClipboardKind::Auto, None) if !env.display => (ClipboardKind::Osc52, "no display server"),
+        (ClipboardKind::Auto, None) => (ClipboardKind::System, "display server found"),
+        (kind, None) => (kind, "configured"),
+    }
+}
+
+/// The backend for `kind` in the current environment.
+pub fn open(kind: ClipboardKind, clip_file: Option<&Path>) -> Box<dyn Clipboard> {
+    let env = Environment::current();
+    match (detect(kind, clip_file, &env).0, clip_file) {
+        (ClipboardKind::File, Some(path)) => Box::new(FileClipboard { path: path.to_path_buf() }),
+        (ClipboardKind::System, _) => Box::new(SystemClipboard),
+        _ => Box::new(Osc52Clipboard { tmux: env.tmux }),
+    }
+}
+
+/// `ESC ] 52 ; c ; <base64> BEL`, inside a DCS passthrough under tmux.
+pub fn osc52_sequence(text: &str, tmux: bool) -> String {
+    let osc = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if tmux {
+        format!("\x1bPtmux;\x1b{}\x1b\\", osc)
+    } else {
+        osc
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn base64_pads_partial_chunks() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("コピー\n".as_bytes()), "44Kz44OU44O8Cg==");
+    }
+
+    #[test]
+    fn osc52_sequence_is_wrapped_for_tmux() {
+        assert_eq!(osc52_sequence("hi", false), "\x1b]52;c;aGk=\x07");
+        assert_eq!(osc52_sequence("hi", true), "\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\");
+    }
+
+    #[test]
+    fn detect_prefers_clip_file_then_osc52_without_a_display() {
+        let desktop = Environment { ssh: false, display: true, tmux: false };
+        let headless = Environment { display: false, ..desktop.clone() };
+        let ssh = Environment { ssh: true, ..desktop.clone() };
+        let file = Path::new("/tmp/clip");
+
+        assert_eq!(detect(ClipboardKind::Auto, None, &desktop).0, ClipboardKind::System);
+        assert_eq!(detect(ClipboardKind::Auto, None, &headless), (ClipboardKind::Osc52, "no display server"));
+        assert_eq!(detect(ClipboardKind::Auto, None, &ssh), (ClipboardKind::Osc52, "over SSH"));
+        assert_eq!(detect(ClipboardKind::System, None, &ssh).0, ClipboardKind::System);
+        assert_eq!(detect(ClipboardKind::System, Some(file), &desktop).0, ClipboardKind::File);
+        assert_eq!(detect(ClipboardKind::File, None, &desktop).0, ClipboardKind::Osc52);
+    }
+
+    #[test]
+    fn file_clipboard_overwrites_the_file() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("clip.txt");
+        let mut clipboard = open(ClipboardKind::Auto, Some(&path));
+        assert_eq!((clipboard.kind(), clipboard.name()), (ClipboardKind::File, path.display().to_string()));
+        clipboard.set_contents("first").unwrap();
+        clipboard.set_contents("second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+
+        let mut missing = FileClipboard { path: tmp.path().join("no/such/dir/clip.txt") };
+        assert!(missing.set_contents("x").is_err());
+    }
+}
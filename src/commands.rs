@@ -0,0 +1,160 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, ExitStatus, Stdio};
+
+/// A user-registered action run against a session, e.g. a summarizer script.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ExternalCommand {
+    /// Label shown in the command menu.
+    pub name: String,
+    /// Shell command line. `{session_id}`, `{session_path}`, `{project_path}`
+    /// and `{project_dir}` are replaced with shell-quoted values.
+    pub command: String,
+}
+
+/// Values substituted into a command template.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CommandContext {
+    pub session_id: String,
+    /// Full path to the session's JSONL file.
+    pub session_path: String,
+    /// The project's original working directory.
+    pub project_path: String,
+    /// The encoded directory name under `~/.claude/projects`.
+    pub project_dir: String,
+}
+
+/// Quote `s` for a POSIX shell so it is passed as a single word.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Fill the placeholders of `template` from `ctx`. Unknown `{...}` are left as is.
+pub fn expand(template: &str, ctx: &CommandContext) -> String {
+    let placeholders = [
+        ("{session_id}", &ctx.session_id),
+        ("{session_path}", &ctx.session_path),
+        ("{project_path}", &ctx.project_path),
+        ("{project_dir}", &ctx.project_dir),
+    ];
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    'outer: while let Some(pos) = rest.find('{') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        for (key, value) in &placeholders {
+            if let Some(after) = rest.strip_prefix(key) {
+                out.push_str(&shell_quote(value));
+                rest = after;
+                continue 'outer;
+            }
+        }
+        out.push('{');
+        rest = &rest[1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Run `command_line` through `sh -c` with the terminal's stdio and wait for it.
+pub fn run(command_line: &str) -> Result<ExitStatus> {
+    Ok(Command::new("sh").arg("-c").arg(command_line).status()?)
+}
+
+/// Run `command_line` through `sh -c` with `input` on stdin, leaving the
+/// terminal to it (for pagers).
+pub fn run_with_input(command_line: &str, input: &str) -> Result<ExitStatus> {
+    let mut child = Command::new("sh").arg("-c").arg(command_line).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // ページャは最後まで読まずに閉じられることがある
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    Ok(child.wait()?)
+}
+
+/// Run `command_line` through `sh -c` with `input` on stdin and return what
+/// it printed. A non-zero exit is an error carrying its stderr.
+pub fn run_piped(command_line: &str, input: &str) -> Result<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command_line)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // 入力を読まずに終わるコマンドもあるので書き込みエラーは無視する
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{} ({})", stderr.trim(), output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The first `http://` or `https://` URL in `text`, e.g. the gist a share
+/// command created.
+pub fn first_url(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .map(|url| url.trim_end_matches(['.', ',', ')', '"', '\'']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> CommandContext {
+        CommandContext {
+            session_id: "abc-123".to_string(),
+            session_path: "/home/me/.claude/projects/-p/abc-123.jsonl".to_string(),
+            project_path: "/home/me/it's here".to_string(),
+            project_dir: "-p".to_string(),
+        }
+    }
+
+    #[test]
+    fn shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn expand_replaces_every_placeholder() {
+        let out = expand("summarize {session_path} --id={session_id} -C {project_path} {project_dir}", &ctx());
+        assert_eq!(
+            out,
+            r"summarize '/home/me/.claude/projects/-p/abc-123.jsonl' --id='abc-123' -C '/home/me/it'\''s here' '-p'"
+        );
+    }
+
+    #[test]
+    fn expand_keeps_unknown_braces() {
+        assert_eq!(expand("awk '{print $1}' {session_id}", &ctx()), "awk '{print $1}' 'abc-123'");
+        assert_eq!(expand("echo {", &ctx()), "echo {");
+    }
+
+    #[test]
+    fn run_reports_exit_status() {
+        assert!(run("true").unwrap().success());
+        assert_eq!(run("exit 3").unwrap().code(), Some(3));
+    }
+
+    #[test]
+    fn run_piped_feeds_stdin_and_returns_stdout() {
+        assert_eq!(run_piped("tr a-z A-Z", "gist me").unwrap(), "GIST ME");
+        let err = run_piped("echo 'bad token' >&2; exit 4", "x").unwrap_err().to_string();
+        assert!(err.starts_with("bad token (") && err.contains('4'), "{}", err);
+    }
+
+    #[test]
+    fn first_url_picks_the_created_link() {
+        assert_eq!(first_url("- Creating gist\nhttps://gist.github.com/me/abc123\n"), Some("https://gist.github.com/me/abc123"));
+        assert_eq!(first_url("Pasted to http://paste.local/x1."), Some("http://paste.local/x1"));
+        assert_eq!(first_url("no link"), None);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::clipboard::ClipboardKind;
+use crate::commands::ExternalCommand;
+use crate::models::PriceTable;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default cap on characters rendered per message in the detail view.
+pub const DEFAULT_MAX_MESSAGE_CHARS: usize = 20_000;
+
+/// Default number of lines an expanded tool result shows before "more lines".
+pub const DEFAULT_MAX_RESULT_LINES: usize = 200;
+
+/// User settings read from `~/.config/cc-sessions-viewer/config.toml`.
+/// Every field is optional in the file; missing ones take the defaults.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Messages longer than this are truncated in SessionDetail until the
+    /// user asks for the full text. 0 disables the cap.
+    pub max_message_chars: usize,
+    /// An expanded tool result shows this many lines until the user asks
+    /// for the rest. 0 shows every line.
+    pub max_result_lines: usize,
+    /// Per-million-token prices keyed by model-name substring, overriding
+    /// the built-in list prices for the cost stored in the index.
+    pub prices: PriceTable,
+    /// External actions offered in the per-session command menu.
+    pub commands: Vec<ExternalCommand>,
+    /// Prompts are stored in the search index cut to this many characters,
+    /// so pasted files do not bloat it. 0 stores them whole.
+    pub max_prompt_chars: usize,
+    /// Accent colors keyed by project path, overriding the one derived from
+    /// the path. Values are color names (`magenta`, `lightblue`) or `#rrggbb`.
+    pub project_colors: HashMap<String, String>,
+    /// Syntax-highlight fenced code in answers and the file-change diffs.
+    /// Turn off if long transcripts scroll slowly.
+    pub syntax_highlighting: bool,
+    /// Show ANSI colors in tool output as the terminal did; off strips the
+    /// escape sequences instead.
+    pub ansi_colors: bool,
+    /// Extra regular expressions whose matches are replaced in anonymized
+    /// exports, on top of the built-in secret patterns.
+    pub redact_patterns: Vec<String>,
+    /// More Claude Code config directories (like `~/.claude-work`) whose
+    /// sessions are indexed and listed alongside those of `~/.claude`.
+    pub claude_dirs: Vec<String>,
+    /// The cleanup review also offers sessions last active this many days
+    /// ago or more. 0 offers only empty and very short sessions.
+    pub cleanup_after_days: u64,
+    /// Directory archived transcripts are moved to, in a folder per project.
+    /// Empty keeps them in an `archive` folder inside the project directory.
+    pub archive_dir: String,
+    /// `apply-retention` archives sessions last written this many days ago
+    /// or more, except favorites and tagged ones. 0 leaves them all.
+    pub retention_days: u64,
+    /// Shell command that publishes a Markdown export (gist, pastebin): the
+    /// Markdown is piped to its stdin and the first URL it prints is copied.
+    /// Empty disables sharing.
+    pub share_command: String,
+    /// Pager a message's full text is piped to. Empty uses `$PAGER`, then
+    /// `less -R`.
+    pub pager: String,
+    /// Pager for the diff of an Edit/MultiEdit call, e.g. `delta`. Empty
+    /// uses `pager`.
+    pub diff_pager: String,
+    /// Keep a copy of each project's CLAUDE.md and settings files in the
+    /// index whenever they change, to see what was in effect for an old
+    /// session.
+    pub snapshot_instructions: bool,
+    /// Where copies go: `auto`, `system`, `osc52` or `file`. `auto` uses
+    /// OSC 52 over SSH or without a display server.
+    pub clipboard: ClipboardKind,
+    /// File that every copy overwrites when `clipboard = "file"`; the
+    /// `--clip-file` flag sets it for one run.
+    pub clip_file: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_message_chars: DEFAULT_MAX_MESSAGE_CHARS,
+            max_result_lines: DEFAULT_MAX_RESULT_LINES,
+            prices: PriceTable::default(),
+            commands: Vec::new(),
+            max_prompt_chars: 0,
+            project_colors: HashMap::new(),
+            syntax_highlighting: true,
+            ansi_colors: true,
+            redact_patterns: Vec::new(),
+            claude_dirs: Vec::new(),
+            cleanup_after_days: 0,
+            archive_dir: String::new(),
+            retention_days: 0,
+            share_command: String::new(),
+            pager: String::new(),
+            diff_pager: String::new(),
+            snapshot_instructions: false,
+            clipboard: ClipboardKind::Auto,
+            clip_file: String::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("cc-sessions-viewer").join("config.toml"))
+    }
+
+    /// Load from `path`; a missing file yields the defaults.
+    pub fn load_from(path: &Path) -> Result<Config> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e: toml::de::Error| {
+            // 状態行に出すので、toml の複数行の説明を1行にまとめる
+            let line = e.span().map_or(1, |span| content[..span.start].matches('\n').count() + 1);
+            anyhow::anyhow!("{} line {}: {}", path.display(), line, e.message())
+        })
+    }
+
+    /// `clip_file` with a leading `~/` expanded, if set.
+    pub fn clip_file(&self) -> Option<PathBuf> {
+        match (self.clip_file.strip_prefix("~/"), dirs::home_dir()) {
+            _ if self.clip_file.is_empty() => None,
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(&self.clip_file)),
+        }
+    }
+
+    /// Load the user's config; a missing file or config directory yields the
+    /// defaults, an invalid file an error.
+    pub fn try_load() -> Result<Config> {
+        match Config::path() {
+            Some(path) => Config::load_from(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Load the user's config, falling back to defaults if it is missing or invalid.
+    pub fn load() -> Config {
+        Config::try_load().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn load_from_missing_file_uses_defaults() {
+        let tmp = TempDir::new().unwrap();
+        let config = Config::load_from(&tmp.path().join("config.toml")).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn load_from_reads_max_message_chars() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(&path, "max_message_chars = 500\n").unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().max_message_chars, 500);
+
+        fs::write(&path, "max_prompt_chars = 2000\n").unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!((config.max_message_chars, config.max_prompt_chars), (DEFAULT_MAX_MESSAGE_CHARS, 2000));
+
+        fs::write(&path, "max_message_chars = \"lots\"\n").unwrap();
+        assert!(Config::load_from(&path).is_err());
+
+        // 状態行に出せるよう、場所つきの1行にする
+        fs::write(&path, "pager = \"less\"\n\nmax_result_lines = -1\n").unwrap();
+        let error = Config::load_from(&path).unwrap_err().to_string();
+        assert!(!error.contains('\n'), "{error}");
+        assert!(error.starts_with(&format!("{} line 3: ", path.display())), "{error}");
+    }
+
+    #[test]
+    fn load_from_reads_price_table() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(
+            &path,
+            "[prices.opus]\ninput = 10.0\noutput = 50.0\n\n[prices.\"my-model\"]\ninput = 1\noutput = 2\ncache_read = 0.1\n",
+        )
+        .unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.prices.price("claude-opus-4").output, 50.0);
+        assert_eq!(config.prices.price("my-model-v2").cache_read, 0.1);
+        assert_eq!(config.max_message_chars, DEFAULT_MAX_MESSAGE_CHARS);
+
+        fs::write(&path, "[prices.opus]\ninput = 10.0\n").unwrap();
+        assert!(Config::load_from(&path).is_err());
+    }
+
+    #[test]
+    fn load_from_reads_project_colors() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(&path, "[project_colors]\n\"/home/me/api\" = \"magenta\"\n\"/home/me/web\" = \"#ff8800\"\n").unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.project_colors.len(), 2);
+        assert_eq!(config.project_colors["/home/me/web"], "#ff8800");
+    }
+
+    #[test]
+    fn syntax_highlighting_is_on_unless_disabled() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(&path, "max_message_chars = 500\n").unwrap();
+        assert!(Config::load_from(&path).unwrap().syntax_highlighting);
+        fs::write(&path, "syntax_highlighting = false\n").unwrap();
+        assert!(!Config::load_from(&path).unwrap().syntax_highlighting);
+    }
+
+    #[test]
+    fn load_from_reads_redact_patterns() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(&path, "redact_patterns = [\"acme-\\\\d+\", \"internal\\\\.example\"]\n").unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().redact_patterns, vec![r"acme-\d+", r"internal\.example"]);
+    }
+
+    #[test]
+    fn load_from_reads_claude_dirs() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(&path, "claude_dirs = [\"~/.claude-work\", \"/mnt/backup/.claude\"]\n").unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().claude_dirs, vec!["~/.claude-work", "/mnt/backup/.claude"]);
+    }
+
+    #[test]
+    fn load_from_reads_clipboard_backend() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(&path, "clipboard = \"file\"\nclip_file = \"/tmp/clip.txt\"\n").unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!((config.clipboard, config.clip_file.as_str()), (ClipboardKind::File, "/tmp/clip.txt"));
+
+        fs::write(&path, "clipboard = \"xclip\"\n").unwrap();
+        assert!(Config::load_from(&path).is_err());
+    }
+
+    #[test]
+    fn load_from_reads_commands() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(
+            &path,
+            "[[commands]]\nname = \"Summarize\"\ncommand = \"summarize {session_path}\"\n\n[[commands]]\nname = \"Notes\"\ncommand = \"notes add {session_id}\"\n",
+        )
+        .unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.commands.len(), 2);
+        assert_eq!(config.commands[0].name, "Summarize");
+        assert_eq!(config.commands[1].command, "notes add {session_id}");
+
+        fs::write(&path, "[[commands]]\nname = \"No command\"\n").unwrap();
+        assert!(Config::load_from(&path).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+//! Synthetic Claude Code transcripts for the benchmarks: `projects` project
+//! directories holding `sessions` sessions of `messages` messages each.
+//!
+//! The output is deterministic for a given spec, so timings from different
+//! builds are measured against the same corpus.
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Shape of a generated corpus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorpusSpec {
+    pub projects: usize,
+    pub sessions: usize,
+    pub messages: usize,
+    pub seed: u64,
+}
+
+impl CorpusSpec {
+    /// Parse `NxMxK` (projects × sessions per project × messages per session).
+    pub fn parse(spec: &str, seed: u64) -> Result<CorpusSpec> {
+        let sizes: Vec<usize> = spec
+            .split('x')
+            .map(|n| n.trim().parse())
+            .collect::<std::result::Result<_, _>>()
+            .with_context(|| format!("corpus size \"{}\" is not NxMxK", spec))?;
+        let [projects, sessions, messages] = sizes[..] else {
+            anyhow::bail!("corpus size \"{}\" is not NxMxK", spec);
+        };
+        Ok(CorpusSpec { projects, sessions, messages, seed })
+    }
+
+    pub fn total_sessions(&self) -> usize {
+        self.projects * self.sessions
+    }
+}
+
+/// Appears in roughly one prompt in fifty, for benchmarking rare-term searches.
+pub const RARE_WORD: &str = "flamingo";
+
+const WORDS: &[&str] = &[
+    "fix", "the", "failing", "test", "in", "parser", "add", "a", "flag", "for", "index", "refactor",
+    "session", "list", "why", "does", "this", "panic", "when", "file", "is", "empty", "rename",
+    "config", "option", "update", "readme", "and", "changelog", "make", "search", "faster", "handle",
+    "unicode", "paths", "on", "windows", "cache", "results", "per", "project", "check", "clippy",
+];
+
+const FILES: &[&str] = &[
+    "src/main.rs", "src/lib.rs", "src/parser.rs", "src/index.rs", "src/app.rs", "src/ui.rs",
+    "README.md", "Cargo.toml", "tests/integration.rs",
+];
+
+const COMMANDS: &[&str] = &["cargo test", "cargo build", "cargo clippy", "git status", "git diff", "ls src"];
+
+/// Directory name of the `i`th project, as Claude Code encodes its path.
+pub fn project_dir_name(i: usize) -> String {
+    format!("-home-bench-src-project-{}", i)
+}
+
+/// Write the corpus under `projects_dir`, replacing files of the same name.
+/// Returns the number of bytes written.
+pub fn generate(projects_dir: &Path, spec: &CorpusSpec) -> Result<u64> {
+    let mut rng = fastrand::Rng::with_seed(spec.seed);
+    let mut bytes = 0;
+    for p in 0..spec.projects {
+        let dir = projects_dir.join(project_dir_name(p));
+        fs::create_dir_all(&dir)?;
+        for s in 0..spec.sessions {
+            let session_id = session_id(&mut rng);
+            let path = dir.join(format!("{}.jsonl", session_id));
+            let mut writer = BufWriter::new(fs::File::create(&path)?);
+            // セッションごとに1時間ずらし、メッセージは30秒おき
+            let start = 1_767_225_600 + ((p * spec.sessions + s) as i64) * 3600;
+            for m in 0..spec.messages {
+                bytes += write_line(&mut writer, &mut rng, &session_id, p, m, start + m as i64 * 30)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Append `messages` more messages to every session file under
+/// `projects_dir`, as sessions still in progress would. Returns the number
+/// of bytes written.
+pub fn append(projects_dir: &Path, messages: usize, seed: u64) -> Result<u64> {
+    let mut rng = fastrand::Rng::with_seed(seed);
+    let mut bytes = 0;
+    let mut dirs: Vec<_> = fs::read_dir(projects_dir)?.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    dirs.sort();
+    for (p, dir) in dirs.iter().enumerate() {
+        let mut files: Vec<_> = fs::read_dir(dir)?.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        files.sort();
+        for path in files.iter().filter(|f| f.extension().is_some_and(|e| e == "jsonl")) {
+            let session_id = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            let mut writer = BufWriter::new(OpenOptions::new().append(true).open(path)?);
+            for m in 0..messages {
+                bytes += write_line(&mut writer, &mut rng, &session_id, p, m, 1_800_000_000 + m as i64 * 30)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(bytes)
+}
+
+fn session_id(rng: &mut fastrand::Rng) -> String {
+    format!("{:08x}-{:04x}-4{:03x}-8{:03x}-{:012x}", rng.u32(..), rng.u16(..), rng.u16(..0x1000), rng.u16(..0x1000), rng.u64(..1 << 48))
+}
+
+fn words(rng: &mut fastrand::Rng, count: usize) -> String {
+    let mut words: Vec<&str> = (0..count).map(|_| WORDS[rng.usize(..WORDS.len())]).collect();
+    if rng.usize(..50) == 0 {
+        words.push(RARE_WORD);
+    }
+    words.join(" ")
+}
+
+/// One transcript line. Messages cycle through a prompt, an answer with a
+/// tool call, the tool's result and a closing answer.
+fn write_line(
+    out: &mut impl Write,
+    rng: &mut fastrand::Rng,
+    session_id: &str,
+    project: usize,
+    m: usize,
+    timestamp: i64,
+) -> Result<u64> {
+    let timestamp = chrono::DateTime::from_timestamp(timestamp, 0).unwrap_or_default().to_rfc3339();
+    let common = json!({
+        "sessionId": session_id,
+        "uuid": format!("{}-{}", session_id, m),
+        "timestamp": timestamp,
+        "cwd": format!("/home/bench/src/project-{}", project),
+        "gitBranch": if project.is_multiple_of(3) { "main" } else { "feature/bench" },
+        "version": "2.0.14",
+        "userType": "external",
+    });
+    let tool_id = format!("toolu_{}_{}", session_id, m / 4);
+    let body = match m % 4 {
+        0 => {
+            let count = 8 + rng.usize(..20);
+            json!({ "type": "user", "message": { "role": "user", "content": words(rng, count) } })
+        }
+        1 => {
+            let tool = match rng.usize(..3) {
+                0 => json!({ "type": "tool_use", "id": tool_id, "name": "Bash", "input": { "command": COMMANDS[rng.usize(..COMMANDS.len())] } }),
+                1 => json!({ "type": "tool_use", "id": tool_id, "name": "Edit", "input": {
+                    "file_path": format!("/home/bench/src/project-{}/{}", project, FILES[rng.usize(..FILES.len())]),
+                    "old_string": words(rng, 6),
+                    "new_string": words(rng, 8),
+                } }),
+                _ => json!({ "type": "tool_use", "id": tool_id, "name": "Read", "input": {
+                    "file_path": format!("/home/bench/src/project-{}/{}", project, FILES[rng.usize(..FILES.len())]),
+                } }),
+            };
+            assistant(&format!("msg_{}_{}", session_id, m), json!([{ "type": "text", "text": words(rng, 20) }, tool]))
+        }
+        2 => json!({ "type": "user", "message": { "role": "user", "content": [{
+            "type": "tool_result",
+            "tool_use_id": tool_id,
+            "content": (0..1 + rng.usize(..30)).map(|_| words(rng, 10)).collect::<Vec<_>>().join("\n"),
+        }] } }),
+        _ => {
+            let count = 40 + rng.usize(..80);
+            assistant(&format!("msg_{}_{}", session_id, m), json!([{ "type": "text", "text": words(rng, count) }]))
+        }
+    };
+    let mut line = common;
+    if let (Some(line), Some(body)) = (line.as_object_mut(), body.as_object()) {
+        line.extend(body.clone());
+    }
+    let mut text = serde_json::to_string(&line)?;
+    text.push('\n');
+    out.write_all(text.as_bytes())?;
+    Ok(text.len() as u64)
+}
+
+fn assistant(id: &str, content: serde_json::Value) -> serde_json::Value {
+    json!({ "type": "assistant", "message": {
+        "id": id,
+        "role": "assistant",
+        "model": "claude-sonnet-4-5",
+        "content": content,
+        "usage": { "input_tokens": 1200, "output_tokens": 300, "cache_creation_input_tokens": 0, "cache_read_input_tokens": 9000 },
+    } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::models::PriceTable;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_reads_the_three_sizes() {
+        assert_eq!(
+            CorpusSpec::parse("3x4x5", 9).unwrap(),
+            CorpusSpec { projects: 3, sessions: 4, messages: 5, seed: 9 }
+        );
+        assert!(CorpusSpec::parse("3x4", 0).is_err());
+        assert!(CorpusSpec::parse("3xfourx5", 0).is_err());
+    }
+
+    #[test]
+    fn generate_is_deterministic_and_parseable() {
+        let spec = CorpusSpec { projects: 2, sessions: 3, messages: 8, seed: 1 };
+        let a = TempDir::new().unwrap();
+        let b = TempDir::new().unwrap();
+        let bytes = generate(a.path(), &spec).unwrap();
+        assert_eq!(bytes, generate(b.path(), &spec).unwrap());
+
+        let projects = parser::list_projects_in(a.path()).unwrap();
+        assert_eq!(projects.len(), 2);
+        assert!(projects.iter().all(|p| p.session_count == 3));
+        let sessions = parser::list_sessions_in(&project_dir_name(0), a.path(), &PriceTable::default()).unwrap();
+        let messages = parser::load_session_in(&project_dir_name(0), &sessions[0].session_id, a.path()).unwrap();
+        // ツール呼び出しを含むアシスタントの行は本文とツールの2件になる
+        assert_eq!(messages.len(), 10);
+
+        let appended = append(a.path(), 4, 2).unwrap();
+        assert!(appended > 0);
+        let messages = parser::load_session_in(&project_dir_name(0), &sessions[0].session_id, a.path()).unwrap();
+        assert_eq!(messages.len(), 15);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::models::{Message, MessageRole};
+use crate::parser;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+/// The user prompts of one session, for a prompts-only digest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestSession {
+    pub session_id: String,
+    /// Summary or first prompt, used as the section heading.
+    pub title: String,
+    pub prompts: Vec<(Option<DateTime<Utc>>, String)>,
+}
+
+/// The prompts the user typed, skipping tool results and empty turns.
+pub fn user_prompts(messages: &[Message]) -> Vec<(Option<DateTime<Utc>>, String)> {
+    messages
+        .iter()
+        .filter(|m| m.role == MessageRole::User && !m.text.trim().is_empty())
+        .map(|m| (m.timestamp, m.text.trim().to_string()))
+        .collect()
+}
+
+pub fn digest_session_in(
+    project_name: &str,
+    session_id: &str,
+    title: &str,
+    projects_dir: &Path,
+) -> Result<DigestSession> {
+    let messages = parser::load_session_in(project_name, session_id, projects_dir)?;
+    Ok(DigestSession {
+        session_id: session_id.to_string(),
+        title: title.to_string(),
+        prompts: user_prompts(&messages),
+    })
+}
+
+pub fn digest_session(project_name: &str, session_id: &str, title: &str) -> Result<DigestSession> {
+    match parser::session_root(project_name, session_id) {
+        Some(root) => digest_session_in(project_name, session_id, title, &root.projects_dir),
+        None => Ok(DigestSession {
+            session_id: session_id.to_string(),
+            title: title.to_string(),
+            prompts: Vec::new(),
+        }),
+    }
+}
+
+/// Render the digest as Markdown: one section per session, oldest first,
+/// with each prompt as a timestamped bullet. Sessions without prompts are left out.
+pub fn format_digest_markdown(heading: &str, sessions: &[DigestSession]) -> String {
+    let mut sessions: Vec<&DigestSession> = sessions.iter().filter(|s| !s.prompts.is_empty()).collect();
+    sessions.sort_by_key(|s| s.prompts[0].0);
+
+    let mut out = format!("# {}\n", heading);
+    for session in sessions {
+        let title = session.title.lines().next().unwrap_or("").trim();
+        out.push_str(&format!("\n## {} ({})\n\n", title, session.session_id));
+        for (timestamp, prompt) in &session.prompts {
+            let timestamp = timestamp
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| "-".to_string());
+            let mut lines = prompt.lines();
+            out.push_str(&format!("- {} {}\n", timestamp, lines.next().unwrap_or("")));
+            for line in lines {
+                if line.trim().is_empty() {
+                    out.push('\n');
+                } else {
+                    out.push_str(&format!("  {}\n", line));
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const SESSION: &str = r#"{"type":"user","timestamp":"2026-01-15T10:00:00Z","message":{"content":"fix the login"}}
+{"type":"assistant","timestamp":"2026-01-15T10:00:01Z","message":{"content":[{"type":"text","text":"Looking"},{"type":"tool_use","id":"t1","name":"Read","input":{"file_path":"/a"}}]}}
+{"type":"user","timestamp":"2026-01-15T10:00:02Z","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"}]}}
+{"type":"user","timestamp":"2026-01-15T10:05:00Z","message":{"content":"now the tests\nand lint"}}"#;
+
+    #[test]
+    fn digest_session_in_keeps_only_user_prompts() {
+        let tmp = TempDir::new().unwrap();
+        fs::create_dir_all(tmp.path().join("-p")).unwrap();
+        fs::write(tmp.path().join("-p").join("s1.jsonl"), SESSION).unwrap();
+
+        let digest = digest_session_in("-p", "s1", "Login fix", tmp.path()).unwrap();
+        let prompts: Vec<&str> = digest.prompts.iter().map(|(_, p)| p.as_str()).collect();
+        assert_eq!(prompts, vec!["fix the login", "now the tests\nand lint"]);
+        assert!(digest.prompts.iter().all(|(t, _)| t.is_some()));
+
+        let missing = digest_session_in("-p", "nope", "", tmp.path()).unwrap();
+        assert!(missing.prompts.is_empty());
+    }
+
+    #[test]
+    fn format_digest_markdown_orders_sessions_and_indents_continuations() {
+        let at = |s: &str| parser::parse_timestamp(Some(s));
+        let sessions = vec![
+            DigestSession {
+                session_id: "s2".to_string(),
+                title: "Later".to_string(),
+                prompts: vec![(at("2026-01-16T09:00:00Z"), "second\n\nmore".to_string())],
+            },
+            DigestSession {
+                session_id: "empty".to_string(),
+                title: "Nothing".to_string(),
+                prompts: Vec::new(),
+            },
+            DigestSession {
+                session_id: "s1".to_string(),
+                title: "Earlier\nsecond line".to_string(),
+                prompts: vec![(at("2026-01-15T10:00:00Z"), "first".to_string()), (None, "untimed".to_string())],
+            },
+        ];
+        assert_eq!(
+            format_digest_markdown("Prompts: -p", &sessions),
+            "# Prompts: -p\n\
+             \n## Earlier (s1)\n\n\
+             - 2026-01-15 10:00:00 first\n\
+             - - untimed\n\
+             \n## Later (s2)\n\n\
+             - 2026-01-16 09:00:00 second\n\
+             \n  more\n"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Syntax highlighting of code in the detail and file-change views.
+//!
+//! The bundled syntax and theme sets take a moment to load, so they are
+//! loaded once on first use.
+
+use ratatui::style::{Color, Modifier, Style};
+use std::path::Path;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+const THEME: &str = "base16-eighties.dark";
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEME_: OnceLock<Theme> = OnceLock::new();
+    THEME_.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults().themes;
+        themes.remove(THEME).unwrap_or_default()
+    })
+}
+
+/// Highlights the lines of one code block or file, fed in order.
+pub struct Highlighter {
+    lines: HighlightLines<'static>,
+}
+
+impl Highlighter {
+    /// For a fenced code block's language tag (`rust`, `py`, `sh`, ...);
+    /// None when the language is unknown.
+    pub fn for_lang(lang: &str) -> Option<Highlighter> {
+        let token = lang.split([',', ' ']).next().unwrap_or("").trim();
+        if token.is_empty() {
+            return None;
+        }
+        syntaxes().find_syntax_by_token(token).map(Highlighter::new)
+    }
+
+    /// For a file, by its extension.
+    pub fn for_path(path: &str) -> Option<Highlighter> {
+        let ext = Path::new(path).extension()?.to_str()?;
+        syntaxes().find_syntax_by_extension(ext).map(Highlighter::new)
+    }
+
+    fn new(syntax: &SyntaxReference) -> Highlighter {
+        Highlighter { lines: HighlightLines::new(syntax, theme()) }
+    }
+
+    /// Style of each char of `line`, over `base`. A line the grammar chokes
+    /// on keeps `base`.
+    pub fn line(&mut self, line: &str, base: Style) -> Vec<Style> {
+        let with_newline = format!("{}\n", line);
+        let mut styles = Vec::with_capacity(line.len());
+        match self.lines.highlight_line(&with_newline, syntaxes()) {
+            Ok(ranges) => {
+                for (style, text) in ranges {
+                    styles.extend(std::iter::repeat_n(base.patch(convert(style)), text.chars().count()));
+                }
+                styles.truncate(line.chars().count());
+            }
+            Err(_) => styles.clear(),
+        }
+        styles.resize(line.chars().count(), base);
+        styles
+    }
+}
+
+fn convert(style: syntect::highlighting::Style) -> Style {
+    let fg = style.foreground;
+    let mut out = Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        out = out.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        out = out.add_modifier(Modifier::ITALIC);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_known_languages_per_char() {
+        let mut hl = Highlighter::for_lang("rust").unwrap();
+        let line = "fn main() { let s = \"é\"; }";
+        let styles = hl.line(line, Style::default());
+        assert_eq!(styles.len(), line.chars().count());
+        // キーワードと文字列は別の色になる
+        assert_ne!(styles[0].fg, styles[21].fg);
+        assert!(styles.iter().all(|s| matches!(s.fg, Some(Color::Rgb(..)))));
+
+        assert!(Highlighter::for_lang("py").is_some());
+        assert!(Highlighter::for_lang("rust,ignore").is_some());
+        assert!(Highlighter::for_lang("no-such-language").is_none());
+        assert!(Highlighter::for_lang("").is_none());
+    }
+
+    #[test]
+    fn for_path_uses_the_extension() {
+        assert!(Highlighter::for_path("/src/lib.rs").is_some());
+        assert!(Highlighter::for_path("/Makefile.unknownext").is_none());
+        assert!(Highlighter::for_path("/no_extension").is_none());
+        assert!(Highlighter::for_path("/a.py").unwrap().line("", Style::default()).is_empty());
+    }
+}
@@ -1,5 +1,13 @@
+use crate::analysis;
+use crate::anonymize::Anonymizer;
+use crate::models::{ProjectInfo, TokenUsage, UsageSummary};
+use crate::parser;
 use anyhow::Result;
 use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
 use std::path::Path;
 
 pub struct SessionRecord {
@@ -13,6 +21,139 @@ pub struct SessionRecord {
     pub created_at: String,
     pub modified_at: String,
     pub file_mtime: i64,
+    /// Byte offset just past the last complete JSONL line that was indexed.
+    pub file_offset: i64,
+    pub usage: UsageSummary,
+    /// Models that answered in the session, in order of first use.
+    pub models: Vec<String>,
+    /// Session this one was resumed from, or empty.
+    pub parent_session_id: String,
+    pub stats: SessionStats,
+    /// Claude Code version and `userType` that wrote the session, or empty.
+    pub version: String,
+    pub user_type: String,
+    /// Label of the Claude root (see `parser::ClaudeRoot`) the file is under.
+    pub source_root: String,
+}
+
+/// How long a session ran and how big its file is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    /// Unix seconds of the first and last timestamped entry (0 = none).
+    pub first_timestamp: i64,
+    pub last_timestamp: i64,
+    /// Size of the `.jsonl` file in bytes.
+    pub file_size: i64,
+}
+
+impl SessionStats {
+    pub fn duration_secs(&self) -> i64 {
+        if self.first_timestamp == 0 {
+            0
+        } else {
+            (self.last_timestamp - self.first_timestamp).max(0)
+        }
+    }
+}
+
+/// What the index last saw of a session file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileState {
+    pub mtime: i64,
+    pub offset: i64,
+}
+
+/// A session whose tool calls touched a file, from `sessions_touching`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileTouch {
+    pub session_id: String,
+    pub project_path: String,
+    pub created_at: String,
+    pub file_path: String,
+    /// An Edit/Write tool changed the file, not just Read.
+    pub modified: bool,
+}
+
+/// What the agent did across a project's sessions, from `project_activity`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectActivity {
+    pub sessions: usize,
+    pub tool_calls: usize,
+    pub commands_run: usize,
+    /// Distinct files changed by an Edit/Write tool.
+    pub files_changed: usize,
+    /// Most-changed files with the number of sessions that changed each.
+    pub top_files: Vec<(String, usize)>,
+    /// Most-run normalized commands (see `parser::command_names`) with run counts.
+    pub top_commands: Vec<(String, usize)>,
+    /// Calls per tool, most used first.
+    pub tools: Vec<(String, usize)>,
+}
+
+/// What happened to a session in one index update.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Updated,
+    /// Its transcript disappeared (see `expire_missing`).
+    Expired,
+    /// The user deleted it from the viewer (see `forget_session`).
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Expired => "expired",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+
+    fn from_label(label: &str) -> ChangeKind {
+        match label {
+            "added" => ChangeKind::Added,
+            "expired" => ChangeKind::Expired,
+            "deleted" => ChangeKind::Deleted,
+            _ => ChangeKind::Updated,
+        }
+    }
+}
+
+/// One row of the `index_events` changefeed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexChange {
+    /// Unix seconds.
+    pub at: i64,
+    pub kind: ChangeKind,
+    pub session_id: String,
+    pub dir_name: String,
+}
+
+/// A message the user marked in the detail view, with their note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub session_id: String,
+    pub dir_name: String,
+    /// Position of the message in the parsed session, used when the uuid
+    /// does not match any more.
+    pub message_index: usize,
+    /// `uuid` of the JSONL entry the message came from; empty if it had none.
+    pub message_uuid: String,
+    pub note: String,
+    /// Unix seconds.
+    pub created_at: i64,
+}
+
+/// A version of a project's CLAUDE.md or settings file, as it was when the
+/// index was updated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstructionSnapshot {
+    pub path: String,
+    pub content: String,
+    /// Unix seconds.
+    pub captured_at: i64,
 }
 
 pub struct PromptRecord {
@@ -27,19 +168,371 @@ pub struct SearchableSession {
     pub git_branch: String,
     pub summary: String,
     pub created_at: String,
+    /// Unix seconds of the latest activity seen (0 = unknown), see `refresh_activity`.
+    pub last_activity: i64,
     pub prompts: Vec<String>,
+    pub parent_session_id: String,
+    /// The `.jsonl` file was deleted (e.g. by Claude Code's `cleanupPeriodDays`
+    /// cleanup); only the indexed prompts remain.
+    pub expired: bool,
+    /// Label of the Claude root the transcript was indexed from.
+    pub source_root: String,
+}
+
+/// One row of the `retention_journal`: what `apply-retention` did to a session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// Unix seconds.
+    pub at: i64,
+    /// `archived` or `failed`.
+    pub action: String,
+    pub session_id: String,
+    pub dir_name: String,
+    /// Where the transcript went, or why it could not be moved.
+    pub detail: String,
 }
 
 pub struct SessionIndex {
     conn: Connection,
 }
 
+/// Outcome of `SessionIndex::compact`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactStats {
+    /// Prompt and model rows removed because their session was gone.
+    pub orphans_removed: usize,
+    /// Bookmarks, favorites and tags of sessions that are not indexed, left
+    /// in place (see `compact`).
+    pub user_rows_kept: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Outcome of `SessionIndex::truncate_prompts`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TruncateStats {
+    pub prompts_truncated: usize,
+    /// Bytes of prompt text dropped (before any `compact`).
+    pub bytes_saved: u64,
+}
+
+impl CompactStats {
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Portable JSON form of the whole index, written by `export` and read by `import`.
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexDump {
+    schema_version: i64,
+    /// Format of the dump itself; bumped whenever it gains data, such as
+    /// the user's own tables, that a rebuild cannot restore. 0 for dumps
+    /// written before it was recorded.
+    #[serde(default)]
+    dump_version: i64,
+    projects: Vec<DumpedProject>,
+    sessions: Vec<DumpedSession>,
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+    #[serde(default)]
+    instruction_snapshots: Vec<InstructionSnapshot>,
+    #[serde(default)]
+    favorites: Vec<DumpedFavorite>,
+    #[serde(default)]
+    session_tags: Vec<DumpedTag>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DumpedTag {
+    session_id: String,
+    dir_name: String,
+    tag: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DumpedFavorite {
+    session_id: String,
+    dir_name: String,
+    created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DumpedProject {
+    dir_name: String,
+    original_path: String,
+    session_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DumpedSession {
+    session_id: String,
+    project_path: String,
+    dir_name: String,
+    git_branch: String,
+    summary: String,
+    first_prompt: String,
+    message_count: i64,
+    created_at: String,
+    modified_at: String,
+    file_mtime: i64,
+    file_offset: i64,
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_creation_tokens: u64,
+    cache_read_tokens: u64,
+    cost_usd: f64,
+    last_message_id: String,
+    #[serde(default)]
+    parent_session_id: String,
+    #[serde(default)]
+    expired: bool,
+    #[serde(default)]
+    first_timestamp: i64,
+    #[serde(default)]
+    last_timestamp: i64,
+    #[serde(default)]
+    file_size: i64,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    user_type: String,
+    #[serde(default)]
+    source_root: String,
+    models: Vec<String>,
+    prompts: Vec<DumpedPrompt>,
+    #[serde(default)]
+    files: Vec<DumpedFile>,
+    #[serde(default)]
+    tools: Vec<(String, usize)>,
+    #[serde(default)]
+    commands: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DumpedFile {
+    file_path: String,
+    tool: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DumpedPrompt {
+    prompt: String,
+    timestamp: Option<String>,
+}
+
+impl DumpedSession {
+    fn anonymize(&mut self, anon: &Anonymizer) {
+        self.project_path = anon.project(&self.project_path);
+        self.dir_name = anon.project(&self.dir_name);
+        self.git_branch = anon.text(&self.git_branch);
+        self.source_root = anon.text(&self.source_root);
+        self.summary = anon.text(&self.summary);
+        self.first_prompt = anon.text(&self.first_prompt);
+        for prompt in &mut self.prompts {
+            prompt.prompt = anon.text(&prompt.prompt);
+        }
+        for file in &mut self.files {
+            file.file_path = anon.text(&file.file_path);
+        }
+        for (command, _) in &mut self.commands {
+            *command = anon.text(command);
+        }
+    }
+
+    fn into_records(self) -> (SessionRecord, Vec<PromptRecord>) {
+        let prompts = self
+            .prompts
+            .into_iter()
+            .map(|p| PromptRecord {
+                prompt: p.prompt,
+                timestamp: p.timestamp,
+            })
+            .collect();
+        let record = SessionRecord {
+            session_id: self.session_id,
+            project_path: self.project_path,
+            dir_name: self.dir_name,
+            git_branch: self.git_branch,
+            summary: self.summary,
+            first_prompt: self.first_prompt,
+            message_count: self.message_count,
+            created_at: self.created_at,
+            modified_at: self.modified_at,
+            file_mtime: self.file_mtime,
+            file_offset: self.file_offset,
+            usage: UsageSummary {
+                usage: TokenUsage {
+                    input_tokens: self.input_tokens,
+                    output_tokens: self.output_tokens,
+                    cache_creation_tokens: self.cache_creation_tokens,
+                    cache_read_tokens: self.cache_read_tokens,
+                },
+                cost_usd: self.cost_usd,
+                last_message_id: self.last_message_id,
+            },
+            models: self.models,
+            parent_session_id: self.parent_session_id,
+            stats: SessionStats {
+                first_timestamp: self.first_timestamp,
+                last_timestamp: self.last_timestamp,
+                file_size: self.file_size,
+            },
+            version: self.version,
+            user_type: self.user_type,
+            source_root: self.source_root,
+        };
+        (record, prompts)
+    }
+}
+
+/// Environment variable holding the passphrase of an encrypted index.
+pub const INDEX_KEY_ENV: &str = "CC_SESSIONS_VIEWER_INDEX_KEY";
+
+/// Whether `err` means the database file itself is damaged (or not a
+/// database at all), as opposed to a failure that retrying could fix. A key
+/// that does not match the file is reported as `KeyMismatch` instead, so it
+/// never counts as damage.
+pub fn is_corruption(err: &anyhow::Error) -> bool {
+    !is_key_mismatch(err)
+        && err
+            .chain()
+            .filter_map(|e| e.downcast_ref::<rusqlite::Error>())
+            .any(|e| {
+                matches!(
+                    e.sqlite_error_code(),
+                    Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+                )
+            })
+}
+
+/// Whether `err` is a `KeyMismatch`.
+pub fn is_key_mismatch(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| e.is::<KeyMismatch>())
+}
+
+/// The index could not be read with the passphrase in `INDEX_KEY_ENV`: the
+/// key is wrong, missing for an encrypted index, or newly set on a plaintext
+/// one. The file is left untouched, since rebuilding it under the wrong key
+/// would lose everything only the index holds.
+#[derive(Debug)]
+pub struct KeyMismatch {
+    pub db_path: std::path::PathBuf,
+    pub key_set: bool,
+}
+
+impl std::fmt::Display for KeyMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let problem = if self.key_set { "wrong index key" } else { "missing index key" };
+        write!(
+            f,
+            "{}: {} cannot be read with {} as set; fix the key, or move the file aside to rebuild the index",
+            problem,
+            self.db_path.display(),
+            INDEX_KEY_ENV
+        )
+    }
+}
+
+impl std::error::Error for KeyMismatch {}
+
+/// Whether the start of an unreadable database file looks like SQLCipher
+/// output (uniformly random bytes) rather than a damaged plaintext file.
+fn looks_encrypted(db_path: &Path) -> bool {
+    use std::io::Read;
+    let mut page = Vec::new();
+    let read = File::open(db_path).and_then(|f| f.take(4096).read_to_end(&mut page));
+    if read.is_err() || page.len() < 1024 || page.starts_with(b"SQLite format 3\0") {
+        return false;
+    }
+    let distinct: HashSet<u8> = page.iter().copied().collect();
+    distinct.len() > 200
+}
+
 impl SessionIndex {
+    /// Move a damaged database aside to `<name>.corrupt-<timestamp>` and drop
+    /// its WAL files, so the next `open` starts from scratch. Earlier backups
+    /// are kept; returns where this one went.
+    pub fn discard(db_path: &Path) -> Result<std::path::PathBuf> {
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut backup = db_path.as_os_str().to_owned();
+        backup.push(format!(".corrupt-{}", stamp));
+        let mut backup = std::path::PathBuf::from(backup);
+        let mut n = 1;
+        while backup.exists() {
+            n += 1;
+            let mut next = db_path.as_os_str().to_owned();
+            next.push(format!(".corrupt-{}-{}", stamp, n));
+            backup = next.into();
+        }
+        std::fs::rename(db_path, &backup)?;
+        for suffix in ["-wal", "-shm"] {
+            let mut path = db_path.as_os_str().to_owned();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(backup)
+    }
+
+    /// Copy the rows a rebuild cannot restore (bookmarks, favorites, tags and
+    /// instruction snapshots) from the damaged database at `backup` into this
+    /// one, table by table, skipping whatever cannot be read. Returns the
+    /// number of rows copied.
+    pub fn salvage_user_data(&self, backup: &Path) -> usize {
+        if self.conn.execute("ATTACH DATABASE ?1 AS damaged", [backup.to_string_lossy()]).is_err() {
+            return 0;
+        }
+        let mut copied = 0;
+        for (table, columns) in [
+            ("bookmarks", "session_id, dir_name, message_index, message_uuid, note, created_at"),
+            ("favorites", "session_id, dir_name, created_at"),
+            ("session_tags", "session_id, dir_name, tag"),
+            ("instruction_snapshots", "path, hash, content, captured_at"),
+        ] {
+            let sql = format!("INSERT OR IGNORE INTO main.{table} ({columns}) SELECT {columns} FROM damaged.{table}");
+            copied += self.conn.execute(&sql, []).unwrap_or(0);
+        }
+        let _ = self.conn.execute("DETACH DATABASE damaged", []);
+        copied
+    }
+
+    /// Open (creating if needed) the index at `db_path`, encrypted with the
+    /// passphrase in `INDEX_KEY_ENV` when that is set.
     pub fn open(db_path: &Path) -> Result<Self> {
+        let key = std::env::var(INDEX_KEY_ENV).ok().filter(|k| !k.is_empty());
+        SessionIndex::open_with_key(db_path, key.as_deref())
+    }
+
+    /// Open the index, encrypting it with SQLCipher under `key`. A key that
+    /// does not match the file (or no key for a file that looks encrypted)
+    /// fails with `KeyMismatch` rather than as a corrupted database. Builds
+    /// without the `encryption` feature refuse a key rather than silently
+    /// writing plaintext.
+    pub fn open_with_key(db_path: &Path, key: Option<&str>) -> Result<Self> {
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)?;
         }
         let conn = Connection::open(db_path)?;
+        if let Some(key) = key {
+            #[cfg(feature = "encryption")]
+            conn.pragma_update(None, "key", key)?;
+            #[cfg(not(feature = "encryption"))]
+            {
+                let _ = key;
+                anyhow::bail!("{} is set, but this build lacks the `encryption` feature", INDEX_KEY_ENV);
+            }
+        }
+        // 鍵の不一致は壊れたファイルと同じエラーになるので、作り直す前にここで見分ける
+        if let Err(e) = conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+            let e = anyhow::Error::from(e);
+            if is_corruption(&e) && (key.is_some() || looks_encrypted(db_path)) {
+                return Err(KeyMismatch { db_path: db_path.to_path_buf(), key_set: key.is_some() }.into());
+            }
+            return Err(e);
+        }
+        // WAL lets the UI read while the indexer writes, and NORMAL sync only
+        // fsyncs at checkpoints instead of on every commit.
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
         conn.execute_batch(
             "
             CREATE TABLE IF NOT EXISTS sessions (
@@ -52,7 +545,24 @@ impl SessionIndex {
                 message_count INTEGER DEFAULT 0,
                 created_at    TEXT DEFAULT '',
                 modified_at   TEXT DEFAULT '',
-                file_mtime    INTEGER DEFAULT 0
+                file_mtime    INTEGER DEFAULT 0,
+                file_offset   INTEGER DEFAULT 0,
+                input_tokens          INTEGER DEFAULT 0,
+                output_tokens         INTEGER DEFAULT 0,
+                cache_creation_tokens INTEGER DEFAULT 0,
+                cache_read_tokens     INTEGER DEFAULT 0,
+                cost_usd              REAL DEFAULT 0,
+                last_message_id       TEXT DEFAULT '',
+                parent_session_id     TEXT DEFAULT '',
+                expired               INTEGER DEFAULT 0,
+                first_timestamp       INTEGER DEFAULT 0,
+                last_timestamp        INTEGER DEFAULT 0,
+                duration_secs         INTEGER DEFAULT 0,
+                file_size             INTEGER DEFAULT 0,
+                version               TEXT DEFAULT '',
+                user_type             TEXT DEFAULT '',
+                source_root           TEXT DEFAULT '',
+                last_activity         INTEGER DEFAULT 0
             );
             CREATE TABLE IF NOT EXISTS user_prompts (
                 id         INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -61,15 +571,123 @@ impl SessionIndex {
                 timestamp  TEXT,
                 UNIQUE(session_id, prompt, timestamp)
             );
+            CREATE TABLE IF NOT EXISTS session_models (
+                session_id TEXT NOT NULL REFERENCES sessions(session_id),
+                model      TEXT NOT NULL,
+                position   INTEGER NOT NULL,
+                UNIQUE(session_id, model)
+            );
+            CREATE INDEX IF NOT EXISTS idx_session_models_model ON session_models(model);
+            CREATE TABLE IF NOT EXISTS session_files (
+                session_id TEXT NOT NULL REFERENCES sessions(session_id),
+                file_path  TEXT NOT NULL,
+                tool       TEXT NOT NULL,
+                UNIQUE(session_id, file_path, tool)
+            );
+            CREATE INDEX IF NOT EXISTS idx_session_files_path ON session_files(file_path);
+            CREATE TABLE IF NOT EXISTS session_tools (
+                session_id TEXT NOT NULL REFERENCES sessions(session_id),
+                tool       TEXT NOT NULL,
+                count      INTEGER NOT NULL,
+                UNIQUE(session_id, tool)
+            );
+            CREATE INDEX IF NOT EXISTS idx_session_tools_tool ON session_tools(tool);
+            CREATE TABLE IF NOT EXISTS session_commands (
+                session_id TEXT NOT NULL REFERENCES sessions(session_id),
+                command    TEXT NOT NULL,
+                count      INTEGER NOT NULL,
+                UNIQUE(session_id, command)
+            );
+            CREATE INDEX IF NOT EXISTS idx_session_commands_command ON session_commands(command);
+            CREATE TABLE IF NOT EXISTS index_events (
+                id         INTEGER PRIMARY KEY AUTOINCREMENT,
+                at         INTEGER NOT NULL,
+                kind       TEXT NOT NULL,
+                session_id TEXT NOT NULL,
+                dir_name   TEXT DEFAULT ''
+            );
+            CREATE TABLE IF NOT EXISTS retention_journal (
+                id         INTEGER PRIMARY KEY AUTOINCREMENT,
+                at         INTEGER NOT NULL,
+                action     TEXT NOT NULL,
+                session_id TEXT NOT NULL,
+                dir_name   TEXT DEFAULT '',
+                detail     TEXT DEFAULT ''
+            );
+            CREATE TABLE IF NOT EXISTS bookmarks (
+                session_id    TEXT NOT NULL,
+                dir_name      TEXT DEFAULT '',
+                message_index INTEGER NOT NULL,
+                message_uuid  TEXT DEFAULT '',
+                note          TEXT DEFAULT '',
+                created_at    INTEGER NOT NULL,
+                UNIQUE(session_id, message_index)
+            );
+            CREATE TABLE IF NOT EXISTS favorites (
+                session_id TEXT PRIMARY KEY,
+                dir_name   TEXT DEFAULT '',
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS session_tags (
+                session_id TEXT NOT NULL,
+                dir_name   TEXT DEFAULT '',
+                tag        TEXT NOT NULL,
+                UNIQUE(session_id, tag)
+            );
+            CREATE TABLE IF NOT EXISTS instruction_snapshots (
+                path        TEXT NOT NULL,
+                hash        TEXT NOT NULL,
+                content     TEXT NOT NULL,
+                captured_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_instruction_snapshots_path ON instruction_snapshots(path, captured_at);
+            CREATE TABLE IF NOT EXISTS projects (
+                dir_name      TEXT PRIMARY KEY,
+                original_path TEXT NOT NULL,
+                session_count INTEGER DEFAULT 0
+            );
         ",
         )?;
+        for (column, decl) in [
+            ("file_offset", "INTEGER DEFAULT 0"),
+            ("input_tokens", "INTEGER DEFAULT 0"),
+            ("output_tokens", "INTEGER DEFAULT 0"),
+            ("cache_creation_tokens", "INTEGER DEFAULT 0"),
+            ("cache_read_tokens", "INTEGER DEFAULT 0"),
+            ("cost_usd", "REAL DEFAULT 0"),
+            ("last_message_id", "TEXT DEFAULT ''"),
+            ("parent_session_id", "TEXT DEFAULT ''"),
+            ("expired", "INTEGER DEFAULT 0"),
+            ("first_timestamp", "INTEGER DEFAULT 0"),
+            ("last_timestamp", "INTEGER DEFAULT 0"),
+            ("duration_secs", "INTEGER DEFAULT 0"),
+            ("file_size", "INTEGER DEFAULT 0"),
+            ("version", "TEXT DEFAULT ''"),
+            ("user_type", "TEXT DEFAULT ''"),
+            ("source_root", "TEXT DEFAULT ''"),
+        ] {
+            ensure_column(&conn, "sessions", column, decl)?;
+        }
+        // 古い索引には数値の時刻が無いので、保存済みの文字列と時刻から埋める
+        if ensure_column(&conn, "sessions", "last_activity", "INTEGER DEFAULT 0")? {
+            refresh_activity(&conn, None)?;
+        }
+        reindex_if_outdated(&conn)?;
         Ok(SessionIndex { conn })
     }
 
+    /// Batch the following writes into one transaction. It is rolled back if
+    /// dropped without `commit`.
+    pub fn transaction(&self) -> Result<rusqlite::Transaction<'_>> {
+        Ok(self.conn.unchecked_transaction()?)
+    }
+
     pub fn upsert_session(&self, rec: &SessionRecord) -> Result<()> {
         self.conn.execute(
-            "INSERT INTO sessions (session_id, project_path, dir_name, git_branch, summary, first_prompt, message_count, created_at, modified_at, file_mtime)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            "INSERT INTO sessions (session_id, project_path, dir_name, git_branch, summary, first_prompt, message_count, created_at, modified_at, file_mtime, file_offset,
+                                   input_tokens, output_tokens, cache_creation_tokens, cache_read_tokens, cost_usd, last_message_id, parent_session_id, expired,
+                                   first_timestamp, last_timestamp, duration_secs, file_size, version, user_type, source_root)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, 0, ?19, ?20, ?21, ?22, ?23, ?24, ?25)
              ON CONFLICT(session_id) DO UPDATE SET
                 project_path = excluded.project_path,
                 dir_name = excluded.dir_name,
@@ -79,7 +697,23 @@ impl SessionIndex {
                 message_count = excluded.message_count,
                 created_at = excluded.created_at,
                 modified_at = excluded.modified_at,
-                file_mtime = excluded.file_mtime",
+                file_mtime = excluded.file_mtime,
+                file_offset = excluded.file_offset,
+                input_tokens = excluded.input_tokens,
+                output_tokens = excluded.output_tokens,
+                cache_creation_tokens = excluded.cache_creation_tokens,
+                cache_read_tokens = excluded.cache_read_tokens,
+                cost_usd = excluded.cost_usd,
+                last_message_id = excluded.last_message_id,
+                parent_session_id = excluded.parent_session_id,
+                expired = 0,
+                first_timestamp = excluded.first_timestamp,
+                last_timestamp = excluded.last_timestamp,
+                duration_secs = excluded.duration_secs,
+                file_size = excluded.file_size,
+                version = excluded.version,
+                user_type = excluded.user_type,
+                source_root = excluded.source_root",
             rusqlite::params![
                 rec.session_id,
                 rec.project_path,
@@ -91,11 +725,207 @@ impl SessionIndex {
                 rec.created_at,
                 rec.modified_at,
                 rec.file_mtime,
+                rec.file_offset,
+                rec.usage.usage.input_tokens as i64,
+                rec.usage.usage.output_tokens as i64,
+                rec.usage.usage.cache_creation_tokens as i64,
+                rec.usage.usage.cache_read_tokens as i64,
+                rec.usage.cost_usd,
+                rec.usage.last_message_id,
+                rec.parent_session_id,
+                rec.stats.first_timestamp,
+                rec.stats.last_timestamp,
+                rec.stats.duration_secs(),
+                rec.stats.file_size,
+                rec.version,
+                rec.user_type,
+                rec.source_root,
             ],
         )?;
+        self.conn
+            .execute("DELETE FROM session_models WHERE session_id = ?1", [&rec.session_id])?;
+        let mut stmt = self.conn.prepare(
+            "INSERT OR IGNORE INTO session_models (session_id, model, position) VALUES (?1, ?2, ?3)",
+        )?;
+        for (position, model) in rec.models.iter().enumerate() {
+            stmt.execute(rusqlite::params![rec.session_id, model, position as i64])?;
+        }
+        Ok(())
+    }
+
+    pub fn get_models(&self, session_id: &str) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT model FROM session_models WHERE session_id = ?1 ORDER BY position")?;
+        let models = stmt
+            .query_map([session_id], |row| row.get(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(models)
+    }
+
+    /// Ids of sessions that used a model whose name contains `pattern`
+    /// (e.g. "opus"), case-insensitively.
+    pub fn sessions_using_model(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT session_id FROM session_models WHERE model LIKE '%' || ?1 || '%' ORDER BY session_id",
+        )?;
+        let ids = stmt
+            .query_map([pattern], |row| row.get(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(ids)
+    }
+
+    /// Replace the per-tool call counts of a session.
+    pub fn insert_tool_counts(&self, session_id: &str, counts: &[(String, usize)]) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM session_tools WHERE session_id = ?1", [session_id])?;
+        self.append_tool_counts(session_id, counts)
+    }
+
+    /// Add the calls made in newly appended lines to the stored counts.
+    pub fn append_tool_counts(&self, session_id: &str, counts: &[(String, usize)]) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "INSERT INTO session_tools (session_id, tool, count) VALUES (?1, ?2, ?3)
+             ON CONFLICT(session_id, tool) DO UPDATE SET count = count + excluded.count",
+        )?;
+        for (tool, count) in counts {
+            stmt.execute(rusqlite::params![session_id, tool, *count as i64])?;
+        }
+        Ok(())
+    }
+
+    /// Calls per tool in a session, most used first.
+    pub fn get_tool_counts(&self, session_id: &str) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tool, count FROM session_tools WHERE session_id = ?1 ORDER BY count DESC, tool",
+        )?;
+        let counts = stmt
+            .query_map([session_id], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(counts)
+    }
+
+    /// Replace the per-command run counts of a session.
+    pub fn insert_command_counts(&self, session_id: &str, counts: &[(String, usize)]) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM session_commands WHERE session_id = ?1", [session_id])?;
+        self.append_command_counts(session_id, counts)
+    }
+
+    /// Add the commands run in newly appended lines to the stored counts.
+    pub fn append_command_counts(&self, session_id: &str, counts: &[(String, usize)]) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "INSERT INTO session_commands (session_id, command, count) VALUES (?1, ?2, ?3)
+             ON CONFLICT(session_id, command) DO UPDATE SET count = count + excluded.count",
+        )?;
+        for (command, count) in counts {
+            stmt.execute(rusqlite::params![session_id, command, *count as i64])?;
+        }
         Ok(())
     }
 
+    /// Runs per command in a session, most run first.
+    pub fn get_command_counts(&self, session_id: &str) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT command, count FROM session_commands WHERE session_id = ?1 ORDER BY count DESC, command",
+        )?;
+        let counts = stmt
+            .query_map([session_id], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(counts)
+    }
+
+    /// Roll the tool, file and command tables up over the sessions of the
+    /// project `dir_name` last active after `since` and before `until` (unix
+    /// seconds; None for an open end), keeping the `limit` most-changed files
+    /// and most-run commands.
+    pub fn project_activity(
+        &self,
+        dir_name: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+        limit: usize,
+    ) -> Result<ProjectActivity> {
+        const IN_RANGE: &str = "SELECT session_id FROM sessions WHERE dir_name = ?1
+            AND (?2 IS NULL OR last_timestamp > ?2) AND (?3 IS NULL OR last_timestamp < ?3)";
+        let params = rusqlite::params![dir_name, since, until];
+        let count_pairs = |sql: &str, params: &[&dyn rusqlite::ToSql]| -> Result<Vec<(String, usize)>> {
+            let mut stmt = self.conn.prepare(sql)?;
+            let pairs = stmt
+                .query_map(params, |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?
+                .filter_map(|r| r.ok())
+                .collect();
+            Ok(pairs)
+        };
+        let modifying: Vec<String> =
+            parser::FILE_MODIFYING_TOOLS.iter().map(|t| format!("'{}'", t)).collect();
+        let changed_files = format!(
+            "FROM session_files WHERE session_id IN ({}) AND tool IN ({})",
+            IN_RANGE,
+            modifying.join(", ")
+        );
+
+        let sessions: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM ({})", IN_RANGE),
+            params,
+            |row| row.get(0),
+        )?;
+        let files_changed: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(DISTINCT file_path) {}", changed_files),
+            params,
+            |row| row.get(0),
+        )?;
+        let tools = count_pairs(
+            &format!(
+                "SELECT tool, SUM(count) AS n FROM session_tools WHERE session_id IN ({})
+                 GROUP BY tool ORDER BY n DESC, tool",
+                IN_RANGE
+            ),
+            params,
+        )?;
+        let commands = count_pairs(
+            &format!(
+                "SELECT command, SUM(count) AS n FROM session_commands WHERE session_id IN ({})
+                 GROUP BY command ORDER BY n DESC, command",
+                IN_RANGE
+            ),
+            params,
+        )?;
+        let top_files = count_pairs(
+            &format!(
+                "SELECT file_path, COUNT(DISTINCT session_id) AS n {}
+                 GROUP BY file_path ORDER BY n DESC, file_path LIMIT {}",
+                changed_files, limit
+            ),
+            params,
+        )?;
+        Ok(ProjectActivity {
+            sessions: sessions as usize,
+            tool_calls: tools.iter().map(|(_, n)| n).sum(),
+            commands_run: commands.iter().map(|(_, n)| n).sum(),
+            files_changed: files_changed as usize,
+            top_files,
+            top_commands: commands.into_iter().take(limit).collect(),
+            tools,
+        })
+    }
+
+    /// Sessions that called `tool` (exact name, e.g. `WebFetch`) at least once.
+    pub fn sessions_using_tool(&self, tool: &str) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT session_id FROM session_tools WHERE tool = ?1 ORDER BY session_id")?;
+        let ids = stmt
+            .query_map([tool], |row| row.get(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(ids)
+    }
+
     pub fn insert_prompts(&self, session_id: &str, prompts: &[PromptRecord]) -> Result<()> {
         self.conn
             .execute("DELETE FROM user_prompts WHERE session_id = ?1", [session_id])?;
@@ -108,6 +938,185 @@ impl SessionIndex {
         Ok(())
     }
 
+    /// Add prompts parsed from newly appended lines, keeping existing ones.
+    /// Lines that were already indexed are ignored by the UNIQUE constraint.
+    pub fn append_prompts(&self, session_id: &str, prompts: &[PromptRecord]) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "INSERT OR IGNORE INTO user_prompts (session_id, prompt, timestamp) VALUES (?1, ?2, ?3)",
+        )?;
+        for p in prompts {
+            stmt.execute(rusqlite::params![session_id, p.prompt, p.timestamp])?;
+        }
+        Ok(())
+    }
+
+    /// Replace the `(file_path, tool)` pairs recorded for a session.
+    pub fn insert_files(&self, session_id: &str, files: &[(String, String)]) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM session_files WHERE session_id = ?1", [session_id])?;
+        self.append_files(session_id, files)
+    }
+
+    /// Add files touched in newly appended lines, keeping existing ones.
+    pub fn append_files(&self, session_id: &str, files: &[(String, String)]) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "INSERT OR IGNORE INTO session_files (session_id, file_path, tool) VALUES (?1, ?2, ?3)",
+        )?;
+        for (path, tool) in files {
+            stmt.execute(rusqlite::params![session_id, path, tool])?;
+        }
+        Ok(())
+    }
+
+    /// `(file_path, tool)` pairs recorded for a session.
+    pub fn get_files(&self, session_id: &str) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT file_path, tool FROM session_files WHERE session_id = ?1 ORDER BY rowid")?;
+        let files = stmt
+            .query_map([session_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(files)
+    }
+
+    /// Sessions that touched `path`, newest first. `path` matches a stored
+    /// path exactly or as a trailing component sequence, so `src/app.rs`
+    /// finds `/home/me/proj/src/app.rs`. With `modified_only`, sessions
+    /// that only read the file are left out.
+    pub fn sessions_touching(&self, path: &str, modified_only: bool) -> Result<Vec<FileTouch>> {
+        let modifying: Vec<String> =
+            parser::FILE_MODIFYING_TOOLS.iter().map(|t| format!("'{}'", t)).collect();
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT f.session_id, s.project_path, s.created_at, f.file_path, MAX(f.tool IN ({})) AS modified
+             FROM session_files f JOIN sessions s ON s.session_id = f.session_id
+             WHERE f.file_path = ?1 OR substr(f.file_path, -length(?1) - 1) = '/' || ?1
+             GROUP BY f.session_id, f.file_path
+             HAVING ?2 = 0 OR modified = 1
+             ORDER BY s.created_at DESC, f.session_id",
+            modifying.join(", ")
+        ))?;
+        let touches = stmt
+            .query_map(rusqlite::params![path.trim_start_matches("./"), modified_only], |row| {
+                Ok(FileTouch {
+                    session_id: row.get(0)?,
+                    project_path: row.get(1)?,
+                    created_at: row.get(2)?,
+                    file_path: row.get(3)?,
+                    modified: row.get(4)?,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(touches)
+    }
+
+    pub fn get_file_state(&self, session_id: &str) -> Result<Option<FileState>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT file_mtime, file_offset FROM sessions WHERE session_id = ?1")?;
+        let mut rows = stmt.query([session_id])?;
+        if let Some(row) = rows.next()? {
+            Ok(Some(FileState {
+                mtime: row.get(0)?,
+                offset: row.get(1)?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_usage(&self, session_id: &str) -> Result<Option<UsageSummary>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT input_tokens, output_tokens, cache_creation_tokens, cache_read_tokens, cost_usd, last_message_id
+             FROM sessions WHERE session_id = ?1",
+        )?;
+        let mut rows = stmt.query([session_id])?;
+        if let Some(row) = rows.next()? {
+            Ok(Some(UsageSummary {
+                usage: TokenUsage {
+                    input_tokens: row.get::<_, i64>(0)? as u64,
+                    output_tokens: row.get::<_, i64>(1)? as u64,
+                    cache_creation_tokens: row.get::<_, i64>(2)? as u64,
+                    cache_read_tokens: row.get::<_, i64>(3)? as u64,
+                },
+                cost_usd: row.get(4)?,
+                last_message_id: row.get(5)?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_stats(&self, session_id: &str) -> Result<Option<SessionStats>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT first_timestamp, last_timestamp, file_size FROM sessions WHERE session_id = ?1",
+        )?;
+        let mut rows = stmt.query([session_id])?;
+        if let Some(row) = rows.next()? {
+            Ok(Some(SessionStats {
+                first_timestamp: row.get(0)?,
+                last_timestamp: row.get(1)?,
+                file_size: row.get(2)?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Every distinct project path of the indexed sessions.
+    pub fn project_paths(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT DISTINCT project_path FROM sessions")?;
+        let paths = stmt.query_map([], |row| row.get(0))?.filter_map(|r| r.ok()).collect();
+        Ok(paths)
+    }
+
+    /// Last message time (unix seconds) of each live session of a project.
+    pub fn last_activity(&self, project_path: &str) -> Result<Vec<i64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT last_timestamp FROM sessions WHERE project_path = ?1 AND expired = 0 AND last_timestamp > 0",
+        )?;
+        let times = stmt.query_map([project_path], |row| row.get(0))?.filter_map(|r| r.ok()).collect();
+        Ok(times)
+    }
+
+    pub fn get_first_prompt(&self, session_id: &str) -> Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT first_prompt FROM sessions WHERE session_id = ?1")?;
+        let mut rows = stmt.query([session_id])?;
+        if let Some(row) = rows.next()? {
+            Ok(Some(row.get(0)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_parent_session_id(&self, session_id: &str) -> Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT parent_session_id FROM sessions WHERE session_id = ?1")?;
+        let mut rows = stmt.query([session_id])?;
+        if let Some(row) = rows.next()? {
+            Ok(Some(row.get(0)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Stored Claude Code version and `userType` of a session.
+    pub fn get_client_info(&self, session_id: &str) -> Result<Option<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT version, user_type FROM sessions WHERE session_id = ?1")?;
+        let mut rows = stmt.query([session_id])?;
+        if let Some(row) = rows.next()? {
+            Ok(Some((row.get(0)?, row.get(1)?)))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn get_file_mtime(&self, session_id: &str) -> Result<Option<i64>> {
         let mut stmt = self
             .conn
@@ -120,46 +1129,648 @@ impl SessionIndex {
         }
     }
 
+    /// Update the session's `last_activity` once its record and prompts are
+    /// stored.
+    pub fn refresh_activity(&self, session_id: &str) -> Result<()> {
+        refresh_activity(&self.conn, Some(session_id))
+    }
+
     pub fn search_all(&self) -> Result<Vec<SearchableSession>> {
+        self.searchable_sessions(None)
+    }
+
+    /// Searchable sessions of one project directory.
+    pub fn search_project(&self, dir_name: &str) -> Result<Vec<SearchableSession>> {
+        self.searchable_sessions(Some(dir_name))
+    }
+
+    fn searchable_sessions(&self, dir_name: Option<&str>) -> Result<Vec<SearchableSession>> {
         let mut sessions_stmt = self.conn.prepare(
-            "SELECT session_id, project_path, dir_name, git_branch, summary, created_at FROM sessions ORDER BY created_at DESC",
+            "SELECT session_id, project_path, dir_name, git_branch, summary, created_at, parent_session_id, expired, source_root, last_activity
+             FROM sessions WHERE ?1 IS NULL OR dir_name = ?1 ORDER BY last_activity DESC, session_id",
         )?;
         let mut prompts_stmt = self
             .conn
             .prepare("SELECT prompt FROM user_prompts WHERE session_id = ?1 ORDER BY id")?;
 
         let mut results = Vec::new();
-        let session_rows = sessions_stmt.query_map([], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-                row.get::<_, String>(4)?,
-                row.get::<_, String>(5)?,
-            ))
+        let session_rows = sessions_stmt.query_map([dir_name], |row| {
+            Ok(SearchableSession {
+                session_id: row.get(0)?,
+                project_path: row.get(1)?,
+                dir_name: row.get(2)?,
+                git_branch: row.get(3)?,
+                summary: row.get(4)?,
+                created_at: row.get(5)?,
+                prompts: Vec::new(),
+                parent_session_id: row.get(6)?,
+                expired: row.get(7)?,
+                source_root: row.get(8)?,
+                last_activity: row.get(9)?,
+            })
         })?;
 
         for session_row in session_rows {
-            let (session_id, project_path, dir_name, git_branch, summary, created_at) =
-                session_row?;
-            let prompts: Vec<String> = prompts_stmt
-                .query_map([&session_id], |row| row.get(0))?
+            let mut session = session_row?;
+            session.prompts = prompts_stmt
+                .query_map([&session.session_id], |row| row.get(0))?
+                .filter_map(|r| r.ok())
+                .collect();
+            results.push(session);
+        }
+
+        Ok(results)
+    }
+
+    /// Replace the stored project list with the result of a full scan.
+    pub fn replace_projects(&self, projects: &[ProjectInfo]) -> Result<()> {
+        self.conn.execute("DELETE FROM projects", [])?;
+        let mut stmt = self.conn.prepare(
+            "INSERT INTO projects (dir_name, original_path, session_count) VALUES (?1, ?2, ?3)",
+        )?;
+        for p in projects {
+            stmt.execute(rusqlite::params![p.dir_name, p.original_path, p.session_count as i64])?;
+        }
+        Ok(())
+    }
+
+    pub fn list_projects(&self) -> Result<Vec<ProjectInfo>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT dir_name, original_path, session_count FROM projects ORDER BY dir_name",
+        )?;
+        let projects = stmt
+            .query_map([], |row| {
+                Ok(ProjectInfo {
+                    dir_name: row.get(0)?,
+                    original_path: row.get(1)?,
+                    session_count: row.get::<_, i64>(2)? as usize,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(projects)
+    }
+
+    /// Flag sessions whose id is not in `existing`, i.e. whose `.jsonl` file
+    /// is gone, as expired. Their prompts stay searchable; the file mtime is
+    /// reset so a restored file is parsed again. Returns the number newly flagged.
+    pub fn expire_missing(&self, existing: &HashSet<String>) -> Result<usize> {
+        let mut stmt = self
+            .conn
+            .prepare("UPDATE sessions SET expired = 1, file_mtime = -1, file_offset = 0 WHERE session_id = ?1 AND expired = 0")?;
+        let mut expired = 0;
+        for id in self.all_session_ids()? {
+            if !existing.contains(&id) && stmt.execute([&id])? > 0 {
+                let dir_name: String =
+                    self.conn.query_row("SELECT dir_name FROM sessions WHERE session_id = ?1", [&id], |row| row.get(0))?;
+                self.record_change(ChangeKind::Expired, &id, &dir_name)?;
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Drop a session the user deleted, with everything stored about it, and
+    /// log it as `Deleted`, so the next run does not take it for one that
+    /// Claude Code's cleanup expired.
+    pub fn forget_session(&self, session_id: &str, dir_name: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for table in [
+            "user_prompts",
+            "session_models",
+            "session_files",
+            "session_tools",
+            "session_commands",
+            "bookmarks",
+            "favorites",
+            "session_tags",
+            "sessions",
+        ] {
+            tx.execute(&format!("DELETE FROM {} WHERE session_id = ?1", table), [session_id])?;
+        }
+        self.record_change(ChangeKind::Deleted, session_id, dir_name)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Append to the `index_events` changefeed, stamped with the current time.
+    pub fn record_change(&self, kind: ChangeKind, session_id: &str, dir_name: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO index_events (at, kind, session_id, dir_name) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![chrono::Utc::now().timestamp(), kind.label(), session_id, dir_name],
+        )?;
+        Ok(())
+    }
+
+    /// The `limit` latest changes, newest first.
+    pub fn recent_changes(&self, limit: usize) -> Result<Vec<IndexChange>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT at, kind, session_id, dir_name FROM index_events ORDER BY id DESC LIMIT ?1")?;
+        let changes = stmt
+            .query_map([limit as i64], |row| {
+                Ok(IndexChange {
+                    at: row.get(0)?,
+                    kind: ChangeKind::from_label(&row.get::<_, String>(1)?),
+                    session_id: row.get(2)?,
+                    dir_name: row.get(3)?,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(changes)
+    }
+
+    /// Drop all but the `keep` latest changes so the changefeed stays small.
+    pub fn trim_changes(&self, keep: usize) -> Result<usize> {
+        let removed = self.conn.execute(
+            "DELETE FROM index_events WHERE id <= (SELECT MAX(id) FROM index_events) - ?1",
+            [keep as i64],
+        )?;
+        Ok(removed)
+    }
+
+    /// Save `bookmark`, replacing the one on the same message.
+    pub fn add_bookmark(&self, bookmark: &Bookmark) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO bookmarks (session_id, dir_name, message_index, message_uuid, note, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                bookmark.session_id,
+                bookmark.dir_name,
+                bookmark.message_index as i64,
+                bookmark.message_uuid,
+                bookmark.note,
+                bookmark.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_bookmark(&self, session_id: &str, message_index: usize) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM bookmarks WHERE session_id = ?1 AND message_index = ?2",
+            rusqlite::params![session_id, message_index as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Bookmarks of one session in message order.
+    pub fn session_bookmarks(&self, session_id: &str) -> Result<Vec<Bookmark>> {
+        self.query_bookmarks("WHERE session_id = ?1 ORDER BY message_index", [session_id])
+    }
+
+    /// Bookmarks of every session, newest first.
+    pub fn bookmarks(&self) -> Result<Vec<Bookmark>> {
+        self.query_bookmarks("ORDER BY created_at DESC, rowid DESC", [])
+    }
+
+    fn query_bookmarks(&self, clause: &str, params: impl rusqlite::Params) -> Result<Vec<Bookmark>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT session_id, dir_name, message_index, message_uuid, note, created_at FROM bookmarks {}",
+            clause
+        ))?;
+        let bookmarks = stmt
+            .query_map(params, |row| {
+                Ok(Bookmark {
+                    session_id: row.get(0)?,
+                    dir_name: row.get(1)?,
+                    message_index: row.get::<_, i64>(2)? as usize,
+                    message_uuid: row.get(3)?,
+                    note: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(bookmarks)
+    }
+
+    /// Mark `session_id` of the project `dir_name` as a favorite.
+    pub fn add_favorite(&self, session_id: &str, dir_name: &str, created_at: i64) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO favorites (session_id, dir_name, created_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![session_id, dir_name, created_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_favorite(&self, session_id: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM favorites WHERE session_id = ?1", [session_id])?;
+        Ok(())
+    }
+
+    /// Ids of every favorite session.
+    pub fn favorites(&self) -> Result<HashSet<String>> {
+        let mut stmt = self.conn.prepare("SELECT session_id FROM favorites")?;
+        let ids = stmt.query_map([], |row| row.get(0))?.filter_map(|r| r.ok()).collect();
+        Ok(ids)
+    }
+
+    /// Replace the tags of `session_id` of the project `dir_name` with `tags`.
+    pub fn set_session_tags(&self, session_id: &str, dir_name: &str, tags: &[String]) -> Result<()> {
+        let tx = self.transaction()?;
+        self.conn.execute("DELETE FROM session_tags WHERE session_id = ?1", [session_id])?;
+        {
+            let mut stmt = self
+                .conn
+                .prepare("INSERT OR IGNORE INTO session_tags (session_id, dir_name, tag) VALUES (?1, ?2, ?3)")?;
+            for tag in tags {
+                stmt.execute(rusqlite::params![session_id, dir_name, tag])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The tags of every tagged session, each sorted.
+    pub fn session_tags(&self) -> Result<HashMap<String, Vec<String>>> {
+        let mut stmt = self.conn.prepare("SELECT session_id, tag FROM session_tags ORDER BY session_id, tag")?;
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        for (session_id, tag) in rows.filter_map(|r| r.ok()) {
+            tags.entry(session_id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+
+    /// Store `content` of the file at `path` unless it is what was stored
+    /// for it last. Returns whether a new version was stored.
+    pub fn record_instruction_file(&self, path: &str, content: &str, at: i64) -> Result<bool> {
+        let hash = format!("{:016x}", analysis::stable_hash(content));
+        let mut stmt = self.conn.prepare(
+            "SELECT hash FROM instruction_snapshots WHERE path = ?1 ORDER BY captured_at DESC, rowid DESC LIMIT 1",
+        )?;
+        let mut rows = stmt.query([path])?;
+        if let Some(row) = rows.next()?
+            && row.get::<_, String>(0)? == hash
+        {
+            return Ok(false);
+        }
+        self.conn.execute(
+            "INSERT INTO instruction_snapshots (path, hash, content, captured_at) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![path, hash, content, at],
+        )?;
+        Ok(true)
+    }
+
+    /// Every stored version of every file, oldest first.
+    pub fn instruction_snapshots(&self) -> Result<Vec<InstructionSnapshot>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path, content, captured_at FROM instruction_snapshots ORDER BY captured_at, rowid")?;
+        let snapshots = stmt
+            .query_map([], |row| {
+                Ok(InstructionSnapshot {
+                    path: row.get(0)?,
+                    content: row.get(1)?,
+                    captured_at: row.get(2)?,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(snapshots)
+    }
+
+    /// The version of `path` that was current at `at`: the last one recorded
+    /// at or before it.
+    pub fn instruction_file_at(&self, path: &str, at: i64) -> Result<Option<InstructionSnapshot>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, content, captured_at FROM instruction_snapshots
+             WHERE path = ?1 AND captured_at <= ?2 ORDER BY captured_at DESC, rowid DESC LIMIT 1",
+        )?;
+        let mut rows = stmt.query(rusqlite::params![path, at])?;
+        if let Some(row) = rows.next()? {
+            Ok(Some(InstructionSnapshot {
+                path: row.get(0)?,
+                content: row.get(1)?,
+                captured_at: row.get(2)?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Write the whole index to `path` as JSON. Returns the number of sessions written.
+    ///
+    /// File mtimes and offsets are kept, so session files copied along with
+    /// their timestamps (`rsync -t`, `cp -p`) are not re-parsed after import.
+    /// With an `anonymizer`, project paths become pseudonyms and text fields
+    /// are redacted, so the dump can be shared.
+    pub fn export(&self, path: &Path, anonymizer: Option<&Anonymizer>) -> Result<usize> {
+        let mut stmt = self.conn.prepare(
+            "SELECT session_id, project_path, dir_name, git_branch, summary, first_prompt, message_count, created_at, modified_at, file_mtime, file_offset,
+                    input_tokens, output_tokens, cache_creation_tokens, cache_read_tokens, cost_usd, last_message_id, parent_session_id, expired,
+                    first_timestamp, last_timestamp, file_size, version, user_type, source_root
+             FROM sessions ORDER BY session_id",
+        )?;
+        let mut prompts_stmt = self
+            .conn
+            .prepare("SELECT prompt, timestamp FROM user_prompts WHERE session_id = ?1 ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(DumpedSession {
+                session_id: row.get(0)?,
+                project_path: row.get(1)?,
+                dir_name: row.get(2)?,
+                git_branch: row.get(3)?,
+                summary: row.get(4)?,
+                first_prompt: row.get(5)?,
+                message_count: row.get(6)?,
+                created_at: row.get(7)?,
+                modified_at: row.get(8)?,
+                file_mtime: row.get(9)?,
+                file_offset: row.get(10)?,
+                input_tokens: row.get::<_, i64>(11)? as u64,
+                output_tokens: row.get::<_, i64>(12)? as u64,
+                cache_creation_tokens: row.get::<_, i64>(13)? as u64,
+                cache_read_tokens: row.get::<_, i64>(14)? as u64,
+                cost_usd: row.get(15)?,
+                last_message_id: row.get(16)?,
+                parent_session_id: row.get(17)?,
+                expired: row.get(18)?,
+                first_timestamp: row.get(19)?,
+                last_timestamp: row.get(20)?,
+                file_size: row.get(21)?,
+                version: row.get(22)?,
+                user_type: row.get(23)?,
+                source_root: row.get(24)?,
+                models: Vec::new(),
+                prompts: Vec::new(),
+                files: Vec::new(),
+                tools: Vec::new(),
+                commands: Vec::new(),
+            })
+        })?;
+
+        let mut sessions = Vec::new();
+        for row in rows {
+            let mut session = row?;
+            session.models = self.get_models(&session.session_id)?;
+            session.files = self
+                .get_files(&session.session_id)?
+                .into_iter()
+                .map(|(file_path, tool)| DumpedFile { file_path, tool })
+                .collect();
+            session.tools = self.get_tool_counts(&session.session_id)?;
+            session.commands = self.get_command_counts(&session.session_id)?;
+            session.prompts = prompts_stmt
+                .query_map([&session.session_id], |row| {
+                    Ok(DumpedPrompt {
+                        prompt: row.get(0)?,
+                        timestamp: row.get(1)?,
+                    })
+                })?
                 .filter_map(|r| r.ok())
                 .collect();
+            if let Some(anon) = anonymizer {
+                session.anonymize(anon);
+            }
+            sessions.push(session);
+        }
+
+        let dump = IndexDump {
+            schema_version: SCHEMA_VERSION,
+            dump_version: DUMP_VERSION,
+            projects: self
+                .list_projects()?
+                .into_iter()
+                .map(|p| match anonymizer {
+                    Some(anon) => DumpedProject {
+                        dir_name: anon.project(&p.dir_name),
+                        original_path: anon.project(&p.original_path),
+                        session_count: p.session_count,
+                    },
+                    None => DumpedProject {
+                        dir_name: p.dir_name,
+                        original_path: p.original_path,
+                        session_count: p.session_count,
+                    },
+                })
+                .collect(),
+            sessions,
+            bookmarks: self
+                .bookmarks()?
+                .into_iter()
+                .map(|b| match anonymizer {
+                    Some(anon) => Bookmark { dir_name: anon.project(&b.dir_name), note: anon.text(&b.note), ..b },
+                    None => b,
+                })
+                .collect(),
+            instruction_snapshots: self
+                .instruction_snapshots()?
+                .into_iter()
+                .map(|snapshot| match anonymizer {
+                    Some(anon) => InstructionSnapshot {
+                        path: anon.text(&snapshot.path),
+                        content: anon.text(&snapshot.content),
+                        ..snapshot
+                    },
+                    None => snapshot,
+                })
+                .collect(),
+            favorites: self
+                .conn
+                .prepare("SELECT session_id, dir_name, created_at FROM favorites ORDER BY created_at, session_id")?
+                .query_map([], |row| {
+                    Ok(DumpedFavorite { session_id: row.get(0)?, dir_name: row.get(1)?, created_at: row.get(2)? })
+                })?
+                .filter_map(|r| r.ok())
+                .map(|f| match anonymizer {
+                    Some(anon) => DumpedFavorite { dir_name: anon.project(&f.dir_name), ..f },
+                    None => f,
+                })
+                .collect(),
+            session_tags: self
+                .conn
+                .prepare("SELECT session_id, dir_name, tag FROM session_tags ORDER BY session_id, tag")?
+                .query_map([], |row| Ok(DumpedTag { session_id: row.get(0)?, dir_name: row.get(1)?, tag: row.get(2)? }))?
+                .filter_map(|r| r.ok())
+                .map(|t| match anonymizer {
+                    Some(anon) => DumpedTag { dir_name: anon.project(&t.dir_name), tag: anon.text(&t.tag), ..t },
+                    None => t,
+                })
+                .collect(),
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, &dump)?;
+        writer.flush()?;
+        Ok(dump.sessions.len())
+    }
+
+    /// Merge a dump written by `export` into this index; sessions and projects
+    /// in the dump replace stored ones with the same id. Returns the number of
+    /// sessions imported.
+    pub fn import(&self, path: &Path) -> Result<usize> {
+        let dump: IndexDump = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if dump.schema_version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "index dump has schema version {}, newer than this build supports ({})",
+                dump.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        if dump.dump_version > DUMP_VERSION {
+            anyhow::bail!(
+                "index dump has format version {}, newer than this build supports ({})",
+                dump.dump_version,
+                DUMP_VERSION
+            );
+        }
+        // Dumps from an older indexer lack data this version stores, so have
+        // the next build re-parse those sessions.
+        let outdated = dump.schema_version < SCHEMA_VERSION;
+
+        let tx = self.transaction()?;
+        let count = dump.sessions.len();
+        for mut session in dump.sessions {
+            let expired = session.expired;
+            let files: Vec<(String, String)> =
+                session.files.iter().map(|f| (f.file_path.clone(), f.tool.clone())).collect();
+            let tools = std::mem::take(&mut session.tools);
+            let commands = std::mem::take(&mut session.commands);
+            let (mut record, prompts) = session.into_records();
+            if outdated {
+                record.file_mtime = -1;
+                record.file_offset = 0;
+            }
+            self.upsert_session(&record)?;
+            self.insert_prompts(&record.session_id, &prompts)?;
+            self.insert_files(&record.session_id, &files)?;
+            self.insert_tool_counts(&record.session_id, &tools)?;
+            self.insert_command_counts(&record.session_id, &commands)?;
+            self.refresh_activity(&record.session_id)?;
+            if expired {
+                self.conn
+                    .execute("UPDATE sessions SET expired = 1 WHERE session_id = ?1", [&record.session_id])?;
+            }
+        }
+        let mut stmt = self.conn.prepare(
+            "INSERT OR REPLACE INTO projects (dir_name, original_path, session_count) VALUES (?1, ?2, ?3)",
+        )?;
+        for p in &dump.projects {
+            stmt.execute(rusqlite::params![p.dir_name, p.original_path, p.session_count as i64])?;
+        }
+        drop(stmt);
+        for bookmark in &dump.bookmarks {
+            self.add_bookmark(bookmark)?;
+        }
+        for favorite in &dump.favorites {
+            self.add_favorite(&favorite.session_id, &favorite.dir_name, favorite.created_at)?;
+        }
+        // タグは置き換えずに足す（取り込み先で付けたタグも残す）
+        for tag in &dump.session_tags {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO session_tags (session_id, dir_name, tag) VALUES (?1, ?2, ?3)",
+                rusqlite::params![tag.session_id, tag.dir_name, tag.tag],
+            )?;
+        }
+        // 同じダンプを取り込み直しても版が増えないよう、同じ時刻の同じ内容は飛ばす
+        for snapshot in &dump.instruction_snapshots {
+            let hash = format!("{:016x}", analysis::stable_hash(&snapshot.content));
+            self.conn.execute(
+                "INSERT INTO instruction_snapshots (path, hash, content, captured_at)
+                 SELECT ?1, ?2, ?3, ?4
+                 WHERE NOT EXISTS (SELECT 1 FROM instruction_snapshots WHERE path = ?1 AND hash = ?2 AND captured_at = ?4)",
+                rusqlite::params![snapshot.path, hash, snapshot.content, snapshot.captured_at],
+            )?;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Append to the `retention_journal`, stamped with the current time.
+    pub fn log_retention(&self, action: &str, session_id: &str, dir_name: &str, detail: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO retention_journal (at, action, session_id, dir_name, detail) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![chrono::Utc::now().timestamp(), action, session_id, dir_name, detail],
+        )?;
+        Ok(())
+    }
+
+    /// The `limit` latest retention journal entries, newest first.
+    pub fn retention_journal(&self, limit: usize) -> Result<Vec<JournalEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT at, action, session_id, dir_name, detail FROM retention_journal ORDER BY id DESC LIMIT ?1",
+        )?;
+        let entries = stmt
+            .query_map([limit as i64], |row| {
+                Ok(JournalEntry {
+                    at: row.get(0)?,
+                    action: row.get(1)?,
+                    session_id: row.get(2)?,
+                    dir_name: row.get(3)?,
+                    detail: row.get(4)?,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(entries)
+    }
 
-            results.push(SearchableSession {
-                session_id,
-                project_path,
-                dir_name,
-                git_branch,
-                summary,
-                created_at,
-                prompts,
-            });
+    /// Delete prompt and model rows whose session no longer exists, then
+    /// `VACUUM` the database file to return the freed pages to the OS.
+    ///
+    /// Only rows derived from transcripts are deleted. Bookmarks, favorites
+    /// and tags are user data that a re-index cannot bring back, and their
+    /// session may come back (an import, a restored file, another Claude
+    /// root), so they are kept and counted; sessions the user deletes in the
+    /// viewer lose theirs in `forget_session`. The `index_events` changefeed
+    /// and the `retention_journal` are logs that name sessions which are gone
+    /// by design; the changefeed is bounded by `trim_changes` instead.
+    pub fn compact(&self) -> Result<CompactStats> {
+        let bytes_before = self.database_size()?;
+        let orphan = |table: &str| {
+            format!("FROM {} WHERE session_id NOT IN (SELECT session_id FROM sessions)", table)
+        };
+        let tx = self.transaction()?;
+        let mut orphans_removed = 0;
+        for table in ["user_prompts", "session_models", "session_files", "session_tools", "session_commands"] {
+            orphans_removed += self.conn.execute(&format!("DELETE {}", orphan(table)), [])?;
         }
+        let mut user_rows_kept = 0;
+        for table in ["bookmarks", "favorites", "session_tags"] {
+            let count: i64 = self.conn.query_row(&format!("SELECT count(*) {}", orphan(table)), [], |row| row.get(0))?;
+            user_rows_kept += count as usize;
+        }
+        tx.commit()?;
+        self.conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(CompactStats {
+            orphans_removed,
+            user_rows_kept,
+            bytes_before,
+            bytes_after: self.database_size()?,
+        })
+    }
 
-        Ok(results)
+    /// Cut every stored prompt down to its first `max_chars` characters, as
+    /// the indexer does for new prompts when `max_prompt_chars` is set.
+    /// Prompts that become identical within a session are merged.
+    pub fn truncate_prompts(&self, max_chars: usize) -> Result<TruncateStats> {
+        let prompt_bytes = |conn: &Connection| -> Result<i64> {
+            Ok(conn.query_row(
+                "SELECT COALESCE(SUM(length(CAST(prompt AS BLOB))), 0) FROM user_prompts",
+                [],
+                |row| row.get(0),
+            )?)
+        };
+        let tx = self.transaction()?;
+        let before = prompt_bytes(&self.conn)?;
+        let prompts_truncated = self.conn.execute(
+            "UPDATE OR REPLACE user_prompts SET prompt = substr(prompt, 1, ?1) WHERE length(prompt) > ?1",
+            [max_chars as i64],
+        )?;
+        let after = prompt_bytes(&self.conn)?;
+        tx.commit()?;
+        Ok(TruncateStats {
+            prompts_truncated,
+            bytes_saved: before.saturating_sub(after) as u64,
+        })
+    }
+
+    fn database_size(&self) -> Result<u64> {
+        let pages: i64 = self.conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: i64 = self.conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        Ok((pages * page_size) as u64)
     }
 
     pub fn all_session_ids(&self) -> Result<Vec<String>> {
@@ -172,11 +1783,205 @@ impl SessionIndex {
     }
 }
 
+/// Bump when the indexer starts storing something new, so sessions indexed
+/// by an older version are re-parsed instead of skipped as unchanged.
+const SCHEMA_VERSION: i64 = 8;
+
+/// Written as `IndexDump::dump_version`.
+const DUMP_VERSION: i64 = 5;
+
+fn reindex_if_outdated(conn: &Connection) -> Result<()> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version < SCHEMA_VERSION {
+        conn.execute_batch(&format!(
+            "UPDATE sessions SET file_mtime = -1, file_offset = 0; PRAGMA user_version = {};",
+            SCHEMA_VERSION
+        ))?;
+    }
+    Ok(())
+}
+
+/// Recompute `last_activity` of one session (or all with None) as the
+/// latest of its transcript timestamps, the `sessions-index.json` dates and
+/// its prompt timestamps, so sessions without index metadata still sort by
+/// when they were used.
+fn refresh_activity(conn: &Connection, session_id: Option<&str>) -> Result<()> {
+    conn.execute(
+        "UPDATE sessions SET last_activity = MAX(
+            last_timestamp,
+            COALESCE(CAST(strftime('%s', NULLIF(modified_at, '')) AS INTEGER), 0),
+            COALESCE(CAST(strftime('%s', NULLIF(created_at, '')) AS INTEGER), 0),
+            COALESCE((SELECT MAX(CAST(strftime('%s', p.timestamp) AS INTEGER)) FROM user_prompts p
+                      WHERE p.session_id = sessions.session_id), 0))
+         WHERE ?1 IS NULL OR session_id = ?1",
+        [session_id],
+    )?;
+    Ok(())
+}
+
+/// Add `column` to `table` if an older database was created without it, and
+/// say whether it had to.
+fn ensure_column(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))?;
+    }
+    Ok(!exists)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_index_needs_its_key() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("index.db");
+        {
+            let index = SessionIndex::open_with_key(&db_path, Some("s3cret")).unwrap();
+            index.upsert_session(&SessionRecord {
+                session_id: "s1".to_string(),
+                project_path: "/p".to_string(),
+                dir_name: "-p".to_string(),
+                git_branch: "".to_string(),
+                summary: "".to_string(),
+                first_prompt: "".to_string(),
+                message_count: 0,
+                created_at: "".to_string(),
+                modified_at: "".to_string(),
+                file_mtime: 0,
+                file_offset: 0,
+                usage: UsageSummary::default(),
+                models: Vec::new(),
+                parent_session_id: String::new(),
+                stats: SessionStats::default(),
+                version: String::new(),
+                user_type: String::new(),
+                source_root: String::new(),
+            })
+            .unwrap();
+            let prompt = PromptRecord { prompt: "the api token is hunter2".to_string(), timestamp: None };
+            index.insert_prompts("s1", &[prompt]).unwrap();
+            index.conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);").unwrap();
+        }
+        let raw = std::fs::read(&db_path).unwrap();
+        assert!(!raw.windows(7).any(|w| w == b"hunter2"));
+
+        let index = SessionIndex::open_with_key(&db_path, Some("s3cret")).unwrap();
+        assert_eq!(index.search_all().unwrap()[0].prompts, vec!["the api token is hunter2"]);
+        drop(index);
+        for key in [None, Some("wrong")] {
+            let err = SessionIndex::open_with_key(&db_path, key).err().unwrap();
+            assert!(is_key_mismatch(&err));
+            assert!(!is_corruption(&err));
+        }
+        assert!(db_path.exists());
+
+        // 平文の索引に後から鍵を設定しても作り直さない
+        let plain = tmp.path().join("plain.db");
+        drop(SessionIndex::open_with_key(&plain, None).unwrap());
+        let err = SessionIndex::open_with_key(&plain, Some("s3cret")).err().unwrap();
+        assert!(is_key_mismatch(&err));
+        assert!(err.to_string().contains("wrong index key"));
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    #[test]
+    fn key_is_refused_without_the_encryption_feature() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("index.db");
+        let err = SessionIndex::open_with_key(&db_path, Some("s3cret")).err().unwrap();
+        assert!(err.to_string().contains(INDEX_KEY_ENV));
+        assert!(!is_corruption(&err));
+    }
+
+    #[test]
+    fn garbage_file_is_reported_as_corruption_and_can_be_discarded() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("index.db");
+        std::fs::write(&db_path, "definitely not sqlite ".repeat(300)).unwrap();
+        std::fs::write(tmp.path().join("index.db-wal"), "").unwrap();
+
+        let err = SessionIndex::open(&db_path).err().unwrap();
+        assert!(is_corruption(&err));
+        assert!(!is_corruption(&anyhow::anyhow!("disk full")));
+
+        let first = SessionIndex::discard(&db_path).unwrap();
+        assert!(first.file_name().unwrap().to_str().unwrap().starts_with("index.db.corrupt-"));
+        assert!(first.exists());
+        assert!(!tmp.path().join("index.db-wal").exists());
+        let index = SessionIndex::open(&db_path).unwrap();
+        assert!(index.search_all().unwrap().is_empty());
+        drop(index);
+
+        // 二度目の退避で前のバックアップを上書きしない
+        std::fs::write(&db_path, "still not sqlite ".repeat(300)).unwrap();
+        let second = SessionIndex::discard(&db_path).unwrap();
+        assert_ne!(first, second);
+        assert!(first.exists() && second.exists());
+    }
+
+    #[test]
+    fn salvage_copies_user_data_from_the_old_database() {
+        let tmp = TempDir::new().unwrap();
+        let old_path = tmp.path().join("old.db");
+        {
+            let old = SessionIndex::open(&old_path).unwrap();
+            old.add_bookmark(&Bookmark {
+                session_id: "s1".to_string(),
+                dir_name: "-p".to_string(),
+                message_index: 3,
+                message_uuid: "u3".to_string(),
+                note: "root cause".to_string(),
+                created_at: 10,
+            })
+            .unwrap();
+            old.add_favorite("s1", "-p", 11).unwrap();
+            old.set_session_tags("s1", "-p", &["auth".to_string(), "bug".to_string()]).unwrap();
+            old.record_instruction_file("/p/CLAUDE.md", "be terse", 12).unwrap();
+        }
+
+        let index = SessionIndex::open(&tmp.path().join("new.db")).unwrap();
+        assert_eq!(index.salvage_user_data(&old_path), 5);
+        assert_eq!(index.bookmarks().unwrap()[0].note, "root cause");
+        assert!(index.favorites().unwrap().contains("s1"));
+        assert_eq!(index.session_tags().unwrap()["s1"], vec!["auth", "bug"]);
+        assert_eq!(index.instruction_file_at("/p/CLAUDE.md", 20).unwrap().unwrap().content, "be terse");
+
+        // 読めないファイルからは何も拾わない
+        let garbage = tmp.path().join("garbage.db");
+        std::fs::write(&garbage, "definitely not sqlite ".repeat(300)).unwrap();
+        assert_eq!(index.salvage_user_data(&garbage), 0);
+    }
+
+    #[test]
+    fn encrypted_looking_file_without_a_key_is_a_key_mismatch() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("index.db");
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let bytes: Vec<u8> = (0..8192)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        std::fs::write(&db_path, &bytes).unwrap();
+
+        let err = SessionIndex::open_with_key(&db_path, None).err().unwrap();
+        assert!(is_key_mismatch(&err));
+        assert!(!is_corruption(&err));
+        assert!(err.to_string().contains("missing index key"));
+        assert_eq!(std::fs::read(&db_path).unwrap(), bytes);
+    }
+
     #[test]
     fn create_db_creates_tables() {
         let tmp = TempDir::new().unwrap();
@@ -221,6 +2026,14 @@ mod tests {
             created_at: "2026-01-15T10:00:00Z".to_string(),
             modified_at: "2026-01-15T11:00:00Z".to_string(),
             file_mtime: 1700000000,
+            file_offset: 0,
+            usage: UsageSummary::default(),
+            models: Vec::new(),
+            parent_session_id: String::new(),
+            stats: SessionStats::default(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
         };
         index.upsert_session(&rec).unwrap();
 
@@ -274,6 +2087,14 @@ mod tests {
             created_at: "".to_string(),
             modified_at: "".to_string(),
             file_mtime: 1700000000,
+            file_offset: 0,
+            usage: UsageSummary::default(),
+            models: Vec::new(),
+            parent_session_id: String::new(),
+            stats: SessionStats::default(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
         };
         index.upsert_session(&rec).unwrap();
 
@@ -298,6 +2119,14 @@ mod tests {
             created_at: "2026-01-15T10:00:00Z".to_string(),
             modified_at: "2026-01-15T10:00:00Z".to_string(),
             file_mtime: 1700000000,
+            file_offset: 0,
+            usage: UsageSummary::default(),
+            models: Vec::new(),
+            parent_session_id: String::new(),
+            stats: SessionStats::default(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
         };
         index.upsert_session(&rec1).unwrap();
 
@@ -312,6 +2141,14 @@ mod tests {
             created_at: "2026-01-15T10:00:00Z".to_string(),
             modified_at: "2026-01-15T12:00:00Z".to_string(),
             file_mtime: 1700001000,
+            file_offset: 0,
+            usage: UsageSummary::default(),
+            models: Vec::new(),
+            parent_session_id: String::new(),
+            stats: SessionStats::default(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
         };
         index.upsert_session(&rec2).unwrap();
 
@@ -323,4 +2160,779 @@ mod tests {
         let mtime = index.get_file_mtime("sess-1").unwrap();
         assert_eq!(mtime, Some(1700001000));
     }
+
+    #[test]
+    fn search_sorts_by_last_activity_even_without_metadata() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let record = |id: &str, created_at: &str, last_timestamp: i64| SessionRecord {
+            session_id: id.to_string(),
+            project_path: "/p".to_string(),
+            dir_name: "-p".to_string(),
+            git_branch: String::new(),
+            summary: String::new(),
+            first_prompt: String::new(),
+            message_count: 0,
+            created_at: created_at.to_string(),
+            modified_at: String::new(),
+            file_mtime: 0,
+            file_offset: 0,
+            usage: UsageSummary::default(),
+            models: Vec::new(),
+            parent_session_id: String::new(),
+            stats: SessionStats { last_timestamp, ..SessionStats::default() },
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+        };
+        // メタデータの日付だけ、トランスクリプトの時刻だけ、プロンプトの時刻だけ、何も無し
+        for (rec, prompt_ts) in [
+            (record("meta", "2026-01-15T10:00:00Z", 0), None),
+            (record("transcript", "", 1_768_500_000), None),
+            (record("prompts", "", 0), Some("2026-03-01T09:30:00.250Z")),
+            (record("unknown", "", 0), None),
+        ] {
+            index.upsert_session(&rec).unwrap();
+            let prompts = [PromptRecord { prompt: "go".to_string(), timestamp: prompt_ts.map(String::from) }];
+            index.insert_prompts(&rec.session_id, &prompts).unwrap();
+            index.refresh_activity(&rec.session_id).unwrap();
+        }
+
+        let order: Vec<(String, i64)> =
+            index.search_all().unwrap().into_iter().map(|s| (s.session_id, s.last_activity)).collect();
+        assert_eq!(
+            order,
+            vec![
+                ("prompts".to_string(), 1_772_357_400),
+                ("transcript".to_string(), 1_768_500_000),
+                ("meta".to_string(), 1_768_471_200),
+                ("unknown".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn open_migrates_old_schema_and_forces_reindex() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE sessions (session_id TEXT PRIMARY KEY, project_path TEXT NOT NULL,
+                 dir_name TEXT NOT NULL, git_branch TEXT DEFAULT '', summary TEXT DEFAULT '',
+                 first_prompt TEXT DEFAULT '', message_count INTEGER DEFAULT 0,
+                 created_at TEXT DEFAULT '', modified_at TEXT DEFAULT '', file_mtime INTEGER DEFAULT 0);
+                 INSERT INTO sessions (session_id, project_path, dir_name, file_mtime, created_at)
+                 VALUES ('old', '/p', '-p', 42, '2026-01-15T10:00:00Z');",
+            )
+            .unwrap();
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        // 旧バージョンの索引は再解析させる
+        assert_eq!(
+            index.get_file_state("old").unwrap(),
+            Some(FileState { mtime: -1, offset: 0 })
+        );
+        // 数値の時刻は文字列の created_at から埋める
+        assert_eq!(index.search_all().unwrap()[0].last_activity, 1_768_471_200);
+    }
+
+    #[test]
+    fn append_prompts_keeps_existing_and_ignores_duplicates() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let prompt = |text: &str| PromptRecord {
+            prompt: text.to_string(),
+            timestamp: Some("2026-01-15T10:00:00Z".to_string()),
+        };
+        index
+            .upsert_session(&SessionRecord {
+                session_id: "sess-1".to_string(),
+                project_path: "/project".to_string(),
+                dir_name: "-project".to_string(),
+                git_branch: "".to_string(),
+                summary: "".to_string(),
+                first_prompt: "".to_string(),
+                message_count: 0,
+                created_at: "".to_string(),
+                modified_at: "".to_string(),
+                file_mtime: 0,
+                file_offset: 0,
+                usage: UsageSummary::default(),
+                models: Vec::new(),
+                parent_session_id: String::new(),
+                stats: SessionStats::default(),
+                version: String::new(),
+                user_type: String::new(),
+                source_root: String::new(),
+            })
+            .unwrap();
+        index.insert_prompts("sess-1", &[prompt("one")]).unwrap();
+        index
+            .append_prompts("sess-1", &[prompt("one"), prompt("two")])
+            .unwrap();
+        let count: i64 = index
+            .conn
+            .query_row("SELECT COUNT(*) FROM user_prompts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn upsert_session_stores_usage() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let usage = UsageSummary {
+            usage: TokenUsage {
+                input_tokens: 10,
+                output_tokens: 20,
+                cache_creation_tokens: 30,
+                cache_read_tokens: 40,
+            },
+            cost_usd: 1.25,
+            last_message_id: "msg_1".to_string(),
+        };
+        index
+            .upsert_session(&SessionRecord {
+                session_id: "sess-1".to_string(),
+                project_path: "/project".to_string(),
+                dir_name: "-project".to_string(),
+                git_branch: "".to_string(),
+                summary: "".to_string(),
+                first_prompt: "".to_string(),
+                message_count: 0,
+                created_at: "".to_string(),
+                modified_at: "".to_string(),
+                file_mtime: 0,
+                file_offset: 0,
+                usage: usage.clone(),
+                models: Vec::new(),
+                parent_session_id: String::new(),
+                stats: SessionStats::default(),
+                version: String::new(),
+                user_type: String::new(),
+                source_root: String::new(),
+            })
+            .unwrap();
+        assert_eq!(index.get_usage("sess-1").unwrap(), Some(usage));
+        assert_eq!(index.get_usage("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn upsert_session_replaces_models_and_finds_by_model() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let record = |id: &str, models: &[&str]| SessionRecord {
+            session_id: id.to_string(),
+            project_path: "/project".to_string(),
+            dir_name: "-project".to_string(),
+            git_branch: "".to_string(),
+            summary: "".to_string(),
+            first_prompt: "".to_string(),
+            message_count: 0,
+            created_at: "".to_string(),
+            modified_at: "".to_string(),
+            file_mtime: 0,
+            file_offset: 0,
+            usage: UsageSummary::default(),
+            models: models.iter().map(|m| m.to_string()).collect(),
+            parent_session_id: String::new(),
+            stats: SessionStats::default(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+        };
+        index
+            .upsert_session(&record("a", &["claude-sonnet-4", "claude-opus-4-1"]))
+            .unwrap();
+        index.upsert_session(&record("b", &["claude-haiku-4-5"])).unwrap();
+        assert_eq!(
+            index.get_models("a").unwrap(),
+            vec!["claude-sonnet-4", "claude-opus-4-1"]
+        );
+        assert_eq!(index.sessions_using_model("OPUS").unwrap(), vec!["a"]);
+
+        index.upsert_session(&record("a", &["claude-sonnet-4"])).unwrap();
+        assert!(index.sessions_using_model("opus").unwrap().is_empty());
+
+        index.forget_session("a", "-project").unwrap();
+        assert!(index.get_models("a").unwrap().is_empty());
+    }
+
+    #[test]
+    fn replace_projects_round_trips() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let project = |dir: &str, count: usize| ProjectInfo {
+            dir_name: dir.to_string(),
+            original_path: format!("/{}", dir.trim_start_matches('-')),
+            session_count: count,
+        };
+        index.replace_projects(&[project("-b", 2), project("-a", 5)]).unwrap();
+        index.replace_projects(&[project("-b", 3), project("-c", 1)]).unwrap();
+
+        let projects = index.list_projects().unwrap();
+        let rows: Vec<(&str, &str, usize)> = projects
+            .iter()
+            .map(|p| (p.dir_name.as_str(), p.original_path.as_str(), p.session_count))
+            .collect();
+        assert_eq!(rows, vec![("-b", "/b", 3), ("-c", "/c", 1)]);
+    }
+
+    #[test]
+    fn open_uses_wal_and_transaction_rolls_back_on_drop() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let mode: String = index
+            .conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        let record = |id: &str| SessionRecord {
+            session_id: id.to_string(),
+            project_path: "/project".to_string(),
+            dir_name: "-project".to_string(),
+            git_branch: "".to_string(),
+            summary: "".to_string(),
+            first_prompt: "".to_string(),
+            message_count: 0,
+            created_at: "".to_string(),
+            modified_at: "".to_string(),
+            file_mtime: 0,
+            file_offset: 0,
+            usage: UsageSummary::default(),
+            models: Vec::new(),
+            parent_session_id: String::new(),
+            stats: SessionStats::default(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+        };
+        {
+            let _tx = index.transaction().unwrap();
+            index.upsert_session(&record("dropped")).unwrap();
+        }
+        let tx = index.transaction().unwrap();
+        index.upsert_session(&record("kept")).unwrap();
+        tx.commit().unwrap();
+        assert_eq!(index.all_session_ids().unwrap(), vec!["kept"]);
+    }
+
+    #[test]
+    fn compact_removes_orphans_and_reclaims_space() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let record = |id: &str| SessionRecord {
+            session_id: id.to_string(),
+            project_path: "/project".to_string(),
+            dir_name: "-project".to_string(),
+            git_branch: "".to_string(),
+            summary: "".to_string(),
+            first_prompt: "".to_string(),
+            message_count: 0,
+            created_at: "".to_string(),
+            modified_at: "".to_string(),
+            file_mtime: 0,
+            file_offset: 0,
+            usage: UsageSummary::default(),
+            models: vec!["claude-opus-4".to_string()],
+            parent_session_id: String::new(),
+            stats: SessionStats::default(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+        };
+        let prompts: Vec<PromptRecord> = (0..200)
+            .map(|i| PromptRecord {
+                prompt: format!("{} {}", "padding ".repeat(50), i),
+                timestamp: None,
+            })
+            .collect();
+        for id in ["keep", "gone"] {
+            index.upsert_session(&record(id)).unwrap();
+            index.insert_prompts(id, &prompts).unwrap();
+        }
+        // 外部キー制約のない古い DB で残った孤立行を再現する
+        index
+            .conn
+            .execute_batch("PRAGMA foreign_keys = OFF; DELETE FROM sessions WHERE session_id = 'gone'; PRAGMA foreign_keys = ON;")
+            .unwrap();
+
+        // 利用者が付けたものは索引から消えたセッションの分も残す
+        index.add_favorite("gone", "-project", 0).unwrap();
+        index.set_session_tags("gone", "-project", &["wip".to_string(), "bug".to_string()]).unwrap();
+        index.add_favorite("keep", "-project", 0).unwrap();
+        index.record_change(ChangeKind::Expired, "gone", "-project").unwrap();
+
+        let stats = index.compact().unwrap();
+        assert_eq!(stats.orphans_removed, 201);
+        assert_eq!(stats.user_rows_kept, 3);
+        assert!(stats.bytes_reclaimed() > 0);
+        assert_eq!(index.search_all().unwrap()[0].prompts.len(), 200);
+        assert_eq!(index.get_models("keep").unwrap(), vec!["claude-opus-4"]);
+        assert!(index.favorites().unwrap().contains("gone"));
+        assert_eq!(index.session_tags().unwrap()["gone"], vec!["bug", "wip"]);
+        assert_eq!(index.recent_changes(10).unwrap().len(), 1);
+
+        assert_eq!(index.compact().unwrap().orphans_removed, 0);
+    }
+
+    #[test]
+    fn truncate_prompts_shortens_long_prompts_and_reports_savings() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        index
+            .upsert_session(&SessionRecord {
+                session_id: "s1".to_string(),
+                project_path: "/p".to_string(),
+                dir_name: "-p".to_string(),
+                git_branch: "".to_string(),
+                summary: "".to_string(),
+                first_prompt: "".to_string(),
+                message_count: 0,
+                created_at: "".to_string(),
+                modified_at: "".to_string(),
+                file_mtime: 0,
+                file_offset: 0,
+                usage: UsageSummary::default(),
+                models: Vec::new(),
+                parent_session_id: String::new(),
+                stats: SessionStats::default(),
+                version: String::new(),
+                user_type: String::new(),
+                source_root: String::new(),
+            })
+            .unwrap();
+        let prompt = |text: String| PromptRecord { prompt: text, timestamp: None };
+        index
+            .insert_prompts(
+                "s1",
+                &[
+                    prompt("short".to_string()),
+                    prompt(format!("日本語{}", "x".repeat(100))),
+                    prompt(format!("日本語{}", "y".repeat(100))),
+                ],
+            )
+            .unwrap();
+
+        let stats = index.truncate_prompts(8).unwrap();
+        assert_eq!(stats.prompts_truncated, 2);
+        assert_eq!(stats.bytes_saved, 2 * 95);
+        assert_eq!(index.search_all().unwrap()[0].prompts, vec!["short", "日本語xxxxx", "日本語yyyyy"]);
+
+        assert_eq!(index.truncate_prompts(8).unwrap(), TruncateStats::default());
+    }
+
+    #[test]
+    fn export_then_import_round_trips() {
+        let tmp = TempDir::new().unwrap();
+        let src = SessionIndex::open(&tmp.path().join("src.db")).unwrap();
+        src.upsert_session(&SessionRecord {
+            session_id: "s1".to_string(),
+            project_path: "/project".to_string(),
+            dir_name: "-project".to_string(),
+            git_branch: "main".to_string(),
+            summary: "Fix login".to_string(),
+            first_prompt: "fix the login".to_string(),
+            message_count: 4,
+            created_at: "2024-01-15T10:00:00Z".to_string(),
+            modified_at: "2024-01-15T11:00:00Z".to_string(),
+            file_mtime: 1700000000,
+            file_offset: 512,
+            usage: UsageSummary {
+                usage: TokenUsage {
+                    input_tokens: 10,
+                    output_tokens: 20,
+                    cache_creation_tokens: 30,
+                    cache_read_tokens: 40,
+                },
+                cost_usd: 0.25,
+                last_message_id: "msg_2".to_string(),
+            },
+            models: vec!["claude-opus-4".to_string()],
+            parent_session_id: String::new(),
+            stats: SessionStats { first_timestamp: 1700000000, last_timestamp: 1700003600, file_size: 4096 },
+            version: "2.0.14".to_string(),
+            user_type: "external".to_string(),
+            source_root: String::new(),
+        })
+        .unwrap();
+        src.insert_prompts(
+            "s1",
+            &[
+                PromptRecord { prompt: "fix the login".to_string(), timestamp: Some("t1".to_string()) },
+                PromptRecord { prompt: "and the tests".to_string(), timestamp: None },
+            ],
+        )
+        .unwrap();
+        src.insert_files("s1", &[("/project/src/login.rs".to_string(), "Edit".to_string())])
+            .unwrap();
+        src.insert_tool_counts("s1", &[("Edit".to_string(), 3)]).unwrap();
+        src.insert_command_counts("s1", &[("cargo test".to_string(), 2)]).unwrap();
+        src.replace_projects(&[ProjectInfo {
+            dir_name: "-project".to_string(),
+            original_path: "/project".to_string(),
+            session_count: 1,
+        }])
+        .unwrap();
+        let bookmark = Bookmark {
+            session_id: "s1".to_string(),
+            dir_name: "-project".to_string(),
+            message_index: 2,
+            message_uuid: "u2".to_string(),
+            note: "see /project/src/login.rs".to_string(),
+            created_at: 1700000100,
+        };
+        src.add_bookmark(&bookmark).unwrap();
+        src.record_instruction_file("/project/CLAUDE.md", "be terse", 1700000000).unwrap();
+        src.record_instruction_file("/project/CLAUDE.md", "be terse, run tests", 1700000200).unwrap();
+        src.add_favorite("s1", "-project", 1700000300).unwrap();
+        src.set_session_tags("s1", "-project", &["auth".to_string(), "incident".to_string()]).unwrap();
+
+        let dump = tmp.path().join("index.json");
+        assert_eq!(src.export(&dump, None).unwrap(), 1);
+
+        let dst = SessionIndex::open(&tmp.path().join("dst.db")).unwrap();
+        assert_eq!(dst.import(&dump).unwrap(), 1);
+        assert_eq!(
+            dst.get_file_state("s1").unwrap(),
+            Some(FileState { mtime: 1700000000, offset: 512 })
+        );
+        assert_eq!(dst.get_usage("s1").unwrap(), src.get_usage("s1").unwrap());
+        assert_eq!(dst.get_models("s1").unwrap(), vec!["claude-opus-4"]);
+        assert_eq!(dst.get_files("s1").unwrap(), src.get_files("s1").unwrap());
+        assert_eq!(dst.get_tool_counts("s1").unwrap(), vec![("Edit".to_string(), 3)]);
+        assert_eq!(dst.get_command_counts("s1").unwrap(), vec![("cargo test".to_string(), 2)]);
+        assert_eq!(dst.get_stats("s1").unwrap(), src.get_stats("s1").unwrap());
+        assert_eq!(dst.get_client_info("s1").unwrap(), Some(("2.0.14".to_string(), "external".to_string())));
+        let sessions = dst.search_all().unwrap();
+        assert_eq!(sessions[0].summary, "Fix login");
+        assert_eq!(sessions[0].prompts, vec!["fix the login", "and the tests"]);
+        assert_eq!(dst.list_projects().unwrap()[0].original_path, "/project");
+        assert_eq!(dst.bookmarks().unwrap(), vec![bookmark.clone()]);
+        assert_eq!(dst.instruction_snapshots().unwrap(), src.instruction_snapshots().unwrap());
+        assert_eq!(dst.instruction_file_at("/project/CLAUDE.md", 1700000100).unwrap().unwrap().content, "be terse");
+        assert_eq!(dst.favorites().unwrap(), HashSet::from(["s1".to_string()]));
+        assert_eq!(dst.session_tags().unwrap()["s1"], vec!["auth", "incident"]);
+
+        // 同じダンプをもう一度取り込んでも重複しない
+        dst.import(&dump).unwrap();
+        assert_eq!(dst.search_all().unwrap()[0].prompts.len(), 2);
+        assert_eq!(dst.bookmarks().unwrap().len(), 1);
+        assert_eq!(dst.instruction_snapshots().unwrap().len(), 2);
+        assert_eq!(dst.session_tags().unwrap()["s1"].len(), 2);
+
+        // 匿名化するとパスは仮名に置き換わり、数値はそのまま残る
+        let anon = Anonymizer::new(src.project_paths().unwrap(), &[]).unwrap();
+        src.export(&dump, Some(&anon)).unwrap();
+        let text = std::fs::read_to_string(&dump).unwrap();
+        assert!(!text.contains("/project"));
+        let shared = SessionIndex::open(&tmp.path().join("shared.db")).unwrap();
+        assert_eq!(shared.import(&dump).unwrap(), 1);
+        let alias = anon.project("/project");
+        assert_eq!(shared.get_files("s1").unwrap(), vec![(format!("{}/src/login.rs", alias), "Edit".to_string())]);
+        assert_eq!(shared.get_usage("s1").unwrap(), src.get_usage("s1").unwrap());
+        assert_eq!(shared.list_projects().unwrap()[0].original_path, alias);
+        assert_eq!(shared.bookmarks().unwrap()[0].note, format!("see {}/src/login.rs", alias));
+        assert_eq!(shared.instruction_snapshots().unwrap()[0].path, format!("{}/CLAUDE.md", alias));
+        assert!(shared.favorites().unwrap().contains("s1"));
+        assert_eq!(shared.session_tags().unwrap()["s1"], vec!["auth", "incident"]);
+        assert!(!text.contains("-project"));
+    }
+
+    #[test]
+    fn import_rejects_newer_dump_and_reindexes_older_one() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let dump = tmp.path().join("index.json");
+        let session = r#"{"session_id":"s1","project_path":"/p","dir_name":"-p","git_branch":"","summary":"","first_prompt":"","message_count":1,"created_at":"","modified_at":"","file_mtime":5,"file_offset":9,"input_tokens":0,"output_tokens":0,"cache_creation_tokens":0,"cache_read_tokens":0,"cost_usd":0.0,"last_message_id":"","models":[],"prompts":[]}"#;
+
+        std::fs::write(&dump, format!(r#"{{"schema_version":99,"projects":[],"sessions":[{}]}}"#, session)).unwrap();
+        assert!(index.import(&dump).is_err());
+        assert!(index.all_session_ids().unwrap().is_empty());
+
+        std::fs::write(&dump, format!(r#"{{"schema_version":8,"dump_version":99,"projects":[],"sessions":[{}]}}"#, session)).unwrap();
+        assert!(index.import(&dump).unwrap_err().to_string().contains("format version 99"));
+        assert!(index.all_session_ids().unwrap().is_empty());
+
+        std::fs::write(&dump, format!(r#"{{"schema_version":0,"projects":[],"sessions":[{}]}}"#, session)).unwrap();
+        assert_eq!(index.import(&dump).unwrap(), 1);
+        assert_eq!(
+            index.get_file_state("s1").unwrap(),
+            Some(FileState { mtime: -1, offset: 0 })
+        );
+    }
+
+    #[test]
+    fn sessions_touching_matches_path_suffix_and_filters_reads() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        for (id, created_at) in [("reader", "2026-01-01"), ("editor", "2026-01-02")] {
+            index
+                .upsert_session(&SessionRecord {
+                    session_id: id.to_string(),
+                    project_path: "/p".to_string(),
+                    dir_name: "-p".to_string(),
+                    git_branch: "".to_string(),
+                    summary: "".to_string(),
+                    first_prompt: "".to_string(),
+                    message_count: 0,
+                    created_at: created_at.to_string(),
+                    modified_at: "".to_string(),
+                    file_mtime: 0,
+                    file_offset: 0,
+                    usage: UsageSummary::default(),
+                    models: Vec::new(),
+                    parent_session_id: String::new(),
+                    stats: SessionStats::default(),
+                    version: String::new(),
+                    user_type: String::new(),
+                    source_root: String::new(),
+                })
+                .unwrap();
+        }
+        let pair = |p: &str, t: &str| (p.to_string(), t.to_string());
+        index.insert_files("reader", &[pair("/p/src/app.rs", "Read")]).unwrap();
+        index
+            .insert_files(
+                "editor",
+                &[pair("/p/src/app.rs", "Read"), pair("/p/src/app.rs", "Edit"), pair("/p/mysrc/app.rs", "Write")],
+            )
+            .unwrap();
+
+        let all = index.sessions_touching("src/app.rs", false).unwrap();
+        let ids: Vec<(&str, bool)> = all.iter().map(|t| (t.session_id.as_str(), t.modified)).collect();
+        assert_eq!(ids, vec![("editor", true), ("reader", false)]);
+        assert_eq!(all[0].file_path, "/p/src/app.rs");
+
+        let modified = index.sessions_touching("./src/app.rs", true).unwrap();
+        assert_eq!(modified.len(), 1);
+        assert_eq!(modified[0].session_id, "editor");
+        assert_eq!(index.sessions_touching("/p/mysrc/app.rs", true).unwrap().len(), 1);
+        assert!(index.sessions_touching("app", false).unwrap().is_empty());
+
+        // 全体を再解析したときは置き換える
+        index.insert_files("editor", &[pair("/p/README.md", "Read")]).unwrap();
+        assert_eq!(index.get_files("editor").unwrap(), vec![pair("/p/README.md", "Read")]);
+    }
+
+    #[test]
+    fn project_activity_rolls_up_sessions_in_range() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        for (id, dir_name, last_timestamp) in [("old", "-p", 1_000), ("new", "-p", 5_000), ("other", "-q", 5_000)] {
+            index
+                .upsert_session(&SessionRecord {
+                    session_id: id.to_string(),
+                    project_path: "/p".to_string(),
+                    dir_name: dir_name.to_string(),
+                    git_branch: "".to_string(),
+                    summary: "".to_string(),
+                    first_prompt: "".to_string(),
+                    message_count: 0,
+                    created_at: "".to_string(),
+                    modified_at: "".to_string(),
+                    file_mtime: 0,
+                    file_offset: 0,
+                    usage: UsageSummary::default(),
+                    models: Vec::new(),
+                    parent_session_id: String::new(),
+                    stats: SessionStats { first_timestamp: last_timestamp, last_timestamp, file_size: 0 },
+                    version: String::new(),
+                    user_type: String::new(),
+                    source_root: String::new(),
+                })
+                .unwrap();
+            let pair = |p: &str, t: &str| (p.to_string(), t.to_string());
+            index
+                .insert_files(id, &[pair("/p/src/app.rs", "Edit"), pair("/p/README.md", "Read"), pair(&format!("/p/{}.rs", id), "Write")])
+                .unwrap();
+            index.insert_tool_counts(id, &[("Edit".to_string(), 2), ("Bash".to_string(), 3)]).unwrap();
+            index.insert_command_counts(id, &[("cargo test".to_string(), 2), ("ls".to_string(), 1)]).unwrap();
+        }
+
+        let all = index.project_activity("-p", None, None, 1).unwrap();
+        assert_eq!(
+            all,
+            ProjectActivity {
+                sessions: 2,
+                tool_calls: 10,
+                commands_run: 6,
+                files_changed: 3,
+                top_files: vec![("/p/src/app.rs".to_string(), 2)],
+                top_commands: vec![("cargo test".to_string(), 4)],
+                tools: vec![("Bash".to_string(), 6), ("Edit".to_string(), 4)],
+            }
+        );
+
+        let recent = index.project_activity("-p", Some(1_000), None, 10).unwrap();
+        assert_eq!(recent.sessions, 1);
+        assert_eq!(recent.files_changed, 2);
+        assert_eq!(recent.top_files, vec![("/p/new.rs".to_string(), 1), ("/p/src/app.rs".to_string(), 1)]);
+        assert_eq!(recent.top_commands, vec![("cargo test".to_string(), 2), ("ls".to_string(), 1)]);
+
+        let early = index.project_activity("-p", None, Some(5_000), 10).unwrap();
+        assert_eq!(early.sessions, 1);
+        assert_eq!(early.top_files[0], ("/p/old.rs".to_string(), 1));
+
+        assert_eq!(index.project_activity("-none", None, None, 10).unwrap(), ProjectActivity::default());
+    }
+
+    #[test]
+    fn changefeed_lists_newest_first_and_trims() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        for (kind, id) in [(ChangeKind::Added, "a"), (ChangeKind::Added, "b"), (ChangeKind::Updated, "a")] {
+            index.record_change(kind, id, "-p").unwrap();
+        }
+        let kinds = |changes: Vec<IndexChange>| -> Vec<(ChangeKind, String)> {
+            changes.into_iter().map(|c| (c.kind, c.session_id)).collect()
+        };
+        assert_eq!(
+            kinds(index.recent_changes(2).unwrap()),
+            vec![(ChangeKind::Updated, "a".to_string()), (ChangeKind::Added, "b".to_string())]
+        );
+        assert!(index.recent_changes(1).unwrap()[0].at > 0);
+
+        assert_eq!(index.trim_changes(2).unwrap(), 1);
+        assert_eq!(index.trim_changes(2).unwrap(), 0);
+        assert_eq!(index.recent_changes(10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn bookmarks_are_listed_per_session_and_across_sessions() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let bookmark = |session_id: &str, message_index: usize, note: &str, created_at: i64| Bookmark {
+            session_id: session_id.to_string(),
+            dir_name: "-p".to_string(),
+            message_index,
+            message_uuid: format!("uuid-{}", message_index),
+            note: note.to_string(),
+            created_at,
+        };
+        index.add_bookmark(&bookmark("a", 7, "the fix", 100)).unwrap();
+        index.add_bookmark(&bookmark("a", 2, "the plan", 200)).unwrap();
+        index.add_bookmark(&bookmark("b", 0, "start", 150)).unwrap();
+        // 同じメッセージに付け直すとメモを置き換える
+        index.add_bookmark(&bookmark("a", 7, "the real fix", 120)).unwrap();
+
+        let notes = |bookmarks: Vec<Bookmark>| bookmarks.into_iter().map(|b| b.note).collect::<Vec<_>>();
+        assert_eq!(notes(index.session_bookmarks("a").unwrap()), vec!["the plan", "the real fix"]);
+        assert_eq!(notes(index.bookmarks().unwrap()), vec!["the plan", "start", "the real fix"]);
+        assert_eq!(index.session_bookmarks("b").unwrap()[0], bookmark("b", 0, "start", 150));
+
+        index.remove_bookmark("a", 2).unwrap();
+        assert_eq!(notes(index.session_bookmarks("a").unwrap()), vec!["the real fix"]);
+    }
+
+    #[test]
+    fn favorites_are_added_once_and_removed() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        index.add_favorite("a", "-p", 100).unwrap();
+        index.add_favorite("b", "-q", 200).unwrap();
+        index.add_favorite("a", "-p", 300).unwrap();
+        assert_eq!(index.favorites().unwrap(), HashSet::from(["a".to_string(), "b".to_string()]));
+
+        index.remove_favorite("a").unwrap();
+        assert_eq!(index.favorites().unwrap(), HashSet::from(["b".to_string()]));
+    }
+
+    #[test]
+    fn session_tags_are_replaced_per_session() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let tags = |names: &[&str]| names.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        index.set_session_tags("a", "-p", &tags(&["refactor", "incident-2026-01"])).unwrap();
+        index.set_session_tags("b", "-q", &tags(&["refactor"])).unwrap();
+        index.set_session_tags("a", "-p", &tags(&["refactor", "auth", "auth"])).unwrap();
+
+        let all = index.session_tags().unwrap();
+        assert_eq!(all["a"], tags(&["auth", "refactor"]));
+        assert_eq!(all["b"], tags(&["refactor"]));
+
+        index.set_session_tags("b", "-q", &[]).unwrap();
+        assert!(!index.session_tags().unwrap().contains_key("b"));
+    }
+
+    #[test]
+    fn instruction_snapshots_store_changes_only_and_answer_as_of_a_time() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let path = "/p/CLAUDE.md";
+        assert!(index.record_instruction_file(path, "Use tabs", 100).unwrap());
+        assert!(!index.record_instruction_file(path, "Use tabs", 200).unwrap());
+        assert!(index.record_instruction_file(path, "Use spaces", 300).unwrap());
+
+        let content_at = |at| index.instruction_file_at(path, at).unwrap().map(|s| s.content);
+        assert_eq!(content_at(99), None);
+        assert_eq!(content_at(250).as_deref(), Some("Use tabs"));
+        assert_eq!(content_at(300).as_deref(), Some("Use spaces"));
+        assert_eq!(index.instruction_file_at(path, 250).unwrap().unwrap().captured_at, 100);
+        assert_eq!(index.instruction_file_at("/other/CLAUDE.md", 300).unwrap(), None);
+    }
+
+    #[test]
+    fn expire_missing_keeps_prompts_until_file_returns() {
+        let tmp = TempDir::new().unwrap();
+        let index = SessionIndex::open(&tmp.path().join("test.db")).unwrap();
+        let record = |id: &str| SessionRecord {
+            session_id: id.to_string(),
+            project_path: "/project".to_string(),
+            dir_name: "-project".to_string(),
+            git_branch: "".to_string(),
+            summary: "".to_string(),
+            first_prompt: "".to_string(),
+            message_count: 0,
+            created_at: "".to_string(),
+            modified_at: "".to_string(),
+            file_mtime: 100,
+            file_offset: 10,
+            usage: UsageSummary::default(),
+            models: Vec::new(),
+            parent_session_id: String::new(),
+            stats: SessionStats::default(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+        };
+        for id in ["keep", "gone"] {
+            index.upsert_session(&record(id)).unwrap();
+            index
+                .insert_prompts(id, &[PromptRecord { prompt: format!("prompt {}", id), timestamp: None }])
+                .unwrap();
+        }
+
+        let existing: HashSet<String> = ["keep".to_string()].into_iter().collect();
+        assert_eq!(index.expire_missing(&existing).unwrap(), 1);
+        // 2回目は既に期限切れなので数えない
+        assert_eq!(index.expire_missing(&existing).unwrap(), 0);
+        let changes = index.recent_changes(10).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].kind, changes[0].session_id.as_str(), changes[0].dir_name.as_str()), (ChangeKind::Expired, "gone", "-project"));
+
+        let results = index.search_all().unwrap();
+        let gone = results.iter().find(|s| s.session_id == "gone").unwrap();
+        assert!(gone.expired);
+        assert_eq!(gone.prompts, vec!["prompt gone"]);
+        assert!(!results.iter().find(|s| s.session_id == "keep").unwrap().expired);
+        assert_eq!(index.get_file_state("gone").unwrap(), Some(FileState { mtime: -1, offset: 0 }));
+
+        // ファイルが戻って再インデックスされたら期限切れを解除する
+        index.upsert_session(&record("gone")).unwrap();
+        assert!(index.search_all().unwrap().iter().all(|s| !s.expired));
+
+        // 利用者が消したセッションは行ごと落とし、期限切れとは別に記録する
+        index.add_favorite("gone", "-project", 1).unwrap();
+        index.set_session_tags("gone", "-project", &["wip".to_string()]).unwrap();
+        index.forget_session("gone", "-project").unwrap();
+        assert_eq!(index.expire_missing(&existing).unwrap(), 0);
+        let ids: Vec<String> = index.search_all().unwrap().into_iter().map(|s| s.session_id).collect();
+        assert_eq!(ids, vec!["keep"]);
+        assert!(index.favorites().unwrap().is_empty());
+        assert!(index.session_tags().unwrap().is_empty());
+        assert_eq!(index.recent_changes(1).unwrap()[0].kind, ChangeKind::Deleted);
+    }
 }
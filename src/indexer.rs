@@ -1,67 +1,279 @@
-use crate::index::{PromptRecord, SessionIndex, SessionRecord};
-use crate::parser;
+use crate::cleanup;
+use crate::config::Config;
+use crate::index::{self, ChangeKind, PromptRecord, SessionIndex, SessionRecord, SessionStats};
+use crate::models::{PriceTable, ProjectInfo};
+use crate::parser::{self, ClaudeRoot};
 use anyhow::Result;
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Changes kept in the `index_events` changefeed; older ones are dropped
+/// after each update.
+pub const MAX_INDEX_EVENTS: usize = 1000;
+
 pub fn default_db_path() -> Option<PathBuf> {
     dirs::cache_dir().map(|c| c.join("cc-sessions-viewer").join("index.db"))
 }
 
-fn default_projects_dir() -> Option<PathBuf> {
-    dirs::home_dir().map(|h| h.join(".claude").join("projects"))
+/// Settings that change what gets stored in the index.
+#[derive(Debug, Clone, Default)]
+pub struct IndexOptions {
+    /// Prices used for the stored cost estimate.
+    pub prices: PriceTable,
+    /// Keep only this many characters of each prompt; 0 stores them whole.
+    pub max_prompt_chars: usize,
+    /// Record the project's CLAUDE.md and settings files whenever their
+    /// content changed since the last run.
+    pub snapshot_instructions: bool,
+    /// `archive_dir` from the config; archived transcripts are indexed too so
+    /// they are not taken for expired ones.
+    pub archive_dir: String,
+}
+
+impl IndexOptions {
+    pub fn from_config(config: &Config) -> IndexOptions {
+        IndexOptions {
+            prices: config.prices.clone(),
+            max_prompt_chars: config.max_prompt_chars,
+            snapshot_instructions: config.snapshot_instructions,
+            archive_dir: config.archive_dir.clone(),
+        }
+    }
+}
+
+/// Files of a project recorded with `snapshot_instructions`, relative to it.
+pub const INSTRUCTION_FILES: [&str; 4] = ["CLAUDE.md", "CLAUDE.local.md", ".claude/settings.json", ".claude/settings.local.json"];
+
+/// Reported after each project directory has been indexed; the final value
+/// is returned as the report of the whole run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexProgress {
+    pub projects_done: usize,
+    pub projects_total: usize,
+    pub sessions_scanned: usize,
+    /// Sessions that were new or changed and had to be parsed.
+    pub sessions_updated: usize,
+    /// Of `sessions_updated`, the ones not in the index before.
+    pub sessions_added: usize,
+    /// Complete lines of parsed transcripts that were not valid JSON.
+    pub bad_lines: usize,
+    /// `path: error` for each file or directory that could not be read.
+    pub unreadable: Vec<String>,
+    /// `path: repair` for each transcript that had a byte-order mark or
+    /// invalid UTF-8 and was indexed with the bytes replaced.
+    pub repaired: Vec<String>,
+    /// `path: reason` for each symlinked project directory that was not
+    /// followed because it loops back up the tree, duplicates another
+    /// project directory or is dangling.
+    pub skipped: Vec<String>,
+    /// Bytes of prompt text left out by `max_prompt_chars`.
+    pub prompt_bytes_saved: u64,
+    /// The existing database was damaged, so it was set aside and this run
+    /// rebuilds the index from scratch.
+    pub recovered: bool,
+    /// Where the damaged database was moved when `recovered`.
+    pub backup: Option<PathBuf>,
+    /// Bookmarks, favorites, tags and instruction snapshots copied over from
+    /// the damaged database when `recovered`.
+    pub salvaged_rows: usize,
+    /// Project directory that was just indexed.
+    pub dir_name: String,
 }
 
-pub fn build_index(db_path: &Path, projects_dir: &Path) -> Result<()> {
+impl IndexProgress {
+    /// Sessions whose file was unchanged since the last run.
+    pub fn sessions_skipped(&self) -> usize {
+        self.sessions_scanned.saturating_sub(self.sessions_updated)
+    }
+
+    /// One-line summary of the run, naming anything that could not be indexed.
+    pub fn summary(&self) -> String {
+        let mut out = format!(
+            "Indexed {} sessions ({} new, {} updated, {} unchanged)",
+            self.sessions_scanned,
+            self.sessions_added,
+            self.sessions_updated - self.sessions_added,
+            self.sessions_skipped()
+        );
+        if self.prompt_bytes_saved > 0 {
+            out.push_str(&format!(", {} KiB of long prompts not stored", self.prompt_bytes_saved.div_ceil(1024)));
+        }
+        let mut problems = Vec::new();
+        match self.unreadable.as_slice() {
+            [] => {}
+            [only] => problems.push(format!("1 unreadable file ({})", only)),
+            [first, rest @ ..] => problems.push(format!("{} unreadable files ({}, …)", rest.len() + 1, first)),
+        }
+        match self.skipped.as_slice() {
+            [] => {}
+            [only] => problems.push(format!("1 directory skipped ({})", only)),
+            [first, rest @ ..] => problems.push(format!("{} directories skipped ({}, …)", rest.len() + 1, first)),
+        }
+        match self.repaired.as_slice() {
+            [] => {}
+            [only] => problems.push(format!("1 file repaired ({})", only)),
+            [first, rest @ ..] => problems.push(format!("{} files repaired ({}, …)", rest.len() + 1, first)),
+        }
+        if self.bad_lines > 0 {
+            problems.push(format!("{} malformed lines skipped", self.bad_lines));
+        }
+        if !problems.is_empty() {
+            out.push_str("; ");
+            out.push_str(&problems.join(", "));
+        }
+        out
+    }
+
+    /// Some file or line could not be indexed.
+    pub fn has_problems(&self) -> bool {
+        !self.unreadable.is_empty() || !self.skipped.is_empty() || !self.repaired.is_empty() || self.bad_lines > 0
+    }
+}
+
+pub fn build_index(db_path: &Path, projects_dir: &Path) -> Result<IndexProgress> {
+    build_index_with_progress(db_path, &[ClaudeRoot::new(projects_dir)], &IndexOptions::default(), |_| {})
+}
+
+/// Bring the index at `db_path` up to date with the sessions under `roots`
+/// and report what was done. A damaged database is moved aside and rebuilt
+/// instead of failing; `IndexProgress::recovered` says so. An index that
+/// does not match the configured key fails with `index::KeyMismatch` and is
+/// never rebuilt.
+pub fn build_index_with_progress(
+    db_path: &Path,
+    roots: &[ClaudeRoot],
+    options: &IndexOptions,
+    mut on_progress: impl FnMut(&IndexProgress),
+) -> Result<IndexProgress> {
+    match update_index(db_path, roots, options, None, &mut on_progress) {
+        Err(e) if index::is_corruption(&e) => {
+            let backup = SessionIndex::discard(db_path)?;
+            // 索引し直せない利用者のデータだけは壊れたファイルから拾えるだけ拾う
+            let salvaged_rows = SessionIndex::open(db_path)?.salvage_user_data(&backup);
+            update_index(db_path, roots, options, Some((backup, salvaged_rows)), &mut on_progress)
+        }
+        result => result,
+    }
+}
+
+fn update_index(
+    db_path: &Path,
+    roots: &[ClaudeRoot],
+    options: &IndexOptions,
+    recovered: Option<(PathBuf, usize)>,
+    on_progress: &mut impl FnMut(&IndexProgress),
+) -> Result<IndexProgress> {
     let index = SessionIndex::open(db_path)?;
+    let (backup, salvaged_rows) = recovered.map_or((None, 0), |(backup, rows)| (Some(backup), rows));
+    let recovered = backup.is_some();
 
-    if !projects_dir.exists() {
-        return Ok(());
+    if !roots.iter().any(|r| r.projects_dir.exists()) {
+        return Ok(IndexProgress { recovered, backup, salvaged_rows, ..IndexProgress::default() });
     }
 
-    let project_dirs: Vec<_> = fs::read_dir(projects_dir)?
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
-        .collect();
+    // ルートをまたいで同じ実体のディレクトリは一度だけ読む
+    let mut real_dirs = HashSet::new();
+    let mut project_dirs = Vec::new();
+    let mut skipped = Vec::new();
+    for root in roots.iter().filter(|r| r.projects_dir.exists()) {
+        let (dirs, root_skipped) = parser::project_dirs_in(&root.projects_dir, &mut real_dirs)?;
+        project_dirs.extend(dirs.into_iter().map(|(name, path)| (root, name, path)));
+        skipped.extend(root_skipped);
+    }
 
-    for project_entry in &project_dirs {
-        let dir_name = project_entry.file_name().to_string_lossy().to_string();
-        let project_dir = project_entry.path();
+    let mut seen_ids = HashSet::new();
+    let mut projects = Vec::new();
+    let mut progress = IndexProgress {
+        projects_total: project_dirs.len(),
+        skipped,
+        recovered,
+        backup,
+        salvaged_rows,
+        ..IndexProgress::default()
+    };
 
+    for (root, dir_name, project_dir) in project_dirs {
         let index_metadata = read_index_metadata(&project_dir);
+        // プロジェクト単位でまとめてコミットする（自動コミットだと1文ごとに同期が走る）
+        let tx = index.transaction()?;
 
-        let jsonl_files: Vec<_> = fs::read_dir(&project_dir)
-            .into_iter()
-            .flatten()
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                e.path()
-                    .extension()
-                    .map(|ext| ext == "jsonl")
-                    .unwrap_or(false)
-            })
-            .collect();
+        let entries = fs::read_dir(&project_dir);
+        if let Err(e) = &entries {
+            progress.unreadable.push(format!("{}: {}", project_dir.display(), e));
+        }
+        let mut jsonl_files: Vec<PathBuf> = jsonl_files_in(entries);
+        let live_count = jsonl_files.len();
+        // アーカイブしたセッションも索引に残す（見えないと期限切れ扱いになる）
+        let archive = cleanup::archive_dir(&options.archive_dir, &project_dir);
+        if archive.is_dir() {
+            jsonl_files.extend(jsonl_files_in(fs::read_dir(&archive)));
+        }
 
-        for jsonl_entry in &jsonl_files {
-            let path = jsonl_entry.path();
+        for path in &jsonl_files {
             let session_id = path
                 .file_stem()
                 .map(|s| s.to_string_lossy().to_string())
                 .unwrap_or_default();
+            // 同じセッションが複数のルートにあれば先のルートのものだけを索引する
+            if !seen_ids.insert(session_id.clone()) {
+                continue;
+            }
+            progress.sessions_scanned += 1;
 
-            let file_mtime = fs::metadata(&path)
+            let file_mtime = fs::metadata(path)
                 .ok()
                 .and_then(|m| m.modified().ok())
                 .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
                 .map(|d| d.as_millis() as i64)
                 .unwrap_or(0);
 
-            if let Some(stored_mtime) = index.get_file_mtime(&session_id)? {
-                if stored_mtime == file_mtime {
+            let stored = index.get_file_state(&session_id)?;
+            if stored.is_some_and(|s| s.mtime == file_mtime) {
+                continue;
+            }
+            let bytes = match fs::read(path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    progress.unreadable.push(format!("{}: {}", path.display(), e));
                     continue;
                 }
+            };
+            progress.sessions_updated += 1;
+            if stored.is_none() {
+                progress.sessions_added += 1;
             }
 
+            // 追記のみなら前回の続きから読む。縮んだ・行境界でない場合は全体を再解析
+            let append_from = stored
+                .map(|s| s.offset as usize)
+                .filter(|&off| off > 0 && off <= bytes.len() && bytes[off - 1] == b'\n');
+            let start = append_from.unwrap_or(0);
+            let tail = &bytes[start..];
+            let (tail_text, repair) = parser::decode_transcript(tail);
+            if let Some(repair) = repair {
+                progress.repaired.push(format!("{}: {}", path.display(), repair));
+            }
+            progress.bad_lines += count_bad_lines(&tail_text);
+            let mut prompts = extract_user_prompts(&tail_text);
+            if options.max_prompt_chars > 0 {
+                for p in &mut prompts {
+                    progress.prompt_bytes_saved += truncate_prompt(&mut p.prompt, options.max_prompt_chars) as u64;
+                }
+            }
+            let files = parser::session_files(&tail_text);
+            let tools = parser::tool_counts(&tail_text);
+            let commands = parser::command_counts(&tail_text);
+            let tail_range = parser::timestamp_range(&tail_text)
+                .map(|(first, last)| (first.timestamp(), last.timestamp()));
+            // 書きかけの最終行は次回に持ち越す
+            let file_offset = start
+                + tail
+                    .iter()
+                    .rposition(|&b| b == b'\n')
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+
             let meta = index_metadata.get(&session_id);
 
             let project_path = meta
@@ -74,15 +286,65 @@ pub fn build_index(db_path: &Path, projects_dir: &Path) -> Result<()> {
             let created_at = meta.map(|m| m.created_at.clone()).unwrap_or_default();
             let modified_at = meta.map(|m| m.modified_at.clone()).unwrap_or_default();
 
-            let prompts = extract_user_prompts(&path);
-
-            let first_prompt = if first_prompt_meta.is_empty() {
+            let mut models = parser::session_models(&tail_text);
+            // 再開元は先頭の行でわかるので、追記分だけを読んだときは保存済みの値を使う
+            let parent_session_id = if append_from.is_some() {
+                index.get_parent_session_id(&session_id)?.unwrap_or_default()
+            } else {
+                parser::parent_session_id(&tail_text, &session_id).unwrap_or_default()
+            };
+            // 追記分にバージョンの記録がなければ保存済みの値を使う
+            let (mut version, mut user_type) = parser::client_info(&tail_text);
+            if append_from.is_some() && (version.is_empty() || user_type.is_empty()) {
+                let (stored_version, stored_user_type) = index.get_client_info(&session_id)?.unwrap_or_default();
+                if version.is_empty() {
+                    version = stored_version;
+                }
+                if user_type.is_empty() {
+                    user_type = stored_user_type;
+                }
+            }
+            // 追記時は最初の時刻を保存済みの値から引き継ぐ
+            let stored_stats = if append_from.is_some() {
+                index.get_stats(&session_id)?.unwrap_or_default()
+            } else {
+                SessionStats::default()
+            };
+            let stats = SessionStats {
+                first_timestamp: match tail_range {
+                    Some((first, _)) if stored_stats.first_timestamp == 0 => first,
+                    _ => stored_stats.first_timestamp,
+                },
+                last_timestamp: tail_range
+                    .map(|(_, last)| last.max(stored_stats.last_timestamp))
+                    .unwrap_or(stored_stats.last_timestamp),
+                file_size: bytes.len() as i64,
+            };
+            let (stored_first_prompt, usage) = if append_from.is_some() {
+                let mut stored_models = index.get_models(&session_id)?;
+                for model in models.drain(..) {
+                    if !stored_models.contains(&model) {
+                        stored_models.push(model);
+                    }
+                }
+                models = stored_models;
+                let mut usage = index.get_usage(&session_id)?.unwrap_or_default();
+                let last_id = Some(usage.last_message_id.as_str()).filter(|id| !id.is_empty());
+                let appended = parser::summarize_usage(&tail_text, &options.prices, last_id);
+                usage.extend(&appended);
+                (index.get_first_prompt(&session_id)?.unwrap_or_default(), usage)
+            } else {
+                (String::new(), parser::summarize_usage(&tail_text, &options.prices, None))
+            };
+            let first_prompt = if !first_prompt_meta.is_empty() {
+                first_prompt_meta
+            } else if !stored_first_prompt.is_empty() {
+                stored_first_prompt
+            } else {
                 prompts
                     .first()
                     .map(|p| p.prompt.clone())
                     .unwrap_or_default()
-            } else {
-                first_prompt_meta
             };
 
             index.upsert_session(&SessionRecord {
@@ -96,22 +358,94 @@ pub fn build_index(db_path: &Path, projects_dir: &Path) -> Result<()> {
                 created_at,
                 modified_at,
                 file_mtime,
+                file_offset: file_offset as i64,
+                usage,
+                models,
+                parent_session_id,
+                stats,
+                version,
+                user_type,
+                source_root: root.label.clone(),
             })?;
 
-            index.insert_prompts(&session_id, &prompts)?;
+            let change = if stored.is_some() { ChangeKind::Updated } else { ChangeKind::Added };
+            index.record_change(change, &session_id, &dir_name)?;
+
+            if append_from.is_some() {
+                index.append_prompts(&session_id, &prompts)?;
+                index.append_files(&session_id, &files)?;
+                index.append_tool_counts(&session_id, &tools)?;
+                index.append_command_counts(&session_id, &commands)?;
+            } else {
+                index.insert_prompts(&session_id, &prompts)?;
+                index.insert_files(&session_id, &files)?;
+                index.insert_tool_counts(&session_id, &tools)?;
+                index.insert_command_counts(&session_id, &commands)?;
+            }
+            index.refresh_activity(&session_id)?;
         }
+
+        let original_path =
+            parser::try_get_original_path(&project_dir).unwrap_or_else(|| parser::decode_project_path(&dir_name));
+        if options.snapshot_instructions {
+            snapshot_instructions(&index, &original_path)?;
+        }
+
+        tx.commit()?;
+
+        parser::merge_project(
+            &mut projects,
+            ProjectInfo {
+                original_path,
+                dir_name: dir_name.clone(),
+                session_count: live_count,
+            },
+        );
+
+        progress.projects_done += 1;
+        progress.dir_name = dir_name;
+        on_progress(&progress);
     }
 
+    let tx = index.transaction()?;
+    index.expire_missing(&seen_ids)?;
+    index.trim_changes(MAX_INDEX_EVENTS)?;
+    projects.sort_by(|a, b| a.dir_name.cmp(&b.dir_name));
+    index.replace_projects(&projects)?;
+    tx.commit()?;
+
+    Ok(progress)
+}
+
+/// 変わっていればプロジェクトの CLAUDE.md と設定ファイルの今の内容を残す
+fn snapshot_instructions(index: &SessionIndex, project_path: &str) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    for file in INSTRUCTION_FILES {
+        let path = Path::new(project_path).join(file);
+        // 無いファイルや読めないファイルは記録しない
+        if let Ok(content) = fs::read_to_string(&path) {
+            index.record_instruction_file(&path.to_string_lossy(), &content, now)?;
+        }
+    }
     Ok(())
 }
 
-pub fn build_default_index() -> Result<PathBuf> {
+pub fn build_default_index() -> Result<(PathBuf, IndexProgress)> {
+    build_default_index_with_progress(&IndexOptions::from_config(&Config::load()), |_| {})
+}
+
+pub fn build_default_index_with_progress(
+    options: &IndexOptions,
+    on_progress: impl FnMut(&IndexProgress),
+) -> Result<(PathBuf, IndexProgress)> {
     let db_path =
         default_db_path().ok_or_else(|| anyhow::anyhow!("Could not determine cache directory"))?;
-    let projects_dir = default_projects_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
-    build_index(&db_path, &projects_dir)?;
-    Ok(db_path)
+    let roots = parser::claude_roots();
+    if roots.is_empty() {
+        anyhow::bail!("Could not determine home directory");
+    }
+    let report = build_index_with_progress(&db_path, roots, options, on_progress)?;
+    Ok((db_path, report))
 }
 
 struct IndexEntryMeta {
@@ -188,11 +522,40 @@ fn read_index_metadata(project_dir: &Path) -> std::collections::HashMap<String,
     map
 }
 
-fn extract_user_prompts(jsonl_path: &Path) -> Vec<PromptRecord> {
-    let content = match fs::read_to_string(jsonl_path) {
-        Ok(c) => c,
-        Err(_) => return Vec::new(),
-    };
+/// Complete (newline-terminated) non-empty lines that are not valid JSON.
+/// A trailing line still being written is not counted.
+/// `.jsonl` files among `entries`; none if the directory could not be read.
+fn jsonl_files_in(entries: std::io::Result<fs::ReadDir>) -> Vec<PathBuf> {
+    entries
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect()
+}
+
+fn count_bad_lines(content: &str) -> usize {
+    content
+        .split_inclusive('\n')
+        .filter(|line| line.ends_with('\n') && !line.trim().is_empty())
+        .filter(|line| serde_json::from_str::<serde::de::IgnoredAny>(line).is_err())
+        .count()
+}
+
+/// Cut `prompt` to its first `max_chars` characters, returning the bytes removed.
+fn truncate_prompt(prompt: &mut String, max_chars: usize) -> usize {
+    match prompt.char_indices().nth(max_chars) {
+        Some((end, _)) => {
+            let removed = prompt.len() - end;
+            prompt.truncate(end);
+            removed
+        }
+        None => 0,
+    }
+}
+
+fn extract_user_prompts(content: &str) -> Vec<PromptRecord> {
     let mut prompts = Vec::new();
     for line in content.lines() {
         let line = line.trim();
@@ -292,6 +655,568 @@ mod tests {
         assert_eq!(results[0].prompts[0], "First");
     }
 
+    fn set_mtime(path: &Path, secs: u64) {
+        let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn appended_lines_are_indexed_from_stored_offset() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let first = "{\"type\":\"user\",\"timestamp\":\"2026-01-15T10:00:00Z\",\"message\":{\"content\":\"First\"}}\n";
+        let jsonl_path = project_dir.join("sess-1.jsonl");
+        fs::write(&jsonl_path, first).unwrap();
+        set_mtime(&jsonl_path, 1_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let state = index.get_file_state("sess-1").unwrap().unwrap();
+        assert_eq!(state.offset, first.len() as i64);
+
+        // 追記：完全な行と書きかけの行
+        let second = "{\"type\":\"user\",\"timestamp\":\"2026-01-15T10:05:00Z\",\"message\":{\"content\":\"Second\"}}\n";
+        let partial = "{\"type\":\"user\",\"timestamp\":\"2026-01-15T10:06:00Z\",\"mess";
+        fs::write(&jsonl_path, format!("{}{}{}", first, second, partial)).unwrap();
+        set_mtime(&jsonl_path, 2_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let results = index.search_all().unwrap();
+        assert_eq!(results[0].prompts, vec!["First", "Second"]);
+        let state = index.get_file_state("sess-1").unwrap().unwrap();
+        assert_eq!(state.offset, (first.len() + second.len()) as i64);
+
+        let stored_first = index.get_first_prompt("sess-1").unwrap().unwrap();
+        assert_eq!(stored_first, "First");
+    }
+
+    #[test]
+    fn parent_session_id_is_kept_across_appends() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let copied = "{\"type\":\"user\",\"sessionId\":\"orig\",\"message\":{\"content\":\"First\"}}\n";
+        let jsonl_path = project_dir.join("resumed.jsonl");
+        fs::write(&jsonl_path, copied).unwrap();
+        set_mtime(&jsonl_path, 1_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let appended = "{\"type\":\"user\",\"sessionId\":\"resumed\",\"message\":{\"content\":\"Next\"}}\n";
+        fs::write(&jsonl_path, format!("{}{}", copied, appended)).unwrap();
+        set_mtime(&jsonl_path, 2_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        assert_eq!(index.get_parent_session_id("resumed").unwrap().as_deref(), Some("orig"));
+        assert_eq!(index.search_all().unwrap()[0].parent_session_id, "orig");
+    }
+
+    #[test]
+    fn client_version_follows_appends() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let first = "{\"type\":\"user\",\"version\":\"2.0.9\",\"userType\":\"external\",\"message\":{\"content\":\"First\"}}\n";
+        let jsonl_path = project_dir.join("sess-1.jsonl");
+        fs::write(&jsonl_path, first).unwrap();
+        set_mtime(&jsonl_path, 1_000);
+        build_index(&db_path, &projects_dir).unwrap();
+        let index = SessionIndex::open(&db_path).unwrap();
+        assert_eq!(
+            index.get_client_info("sess-1").unwrap(),
+            Some(("2.0.9".to_string(), "external".to_string()))
+        );
+
+        // バージョンのない行だけが追記されても保存済みの値が残る
+        let plain = "{\"type\":\"user\",\"message\":{\"content\":\"Next\"}}\n";
+        fs::write(&jsonl_path, format!("{}{}", first, plain)).unwrap();
+        set_mtime(&jsonl_path, 2_000);
+        build_index(&db_path, &projects_dir).unwrap();
+        assert_eq!(index.get_client_info("sess-1").unwrap().unwrap().0, "2.0.9");
+
+        let upgraded = "{\"type\":\"user\",\"version\":\"2.0.10\",\"message\":{\"content\":\"Later\"}}\n";
+        fs::write(&jsonl_path, format!("{}{}{}", first, plain, upgraded)).unwrap();
+        set_mtime(&jsonl_path, 3_000);
+        build_index(&db_path, &projects_dir).unwrap();
+        assert_eq!(
+            index.get_client_info("sess-1").unwrap(),
+            Some(("2.0.10".to_string(), "external".to_string()))
+        );
+    }
+
+    #[test]
+    fn touched_files_tool_and_command_counts_accumulate_across_appends() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let read = "{\"type\":\"assistant\",\"message\":{\"content\":[{\"type\":\"tool_use\",\"id\":\"t1\",\"name\":\"Read\",\"input\":{\"file_path\":\"/p/src/app.rs\"}}]}}\n";
+        let jsonl_path = project_dir.join("s1.jsonl");
+        let bash = "{\"type\":\"assistant\",\"message\":{\"content\":[{\"type\":\"tool_use\",\"id\":\"t3\",\"name\":\"Bash\",\"input\":{\"command\":\"cd /p && cargo test\"}}]}}\n";
+        fs::write(&jsonl_path, format!("{}{}", read, bash)).unwrap();
+        set_mtime(&jsonl_path, 1_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let edit = read.replace("Read", "Edit").replace("t1", "t2");
+        fs::write(&jsonl_path, format!("{}{}{}{}{}", read, bash, edit, read, bash)).unwrap();
+        set_mtime(&jsonl_path, 2_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let pair = |t: &str| ("/p/src/app.rs".to_string(), t.to_string());
+        assert_eq!(index.get_files("s1").unwrap(), vec![pair("Read"), pair("Edit")]);
+        assert_eq!(
+            index.get_tool_counts("s1").unwrap(),
+            vec![("Bash".to_string(), 2), ("Read".to_string(), 2), ("Edit".to_string(), 1)]
+        );
+        assert_eq!(index.get_command_counts("s1").unwrap(), vec![("cargo test".to_string(), 2)]);
+        assert_eq!(index.sessions_using_tool("Edit").unwrap(), vec!["s1"]);
+        assert!(index.sessions_using_tool("WebFetch").unwrap().is_empty());
+        let touches = index.sessions_touching("src/app.rs", true).unwrap();
+        assert_eq!(touches.len(), 1);
+        assert!(touches[0].modified);
+    }
+
+    #[test]
+    fn duration_and_file_size_follow_appends() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let first = "{\"type\":\"user\",\"timestamp\":\"2026-01-15T10:00:00Z\",\"message\":{\"content\":\"First\"}}\n";
+        let jsonl_path = project_dir.join("s1.jsonl");
+        fs::write(&jsonl_path, first).unwrap();
+        set_mtime(&jsonl_path, 1_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let stats = index.get_stats("s1").unwrap().unwrap();
+        assert_eq!(stats.duration_secs(), 0);
+        assert_eq!(stats.file_size, first.len() as i64);
+
+        let later = "{\"type\":\"user\",\"timestamp\":\"2026-01-15T10:45:30Z\",\"message\":{\"content\":\"Later\"}}\n";
+        fs::write(&jsonl_path, format!("{}{}", first, later)).unwrap();
+        set_mtime(&jsonl_path, 2_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let stats = index.get_stats("s1").unwrap().unwrap();
+        assert_eq!(stats.duration_secs(), 45 * 60 + 30);
+        assert_eq!(stats.file_size, (first.len() + later.len()) as i64);
+    }
+
+    #[test]
+    fn usage_accumulates_across_appends_without_double_counting() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        // m1 は2エントリに分割され、追記の境界をまたぐ
+        let part = |id: &str, input: u64| {
+            format!(
+                "{{\"type\":\"assistant\",\"message\":{{\"id\":\"{}\",\"model\":\"claude-sonnet-4\",\"usage\":{{\"input_tokens\":{},\"output_tokens\":1}}}}}}\n",
+                id, input
+            )
+        };
+        let jsonl_path = project_dir.join("sess-1.jsonl");
+        fs::write(&jsonl_path, part("m1", 100)).unwrap();
+        set_mtime(&jsonl_path, 1_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        fs::write(&jsonl_path, format!("{}{}{}", part("m1", 100), part("m1", 100), part("m2", 5))).unwrap();
+        set_mtime(&jsonl_path, 2_000);
+        let mut prices = PriceTable::default();
+        prices.0.insert(
+            "sonnet".to_string(),
+            crate::models::ModelPrice { input: 1_000_000.0, output: 0.0, cache_write: 0.0, cache_read: 0.0 },
+        );
+        build_index_with_progress(&db_path, &[ClaudeRoot::new(&projects_dir)], &IndexOptions { prices, ..IndexOptions::default() }, |_| {}).unwrap();
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let usage = index.get_usage("sess-1").unwrap().unwrap();
+        assert_eq!(usage.usage.input_tokens, 105);
+        assert_eq!(usage.usage.output_tokens, 2);
+        assert_eq!(usage.last_message_id, "m2");
+        assert_eq!(index.get_models("sess-1").unwrap(), vec!["claude-sonnet-4"]);
+        // 最初の100トークンは既定価格、追記分の5トークンは設定価格
+        let expected = (100.0 * 3.0 + 15.0) / 1e6 + 5.0;
+        assert!((usage.cost_usd - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn shrunk_file_is_fully_reparsed() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let jsonl_path = project_dir.join("sess-1.jsonl");
+        fs::write(
+            &jsonl_path,
+            "{\"type\":\"user\",\"message\":{\"content\":\"Old long prompt\"}}\n{\"type\":\"user\",\"message\":{\"content\":\"More\"}}\n",
+        )
+        .unwrap();
+        set_mtime(&jsonl_path, 1_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        fs::write(&jsonl_path, "{\"type\":\"user\",\"message\":{\"content\":\"New\"}}\n").unwrap();
+        set_mtime(&jsonl_path, 2_000);
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let results = index.search_all().unwrap();
+        assert_eq!(results[0].prompts, vec!["New"]);
+    }
+
+    #[test]
+    fn build_index_reports_progress_per_project() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let jsonl = r#"{"type":"user","message":{"content":"Hello"}}"#;
+        for (project, sessions) in [("-a", 2), ("-b", 1)] {
+            let dir = projects_dir.join(project);
+            fs::create_dir_all(&dir).unwrap();
+            for i in 0..sessions {
+                fs::write(dir.join(format!("{}-{}.jsonl", project, i)), jsonl).unwrap();
+            }
+        }
+
+        let mut reports = Vec::new();
+        build_index_with_progress(&db_path, &[ClaudeRoot::new(&projects_dir)], &IndexOptions::default(), |p| {
+            reports.push(p.clone())
+        })
+        .unwrap();
+
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|p| p.projects_total == 2));
+        let last = reports.last().unwrap();
+        assert_eq!(last.projects_done, 2);
+        assert_eq!(last.sessions_scanned, 3);
+        assert_eq!(last.sessions_updated, 3);
+
+        // 変更がなければ再解析しない
+        let mut last = IndexProgress::default();
+        build_index_with_progress(&db_path, &[ClaudeRoot::new(&projects_dir)], &IndexOptions::default(), |p| {
+            last = p.clone()
+        })
+        .unwrap();
+        assert_eq!((last.sessions_scanned, last.sessions_updated), (3, 0));
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        assert_eq!(index.search_project("-a").unwrap().len(), 2);
+        assert_eq!(index.search_project("-b").unwrap().len(), 1);
+
+        let projects = index.list_projects().unwrap();
+        let rows: Vec<(&str, &str, usize)> = projects
+            .iter()
+            .map(|p| (p.dir_name.as_str(), p.original_path.as_str(), p.session_count))
+            .collect();
+        assert_eq!(rows, vec![("-a", "/a", 2), ("-b", "/b", 1)]);
+    }
+
+    #[test]
+    fn build_index_covers_every_root() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let roots = [
+            ClaudeRoot::new(&tmp.path().join(".claude").join("projects")),
+            ClaudeRoot::new(&tmp.path().join(".claude-work").join("projects")),
+        ];
+        let jsonl = r#"{"type":"user","message":{"content":"Hello"}}"#;
+        for (root, project, id) in [(&roots[0], "-a", "s1"), (&roots[1], "-a", "s2"), (&roots[1], "-b", "s3")] {
+            let dir = root.projects_dir.join(project);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("{}.jsonl", id)), jsonl).unwrap();
+        }
+
+        let report = build_index_with_progress(&db_path, &roots, &IndexOptions::default(), |_| {}).unwrap();
+        assert_eq!((report.projects_total, report.sessions_scanned), (3, 3));
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let mut tagged: Vec<(String, String)> =
+            index.search_all().unwrap().into_iter().map(|s| (s.session_id, s.source_root)).collect();
+        tagged.sort();
+        assert_eq!(
+            tagged,
+            vec![
+                ("s1".to_string(), "claude".to_string()),
+                ("s2".to_string(), "claude-work".to_string()),
+                ("s3".to_string(), "claude-work".to_string()),
+            ]
+        );
+        // 両方のルートにあるプロジェクトは1つにまとめる
+        let counts: Vec<(String, usize)> =
+            index.list_projects().unwrap().into_iter().map(|p| (p.dir_name, p.session_count)).collect();
+        assert_eq!(counts, vec![("-a".to_string(), 2), ("-b".to_string(), 1)]);
+
+        // 片方のルートを外すと、そのセッションは期限切れになる
+        build_index_with_progress(&db_path, &roots[..1], &IndexOptions::default(), |_| {}).unwrap();
+        let expired: Vec<bool> = index.search_all().unwrap().iter().filter(|s| s.session_id != "s1").map(|s| s.expired).collect();
+        assert_eq!(expired, vec![true, true]);
+    }
+
+    #[test]
+    fn build_index_counts_a_session_in_two_roots_once() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let roots = [
+            ClaudeRoot::new(&tmp.path().join(".claude").join("projects")),
+            ClaudeRoot::new(&tmp.path().join(".claude-work").join("projects")),
+        ];
+        let jsonl = r#"{"type":"user","message":{"content":"Hello"}}"#;
+        // s1 は両方のルートにコピーされている
+        for (root, id) in [(&roots[0], "s1"), (&roots[1], "s1"), (&roots[1], "s2")] {
+            let dir = root.projects_dir.join("-a");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("{}.jsonl", id)), jsonl).unwrap();
+        }
+
+        let report = build_index_with_progress(&db_path, &roots, &IndexOptions::default(), |_| {}).unwrap();
+        assert_eq!((report.sessions_scanned, report.sessions_added), (2, 2));
+        assert_eq!(report.sessions_skipped(), 0);
+
+        let report = build_index_with_progress(&db_path, &roots, &IndexOptions::default(), |_| {}).unwrap();
+        assert_eq!((report.sessions_scanned, report.sessions_updated, report.sessions_skipped()), (2, 0, 2));
+    }
+
+    #[test]
+    fn build_index_reports_added_updated_and_unreadable_sessions() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let dir = projects_dir.join("-p");
+        fs::create_dir_all(&dir).unwrap();
+        let line = r#"{"type":"user","message":{"content":"Hello"}}"#;
+        fs::write(dir.join("a.jsonl"), format!("{}\n", line)).unwrap();
+        fs::write(dir.join("b.jsonl"), format!("{}\nnot json\n{{\"type\":", line)).unwrap();
+        // 読めない（ディレクトリになっている）ファイル
+        fs::create_dir_all(dir.join("c.jsonl")).unwrap();
+
+        let report = build_index(&db_path, &projects_dir).unwrap();
+        assert_eq!((report.sessions_scanned, report.sessions_added, report.sessions_updated), (3, 2, 2));
+        assert_eq!(report.bad_lines, 1);
+        assert_eq!(report.unreadable.len(), 1);
+        assert!(report.unreadable[0].contains("c.jsonl"));
+        assert!(report.has_problems());
+
+        fs::write(dir.join("a.jsonl"), format!("{}\n{}\n", line, line)).unwrap();
+        let file = fs::File::options().write(true).open(dir.join("a.jsonl")).unwrap();
+        file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(5)).unwrap();
+        let report = build_index(&db_path, &projects_dir).unwrap();
+        assert_eq!((report.sessions_added, report.sessions_updated, report.sessions_skipped()), (0, 1, 2));
+        assert_eq!(report.bad_lines, 0);
+        // 変更履歴には新規2件と更新1件が残る
+        let changes: Vec<(&str, String)> = SessionIndex::open(&db_path)
+            .unwrap()
+            .recent_changes(10)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.kind.label(), c.session_id))
+            .collect();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0], ("updated", "a".to_string()));
+        assert!(changes[1..].iter().all(|(kind, _)| *kind == "added"));
+        assert_eq!(
+            report.summary(),
+            format!(
+                "Indexed 3 sessions (0 new, 1 updated, 2 unchanged); 1 unreadable file ({})",
+                report.unreadable[0]
+            )
+        );
+    }
+
+    #[test]
+    fn build_index_repairs_bom_and_invalid_utf8() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let dir = projects_dir.join("-p");
+        fs::create_dir_all(&dir).unwrap();
+        let mut bytes = b"\xEF\xBB\xBF".to_vec();
+        bytes.extend_from_slice(br#"{"type":"user","message":{"content":"caf"#);
+        bytes.push(0xE9);
+        bytes.extend_from_slice(b"\"}}\n");
+        fs::write(dir.join("win.jsonl"), bytes).unwrap();
+
+        let report = build_index(&db_path, &projects_dir).unwrap();
+        assert_eq!(report.sessions_added, 1);
+        assert_eq!(report.bad_lines, 0);
+        assert_eq!(report.repaired.len(), 1);
+        assert!(report.repaired[0].contains("win.jsonl"));
+        assert!(report.summary().contains("1 file repaired"));
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let sessions = index.search_all().unwrap();
+        assert_eq!(sessions[0].prompts, vec!["caf\u{FFFD}"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_index_skips_symlink_loops_and_duplicates() {
+        use std::os::unix::fs::symlink;
+
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let real = projects_dir.join("-real");
+        fs::create_dir_all(&real).unwrap();
+        fs::write(real.join("s1.jsonl"), "{\"type\":\"user\",\"message\":{\"content\":\"hi\"}}\n").unwrap();
+        let elsewhere = tmp.path().join("elsewhere").join("-linked");
+        fs::create_dir_all(&elsewhere).unwrap();
+        fs::write(elsewhere.join("s2.jsonl"), "{\"type\":\"user\",\"message\":{\"content\":\"yo\"}}\n").unwrap();
+        symlink(&elsewhere, projects_dir.join("-linked")).unwrap();
+        symlink(&projects_dir, projects_dir.join("-loop")).unwrap();
+        symlink(tmp.path(), projects_dir.join("-up")).unwrap();
+        symlink(&real, projects_dir.join("-alias")).unwrap();
+        symlink(tmp.path().join("gone"), projects_dir.join("-dangling")).unwrap();
+
+        let report = build_index(&db_path, &projects_dir).unwrap();
+        // リンク先のプロジェクトは索引し、ループと別名は飛ばす
+        assert_eq!(report.projects_total, 2);
+        assert_eq!(report.sessions_added, 2);
+        let mut skipped = report.skipped.clone();
+        skipped.sort();
+        assert_eq!(skipped.len(), 4);
+        assert!(skipped[0].contains("-alias: same directory as"));
+        assert!(skipped[1].contains("-dangling: broken symlink"));
+        assert!(skipped[2].ends_with("-loop: symlink loop"));
+        assert!(skipped[3].ends_with("-up: symlink loop"));
+        assert!(report.summary().contains("4 directories skipped"));
+        assert!(report.has_problems());
+    }
+
+    #[test]
+    fn summary_lists_problems_compactly() {
+        let report = IndexProgress {
+            sessions_scanned: 4,
+            sessions_updated: 3,
+            sessions_added: 1,
+            bad_lines: 2,
+            unreadable: vec!["/a: denied".to_string(), "/b: denied".to_string()],
+            ..IndexProgress::default()
+        };
+        assert_eq!(
+            report.summary(),
+            "Indexed 4 sessions (1 new, 2 updated, 1 unchanged); 2 unreadable files (/a: denied, …), 2 malformed lines skipped"
+        );
+        assert!(!IndexProgress::default().has_problems());
+    }
+
+    #[test]
+    fn corrupted_database_is_set_aside_and_rebuilt() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("index.db");
+        let projects_dir = tmp.path().join("projects");
+        fs::create_dir_all(projects_dir.join("-p")).unwrap();
+        fs::write(
+            projects_dir.join("-p").join("s1.jsonl"),
+            r#"{"type":"user","message":{"content":"Hello"}}"#,
+        )
+        .unwrap();
+        fs::write(&db_path, "definitely not sqlite ".repeat(300)).unwrap();
+
+        let mut last = IndexProgress::default();
+        build_index_with_progress(&db_path, &[ClaudeRoot::new(&projects_dir)], &IndexOptions::default(), |p| last = p.clone()).unwrap();
+        assert!(last.recovered);
+        let backup = last.backup.clone().unwrap();
+        assert!(backup.file_name().unwrap().to_string_lossy().starts_with("index.db.corrupt-"));
+        assert!(backup.exists());
+        assert_eq!(last.salvaged_rows, 0);
+        let index = SessionIndex::open(&db_path).unwrap();
+        assert_eq!(index.search_all().unwrap()[0].prompts, vec!["Hello"]);
+
+        build_index_with_progress(&db_path, &[ClaudeRoot::new(&projects_dir)], &IndexOptions::default(), |p| last = p.clone()).unwrap();
+        assert!(!last.recovered);
+    }
+
+    #[test]
+    fn max_prompt_chars_truncates_stored_prompts() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let dir = projects_dir.join("-p");
+        fs::create_dir_all(&dir).unwrap();
+        let pasted = format!("review this: {}", "fn main() {} ".repeat(100));
+        let jsonl = format!(
+            "{}\n{}\n",
+            serde_json::json!({"type": "user", "message": {"content": pasted}}),
+            serde_json::json!({"type": "user", "message": {"content": "ok"}})
+        );
+        fs::write(dir.join("s1.jsonl"), jsonl).unwrap();
+
+        let options = IndexOptions { max_prompt_chars: 11, ..IndexOptions::default() };
+        let mut last = IndexProgress::default();
+        build_index_with_progress(&db_path, &[ClaudeRoot::new(&projects_dir)], &options, |p| last = p.clone()).unwrap();
+        assert_eq!(last.prompt_bytes_saved, (pasted.len() - 11) as u64);
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let results = index.search_all().unwrap();
+        assert_eq!(results[0].prompts, vec!["review this", "ok"]);
+        assert_eq!(index.get_first_prompt("s1").unwrap().as_deref(), Some("review this"));
+    }
+
+    #[test]
+    fn snapshot_instructions_records_the_project_files_when_enabled() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-work-app");
+        let workspace = tmp.path().join("app");
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::create_dir_all(workspace.join(".claude")).unwrap();
+        fs::write(
+            project_dir.join("sessions-index.json"),
+            serde_json::json!({ "originalPath": workspace }).to_string(),
+        )
+        .unwrap();
+        fs::write(project_dir.join("s1.jsonl"), r#"{"type":"user","message":{"content":"hi"}}"#).unwrap();
+        fs::write(workspace.join("CLAUDE.md"), "Use tabs").unwrap();
+        fs::write(workspace.join(".claude/settings.json"), "{}").unwrap();
+        let claude_md = workspace.join("CLAUDE.md").to_string_lossy().to_string();
+        let far_future = i64::MAX;
+
+        let roots = [ClaudeRoot::new(&projects_dir)];
+        build_index_with_progress(&db_path, &roots, &IndexOptions::default(), |_| {}).unwrap();
+        let index = SessionIndex::open(&db_path).unwrap();
+        assert_eq!(index.instruction_file_at(&claude_md, far_future).unwrap(), None);
+
+        let options = IndexOptions { snapshot_instructions: true, ..IndexOptions::default() };
+        build_index_with_progress(&db_path, &roots, &options, |_| {}).unwrap();
+        let snapshot = index.instruction_file_at(&claude_md, far_future).unwrap().unwrap();
+        assert_eq!(snapshot.content, "Use tabs");
+        let settings = workspace.join(".claude/settings.json").to_string_lossy().to_string();
+        assert!(index.instruction_file_at(&settings, far_future).unwrap().is_some());
+        // 変わっていなければ新しい版は増えない
+        assert!(!index.record_instruction_file(&claude_md, "Use tabs", snapshot.captured_at + 1).unwrap());
+    }
+
+    #[test]
+    fn truncate_prompt_respects_char_boundaries() {
+        let mut prompt = "日本語のプロンプト".to_string();
+        assert_eq!(truncate_prompt(&mut prompt, 3), 18);
+        assert_eq!(prompt, "日本語");
+        assert_eq!(truncate_prompt(&mut prompt, 3), 0);
+        assert_eq!(truncate_prompt(&mut prompt, 10), 0);
+    }
+
     #[test]
     fn uses_sessions_index_json_metadata() {
         let tmp = TempDir::new().unwrap();
@@ -335,4 +1260,62 @@ mod tests {
         assert_eq!(results[0].prompts.len(), 1);
         assert_eq!(results[0].prompts[0], "Hello");
     }
+
+    #[test]
+    fn rebuild_marks_deleted_sessions_expired() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let jsonl = r#"{"type":"user","timestamp":"2026-01-15T10:00:00Z","message":{"content":"Hello"}}"#;
+        fs::write(project_dir.join("sess-1.jsonl"), jsonl).unwrap();
+        fs::write(project_dir.join("sess-2.jsonl"), jsonl).unwrap();
+        build_index(&db_path, &projects_dir).unwrap();
+
+        fs::remove_file(project_dir.join("sess-2.jsonl")).unwrap();
+        build_index(&db_path, &projects_dir).unwrap();
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        let mut results = index.search_all().unwrap();
+        results.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+        assert_eq!(results.len(), 2);
+        assert!(!results[0].expired);
+        assert!(results[1].expired);
+        assert_eq!(results[1].prompts, vec!["Hello"]);
+
+        // ファイルが戻れば再び通常のセッションになる
+        fs::write(project_dir.join("sess-2.jsonl"), jsonl).unwrap();
+        build_index(&db_path, &projects_dir).unwrap();
+        assert!(index.search_all().unwrap().iter().all(|s| !s.expired));
+    }
+
+    #[test]
+    fn archived_sessions_stay_indexed() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let projects_dir = tmp.path().join("projects");
+        let project_dir = projects_dir.join("-project");
+        fs::create_dir_all(&project_dir).unwrap();
+        let jsonl = r#"{"type":"user","timestamp":"2026-01-15T10:00:00Z","message":{"content":"Hello"}}"#;
+        fs::write(project_dir.join("sess-1.jsonl"), jsonl).unwrap();
+        fs::write(project_dir.join("sess-2.jsonl"), jsonl).unwrap();
+        build_index(&db_path, &projects_dir).unwrap();
+
+        crate::cleanup::archive_session(&project_dir.join("sess-2.jsonl"), &project_dir.join("archive")).unwrap();
+        let report = build_index(&db_path, &projects_dir).unwrap();
+        assert_eq!(report.sessions_scanned, 2);
+
+        let index = SessionIndex::open(&db_path).unwrap();
+        assert!(index.search_all().unwrap().iter().all(|s| !s.expired));
+        assert_eq!(index.list_projects().unwrap()[0].session_count, 1);
+
+        // 設定したアーカイブ先も読む
+        let elsewhere = tmp.path().join("vault");
+        let options = IndexOptions { archive_dir: elsewhere.to_string_lossy().to_string(), ..IndexOptions::default() };
+        crate::cleanup::archive_session(&project_dir.join("archive").join("sess-2.jsonl"), &elsewhere.join("-project")).unwrap();
+        build_index_with_progress(&db_path, &[ClaudeRoot::new(&projects_dir)], &options, |_| {}).unwrap();
+        assert!(index.search_all().unwrap().iter().all(|s| !s.expired));
+    }
 }
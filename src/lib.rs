@@ -4,3 +4,23 @@ pub mod app;
 pub mod ui;
 pub mod index;
 pub mod indexer;
+pub mod audit;
+pub mod digest;
+pub mod analysis;
+pub mod changes;
+pub mod cleanup;
+pub mod actions;
+pub mod anonymize;
+pub mod list;
+pub mod markdown;
+pub mod ansi;
+pub mod highlight;
+pub mod snapshots;
+pub mod config;
+pub mod clipboard;
+pub mod commands;
+pub mod plugins;
+pub mod scripting;
+pub mod status;
+pub mod sample;
+pub mod corpus;
@@ -0,0 +1,88 @@
+/// Selection and scroll position of a table screen.
+///
+/// `offset` is the first row drawn; `scroll_into_view` keeps the selected row
+/// inside the `visible` rows below it. Methods that move the selection take
+/// the current row count so they never leave it past the end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ListState {
+    pub selected: usize,
+    pub offset: usize,
+}
+
+impl ListState {
+    pub fn reset(&mut self) {
+        *self = ListState::default();
+    }
+
+    pub fn up(&mut self, n: usize) {
+        self.selected = self.selected.saturating_sub(n);
+    }
+
+    pub fn down(&mut self, n: usize, len: usize) {
+        if len > 0 {
+            self.selected = (self.selected + n).min(len - 1);
+        }
+    }
+
+    pub fn bottom(&mut self, len: usize) {
+        if len > 0 {
+            self.selected = len - 1;
+        }
+    }
+
+    /// Select row `i` and scroll it into view.
+    pub fn select(&mut self, i: usize, visible: usize) {
+        self.selected = i;
+        self.scroll_into_view(visible);
+    }
+
+    pub fn scroll_into_view(&mut self, visible: usize) {
+        if visible == 0 {
+            return;
+        }
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + visible {
+            self.offset = self.selected - visible + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn down_and_bottom_stop_at_last_row() {
+        let mut list = ListState::default();
+        list.down(1, 3);
+        assert_eq!(list.selected, 1);
+        list.down(10, 3);
+        assert_eq!(list.selected, 2);
+        list.up(5);
+        assert_eq!(list.selected, 0);
+        list.bottom(4);
+        assert_eq!(list.selected, 3);
+
+        let mut empty = ListState::default();
+        empty.down(1, 0);
+        empty.bottom(0);
+        assert_eq!(empty, ListState::default());
+    }
+
+    #[test]
+    fn scroll_into_view_follows_selection() {
+        let mut list = ListState::default();
+        list.select(7, 5);
+        assert_eq!(list.offset, 3);
+        list.select(1, 5);
+        assert_eq!(list.offset, 1);
+        list.select(3, 5);
+        assert_eq!(list.offset, 1);
+        // 高さ0（端末が極端に小さい）ならスクロールしない
+        list.select(9, 0);
+        assert_eq!(list.offset, 1);
+        list.reset();
+        assert_eq!(list, ListState::default());
+    }
+}
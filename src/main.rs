@@ -1,12 +1,211 @@
-mod models;
-mod parser;
-mod app;
-mod ui;
-pub mod index;
-pub mod indexer;
-
 use anyhow::Result;
+use cc_sessions_viewer::anonymize::Anonymizer;
+use cc_sessions_viewer::cleanup;
+use cc_sessions_viewer::clipboard::{self, Environment};
+use cc_sessions_viewer::config::Config;
+use cc_sessions_viewer::index::{self, SessionIndex};
+use cc_sessions_viewer::indexer;
+use cc_sessions_viewer::parser::{self, ParseDiagnostics};
+use cc_sessions_viewer::plugins::{self, Hook};
+use cc_sessions_viewer::sample::{self, SampleOptions};
+use cc_sessions_viewer::status;
+use std::path::Path;
+
+const USAGE: &str = "usage: cc-sessions-viewer [export-index <file.json> [--anonymize] | import-index <file.json> | compact-index | apply-retention [--days N] | truncate-prompts <chars> | sessions-touching <path> [--modified] | sample [--n N] [--filter key=value]... [--stratify project|version] [--seed N] [--anonymize] | status [--cwd <dir> | --tmux] | doctor | --debug-parse <file.jsonl> | --clip-file <file>]";
 
 fn main() -> Result<()> {
-    app::run()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        // 壊れた索引は作り直してからもう一度だけ実行する
+        Err(e) if index::is_corruption(&e) => {
+            eprintln!("The search index was damaged; rebuilding it from ~/.claude/projects...");
+            if let Some(db_path) = indexer::default_db_path() {
+                let backup = SessionIndex::discard(&db_path)?;
+                let kept = SessionIndex::open(&db_path)?.salvage_user_data(&backup);
+                eprintln!("Moved the old file to {} and kept {} bookmarks/favorites/tags/snapshots", backup.display(), kept);
+            }
+            indexer::build_default_index()?;
+            run(&args)
+        }
+        result => result,
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    match args {
+        [] => cc_sessions_viewer::app::run(None),
+        [flag, path] if flag == "--clip-file" => cc_sessions_viewer::app::run(Some(path.into())),
+        [cmd, path, rest @ ..] if cmd == "export-index" && (rest.is_empty() || rest == ["--anonymize"]) => {
+            let index = open_default_index()?;
+            let anonymizer = if rest.is_empty() { None } else { Some(Anonymizer::for_index(&index)?) };
+            let count = index.export(Path::new(path), anonymizer.as_ref())?;
+            println!("Exported {} sessions to {}", count, path);
+            if let Some(dir) = plugins::plugins_dir() {
+                let path = std::path::absolute(path)?;
+                plugins::run_hook_in(&dir, Hook::OnExport, &serde_json::json!({ "kind": "index", "path": path }));
+            }
+            Ok(())
+        }
+        [cmd, path] if cmd == "import-index" => {
+            let count = open_default_index()?.import(Path::new(path))?;
+            println!("Imported {} sessions from {}", count, path);
+            Ok(())
+        }
+        [cmd] if cmd == "compact-index" => {
+            let stats = open_default_index()?.compact()?;
+            println!(
+                "Removed {} orphaned rows, kept {} bookmarks/favorites/tags of unindexed sessions, reclaimed {} KiB ({} KiB -> {} KiB)",
+                stats.orphans_removed,
+                stats.user_rows_kept,
+                stats.bytes_reclaimed() / 1024,
+                stats.bytes_before / 1024,
+                stats.bytes_after / 1024
+            );
+            Ok(())
+        }
+        [cmd, rest @ ..] if cmd == "apply-retention" && (rest.is_empty() || rest.len() == 2 && rest[0] == "--days") => {
+            // 壊れた設定のまま既定のアーカイブ先へ移さない
+            let config = Config::try_load()?;
+            let days = match rest.get(1) {
+                Some(days) => days.parse().map_err(|_| anyhow::anyhow!("--days needs a number of days"))?,
+                None => config.retention_days,
+            };
+            if days == 0 {
+                anyhow::bail!("apply-retention needs --days N or retention_days in config.toml");
+            }
+            let projects_dirs: Vec<_> = parser::claude_roots().iter().map(|r| r.projects_dir.clone()).collect();
+            let report = cleanup::apply_retention(
+                &open_default_index()?,
+                &projects_dirs,
+                &config.archive_dir,
+                days,
+                chrono::Utc::now(),
+            )?;
+            println!(
+                "Archived {} sessions inactive for {} days or more; kept {} favorites/tagged, {} failed",
+                report.archived, days, report.protected, report.failed
+            );
+            Ok(())
+        }
+        [cmd, chars] if cmd == "truncate-prompts" => {
+            let chars: usize = chars
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| anyhow::anyhow!("truncate-prompts needs a positive character count"))?;
+            let index = open_default_index()?;
+            let truncated = index.truncate_prompts(chars)?;
+            let stats = index.compact()?;
+            println!(
+                "Truncated {} prompts to {} characters, dropping {} KiB of text; reclaimed {} KiB ({} KiB -> {} KiB)",
+                truncated.prompts_truncated,
+                chars,
+                truncated.bytes_saved / 1024,
+                stats.bytes_reclaimed() / 1024,
+                stats.bytes_before / 1024,
+                stats.bytes_after / 1024
+            );
+            Ok(())
+        }
+        [cmd, path, rest @ ..]
+            if cmd == "sessions-touching" && (rest.is_empty() || rest == ["--modified"]) =>
+        {
+            let touches = open_default_index()?.sessions_touching(path, !rest.is_empty())?;
+            for t in touches {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    t.created_at,
+                    t.session_id,
+                    if t.modified { "modified" } else { "read" },
+                    t.project_path,
+                    t.file_path
+                );
+            }
+            Ok(())
+        }
+        [cmd, rest @ ..] if cmd == "sample" => {
+            let options = SampleOptions::parse(rest, chrono::Utc::now().timestamp_micros() as u64)?;
+            let sample = sample::sample(&open_default_index()?, &options)?;
+            // 標本は標準出力に出すので、件数は標準エラーに
+            eprintln!(
+                "Sampled {} of {} matching sessions (seed {})",
+                sample.sessions.len(),
+                sample.matched,
+                sample.seed
+            );
+            println!("{}", serde_json::to_string_pretty(&sample)?);
+            Ok(())
+        }
+        [cmd, flag] if cmd == "status" && flag == "--tmux" => {
+            println!("{}", status::tmux_status());
+            Ok(())
+        }
+        [cmd, rest @ ..] if cmd == "status" && (rest.is_empty() || rest.len() == 2 && rest[0] == "--cwd") => {
+            let dir = std::path::absolute(rest.get(1).map(String::as_str).unwrap_or("."))?;
+            // プロンプトに埋め込むので、該当するプロジェクトが無ければ何も出さない
+            if let Some(line) = status::status_for_dir(&open_default_index()?, &dir)? {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        [cmd] if cmd == "doctor" => doctor(),
+        [flag, path] if flag == "--debug-parse" => {
+            let (content, repaired) = parser::read_transcript(Path::new(path))?;
+            if let Some(repaired) = repaired {
+                println!("repaired: {}\n", repaired);
+            }
+            print!("{}", ParseDiagnostics::of(&content).report());
+            Ok(())
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Print where settings, the index and copies go, to diagnose a setup.
+fn doctor() -> Result<()> {
+    let config = match Config::path() {
+        Some(path) if !path.exists() => {
+            println!("config      {} (missing, using defaults)", path.display());
+            Config::default()
+        }
+        Some(path) => match Config::load_from(&path) {
+            Ok(config) => {
+                println!("config      {}", path.display());
+                config
+            }
+            Err(e) => {
+                println!("config      {} (invalid, using defaults: {})", path.display(), e);
+                Config::default()
+            }
+        },
+        None => {
+            println!("config      (no config directory)");
+            Config::default()
+        }
+    };
+    match indexer::default_db_path() {
+        Some(path) => match std::fs::metadata(&path) {
+            Ok(meta) => println!("index       {} ({} KiB)", path.display(), meta.len() / 1024),
+            Err(_) => println!("index       {} (not built yet)", path.display()),
+        },
+        None => println!("index       (no cache directory)"),
+    }
+    for root in parser::claude_roots() {
+        let state = if root.projects_dir.is_dir() { "" } else { " (missing)" };
+        println!("sessions    {}{}", root.projects_dir.display(), state);
+    }
+    let clip_file = config.clip_file();
+    let (_, reason) = clipboard::detect(config.clipboard, clip_file.as_deref(), &Environment::current());
+    let backend = clipboard::open(config.clipboard, clip_file.as_deref());
+    println!("clipboard   {} ({})", backend.name(), reason);
+    Ok(())
+}
+
+fn open_default_index() -> Result<SessionIndex> {
+    let db_path = indexer::default_db_path()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine cache directory"))?;
+    SessionIndex::open(&db_path)
 }
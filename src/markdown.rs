@@ -0,0 +1,409 @@
+//! Markdown in assistant messages, rendered to styled lines for the detail view.
+//!
+//! Only the structure that matters in a terminal is kept: headings, emphasis,
+//! inline code, fenced code, lists and quotes. Source line breaks inside a
+//! paragraph are preserved, since the view wraps lines itself.
+
+use crate::highlight::Highlighter;
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::style::{Color, Modifier, Style};
+
+/// One rendered line before wrapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MdLine {
+    /// Drawn before the first row: list bullet, quote bar or code gutter.
+    pub lead: String,
+    /// Drawn before each wrapped continuation row, as wide as `lead`.
+    pub hang: String,
+    pub text: String,
+    /// Style of each char of `text`.
+    pub styles: Vec<Style>,
+}
+
+impl MdLine {
+    fn new(lead: String, hang: String) -> MdLine {
+        MdLine { lead, hang, text: String::new(), styles: Vec::new() }
+    }
+
+    fn push(&mut self, s: &str, style: Style) {
+        self.text.push_str(s);
+        self.styles.extend(std::iter::repeat_n(style, s.chars().count()));
+    }
+
+    fn push_styled(&mut self, s: &str, styles: Vec<Style>) {
+        self.text.push_str(s);
+        self.styles.extend(styles);
+    }
+}
+
+/// Style of the bullet, quote bar and code gutter in front of a line.
+pub fn lead_style() -> Style {
+    Style::default().fg(Color::DarkGray)
+}
+
+/// Render `text` as Markdown, with `base` as the style of plain prose.
+/// With `highlight`, fenced code blocks in a known language are
+/// syntax-highlighted instead of drawn in one color.
+pub fn render(text: &str, base: Style, highlight: bool) -> Vec<MdLine> {
+    let mut r = Renderer {
+        base,
+        highlight,
+        highlighter: None,
+        lines: Vec::new(),
+        current: None,
+        inline: Vec::new(),
+        quotes: 0,
+        lists: Vec::new(),
+        bullet: None,
+        code: false,
+        blank_pending: false,
+    };
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    for event in Parser::new_ext(text, options) {
+        r.event(event);
+    }
+    r.end_line();
+    r.lines
+}
+
+/// A fenced code block of a message, for copying.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    /// First word of the info string (`rust`, `sh`, ...), may be empty.
+    pub lang: String,
+    /// The code as written, without the final line break before the fence.
+    pub code: String,
+}
+
+/// The fenced code blocks of `text`, in order. Indented code is left out.
+pub fn code_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut current: Option<CodeBlock> = None;
+    for event in Parser::new_ext(text, Options::empty()) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let lang = info.split_whitespace().next().unwrap_or("").to_string();
+                current = Some(CodeBlock { lang, code: String::new() });
+            }
+            Event::Text(t) => {
+                if let Some(block) = current.as_mut() {
+                    block.code.push_str(&t);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some(mut block) = current.take() {
+                    if block.code.ends_with('\n') {
+                        block.code.pop();
+                    }
+                    blocks.push(block);
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+struct Renderer {
+    base: Style,
+    highlight: bool,
+    /// Set inside a fenced code block whose language is known.
+    highlighter: Option<Highlighter>,
+    lines: Vec<MdLine>,
+    current: Option<MdLine>,
+    /// Styles of the open inline spans (emphasis, links, headings), innermost last.
+    inline: Vec<Style>,
+    quotes: usize,
+    /// Open lists: the next number of an ordered list, `None` for bullets.
+    lists: Vec<Option<u64>>,
+    /// Marker of a list item whose first line has not started yet.
+    bullet: Option<String>,
+    code: bool,
+    /// A block ended; the next block is separated by a blank line.
+    blank_pending: bool,
+}
+
+impl Renderer {
+    fn style(&self) -> Style {
+        self.inline.iter().fold(self.base, |style, patch| style.patch(*patch))
+    }
+
+    /// Lead and hang for a new line in the current containers.
+    fn prefix(&mut self) -> (String, String) {
+        let quote = "│ ".repeat(self.quotes);
+        let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+        let code = if self.code { "▏ " } else { "" };
+        let hang_list = if self.lists.is_empty() { String::new() } else { "  ".to_string() };
+        match self.bullet.take() {
+            Some(bullet) => {
+                let hang = format!("{}{}{}{}", quote, indent, " ".repeat(bullet.chars().count()), code);
+                (format!("{}{}{}{}", quote, indent, bullet, code), hang)
+            }
+            None => {
+                let pad = match self.lists.last() {
+                    Some(Some(n)) => " ".repeat(format!("{}. ", n.saturating_sub(1)).len()),
+                    Some(None) => hang_list,
+                    None => String::new(),
+                };
+                let prefix = format!("{}{}{}{}", quote, indent, pad, code);
+                (prefix.clone(), prefix)
+            }
+        }
+    }
+
+    fn line(&mut self) -> &mut MdLine {
+        if self.current.is_none() {
+            let (lead, hang) = self.prefix();
+            self.current = Some(MdLine::new(lead, hang));
+        }
+        self.current.as_mut().unwrap()
+    }
+
+    fn end_line(&mut self) {
+        if let Some(line) = self.current.take() {
+            self.lines.push(line);
+        }
+    }
+
+    fn start_block(&mut self) {
+        self.end_line();
+        if std::mem::take(&mut self.blank_pending) && !self.lines.is_empty() {
+            let quote = "│ ".repeat(self.quotes);
+            self.lines.push(MdLine::new(quote.clone(), quote));
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let style = self.style();
+        let mut parts = text.split('\n').peekable();
+        while let Some(part) = parts.next() {
+            let last = parts.peek().is_none();
+            if self.code && last && part.is_empty() {
+                // コードブロックの末尾の改行で空行を作らない
+                self.current = None;
+                break;
+            }
+            if let Some(hl) = self.highlighter.as_mut() {
+                let styles = hl.line(part, style);
+                self.line().push_styled(part, styles);
+            } else if !part.is_empty() || self.code {
+                self.line().push(part, style);
+            }
+            if !last {
+                self.end_line();
+            }
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(&text),
+            Event::Code(code) => {
+                let style = self.style().fg(Color::Yellow);
+                self.line().push(&code, style);
+            }
+            Event::InlineMath(s) | Event::DisplayMath(s) | Event::Html(s) | Event::InlineHtml(s) => {
+                self.text(&s);
+            }
+            Event::SoftBreak | Event::HardBreak => self.end_line(),
+            Event::Rule => {
+                self.start_block();
+                self.line().push(&"─".repeat(20), lead_style());
+                self.end_line();
+                self.blank_pending = true;
+            }
+            Event::TaskListMarker(done) => {
+                let style = self.style();
+                self.line().push(if done { "[x] " } else { "[ ] " }, style);
+            }
+            Event::FootnoteReference(name) => {
+                let style = self.style();
+                self.line().push(&format!("[^{}]", name), style);
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.start_block(),
+            Tag::Heading { level, .. } => {
+                self.start_block();
+                let style = match level {
+                    HeadingLevel::H1 => Style::default()
+                        .fg(Color::LightBlue)
+                        .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                    HeadingLevel::H2 => Style::default().fg(Color::LightBlue).add_modifier(Modifier::BOLD),
+                    _ => Style::default().add_modifier(Modifier::BOLD),
+                };
+                self.inline.push(style);
+            }
+            Tag::BlockQuote(_) => {
+                self.start_block();
+                self.quotes += 1;
+                self.inline.push(Style::default().add_modifier(Modifier::ITALIC));
+            }
+            Tag::CodeBlock(kind) => {
+                self.start_block();
+                self.code = true;
+                if let CodeBlockKind::Fenced(lang) = kind
+                    && !lang.is_empty()
+                {
+                    let (lead, hang) = self.prefix();
+                    let mut label = MdLine::new(lead, hang);
+                    label.push(&lang, lead_style());
+                    self.lines.push(label);
+                    if self.highlight {
+                        self.highlighter = Highlighter::for_lang(&lang);
+                    }
+                }
+                self.inline.push(Style::default().fg(Color::Yellow));
+            }
+            Tag::List(start) => {
+                if self.lists.is_empty() {
+                    self.start_block();
+                } else {
+                    self.end_line();
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.end_line();
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.bullet = Some(bullet);
+                self.blank_pending = false;
+            }
+            Tag::Emphasis => self.inline.push(Style::default().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.inline.push(Style::default().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self.inline.push(Style::default().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link { .. } | Tag::Image { .. } => {
+                self.inline.push(Style::default().fg(Color::Blue).add_modifier(Modifier::UNDERLINED));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.end_line();
+                self.blank_pending = true;
+            }
+            TagEnd::Heading(_) => {
+                self.inline.pop();
+                self.end_line();
+                self.blank_pending = true;
+            }
+            TagEnd::BlockQuote(_) => {
+                self.inline.pop();
+                self.end_line();
+                self.quotes -= 1;
+                self.blank_pending = true;
+            }
+            TagEnd::CodeBlock => {
+                self.inline.pop();
+                self.highlighter = None;
+                self.end_line();
+                self.code = false;
+                self.blank_pending = true;
+            }
+            TagEnd::List(_) => {
+                self.end_line();
+                self.lists.pop();
+                self.blank_pending = self.lists.is_empty();
+            }
+            TagEnd::Item => {
+                self.end_line();
+                self.bullet = None;
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link | TagEnd::Image => {
+                self.inline.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[MdLine]) -> Vec<String> {
+        lines.iter().map(|l| format!("{}{}", l.lead, l.text)).collect()
+    }
+
+    #[test]
+    fn render_strips_markup_and_styles_spans() {
+        let base = Style::default().fg(Color::White);
+        let lines = render("# Plan\n\nUse **bold** and `code`\nsecond line", base, false);
+        assert_eq!(texts(&lines), vec!["Plan", "", "Use bold and code", "second line"]);
+
+        let heading = &lines[0];
+        assert!(heading.styles.iter().all(|s| s.add_modifier.contains(Modifier::BOLD)));
+        let body = &lines[2];
+        let style_at = |i: usize| body.styles[i];
+        assert_eq!(style_at(0), base);
+        assert!(style_at(4).add_modifier.contains(Modifier::BOLD));
+        assert_eq!(style_at(13).fg, Some(Color::Yellow));
+        assert_eq!(body.styles.len(), body.text.chars().count());
+    }
+
+    #[test]
+    fn render_lists_with_bullets_numbers_and_nesting() {
+        let lines = render("Steps:\n\n1. first\n2. second\n   - nested\n\nafter", Style::default(), false);
+        assert_eq!(
+            texts(&lines),
+            vec!["Steps:", "", "1. first", "2. second", "  • nested", "", "after"]
+        );
+        assert_eq!(lines[2].hang, "   ");
+        assert_eq!(lines[4].hang, "    ");
+    }
+
+    #[test]
+    fn render_code_blocks_keep_lines_and_blank_lines() {
+        let lines = render("```rust\nfn main() {\n\n    run();\n}\n```\ndone", Style::default(), false);
+        assert_eq!(
+            texts(&lines),
+            vec!["▏ rust", "▏ fn main() {", "▏ ", "▏     run();", "▏ }", "", "done"]
+        );
+        assert!(lines[1].styles.iter().all(|s| s.fg == Some(Color::Yellow)));
+    }
+
+    #[test]
+    fn code_blocks_are_extracted_verbatim() {
+        let text = "Run:\n\n```sh title=x\ncargo test  \n\n# **not bold**\n```\n\n    indented\n\n```\nplain\n```";
+        assert_eq!(
+            code_blocks(text),
+            vec![
+                CodeBlock { lang: "sh".to_string(), code: "cargo test  \n\n# **not bold**".to_string() },
+                CodeBlock { lang: String::new(), code: "plain".to_string() },
+            ]
+        );
+        assert!(code_blocks("no code here").is_empty());
+    }
+
+    #[test]
+    fn render_highlights_code_blocks_of_known_languages() {
+        let source = "```rust\nlet x = \"s\";\n```\n\n```nosuchlang\nplain\n```";
+        let lines = render(source, Style::default(), true);
+        assert_eq!(texts(&lines), vec!["▏ rust", "▏ let x = \"s\";", "", "▏ nosuchlang", "▏ plain"]);
+        let code = &lines[1];
+        assert_eq!(code.styles.len(), code.text.chars().count());
+        assert!(matches!(code.styles[0].fg, Some(Color::Rgb(..))));
+        assert_ne!(code.styles[0].fg, code.styles[8].fg);
+        assert!(lines[4].styles.iter().all(|s| s.fg == Some(Color::Yellow)));
+    }
+
+    #[test]
+    fn render_quotes_and_rules() {
+        let lines = render("> careful\n> here\n\n---\n\nend", Style::default(), false);
+        assert_eq!(texts(&lines), vec!["│ careful", "│ here", "", "────────────────────", "", "end"]);
+    }
+}
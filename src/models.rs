@@ -1,4 +1,6 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone)]
 pub struct ProjectInfo {
@@ -13,9 +15,31 @@ pub struct SessionInfo {
     pub project_name: String,
     pub preview: String,
     pub timestamp: Option<DateTime<Utc>>,
+    /// Time of the latest entry; `timestamp` is when the session started.
+    pub modified: Option<DateTime<Utc>>,
     pub message_count: usize,
     pub git_branch: String,
     pub summary: String,
+    /// Claude had the last word: the session ended on an assistant turn
+    /// (final answer or a tool call pending permission) with no user reply.
+    pub needs_input: bool,
+    pub usage: TokenUsage,
+    /// Estimated API cost in USD, priced per assistant message by its model.
+    pub cost_usd: f64,
+    /// The assistant got stuck repeating the same tool call.
+    pub has_loops: bool,
+    /// Session this one was resumed from, or empty.
+    pub parent_session_id: String,
+    /// Claude Code version that wrote the latest entry, or empty.
+    pub version: String,
+    /// `userType` of the latest entry (e.g. "external"), or empty.
+    pub user_type: String,
+    /// Label of the Claude config directory the transcript is under (see
+    /// `parser::ClaudeRoot`), or empty.
+    pub source_root: String,
+    /// What was repaired to read the transcript (a byte-order mark, invalid
+    /// UTF-8), or empty.
+    pub warning: String,
 }
 
 impl SessionInfo {
@@ -26,6 +50,108 @@ impl SessionInfo {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub cache_read_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens + self.cache_creation_tokens + self.cache_read_tokens
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_tokens += other.cache_creation_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+    }
+
+    pub fn cost(&self, price: &ModelPrice) -> f64 {
+        (self.input_tokens as f64 * price.input
+            + self.output_tokens as f64 * price.output
+            + self.cache_creation_tokens as f64 * price.cache_write
+            + self.cache_read_tokens as f64 * price.cache_read)
+            / 1_000_000.0
+    }
+}
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    #[serde(default)]
+    pub cache_write: f64,
+    #[serde(default)]
+    pub cache_read: f64,
+}
+
+impl ModelPrice {
+    /// Public list prices, matched by model-name substring.
+    pub fn builtin(model: &str) -> ModelPrice {
+        let (input, output, cache_write, cache_read) = if model.contains("opus-4-5") {
+            (5.0, 25.0, 6.25, 0.5)
+        } else if model.contains("opus") {
+            (15.0, 75.0, 18.75, 1.5)
+        } else if model.contains("haiku-4-5") {
+            (1.0, 5.0, 1.25, 0.1)
+        } else if model.contains("haiku") {
+            (0.8, 4.0, 1.0, 0.08)
+        } else {
+            (3.0, 15.0, 3.75, 0.3)
+        };
+        ModelPrice {
+            input,
+            output,
+            cache_write,
+            cache_read,
+        }
+    }
+}
+
+/// User-configured prices keyed by model-name substring. The longest key
+/// contained in the model name wins; models matching no key use the
+/// built-in list prices.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct PriceTable(pub BTreeMap<String, ModelPrice>);
+
+impl PriceTable {
+    pub fn price(&self, model: &str) -> ModelPrice {
+        self.0
+            .iter()
+            .filter(|(key, _)| model.contains(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, price)| *price)
+            .unwrap_or_else(|| ModelPrice::builtin(model))
+    }
+}
+
+/// Token usage of a session as stored in the index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageSummary {
+    pub usage: TokenUsage,
+    pub cost_usd: f64,
+    /// `message.id` of the last counted assistant message, so counting can
+    /// resume after an append without double-counting a split message.
+    pub last_message_id: String,
+}
+
+impl UsageSummary {
+    /// Add usage counted from lines appended after `self`.
+    pub fn extend(&mut self, appended: &UsageSummary) {
+        self.usage.add(&appended.usage);
+        self.cost_usd += appended.cost_usd;
+        if !appended.last_message_id.is_empty() {
+            self.last_message_id = appended.last_message_id.clone();
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum MessageRole {
     User,
@@ -42,6 +168,26 @@ pub struct Message {
     pub text: String,
     pub timestamp: Option<DateTime<Utc>>,
     pub tool_name: Option<String>,
+    /// `input` of a tool call, for views that show more than its one-line
+    /// summary (e.g. the diff of an Edit).
+    pub tool_input: Option<serde_json::Value>,
+    /// `message.model` of an assistant answer.
+    pub model: Option<String>,
+    /// `message.usage` of an assistant answer.
+    pub usage: Option<TokenUsage>,
+    /// The JSONL line the message was parsed from, kept when a session is
+    /// opened so the raw entry can be shown. One line can yield several
+    /// messages, which then share it.
+    pub source_line: Option<std::sync::Arc<str>>,
+    /// `uuid` of the JSONL entry, shared by the messages parsed from it.
+    pub uuid: Option<String>,
+    /// `parentUuid` of the entry. When a session is opened, entries that
+    /// yield no messages (progress and the like) are skipped over, so this
+    /// names the nearest ancestor that has messages.
+    pub parent_uuid: Option<String>,
+    /// `isSidechain` of the entry: the message belongs to a subagent's
+    /// conversation rather than the main one.
+    pub sidechain: bool,
 }
 
 impl Message {
@@ -61,6 +207,207 @@ impl Message {
             MessageRole::Progress => "PROGRESS",
         }
     }
+
+    /// The JSONL entry this message came from, pretty-printed; as is if it
+    /// is not valid JSON.
+    pub fn pretty_source_line(&self) -> Option<String> {
+        let line = self.source_line.as_deref()?;
+        let pretty = serde_json::from_str::<serde_json::Value>(line)
+            .and_then(|v| serde_json::to_string_pretty(&v))
+            .unwrap_or_else(|_| line.to_string());
+        Some(pretty)
+    }
+
+    /// `uuid` of the JSONL entry this message came from.
+    pub fn source_uuid(&self) -> Option<String> {
+        let line = self.source_line.as_deref()?;
+        let value = serde_json::from_str::<serde_json::Value>(line).ok()?;
+        value.get("uuid")?.as_str().map(str::to_string)
+    }
+
+    /// Text to render under a cap of `max_chars` characters (0 = no cap),
+    /// plus how many characters were cut off.
+    pub fn capped_text(&self, max_chars: usize) -> (&str, usize) {
+        if max_chars == 0 {
+            return (&self.text, 0);
+        }
+        match self.text.char_indices().nth(max_chars) {
+            Some((byte_idx, _)) => {
+                let hidden = self.text[byte_idx..].chars().count();
+                (&self.text[..byte_idx], hidden)
+            }
+            None => (&self.text, 0),
+        }
+    }
+}
+
+/// One file backup recorded by a `file-history-snapshot` entry.
+#[derive(Debug, Clone)]
+pub struct FileSnapshot {
+    /// Message the snapshot was taken for (the state before that turn).
+    pub message_id: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub path: String,
+    /// Name under `~/.claude/file-history/<session>/`; None when the file did
+    /// not exist yet.
+    pub backup_file: Option<String>,
+    pub version: u64,
+    pub backup_time: Option<DateTime<Utc>>,
+}
+
+impl FileSnapshot {
+    pub fn timestamp_str(&self) -> String {
+        self.timestamp
+            .or(self.backup_time)
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Cumulative change a session made to one file, as a unified diff.
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub path: String,
+    /// Number of Write/Edit operations applied to the file.
+    pub operations: usize,
+    /// The first operation was a Write, so the file is treated as new.
+    pub created: bool,
+    /// False when the diff is stitched together from edit fragments because
+    /// the full file content could not be reconstructed.
+    pub exact: bool,
+    pub diff: String,
+}
+
+/// A run of near-identical tool calls in a transcript, by message index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopRegion {
+    pub start: usize,
+    pub end: usize,
+    pub tool_name: String,
+    pub repeats: usize,
+}
+
+impl LoopRegion {
+    pub fn contains(&self, index: usize) -> bool {
+        (self.start..=self.end).contains(&index)
+    }
+}
+
+/// A subagent's conversation, split out of the session it ran in.
+#[derive(Debug, Clone)]
+pub struct Subagent {
+    /// Index of the Task/Agent call that started it among the session's
+    /// messages, if one could be matched.
+    pub spawned_by: Option<usize>,
+    /// The call's `description`, else the first line of the prompt.
+    pub description: String,
+    pub messages: Vec<Message>,
+}
+
+/// Where a message sits in the `uuid`/`parentUuid` tree of a transcript.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadMark {
+    /// Forks passed on the way from the root, each one level of indent.
+    pub depth: usize,
+    /// `(k, n)` on the first message of the k-th of n branches of a fork
+    /// (an edited prompt or a retried answer).
+    pub branch: Option<(usize, usize)>,
+}
+
+/// A tool call on a session's timeline, from the call to its result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineCall {
+    /// Index of the call among the session's messages.
+    pub message: usize,
+    pub tool: String,
+    pub start: DateTime<Utc>,
+    /// When the result came back; `start` for a call that got none.
+    pub end: DateTime<Utc>,
+}
+
+impl TimelineCall {
+    pub fn secs(&self) -> i64 {
+        (self.end - self.start).num_seconds()
+    }
+}
+
+/// The timed events of a session, drawn by the timeline view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Index and time of each user prompt.
+    pub prompts: Vec<(usize, DateTime<Utc>)>,
+    pub calls: Vec<TimelineCall>,
+}
+
+impl Timeline {
+    pub fn secs(&self) -> i64 {
+        (self.end - self.start).num_seconds()
+    }
+
+    /// Seconds spent in each tool and its number of calls, most time first.
+    pub fn tool_totals(&self) -> Vec<(String, i64, usize)> {
+        let mut totals: Vec<(String, i64, usize)> = Vec::new();
+        for call in &self.calls {
+            match totals.iter_mut().find(|(tool, _, _)| *tool == call.tool) {
+                Some((_, secs, n)) => {
+                    *secs += call.secs();
+                    *n += 1;
+                }
+                None => totals.push((call.tool.clone(), call.secs(), 1)),
+            }
+        }
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then_with(|| a.0.cmp(&b.0)));
+        totals
+    }
+
+    /// The column of `at` on an axis `width` columns wide.
+    pub fn column(&self, at: DateTime<Utc>, width: usize) -> usize {
+        let total = (self.end - self.start).num_milliseconds();
+        if total <= 0 || width == 0 {
+            return 0;
+        }
+        let offset = (at - self.start).num_milliseconds().clamp(0, total);
+        ((offset as i128 * (width - 1) as i128 / total as i128) as usize).min(width - 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionDecision {
+    Approved,
+    AutoApproved,
+    Denied,
+    Pending,
+}
+
+impl PermissionDecision {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PermissionDecision::Approved => "Approved",
+            PermissionDecision::AutoApproved => "Auto-approved",
+            PermissionDecision::Denied => "Denied",
+            PermissionDecision::Pending => "Pending",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PermissionAuditEntry {
+    pub session_id: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub tool_name: String,
+    pub summary: String,
+    pub permission_mode: String,
+    pub decision: PermissionDecision,
+}
+
+impl PermissionAuditEntry {
+    pub fn timestamp_str(&self) -> String {
+        self.timestamp
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -69,10 +416,85 @@ pub struct SearchResult {
     pub project_path: String,
     pub dir_name: String,
     pub git_branch: String,
+    pub summary: String,
     pub created_at: String,
+    /// Unix seconds of the session's latest activity (0 = unknown).
+    pub last_activity: i64,
     pub prompts: Vec<String>,
     pub best_match_prompt: String,
     pub best_match_indices: Vec<usize>,
+    /// `best_match_prompt` holds the session summary rather than a prompt.
+    pub best_match_is_summary: bool,
+    /// Every other prompt that matched, with its highlight indices.
+    pub other_matches: Vec<(String, Vec<usize>)>,
+    /// Session this one was resumed from, or empty.
+    pub parent_session_id: String,
+    /// The transcript file was removed by Claude Code's cleanup; only the
+    /// indexed prompts are left.
+    pub expired: bool,
+    /// Label of the Claude root the session was indexed from.
+    pub source_root: String,
+}
+
+/// Order of the session list, cycled with `o`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionSort {
+    /// Newest first by when the session started.
+    #[default]
+    Created,
+    /// Most recently active first.
+    Modified,
+    /// Longest first.
+    Messages,
+    /// Alphabetical by git branch, sessions without one last.
+    Branch,
+    /// Most tokens first, counting cache reads and writes.
+    Tokens,
+    /// Most expensive first by the estimated cost.
+    Cost,
+}
+
+impl SessionSort {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SessionSort::Created => "created",
+            SessionSort::Modified => "modified",
+            SessionSort::Messages => "messages",
+            SessionSort::Branch => "branch",
+            SessionSort::Tokens => "tokens",
+            SessionSort::Cost => "cost",
+        }
+    }
+
+    pub fn next(&self) -> SessionSort {
+        match self {
+            SessionSort::Created => SessionSort::Modified,
+            SessionSort::Modified => SessionSort::Messages,
+            SessionSort::Messages => SessionSort::Branch,
+            SessionSort::Branch => SessionSort::Tokens,
+            SessionSort::Tokens => SessionSort::Cost,
+            SessionSort::Cost => SessionSort::Created,
+        }
+    }
+
+    /// Sort `sessions` in this order. Ties keep newest first.
+    pub fn apply(&self, sessions: &mut [SessionInfo]) {
+        let created = |s: &SessionInfo| std::cmp::Reverse(s.timestamp);
+        match self {
+            SessionSort::Created => sessions.sort_by_key(created),
+            SessionSort::Modified => sessions.sort_by_key(|s| (std::cmp::Reverse(s.modified.or(s.timestamp)), created(s))),
+            SessionSort::Messages => sessions.sort_by_key(|s| (std::cmp::Reverse(s.message_count), created(s))),
+            SessionSort::Branch => {
+                sessions.sort_by(|a, b| {
+                    (a.git_branch.is_empty(), &a.git_branch, created(a)).cmp(&(b.git_branch.is_empty(), &b.git_branch, created(b)))
+                })
+            }
+            SessionSort::Tokens => sessions.sort_by_key(|s| (std::cmp::Reverse(s.usage.total()), created(s))),
+            SessionSort::Cost => {
+                sessions.sort_by(|a, b| b.cost_usd.total_cmp(&a.cost_usd).then_with(|| created(a).cmp(&created(b))))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -81,18 +503,31 @@ pub enum TimeFilter {
     Week,
     Month,
     All,
+    /// Whole days from `from` through `to` (UTC, like the listed times);
+    /// an open end reaches as far as there are sessions.
+    Range {
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    },
 }
 
 impl TimeFilter {
-    pub fn label(&self) -> &'static str {
+    pub fn label(&self) -> String {
         match self {
-            TimeFilter::Yesterday => "Yesterday",
-            TimeFilter::Week => "Week",
-            TimeFilter::Month => "Month",
-            TimeFilter::All => "All",
+            TimeFilter::Yesterday => "Yesterday".to_string(),
+            TimeFilter::Week => "Week".to_string(),
+            TimeFilter::Month => "Month".to_string(),
+            TimeFilter::All => "All".to_string(),
+            TimeFilter::Range { from, to } if from == to => from.map(|d| d.to_string()).unwrap_or_default(),
+            TimeFilter::Range { from, to } => format!(
+                "{}..{}",
+                from.map(|d| d.to_string()).unwrap_or_default(),
+                to.map(|d| d.to_string()).unwrap_or_default()
+            ),
         }
     }
 
+    /// The presets shown as tabs; a `Range` is shown after them while active.
     pub fn all_filters() -> &'static [TimeFilter] {
         &[
             TimeFilter::Yesterday,
@@ -102,18 +537,81 @@ impl TimeFilter {
         ]
     }
 
+    /// Parse a range typed as `2026-01-01..2026-01-15`, with either end left
+    /// open (`2026-01-01..`), or a single day. None if it is not one.
+    pub fn parse_range(input: &str) -> Option<TimeFilter> {
+        let date = |s: &str| match s.trim() {
+            "" => Some(None),
+            s => NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(Some),
+        };
+        let (from, to) = match input.split_once("..") {
+            Some((from, to)) => (date(from)?, date(to)?),
+            None => {
+                let day = date(input)?;
+                (day, day)
+            }
+        };
+        match (from, to) {
+            (None, None) => None,
+            (Some(from), Some(to)) if from > to => None,
+            _ => Some(TimeFilter::Range { from, to }),
+        }
+    }
+
+    /// Start of the filter's window ending at `now`; None for `All`.
+    /// Something is in the window when its timestamp is after the cutoff.
+    /// For a `Range` this is the start of its first day, see [`Self::contains`].
+    pub fn cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            TimeFilter::Yesterday => Some(now - chrono::Duration::hours(24)),
+            TimeFilter::Week => Some(now - chrono::Duration::days(7)),
+            TimeFilter::Month => Some(now - chrono::Duration::days(30)),
+            TimeFilter::All => None,
+            TimeFilter::Range { from, .. } => from.map(|d| d.and_time(chrono::NaiveTime::MIN).and_utc()),
+        }
+    }
+
+    /// End of a `Range`, the start of the day after its last day; None for
+    /// an open end and for the presets, which run up to now.
+    pub fn until(&self) -> Option<DateTime<Utc>> {
+        match self {
+            TimeFilter::Range { to: Some(to), .. } => to.succ_opt().map(|d| d.and_time(chrono::NaiveTime::MIN).and_utc()),
+            _ => None,
+        }
+    }
+
+    /// Whether something at `at` is in the window ending at `now`.
+    pub fn contains(&self, at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        match self {
+            TimeFilter::Range { from, to } => {
+                let day = at.date_naive();
+                from.is_none_or(|from| day >= from) && to.is_none_or(|to| day <= to)
+            }
+            _ => self.cutoff(now).is_none_or(|cutoff| at > cutoff),
+        }
+    }
+
+    /// The days a preset covers at `now` as a `Range`, to start editing a
+    /// custom range from. `All` and a `Range` stay as they are.
+    pub fn as_range(&self, now: DateTime<Utc>) -> TimeFilter {
+        match self {
+            TimeFilter::All | TimeFilter::Range { .. } => *self,
+            _ => TimeFilter::Range { from: self.cutoff(now).map(|t| t.date_naive()), to: Some(now.date_naive()) },
+        }
+    }
+
     pub fn next(&self) -> TimeFilter {
         match self {
             TimeFilter::Yesterday => TimeFilter::Week,
             TimeFilter::Week => TimeFilter::Month,
             TimeFilter::Month => TimeFilter::All,
-            TimeFilter::All => TimeFilter::Yesterday,
+            TimeFilter::All | TimeFilter::Range { .. } => TimeFilter::Yesterday,
         }
     }
 
     pub fn prev(&self) -> TimeFilter {
         match self {
-            TimeFilter::Yesterday => TimeFilter::All,
+            TimeFilter::Yesterday | TimeFilter::Range { .. } => TimeFilter::All,
             TimeFilter::Week => TimeFilter::Yesterday,
             TimeFilter::Month => TimeFilter::Week,
             TimeFilter::All => TimeFilter::Month,
@@ -175,6 +673,43 @@ mod tests {
         assert_eq!(TimeFilter::All.label(), "All");
     }
 
+    #[test]
+    fn time_filter_cutoff() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 10, 12, 0, 0).unwrap();
+        assert_eq!(TimeFilter::Yesterday.cutoff(now), Some(Utc.with_ymd_and_hms(2026, 3, 9, 12, 0, 0).unwrap()));
+        assert_eq!(TimeFilter::Week.cutoff(now), Some(Utc.with_ymd_and_hms(2026, 3, 3, 12, 0, 0).unwrap()));
+        assert_eq!(TimeFilter::Month.cutoff(now), Some(Utc.with_ymd_and_hms(2026, 2, 8, 12, 0, 0).unwrap()));
+        assert_eq!(TimeFilter::All.cutoff(now), None);
+    }
+
+    #[test]
+    fn time_filter_range_parses_open_ends_and_single_days() {
+        let day = |d: u32| Some(NaiveDate::from_ymd_opt(2026, 1, d).unwrap());
+        let range = TimeFilter::parse_range("2026-01-01..2026-01-15").unwrap();
+        assert_eq!(range, TimeFilter::Range { from: day(1), to: day(15) });
+        assert_eq!(range.label(), "2026-01-01..2026-01-15");
+        assert_eq!(TimeFilter::parse_range(" 2026-01-03.. ").unwrap().label(), "2026-01-03..");
+        assert_eq!(TimeFilter::parse_range("..2026-01-03").unwrap(), TimeFilter::Range { from: None, to: day(3) });
+        assert_eq!(TimeFilter::parse_range("2026-01-07").unwrap().label(), "2026-01-07");
+        for bad in ["", "..", "2026-01-15..2026-01-01", "last week", "2026-13-01"] {
+            assert_eq!(TimeFilter::parse_range(bad), None, "{}", bad);
+        }
+
+        // 終わりの日も丸一日含む
+        let now = Utc.with_ymd_and_hms(2026, 3, 10, 12, 0, 0).unwrap();
+        assert!(range.contains(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap(), now));
+        assert!(range.contains(Utc.with_ymd_and_hms(2026, 1, 15, 23, 59, 59).unwrap(), now));
+        assert!(!range.contains(Utc.with_ymd_and_hms(2026, 1, 16, 0, 0, 0).unwrap(), now));
+        assert!(!range.contains(Utc.with_ymd_and_hms(2025, 12, 31, 23, 0, 0).unwrap(), now));
+        assert!(TimeFilter::Week.contains(Utc.with_ymd_and_hms(2026, 3, 4, 0, 0, 0).unwrap(), now));
+        assert_eq!(range.until(), Some(Utc.with_ymd_and_hms(2026, 1, 16, 0, 0, 0).unwrap()));
+        assert_eq!(TimeFilter::Week.until(), None);
+
+        assert_eq!(TimeFilter::Week.as_range(now).label(), "2026-03-03..2026-03-10");
+        assert_eq!(range.next(), TimeFilter::Yesterday);
+        assert_eq!(range.prev(), TimeFilter::All);
+    }
+
     #[test]
     fn time_filter_all_filters_length() {
         assert_eq!(TimeFilter::all_filters().len(), 4);
@@ -199,6 +734,13 @@ mod tests {
             text: String::new(),
             timestamp,
             tool_name: None,
+            tool_input: None,
+            model: None,
+            usage: None,
+            source_line: None,
+            uuid: None,
+            parent_uuid: None,
+            sidechain: false,
         }
     }
 
@@ -260,6 +802,81 @@ mod tests {
         assert_eq!(msg.timestamp_str(), "2024-01-15 10:30:00");
     }
 
+    #[test]
+    fn message_capped_text() {
+        let mut msg = make_message(MessageRole::ToolResult, None);
+        msg.text = "あいうえお".to_string();
+        assert_eq!(msg.capped_text(3), ("あいう", 2));
+        assert_eq!(msg.capped_text(5), ("あいうえお", 0));
+        assert_eq!(msg.capped_text(0), ("あいうえお", 0));
+    }
+
+    // ============================================================
+    // TokenUsage tests
+    // ============================================================
+
+    #[test]
+    fn token_usage_total_and_add() {
+        let mut usage = TokenUsage {
+            input_tokens: 10,
+            output_tokens: 20,
+            cache_creation_tokens: 30,
+            cache_read_tokens: 40,
+        };
+        assert_eq!(usage.total(), 100);
+        usage.add(&usage.clone());
+        assert_eq!(usage.total(), 200);
+    }
+
+    #[test]
+    fn builtin_prices_by_model() {
+        let usage = TokenUsage {
+            input_tokens: 1_000_000,
+            output_tokens: 1_000_000,
+            ..Default::default()
+        };
+        let cost = |model: &str| usage.cost(&ModelPrice::builtin(model));
+        assert_eq!(cost("claude-sonnet-4-20250514"), 18.0);
+        assert_eq!(cost("claude-opus-4-1-20250805"), 90.0);
+        assert_eq!(cost("claude-opus-4-5-20251101"), 30.0);
+        assert_eq!(cost("<synthetic>"), 18.0);
+    }
+
+    #[test]
+    fn price_table_prefers_longest_matching_key() {
+        let mut table = PriceTable::default();
+        table.0.insert(
+            "opus".to_string(),
+            ModelPrice { input: 1.0, output: 2.0, cache_write: 0.0, cache_read: 0.0 },
+        );
+        table.0.insert(
+            "opus-4-5".to_string(),
+            ModelPrice { input: 3.0, output: 4.0, cache_write: 0.0, cache_read: 0.0 },
+        );
+        assert_eq!(table.price("claude-opus-4-5-20251101").input, 3.0);
+        assert_eq!(table.price("claude-opus-4-1").input, 1.0);
+        assert_eq!(table.price("claude-sonnet-4"), ModelPrice::builtin("claude-sonnet-4"));
+    }
+
+    #[test]
+    fn usage_summary_extend_keeps_last_id_when_nothing_new() {
+        let mut summary = UsageSummary {
+            usage: TokenUsage { input_tokens: 5, ..Default::default() },
+            cost_usd: 1.0,
+            last_message_id: "m1".to_string(),
+        };
+        summary.extend(&UsageSummary::default());
+        assert_eq!(summary.last_message_id, "m1");
+        summary.extend(&UsageSummary {
+            usage: TokenUsage { input_tokens: 2, ..Default::default() },
+            cost_usd: 0.5,
+            last_message_id: "m2".to_string(),
+        });
+        assert_eq!(summary.usage.input_tokens, 7);
+        assert_eq!(summary.cost_usd, 1.5);
+        assert_eq!(summary.last_message_id, "m2");
+    }
+
     // ============================================================
     // SessionInfo tests
     // ============================================================
@@ -270,9 +887,19 @@ mod tests {
             project_name: String::new(),
             preview: String::new(),
             timestamp,
+            modified: None,
             message_count: 0,
             git_branch: String::new(),
             summary: String::new(),
+            needs_input: false,
+            usage: TokenUsage::default(),
+            cost_usd: 0.0,
+            has_loops: false,
+            parent_session_id: String::new(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+            warning: String::new(),
         }
     }
 
@@ -288,4 +915,36 @@ mod tests {
         let session = make_session(Some(dt));
         assert_eq!(session.timestamp_str(), "2024-01-15 10:30:00");
     }
+
+    #[test]
+    fn session_sort_orders_with_newest_first_ties() {
+        let at = |day: u32| Some(Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap());
+        let session = |id: &str, created: u32, modified: u32, messages: usize, branch: &str, tokens: u64| SessionInfo {
+            session_id: id.to_string(),
+            modified: at(modified),
+            message_count: messages,
+            git_branch: branch.to_string(),
+            usage: TokenUsage { input_tokens: tokens, cache_read_tokens: tokens, ..TokenUsage::default() },
+            cost_usd: tokens as f64 / 1000.0,
+            ..make_session(at(created))
+        };
+        let mut sessions = vec![
+            session("a", 1, 20, 5, "main", 300),
+            session("b", 3, 4, 50, "", 300),
+            session("c", 2, 10, 5, "feat/x", 900),
+            SessionInfo { session_id: "d".to_string(), ..make_session(None) },
+        ];
+        let order = |sort: SessionSort, sessions: &mut Vec<SessionInfo>| {
+            sort.apply(sessions);
+            sessions.iter().map(|s| s.session_id.clone()).collect::<String>()
+        };
+        assert_eq!(order(SessionSort::Created, &mut sessions), "bcad");
+        assert_eq!(order(SessionSort::Modified, &mut sessions), "acbd");
+        assert_eq!(order(SessionSort::Messages, &mut sessions), "bcad");
+        assert_eq!(order(SessionSort::Branch, &mut sessions), "cabd");
+        assert_eq!(order(SessionSort::Tokens, &mut sessions), "cbad");
+        assert_eq!(order(SessionSort::Cost, &mut sessions), "cbad");
+        assert_eq!(SessionSort::Branch.next(), SessionSort::Tokens);
+        assert_eq!(SessionSort::Cost.next(), SessionSort::Created);
+    }
 }
@@ -1,9 +1,15 @@
-use crate::models::{Message, MessageRole, ProjectInfo, SessionInfo};
+use crate::config::Config;
+use crate::models::{
+    Message, MessageRole, PriceTable, ProjectInfo, SessionInfo, TokenUsage, UsageSummary,
+};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 pub(crate) fn truncate_str(s: &str, max_chars: usize) -> String {
     if s.chars().count() <= max_chars {
@@ -14,8 +20,111 @@ pub(crate) fn truncate_str(s: &str, max_chars: usize) -> String {
     }
 }
 
-fn claude_projects_dir() -> Option<PathBuf> {
-    dirs::home_dir().map(|h| h.join(".claude").join("projects"))
+/// Text of a session transcript. A leading byte-order mark is dropped and
+/// invalid UTF-8 becomes U+FFFD, so a transcript copied from another machine
+/// still loads; the second value says what had to be repaired.
+pub fn decode_transcript(bytes: &[u8]) -> (Cow<'_, str>, Option<String>) {
+    let (bytes, bom) = match bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        Some(rest) => (rest, true),
+        None => (bytes, false),
+    };
+    let invalid: usize = bytes.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+    let mut repairs = Vec::new();
+    if bom {
+        repairs.push("byte-order mark removed".to_string());
+    }
+    if invalid > 0 {
+        repairs.push(format!("{} invalid UTF-8 byte{} replaced", invalid, if invalid == 1 { "" } else { "s" }));
+    }
+    let warning = (!repairs.is_empty()).then(|| repairs.join(", "));
+    (String::from_utf8_lossy(bytes), warning)
+}
+
+/// Read a transcript with `decode_transcript`.
+pub fn read_transcript(path: &Path) -> Result<(String, Option<String>)> {
+    let bytes = fs::read(path)?;
+    let (text, warning) = decode_transcript(&bytes);
+    Ok((text.into_owned(), warning))
+}
+
+/// A Claude Code config directory whose `projects` subdirectory holds
+/// session transcripts: `~/.claude`, or one of the config's `claude_dirs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaudeRoot {
+    /// Directory name without its leading dot (`claude`, `claude-work`),
+    /// shown in the Root column and matched by `root:` in global search.
+    pub label: String,
+    pub projects_dir: PathBuf,
+}
+
+impl ClaudeRoot {
+    /// The root that `projects_dir` belongs to.
+    pub fn new(projects_dir: &Path) -> ClaudeRoot {
+        let label = projects_dir
+            .parent()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().trim_start_matches('.').to_string())
+            .unwrap_or_default();
+        ClaudeRoot { label, projects_dir: projects_dir.to_path_buf() }
+    }
+
+    /// Where Claude Code keeps this root's file backups.
+    pub fn history_dir(&self) -> PathBuf {
+        self.projects_dir.parent().unwrap_or(&self.projects_dir).join("file-history")
+    }
+}
+
+/// `~/.claude` followed by the `claude_dirs` of the user's config, read once.
+pub fn claude_roots() -> &'static [ClaudeRoot] {
+    static ROOTS: OnceLock<Vec<ClaudeRoot>> = OnceLock::new();
+    ROOTS.get_or_init(|| match dirs::home_dir() {
+        Some(home) => roots_in(&home, &Config::load().claude_dirs),
+        None => Vec::new(),
+    })
+}
+
+/// `home/.claude` and `claude_dirs` (a leading `~/` means `home`), without
+/// duplicates. A root whose label is taken is labelled by its full path.
+pub(crate) fn roots_in(home: &Path, claude_dirs: &[String]) -> Vec<ClaudeRoot> {
+    let mut roots = vec![ClaudeRoot::new(&home.join(".claude").join("projects"))];
+    for dir in claude_dirs {
+        let dir = match dir.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => PathBuf::from(dir),
+        };
+        let mut root = ClaudeRoot::new(&dir.join("projects"));
+        if roots.iter().any(|r| r.projects_dir == root.projects_dir) {
+            continue;
+        }
+        if roots.iter().any(|r| r.label == root.label) {
+            root.label = dir.display().to_string();
+        }
+        roots.push(root);
+    }
+    roots
+}
+
+/// The root whose projects directory has the session's transcript, or the
+/// first root when none has it.
+pub(crate) fn session_root_in<'a>(roots: &'a [ClaudeRoot], project_name: &str, session_id: &str) -> Option<&'a ClaudeRoot> {
+    let file_name = format!("{}.jsonl", session_id);
+    roots
+        .iter()
+        .find(|r| r.projects_dir.join(project_name).join(&file_name).exists())
+        .or(roots.first())
+}
+
+pub(crate) fn session_root(project_name: &str, session_id: &str) -> Option<&'static ClaudeRoot> {
+    session_root_in(claude_roots(), project_name, session_id)
+}
+
+/// Add `project` to `projects`, summing the session counts of a project
+/// that is under more than one root.
+pub(crate) fn merge_project(projects: &mut Vec<ProjectInfo>, project: ProjectInfo) {
+    match projects.iter_mut().find(|p| p.dir_name == project.dir_name) {
+        Some(existing) => existing.session_count += project.session_count,
+        None => projects.push(project),
+    }
 }
 
 /// Decode a project directory name back to the original filesystem path.
@@ -62,7 +171,7 @@ pub(crate) fn decode_project_path(dir_name: &str) -> String {
 }
 
 /// Try to read originalPath (or projectPath from entries) from sessions-index.json.
-fn try_get_original_path(project_dir: &Path) -> Option<String> {
+pub(crate) fn try_get_original_path(project_dir: &Path) -> Option<String> {
     let index_path = project_dir.join("sessions-index.json");
     let content = fs::read_to_string(&index_path).ok()?;
     let data: Value = serde_json::from_str(&content).ok()?;
@@ -71,12 +180,11 @@ fn try_get_original_path(project_dir: &Path) -> Option<String> {
         return Some(orig.to_string());
     }
 
-    if let Some(entries) = data.get("entries").and_then(Value::as_array) {
-        if let Some(first) = entries.first() {
-            if let Some(pp) = first.get("projectPath").and_then(Value::as_str) {
-                return Some(pp.to_string());
-            }
-        }
+    if let Some(entries) = data.get("entries").and_then(Value::as_array)
+        && let Some(first) = entries.first()
+        && let Some(pp) = first.get("projectPath").and_then(Value::as_str)
+    {
+        return Some(pp.to_string());
     }
 
     None
@@ -187,32 +295,471 @@ pub(crate) fn summarize_tool_use(tool_name: &str, input: &Value) -> String {
     }
 }
 
-/// List all projects under ~/.claude/projects/.
-pub fn list_projects() -> Result<Vec<ProjectInfo>> {
-    let projects_dir = match claude_projects_dir() {
-        Some(d) => d,
-        None => return Ok(Vec::new()),
+/// Determine whether a session is waiting on the user.
+///
+/// Walks the JSONL content backwards to the last user/assistant entry. An
+/// assistant entry there means Claude finished its turn (or issued a tool call
+/// that is still pending permission) and nobody replied.
+pub(crate) fn is_awaiting_input(content: &str) -> bool {
+    for line in content.lines().rev() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let obj: Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        match obj.get("type").and_then(Value::as_str).unwrap_or("") {
+            "assistant" => return true,
+            "user" => return false,
+            _ => continue,
+        }
+    }
+    false
+}
+
+/// Parse a `message.usage` object from an assistant entry.
+pub(crate) fn parse_usage(usage: &Value) -> TokenUsage {
+    let get = |key: &str| usage.get(key).and_then(Value::as_u64).unwrap_or(0);
+    TokenUsage {
+        input_tokens: get("input_tokens"),
+        output_tokens: get("output_tokens"),
+        cache_creation_tokens: get("cache_creation_input_tokens"),
+        cache_read_tokens: get("cache_read_input_tokens"),
+    }
+}
+
+/// Sum the `usage` of assistant messages, counting each `message.id` once
+/// (a message split over several entries repeats its usage). `after_id` is
+/// the last message already counted when continuing from an earlier offset.
+pub fn summarize_usage(content: &str, prices: &PriceTable, after_id: Option<&str>) -> UsageSummary {
+    let mut summary = UsageSummary::default();
+    let mut seen = HashSet::new();
+    if let Some(id) = after_id {
+        seen.insert(id.to_string());
+    }
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let obj: Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        add_entry_usage(&mut summary, &mut seen, &obj, prices);
+    }
+    summary
+}
+
+/// Add the `usage` of one assistant entry to `summary` unless its
+/// `message.id` is in `seen`.
+fn add_entry_usage(summary: &mut UsageSummary, seen: &mut HashSet<String>, obj: &Value, prices: &PriceTable) {
+    if obj.get("type").and_then(Value::as_str) != Some("assistant") {
+        return;
+    }
+    let Some(message) = obj.get("message") else {
+        return;
     };
-    list_projects_in(&projects_dir)
+    let Some(usage) = message.get("usage") else {
+        return;
+    };
+    if let Some(id) = message.get("id").and_then(Value::as_str) {
+        if !seen.insert(id.to_string()) {
+            return;
+        }
+        summary.last_message_id = id.to_string();
+    }
+    let usage = parse_usage(usage);
+    let model = message.get("model").and_then(Value::as_str).unwrap_or("");
+    summary.cost_usd += usage.cost(&prices.price(model));
+    summary.usage.add(&usage);
 }
 
-pub(crate) fn list_projects_in(projects_dir: &Path) -> Result<Vec<ProjectInfo>> {
-    if !projects_dir.exists() {
-        return Ok(Vec::new());
+/// Models of the assistant entries, in order of first appearance. Placeholder
+/// names such as `<synthetic>` (locally generated messages) are skipped.
+pub fn session_models(content: &str) -> Vec<String> {
+    let mut models: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let obj: Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if obj.get("type").and_then(Value::as_str) != Some("assistant") {
+            continue;
+        }
+        let Some(model) = obj
+            .get("message")
+            .and_then(|m| m.get("model"))
+            .and_then(Value::as_str)
+        else {
+            continue;
+        };
+        if model.is_empty() || model.starts_with('<') || models.iter().any(|m| m == model) {
+            continue;
+        }
+        models.push(model.to_string());
+    }
+    models
+}
+
+/// Earliest and latest entry `timestamp` in `content`.
+pub fn timestamp_range(content: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut range: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    for line in content.lines() {
+        let Ok(obj) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        let Some(ts) = parse_timestamp(obj.get("timestamp").and_then(Value::as_str)) else {
+            continue;
+        };
+        range = Some(match range {
+            Some((first, last)) => (first.min(ts), last.max(ts)),
+            None => (ts, ts),
+        });
+    }
+    range
+}
+
+/// How many times each tool was called, in order of first use.
+pub fn tool_counts(content: &str) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for line in content.lines() {
+        let Ok(obj) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        if obj.get("type").and_then(Value::as_str) != Some("assistant") {
+            continue;
+        }
+        let Some(content) = obj.get("message").and_then(|m| m.get("content")) else {
+            continue;
+        };
+        for block in extract_tool_blocks(content) {
+            let Some(tool) = block.get("name").and_then(Value::as_str) else {
+                continue;
+            };
+            match counts.iter_mut().find(|(t, _)| t == tool) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((tool.to_string(), 1)),
+            }
+        }
+    }
+    counts
+}
+
+/// Programs whose first argument is a subcommand worth keeping apart
+/// (`git commit` vs `git status`).
+const SUBCOMMAND_PROGRAMS: &[&str] = &[
+    "git", "cargo", "npm", "npx", "yarn", "pnpm", "bun", "deno", "go", "docker", "kubectl", "uv", "pip", "make",
+    "gh",
+];
+
+/// The programs a shell command runs, normalized for counting: one name per
+/// `&&`/`||`/`;`-separated step, the first program of a pipeline, without
+/// leading `VAR=value` assignments or `cd` steps. Programs in
+/// `SUBCOMMAND_PROGRAMS` keep their subcommand, e.g. `cargo test`.
+pub fn command_names(command: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for step in command.replace("&&", ";").replace("||", ";").split([';', '\n']) {
+        let pipeline_head = step.split('|').next().unwrap_or("").trim_matches(['&', '(', ')', ' ', '\t']);
+        let mut words = pipeline_head
+            .split_whitespace()
+            .skip_while(|w| w.split_once('=').is_some_and(|(var, _)| !var.is_empty() && !var.contains('/')));
+        let Some(program) = words.next() else {
+            continue;
+        };
+        let program = program.rsplit('/').next().unwrap_or(program);
+        if program.is_empty() || program == "cd" {
+            continue;
+        }
+        match words.next() {
+            Some(sub) if SUBCOMMAND_PROGRAMS.contains(&program) && !sub.starts_with('-') => {
+                names.push(format!("{} {}", program, sub));
+            }
+            _ => names.push(program.to_string()),
+        }
+    }
+    names
+}
+
+/// How many times each normalized program (see `command_names`) was run
+/// through the Bash tool, in order of first run.
+pub fn command_counts(content: &str) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for line in content.lines() {
+        let Ok(obj) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        if obj.get("type").and_then(Value::as_str) != Some("assistant") {
+            continue;
+        }
+        let Some(content) = obj.get("message").and_then(|m| m.get("content")) else {
+            continue;
+        };
+        for block in extract_tool_blocks(content) {
+            if block.get("name").and_then(Value::as_str) != Some("Bash") {
+                continue;
+            }
+            let command = block
+                .get("input")
+                .and_then(|i| i.get("command"))
+                .and_then(Value::as_str)
+                .unwrap_or("");
+            for name in command_names(command) {
+                match counts.iter_mut().find(|(c, _)| *c == name) {
+                    Some((_, n)) => *n += 1,
+                    None => counts.push((name, 1)),
+                }
+            }
+        }
     }
+    counts
+}
+
+/// Tools whose `file_path` input is recorded by `session_files`.
+const FILE_TOOLS: &[&str] = &["Read", "Edit", "MultiEdit", "Write", "NotebookEdit"];
 
+/// Tools among `FILE_TOOLS` that change the file.
+pub const FILE_MODIFYING_TOOLS: &[&str] = &["Edit", "MultiEdit", "Write", "NotebookEdit"];
+
+pub fn is_file_modifying_tool(tool_name: &str) -> bool {
+    FILE_MODIFYING_TOOLS.contains(&tool_name)
+}
+
+/// `(file_path, tool)` of every Read/Edit/Write tool call, each pair once in
+/// order of first use.
+pub fn session_files(content: &str) -> Vec<(String, String)> {
+    let mut files: Vec<(String, String)> = Vec::new();
+    for line in content.lines() {
+        let Ok(obj) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        if obj.get("type").and_then(Value::as_str) != Some("assistant") {
+            continue;
+        }
+        let Some(content) = obj.get("message").and_then(|m| m.get("content")) else {
+            continue;
+        };
+        for block in extract_tool_blocks(content) {
+            let Some(tool) = block.get("name").and_then(Value::as_str) else {
+                continue;
+            };
+            if !FILE_TOOLS.contains(&tool) {
+                continue;
+            }
+            let input = block.get("input");
+            let Some(path) = input
+                .and_then(|i| i.get("file_path").or_else(|| i.get("notebook_path")))
+                .and_then(Value::as_str)
+                .filter(|p| !p.is_empty())
+            else {
+                continue;
+            };
+            if !files.iter().any(|(p, t)| p == path && t == tool) {
+                files.push((path.to_string(), tool.to_string()));
+            }
+        }
+    }
+    files
+}
+
+/// Fill in the fields that can only be computed from the JSONL itself.
+/// What the session list shows about a transcript, gathered in one pass.
+#[derive(Debug, Default)]
+struct TranscriptScan {
+    /// User and assistant entries.
+    message_count: usize,
+    /// Text, `timestamp` and `gitBranch` of the first user entry.
+    preview: String,
+    first_prompt_at: Option<DateTime<Utc>>,
+    git_branch: String,
+    /// Latest entry `timestamp`.
+    last_timestamp: Option<DateTime<Utc>>,
+    /// Type of the last user or assistant entry.
+    last_turn: String,
+    usage: UsageSummary,
+    messages: Vec<Message>,
+    /// `sessionId` of the first entry carrying one.
+    first_session_id: Option<String>,
+    version: String,
+    user_type: String,
+}
+
+/// Parse each line of `content` once and collect what `apply_file_stats` and
+/// the file-based session list need. The rules match `timestamp_range`,
+/// `is_awaiting_input`, `summarize_usage`, `parent_session_id` and
+/// `client_info`.
+fn scan_transcript(content: &str, prices: &PriceTable) -> TranscriptScan {
+    let mut scan = TranscriptScan::default();
+    let mut seen = HashSet::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Ok(obj) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        let field = |key: &str| obj.get(key).and_then(Value::as_str).unwrap_or("");
+        let msg_type = field("type");
+        if msg_type == "user" || msg_type == "assistant" {
+            scan.message_count += 1;
+            scan.last_turn = msg_type.to_string();
+        }
+        if msg_type == "user" && scan.preview.is_empty() {
+            let msg_content = obj.get("message").and_then(|m| m.get("content")).cloned().unwrap_or(Value::String(String::new()));
+            scan.preview = truncate_str(&extract_text_from_content(&msg_content), 200);
+            scan.first_prompt_at = parse_timestamp(obj.get("timestamp").and_then(Value::as_str));
+            scan.git_branch = field("gitBranch").to_string();
+        }
+        if let Some(ts) = parse_timestamp(obj.get("timestamp").and_then(Value::as_str)) {
+            scan.last_timestamp = Some(scan.last_timestamp.map_or(ts, |last| last.max(ts)));
+        }
+        if scan.first_session_id.is_none()
+            && let Some(id) = obj.get("sessionId").and_then(Value::as_str)
+        {
+            scan.first_session_id = Some(id.to_string());
+        }
+        if !field("version").is_empty() {
+            scan.version = field("version").to_string();
+        }
+        if !field("userType").is_empty() {
+            scan.user_type = field("userType").to_string();
+        }
+        add_entry_usage(&mut scan.usage, &mut seen, &obj, prices);
+        scan.messages.extend(entry_messages(&obj));
+    }
+    scan
+}
+
+fn apply_file_stats(info: &mut SessionInfo, scan: TranscriptScan) {
+    info.modified = scan.last_timestamp;
+    info.needs_input = scan.last_turn == "assistant";
+    info.usage = scan.usage.usage;
+    info.cost_usd = scan.usage.cost_usd;
+    info.has_loops = !crate::analysis::detect_loops(&scan.messages).is_empty();
+    info.parent_session_id = scan
+        .first_session_id
+        .filter(|id| !id.is_empty() && *id != info.session_id)
+        .unwrap_or_default();
+    info.version = scan.version;
+    info.user_type = scan.user_type;
+}
+
+/// Claude Code `version` and `userType` of the latest entry recording each,
+/// so a session continued after a CLI upgrade shows the newer version.
+pub fn client_info(content: &str) -> (String, String) {
+    let mut version = String::new();
+    let mut user_type = String::new();
+    for line in content.lines().rev() {
+        if !version.is_empty() && !user_type.is_empty() {
+            break;
+        }
+        let Ok(obj) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        let field = |key: &str| obj.get(key).and_then(Value::as_str).unwrap_or("").to_string();
+        if version.is_empty() {
+            version = field("version");
+        }
+        if user_type.is_empty() {
+            user_type = field("userType");
+        }
+    }
+    (version, user_type)
+}
+
+/// The session `session_id` was resumed from.
+///
+/// A resumed session's file starts with the earlier conversation copied over
+/// with its original `sessionId`, so the first entry carrying an id tells
+/// whether the file continues another session.
+pub fn parent_session_id(content: &str, session_id: &str) -> Option<String> {
+    for line in content.lines() {
+        let Ok(obj) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        if let Some(id) = obj.get("sessionId").and_then(Value::as_str) {
+            return (!id.is_empty() && id != session_id).then(|| id.to_string());
+        }
+    }
+    None
+}
+
+/// List all projects under the `projects` directory of every Claude root.
+pub fn list_projects() -> Result<Vec<ProjectInfo>> {
+    let mut projects = Vec::new();
+    for root in claude_roots() {
+        for project in list_projects_in(&root.projects_dir)? {
+            merge_project(&mut projects, project);
+        }
+    }
+    projects.sort_by(|a, b| a.dir_name.cmp(&b.dir_name));
+    Ok(projects)
+}
+
+/// Project directories as `(name, path)`, and the symlinks skipped as
+/// `path: reason`.
+pub(crate) type ProjectDirs = (Vec<(String, PathBuf)>, Vec<String>);
+
+/// Project directories under `projects_dir` as `(name, path)`, sorted by
+/// name. Symlinked directories are followed, except one that resolves to a
+/// directory already in `seen` (another name for a listed project) or to
+/// `projects_dir` or one of its parents (a loop back up the tree). Those, and
+/// dangling links, are returned in the second list as `path: reason`.
+pub(crate) fn project_dirs_in(
+    projects_dir: &Path,
+    seen: &mut HashSet<PathBuf>,
+) -> Result<ProjectDirs> {
+    let base = fs::canonicalize(projects_dir)?;
     let mut entries: Vec<_> = fs::read_dir(projects_dir)?
         .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
+        .filter_map(|e| Some((e.file_type().ok()?.is_symlink(), e)))
         .collect();
+    // 実体のディレクトリを先に見て、同じ場所へのリンクの方を飛ばす
+    entries.sort_by_key(|(link, e)| (*link, e.file_name()));
+
+    let mut dirs = Vec::new();
+    let mut skipped = Vec::new();
+    for (link, entry) in entries {
+        let path = entry.path();
+        let real = match fs::canonicalize(&path) {
+            Ok(real) if real.is_dir() => real,
+            Ok(_) => continue,
+            Err(e) => {
+                if link {
+                    skipped.push(format!("{}: broken symlink ({})", path.display(), e));
+                }
+                continue;
+            }
+        };
+        if link && base.starts_with(&real) {
+            skipped.push(format!("{}: symlink loop", path.display()));
+            continue;
+        }
+        if !seen.insert(real.clone()) {
+            skipped.push(format!("{}: same directory as {}", path.display(), real.display()));
+            continue;
+        }
+        dirs.push((entry.file_name().to_string_lossy().to_string(), path));
+    }
+    dirs.sort();
+    Ok((dirs, skipped))
+}
 
-    entries.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+pub(crate) fn list_projects_in(projects_dir: &Path) -> Result<Vec<ProjectInfo>> {
+    if !projects_dir.exists() {
+        return Ok(Vec::new());
+    }
 
+    let (dirs, _) = project_dirs_in(projects_dir, &mut HashSet::new())?;
     let mut projects = Vec::new();
-    for entry in entries {
-        let dir_name = entry.file_name().to_string_lossy().to_string();
-        let dir_path = entry.path();
-
+    for (dir_name, dir_path) in dirs {
         let original_path = try_get_original_path(&dir_path)
             .unwrap_or_else(|| decode_project_path(&dir_name));
 
@@ -242,15 +789,37 @@ pub(crate) fn list_projects_in(projects_dir: &Path) -> Result<Vec<ProjectInfo>>
 /// List sessions for a given project.
 ///
 /// Prefers sessions-index.json when available; falls back to scanning .jsonl files.
-pub fn list_sessions(project_name: &str) -> Result<Vec<SessionInfo>> {
-    let projects_dir = match claude_projects_dir() {
-        Some(d) => d,
-        None => return Ok(Vec::new()),
-    };
-    list_sessions_in(project_name, &projects_dir)
+/// Sessions of the project under every Claude root are listed together, with
+/// their cost estimated from `prices`.
+pub fn list_sessions(project_name: &str, prices: &PriceTable) -> Result<Vec<SessionInfo>> {
+    list_sessions_in_roots(project_name, claude_roots(), prices)
+}
+
+pub(crate) fn list_sessions_in_roots(project_name: &str, roots: &[ClaudeRoot], prices: &PriceTable) -> Result<Vec<SessionInfo>> {
+    let mut sessions: Vec<SessionInfo> = Vec::new();
+    for root in roots {
+        for mut session in list_sessions_in(project_name, &root.projects_dir, prices)? {
+            // 同じセッションが複数のルートにあれば先のルートを使う
+            if sessions.iter().any(|s| s.session_id == session.session_id) {
+                continue;
+            }
+            session.source_root = root.label.clone();
+            sessions.push(session);
+        }
+    }
+    sort_newest_first(&mut sessions);
+    Ok(sessions)
+}
+
+fn sort_newest_first(sessions: &mut [SessionInfo]) {
+    sessions.sort_by(|a, b| {
+        let ta = a.timestamp.unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let tb = b.timestamp.unwrap_or(DateTime::<Utc>::MIN_UTC);
+        tb.cmp(&ta)
+    });
 }
 
-pub(crate) fn list_sessions_in(project_name: &str, projects_dir: &Path) -> Result<Vec<SessionInfo>> {
+pub(crate) fn list_sessions_in(project_name: &str, projects_dir: &Path, prices: &PriceTable) -> Result<Vec<SessionInfo>> {
     let project_dir = projects_dir.join(project_name);
     if !project_dir.exists() {
         return Ok(Vec::new());
@@ -258,13 +827,13 @@ pub(crate) fn list_sessions_in(project_name: &str, projects_dir: &Path) -> Resul
 
     let index_path = project_dir.join("sessions-index.json");
     if index_path.exists() {
-        let sessions = list_sessions_from_index(project_name, &index_path);
+        let sessions = list_sessions_from_index(project_name, &index_path, prices);
         if !sessions.is_empty() {
             return Ok(sessions);
         }
     }
 
-    Ok(list_sessions_from_files(project_name, &project_dir))
+    Ok(list_sessions_from_files(project_name, &project_dir, prices))
 }
 
 /// Parse a single entry from sessions-index.json into a SessionInfo.
@@ -282,6 +851,7 @@ pub(crate) fn parse_index_entry(entry: &Value, project_name: &str) -> SessionInf
         200,
     );
     let timestamp = parse_timestamp(entry.get("created").and_then(Value::as_str));
+    let modified = parse_timestamp(entry.get("modified").and_then(Value::as_str));
     let message_count = entry
         .get("messageCount")
         .and_then(Value::as_u64)
@@ -302,13 +872,23 @@ pub(crate) fn parse_index_entry(entry: &Value, project_name: &str) -> SessionInf
         project_name: project_name.to_string(),
         preview,
         timestamp,
+        modified,
         message_count,
         git_branch,
         summary,
+        needs_input: false,
+        usage: TokenUsage::default(),
+        cost_usd: 0.0,
+        has_loops: false,
+        parent_session_id: String::new(),
+        version: String::new(),
+        user_type: String::new(),
+        source_root: String::new(),
+        warning: String::new(),
     }
 }
 
-fn list_sessions_from_index(project_name: &str, index_path: &Path) -> Vec<SessionInfo> {
+fn list_sessions_from_index(project_name: &str, index_path: &Path, prices: &PriceTable) -> Vec<SessionInfo> {
     let content = match fs::read_to_string(index_path) {
         Ok(c) => c,
         Err(_) => return Vec::new(),
@@ -323,22 +903,42 @@ fn list_sessions_from_index(project_name: &str, index_path: &Path) -> Vec<Sessio
         None => return Vec::new(),
     };
 
+    let project_dir = index_path.parent().unwrap_or(Path::new("."));
     let mut sessions: Vec<SessionInfo> = entries
         .iter()
-        .map(|entry| parse_index_entry(entry, project_name))
+        .map(|entry| {
+            let mut info = parse_index_entry(entry, project_name);
+            let jsonl_path = project_dir.join(format!("{}.jsonl", info.session_id));
+            if let Ok((content, warning)) = read_transcript(&jsonl_path) {
+                apply_file_stats(&mut info, scan_transcript(&content, prices));
+                info.warning = warning.unwrap_or_default();
+            }
+            info
+        })
         .collect();
 
-    // Sort by timestamp descending (newest first)
-    sessions.sort_by(|a, b| {
-        let ta = a.timestamp.unwrap_or(DateTime::<Utc>::MIN_UTC);
-        let tb = b.timestamp.unwrap_or(DateTime::<Utc>::MIN_UTC);
-        tb.cmp(&ta)
-    });
+    sort_newest_first(&mut sessions);
+
+    sessions
+}
 
+/// Sessions whose transcripts were archived into `dirs` (see
+/// `cleanup::archive_dir`), newest first. A session in more than one of
+/// them is listed once.
+pub fn list_archived_sessions(project_name: &str, dirs: &[PathBuf], prices: &PriceTable) -> Vec<SessionInfo> {
+    let mut sessions: Vec<SessionInfo> = Vec::new();
+    for dir in dirs {
+        for session in list_sessions_from_files(project_name, dir, prices) {
+            if !sessions.iter().any(|s| s.session_id == session.session_id) {
+                sessions.push(session);
+            }
+        }
+    }
+    sort_newest_first(&mut sessions);
     sessions
 }
 
-fn list_sessions_from_files(project_name: &str, project_dir: &Path) -> Vec<SessionInfo> {
+fn list_sessions_from_files(project_name: &str, project_dir: &Path, prices: &PriceTable) -> Vec<SessionInfo> {
     let mut sessions = Vec::new();
 
     let entries = match fs::read_dir(project_dir) {
@@ -354,87 +954,183 @@ fn list_sessions_from_files(project_name: &str, project_dir: &Path) -> Vec<Sessi
                 .map(|s| s.to_string_lossy().to_string())
                 .unwrap_or_default();
 
-            let mut preview = String::new();
-            let mut timestamp: Option<DateTime<Utc>> = None;
-            let mut git_branch = String::new();
-            let mut message_count: usize = 0;
-
-            if let Ok(content) = fs::read_to_string(&path) {
-                for line in content.lines() {
-                    let line = line.trim();
-                    if line.is_empty() {
-                        continue;
-                    }
-                    let obj: Value = match serde_json::from_str(line) {
-                        Ok(v) => v,
-                        Err(_) => continue,
-                    };
-
-                    let msg_type = obj.get("type").and_then(Value::as_str).unwrap_or("");
-                    if msg_type == "user" || msg_type == "assistant" {
-                        message_count += 1;
-                    }
-
-                    if msg_type == "user" && preview.is_empty() {
-                        let msg_content = obj
-                            .get("message")
-                            .and_then(|m| m.get("content"))
-                            .cloned()
-                            .unwrap_or(Value::String(String::new()));
-                        preview = truncate_str(&extract_text_from_content(&msg_content), 200);
-                        timestamp =
-                            parse_timestamp(obj.get("timestamp").and_then(Value::as_str));
-                        git_branch = obj
-                            .get("gitBranch")
-                            .and_then(Value::as_str)
-                            .unwrap_or("")
-                            .to_string();
-                    }
-                }
-            }
-
-            sessions.push(SessionInfo {
+            let (scan, warning) = match read_transcript(&path) {
+                Ok((content, warning)) => (Some(scan_transcript(&content, prices)), warning),
+                Err(_) => (None, None),
+            };
+            let mut info = SessionInfo {
                 session_id,
                 project_name: project_name.to_string(),
-                preview,
-                timestamp,
-                message_count,
-                git_branch,
+                preview: scan.as_ref().map(|s| s.preview.clone()).unwrap_or_default(),
+                timestamp: scan.as_ref().and_then(|s| s.first_prompt_at),
+                modified: None,
+                message_count: scan.as_ref().map_or(0, |s| s.message_count),
+                git_branch: scan.as_ref().map(|s| s.git_branch.clone()).unwrap_or_default(),
                 summary: String::new(),
-            });
+                needs_input: false,
+                usage: TokenUsage::default(),
+                cost_usd: 0.0,
+                has_loops: false,
+                parent_session_id: String::new(),
+                version: String::new(),
+                user_type: String::new(),
+                source_root: String::new(),
+                warning: warning.unwrap_or_default(),
+            };
+            if let Some(scan) = scan {
+                apply_file_stats(&mut info, scan);
+            }
+            sessions.push(info);
         }
     }
 
-    // Sort by timestamp descending
-    sessions.sort_by(|a, b| {
-        let ta = a.timestamp.unwrap_or(DateTime::<Utc>::MIN_UTC);
-        let tb = b.timestamp.unwrap_or(DateTime::<Utc>::MIN_UTC);
-        tb.cmp(&ta)
-    });
+    sort_newest_first(&mut sessions);
 
     sessions
 }
 
-/// Load all messages from a session JSONL file.
+/// Path of a session's JSONL file under the projects directory of its root.
+pub fn session_file_path(project_name: &str, session_id: &str) -> Option<PathBuf> {
+    session_root(project_name, session_id)
+        .map(|r| r.projects_dir.join(project_name).join(format!("{}.jsonl", session_id)))
+}
+
+/// Load all messages from a session JSONL file, followed by those of the
+/// subagent transcripts kept beside it (marked `sidechain`).
 pub fn load_session(project_name: &str, session_id: &str) -> Result<Vec<Message>> {
-    let projects_dir = match claude_projects_dir() {
-        Some(d) => d,
-        None => return Ok(Vec::new()),
-    };
-    load_session_in(project_name, session_id, &projects_dir)
+    match session_root(project_name, session_id) {
+        Some(root) => load_session_in(project_name, session_id, &root.projects_dir),
+        None => Ok(Vec::new()),
+    }
 }
 
 pub(crate) fn load_session_in(project_name: &str, session_id: &str, projects_dir: &Path) -> Result<Vec<Message>> {
-    let jsonl_path = projects_dir
-        .join(project_name)
-        .join(format!("{}.jsonl", session_id));
+    load_transcript_in(&projects_dir.join(project_name), session_id)
+}
+
+/// Load the session `session_id` from the directory holding its JSONL file,
+/// such as an archive folder, with its subagent transcripts.
+pub fn load_transcript_in(dir: &Path, session_id: &str) -> Result<Vec<Message>> {
+    let jsonl_path = dir.join(format!("{}.jsonl", session_id));
 
     if !jsonl_path.exists() {
         return Ok(Vec::new());
     }
 
-    let content = fs::read_to_string(&jsonl_path)?;
-    Ok(content.lines().flat_map(parse_jsonl_line).collect())
+    let (content, _) = read_transcript(&jsonl_path)?;
+    let mut messages = transcript_messages(&content);
+    // 新しい Claude Code はサブエージェントの会話を <session>/subagents/ に別ファイルで書く
+    let subagents_dir = dir.join(session_id).join("subagents");
+    if let Ok(entries) = fs::read_dir(&subagents_dir) {
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let Ok((content, _)) = read_transcript(&path) else { continue };
+            messages.extend(transcript_messages(&content).into_iter().map(|m| Message { sidechain: true, ..m }));
+        }
+    }
+    Ok(messages)
+}
+
+/// The messages of a transcript, each keeping its source line.
+fn transcript_messages(content: &str) -> Vec<Message> {
+    let mut messages = Vec::new();
+    // メッセージにならなかったエントリの親。メッセージの親はこれをたどって付け替える
+    let mut skipped: HashMap<String, Option<String>> = HashMap::new();
+    for line in content.lines() {
+        let parsed = parse_jsonl_line(line);
+        if parsed.is_empty() {
+            if let Ok(obj) = serde_json::from_str::<Value>(line)
+                && let Some(uuid) = obj.get("uuid").and_then(Value::as_str)
+            {
+                let parent = obj.get("parentUuid").and_then(Value::as_str).map(String::from);
+                skipped.insert(uuid.to_string(), parent);
+            }
+            continue;
+        }
+        let source: std::sync::Arc<str> = line.trim().into();
+        let mut parent_uuid = parsed[0].parent_uuid.clone();
+        // 循環した親でも止まるよう、たどる回数を飛ばしたエントリの数までにする
+        for _ in 0..skipped.len() {
+            match parent_uuid.as_ref().and_then(|p| skipped.get(p)) {
+                Some(grandparent) => parent_uuid = grandparent.clone(),
+                None => break,
+            }
+        }
+        messages.extend(parsed.into_iter().map(|m| Message {
+            source_line: Some(source.clone()),
+            parent_uuid: parent_uuid.clone(),
+            ..m
+        }));
+    }
+    messages
+}
+
+/// What the parser made of each line of a transcript, for `--debug-parse`.
+#[derive(Debug, Default)]
+pub struct ParseDiagnostics {
+    /// Each parsed message with the 1-based line it came from.
+    pub messages: Vec<(usize, Message)>,
+    /// Non-blank lines that produced no message, with the reason.
+    pub skipped: Vec<(usize, String)>,
+}
+
+impl ParseDiagnostics {
+    /// Parse `content` line by line, keeping track of what was dropped.
+    pub fn of(content: &str) -> ParseDiagnostics {
+        let mut diag = ParseDiagnostics::default();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed = parse_jsonl_line(line);
+            if parsed.is_empty() {
+                let reason = match serde_json::from_str::<Value>(line.trim()) {
+                    Err(_) => "invalid JSON".to_string(),
+                    Ok(obj) => match obj.get("type").and_then(Value::as_str) {
+                        Some(t) => format!("type {}", t),
+                        None => "no type".to_string(),
+                    },
+                };
+                diag.skipped.push((i + 1, reason));
+            }
+            diag.messages.extend(parsed.into_iter().map(|m| (i + 1, m)));
+        }
+        diag
+    }
+
+    /// One line per message (`line role tool text`), then the skipped lines
+    /// counted by reason, most frequent first.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        for (line, msg) in &self.messages {
+            let text: String = msg.text.chars().take(80).collect();
+            out.push_str(&format!(
+                "{:>6}  {:<9}  {:<12}  {}\n",
+                line,
+                msg.role_label(),
+                msg.tool_name.as_deref().unwrap_or("-"),
+                text.replace('\n', "\\n")
+            ));
+        }
+        let mut reasons: Vec<(&str, usize)> = Vec::new();
+        for (_, reason) in &self.skipped {
+            match reasons.iter_mut().find(|(r, _)| *r == reason) {
+                Some((_, n)) => *n += 1,
+                None => reasons.push((reason, 1)),
+            }
+        }
+        reasons.sort_by_key(|r| std::cmp::Reverse(r.1));
+        out.push_str(&format!("\n{} messages, {} lines skipped\n", self.messages.len(), self.skipped.len()));
+        for (reason, n) in reasons {
+            let first = self.skipped.iter().find(|(_, r)| r == reason).map_or(0, |(l, _)| *l);
+            out.push_str(&format!("{:>6}  {} (first at line {})\n", n, reason, first));
+        }
+        out
+    }
 }
 
 /// Parse a single JSONL line into zero or more Messages.
@@ -445,15 +1141,18 @@ pub(crate) fn parse_jsonl_line(line: &str) -> Vec<Message> {
     if line.is_empty() {
         return Vec::new();
     }
-    let obj: Value = match serde_json::from_str(line) {
-        Ok(v) => v,
-        Err(_) => return Vec::new(),
-    };
+    match serde_json::from_str(line) {
+        Ok(obj) => entry_messages(&obj),
+        Err(_) => Vec::new(),
+    }
+}
 
+/// The messages of one parsed JSONL entry (see `parse_jsonl_line`).
+fn entry_messages(obj: &Value) -> Vec<Message> {
     let msg_type = obj.get("type").and_then(Value::as_str).unwrap_or("");
     let timestamp = parse_timestamp(obj.get("timestamp").and_then(Value::as_str));
 
-    match msg_type {
+    let mut messages = match msg_type {
         "user" => {
             let msg_content = obj
                 .get("message")
@@ -484,6 +1183,13 @@ pub(crate) fn parse_jsonl_line(line: &str) -> Vec<Message> {
                                 text: result_text,
                                 timestamp,
                                 tool_name: None,
+                                tool_input: None,
+                                model: None,
+                                usage: None,
+                                source_line: None,
+                                uuid: None,
+                                parent_uuid: None,
+                                sidechain: false,
                             });
                         }
                     }
@@ -496,6 +1202,13 @@ pub(crate) fn parse_jsonl_line(line: &str) -> Vec<Message> {
                             text,
                             timestamp,
                             tool_name: None,
+                            tool_input: None,
+                            model: None,
+                            usage: None,
+                            source_line: None,
+                            uuid: None,
+                            parent_uuid: None,
+                            sidechain: false,
                         });
                     }
                 }
@@ -507,6 +1220,13 @@ pub(crate) fn parse_jsonl_line(line: &str) -> Vec<Message> {
                         text,
                         timestamp,
                         tool_name: None,
+                        tool_input: None,
+                        model: None,
+                        usage: None,
+                        source_line: None,
+                        uuid: None,
+                        parent_uuid: None,
+                        sidechain: false,
                     });
                 }
             }
@@ -524,11 +1244,19 @@ pub(crate) fn parse_jsonl_line(line: &str) -> Vec<Message> {
             // Extract text portion
             let text = extract_text_from_content(&msg_content);
             if !text.is_empty() {
+                let message = obj.get("message");
                 messages.push(Message {
                     role: MessageRole::Assistant,
                     text,
                     timestamp,
                     tool_name: None,
+                    tool_input: None,
+                    model: message.and_then(|m| m.get("model")).and_then(Value::as_str).map(String::from),
+                    usage: message.and_then(|m| m.get("usage")).map(parse_usage),
+                    source_line: None,
+                    uuid: None,
+                    parent_uuid: None,
+                    sidechain: false,
                 });
             }
 
@@ -551,6 +1279,13 @@ pub(crate) fn parse_jsonl_line(line: &str) -> Vec<Message> {
                             text: summary,
                             timestamp,
                             tool_name: Some(tool_name),
+                            tool_input: Some(tool_input),
+                            model: None,
+                            usage: None,
+                            source_line: None,
+                            uuid: None,
+                            parent_uuid: None,
+                            sidechain: false,
                         });
                     }
                 }
@@ -589,19 +1324,35 @@ pub(crate) fn parse_jsonl_line(line: &str) -> Vec<Message> {
                 text,
                 timestamp,
                 tool_name: None,
+                tool_input: None,
+                model: None,
+                usage: None,
+                source_line: None,
+                uuid: None,
+                parent_uuid: None,
+                sidechain: false,
             }]
         }
         _ => {
             // Skip unknown types (e.g. "file-history-snapshot", "progress")
             Vec::new()
         }
+    };
+    let uuid = obj.get("uuid").and_then(Value::as_str);
+    let parent_uuid = obj.get("parentUuid").and_then(Value::as_str);
+    let sidechain = obj.get("isSidechain").and_then(Value::as_bool).unwrap_or(false);
+    for message in &mut messages {
+        message.uuid = uuid.map(String::from);
+        message.parent_uuid = parent_uuid.map(String::from);
+        message.sidechain = sidechain;
     }
+    messages
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::MessageRole;
+    use crate::models::{MessageRole, ModelPrice};
     use serde_json::json;
     use std::fs;
     use tempfile::TempDir;
@@ -782,6 +1533,17 @@ mod tests {
         assert_eq!(msgs[0].text, "response");
     }
 
+    #[test]
+    fn parse_jsonl_line_assistant_model_and_usage() {
+        let line = r#"{"type":"assistant","message":{"model":"claude-sonnet-4-5","usage":{"input_tokens":200,"output_tokens":3400,"cache_read_input_tokens":1000},"content":[{"type":"text","text":"done"},{"type":"tool_use","name":"Read","input":{}}]}}"#;
+        let msgs = parse_jsonl_line(line);
+        assert_eq!(msgs[0].model.as_deref(), Some("claude-sonnet-4-5"));
+        let usage = msgs[0].usage.unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens, usage.cache_read_tokens), (200, 3400, 1000));
+        // 見出しを出すのは回答の本文だけ
+        assert_eq!((msgs[1].model.as_deref(), msgs[1].usage), (None, None));
+    }
+
     #[test]
     fn parse_jsonl_line_assistant_with_tool_use() {
         let line = r#"{"type":"assistant","timestamp":"2024-01-15T10:30:00Z","message":{"content":[{"type":"text","text":"Let me check"},{"type":"tool_use","name":"Read","input":{"file_path":"/tmp/test.txt"}}]}}"#;
@@ -819,6 +1581,222 @@ mod tests {
         assert!(parse_jsonl_line(line).is_empty());
     }
 
+    #[test]
+    fn parse_diagnostics_report_messages_and_skipped_lines() {
+        let content = [
+            r#"{"type":"user","message":{"content":"fix the\nbug"}}"#,
+            "",
+            r#"{"type":"progress","data":{}}"#,
+            "{broken",
+            r#"{"type":"progress","data":{}}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"ok"},{"type":"tool_use","name":"Read","input":{}}]}}"#,
+        ]
+        .join("\n");
+        let diag = ParseDiagnostics::of(&content);
+        let lines: Vec<usize> = diag.messages.iter().map(|(l, _)| *l).collect();
+        assert_eq!(lines, vec![1, 6, 6]);
+        assert_eq!(diag.skipped, vec![(3, "type progress".to_string()), (4, "invalid JSON".to_string()), (5, "type progress".to_string())]);
+
+        let report = diag.report();
+        assert!(report.starts_with("     1  USER       -             fix the\\nbug\n"), "{}", report);
+        assert!(report.contains("     6  TOOL       Read "));
+        assert!(report.ends_with("3 messages, 3 lines skipped\n     2  type progress (first at line 3)\n     1  invalid JSON (first at line 4)\n"));
+    }
+
+    // ================================================================
+    // is_awaiting_input
+    // ================================================================
+
+    #[test]
+    fn is_awaiting_input_last_assistant() {
+        let content = r#"{"type":"user","message":{"content":"hello"}}
+{"type":"assistant","message":{"content":"hi there"}}
+{"type":"system","subtype":"turn_duration"}"#;
+        assert!(is_awaiting_input(content));
+    }
+
+    #[test]
+    fn is_awaiting_input_pending_tool_use() {
+        let content = r#"{"type":"user","message":{"content":"delete tmp"}}
+{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Bash","input":{"command":"rm -rf tmp"}}]}}"#;
+        assert!(is_awaiting_input(content));
+    }
+
+    #[test]
+    fn is_awaiting_input_last_user() {
+        let content = r#"{"type":"assistant","message":{"content":"done"}}
+{"type":"user","message":{"content":"thanks"}}"#;
+        assert!(!is_awaiting_input(content));
+    }
+
+    #[test]
+    fn is_awaiting_input_empty() {
+        assert!(!is_awaiting_input(""));
+    }
+
+    #[test]
+    fn scan_transcript_agrees_with_the_single_purpose_parsers() {
+        let content = r#"{"type":"user","sessionId":"parent","version":"2.0.1","userType":"external","timestamp":"2026-01-15T10:00:05Z","gitBranch":"main","message":{"content":"fix the build"}}
+not json
+{"type":"assistant","sessionId":"child","version":"2.0.2","timestamp":"2026-01-15T10:00:01Z","message":{"id":"m1","model":"claude-sonnet-4","usage":{"input_tokens":10,"output_tokens":5},"content":[{"type":"text","text":"done"}]}}
+{"type":"assistant","sessionId":"child","timestamp":"2026-01-15T10:00:09Z","message":{"id":"m1","model":"claude-sonnet-4","usage":{"input_tokens":10,"output_tokens":5},"content":[{"type":"tool_use","name":"Bash","input":{"command":"cargo build"}}]}}
+{"type":"system","timestamp":"2026-01-15T10:00:10Z","message":{"content":"compacted"}}"#;
+        let scan = scan_transcript(content, &PriceTable::default());
+        assert_eq!(scan.message_count, 3);
+        assert_eq!(scan.preview, "fix the build");
+        assert_eq!(scan.git_branch, "main");
+        assert_eq!(scan.last_timestamp, timestamp_range(content).map(|(_, last)| last));
+        assert_eq!(scan.last_turn == "assistant", is_awaiting_input(content));
+        assert_eq!(scan.usage, summarize_usage(content, &PriceTable::default(), None));
+        assert_eq!(scan.messages.len(), content.lines().flat_map(parse_jsonl_line).count());
+        assert_eq!((scan.version.clone(), scan.user_type.clone()), client_info(content));
+
+        let mut info = parse_index_entry(&json!({"sessionId": "child"}), "p");
+        apply_file_stats(&mut info, scan);
+        assert_eq!(info.parent_session_id, parent_session_id(content, "child").unwrap());
+        assert_eq!(info.usage.total(), 15);
+    }
+
+    // ================================================================
+    // summarize_usage
+    // ================================================================
+
+    #[test]
+    fn summarize_usage_counts_each_message_once() {
+        let content = r#"{"type":"assistant","message":{"id":"m1","model":"claude-sonnet-4","usage":{"input_tokens":1000,"output_tokens":200,"cache_read_input_tokens":5000},"content":[{"type":"text","text":"a"}]}}
+{"type":"assistant","message":{"id":"m1","model":"claude-sonnet-4","usage":{"input_tokens":1000,"output_tokens":200,"cache_read_input_tokens":5000},"content":[{"type":"tool_use","name":"Read","input":{}}]}}
+{"type":"user","message":{"content":"next"}}
+{"type":"assistant","message":{"id":"m2","model":"claude-opus-4-1","usage":{"input_tokens":10,"output_tokens":10,"cache_creation_input_tokens":100}}}"#;
+        let UsageSummary { usage, cost_usd: cost, .. } = summarize_usage(content, &PriceTable::default(), None);
+        assert_eq!(usage.input_tokens, 1010);
+        assert_eq!(usage.output_tokens, 210);
+        assert_eq!(usage.cache_read_tokens, 5000);
+        assert_eq!(usage.cache_creation_tokens, 100);
+        let expected = (1000.0 * 3.0 + 200.0 * 15.0 + 5000.0 * 0.3) / 1e6
+            + (10.0 * 15.0 + 10.0 * 75.0 + 100.0 * 18.75) / 1e6;
+        assert!((cost - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn summarize_usage_resumes_after_last_counted_message() {
+        let content = r#"{"type":"assistant","message":{"id":"m1","model":"claude-sonnet-4","usage":{"input_tokens":100,"output_tokens":10}}}
+{"type":"assistant","message":{"id":"m2","model":"claude-sonnet-4","usage":{"input_tokens":7,"output_tokens":3}}}"#;
+        let mut prices = PriceTable::default();
+        prices.0.insert(
+            "sonnet".to_string(),
+            ModelPrice { input: 1_000_000.0, output: 0.0, cache_write: 0.0, cache_read: 0.0 },
+        );
+
+        let summary = summarize_usage(content, &prices, Some("m1"));
+        assert_eq!(summary.usage.input_tokens, 7);
+        assert_eq!(summary.cost_usd, 7.0);
+        assert_eq!(summary.last_message_id, "m2");
+    }
+
+    #[test]
+    fn session_models_in_first_use_order() {
+        let content = r#"{"type":"assistant","message":{"model":"claude-sonnet-4","content":"a"}}
+{"type":"user","message":{"content":"q"}}
+{"type":"assistant","message":{"model":"<synthetic>","content":"b"}}
+{"type":"assistant","message":{"model":"claude-opus-4-1","content":"c"}}
+{"type":"assistant","message":{"model":"claude-sonnet-4","content":"d"}}"#;
+        assert_eq!(session_models(content), vec!["claude-sonnet-4", "claude-opus-4-1"]);
+    }
+
+    #[test]
+    fn timestamp_range_spans_all_entries() {
+        let content = r#"{"type":"summary","summary":"x"}
+{"type":"user","timestamp":"2026-01-15T10:05:00Z","message":{"content":"b"}}
+{"type":"user","timestamp":"2026-01-15T10:00:00Z","message":{"content":"a"}}
+{"type":"assistant","timestamp":"2026-01-15T11:30:00Z","message":{"content":"c"}}"#;
+        let (first, last) = timestamp_range(content).unwrap();
+        assert_eq!(first.to_rfc3339(), "2026-01-15T10:00:00+00:00");
+        assert_eq!(last.to_rfc3339(), "2026-01-15T11:30:00+00:00");
+        assert!(timestamp_range(r#"{"type":"summary"}"#).is_none());
+    }
+
+    #[test]
+    fn tool_counts_in_first_use_order() {
+        let content = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"ls"}},{"type":"tool_use","id":"t2","name":"Read","input":{}}]}}
+{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"}]}}
+{"type":"assistant","message":{"content":[{"type":"text","text":"again"},{"type":"tool_use","id":"t3","name":"Bash","input":{"command":"ls -a"}}]}}"#;
+        assert_eq!(
+            tool_counts(content),
+            vec![("Bash".to_string(), 2), ("Read".to_string(), 1)]
+        );
+        assert!(tool_counts("").is_empty());
+    }
+
+    #[test]
+    fn command_names_normalizes_each_step() {
+        assert_eq!(
+            command_names("cd /p && RUST_LOG=debug cargo test -q; git status | head -5"),
+            vec!["cargo test", "git status"]
+        );
+        assert_eq!(command_names("/usr/bin/python3 x.py || echo failed"), vec!["python3", "echo"]);
+        assert_eq!(command_names("cargo --version\nls -la &"), vec!["cargo", "ls"]);
+        assert!(command_names("  ").is_empty());
+    }
+
+    #[test]
+    fn command_counts_only_counts_bash_calls() {
+        let content = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"cargo build && cargo test"}},{"type":"tool_use","id":"t2","name":"Read","input":{"file_path":"/a"}}]}}
+{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t3","name":"Bash","input":{"command":"cargo test parser"}}]}}"#;
+        assert_eq!(
+            command_counts(content),
+            vec![("cargo build".to_string(), 1), ("cargo test".to_string(), 2)]
+        );
+        assert!(command_counts("").is_empty());
+    }
+
+    #[test]
+    fn session_files_collects_file_tool_paths() {
+        let content = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Read","input":{"file_path":"/p/src/app.rs"}},{"type":"tool_use","id":"t2","name":"Bash","input":{"command":"ls"}}]}}
+{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"fn main"}]}}
+{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t3","name":"Edit","input":{"file_path":"/p/src/app.rs"}},{"type":"tool_use","id":"t4","name":"Read","input":{"file_path":"/p/src/app.rs"}}]}}
+{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t5","name":"NotebookEdit","input":{"notebook_path":"/p/nb.ipynb"}},{"type":"tool_use","id":"t6","name":"Write","input":{}}]}}"#;
+        let pair = |p: &str, t: &str| (p.to_string(), t.to_string());
+        assert_eq!(
+            session_files(content),
+            vec![
+                pair("/p/src/app.rs", "Read"),
+                pair("/p/src/app.rs", "Edit"),
+                pair("/p/nb.ipynb", "NotebookEdit"),
+            ]
+        );
+        assert!(is_file_modifying_tool("Write"));
+        assert!(!is_file_modifying_tool("Read"));
+    }
+
+    #[test]
+    fn parent_session_id_from_copied_entries() {
+        let resumed = r#"{"type":"summary","summary":"Fix login","leafUuid":"u2"}
+{"type":"user","sessionId":"orig-1","uuid":"u1","message":{"content":"fix login"}}
+{"type":"user","sessionId":"new-2","uuid":"u3","message":{"content":"continue"}}"#;
+        assert_eq!(parent_session_id(resumed, "new-2").as_deref(), Some("orig-1"));
+
+        let fresh = r#"{"type":"user","sessionId":"new-2","message":{"content":"hi"}}
+{"type":"user","sessionId":"other","message":{"content":"later"}}"#;
+        assert_eq!(parent_session_id(fresh, "new-2"), None);
+        assert_eq!(parent_session_id("", "new-2"), None);
+    }
+
+    #[test]
+    fn client_info_takes_the_latest_recorded_values() {
+        let content = r#"{"type":"user","version":"2.0.9","userType":"external","message":{"content":"hi"}}
+{"type":"assistant","version":"2.0.10","message":{"content":"ok"}}
+{"type":"summary","summary":"Fix login"}"#;
+        assert_eq!(client_info(content), ("2.0.10".to_string(), "external".to_string()));
+        assert_eq!(client_info(""), (String::new(), String::new()));
+    }
+
+    #[test]
+    fn summarize_usage_empty() {
+        let summary = summarize_usage("", &PriceTable::default(), None);
+        assert_eq!(summary.usage.total(), 0);
+        assert_eq!(summary.cost_usd, 0.0);
+    }
+
     // ================================================================
     // parse_index_entry
     // ================================================================
@@ -888,6 +1866,42 @@ mod tests {
         assert!(result.is_empty());
     }
 
+    #[test]
+    fn roots_in_expands_home_and_labels_each_root() {
+        let home = Path::new("/home/me");
+        let roots = roots_in(
+            home,
+            &["~/.claude-work".to_string(), "/home/me/.claude".to_string(), "/mnt/old/.claude".to_string()],
+        );
+        let labels: Vec<&str> = roots.iter().map(|r| r.label.as_str()).collect();
+        // 重複は除き、名前がかぶるルートはパスで呼ぶ
+        assert_eq!(labels, vec!["claude", "claude-work", "/mnt/old/.claude"]);
+        assert_eq!(roots[1].projects_dir, Path::new("/home/me/.claude-work/projects"));
+        assert_eq!(roots[1].history_dir(), Path::new("/home/me/.claude-work/file-history"));
+    }
+
+    #[test]
+    fn list_sessions_in_roots_tags_and_merges_roots() {
+        let tmp = TempDir::new().unwrap();
+        let roots = [
+            ClaudeRoot::new(&tmp.path().join(".claude").join("projects")),
+            ClaudeRoot::new(&tmp.path().join(".claude-work").join("projects")),
+        ];
+        let line = |day: u32| format!(r#"{{"type":"user","timestamp":"2024-01-{:02}T10:00:00Z","message":{{"content":"hi"}}}}"#, day);
+        for (root, id, day) in [(&roots[0], "a", 1), (&roots[1], "b", 2), (&roots[1], "a", 3)] {
+            let dir = root.projects_dir.join("-p");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("{}.jsonl", id)), line(day)).unwrap();
+        }
+
+        let sessions = list_sessions_in_roots("-p", &roots, &PriceTable::default()).unwrap();
+        let rows: Vec<(&str, &str)> = sessions.iter().map(|s| (s.session_id.as_str(), s.source_root.as_str())).collect();
+        assert_eq!(rows, vec![("b", "claude-work"), ("a", "claude")]);
+        assert_eq!(session_root_in(&roots, "-p", "b"), Some(&roots[1]));
+        assert_eq!(session_root_in(&roots, "-p", "a"), Some(&roots[0]));
+        assert_eq!(session_root_in(&roots, "-p", "missing"), Some(&roots[0]));
+    }
+
     #[test]
     fn list_sessions_in_from_jsonl_files() {
         let tmp = TempDir::new().unwrap();
@@ -898,11 +1912,34 @@ mod tests {
 {"type":"assistant","timestamp":"2024-01-15T10:31:00Z","message":{"content":"hi there"}}"#;
         fs::write(project_dir.join("session-abc.jsonl"), jsonl_content).unwrap();
 
-        let result = list_sessions_in("my-project", tmp.path()).unwrap();
+        let result = list_sessions_in("my-project", tmp.path(), &PriceTable::default()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].session_id, "session-abc");
         assert_eq!(result[0].message_count, 2);
         assert_eq!(result[0].preview, "hello");
+        assert!(result[0].needs_input);
+        assert_eq!(result[0].timestamp_str(), "2024-01-15 10:30:00");
+        assert_eq!(result[0].modified.map(|t| t.to_rfc3339()).as_deref(), Some("2024-01-15T10:31:00+00:00"));
+    }
+
+    #[test]
+    fn list_sessions_in_prices_usage_with_the_given_table() {
+        let tmp = TempDir::new().unwrap();
+        let project_dir = tmp.path().join("my-project");
+        fs::create_dir(&project_dir).unwrap();
+        fs::write(
+            project_dir.join("s1.jsonl"),
+            r#"{"type":"assistant","message":{"id":"m1","model":"claude-sonnet-4","usage":{"input_tokens":1000000,"output_tokens":0}}}"#,
+        )
+        .unwrap();
+
+        let builtin = list_sessions_in("my-project", tmp.path(), &PriceTable::default()).unwrap();
+        assert_eq!(builtin[0].cost_usd, 3.0);
+        let mut prices = PriceTable::default();
+        prices.0.insert("sonnet".to_string(), ModelPrice { input: 1.0, output: 1.0, cache_write: 0.0, cache_read: 0.0 });
+        let configured = list_sessions_in("my-project", tmp.path(), &prices).unwrap();
+        assert_eq!(configured[0].usage.input_tokens, 1_000_000);
+        assert_eq!(configured[0].cost_usd, 1.0);
     }
 
     #[test]
@@ -929,7 +1966,7 @@ mod tests {
         )
         .unwrap();
 
-        let result = list_sessions_in("my-project", tmp.path()).unwrap();
+        let result = list_sessions_in("my-project", tmp.path(), &PriceTable::default()).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].session_id, "sess-1");
         assert_eq!(result[0].preview, "First prompt");
@@ -954,6 +1991,120 @@ mod tests {
         assert_eq!(msgs[1].text, "hi there");
     }
 
+    #[test]
+    fn load_session_in_keeps_each_source_line() {
+        let tmp = TempDir::new().unwrap();
+        let project_dir = tmp.path().join("my-project");
+        fs::create_dir(&project_dir).unwrap();
+        let line = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Let me check"},{"type":"tool_use","name":"Read","input":{"file_path":"/a"}}]}}"#;
+        fs::write(project_dir.join("sess-1.jsonl"), format!("  {}\n", line)).unwrap();
+
+        let msgs = load_session_in("my-project", "sess-1", tmp.path()).unwrap();
+        assert_eq!(msgs.len(), 2);
+        // 同じ行から作ったメッセージは元の行を共有する
+        assert_eq!(msgs[0].source_line.as_deref(), Some(line));
+        assert!(std::sync::Arc::ptr_eq(msgs[0].source_line.as_ref().unwrap(), msgs[1].source_line.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn load_session_in_links_messages_past_skipped_entries() {
+        let tmp = TempDir::new().unwrap();
+        let project_dir = tmp.path().join("my-project");
+        fs::create_dir(&project_dir).unwrap();
+        fs::write(
+            project_dir.join("sess-1.jsonl"),
+            r#"{"type":"user","uuid":"u1","parentUuid":null,"message":{"content":"go"}}
+{"type":"progress","uuid":"p1","parentUuid":"u1"}
+{"type":"progress","uuid":"p2","parentUuid":"p1"}
+{"type":"assistant","uuid":"a1","parentUuid":"p2","message":{"content":[{"type":"text","text":"ok"},{"type":"tool_use","name":"Bash","input":{}}]}}
+"#,
+        )
+        .unwrap();
+
+        let msgs = load_session_in("my-project", "sess-1", tmp.path()).unwrap();
+        assert_eq!((msgs[0].uuid.as_deref(), msgs[0].parent_uuid.as_deref()), (Some("u1"), None));
+        // progress のエントリを飛ばして、メッセージのある u1 を親にする
+        for msg in &msgs[1..] {
+            assert_eq!((msg.uuid.as_deref(), msg.parent_uuid.as_deref()), (Some("a1"), Some("u1")));
+        }
+    }
+
+    #[test]
+    fn load_session_in_appends_subagent_transcripts_as_sidechain() {
+        let tmp = TempDir::new().unwrap();
+        let project_dir = tmp.path().join("my-project");
+        let subagents = project_dir.join("sess-1").join("subagents");
+        fs::create_dir_all(&subagents).unwrap();
+        fs::write(
+            project_dir.join("sess-1.jsonl"),
+            r#"{"type":"user","uuid":"u1","message":{"content":"review it"}}
+{"type":"user","uuid":"s1","isSidechain":true,"message":{"content":"inline agent"}}
+"#,
+        )
+        .unwrap();
+        fs::write(subagents.join("agent-b.jsonl"), r#"{"type":"user","uuid":"b1","message":{"content":"agent b"}}"#).unwrap();
+        fs::write(subagents.join("agent-a.jsonl"), r#"{"type":"user","uuid":"a1","message":{"content":"agent a"}}"#).unwrap();
+        fs::write(subagents.join("notes.txt"), "not a transcript").unwrap();
+
+        let msgs = load_session_in("my-project", "sess-1", tmp.path()).unwrap();
+        let summary: Vec<(&str, bool)> = msgs.iter().map(|m| (m.text.as_str(), m.sidechain)).collect();
+        assert_eq!(
+            summary,
+            vec![("review it", false), ("inline agent", true), ("agent a", true), ("agent b", true)]
+        );
+    }
+
+    #[test]
+    fn archived_sessions_are_listed_from_their_folders_only() {
+        let tmp = TempDir::new().unwrap();
+        let project_dir = tmp.path().join("my-project");
+        let archive = project_dir.join("archive");
+        let elsewhere = tmp.path().join("backup").join("my-project");
+        fs::create_dir_all(&archive).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        let line = |text: &str, ts: &str| {
+            format!(r#"{{"type":"user","timestamp":"{}","message":{{"content":"{}"}}}}"#, ts, text)
+        };
+        fs::write(project_dir.join("live.jsonl"), line("still here", "2026-01-03T00:00:00Z")).unwrap();
+        fs::write(archive.join("old.jsonl"), line("archived", "2026-01-01T00:00:00Z")).unwrap();
+        fs::write(elsewhere.join("old.jsonl"), line("archived twice", "2026-01-01T00:00:00Z")).unwrap();
+        fs::write(elsewhere.join("older.jsonl"), line("moved away", "2025-12-01T00:00:00Z")).unwrap();
+
+        let live = list_sessions_in("my-project", tmp.path(), &PriceTable::default()).unwrap();
+        assert_eq!(live.iter().map(|s| s.session_id.as_str()).collect::<Vec<_>>(), vec!["live"]);
+
+        let archived = list_archived_sessions("my-project", &[archive.clone(), elsewhere], &PriceTable::default());
+        let summary: Vec<(&str, &str)> = archived.iter().map(|s| (s.session_id.as_str(), s.preview.as_str())).collect();
+        assert_eq!(summary, vec![("old", "archived"), ("older", "moved away")]);
+        assert_eq!(load_transcript_in(&archive, "old").unwrap()[0].text, "archived");
+    }
+
+    #[test]
+    fn decode_transcript_strips_bom_and_replaces_invalid_bytes() {
+        let (text, warning) = decode_transcript(b"plain");
+        assert_eq!((text.as_ref(), warning), ("plain", None));
+        let (text, warning) = decode_transcript(b"\xEF\xBB\xBFna\xEFve\xFF");
+        assert_eq!(text, "na\u{FFFD}ve\u{FFFD}");
+        assert_eq!(warning.as_deref(), Some("byte-order mark removed, 2 invalid UTF-8 bytes replaced"));
+    }
+
+    #[test]
+    fn repaired_transcripts_load_and_are_listed_with_a_warning() {
+        let tmp = TempDir::new().unwrap();
+        let project_dir = tmp.path().join("my-project");
+        fs::create_dir(&project_dir).unwrap();
+        let mut bytes = b"\xEF\xBB\xBF".to_vec();
+        bytes.extend_from_slice(b"{\"type\":\"user\",\"message\":{\"content\":\"h\xE9llo\"}}\n");
+        fs::write(project_dir.join("sess-1.jsonl"), bytes).unwrap();
+
+        let msgs = load_session_in("my-project", "sess-1", tmp.path()).unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].text, "h\u{FFFD}llo");
+        let sessions = list_sessions_in("my-project", tmp.path(), &PriceTable::default()).unwrap();
+        assert_eq!(sessions[0].message_count, 1);
+        assert_eq!(sessions[0].warning, "byte-order mark removed, 1 invalid UTF-8 byte replaced");
+    }
+
     #[test]
     fn load_session_in_nonexistent_file() {
         let tmp = TempDir::new().unwrap();
@@ -0,0 +1,160 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+/// Points at which every plugin executable is invoked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// A background index build finished.
+    PostIndex,
+    /// A report, snapshot or index dump was written to disk.
+    OnExport,
+    /// A session was opened in the detail view.
+    OnOpenSession,
+}
+
+impl Hook {
+    /// Name passed to plugins as their first argument.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Hook::PostIndex => "post-index",
+            Hook::OnExport => "on-export",
+            Hook::OnOpenSession => "on-open-session",
+        }
+    }
+}
+
+pub fn plugins_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("cc-sessions-viewer").join("plugins"))
+}
+
+/// Executable files directly in `dir`, sorted by name. A missing directory has none.
+pub fn list_plugins_in(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut plugins: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|e| e.metadata().is_ok_and(|m| m.is_file() && is_executable(&m)))
+        .map(|e| e.path())
+        .collect();
+    plugins.sort();
+    plugins
+}
+
+#[cfg(unix)]
+fn is_executable(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_meta: &fs::Metadata) -> bool {
+    true
+}
+
+/// Run every plugin in `dir` as `<plugin> <hook>` with `payload` as JSON on
+/// stdin, one after another. Plugin output is discarded so it cannot draw
+/// over the TUI. Returns each plugin with its exit status.
+pub fn run_hook_in(
+    dir: &Path,
+    hook: Hook,
+    payload: &Value,
+) -> Vec<(PathBuf, Result<ExitStatus>)> {
+    let mut input = serde_json::to_vec(payload).unwrap_or_default();
+    input.push(b'\n');
+    list_plugins_in(dir)
+        .into_iter()
+        .map(|plugin| {
+            let status = run_plugin(&plugin, hook, &input);
+            (plugin, status)
+        })
+        .collect()
+}
+
+fn run_plugin(plugin: &Path, hook: Hook, input: &[u8]) -> Result<ExitStatus> {
+    let mut child = Command::new(plugin)
+        .arg(hook.as_str())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A plugin that ignores stdin may exit before reading it.
+        let _ = stdin.write_all(input);
+    }
+    Ok(child.wait()?)
+}
+
+/// Run the hook's plugins on a background thread without waiting for them.
+pub fn fire(dir: Option<&Path>, hook: Hook, payload: Value) {
+    let Some(dir) = dir.map(Path::to_path_buf) else {
+        return;
+    };
+    thread::spawn(move || run_hook_in(&dir, hook, &payload));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[cfg(unix)]
+    fn write_script(dir: &Path, name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn list_plugins_in_missing_dir_is_empty() {
+        let tmp = TempDir::new().unwrap();
+        assert!(list_plugins_in(&tmp.path().join("plugins")).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_plugins_in_skips_non_executables() {
+        let tmp = TempDir::new().unwrap();
+        write_script(tmp.path(), "b-hook", "true");
+        write_script(tmp.path(), "a-hook", "true");
+        fs::write(tmp.path().join("README"), "not a plugin").unwrap();
+        fs::create_dir(tmp.path().join("subdir")).unwrap();
+        let names: Vec<String> = list_plugins_in(tmp.path())
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["a-hook", "b-hook"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_hook_in_passes_hook_name_and_json_stdin() {
+        let tmp = TempDir::new().unwrap();
+        let plugins = tmp.path().join("plugins");
+        fs::create_dir(&plugins).unwrap();
+        let out = tmp.path().join("out");
+        write_script(
+            &plugins,
+            "record",
+            &format!("echo \"$1\" > '{0}'; cat >> '{0}'", out.display()),
+        );
+        write_script(&plugins, "fail", "exit 4");
+
+        let results = run_hook_in(&plugins, Hook::OnOpenSession, &json!({"session_id": "s1"}));
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].1.as_ref().unwrap().code(), Some(4));
+        assert!(results[1].1.as_ref().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "on-open-session\n{\"session_id\":\"s1\"}\n"
+        );
+    }
+}
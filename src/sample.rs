@@ -0,0 +1,416 @@
+use crate::anonymize::Anonymizer;
+use crate::index::{SearchableSession, SessionIndex};
+use crate::models::{Message, MessageRole};
+use crate::parser::{self, ClaudeRoot};
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// One `--filter key=value` criterion of the `sample` command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Criterion {
+    /// Project path contains the text.
+    Project(String),
+    /// Git branch contains the text.
+    Branch(String),
+    /// A model whose name contains the text answered (e.g. `opus`).
+    Model(String),
+    /// The tool was called at least once (exact name, e.g. `WebFetch`).
+    Tool(String),
+    /// Written by this Claude Code version.
+    Version(String),
+    /// Last active within this many days.
+    SinceDays(i64),
+    /// At least this many user prompts.
+    MinPrompts(usize),
+}
+
+impl Criterion {
+    pub fn parse(spec: &str) -> Result<Criterion> {
+        let (key, value) = spec
+            .split_once('=')
+            .with_context(|| format!("filter \"{}\" is not key=value", spec))?;
+        let value = value.trim().to_string();
+        Ok(match key.trim() {
+            "project" => Criterion::Project(value),
+            "branch" => Criterion::Branch(value),
+            "model" => Criterion::Model(value),
+            "tool" => Criterion::Tool(value),
+            "version" => Criterion::Version(value),
+            "since" => Criterion::SinceDays(
+                value
+                    .trim_end_matches('d')
+                    .parse()
+                    .with_context(|| format!("since needs a number of days, e.g. 30d, not \"{}\"", value))?,
+            ),
+            "min-prompts" => Criterion::MinPrompts(
+                value
+                    .parse()
+                    .with_context(|| format!("min-prompts needs a number, not \"{}\"", value))?,
+            ),
+            other => bail!(
+                "unknown filter \"{}\" (use project, branch, model, tool, version, since or min-prompts)",
+                other
+            ),
+        })
+    }
+}
+
+/// What the sample is spread evenly across with `--stratify`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stratum {
+    Project,
+    Version,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleOptions {
+    pub n: usize,
+    pub criteria: Vec<Criterion>,
+    pub stratify: Option<Stratum>,
+    /// Same seed and index give the same sample.
+    pub seed: u64,
+    /// Replace project paths and redact secrets in the output.
+    pub anonymize: bool,
+}
+
+impl SampleOptions {
+    /// Parse the arguments after `sample`:
+    /// `[--n N] [--filter key=value]... [--stratify project|version] [--seed N] [--anonymize]`.
+    pub fn parse(args: &[String], default_seed: u64) -> Result<SampleOptions> {
+        let mut options = SampleOptions { n: 50, criteria: Vec::new(), stratify: None, seed: default_seed, anonymize: false };
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            if flag == "--anonymize" {
+                options.anonymize = true;
+                continue;
+            }
+            let mut value = || args.next().with_context(|| format!("{} needs a value", flag));
+            match flag.as_str() {
+                "--n" => {
+                    let n = value()?;
+                    options.n = n
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .with_context(|| format!("--n needs a positive number, not \"{}\"", n))?;
+                }
+                "--filter" => options.criteria.push(Criterion::parse(value()?)?),
+                "--stratify" => {
+                    options.stratify = Some(match value()?.as_str() {
+                        "project" => Stratum::Project,
+                        "version" => Stratum::Version,
+                        other => bail!("cannot stratify by \"{}\" (use project or version)", other),
+                    })
+                }
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = seed
+                        .parse()
+                        .with_context(|| format!("--seed needs a number, not \"{}\"", seed))?;
+                }
+                other => bail!("unknown option \"{}\"", other),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// A sampled session with its conversation, as written by `sample`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SampledSession {
+    pub session_id: String,
+    pub project_path: String,
+    pub git_branch: String,
+    pub created_at: String,
+    pub version: String,
+    /// Label of the Claude root the transcript is under.
+    pub source_root: String,
+    pub models: Vec<String>,
+    pub messages: Vec<SampledMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SampledMessage {
+    pub role: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    pub text: String,
+}
+
+impl SampledSession {
+    fn anonymize(&mut self, anon: &Anonymizer) {
+        self.project_path = anon.project(&self.project_path);
+        self.git_branch = anon.text(&self.git_branch);
+        self.source_root = anon.text(&self.source_root);
+        for message in &mut self.messages {
+            message.text = anon.text(&message.text);
+        }
+    }
+}
+
+impl SampledMessage {
+    fn from_message(msg: &Message) -> SampledMessage {
+        let role = match msg.role {
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+            MessageRole::System => "system",
+            MessageRole::ToolUse => "tool_use",
+            MessageRole::ToolResult => "tool_result",
+            MessageRole::Progress => "progress",
+        };
+        SampledMessage {
+            role,
+            timestamp: msg.timestamp.map(|t| t.to_rfc3339()),
+            tool: msg.tool_name.clone(),
+            text: msg.text.clone(),
+        }
+    }
+}
+
+/// The JSON document written by `sample`.
+#[derive(Debug, Serialize)]
+pub struct Sample {
+    /// Sessions that matched the filters, before sampling.
+    pub matched: usize,
+    pub seed: u64,
+    pub sessions: Vec<SampledSession>,
+}
+
+/// Indexed sessions that match every criterion. Sessions whose transcript
+/// is gone are left out, and so are parts that were resumed into a later
+/// session, since the later file repeats their conversation.
+pub fn matching_sessions(index: &SessionIndex, criteria: &[Criterion], now: i64) -> Result<Vec<SearchableSession>> {
+    let sessions = index.search_all()?;
+    let resumed: HashSet<String> = sessions.iter().map(|s| s.parent_session_id.clone()).collect();
+    // モデルとツールは一度だけ引いておく
+    let mut ids_by_criterion: Vec<Option<HashSet<String>>> = Vec::new();
+    for criterion in criteria {
+        ids_by_criterion.push(match criterion {
+            Criterion::Model(pattern) => Some(index.sessions_using_model(pattern)?.into_iter().collect()),
+            Criterion::Tool(tool) => Some(index.sessions_using_tool(tool)?.into_iter().collect()),
+            _ => None,
+        });
+    }
+    let mut matching = Vec::new();
+    'sessions: for session in sessions {
+        if session.expired || resumed.contains(&session.session_id) {
+            continue;
+        }
+        for (criterion, ids) in criteria.iter().zip(&ids_by_criterion) {
+            let keep = match criterion {
+                Criterion::Project(text) => session.project_path.contains(text.as_str()),
+                Criterion::Branch(text) => session.git_branch.contains(text.as_str()),
+                Criterion::MinPrompts(n) => session.prompts.len() >= *n,
+                Criterion::Version(version) => {
+                    index.get_client_info(&session.session_id)?.is_some_and(|(v, _)| v == *version)
+                }
+                Criterion::SinceDays(days) => index
+                    .get_stats(&session.session_id)?
+                    .is_some_and(|s| s.last_timestamp > now - days * 86_400),
+                Criterion::Model(_) | Criterion::Tool(_) => {
+                    ids.as_ref().is_some_and(|ids| ids.contains(&session.session_id))
+                }
+            };
+            if !keep {
+                continue 'sessions;
+            }
+        }
+        matching.push(session);
+    }
+    Ok(matching)
+}
+
+/// Pick up to `n` of `sessions` at random. With `strata`, the pick is spread
+/// evenly: one session from each stratum in turn until `n` are chosen.
+pub fn pick<T>(mut sessions: Vec<T>, n: usize, strata: Option<&dyn Fn(&T) -> String>, rng: &mut fastrand::Rng) -> Vec<T> {
+    rng.shuffle(&mut sessions);
+    let Some(key) = strata else {
+        sessions.truncate(n);
+        return sessions;
+    };
+    let mut groups: BTreeMap<String, Vec<T>> = BTreeMap::new();
+    for session in sessions {
+        groups.entry(key(&session)).or_default().push(session);
+    }
+    let mut groups: Vec<std::vec::IntoIter<T>> = groups.into_values().map(Vec::into_iter).collect();
+    let mut picked = Vec::new();
+    while picked.len() < n {
+        let before = picked.len();
+        for group in &mut groups {
+            if picked.len() == n {
+                break;
+            }
+            picked.extend(group.next());
+        }
+        if picked.len() == before {
+            break;
+        }
+    }
+    picked
+}
+
+/// `anonymizer` is applied to every sampled session; `sample` builds one
+/// when `options.anonymize` is set.
+pub fn sample_in(
+    index: &SessionIndex,
+    roots: &[ClaudeRoot],
+    options: &SampleOptions,
+    anonymizer: Option<&Anonymizer>,
+    now: i64,
+) -> Result<Sample> {
+    let candidates = matching_sessions(index, &options.criteria, now)?;
+    let matched = candidates.len();
+    let mut rng = fastrand::Rng::with_seed(options.seed);
+    let picked = match options.stratify {
+        None => pick(candidates, options.n, None, &mut rng),
+        Some(Stratum::Project) => {
+            pick(candidates, options.n, Some(&|s: &SearchableSession| s.dir_name.clone()), &mut rng)
+        }
+        Some(Stratum::Version) => {
+            let version = |s: &SearchableSession| {
+                index.get_client_info(&s.session_id).ok().flatten().map(|(v, _)| v).unwrap_or_default()
+            };
+            pick(candidates, options.n, Some(&version), &mut rng)
+        }
+    };
+
+    let mut sessions = Vec::new();
+    for session in picked {
+        let messages = match parser::session_root_in(roots, &session.dir_name, &session.session_id) {
+            Some(root) => parser::load_session_in(&session.dir_name, &session.session_id, &root.projects_dir)?,
+            None => Vec::new(),
+        };
+        let mut sampled = SampledSession {
+            version: index.get_client_info(&session.session_id)?.map(|(v, _)| v).unwrap_or_default(),
+            models: index.get_models(&session.session_id)?,
+            messages: messages.iter().map(SampledMessage::from_message).collect(),
+            session_id: session.session_id,
+            project_path: session.project_path,
+            git_branch: session.git_branch,
+            created_at: session.created_at,
+            source_root: session.source_root,
+        };
+        if let Some(anon) = anonymizer {
+            sampled.anonymize(anon);
+        }
+        sessions.push(sampled);
+    }
+    Ok(Sample { matched, seed: options.seed, sessions })
+}
+
+pub fn sample(index: &SessionIndex, options: &SampleOptions) -> Result<Sample> {
+    let anonymizer = if options.anonymize { Some(Anonymizer::for_index(index)?) } else { None };
+    sample_in(index, parser::claude_roots(), options, anonymizer.as_ref(), chrono::Utc::now().timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parse_options_and_filters() {
+        let options = SampleOptions::parse(
+            &args("--n 10 --filter project=api --filter since=30d --filter min-prompts=2 --stratify project --seed 7 --anonymize"),
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            options,
+            SampleOptions {
+                n: 10,
+                criteria: vec![
+                    Criterion::Project("api".to_string()),
+                    Criterion::SinceDays(30),
+                    Criterion::MinPrompts(2),
+                ],
+                stratify: Some(Stratum::Project),
+                seed: 7,
+                anonymize: true,
+            }
+        );
+        assert_eq!(SampleOptions::parse(&[], 3).unwrap().n, 50);
+        assert!(SampleOptions::parse(&args("--n 0"), 1).is_err());
+        assert!(SampleOptions::parse(&args("--n"), 1).is_err());
+        assert!(SampleOptions::parse(&args("--filter color=red"), 1).is_err());
+        assert!(SampleOptions::parse(&args("--stratify month"), 1).is_err());
+        assert!(Criterion::parse("since=soon").is_err());
+    }
+
+    #[test]
+    fn pick_spreads_strata_evenly() {
+        let items: Vec<(char, u32)> = (0..10).map(|i| ('a', i)).chain((0..2).map(|i| ('b', i))).collect();
+        let key = |item: &(char, u32)| item.0.to_string();
+
+        let picked = pick(items.clone(), 4, Some(&key), &mut fastrand::Rng::with_seed(1));
+        assert_eq!(picked.iter().filter(|i| i.0 == 'b').count(), 2);
+        assert_eq!(picked.len(), 4);
+
+        // 足りなければあるだけ返す
+        assert_eq!(pick(items.clone(), 100, Some(&key), &mut fastrand::Rng::with_seed(1)).len(), 12);
+        let random = pick(items.clone(), 5, None, &mut fastrand::Rng::with_seed(9));
+        assert_eq!(random.len(), 5);
+        assert_eq!(random, pick(items, 5, None, &mut fastrand::Rng::with_seed(9)));
+    }
+
+    #[test]
+    fn sample_in_exports_matching_conversations() {
+        let tmp = TempDir::new().unwrap();
+        let projects_dir = tmp.path().join("projects");
+        let session = |prompt: &str, extra: &str| {
+            format!(
+                "{{\"type\":\"user\",\"timestamp\":\"2026-01-15T10:00:00Z\",\"message\":{{\"content\":\"{}\"}}}}\n\
+                 {{\"type\":\"assistant\",\"timestamp\":\"2026-01-15T10:00:05Z\",\"message\":{{\"model\":\"claude-opus-4\",\"content\":[{{\"type\":\"text\",\"text\":\"done\"}}]}}}}\n{}",
+                prompt, extra
+            )
+        };
+        for (project, id, content) in [
+            ("-work-api", "s1", session("fix api", "")),
+            ("-work-api", "s2", session("add tests", "")),
+            ("-work-web", "s3", session("style page", "")),
+        ] {
+            fs::create_dir_all(projects_dir.join(project)).unwrap();
+            fs::write(projects_dir.join(project).join(format!("{}.jsonl", id)), content).unwrap();
+        }
+        let db_path = tmp.path().join("index.db");
+        indexer::build_index(&db_path, &projects_dir).unwrap();
+        let index = SessionIndex::open(&db_path).unwrap();
+        let roots = [ClaudeRoot::new(&projects_dir)];
+
+        let options = SampleOptions::parse(&args("--n 1 --filter project=api --seed 5"), 0).unwrap();
+        let sample = sample_in(&index, &roots, &options, None, 0).unwrap();
+        assert_eq!(sample.matched, 2);
+        assert_eq!(sample.sessions.len(), 1);
+        let session = &sample.sessions[0];
+        assert!(session.project_path.ends_with("api"));
+        assert_eq!(session.models, vec!["claude-opus-4"]);
+        let roles: Vec<&str> = session.messages.iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec!["user", "assistant"]);
+
+        let json = serde_json::to_value(&sample).unwrap();
+        assert_eq!(json["seed"], 5);
+        assert_eq!(json["sessions"][0]["messages"][1]["text"], "done");
+
+        let none = SampleOptions::parse(&args("--filter model=sonnet"), 0).unwrap();
+        assert_eq!(sample_in(&index, &roots, &none, None, 0).unwrap().matched, 0);
+        let recent = SampleOptions::parse(&args("--filter since=1d"), 0).unwrap();
+        let now = parser::parse_timestamp(Some("2026-01-15T12:00:00Z")).unwrap().timestamp();
+        assert_eq!(sample_in(&index, &roots, &recent, None, now).unwrap().matched, 3);
+
+        let anon = Anonymizer::new(index.project_paths().unwrap(), &["fix".to_string()]).unwrap();
+        let shared = sample_in(&index, &roots, &options, Some(&anon), 0).unwrap();
+        let session = &shared.sessions[0];
+        assert!(session.project_path.starts_with("project-"));
+        assert_eq!(session.session_id, sample.sessions[0].session_id);
+        assert!(session.messages.iter().all(|m| !m.text.contains("fix")));
+    }
+}
@@ -0,0 +1,303 @@
+//! Custom session-list columns and filters defined in Rhai scripts.
+//!
+//! Every `*.rhai` file in `~/.config/cc-sessions-viewer/scripts/` is run once
+//! at startup and registers its columns and filters:
+//!
+//! ```rhai
+//! column("Cache %", |s| if s.input_tokens == 0 { "" } else { `${s.cache_read_tokens * 100 / s.input_tokens}` });
+//! filter("Opus over $1", |s| s.cost > 1.0 && s.preview.contains("opus"));
+//! ```
+//!
+//! The engine is only compiled in with the `scripting` feature; without it
+//! scripts are reported as unsupported instead of silently ignored.
+
+use crate::models::SessionInfo;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn scripts_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("cc-sessions-viewer").join("scripts"))
+}
+
+/// `*.rhai` files directly in `dir`, sorted by name.
+fn script_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "rhai"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(feature = "scripting")]
+pub use engine::ScriptSet;
+
+#[cfg(feature = "scripting")]
+mod engine {
+    use super::*;
+    use rhai::{Dynamic, Engine, FnPtr, Map, AST};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Upper bound on script steps per call, so a runaway loop cannot hang the UI.
+    const MAX_OPERATIONS: u64 = 100_000;
+
+    struct Entry {
+        name: String,
+        /// Index into `ScriptSet::asts` of the file that defined it.
+        ast: usize,
+        func: FnPtr,
+    }
+
+    /// Columns and filters registered by the user's scripts.
+    pub struct ScriptSet {
+        engine: Engine,
+        asts: Vec<AST>,
+        columns: Vec<Entry>,
+        filters: Vec<Entry>,
+    }
+
+    impl Default for ScriptSet {
+        fn default() -> Self {
+            ScriptSet {
+                engine: Engine::new(),
+                asts: Vec::new(),
+                columns: Vec::new(),
+                filters: Vec::new(),
+            }
+        }
+    }
+
+    impl ScriptSet {
+        /// Run every script in `dir`. A missing directory yields no scripts.
+        pub fn load_from(dir: &Path) -> Result<ScriptSet> {
+            let columns: Rc<RefCell<Vec<Entry>>> = Rc::default();
+            let filters: Rc<RefCell<Vec<Entry>>> = Rc::default();
+            let current = Rc::new(RefCell::new(0usize));
+
+            let mut engine = Engine::new();
+            engine.set_max_operations(MAX_OPERATIONS);
+            let (c, file) = (Rc::clone(&columns), Rc::clone(&current));
+            engine.register_fn("column", move |name: &str, func: FnPtr| {
+                c.borrow_mut().push(Entry { name: name.to_string(), ast: *file.borrow(), func });
+            });
+            let (f, file) = (Rc::clone(&filters), Rc::clone(&current));
+            engine.register_fn("filter", move |name: &str, func: FnPtr| {
+                f.borrow_mut().push(Entry { name: name.to_string(), ast: *file.borrow(), func });
+            });
+
+            let mut asts = Vec::new();
+            for path in script_files(dir) {
+                *current.borrow_mut() = asts.len();
+                let ast = engine
+                    .compile_file(path.clone())
+                    .and_then(|ast| engine.run_ast(&ast).map(|_| ast))
+                    .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+                asts.push(ast);
+            }
+
+            let columns = columns.take();
+            let filters = filters.take();
+            Ok(ScriptSet { engine, asts, columns, filters })
+        }
+
+        pub fn column_names(&self) -> Vec<&str> {
+            self.columns.iter().map(|c| c.name.as_str()).collect()
+        }
+
+        pub fn filter_names(&self) -> Vec<&str> {
+            self.filters.iter().map(|f| f.name.as_str()).collect()
+        }
+
+        /// Cell text of a custom column; script errors show as `!`.
+        pub fn column_value(&self, column: usize, session: &SessionInfo) -> String {
+            self.columns
+                .get(column)
+                .and_then(|c| self.call(c, session).ok())
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "!".to_string())
+        }
+
+        /// Whether `session` passes a custom filter; script errors count as no.
+        pub fn filter_matches(&self, filter: usize, session: &SessionInfo) -> bool {
+            self.filters
+                .get(filter)
+                .and_then(|f| self.call(f, session).ok())
+                .and_then(|v| v.as_bool().ok())
+                .unwrap_or(false)
+        }
+
+        fn call(&self, entry: &Entry, session: &SessionInfo) -> Result<Dynamic> {
+            let ast = &self.asts[entry.ast];
+            entry
+                .func
+                .call::<Dynamic>(&self.engine, ast, (session_map(session),))
+                .map_err(|e| anyhow::anyhow!("{}", e))
+        }
+    }
+
+    /// The fields of a session visible to scripts.
+    fn session_map(s: &SessionInfo) -> Map {
+        let mut map = Map::new();
+        let mut put = |key: &str, value: Dynamic| {
+            map.insert(key.into(), value);
+        };
+        put("session_id", s.session_id.clone().into());
+        put("project", s.project_name.clone().into());
+        put("preview", s.preview.clone().into());
+        put("summary", s.summary.clone().into());
+        put("branch", s.git_branch.clone().into());
+        put("timestamp", s.timestamp.map(|t| t.to_rfc3339()).unwrap_or_default().into());
+        put("messages", (s.message_count as i64).into());
+        put("needs_input", s.needs_input.into());
+        put("has_loops", s.has_loops.into());
+        put("parent_session_id", s.parent_session_id.clone().into());
+        put("version", s.version.clone().into());
+        put("user_type", s.user_type.clone().into());
+        put("source_root", s.source_root.clone().into());
+        put("cost", s.cost_usd.into());
+        put("input_tokens", (s.usage.input_tokens as i64).into());
+        put("output_tokens", (s.usage.output_tokens as i64).into());
+        put("cache_write_tokens", (s.usage.cache_creation_tokens as i64).into());
+        put("cache_read_tokens", (s.usage.cache_read_tokens as i64).into());
+        map
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+/// Stand-in used when the crate is built without the `scripting` feature.
+#[derive(Default)]
+pub struct ScriptSet {}
+
+#[cfg(not(feature = "scripting"))]
+impl ScriptSet {
+    /// Fails if `dir` holds scripts this build cannot run.
+    pub fn load_from(dir: &Path) -> Result<ScriptSet> {
+        if !script_files(dir).is_empty() {
+            anyhow::bail!("scripts in {} need a build with the `scripting` feature", dir.display());
+        }
+        Ok(ScriptSet {})
+    }
+
+    pub fn column_names(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    pub fn filter_names(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    pub fn column_value(&self, _column: usize, _session: &SessionInfo) -> String {
+        String::new()
+    }
+
+    pub fn filter_matches(&self, _filter: usize, _session: &SessionInfo) -> bool {
+        true
+    }
+}
+
+impl ScriptSet {
+    /// Load the user's scripts; a missing scripts directory yields none.
+    pub fn load() -> Result<ScriptSet> {
+        match scripts_dir() {
+            Some(dir) => ScriptSet::load_from(&dir),
+            None => Ok(ScriptSet::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "scripting")]
+    use crate::models::TokenUsage;
+    use tempfile::TempDir;
+
+    #[cfg(feature = "scripting")]
+    fn session(id: &str, cost: f64) -> SessionInfo {
+        SessionInfo {
+            session_id: id.to_string(),
+            project_name: "p".to_string(),
+            preview: "fix the build".to_string(),
+            timestamp: None,
+            modified: None,
+            message_count: 3,
+            git_branch: "main".to_string(),
+            summary: String::new(),
+            needs_input: false,
+            usage: TokenUsage {
+                input_tokens: 100,
+                output_tokens: 50,
+                cache_creation_tokens: 0,
+                cache_read_tokens: 25,
+            },
+            cost_usd: cost,
+            has_loops: false,
+            parent_session_id: String::new(),
+            version: String::new(),
+            user_type: String::new(),
+            source_root: String::new(),
+            warning: String::new(),
+        }
+    }
+
+    #[test]
+    fn load_from_missing_dir_has_no_scripts() {
+        let tmp = TempDir::new().unwrap();
+        let scripts = ScriptSet::load_from(&tmp.path().join("scripts")).unwrap();
+        assert!(scripts.column_names().is_empty());
+        assert!(scripts.filter_names().is_empty());
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    #[test]
+    fn load_from_reports_scripts_without_the_feature() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("cols.rhai"), "column(\"x\", |s| 1);").unwrap();
+        assert!(ScriptSet::load_from(tmp.path()).is_err());
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn scripts_define_columns_and_filters() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(
+            tmp.path().join("a.rhai"),
+            "column(\"Tokens\", |s| s.input_tokens + s.output_tokens);\nfilter(\"Pricey\", |s| s.cost > 1.0);\n",
+        )
+        .unwrap();
+        fs::write(
+            tmp.path().join("b.rhai"),
+            "fn label(s) { s.branch + \":\" + s.messages }\ncolumn(\"Label\", |s| label(s));\ncolumn(\"Broken\", |s| s.missing.len());\n",
+        )
+        .unwrap();
+        let scripts = ScriptSet::load_from(tmp.path()).unwrap();
+        assert_eq!(scripts.column_names(), vec!["Tokens", "Label", "Broken"]);
+        assert_eq!(scripts.filter_names(), vec!["Pricey"]);
+
+        let cheap = session("s1", 0.5);
+        assert_eq!(scripts.column_value(0, &cheap), "150");
+        assert_eq!(scripts.column_value(1, &cheap), "main:3");
+        assert_eq!(scripts.column_value(2, &cheap), "!");
+        assert!(!scripts.filter_matches(0, &cheap));
+        assert!(scripts.filter_matches(0, &session("s2", 2.0)));
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn load_from_reports_script_errors_and_runaway_loops() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("bad.rhai"), "column(\"x\", ").unwrap();
+        let err = ScriptSet::load_from(tmp.path()).err().unwrap().to_string();
+        assert!(err.contains("bad.rhai"));
+
+        fs::write(tmp.path().join("bad.rhai"), "filter(\"spin\", |s| { loop {} });").unwrap();
+        let scripts = ScriptSet::load_from(tmp.path()).unwrap();
+        assert!(!scripts.filter_matches(0, &session("s1", 0.0)));
+    }
+}
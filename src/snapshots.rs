@@ -0,0 +1,255 @@
+use crate::models::FileSnapshot;
+use crate::parser;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where Claude Code keeps a session's file backups:
+/// `<root>/file-history/<session>/`, under the root that has them.
+pub fn file_history_dir(session_id: &str) -> Option<PathBuf> {
+    let roots = parser::claude_roots();
+    roots
+        .iter()
+        .find(|r| r.history_dir().join(session_id).exists())
+        .or(roots.first())
+        .map(|r| r.history_dir())
+}
+
+/// Parse `file-history-snapshot` entries into one record per tracked backup.
+///
+/// Snapshot updates repeat files that did not change, so each
+/// (path, backup file) pair is reported once, at its first appearance.
+pub fn parse_file_snapshots(content: &str) -> Vec<FileSnapshot> {
+    let mut seen = HashSet::new();
+    let mut snapshots = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let obj: Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if obj.get("type").and_then(Value::as_str) != Some("file-history-snapshot") {
+            continue;
+        }
+        let Some(snapshot) = obj.get("snapshot") else {
+            continue;
+        };
+        let timestamp = parser::parse_timestamp(snapshot.get("timestamp").and_then(Value::as_str));
+        let message_id = snapshot
+            .get("messageId")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string();
+        let Some(Value::Object(backups)) = snapshot.get("trackedFileBackups") else {
+            continue;
+        };
+        let mut paths: Vec<&String> = backups.keys().collect();
+        paths.sort();
+        for path in paths {
+            let backup = &backups[path];
+            let backup_file = backup
+                .get("backupFileName")
+                .and_then(Value::as_str)
+                .map(String::from);
+            if !seen.insert((path.clone(), backup_file.clone())) {
+                continue;
+            }
+            snapshots.push(FileSnapshot {
+                message_id: message_id.clone(),
+                timestamp,
+                path: path.clone(),
+                backup_file,
+                version: backup.get("version").and_then(Value::as_u64).unwrap_or(0),
+                backup_time: parser::parse_timestamp(
+                    backup.get("backupTime").and_then(Value::as_str),
+                ),
+            });
+        }
+    }
+    snapshots
+}
+
+pub fn session_snapshots_in(
+    project_name: &str,
+    session_id: &str,
+    projects_dir: &Path,
+) -> Result<Vec<FileSnapshot>> {
+    let jsonl_path = projects_dir
+        .join(project_name)
+        .join(format!("{}.jsonl", session_id));
+    if !jsonl_path.exists() {
+        return Ok(Vec::new());
+    }
+    let (content, _) = parser::read_transcript(&jsonl_path)?;
+    Ok(parse_file_snapshots(&content))
+}
+
+pub fn session_snapshots(project_name: &str, session_id: &str) -> Result<Vec<FileSnapshot>> {
+    match parser::session_root(project_name, session_id) {
+        Some(root) => session_snapshots_in(project_name, session_id, &root.projects_dir),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Read the stored content of a snapshot for display. A snapshot without a
+/// backup file means the file did not exist yet at that point. Backups that
+/// are not UTF-8 (binary files, other encodings) are shown with the invalid
+/// bytes replaced; `export_snapshot_in` still writes them back unchanged.
+pub fn read_snapshot_in(history_dir: &Path, session_id: &str, snapshot: &FileSnapshot) -> Result<String> {
+    match &snapshot.backup_file {
+        Some(name) => {
+            let bytes = fs::read(history_dir.join(session_id).join(name))?;
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        None => Ok(String::new()),
+    }
+}
+
+pub fn read_snapshot(session_id: &str, snapshot: &FileSnapshot) -> Result<String> {
+    let dir = file_history_dir(session_id)
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    read_snapshot_in(&dir, session_id, snapshot)
+}
+
+/// Copy a snapshot's stored content byte for byte to `dest`, creating parent
+/// directories. Refuses to replace an existing file unless `overwrite` is set.
+pub fn export_snapshot_in(
+    history_dir: &Path,
+    session_id: &str,
+    snapshot: &FileSnapshot,
+    dest: &Path,
+    overwrite: bool,
+) -> Result<usize> {
+    let Some(name) = &snapshot.backup_file else {
+        anyhow::bail!("{} did not exist at this point", snapshot.path);
+    };
+    if dest.exists() && !overwrite {
+        anyhow::bail!("{} already exists", dest.display());
+    }
+    let content = fs::read(history_dir.join(session_id).join(name))?;
+    if let Some(parent) = dest.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(dest, &content)?;
+    Ok(content.len())
+}
+
+pub fn export_snapshot(
+    session_id: &str,
+    snapshot: &FileSnapshot,
+    dest: &Path,
+    overwrite: bool,
+) -> Result<usize> {
+    let dir = file_history_dir(session_id)
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    export_snapshot_in(&dir, session_id, snapshot, dest, overwrite)
+}
+
+/// Expand a leading `~/` in a user-entered destination path.
+pub fn expand_destination(input: &str) -> PathBuf {
+    if let Some(rest) = input.strip_prefix("~/")
+        && let Some(home) = dirs::home_dir()
+    {
+        return home.join(rest);
+    }
+    PathBuf::from(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const SESSION: &str = r#"{"type":"user","message":{"content":"edit main"}}
+{"type":"file-history-snapshot","messageId":"m1","snapshot":{"messageId":"m1","timestamp":"2026-01-15T10:00:00Z","trackedFileBackups":{}},"isSnapshotUpdate":false}
+{"type":"file-history-snapshot","messageId":"m1","snapshot":{"messageId":"m1","timestamp":"2026-01-15T10:00:00Z","trackedFileBackups":{"src/main.rs":{"backupFileName":"abc@v1","version":1,"backupTime":"2026-01-15T10:00:05Z"},"src/new.rs":{"backupFileName":null,"version":1,"backupTime":"2026-01-15T10:00:06Z"}}},"isSnapshotUpdate":true}
+{"type":"file-history-snapshot","messageId":"m2","snapshot":{"messageId":"m2","timestamp":"2026-01-15T10:05:00Z","trackedFileBackups":{"src/main.rs":{"backupFileName":"abc@v2","version":2,"backupTime":"2026-01-15T10:05:01Z"},"src/new.rs":{"backupFileName":null,"version":1,"backupTime":"2026-01-15T10:00:06Z"}}},"isSnapshotUpdate":false}"#;
+
+    #[test]
+    fn parse_file_snapshots_dedupes_repeated_backups() {
+        let snapshots = parse_file_snapshots(SESSION);
+        let files: Vec<(&str, Option<&str>, u64)> = snapshots
+            .iter()
+            .map(|s| (s.path.as_str(), s.backup_file.as_deref(), s.version))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("src/main.rs", Some("abc@v1"), 1),
+                ("src/new.rs", None, 1),
+                ("src/main.rs", Some("abc@v2"), 2),
+            ]
+        );
+        assert_eq!(snapshots[2].message_id, "m2");
+        assert!(snapshots[0].timestamp.is_some());
+    }
+
+    #[test]
+    fn read_snapshot_in_reads_backup_file() {
+        let tmp = TempDir::new().unwrap();
+        let session_dir = tmp.path().join("sess-1");
+        fs::create_dir(&session_dir).unwrap();
+        fs::write(session_dir.join("abc@v1"), "fn main() {}\n").unwrap();
+
+        let snapshots = parse_file_snapshots(SESSION);
+        let content = read_snapshot_in(tmp.path(), "sess-1", &snapshots[0]).unwrap();
+        assert_eq!(content, "fn main() {}\n");
+        // 新規ファイル（バックアップなし）は空
+        assert_eq!(read_snapshot_in(tmp.path(), "sess-1", &snapshots[1]).unwrap(), "");
+        assert!(read_snapshot_in(tmp.path(), "sess-1", &snapshots[2]).is_err());
+    }
+
+    #[test]
+    fn export_snapshot_in_refuses_overwrite_without_flag() {
+        let tmp = TempDir::new().unwrap();
+        let session_dir = tmp.path().join("sess-1");
+        fs::create_dir(&session_dir).unwrap();
+        fs::write(session_dir.join("abc@v1"), "old\n").unwrap();
+        let snapshots = parse_file_snapshots(SESSION);
+
+        let dest = tmp.path().join("out").join("main.rs");
+        assert_eq!(export_snapshot_in(tmp.path(), "sess-1", &snapshots[0], &dest, false).unwrap(), 4);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "old\n");
+
+        fs::write(&dest, "clobbered\n").unwrap();
+        assert!(export_snapshot_in(tmp.path(), "sess-1", &snapshots[0], &dest, false).is_err());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "clobbered\n");
+        export_snapshot_in(tmp.path(), "sess-1", &snapshots[0], &dest, true).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "old\n");
+
+        // バックアップのないスナップショットは書き出せない
+        assert!(export_snapshot_in(tmp.path(), "sess-1", &snapshots[1], &dest, true).is_err());
+    }
+
+    #[test]
+    fn non_utf8_backup_is_shown_lossily_and_restored_exactly() {
+        let tmp = TempDir::new().unwrap();
+        let session_dir = tmp.path().join("sess-1");
+        fs::create_dir(&session_dir).unwrap();
+        // Latin-1 の「café」と NUL を含むバックアップ
+        let bytes = b"caf\xe9\x00\n".to_vec();
+        fs::write(session_dir.join("abc@v1"), &bytes).unwrap();
+        let snapshots = parse_file_snapshots(SESSION);
+
+        let content = read_snapshot_in(tmp.path(), "sess-1", &snapshots[0]).unwrap();
+        assert_eq!(content, "caf\u{FFFD}\0\n");
+
+        let dest = tmp.path().join("restored.txt");
+        assert_eq!(export_snapshot_in(tmp.path(), "sess-1", &snapshots[0], &dest, false).unwrap(), bytes.len());
+        assert_eq!(fs::read(&dest).unwrap(), bytes);
+    }
+
+    #[test]
+    fn session_snapshots_in_missing_file() {
+        let tmp = TempDir::new().unwrap();
+        assert!(session_snapshots_in("proj", "nope", tmp.path()).unwrap().is_empty());
+    }
+}
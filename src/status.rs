@@ -0,0 +1,193 @@
+//! One-line activity summaries for a shell prompt (`cc-sessions-viewer status`)
+//! and a tmux status bar (`status --tmux`).
+
+use crate::index::SessionIndex;
+use crate::parser;
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeZone};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// A transcript written to within this long counts as a live session.
+pub const LIVE_WINDOW: Duration = Duration::from_secs(30 * 60);
+
+/// Bytes read from the end of a transcript to find who spoke last.
+const TAIL_BYTES: u64 = 256 * 1024;
+
+/// The indexed project that `dir` belongs to: the project path equal to it
+/// or, failing that, its deepest ancestor.
+pub fn project_for_dir<'a>(dir: &Path, project_paths: &'a [String]) -> Option<&'a str> {
+    project_paths
+        .iter()
+        .filter(|p| !p.is_empty() && dir.starts_with(p.as_str()))
+        .max_by_key(|p| Path::new(p.as_str()).components().count())
+        .map(|p| p.as_str())
+}
+
+/// Summarize session activity given the last-activity time (unix seconds)
+/// of each session, e.g. "3 sessions today, last 14:20".
+pub fn format_status(last_activity: &[i64], now: DateTime<Local>) -> String {
+    let Some(last) = last_activity.iter().copied().max().and_then(|t| Local.timestamp_opt(t, 0).single()) else {
+        return "no sessions".to_string();
+    };
+    let midnight = now
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| t.and_local_timezone(Local).earliest())
+        .map(|t| t.timestamp())
+        .unwrap_or(i64::MIN);
+    let today = last_activity.iter().filter(|&&t| t >= midnight).count();
+    match today {
+        0 => format!("no sessions today, last {}", last.format("%b %d")),
+        1 => format!("1 session today, last {}", last.format("%H:%M")),
+        n => format!("{} sessions today, last {}", n, last.format("%H:%M")),
+    }
+}
+
+/// The status line for the project containing `dir`, or `None` when no
+/// indexed project matches.
+pub fn status_for_dir(index: &SessionIndex, dir: &Path) -> Result<Option<String>> {
+    let paths = index.project_paths()?;
+    let Some(project) = project_for_dir(dir, &paths) else {
+        return Ok(None);
+    };
+    Ok(Some(format_status(&index.last_activity(project)?, Local::now())))
+}
+
+/// Live sessions across all projects, split by whose turn it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LiveCounts {
+    /// Claude is still working (the last entry is a prompt or tool result).
+    pub active: usize,
+    /// Claude finished its turn or waits for a permission answer.
+    pub waiting: usize,
+}
+
+/// Count the sessions under `projects_dir` written to within `LIVE_WINDOW`
+/// of `now`. Reads the transcripts directly, so it is current even when the
+/// index is not.
+pub fn live_sessions_in(projects_dir: &Path, now: SystemTime) -> LiveCounts {
+    let mut counts = LiveCounts::default();
+    let files = fs::read_dir(projects_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .flat_map(|dir| fs::read_dir(dir.path()).into_iter().flatten().flatten())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"));
+    for path in files {
+        let recent = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .is_ok_and(|t| now.duration_since(t).unwrap_or_default() <= LIVE_WINDOW);
+        if !recent {
+            continue;
+        }
+        let Ok(tail) = read_tail(&path) else {
+            continue;
+        };
+        if parser::is_awaiting_input(&tail) {
+            counts.waiting += 1;
+        } else {
+            counts.active += 1;
+        }
+    }
+    counts
+}
+
+/// The last `TAIL_BYTES` of a file; a line cut at the start is skipped by the
+/// JSON parsing that follows.
+fn read_tail(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// tmux status-line text with `#[...]` styles; empty when nothing is live so
+/// the segment disappears.
+pub fn format_tmux(counts: LiveCounts) -> String {
+    let mut parts = Vec::new();
+    if counts.active > 0 {
+        parts.push(format!("#[fg=green]● {} active#[default]", counts.active));
+    }
+    if counts.waiting > 0 {
+        parts.push(format!("#[fg=yellow,bold]◆ {} waiting#[default]", counts.waiting));
+    }
+    parts.join(" ")
+}
+
+/// `format_tmux` for the sessions under every Claude root.
+pub fn tmux_status() -> String {
+    let now = SystemTime::now();
+    let mut counts = LiveCounts::default();
+    for root in parser::claude_roots() {
+        let root_counts = live_sessions_in(&root.projects_dir, now);
+        counts.active += root_counts.active;
+        counts.waiting += root_counts.waiting;
+    }
+    format_tmux(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn live_sessions_split_by_whose_turn_it_is() {
+        let tmp = TempDir::new().unwrap();
+        let write = |project: &str, id: &str, content: &str, age: Duration| {
+            let dir = tmp.path().join(project);
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(format!("{}.jsonl", id));
+            fs::write(&path, content).unwrap();
+            let file = File::options().write(true).open(&path).unwrap();
+            file.set_modified(SystemTime::now() - age).unwrap();
+        };
+        let user = r#"{"type":"user","message":{"content":"go"}}"#;
+        let assistant = r#"{"type":"assistant","message":{"content":"done"}}"#;
+        let minute = Duration::from_secs(60);
+        write("-a", "working", &format!("{}\n{}\n{}\n", user, assistant, user), minute);
+        write("-a", "finished", &format!("{}\n{}\n", user, assistant), 5 * minute);
+        write("-b", "waiting", &format!("{}\n{}\n", user, assistant), minute);
+        write("-b", "stale", &format!("{}\n{}\n", user, assistant), 120 * minute);
+
+        let counts = live_sessions_in(tmp.path(), SystemTime::now());
+        assert_eq!(counts, LiveCounts { active: 1, waiting: 2 });
+        assert_eq!(live_sessions_in(&tmp.path().join("missing"), SystemTime::now()), LiveCounts::default());
+    }
+
+    #[test]
+    fn format_tmux_hides_empty_counts() {
+        assert_eq!(format_tmux(LiveCounts::default()), "");
+        assert_eq!(format_tmux(LiveCounts { active: 2, waiting: 0 }), "#[fg=green]● 2 active#[default]");
+        assert_eq!(
+            format_tmux(LiveCounts { active: 1, waiting: 3 }),
+            "#[fg=green]● 1 active#[default] #[fg=yellow,bold]◆ 3 waiting#[default]"
+        );
+    }
+
+    #[test]
+    fn project_for_dir_prefers_the_deepest_match() {
+        let paths = vec!["/work/api".to_string(), "/work/api/tools".to_string(), "/work/apiary".to_string()];
+        assert_eq!(project_for_dir(Path::new("/work/api"), &paths), Some("/work/api"));
+        assert_eq!(project_for_dir(Path::new("/work/api/src"), &paths), Some("/work/api"));
+        assert_eq!(project_for_dir(Path::new("/work/api/tools/gen"), &paths), Some("/work/api/tools"));
+        assert_eq!(project_for_dir(Path::new("/work"), &paths), None);
+        assert_eq!(project_for_dir(Path::new("/work/apiary2"), &paths), None);
+    }
+
+    #[test]
+    fn format_status_counts_todays_sessions() {
+        let now = Local.with_ymd_and_hms(2026, 3, 10, 18, 0, 0).unwrap();
+        let at = |d: u32, h: u32, m: u32| Local.with_ymd_and_hms(2026, 3, d, h, m, 0).unwrap().timestamp();
+        assert_eq!(format_status(&[at(10, 9, 5), at(9, 23, 0), at(10, 14, 20), at(10, 11, 0)], now), "3 sessions today, last 14:20");
+        assert_eq!(format_status(&[at(10, 8, 0)], now), "1 session today, last 08:00");
+        assert_eq!(format_status(&[at(7, 8, 0), at(9, 8, 0)], now), "no sessions today, last Mar 09");
+        assert_eq!(format_status(&[], now), "no sessions");
+    }
+}
@@ -1,13 +1,22 @@
 use ratatui::{
     Frame,
-    layout::{Constraint, Layout},
+    layout::{Constraint, Flex, Layout},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table, Tabs, Wrap},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, Tabs, Wrap},
 };
 
+use unicode_width::UnicodeWidthChar;
+
+use crate::analysis;
+use crate::ansi;
+use crate::changes;
+use crate::highlight::Highlighter;
+use crate::index::{ChangeKind, ProjectActivity};
+use crate::markdown;
 use crate::app::{App, Screen};
 use crate::models::*;
+use crate::parser;
 
 pub fn draw(frame: &mut Frame, app: &App) {
     let chunks = Layout::vertical([
@@ -17,14 +26,21 @@ pub fn draw(frame: &mut Frame, app: &App) {
     ])
     .split(frame.area());
 
-    // Title bar
-    let title = Paragraph::new(Line::from(vec![Span::styled(
-        " Claude Session Viewer",
-        Style::default()
-            .fg(Color::Cyan)
-            .add_modifier(Modifier::BOLD),
-    )]));
-    frame.render_widget(title, chunks[0]);
+    draw_title_bar(frame, app, chunks[0]);
+
+    // Screen content（詳細画面の下端の位置表示は描画後の行キャッシュを使う）
+    match app.screen {
+        Screen::ProjectList => draw_project_list(frame, app, chunks[1]),
+        Screen::SessionList => draw_session_list(frame, app, chunks[1]),
+        Screen::SessionDetail => draw_session_detail(frame, app, chunks[1]),
+        Screen::GlobalSearch => draw_global_search(frame, app, chunks[1]),
+        Screen::PermissionAudit => draw_permission_audit(frame, app, chunks[1]),
+        Screen::SessionChanges => draw_session_changes(frame, app, chunks[1]),
+        Screen::FileSnapshots => draw_file_snapshots(frame, app, chunks[1]),
+        Screen::SnapshotView => draw_snapshot_view(frame, app, chunks[1]),
+        Screen::CleanupReview => draw_cleanup_review(frame, app, chunks[1]),
+        Screen::IndexActivity => draw_index_activity(frame, app, chunks[1]),
+    }
 
     // Help bar
     if app.search_active {
@@ -36,33 +52,2151 @@ pub fn draw(frame: &mut Frame, app: &App) {
         ]);
         let search_bar = Paragraph::new(search_line);
         frame.render_widget(search_bar, chunks[2]);
-    } else {
-        let help_text = match app.screen {
-            Screen::ProjectList => "Enter: Open  s: Global Search  q: Quit  j/k: Navigate  /: Filter",
-            Screen::SessionList => "Enter: Open  Esc: Back  j/k: Navigate  d/u: Half Page  Tab: Filter  /: Search",
-            Screen::SessionDetail => "Esc: Back  j/k: Scroll  d/u: Half Page  g/G: Top/Bottom",
-            Screen::GlobalSearch => "Enter: Detail  y: Copy resume cmd  Esc: Back  j/k: Navigate",
+    } else if app.detail_search_active {
+        let k = app.detail_terms.len();
+        let search_line = Line::from(vec![
+            Span::styled(" /", Style::default().fg(highlight_color(k)).add_modifier(Modifier::BOLD)),
+            Span::styled(&app.detail_query, Style::default().fg(Color::White)),
+            Span::styled("█", Style::default().fg(highlight_color(k))),
+            Span::styled(
+                "  +: Add term  Enter: Done  Esc: Cancel",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]);
+        frame.render_widget(Paragraph::new(search_line), chunks[2]);
+    } else if let Some(note) = &app.bookmark_note {
+        let line = Line::from(vec![
+            Span::styled(" Bookmark note: ", Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)),
+            Span::styled(note.as_str(), Style::default().fg(Color::White)),
+            Span::styled("█", Style::default().fg(Color::Magenta)),
+            Span::styled("  Enter: Save  Esc: Cancel", Style::default().fg(Color::DarkGray)),
+        ]);
+        frame.render_widget(Paragraph::new(line), chunks[2]);
+    } else if let Some(input) = &app.tag_input {
+        let marked = app.marked_in_list().len();
+        let label = if app.screen == Screen::SessionList && marked > 0 {
+            format!(" Add tags to {} marked: ", marked)
+        } else {
+            " Tags: ".to_string()
         };
+        let line = Line::from(vec![
+            Span::styled(label, Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+            Span::styled(input.as_str(), Style::default().fg(Color::White)),
+            Span::styled("█", Style::default().fg(Color::Green)),
+            Span::styled(
+                "  #refactor #incident-2026-01, separated by spaces  Enter: Save  Esc: Cancel",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]);
+        frame.render_widget(Paragraph::new(line), chunks[2]);
+    } else if let Some(input) = &app.date_range_input {
+        let line = Line::from(vec![
+            Span::styled(" Date range: ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::styled(input.as_str(), Style::default().fg(Color::White)),
+            Span::styled("█", Style::default().fg(Color::Cyan)),
+            Span::styled(
+                "  2026-01-01..2026-01-15, 2026-01-01.. or one day; empty for all  Enter: Apply  Esc: Cancel",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]);
+        frame.render_widget(Paragraph::new(line), chunks[2]);
+    } else if let Some(share) = &app.share_pending {
+        // 選択行ならもう行数なので、ダイジェストのときだけ Markdown の行数を添える
+        let mut what = share.what.clone();
+        if !what.ends_with("line") && !what.ends_with("lines") {
+            let lines = share.text.lines().count();
+            what.push_str(&format!(" ({} line{})", lines, if lines == 1 { "" } else { "s" }));
+        }
+        let mut spans = vec![
+            Span::styled(" Share ", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled(what, Style::default().fg(Color::White)),
+        ];
+        if share.anonymized {
+            spans.push(Span::styled(" anonymized", Style::default().fg(Color::Green)));
+        }
+        let keys = if share.anonymized { "?  E/Enter: Send  Esc: Cancel" } else { "?  E/Enter: Send  a: Anonymize first  Esc: Cancel" };
+        spans.push(Span::styled(keys, Style::default().fg(Color::DarkGray)));
+        spans.push(Span::styled(
+            format!("  → {}", app.config.share_command),
+            Style::default().fg(Color::DarkGray),
+        ));
+        frame.render_widget(Paragraph::new(Line::from(spans)), chunks[2]);
+    } else if let Some(dest) = &app.export_prompt {
+        let mut spans = vec![
+            Span::styled(" Write to: ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::styled(dest.as_str(), Style::default().fg(Color::White)),
+            Span::styled("█", Style::default().fg(Color::Cyan)),
+        ];
+        if app.export_confirm_overwrite {
+            spans.push(Span::styled(
+                "  exists — Enter again to overwrite, Esc to cancel",
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        frame.render_widget(Paragraph::new(Line::from(spans)), chunks[2]);
+    } else if app.project_activity.is_some() {
+        let help = Paragraph::new(Line::from(vec![Span::styled(
+            "Esc/P: Close",
+            Style::default().fg(Color::DarkGray),
+        )]));
+        frame.render_widget(help, chunks[2]);
+    } else if app.session_timeline.is_some() {
+        let help = Paragraph::new(Line::from(vec![Span::styled(
+            "Esc/L: Close",
+            Style::default().fg(Color::DarkGray),
+        )]));
+        frame.render_widget(help, chunks[2]);
+    } else if app.raw_json.is_some() {
+        let help = Paragraph::new(Line::from(vec![Span::styled(
+            "Esc/J: Close  j/k: Scroll  d/u: Half Page  g/G: Top/Bottom",
+            Style::default().fg(Color::DarkGray),
+        )]));
+        frame.render_widget(help, chunks[2]);
+    } else if app.prompt_toc.is_some() {
+        let help = Paragraph::new(Line::from(vec![Span::styled(
+            "Enter: Jump to prompt  Esc/T: Close  j/k: Navigate  d/u: Half Page  g/G: Top/Bottom",
+            Style::default().fg(Color::DarkGray),
+        )]));
+        frame.render_widget(help, chunks[2]);
+    } else if app.tool_picker.is_some() {
+        let help = Paragraph::new(Line::from(vec![Span::styled(
+            "Space: Show/hide tool  a: All tools  Esc/Enter/F: Close  j/k: Navigate  d/u: Half Page  g/G: Top/Bottom",
+            Style::default().fg(Color::DarkGray),
+        )]));
+        frame.render_widget(help, chunks[2]);
+    } else if app.instruction_list.is_some() {
+        let help = Paragraph::new(Line::from(vec![Span::styled(
+            "Enter/e: Open in $EDITOR  s: Version at the time  Esc/I: Close  j/k: Navigate",
+            Style::default().fg(Color::DarkGray),
+        )]));
+        frame.render_widget(help, chunks[2]);
+    } else if app.bookmark_list.is_some() {
+        let help = Paragraph::new(Line::from(vec![Span::styled(
+            "Enter: Open  x: Delete  Esc/B: Close  j/k: Navigate  d/u: Half Page  g/G: Top/Bottom",
+            Style::default().fg(Color::DarkGray),
+        )]));
+        frame.render_widget(help, chunks[2]);
+    } else if app.code_picker.is_some() {
+        let help = Paragraph::new(Line::from(vec![Span::styled(
+            "1-9/Enter: Copy  Esc: Close  j/k: Navigate",
+            Style::default().fg(Color::DarkGray),
+        )]));
+        frame.render_widget(help, chunks[2]);
+    } else if let Some(range) = app.visual_range() {
+        let help = Paragraph::new(Line::from(vec![
+            Span::styled(
+                format!(" VISUAL {} lines ", range.end() - range.start() + 1),
+                Style::default().fg(Color::Black).bg(Color::Cyan),
+            ),
+            Span::styled(
+                "  y/Enter: Copy  E: Share  Esc/V: Cancel  j/k: Extend  d/u: Half Page  g/G: Top/Bottom",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+        frame.render_widget(help, chunks[2]);
+    } else if app.command_menu.is_some() {
         let help = Paragraph::new(Line::from(vec![Span::styled(
-            help_text,
+            "Enter: Run  Esc: Close  j/k: Navigate",
             Style::default().fg(Color::DarkGray),
         )]));
         frame.render_widget(help, chunks[2]);
+    } else if let Some(status) = &app.status_message {
+        let status_bar = Paragraph::new(Line::from(vec![Span::styled(
+            format!(" {}", status),
+            Style::default().fg(Color::Green),
+        )]));
+        frame.render_widget(status_bar, chunks[2]);
+    } else {
+        let mut spans = Vec::new();
+        if let Some(progress) = &app.index_progress {
+            spans.push(Span::styled(
+                format!(
+                    " Indexing… {}/{} projects, {} sessions  ",
+                    progress.projects_done, progress.projects_total, progress.sessions_scanned
+                ),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        if app.screen == Screen::SessionDetail && !app.show_detail_keys {
+            spans.extend(detail_position_spans(app));
+        } else {
+            let help_text = match app.screen {
+                Screen::ProjectList => "Enter: Open  s: Global Search  B: Bookmarks  Ctrl-R: Re-index  i: Index activity  q: Quit  j/k: Navigate  /: Filter",
+                Screen::SessionList => "Enter: Open  Esc: Back  j/k: Navigate  d/u: Half Page  B: Bookmarks  Tab: Filter  *: Favorite  f: Favorites  #: Tags  Space: Mark  X: Delete marked  Z: Archive  A: Archived  D: Date range  n: Needs Input  x: Expensive  o: Sort  F: Script filter  V: Version  P: Activity  C: Cleanup review  r: Resumed parts  a: Audit  e: Export prompts  E: Share  !: Commands  /: Search",
+                Screen::SessionDetail if app.overview => "?: Position  Enter/v: Expand at message  j/k: Navigate  d/u: Half Page  g/G: Top/Bottom  Esc: Back",
+                Screen::SessionDetail => "?: Position  Esc: Back  Ctrl-n/p: Next/Prev session  j/k: Prev/Next message  [/]: Prev/Next prompt  T: Prompt list  I: CLAUDE.md  m: Bookmark  B: Bookmarks  *: Favorite  #: Tags  R: Re-ask  1-5/t: Hide roles  F: Filter tools  d/u: Half Page  g/G: Top/Bottom  a: Audit  c: File Changes  f: Snapshots  Space: Expand result/diff/content  x: All result lines  o: Full messages  A: Answers only  b: Threaded  S: Subagent  L: Timeline  M: Raw markdown  J: Raw JSON  D: JSONL pane  P: Pager  V: Select lines  y: Copy code  v: Overview  e: Export prompts  E: Share  !: Commands  /: Highlight  n/N: Next/Prev match",
+                Screen::GlobalSearch => "Enter: Detail  Tab: Expand matches  y: Copy resume cmd  Y: Copy prompt  R: Re-ask  Ctrl-R: Re-index  Esc: Back  j/k: Navigate",
+                Screen::PermissionAudit => "e: Export TSV  Esc: Back  j/k: Navigate  d/u: Half Page  g/G: Top/Bottom",
+                Screen::SessionChanges => "Esc: Back  j/k: Scroll  d/u: Half Page  g/G: Top/Bottom",
+                Screen::FileSnapshots => "Enter: View content  w: Write to file  Esc: Back  j/k: Navigate  d/u: Half Page  g/G: Top/Bottom",
+                Screen::SnapshotView => "w: Write to file  Esc: Back  j/k: Scroll  d/u: Half Page  g/G: Top/Bottom",
+                Screen::IndexActivity => "Ctrl-R: Re-index  Esc: Back  j/k: Navigate  d/u: Half Page  g/G: Top/Bottom",
+                Screen::CleanupReview => "k: Keep  d: Delete  a: Archive  s: Skip for now  ↑/↓: Scroll preview  PgUp/PgDn: Half Page  Esc: Stop review",
+            };
+            spans.push(Span::styled(help_text, Style::default().fg(Color::DarkGray)));
+        }
+        frame.render_widget(Paragraph::new(Line::from(spans)), chunks[2]);
+    }
+
+    if let Some(selected) = app.command_menu {
+        draw_command_menu(frame, app, selected, chunks[1]);
+    }
+    if let Some(list) = &app.prompt_toc {
+        draw_prompt_toc(frame, app, list, chunks[1]);
+    }
+    if let Some(list) = &app.tool_picker {
+        draw_tool_picker(frame, app, list, chunks[1]);
+    }
+    if let Some(selected) = app.code_picker {
+        draw_code_picker(frame, app, selected, chunks[1]);
+    }
+    if let Some(list) = &app.bookmark_list {
+        draw_bookmarks(frame, app, list, chunks[1]);
+    }
+    if let Some(list) = &app.instruction_list {
+        draw_instruction_files(frame, app, list.selected, chunks[1]);
+    }
+    if let Some(activity) = &app.project_activity {
+        draw_project_activity(frame, app, activity, chunks[1]);
+    }
+    if let Some(timeline) = &app.session_timeline {
+        draw_session_timeline(frame, app, timeline, chunks[1]);
+    }
+    if let Some(json) = &app.raw_json {
+        draw_raw_json(frame, app, json, chunks[1]);
+    }
+}
+
+/// タイトルバー: 左に現在地と絞り込み、右に件数と索引の鮮度
+fn draw_title_bar(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let bold = Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+    let mut left = vec![Span::styled(" ", bold)];
+    match app.screen {
+        Screen::ProjectList => left.push(Span::styled("Projects", bold)),
+        Screen::GlobalSearch => left.push(Span::styled("Global Search", bold)),
+        Screen::IndexActivity => left.push(Span::styled("Index Activity", bold)),
+        _ => {
+            let project_path = app.current_project_path();
+            let project = project_path.rsplit('/').find(|s| !s.is_empty()).unwrap_or(&project_path);
+            left.push(Span::styled(
+                project.to_string(),
+                Style::default().fg(project_accent(app, &project_path)).add_modifier(Modifier::BOLD),
+            ));
+            if app.screen == Screen::CleanupReview {
+                left.push(Span::styled(" › cleanup review", Style::default().fg(Color::Cyan)));
+            } else if app.screen != Screen::SessionList && !app.current_session_id.is_empty() {
+                let id = &app.current_session_id[..app.current_session_id.len().min(8)];
+                left.push(Span::styled(format!(" › {}", id), Style::default().fg(Color::Cyan)));
+            }
+        }
+    }
+    for filter in app.title_filters() {
+        left.push(Span::styled(format!(" [{}]", filter), Style::default().fg(Color::Yellow)));
+    }
+
+    let freshness = if app.index_rx.is_some() {
+        "indexing…".to_string()
+    } else {
+        match app.last_indexed {
+            Some(at) => format!("indexed {}", format_age((chrono::Utc::now() - at).num_seconds())),
+            None => "not indexed".to_string(),
+        }
+    };
+    let right = format!("{}  ·  {} ", app.title_counts(), freshness);
+
+    frame.render_widget(Paragraph::new(Line::from(left)), area);
+    frame.render_widget(
+        Paragraph::new(Line::from(Span::styled(right, Style::default().fg(Color::DarkGray))))
+            .alignment(ratatui::layout::Alignment::Right),
+        area,
+    );
+}
+
+/// 経過秒数を「3m ago」のような短い表記にする
+fn format_age(secs: i64) -> String {
+    match secs.max(0) {
+        s if s < 60 => "just now".to_string(),
+        s if s < 3600 => format!("{}m ago", s / 60),
+        s if s < 86400 => format!("{}h ago", s / 3600),
+        s => format!("{}d ago", s / 86400),
+    }
+}
+
+/// 外部コマンドのメニューを画面中央に重ねて表示
+fn draw_command_menu(frame: &mut Frame, app: &App, selected: usize, area: ratatui::layout::Rect) {
+    let names: Vec<&str> = app.config.commands.iter().map(|c| c.name.as_str()).collect();
+    let width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0).max(20) as u16 + 6;
+    let height = names.len() as u16 + 2;
+    let [popup] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup);
+
+    let lines: Vec<Line> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let style = if i == selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            Line::from(Span::styled(format!(" {} ", name), style))
+        })
+        .collect();
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(" Run command ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        popup,
+    );
+}
+
+/// 自分のプロンプトを番号付きで並べた目次を重ねて表示
+fn draw_prompt_toc(frame: &mut Frame, app: &App, list: &crate::list::ListState, area: ratatui::layout::Rect) {
+    let prompts = app.toc_prompts();
+    let visible = app.toc_visible_rows();
+    let width = area.width.min(90);
+    let text_width = (width as usize).saturating_sub(16);
+    let number_width = prompts.len().to_string().len();
+    let lines: Vec<Line> = prompts
+        .iter()
+        .enumerate()
+        .skip(list.offset)
+        .take(visible)
+        .map(|(row, &i)| {
+            let msg = &app.messages[i];
+            let base = if row == list.selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let first = msg.text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+            let mut text: String = first.chars().take(text_width).collect();
+            if first.chars().count() > text_width || msg.text.trim().lines().nth(1).is_some() {
+                text.push('…');
+            }
+            let time = msg.timestamp.map(|t| t.format("%H:%M").to_string()).unwrap_or_default();
+            Line::from(vec![
+                Span::styled(format!(" {:>w$}. ", row + 1, w = number_width), base.fg(Color::Yellow)),
+                Span::styled(format!("{:<5} ", time), base.fg(Color::DarkGray)),
+                Span::styled(text, base),
+            ])
+        })
+        .collect();
+
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let [popup] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup);
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(format!(" Prompts ({}) ", prompts.len()))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        popup,
+    );
+}
+
+/// セッションで使われたツールと呼び出し回数。印の付いたツールだけを表示する
+fn draw_tool_picker(frame: &mut Frame, app: &App, list: &crate::list::ListState, area: ratatui::layout::Rect) {
+    let tools = app.session_tools();
+    let visible = app.toc_visible_rows();
+    let name_width = tools.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    let lines: Vec<Line> = tools
+        .iter()
+        .enumerate()
+        .skip(list.offset)
+        .take(visible)
+        .map(|(row, (name, calls))| {
+            let base = if row == list.selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let shown = app.shown_tools.iter().any(|t| t == name);
+            Line::from(vec![
+                Span::styled(if shown { " [x] " } else { " [ ] " }, base.fg(Color::Yellow)),
+                Span::styled(format!("{:<w$} ", name, w = name_width), base),
+                Span::styled(format!("{:>5} ", calls), base.fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+
+    let title = if app.shown_tools.is_empty() {
+        " Tools (all shown) ".to_string()
+    } else {
+        format!(" Tools ({} shown) ", app.shown_tools.len())
+    };
+    let width = lines
+        .iter()
+        .map(|l| l.width() as u16 + 2)
+        .chain([title.chars().count() as u16 + 2])
+        .max()
+        .unwrap_or(0)
+        .clamp(30, area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let [popup] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup);
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        popup,
+    );
+}
+
+/// セッションが参照した CLAUDE.md と設定ファイル。今は無いものは赤で示す
+fn draw_instruction_files(frame: &mut Frame, app: &App, selected: usize, area: ratatui::layout::Rect) {
+    let lines: Vec<Line> = app
+        .instruction_files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let base = if i == selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let mut spans = vec![Span::styled(format!(" {} ", file), base)];
+            if !crate::app::expand_home(file).exists() {
+                spans.push(Span::styled("(missing) ", base.fg(Color::Red)));
+            }
+            Line::from(spans)
+        })
+        .collect();
+
+    let width = lines.iter().map(|l| l.width() as u16 + 2).max().unwrap_or(0).clamp(30, area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let [popup] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup);
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(" Instruction files ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        popup,
+    );
+}
+
+/// 全セッションのブックマーク: 付けた時期、プロジェクト、セッション、メモ
+fn draw_bookmarks(frame: &mut Frame, app: &App, list: &crate::list::ListState, area: ratatui::layout::Rect) {
+    let visible = app.toc_visible_rows();
+    let width = area.width.min(100);
+    let now = chrono::Utc::now().timestamp();
+    let lines: Vec<Line> = app
+        .bookmarks
+        .iter()
+        .enumerate()
+        .skip(list.offset)
+        .take(visible)
+        .map(|(row, bookmark)| {
+            let base = if row == list.selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let path = app
+                .projects
+                .iter()
+                .find(|p| p.dir_name == bookmark.dir_name)
+                .map(|p| p.original_path.clone())
+                .unwrap_or_else(|| parser::decode_project_path(&bookmark.dir_name));
+            let project = path.rsplit('/').find(|s| !s.is_empty()).unwrap_or(&path).to_string();
+            let note = if bookmark.note.is_empty() { "(no note)" } else { bookmark.note.as_str() };
+            Line::from(vec![
+                Span::styled(" ◆ ", base.fg(Color::Magenta)),
+                Span::styled(format!("{:>8} ", format_age(now - bookmark.created_at)), base.fg(Color::DarkGray)),
+                Span::styled(format!("{} ", project), base.fg(project_accent(app, &path))),
+                Span::styled(
+                    format!("{} ", &bookmark.session_id[..bookmark.session_id.len().min(8)]),
+                    base.fg(Color::DarkGray),
+                ),
+                Span::styled(note.to_string(), base),
+            ])
+        })
+        .collect();
+
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let [popup] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup);
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(format!(" Bookmarks ({}) ", app.bookmarks.len()))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Magenta)),
+        ),
+        popup,
+    );
+}
+
+/// 回答のコードブロックを番号付きで並べ、コピーするものを選ばせる
+fn draw_code_picker(frame: &mut Frame, app: &App, selected: usize, area: ratatui::layout::Rect) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let lines: Vec<Line> = app
+        .code_blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| {
+            let base = if i == selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let number = if i < 9 { format!(" {} ", i + 1) } else { "   ".to_string() };
+            let lang = if block.lang.is_empty() { "code" } else { block.lang.as_str() };
+            let first = block.code.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+            Line::from(vec![
+                Span::styled(number, base.fg(Color::Yellow)),
+                Span::styled(format!("{:<10} ", lang), base.fg(Color::Cyan)),
+                Span::styled(format!("{:>4} lines  ", block.code.lines().count()), base.patch(dim)),
+                Span::styled(first.to_string(), base),
+            ])
+        })
+        .collect();
+
+    let width = area.width.min(80);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let [popup] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup);
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(" Copy code block ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        popup,
+    );
+}
+
+/// 選択中のメッセージの元の JSON を画面いっぱいに重ねて表示
+fn draw_raw_json(frame: &mut Frame, app: &App, json: &str, area: ratatui::layout::Rect) {
+    let lines = json_lines(json);
+
+    let [popup] = Layout::horizontal([Constraint::Length(area.width.min(120))])
+        .flex(Flex::Center)
+        .areas(area);
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).scroll((app.raw_json_scroll as u16, 0)).block(
+            Block::default()
+                .title(" Raw JSON ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        popup,
+    );
+}
+
+/// 分割表示の右側: メッセージカーソルに合わせて元の JSONL 行を整形して表示
+fn draw_raw_pane(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let msg = app.messages.get(app.selected_message);
+    let (title, lines) = match msg.and_then(Message::pretty_source_line) {
+        Some(json) => {
+            // 1行から複数のメッセージができた場合はそれも示す
+            let line = msg.and_then(|m| m.source_line.as_ref());
+            let shared = app
+                .messages
+                .iter()
+                .filter(|m| m.source_line.as_ref().zip(line).is_some_and(|(a, b)| std::sync::Arc::ptr_eq(a, b)))
+                .count();
+            let title = if shared > 1 {
+                format!(" Raw JSONL: message {} (line shared by {}) ", app.selected_message + 1, shared)
+            } else {
+                format!(" Raw JSONL: message {} ", app.selected_message + 1)
+            };
+            (title, json_lines(&json))
+        }
+        None => (
+            " Raw JSONL ".to_string(),
+            vec![Line::from(Span::styled("No raw entry for the selected message", Style::default().fg(Color::DarkGray)))],
+        ),
+    };
+    frame.render_widget(
+        Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::DarkGray)),
+        ),
+        area,
+    );
+}
+
+/// 整形済みの JSON を行に分け、キーに色を付ける
+fn json_lines(json: &str) -> Vec<Line<'static>> {
+    let key = Style::default().fg(Color::Cyan);
+    json.lines()
+        .map(|line| {
+            // "key": の部分だけ色を変える
+            let indent = line.len() - line.trim_start().len();
+            match line.trim_start().split_once("\": ") {
+                Some((name, rest)) if name.starts_with('"') => Line::from(vec![
+                    Span::raw(line[..indent].to_string()),
+                    Span::styled(format!("{}\":", name), key),
+                    Span::raw(format!(" {}", rest)),
+                ]),
+                _ => Line::from(line.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// プロジェクト全体の活動集計を画面中央に重ねて表示
+fn draw_project_activity(frame: &mut Frame, app: &App, activity: &ProjectActivity, area: ratatui::layout::Rect) {
+    let heading = Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+    let count = Style::default().fg(Color::Yellow);
+    let project_prefix = format!("{}/", app.current_project_path());
+    let ranked = |lines: &mut Vec<Line>, title: &str, rows: &[(String, usize)]| {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(format!(" {}", title), heading)));
+        if rows.is_empty() {
+            lines.push(Line::from(Span::styled("   (none)", Style::default().fg(Color::DarkGray))));
+        }
+        for (name, n) in rows {
+            lines.push(Line::from(vec![
+                Span::styled(format!(" {:>5}  ", n), count),
+                Span::raw(name.strip_prefix(&project_prefix).unwrap_or(name).to_string()),
+            ]));
+        }
+    };
+
+    let mut lines = vec![Line::from(format!(
+        " {} sessions · {} tool calls · {} commands · {} files changed",
+        activity.sessions, activity.tool_calls, activity.commands_run, activity.files_changed
+    ))];
+    ranked(&mut lines, "Most-changed files (sessions)", &activity.top_files);
+    ranked(&mut lines, "Most-run commands (runs)", &activity.top_commands);
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(" Tools", heading)));
+    let tools: Vec<String> = activity.tools.iter().map(|(tool, n)| format!("{} {}", tool, n)).collect();
+    lines.push(Line::from(format!(" {}", if tools.is_empty() { "(none)".to_string() } else { tools.join(" · ") })));
+
+    let [popup] = Layout::horizontal([Constraint::Length(area.width.min(80))])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup] = Layout::vertical([Constraint::Length((lines.len() as u16 + 2).min(area.height))])
+        .flex(Flex::Center)
+        .areas(popup);
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(format!(" Activity ({}) ", app.time_filter.label()))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        popup,
+    );
+}
+
+/// セッションの時間軸: プロンプトを ▼ で、ツール呼び出しを結果が返るまでの棒で、ツールごとの段に描く
+fn draw_session_timeline(frame: &mut Frame, app: &App, timeline: &Timeline, area: ratatui::layout::Rect) {
+    let heading = Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+    let dim = Style::default().fg(Color::DarkGray);
+    let totals = timeline.tool_totals();
+    let label_width = totals.iter().map(|(tool, _, _)| tool.chars().count()).chain([7]).max().unwrap_or(7).min(16);
+    let suffix_width = 14;
+    let chart = (area.width as usize).saturating_sub(2 + 1 + label_width + 1 + suffix_width).max(10);
+    let label = |text: &str| format!(" {:<w$.w$} ", text, w = label_width);
+    let tool_color = |tool: &str| PROJECT_ACCENTS[analysis::accent_slot(tool, PROJECT_ACCENTS.len())];
+    // 各列の文字（None は空白）を、同じものが続くところはまとめて Span にする
+    let lane = |cells: Vec<Option<char>>, style: Style| -> Vec<Span<'static>> {
+        let mut spans: Vec<Span> = Vec::new();
+        let mut run = String::new();
+        let mut filled = false;
+        for cell in cells {
+            if cell.is_some() != filled && !run.is_empty() {
+                spans.push(Span::styled(std::mem::take(&mut run), if filled { style } else { dim }));
+            }
+            filled = cell.is_some();
+            run.push(cell.unwrap_or(' '));
+        }
+        spans.push(Span::styled(run, if filled { style } else { dim }));
+        spans
+    };
+
+    let mut lines = vec![Line::from(format!(
+        " {} – {} · {} · {} prompts · {} tool calls",
+        timeline.start.format("%Y-%m-%d %H:%M:%S"),
+        timeline.end.format("%H:%M:%S"),
+        format_gap(timeline.secs()),
+        timeline.prompts.len(),
+        timeline.calls.len()
+    ))];
+    lines.push(Line::from(""));
+    let (first, last) = (timeline.start.format("%H:%M").to_string(), timeline.end.format("%H:%M").to_string());
+    let middle = (timeline.start + (timeline.end - timeline.start) / 2).format("%H:%M").to_string();
+    let mut ticks = format!("{:<w$}", first, w = chart);
+    if chart >= 3 * 5 + 2 {
+        ticks.replace_range(chart / 2 - 2..chart / 2 + 3, &middle);
+    }
+    ticks.replace_range(chart - 5.., &last);
+    lines.push(Line::from(vec![Span::raw(label("")), Span::styled(ticks, dim)]));
+    lines.push(Line::from(vec![
+        Span::raw(label("")),
+        Span::styled(format!("├{}┤", "─".repeat(chart - 2)), dim),
+    ]));
+
+    let mut cells = vec![None; chart];
+    for &(_, at) in &timeline.prompts {
+        cells[timeline.column(at, chart)] = Some('▼');
+    }
+    let mut spans = vec![Span::styled(label("prompts"), Style::default().fg(Color::Yellow))];
+    spans.extend(lane(cells, Style::default().fg(Color::Yellow)));
+    spans.push(Span::styled(format!(" {:>8} ×{:<4}", "", timeline.prompts.len()), dim));
+    lines.push(Line::from(spans));
+
+    // 下の「長かった呼び出し」に少なくとも数行を残す
+    let lanes = (area.height as usize).saturating_sub(2 + lines.len() + 5).max(1);
+    for (tool, secs, calls) in totals.iter().take(lanes) {
+        let mut cells = vec![None; chart];
+        for call in timeline.calls.iter().filter(|c| c.tool == *tool) {
+            for cell in &mut cells[timeline.column(call.start, chart)..=timeline.column(call.end, chart)] {
+                *cell = Some('█');
+            }
+        }
+        let color = tool_color(tool);
+        let mut spans = vec![Span::styled(label(tool), Style::default().fg(color))];
+        spans.extend(lane(cells, Style::default().fg(color)));
+        spans.push(Span::styled(format!(" {:>8} ×{:<4}", format_gap(*secs), calls), dim));
+        lines.push(Line::from(spans));
+    }
+    if totals.len() > lanes {
+        lines.push(Line::from(Span::styled(format!(" … {} more tools", totals.len() - lanes), dim)));
     }
 
-    // Screen content
-    match app.screen {
-        Screen::ProjectList => draw_project_list(frame, app, chunks[1]),
-        Screen::SessionList => draw_session_list(frame, app, chunks[1]),
-        Screen::SessionDetail => draw_session_detail(frame, app, chunks[1]),
-        Screen::GlobalSearch => draw_global_search(frame, app, chunks[1]),
-    }
+    let mut longest: Vec<&TimelineCall> = timeline.calls.iter().filter(|c| c.secs() > 0).collect();
+    longest.sort_by_key(|c| std::cmp::Reverse(c.secs()));
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(" Longest calls", heading)));
+    if longest.is_empty() {
+        lines.push(Line::from(Span::styled("   (none)", dim)));
+    }
+    let rows = (area.height as usize).saturating_sub(2 + lines.len());
+    let text_width = (area.width as usize).saturating_sub(2 + 10 + label_width + 2);
+    for call in longest.into_iter().take(rows) {
+        let text = app.messages[call.message].text.lines().next().unwrap_or("");
+        lines.push(Line::from(vec![
+            Span::styled(format!(" {:>8}  ", format_gap(call.secs())), Style::default().fg(Color::Yellow)),
+            Span::styled(format!("{:<w$}  ", call.tool, w = label_width), Style::default().fg(tool_color(&call.tool))),
+            Span::raw(text.chars().take(text_width).collect::<String>()),
+        ]));
+    }
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(" Timeline ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        ),
+        area,
+    );
+}
+
+/// 設定で色を指定していないプロジェクトに割り当てる色（枠の Cyan と選択行の DarkGray は避ける）
+const PROJECT_ACCENTS: [Color; 10] = [
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+];
+
+/// プロジェクトの目印の色: 設定の `project_colors` があればそれ、なければパスから決まる色
+fn project_accent(app: &App, project_path: &str) -> Color {
+    app.config
+        .project_colors
+        .get(project_path)
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(PROJECT_ACCENTS[analysis::accent_slot(project_path, PROJECT_ACCENTS.len())])
+}
+
+fn draw_project_list(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let header = Row::new(vec![
+        Cell::from("Project Path"),
+        Cell::from("Sessions"),
+    ])
+    .style(
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    // borders(2) + header(1) = 3
+    let visible_height = (area.height as usize).saturating_sub(3);
+
+    let rows: Vec<Row> = app
+        .displayed_projects
+        .iter()
+        .enumerate()
+        .skip(app.project_list.offset)
+        .take(visible_height)
+        .map(|(i, project)| {
+            let style = if i == app.project_list.selected {
+                Style::default()
+                    .bg(Color::DarkGray)
+                    .fg(Color::White)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            Row::new(vec![
+                Cell::from(Span::styled(
+                    project.original_path.clone(),
+                    Style::default().fg(project_accent(app, &project.original_path)),
+                )),
+                Cell::from(project.session_count.to_string()),
+            ])
+            .style(style)
+        })
+        .collect();
+
+    let title = if app.search_query.is_empty() {
+        " Projects ".to_string()
+    } else {
+        format!(" Projects ({} matches) ", app.displayed_projects.len())
+    };
+
+    let table = Table::new(
+        rows,
+        [Constraint::Percentage(70), Constraint::Percentage(30)],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    frame.render_widget(table, area);
+}
+
+fn draw_session_list(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let inner_chunks = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(1),
+        Constraint::Min(0),
+    ])
+    .split(area);
+
+    // Breadcrumb
+    let breadcrumb = Paragraph::new(Line::from(vec![
+        Span::styled(" Project: ", Style::default().fg(Color::DarkGray)),
+        Span::styled(
+            app.current_project_name.clone(),
+            Style::default().fg(project_accent(app, &app.current_project_path())),
+        ),
+    ]));
+    frame.render_widget(breadcrumb, inner_chunks[0]);
+
+    // Filter tabs
+    let mut filter_labels: Vec<String> = TimeFilter::all_filters()
+        .iter()
+        .map(|f| f.label())
+        .collect();
+    // 入力した期間はプリセットの後ろにタブとして出す
+    let selected_index = match TimeFilter::all_filters().iter().position(|f| *f == app.time_filter) {
+        Some(i) => i,
+        None => {
+            filter_labels.push(app.time_filter.label());
+            filter_labels.len() - 1
+        }
+    };
+    let tabs = Tabs::new(filter_labels)
+        .select(selected_index)
+        .style(Style::default().fg(Color::DarkGray))
+        .highlight_style(
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        );
+    frame.render_widget(tabs, inner_chunks[1]);
+
+    // Session table
+    let script_columns = app.scripts.column_names();
+    // 更新順のときは時刻の列も最後の更新時刻にする
+    let by_modified = app.session_sort == SessionSort::Modified;
+    // 費用順と高額セッションの表示中はトークン数の代わりに推定費用を出す
+    let show_cost = app.expensive_only || app.session_sort == SessionSort::Cost;
+    let mut header_cells = vec![
+        Cell::from(if by_modified { "Modified" } else { "Timestamp" }),
+        Cell::from("Msgs"),
+        Cell::from(if show_cost { "Cost" } else { "Tokens" }),
+        Cell::from("Version"),
+        Cell::from("Branch"),
+    ];
+    if app.show_root_column {
+        header_cells.push(Cell::from("Root"));
+    }
+    // タグの付いたセッションが一覧にあるときだけタグの列を出す
+    let show_tags = app.filtered_sessions.iter().any(|s| app.session_tags.contains_key(&s.session_id));
+    if show_tags {
+        header_cells.push(Cell::from("Tags"));
+    }
+    header_cells.extend(script_columns.iter().map(|name| Cell::from(name.to_string())));
+    header_cells.push(Cell::from("Preview"));
+    let header = Row::new(header_cells)
+    .style(
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    // borders(2) + header(1) = 3
+    let visible_height = (inner_chunks[2].height as usize).saturating_sub(3);
+
+    let rows: Vec<Row> = app
+        .filtered_sessions
+        .iter()
+        .enumerate()
+        .skip(app.session_list.offset)
+        .take(visible_height)
+        .map(|(i, session)| {
+            let fg = if app.is_expensive(session) {
+                Color::LightRed
+            } else {
+                Color::White
+            };
+            let style = if i == app.session_list.selected {
+                Style::default().bg(Color::DarkGray).fg(fg)
+            } else {
+                Style::default().fg(fg)
+            };
+            let preview = if session.preview.chars().count() > 80 {
+                let truncated: String = session.preview.chars().take(80).collect();
+                format!("{}...", truncated)
+            } else {
+                session.preview.clone()
+            }
+            .replace('\n', " ");
+            let mut preview_spans = Vec::new();
+            if app.favorites.contains(&session.session_id) {
+                preview_spans.push(Span::styled("★ ", Style::default().fg(Color::Yellow)));
+            }
+            if app.marked_sessions.contains(&session.session_id) {
+                preview_spans.push(Span::styled(
+                    "● ",
+                    Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+                ));
+            }
+            if session.needs_input {
+                preview_spans.push(Span::styled(
+                    "[needs input] ",
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            if let Some(folded) = app.session_resumes.get(&session.session_id) {
+                preview_spans.push(Span::styled(
+                    format!("[{} parts] ", folded + 1),
+                    Style::default().fg(Color::Cyan),
+                ));
+            } else if !session.parent_session_id.is_empty() {
+                preview_spans.push(Span::styled("[resumed] ", Style::default().fg(Color::Cyan)));
+            }
+            if session.has_loops {
+                preview_spans.push(Span::styled(
+                    "[loop] ",
+                    Style::default()
+                        .fg(Color::Red)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            // BOM や不正な UTF-8 を置き換えて読んだセッション
+            if !session.warning.is_empty() {
+                preview_spans.push(Span::styled("[repaired] ", Style::default().fg(Color::Magenta)));
+            }
+            preview_spans.push(Span::raw(preview));
+            let mut cells = vec![
+                Cell::from(if by_modified {
+                    session.modified.or(session.timestamp).map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default()
+                } else {
+                    session.timestamp_str()
+                }),
+                Cell::from(session.message_count.to_string()),
+                Cell::from(if show_cost {
+                    format!("${:.2}", session.cost_usd)
+                } else {
+                    compact_count(session.usage.total())
+                }),
+                Cell::from(session.version.clone()),
+                Cell::from(session.git_branch.clone()),
+            ];
+            if app.show_root_column {
+                cells.push(Cell::from(session.source_root.clone()));
+            }
+            if show_tags {
+                let tags = app.session_tags.get(&session.session_id).map(Vec::as_slice).unwrap_or_default();
+                cells.push(Cell::from(Span::styled(
+                    tags.iter().map(|t| format!("#{}", t)).collect::<Vec<_>>().join(" "),
+                    Style::default().fg(Color::Green),
+                )));
+            }
+            cells.extend(
+                (0..script_columns.len()).map(|c| Cell::from(app.scripts.column_value(c, session))),
+            );
+            cells.push(Cell::from(Line::from(preview_spans)));
+            Row::new(cells).style(style)
+        })
+        .collect();
+
+    let mut title = if app.search_query.is_empty() {
+        " Sessions ".to_string()
+    } else {
+        format!(" Sessions ({} matches) ", app.filtered_sessions.len())
+    };
+    let marked = app.marked_in_list().len();
+    if marked > 0 {
+        title.push_str(&format!("[{} marked] ", marked));
+    }
+    // 高額順の表示中は費用の順に並ぶ
+    if !app.expensive_only {
+        title.push_str(&format!("[sort: {}] ", app.session_sort.label()));
+    }
+    if app.needs_input_only {
+        title.push_str("[needs input] ");
+    }
+    if app.favorites_only {
+        title.push_str("[favorites] ");
+    }
+    if app.expensive_only {
+        title.push_str("[expensive] ");
+    }
+    if app.show_resumed {
+        title.push_str("[all resumed parts] ");
+    }
+    if app.show_archived {
+        title.push_str("[archived] ");
+    }
+    if let Some(name) = app.script_filter.and_then(|f| app.scripts.filter_names().get(f).copied()) {
+        title.push_str(&format!("[{}] ", name));
+    }
+    if let Some(version) = &app.version_filter {
+        title.push_str(&format!("[v{}] ", version));
+    }
+
+    // スクリプトのカラムは1つ10%ずつ Preview から割り当てる
+    let mut widths = vec![
+        Constraint::Percentage(20),
+        Constraint::Percentage(6),
+        Constraint::Percentage(8),
+        Constraint::Percentage(9),
+        Constraint::Percentage(16),
+    ];
+    if app.show_root_column {
+        widths.push(Constraint::Percentage(10));
+    }
+    if show_tags {
+        widths.push(Constraint::Percentage(12));
+    }
+    widths.extend(script_columns.iter().map(|_| Constraint::Percentage(10)));
+    widths.push(Constraint::Min(10));
+
+    let table = Table::new(rows, widths)
+    .header(header)
+    .block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    frame.render_widget(table, inner_chunks[2]);
+}
+
+fn draw_session_detail(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    // ツール呼び出しがあったセッションだけ、何をしたかの要約行を出す
+    let actions_height = if app.session_actions.is_empty() { 0 } else { 1 };
+    let inner_chunks = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(actions_height),
+        Constraint::Min(0),
+    ])
+    .split(area);
+    if actions_height > 0 {
+        let actions = Paragraph::new(Line::from(vec![
+            Span::styled(" Did: ", Style::default().fg(Color::DarkGray)),
+            Span::styled(app.session_actions.summary(), Style::default().fg(Color::Magenta)),
+        ]));
+        frame.render_widget(actions, inner_chunks[1]);
+    }
+
+    // Breadcrumb
+    let session_id_short = if app.current_session_id.is_empty() {
+        "unknown"
+    } else {
+        &app.current_session_id[..app.current_session_id.len().min(8)]
+    };
+    let mut breadcrumb_spans = vec![
+        Span::styled("▌", Style::default().fg(project_accent(app, &app.current_project_path()))),
+        Span::styled(format!("Session: {}", session_id_short), Style::default().fg(Color::DarkGray)),
+    ];
+    if let Some(main) = &app.main_conversation {
+        breadcrumb_spans.push(Span::styled(
+            format!(" › subagent: {}", main.agent),
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        ));
+        breadcrumb_spans.push(Span::styled("  (Esc: back)", Style::default().fg(Color::DarkGray)));
+    } else if !app.subagents.is_empty() {
+        breadcrumb_spans.push(Span::styled(
+            format!("  {} subagent(s)", app.subagents.len()),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+    if !app.loop_regions.is_empty() {
+        breadcrumb_spans.push(Span::styled(
+            format!("  ⟳ {} loop(s) detected", app.loop_regions.len()),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+    }
+    let forks = app.fork_count();
+    if forks > 0 && !app.threaded {
+        breadcrumb_spans.push(Span::styled(
+            format!("  ⎇ {} fork(s), b: threaded", forks),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+
+    if app.overview {
+        breadcrumb_spans.push(Span::styled(
+            format!("  [overview: {} messages]", app.overview_indices().len()),
+            Style::default().fg(Color::Cyan),
+        ));
+        frame.render_widget(Paragraph::new(Line::from(breadcrumb_spans)), inner_chunks[0]);
+        draw_detail_overview(frame, app, inner_chunks[2]);
+        return;
+    }
+
+    // 分割表示では右側に選択中のメッセージの元の JSONL を出す
+    let mut messages_area = inner_chunks[2];
+    if app.raw_pane {
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(messages_area);
+        draw_raw_pane(frame, app, right);
+        messages_area = left;
+    }
+
+    // Messages: 折り返し済みの行をメッセージ単位でキャッシュし、表示範囲だけ描画する
+    let [text_area, map_area] =
+        Layout::horizontal([Constraint::Min(0), Constraint::Length(1)]).areas(messages_area);
+    let width = text_area.width.saturating_sub(2);
+    let visible_height = text_area.height.saturating_sub(2) as usize;
+
+    let mut cache = app.detail_cache.borrow_mut();
+    cache.sync(app, width);
+
+    let queries = app.highlight_queries();
+    if !queries.is_empty() {
+        breadcrumb_spans.push(Span::raw(" "));
+        for (k, query) in queries.iter().enumerate() {
+            breadcrumb_spans.push(Span::raw(" "));
+            breadcrumb_spans.push(Span::styled(
+                format!("\"{}\"", query),
+                Style::default().bg(highlight_color(k)).fg(Color::Black),
+            ));
+        }
+        let position = app
+            .detail_match
+            .map(|i| format!("{}/", i + 1))
+            .unwrap_or_default();
+        breadcrumb_spans.push(Span::styled(
+            format!(" {}{} matches", position, cache.match_rows.len()),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    let max_scroll = cache.total_rows.saturating_sub(visible_height);
+    let scroll = app.scroll_offset.min(max_scroll);
+    frame.render_widget(Paragraph::new(Line::from(breadcrumb_spans)), inner_chunks[0]);
+
+    let lines = cache.window(scroll, visible_height);
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    frame.render_widget(paragraph, text_area);
+
+    // 選択中のメッセージの行は左の枠を太線にして示す
+    let selected = cache.message_rows(app.selected_message);
+    let marker = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    for row in selected.start.max(scroll)..selected.end.min(scroll + visible_height) {
+        let y = text_area.y + 1 + (row - scroll) as u16;
+        if let Some(cell) = frame.buffer_mut().cell_mut((text_area.x, y)) {
+            cell.set_symbol("┃").set_style(marker);
+        }
+    }
+
+    // ブックマークしたメッセージは先頭行の左の枠に ◆ を出す
+    for &i in &app.bookmarked {
+        let head = cache.message_rows(i).start;
+        if (scroll..scroll + visible_height).contains(&head)
+            && let Some(cell) = frame.buffer_mut().cell_mut((text_area.x, text_area.y + 1 + (head - scroll) as u16))
+        {
+            cell.set_symbol("◆").set_style(Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD));
+        }
+    }
+
+    // ビジュアル選択の行は反転し、カーソル行は左の枠に ▶ を出す
+    if let Some(range) = app.visual_range() {
+        let rows = (*range.start()).max(scroll)..=*range.end();
+        for row in rows.take_while(|&row| row < scroll + visible_height) {
+            let y = text_area.y + 1 + (row - scroll) as u16;
+            let rect = ratatui::layout::Rect::new(text_area.x + 1, y, width, 1);
+            frame.buffer_mut().set_style(rect, Style::default().add_modifier(Modifier::REVERSED));
+        }
+        if let Some((_, cursor)) = app.visual
+            && (scroll..scroll + visible_height).contains(&cursor)
+            && let Some(cell) = frame.buffer_mut().cell_mut((text_area.x, text_area.y + 1 + (cursor - scroll) as u16))
+        {
+            cell.set_symbol("▶").set_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD));
+        }
+    }
+
+    // ミニマップ: 本文の枠の内側と同じ高さで、各行の色がその区間で最も多い役割を示す
+    let map_inner = ratatui::layout::Rect {
+        y: map_area.y + 1,
+        height: map_area.height.saturating_sub(2),
+        ..map_area
+    };
+    cache.minimap_area = map_inner;
+    let total = cache.total_rows;
+    let height = map_inner.height as usize;
+    let map_lines: Vec<Line> = cache
+        .minimap(app, height)
+        .iter()
+        .enumerate()
+        .map(|(k, role)| {
+            let lo = k * total / height.max(1);
+            let hi = ((k + 1) * total / height.max(1)).max(lo + 1);
+            let in_view = lo < scroll + visible_height && hi > scroll;
+            let symbol = if in_view { "█" } else { "▐" };
+            match role {
+                Some(role) => Line::from(Span::styled(symbol, Style::default().fg(role_color(role)))),
+                None => Line::from(""),
+            }
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(map_lines), map_inner);
+}
+
+fn role_color(role: &MessageRole) -> Color {
+    match role {
+        MessageRole::User => Color::Cyan,
+        MessageRole::Assistant => Color::Green,
+        MessageRole::System => Color::Yellow,
+        MessageRole::ToolUse => Color::Yellow,
+        MessageRole::ToolResult => Color::Magenta,
+        MessageRole::Progress => Color::DarkGray,
+    }
+}
+
+/// 1メッセージ1行の概観。役割の記号と本文の先頭だけを表示する
+fn draw_detail_overview(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let visible_height = area.height.saturating_sub(2) as usize;
+    let text_width = (area.width as usize).saturating_sub(2 + 2 + 6);
+    let indices = app.overview_indices();
+    let lines: Vec<Line> = indices
+        .iter()
+        .enumerate()
+        .skip(app.overview_list.offset)
+        .take(visible_height)
+        .map(|(row, &i)| {
+            let msg = &app.messages[i];
+            let (glyph, color) = role_glyph(&msg.role);
+            let time = msg
+                .timestamp
+                .map(|t| t.format("%H:%M ").to_string())
+                .unwrap_or_else(|| " ".repeat(6));
+            let text = msg.text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let text = crate::parser::truncate_str(&text, text_width);
+            let selected = row == app.overview_list.selected;
+            let base = if selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default()
+            };
+            let mut glyph_style = base.fg(color);
+            if selected {
+                glyph_style = glyph_style.add_modifier(Modifier::BOLD);
+            }
+            Line::from(vec![
+                Span::styled(format!("{} ", glyph), glyph_style),
+                Span::styled(time, base.fg(Color::DarkGray)),
+                Span::styled(text, base),
+            ])
+        })
+        .collect();
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    frame.render_widget(paragraph, area);
+}
+
+fn role_glyph(role: &MessageRole) -> (&'static str, Color) {
+    let glyph = match role {
+        MessageRole::User => "❯",
+        MessageRole::Assistant => "●",
+        MessageRole::System => "!",
+        MessageRole::ToolUse => "⚙",
+        MessageRole::ToolResult => "↳",
+        MessageRole::Progress => "…",
+    };
+    (glyph, role_color(role))
+}
+
+/// 詳細画面の下端: 「message 12/87 · line 240/1893 · 34%」と表示中のロールの絞り込み。
+/// 概観では1メッセージ1行なので行数は出さない。
+/// 行数は描画済みの行キャッシュから取るので、本文の描画の後に呼ぶ
+fn detail_position_spans(app: &App) -> Vec<Span<'static>> {
+    let position = if app.overview {
+        let messages = app.overview_indices().len();
+        let max_scroll = messages.saturating_sub(app.visible_rows(&Screen::SessionDetail));
+        format!(
+            " message {}/{} · overview · {}",
+            (app.overview_list.selected + 1).min(messages),
+            messages,
+            scroll_position(app.overview_list.offset.min(max_scroll), max_scroll)
+        )
+    } else {
+        let (message, messages) = app.selected_message_position();
+        let total_rows = app.detail_cache.borrow().total_rows();
+        let max_scroll = total_rows.saturating_sub(app.detail_visible_rows());
+        let scroll = app.scroll_offset.min(max_scroll);
+        format!(
+            " message {}/{} · line {}/{} · {}",
+            message,
+            messages,
+            (scroll + 1).min(total_rows),
+            total_rows,
+            scroll_position(scroll, max_scroll)
+        )
+    };
+    let mut spans = vec![Span::styled(position, Style::default().fg(Color::Cyan))];
+    if app.answers_only {
+        spans.push(Span::styled("  [answers only]", Style::default().fg(Color::Green)));
+    }
+    let hidden = app.hidden_role_labels();
+    if !hidden.is_empty() {
+        spans.push(Span::styled(
+            format!("  [hidden: {}]", hidden.join(", ")),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if !app.shown_tools.is_empty() {
+        spans.push(Span::styled(
+            format!("  [tools: {}]", app.shown_tools.join(", ")),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    spans.push(Span::styled("  ?: Keys", Style::default().fg(Color::DarkGray)));
+    spans
+}
+
+/// vim のルーラーのような位置表示: 全部見えていれば All、端なら Top/Bot、ほかは割合
+fn scroll_position(scroll: usize, max_scroll: usize) -> String {
+    match (scroll, max_scroll) {
+        (_, 0) => "All".to_string(),
+        (0, _) => "Top".to_string(),
+        (s, m) if s >= m => "Bot".to_string(),
+        (s, m) => format!("{}%", s * 100 / m),
+    }
+}
+
+/// SessionDetail の描画行キャッシュ。メッセージごとに `width` で折り返した行を保持する
+#[derive(Default)]
+pub struct DetailLineCache {
+    width: u16,
+    rows: Vec<Line<'static>>,
+    /// 各メッセージの先頭行の位置
+    starts: Vec<usize>,
+    /// 各メッセージの区切りの空行を除いた最初の行
+    heads: Vec<usize>,
+    total_rows: usize,
+    /// 検索語の出現を含む行（出現ごとに1つ、昇順）
+    pub match_rows: Vec<usize>,
+    /// 最後に描画したミニマップの位置（クリック判定用）
+    pub minimap_area: ratatui::layout::Rect,
+    /// ミニマップの高さと各行の役割
+    minimap: (usize, Vec<Option<MessageRole>>),
+}
+
+impl DetailLineCache {
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn total_rows(&self) -> usize {
+        self.total_rows
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.starts.clear();
+        self.heads.clear();
+        self.total_rows = 0;
+        self.match_rows.clear();
+        self.minimap = (0, Vec::new());
+    }
+
+    /// 幅が変わったかクリアされていたら作り直す
+    pub fn sync(&mut self, app: &App, width: u16) {
+        if self.width != width || self.starts.len() != app.messages.len() {
+            self.rebuild(app, width);
+        }
+    }
+
+    fn rebuild(&mut self, app: &App, width: u16) {
+        self.clear();
+        self.width = width;
+        let terms = highlight_terms(&app.highlight_queries());
+        for (i, msg) in app.messages.iter().enumerate() {
+            let start = self.rows.len();
+            self.starts.push(start);
+            if !app.shows_message_at(i) {
+                self.heads.push(start);
+                continue;
+            }
+            let first = self.rows.is_empty();
+            self.heads.push(if first { start } else { start + 1 });
+            let mark = if app.threaded { app.thread_marks.get(i).copied().unwrap_or_default() } else { ThreadMark::default() };
+            let indent = (THREAD_INDENT.chars().count() * mark.depth) as u16;
+            let text_width = if width == 0 { 0 } else { width.saturating_sub(indent).max(1) };
+            let (mut rows, matches) = build_message_rows(app, i, msg, first, text_width as usize, &terms);
+            if app.threaded {
+                // 見出しは区切りの空行の次。読書モードには見出しが無い
+                let header = (!app.answers_only).then_some(if first { 0 } else { 1 });
+                indent_thread_rows(&mut rows, mark, header);
+            }
+            self.rows.extend(rows);
+            self.match_rows.extend(matches.into_iter().map(|r| start + r));
+        }
+        self.total_rows = self.rows.len();
+    }
+
+    /// メッセージ `i` の先頭行（非表示のメッセージは次に表示されるものの位置）
+    pub fn message_start(&self, i: usize) -> Option<usize> {
+        self.starts.get(i).copied()
+    }
+
+    /// メッセージ `i` の行範囲（区切りの空行を除く。非表示のメッセージは空）
+    pub fn message_rows(&self, i: usize) -> std::ops::Range<usize> {
+        let Some(&head) = self.heads.get(i) else {
+            return 0..0;
+        };
+        let end = self.starts.get(i + 1).copied().unwrap_or(self.total_rows);
+        head.min(end)..end
+    }
+
+    /// 高さ `height` のミニマップ（高さか中身が変わったときだけ計算し直す）
+    fn minimap(&mut self, app: &App, height: usize) -> &[Option<MessageRole>] {
+        if self.minimap.0 != height || self.minimap.1.len() != height {
+            let segments: Vec<(usize, MessageRole)> = app
+                .messages
+                .iter()
+                .zip(&self.starts)
+                .map(|(msg, &start)| (start, msg.role.clone()))
+                .collect();
+            self.minimap = (height, analysis::minimap(&segments, self.total_rows, height));
+        }
+        &self.minimap.1
+    }
+
+    /// ミニマップ上の位置 (`column`, `row`) がクリックされたら、対応する本文の行
+    pub fn minimap_target(&self, column: u16, row: u16) -> Option<usize> {
+        let area = self.minimap_area;
+        if self.total_rows == 0
+            || column < area.x
+            || column >= area.x + area.width
+            || row < area.y
+            || row >= area.y + area.height
+        {
+            return None;
+        }
+        Some((row - area.y) as usize * self.total_rows / area.height as usize)
+    }
+
+    /// 行 `row` を含むメッセージ
+    pub fn message_at_row(&self, row: usize) -> Option<usize> {
+        self.starts.iter().rposition(|&start| start <= row)
+    }
+
+    /// 行 `range` の文字（行末の空白を除き、改行でつなぐ）
+    pub fn rows_text(&self, range: std::ops::RangeInclusive<usize>) -> String {
+        self.rows
+            .get(range)
+            .unwrap_or_default()
+            .iter()
+            .map(|line| {
+                let text: String = line.spans.iter().map(|span| span.content.as_ref()).collect();
+                text.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn window(&self, first: usize, height: usize) -> Vec<Line<'static>> {
+        self.rows.iter().skip(first).take(height).cloned().collect()
+    }
+}
+
+/// 分岐1段分の字下げ
+const THREAD_INDENT: &str = "│ ";
+
+/// 分岐の深さだけ行頭に罫線を足し、枝の最初のメッセージの見出しに「⎇ 2/3」を添える
+fn indent_thread_rows(rows: &mut [Line<'static>], mark: ThreadMark, header: Option<usize>) {
+    if mark.depth > 0 {
+        let guide = Span::styled(THREAD_INDENT.repeat(mark.depth), Style::default().fg(Color::DarkGray));
+        for row in rows.iter_mut() {
+            row.spans.insert(0, guide.clone());
+        }
+    }
+    if let (Some((k, n)), Some(row)) = (mark.branch, header.and_then(|h| rows.get_mut(h))) {
+        row.spans.push(Span::styled(
+            format!("  ⎇ branch {}/{}", k, n),
+            Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+        ));
+    }
+}
+
+/// ハイライト語ごとの色。語の数は `app::MAX_HIGHLIGHT_TERMS` まで
+const HIGHLIGHT_COLORS: [Color; 6] = [
+    Color::Yellow,
+    Color::LightCyan,
+    Color::LightMagenta,
+    Color::LightGreen,
+    Color::LightRed,
+    Color::LightBlue,
+];
+
+fn highlight_color(k: usize) -> Color {
+    HIGHLIGHT_COLORS[k % HIGHLIGHT_COLORS.len()]
+}
+
+/// 各クエリを小文字の語に分解し、色番号（クエリの順番）を添える
+fn highlight_terms(queries: &[&str]) -> Vec<(Vec<char>, usize)> {
+    queries
+        .iter()
+        .enumerate()
+        .flat_map(|(k, query)| {
+            query
+                .split_whitespace()
+                .map(move |t| (t.chars().map(fold_char).collect(), k))
+        })
+        .collect()
+}
+
+fn fold_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// 複数行のツール結果と Edit/MultiEdit の差分、Write の内容は既定で1行に畳む
+pub fn is_collapsible(msg: &Message) -> bool {
+    (msg.role == MessageRole::ToolResult && msg.text.trim_end().lines().nth(1).is_some()) || tool_body(msg).is_some()
+}
+
+/// ツール呼び出しの見出しの下に畳んでおく本文
+enum ToolBody<'a> {
+    /// Edit/MultiEdit の old_string → new_string の差分
+    Diff(String),
+    /// Write で書いた content
+    Content(&'a str),
+}
+
+fn tool_body(msg: &Message) -> Option<ToolBody<'_>> {
+    if msg.role != MessageRole::ToolUse {
+        return None;
+    }
+    let (name, input) = (msg.tool_name.as_deref()?, msg.tool_input.as_ref()?);
+    if name == "Write" {
+        return input.get("content")?.as_str().map(ToolBody::Content);
+    }
+    changes::tool_call_diff(name, input).map(ToolBody::Diff)
+}
+
+impl ToolBody<'_> {
+    /// 畳んだ本文の1行: 差分なら追加・削除した行数、内容なら行数と大きさ
+    fn summary(&self) -> String {
+        match self {
+            ToolBody::Diff(diff) => {
+                let count = |marker: char| diff.lines().filter(|l| l.starts_with(marker)).count();
+                format!("▸ diff +{} −{} lines", count('+'), count('-'))
+            }
+            ToolBody::Content(content) => {
+                format!("▸ content {} lines, {}", content.lines().count(), format_size(content.len()))
+            }
+        }
+    }
+
+    /// 本文を行ごとに色付けする。ハイライトは書いたファイルの拡張子で選ぶ
+    fn md_lines(&self, msg: &Message, highlight: bool) -> Vec<markdown::MdLine> {
+        let path = msg.tool_input.as_ref().and_then(|i| i.get("file_path")).and_then(|p| p.as_str()).unwrap_or("");
+        let mut highlighter = if highlight { Highlighter::for_path(path) } else { None };
+        let text = match self {
+            ToolBody::Diff(diff) => diff.as_str(),
+            ToolBody::Content(content) => content,
+        };
+        text.lines()
+            .map(|l| markdown::MdLine {
+                lead: String::new(),
+                hang: " ".to_string(),
+                text: l.to_string(),
+                styles: match self {
+                    ToolBody::Diff(_) => diff_line_styles(l, highlighter.as_mut()),
+                    ToolBody::Content(_) => content_line_styles(l, highlighter.as_mut()),
+                },
+            })
+            .collect()
+    }
+}
+
+/// 書いた内容の1行の文字ごとのスタイル。ハイライタがなければ白一色
+fn content_line_styles(line: &str, highlighter: Option<&mut Highlighter>) -> Vec<Style> {
+    let base = Style::default().fg(Color::White);
+    match highlighter {
+        Some(hl) => hl.line(line, base),
+        None => vec![base; line.chars().count()],
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    if bytes >= 1024 { format!("{:.1} KiB", bytes as f64 / 1024.0) } else { format!("{} B", bytes) }
+}
+
+/// 畳んだツール結果の1行: 行数・大きさと最初の空でない行
+fn collapsed_summary(text: &str) -> String {
+    let lines = text.trim_end().lines().count();
+    let size = format_size(text.len());
+    let plain = ansi::strip(text);
+    let first = plain.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+    let first: String = first.chars().take(80).collect();
+    format!("▸ {} lines, {}: {}", lines, size, first)
+}
+
+/// 回答の見出しに添える ` (モデル) 入力→出力 tok`。入力にはキャッシュ分も含める
+fn model_usage_suffix(msg: &Message) -> Option<String> {
+    if msg.model.is_none() && msg.usage.is_none() {
+        return None;
+    }
+    let mut suffix = String::new();
+    if let Some(model) = &msg.model {
+        suffix.push_str(&format!(" ({})", short_model_name(model)));
+    }
+    if let Some(usage) = &msg.usage {
+        let input = usage.input_tokens + usage.cache_creation_tokens + usage.cache_read_tokens;
+        suffix.push_str(&format!(" {}→{} tok", compact_count(input), compact_count(usage.output_tokens)));
+    }
+    Some(suffix)
+}
+
+/// 末尾の日付を落としたモデル名（claude-sonnet-4-5-20250929 → claude-sonnet-4-5）
+fn short_model_name(model: &str) -> &str {
+    match model.rsplit_once('-') {
+        Some((name, date)) if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => model,
+    }
+}
+
+/// Gaps at least this long (seconds) are drawn in yellow in the headers.
+const LONG_GAP_SECS: i64 = 300;
+
+/// 直前の時刻付きメッセージからの経過秒数。1秒未満（同じ行から出たものなど）は None
+fn message_gap(messages: &[Message], i: usize) -> Option<i64> {
+    let at = messages.get(i)?.timestamp?;
+    let before = messages[..i].iter().rev().find_map(|m| m.timestamp)?;
+    let secs = (at - before).num_seconds();
+    (secs >= 1).then_some(secs)
+}
+
+/// 45 → 45s, 133 → 2m 13s, 3900 → 1h 05m
+fn format_gap(secs: i64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// 1234 → 1.2k, 3456789 → 3.5M
+fn compact_count(n: u64) -> String {
+    match n {
+        0..1_000 => n.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", n as f64 / 1_000.0),
+        _ => format!("{:.1}M", n as f64 / 1_000_000.0),
+    }
+}
+
+/// Rows of one message, plus the (relative) row of every search-term occurrence.
+fn build_message_rows(
+    app: &App,
+    i: usize,
+    msg: &Message,
+    first: bool,
+    width: usize,
+    terms: &[(Vec<char>, usize)],
+) -> (Vec<Line<'static>>, Vec<usize>) {
+    let mut lines = Vec::new();
+    let mut match_rows = Vec::new();
+    if !first {
+        lines.push(Line::from(""));
+    }
+
+    let role_color = role_color(&msg.role);
+
+    let ts = msg.timestamp_str();
+    let mut header_spans = vec![Span::styled(
+        msg.role_label(),
+        Style::default()
+            .fg(role_color)
+            .add_modifier(Modifier::BOLD),
+    )];
+    if let Some(suffix) = model_usage_suffix(msg) {
+        header_spans.push(Span::styled(suffix, Style::default().fg(Color::DarkGray)));
+    }
+    if !ts.is_empty() {
+        header_spans.push(Span::raw(" "));
+        header_spans.push(Span::styled(ts, Style::default().fg(Color::DarkGray)));
+    }
+    if let Some(gap) = message_gap(&app.messages, i) {
+        // 5分以上空いたところは目立たせる
+        let color = if gap >= LONG_GAP_SECS { Color::Yellow } else { Color::DarkGray };
+        header_spans.push(Span::styled(format!("  +{}", format_gap(gap)), Style::default().fg(color)));
+    }
+    if let Some(region) = app.loop_regions.iter().find(|r| r.contains(i)) {
+        let marker = if region.start == i {
+            format!(" ⟳ LOOP: {} ×{}", region.tool_name, region.repeats)
+        } else {
+            " ⟳".to_string()
+        };
+        header_spans.push(Span::styled(
+            marker,
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(agent) = app.subagents.iter().find(|a| a.spawned_by == Some(i)) {
+        header_spans.push(Span::styled(
+            format!("  ▸ subagent, {} messages (S: open)", agent.messages.len()),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+    let collapsed = is_collapsible(msg) && !app.expanded_results.contains(&i);
+    if is_collapsible(msg) && !collapsed {
+        header_spans.push(Span::styled(" ▾", Style::default().fg(Color::DarkGray)));
+    }
+    // 読書モードでは見出しを付けず本文だけを続ける
+    if !app.answers_only {
+        lines.push(Line::from(header_spans));
+    }
+    let body = tool_body(msg);
+    if collapsed && body.is_none() {
+        // 畳んだ中に検索語があれば、この行を一致として数える
+        let hits: usize = msg.text.lines().map(|l| mark_terms(l, terms).1.len()).sum();
+        let mut spans = vec![Span::styled(collapsed_summary(&msg.text), Style::default().fg(Color::DarkGray))];
+        if hits > 0 {
+            spans.push(Span::styled(format!("  [{} matches inside]", hits), Style::default().fg(Color::Yellow)));
+            match_rows.extend(std::iter::repeat_n(lines.len(), hits));
+        }
+        lines.push(Line::from(spans));
+        return (lines, match_rows);
+    }
+
+    let text_color = match msg.role {
+        MessageRole::ToolUse | MessageRole::ToolResult => Color::DarkGray,
+        _ => Color::White,
+    };
+
+    let base = Style::default().fg(text_color);
+    let (text, hidden) = msg.capped_text(app.message_char_cap());
+    // 巨大なツール結果は先頭の行だけにして、描画とスクロールを軽く保つ
+    let line_cap = if msg.role == MessageRole::ToolResult { app.result_line_cap(i) } else { 0 };
+    let more_lines = if line_cap > 0 { text.trim_end().lines().count().saturating_sub(line_cap) } else { 0 };
+    let shown = if more_lines > 0 { line_cap } else { usize::MAX };
+    let mut md_lines = if app.markdown && msg.role == MessageRole::Assistant {
+        markdown::render(text, base, app.config.syntax_highlighting)
+    } else if msg.role == MessageRole::ToolResult && text.contains('\x1b') {
+        // コマンド出力の色はそのまま、色を切っていればエスケープ列を消すだけ
+        let styled = if app.config.ansi_colors {
+            ansi::styled_lines(text, base)
+        } else {
+            ansi::strip(text).lines().map(|l| (l.to_string(), vec![base; l.chars().count()])).collect()
+        };
+        styled
+            .into_iter()
+            .take(shown)
+            .map(|(text, styles)| markdown::MdLine { lead: String::new(), hang: String::new(), text, styles })
+            .collect()
+    } else {
+        text.lines()
+            .take(shown)
+            .map(|l| markdown::MdLine {
+                lead: String::new(),
+                hang: String::new(),
+                text: l.to_string(),
+                styles: vec![base; l.chars().count()],
+            })
+            .collect()
+    };
+    match body {
+        Some(body) if collapsed => {
+            let summary = body.summary();
+            md_lines.push(markdown::MdLine {
+                lead: String::new(),
+                hang: String::new(),
+                styles: vec![Style::default().fg(Color::DarkGray); summary.chars().count()],
+                text: summary,
+            });
+        }
+        Some(body) => md_lines.extend(body.md_lines(msg, app.config.syntax_highlighting)),
+        None => {}
+    }
+    for md_line in md_lines {
+        let (marked, starts) = mark_terms(&md_line.text, terms);
+        let lead_width: usize = md_line.lead.chars().map(|c| c.width().unwrap_or(0)).sum();
+        let mut offset = 0;
+        let mut next_start = starts.iter().peekable();
+        // 幅0（未描画）は折り返さない
+        let wrap_width = if width == 0 { 0 } else { width.saturating_sub(lead_width).max(1) };
+        for (k, row) in wrap_text(&md_line.text, wrap_width).into_iter().enumerate() {
+            let len = row.chars().count();
+            while next_start.next_if(|&&s| s < offset + len).is_some() {
+                match_rows.push(lines.len());
+            }
+            let prefix = if k == 0 { &md_line.lead } else { &md_line.hang };
+            let mut spans = Vec::new();
+            if !prefix.is_empty() {
+                spans.push(Span::styled(prefix.clone(), markdown::lead_style()));
+            }
+            let marked = if starts.is_empty() { &[][..] } else { &marked[offset..offset + len] };
+            spans.extend(styled_spans(&row, &md_line.styles[offset..offset + len], marked));
+            lines.push(Line::from(spans));
+            offset += len;
+        }
+    }
+    if more_lines > 0 {
+        lines.push(Line::from(Span::styled(
+            format!("… {} more lines (press x to expand)", more_lines),
+            Style::default().fg(Color::Yellow),
+        )));
+    } else if hidden > 0 {
+        lines.push(Line::from(Span::styled(
+            format!("… truncated ({} more chars), press o for full", hidden),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    (lines, match_rows)
+}
+
+/// 各文字がどの検索語（色番号）に含まれるかと、出現開始位置（文字単位）。
+/// 重なった場合は後のクエリの色が優先される
+fn mark_terms(line: &str, terms: &[(Vec<char>, usize)]) -> (Vec<Option<usize>>, Vec<usize>) {
+    if terms.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let folded: Vec<char> = line.chars().map(fold_char).collect();
+    let mut marked = vec![None; folded.len()];
+    let mut starts = Vec::new();
+    for (term, color) in terms {
+        if term.is_empty() || term.len() > folded.len() {
+            continue;
+        }
+        for i in 0..=folded.len() - term.len() {
+            if folded[i..i + term.len()] == term[..] {
+                marked[i..i + term.len()].fill(Some(*color));
+                starts.push(i);
+            }
+        }
+    }
+    starts.sort_unstable();
+    (marked, starts)
+}
+
+/// 文字ごとのスタイルで行を Span に分ける。検索語の出現（`marked`、空なら無し）は色で上書きする
+fn styled_spans(row: &str, styles: &[Style], marked: &[Option<usize>]) -> Vec<Span<'static>> {
+    let style_at = |i: usize| match marked.get(i).copied().flatten() {
+        Some(k) => Style::default().bg(highlight_color(k)).fg(Color::Black),
+        None => styles[i],
+    };
+    let mut spans = Vec::new();
+    let mut current = String::new();
+    let mut current_style = None;
+    for (i, ch) in row.chars().enumerate() {
+        let style = style_at(i);
+        if current_style != Some(style) && !current.is_empty() {
+            spans.push(Span::styled(std::mem::take(&mut current), current_style.unwrap_or_default()));
+        }
+        current_style = Some(style);
+        current.push(ch);
+    }
+    if !current.is_empty() || spans.is_empty() {
+        spans.push(Span::styled(current, current_style.unwrap_or(styles.first().copied().unwrap_or_default())));
+    }
+    spans
+}
+
+/// `width` 桁ごとに折り返す。可能なら空白の直後で改行する（Paragraph の Wrap 相当）
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    if width == 0 {
+        return vec![text.to_string()];
+    }
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut row_width = 0;
+    // 行内の最後の空白の直後（バイト位置, そこまでの幅）
+    let mut last_break: Option<(usize, usize)> = None;
+    for ch in text.chars() {
+        let w = ch.width().unwrap_or(0);
+        while row_width + w > width && !row.is_empty() {
+            match last_break.take() {
+                Some((idx, break_width)) if idx < row.len() => {
+                    let rest = row.split_off(idx);
+                    rows.push(std::mem::replace(&mut row, rest));
+                    row_width -= break_width;
+                }
+                _ => {
+                    rows.push(std::mem::take(&mut row));
+                    row_width = 0;
+                }
+            }
+        }
+        row.push(ch);
+        row_width += w;
+        if ch.is_whitespace() {
+            last_break = Some((row.len(), row_width));
+        }
+    }
+    rows.push(row);
+    rows
+}
+
+fn draw_global_search(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let inner_chunks = Layout::vertical([
+        Constraint::Length(1), // search input
+        Constraint::Min(0),   // results
+    ])
+    .split(area);
+
+    // Search input
+    let search_line = Line::from(vec![
+        Span::styled(
+            " Search: ",
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(&app.global_search_query, Style::default().fg(Color::White)),
+        Span::styled("█", Style::default().fg(Color::Cyan)),
+    ]);
+    frame.render_widget(Paragraph::new(search_line), inner_chunks[0]);
+
+    // Results table
+    let header = Row::new(vec![
+        Cell::from("Time"),
+        Cell::from("Project"),
+        Cell::from("Branch"),
+        Cell::from("Prompt"),
+    ])
+    .style(
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    // borders(2) + header(1) = 3
+    let visible_height = (inner_chunks[1].height as usize).saturating_sub(3);
+
+    let rows: Vec<Row> = app
+        .global_search_filtered
+        .iter()
+        .enumerate()
+        .skip(app.global_search_list.offset)
+        .take(visible_height)
+        .flat_map(|(i, result)| {
+            let style = if i == app.global_search_list.selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else {
+                Style::default().fg(Color::White)
+            };
+
+            let time_str = format_relative_time(result.last_activity);
+
+            let project_short = result
+                .project_path
+                .rsplit('/')
+                .next()
+                .unwrap_or(&result.project_path);
+
+            let prompt = if result.best_match_prompt.is_empty() {
+                result.prompts.first().cloned().unwrap_or_default()
+            } else {
+                result.best_match_prompt.clone()
+            };
+            let prompt = prompt.replace('\n', " ");
+            let mut prompt_line = build_match_snippet(&prompt, &result.best_match_indices, 60);
+            if result.best_match_is_summary {
+                prompt_line.spans.insert(
+                    0,
+                    Span::styled("[summary] ", Style::default().fg(Color::Magenta)),
+                );
+            }
+            if let Some(folded) = app.global_search_resumes.get(&result.session_id) {
+                prompt_line.spans.insert(
+                    0,
+                    Span::styled(format!("[{} parts] ", folded + 1), Style::default().fg(Color::Cyan)),
+                );
+            }
+            if result.expired {
+                prompt_line.spans.insert(
+                    0,
+                    Span::styled("[expired] ", Style::default().fg(Color::DarkGray)),
+                );
+            }
+            if app.show_root_column && !result.source_root.is_empty() {
+                prompt_line.spans.insert(
+                    0,
+                    Span::styled(format!("[{}] ", result.source_root), Style::default().fg(Color::Blue)),
+                );
+            }
+            if !result.other_matches.is_empty() {
+                prompt_line.spans.push(Span::styled(
+                    format!(" +{} more", result.other_matches.len()),
+                    Style::default().fg(Color::Cyan),
+                ));
+            }
+
+            let mut rows = vec![
+                Row::new(vec![
+                    Cell::from(time_str),
+                    Cell::from(Span::styled(
+                        project_short.to_string(),
+                        Style::default().fg(project_accent(app, &result.project_path)),
+                    )),
+                    Cell::from(result.git_branch.clone()),
+                    Cell::from(prompt_line),
+                ])
+                .style(style),
+            ];
+
+            // 展開中なら他のマッチしたプロンプトを続けて表示
+            if app.global_search_expanded.as_deref() == Some(result.session_id.as_str()) {
+                for (other, indices) in &result.other_matches {
+                    let mut line = build_match_snippet(&other.replace('\n', " "), indices, 58);
+                    line.spans.insert(0, Span::styled("↳ ", Style::default().fg(Color::DarkGray)));
+                    rows.push(Row::new(vec![
+                        Cell::from(""),
+                        Cell::from(""),
+                        Cell::from(""),
+                        Cell::from(line),
+                    ]));
+                }
+            }
+            rows
+        })
+        .collect();
+
+    let title = format!(
+        " Global Search ({} results) ",
+        app.global_search_filtered.len()
+    );
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(12),
+            Constraint::Percentage(20),
+            Constraint::Percentage(18),
+            Constraint::Percentage(50),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    frame.render_widget(table, inner_chunks[1]);
 }
 
-fn draw_project_list(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+fn draw_permission_audit(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let inner_chunks = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+    ])
+    .split(area);
+
+    // Breadcrumb
+    let breadcrumb = Paragraph::new(Line::from(vec![Span::styled(
+        format!(" {}", app.audit_title),
+        Style::default().fg(Color::DarkGray),
+    )]));
+    frame.render_widget(breadcrumb, inner_chunks[0]);
+
     let header = Row::new(vec![
-        Cell::from("Project Path"),
-        Cell::from("Sessions"),
+        Cell::from("Timestamp"),
+        Cell::from("Session"),
+        Cell::from("Tool"),
+        Cell::from("Mode"),
+        Cell::from("Decision"),
+        Cell::from("Call"),
     ])
     .style(
         Style::default()
@@ -71,39 +2205,61 @@ fn draw_project_list(frame: &mut Frame, app: &App, area: ratatui::layout::Rect)
     );
 
     // borders(2) + header(1) = 3
-    let visible_height = (area.height as usize).saturating_sub(3);
+    let visible_height = (inner_chunks[1].height as usize).saturating_sub(3);
 
     let rows: Vec<Row> = app
-        .displayed_projects
+        .audit_entries
         .iter()
         .enumerate()
-        .skip(app.project_scroll_offset)
+        .skip(app.audit_list.offset)
         .take(visible_height)
-        .map(|(i, project)| {
-            let style = if i == app.selected_project {
-                Style::default()
-                    .bg(Color::DarkGray)
-                    .fg(Color::White)
+        .map(|(i, entry)| {
+            let style = if i == app.audit_list.selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
             } else {
                 Style::default().fg(Color::White)
             };
+            let decision_color = match entry.decision {
+                PermissionDecision::Approved => Color::Green,
+                PermissionDecision::AutoApproved => Color::Yellow,
+                PermissionDecision::Denied => Color::Red,
+                PermissionDecision::Pending => Color::DarkGray,
+            };
             Row::new(vec![
-                Cell::from(project.original_path.clone()),
-                Cell::from(project.session_count.to_string()),
+                Cell::from(entry.timestamp_str()),
+                Cell::from(entry.session_id[..entry.session_id.len().min(8)].to_string()),
+                Cell::from(entry.tool_name.clone()),
+                Cell::from(entry.permission_mode.clone()),
+                Cell::from(Span::styled(
+                    entry.decision.label(),
+                    Style::default().fg(decision_color),
+                )),
+                Cell::from(entry.summary.replace('\n', " ")),
             ])
             .style(style)
         })
         .collect();
 
-    let title = if app.search_query.is_empty() {
-        " Projects ".to_string()
-    } else {
-        format!(" Projects ({} matches) ", app.displayed_projects.len())
-    };
-
+    let denied = app
+        .audit_entries
+        .iter()
+        .filter(|e| e.decision == PermissionDecision::Denied)
+        .count();
+    let title = format!(
+        " Permission Audit ({} calls, {} denied) ",
+        app.audit_entries.len(),
+        denied
+    );
     let table = Table::new(
         rows,
-        [Constraint::Percentage(70), Constraint::Percentage(30)],
+        [
+            Constraint::Length(19),
+            Constraint::Length(8),
+            Constraint::Percentage(12),
+            Constraint::Percentage(14),
+            Constraint::Length(13),
+            Constraint::Min(0),
+        ],
     )
     .header(header)
     .block(
@@ -113,49 +2269,139 @@ fn draw_project_list(frame: &mut Frame, app: &App, area: ratatui::layout::Rect)
             .border_style(Style::default().fg(Color::Cyan)),
     );
 
-    frame.render_widget(table, area);
+    frame.render_widget(table, inner_chunks[1]);
 }
 
-fn draw_session_list(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+fn draw_session_changes(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
     let inner_chunks = Layout::vertical([
-        Constraint::Length(1),
         Constraint::Length(1),
         Constraint::Min(0),
     ])
     .split(area);
 
-    // Breadcrumb
+    let total_ops: usize = app.file_changes.iter().map(|c| c.operations).sum();
     let breadcrumb = Paragraph::new(Line::from(vec![Span::styled(
-        format!(" Project: {}", app.current_project_name),
+        format!(
+            " Session: {}  {} files, {} edits",
+            &app.current_session_id[..app.current_session_id.len().min(8)],
+            app.file_changes.len(),
+            total_ops
+        ),
         Style::default().fg(Color::DarkGray),
     )]));
     frame.render_widget(breadcrumb, inner_chunks[0]);
 
-    // Filter tabs
-    let filter_labels: Vec<String> = TimeFilter::all_filters()
+    let mut cache = app.changes_cache.borrow_mut();
+    if cache.is_empty() {
+        *cache = app
+            .file_changes
+            .iter()
+            .flat_map(|change| change_lines(change, app.config.syntax_highlighting))
+            .collect();
+    }
+    let visible_height = inner_chunks[1].height.saturating_sub(2) as usize;
+    let mut lines: Vec<Line> = cache
         .iter()
-        .map(|f| f.label().to_string())
+        .skip(app.changes_scroll_offset)
+        .take(visible_height)
+        .cloned()
         .collect();
-    let selected_index = TimeFilter::all_filters()
-        .iter()
-        .position(|f| *f == app.time_filter)
-        .unwrap_or(0);
-    let tabs = Tabs::new(filter_labels)
-        .select(selected_index)
-        .style(Style::default().fg(Color::DarkGray))
-        .highlight_style(
+    if cache.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No Write/Edit tool calls in this session.",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .title(" File Changes ")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+
+    frame.render_widget(paragraph, inner_chunks[1]);
+}
+
+/// 1ファイル分の見出しと差分の行。`highlight` なら本文をファイルの拡張子で色付けし、
+/// 行頭の +/- だけ差分の色で示す
+fn change_lines(change: &FileChange, highlight: bool) -> Vec<Line<'static>> {
+    let mut header = vec![
+        Span::styled(
+            change.path.clone(),
             Style::default()
                 .fg(Color::Cyan)
                 .add_modifier(Modifier::BOLD),
-        );
-    frame.render_widget(tabs, inner_chunks[1]);
+        ),
+        Span::styled(
+            format!(
+                "  {} ({} ops)",
+                if change.created { "created" } else { "modified" },
+                change.operations
+            ),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ];
+    if !change.exact {
+        header.push(Span::styled(
+            "  [fragments: file changed since]",
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    let mut lines = vec![Line::from(header)];
+
+    let mut highlighter = if highlight { Highlighter::for_path(&change.path) } else { None };
+    for diff_line in change.diff.lines() {
+        let styles = diff_line_styles(diff_line, highlighter.as_mut());
+        lines.push(Line::from(styled_spans(diff_line, &styles, &[])));
+    }
+    lines.push(Line::from(""));
+    lines
+}
+
+/// 差分の1行の文字ごとのスタイル。+ は緑、- は赤、@@ は水色で、ハイライタがあれば記号の後ろのコードを色付けする
+fn diff_line_styles(diff_line: &str, highlighter: Option<&mut Highlighter>) -> Vec<Style> {
+    let color = if diff_line.starts_with("@@") {
+        Color::Cyan
+    } else if diff_line.starts_with('+') {
+        Color::Green
+    } else if diff_line.starts_with('-') {
+        Color::Red
+    } else {
+        Color::White
+    };
+    let marker_len = diff_line.chars().next().map_or(0, char::len_utf8);
+    match highlighter {
+        Some(hl) if !diff_line.starts_with("@@") && !diff_line.starts_with('\\') && marker_len > 0 => {
+            let mut styles = vec![Style::default().fg(color)];
+            styles.extend(hl.line(&diff_line[marker_len..], Style::default().fg(Color::White)));
+            styles
+        }
+        _ => vec![Style::default().fg(color); diff_line.chars().count()],
+    }
+}
+
+fn draw_file_snapshots(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let inner_chunks = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+    ])
+    .split(area);
+
+    let breadcrumb = Paragraph::new(Line::from(vec![Span::styled(
+        format!(
+            " Session: {}",
+            &app.current_session_id[..app.current_session_id.len().min(8)]
+        ),
+        Style::default().fg(Color::DarkGray),
+    )]));
+    frame.render_widget(breadcrumb, inner_chunks[0]);
 
-    // Session table
     let header = Row::new(vec![
-        Cell::from("Timestamp"),
-        Cell::from("Msgs"),
-        Cell::from("Branch"),
-        Cell::from("Preview"),
+        Cell::from("Time"),
+        Cell::from("File"),
+        Cell::from("Version"),
+        Cell::from("Backup"),
     ])
     .style(
         Style::default()
@@ -164,52 +2410,42 @@ fn draw_session_list(frame: &mut Frame, app: &App, area: ratatui::layout::Rect)
     );
 
     // borders(2) + header(1) = 3
-    let visible_height = (inner_chunks[2].height as usize).saturating_sub(3);
+    let visible_height = (inner_chunks[1].height as usize).saturating_sub(3);
 
     let rows: Vec<Row> = app
-        .filtered_sessions
+        .snapshots
         .iter()
         .enumerate()
-        .skip(app.session_scroll_offset)
+        .skip(app.snapshot_list.offset)
         .take(visible_height)
-        .map(|(i, session)| {
-            let style = if i == app.selected_session {
-                Style::default()
-                    .bg(Color::DarkGray)
-                    .fg(Color::White)
+        .map(|(i, snapshot)| {
+            let style = if i == app.snapshot_list.selected {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
             } else {
                 Style::default().fg(Color::White)
             };
-            let preview = if session.preview.chars().count() > 80 {
-                let truncated: String = session.preview.chars().take(80).collect();
-                format!("{}...", truncated)
-            } else {
-                session.preview.clone()
-            }
-            .replace('\n', " ");
+            let backup = match &snapshot.backup_file {
+                Some(name) => Span::raw(name.clone()),
+                None => Span::styled("(not yet created)", Style::default().fg(Color::DarkGray)),
+            };
             Row::new(vec![
-                Cell::from(session.timestamp_str()),
-                Cell::from(session.message_count.to_string()),
-                Cell::from(session.git_branch.clone()),
-                Cell::from(preview),
+                Cell::from(snapshot.timestamp_str()),
+                Cell::from(snapshot.path.clone()),
+                Cell::from(format!("v{}", snapshot.version)),
+                Cell::from(backup),
             ])
             .style(style)
         })
         .collect();
 
-    let title = if app.search_query.is_empty() {
-        " Sessions ".to_string()
-    } else {
-        format!(" Sessions ({} matches) ", app.filtered_sessions.len())
-    };
-
+    let title = format!(" File Snapshots ({}) ", app.snapshots.len());
     let table = Table::new(
         rows,
         [
-            Constraint::Percentage(20),
-            Constraint::Percentage(8),
-            Constraint::Percentage(20),
-            Constraint::Percentage(52),
+            Constraint::Length(19),
+            Constraint::Min(0),
+            Constraint::Length(8),
+            Constraint::Percentage(25),
         ],
     )
     .header(header)
@@ -220,76 +2456,48 @@ fn draw_session_list(frame: &mut Frame, app: &App, area: ratatui::layout::Rect)
             .border_style(Style::default().fg(Color::Cyan)),
     );
 
-    frame.render_widget(table, inner_chunks[2]);
+    frame.render_widget(table, inner_chunks[1]);
 }
 
-fn draw_session_detail(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+fn draw_snapshot_view(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
     let inner_chunks = Layout::vertical([
         Constraint::Length(1),
         Constraint::Min(0),
     ])
     .split(area);
 
-    // Breadcrumb
-    let session_id_short = app
-        .filtered_sessions
-        .get(app.selected_session)
-        .map(|s| &s.session_id[..s.session_id.len().min(8)])
-        .unwrap_or("unknown");
+    let (path, when) = app
+        .snapshots
+        .get(app.snapshot_list.selected)
+        .map(|s| (s.path.clone(), format!("v{}  {}", s.version, s.timestamp_str())))
+        .unwrap_or_default();
     let breadcrumb = Paragraph::new(Line::from(vec![Span::styled(
-        format!(" Session: {}", session_id_short),
+        format!(" {}  {}", path, when),
         Style::default().fg(Color::DarkGray),
     )]));
     frame.render_widget(breadcrumb, inner_chunks[0]);
 
-    // Messages
-    let mut lines: Vec<Line> = Vec::new();
-
-    for (i, msg) in app.messages.iter().enumerate() {
-        if i > 0 {
-            lines.push(Line::from(""));
-        }
-
-        let role_color = match msg.role {
-            MessageRole::User => Color::Cyan,
-            MessageRole::Assistant => Color::Green,
-            MessageRole::System => Color::Yellow,
-            MessageRole::ToolUse => Color::Yellow,
-            MessageRole::ToolResult => Color::Magenta,
-            MessageRole::Progress => Color::DarkGray,
-        };
-
-        let ts = msg.timestamp_str();
-        let mut header_spans = vec![Span::styled(
-            msg.role_label(),
-            Style::default()
-                .fg(role_color)
-                .add_modifier(Modifier::BOLD),
-        )];
-        if !ts.is_empty() {
-            header_spans.push(Span::raw(" "));
-            header_spans.push(Span::styled(ts, Style::default().fg(Color::DarkGray)));
-        }
-        lines.push(Line::from(header_spans));
-
-        let text_color = match msg.role {
-            MessageRole::ToolUse | MessageRole::ToolResult => Color::DarkGray,
-            _ => Color::White,
-        };
-
-        for text_line in msg.text.lines() {
-            lines.push(Line::from(Span::styled(
-                text_line.to_string(),
-                Style::default().fg(text_color),
-            )));
-        }
-    }
+    let width = app.snapshot_content.lines().count().to_string().len();
+    let lines: Vec<Line> = app
+        .snapshot_content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:>width$} ", i + 1, width = width),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(line.to_string()),
+            ])
+        })
+        .collect();
 
     let paragraph = Paragraph::new(lines)
-        .scroll((app.scroll_offset as u16, 0))
-        .wrap(Wrap { trim: false })
+        .scroll((app.snapshot_content_scroll as u16, 0))
         .block(
             Block::default()
+                .title(" Snapshot ")
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Cyan)),
         );
@@ -297,32 +2505,22 @@ fn draw_session_detail(frame: &mut Frame, app: &App, area: ratatui::layout::Rect
     frame.render_widget(paragraph, inner_chunks[1]);
 }
 
-fn draw_global_search(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
-    let inner_chunks = Layout::vertical([
-        Constraint::Length(1), // search input
-        Constraint::Min(0),   // results
-    ])
-    .split(area);
-
-    // Search input
-    let search_line = Line::from(vec![
-        Span::styled(
-            " Search: ",
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
-        ),
-        Span::styled(&app.global_search_query, Style::default().fg(Color::White)),
-        Span::styled("█", Style::default().fg(Color::Cyan)),
-    ]);
-    frame.render_widget(Paragraph::new(search_line), inner_chunks[0]);
-
-    // Results table
+/// 索引の変更履歴: いつ、どのセッションが追加・更新・期限切れになったか
+fn draw_index_activity(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let area = if app.retention_journal.is_empty() {
+        area
+    } else {
+        let chunks =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(app.journal_panel_rows() as u16)]).split(area);
+        draw_retention_journal(frame, app, chunks[1]);
+        chunks[0]
+    };
     let header = Row::new(vec![
         Cell::from("Time"),
+        Cell::from("Age"),
+        Cell::from("Change"),
+        Cell::from("Session"),
         Cell::from("Project"),
-        Cell::from("Branch"),
-        Cell::from("Prompt"),
     ])
     .style(
         Style::default()
@@ -331,58 +2529,56 @@ fn draw_global_search(frame: &mut Frame, app: &App, area: ratatui::layout::Rect)
     );
 
     // borders(2) + header(1) = 3
-    let visible_height = (inner_chunks[1].height as usize).saturating_sub(3);
+    let visible_height = (area.height as usize).saturating_sub(3);
+    let now = chrono::Utc::now().timestamp();
 
     let rows: Vec<Row> = app
-        .global_search_filtered
+        .index_changes
         .iter()
         .enumerate()
-        .skip(app.global_search_scroll_offset)
+        .skip(app.index_changes_list.offset)
         .take(visible_height)
-        .map(|(i, result)| {
-            let style = if i == app.global_search_selected {
+        .map(|(i, change)| {
+            let style = if i == app.index_changes_list.selected {
                 Style::default().bg(Color::DarkGray).fg(Color::White)
             } else {
                 Style::default().fg(Color::White)
             };
-
-            let time_str = format_relative_time(&result.created_at);
-
-            let project_short = result
-                .project_path
-                .rsplit('/')
-                .next()
-                .unwrap_or(&result.project_path);
-
-            let prompt = if result.best_match_prompt.is_empty() {
-                result.prompts.first().cloned().unwrap_or_default()
-            } else {
-                result.best_match_prompt.clone()
+            let kind_color = match change.kind {
+                ChangeKind::Added => Color::Green,
+                ChangeKind::Updated => Color::Yellow,
+                ChangeKind::Expired => Color::Red,
+                ChangeKind::Deleted => Color::Magenta,
             };
-            let prompt = prompt.replace('\n', " ");
-            let prompt_line = build_match_snippet(&prompt, &result.best_match_indices, 60);
-
+            let time = chrono::DateTime::from_timestamp(change.at, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            let project = app
+                .projects
+                .iter()
+                .find(|p| p.dir_name == change.dir_name)
+                .map(|p| p.original_path.clone())
+                .unwrap_or_else(|| parser::decode_project_path(&change.dir_name));
             Row::new(vec![
-                Cell::from(time_str),
-                Cell::from(project_short.to_string()),
-                Cell::from(result.git_branch.clone()),
-                Cell::from(prompt_line),
+                Cell::from(time),
+                Cell::from(Span::styled(format_age(now - change.at), Style::default().fg(Color::DarkGray))),
+                Cell::from(Span::styled(change.kind.label(), Style::default().fg(kind_color))),
+                Cell::from(change.session_id[..change.session_id.len().min(8)].to_string()),
+                Cell::from(project),
             ])
             .style(style)
         })
         .collect();
 
-    let title = format!(
-        " Global Search ({} results) ",
-        app.global_search_filtered.len()
-    );
+    let title = format!(" Index Activity ({}) ", app.index_changes.len());
     let table = Table::new(
         rows,
         [
-            Constraint::Percentage(12),
-            Constraint::Percentage(20),
-            Constraint::Percentage(18),
-            Constraint::Percentage(50),
+            Constraint::Length(19),
+            Constraint::Length(9),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Min(0),
         ],
     )
     .header(header)
@@ -393,7 +2589,99 @@ fn draw_global_search(frame: &mut Frame, app: &App, area: ratatui::layout::Rect)
             .border_style(Style::default().fg(Color::Cyan)),
     );
 
-    frame.render_widget(table, inner_chunks[1]);
+    frame.render_widget(table, area);
+}
+
+/// apply-retention が移したセッション（と移せなかったもの）の最新の記録
+fn draw_retention_journal(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let header = Row::new(vec![Cell::from("Time"), Cell::from("Action"), Cell::from("Session"), Cell::from("Detail")])
+        .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD));
+    let rows: Vec<Row> = app
+        .retention_journal
+        .iter()
+        .take(area.height.saturating_sub(3) as usize)
+        .map(|entry| {
+            let time = chrono::DateTime::from_timestamp(entry.at, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            let color = if entry.action == "failed" { Color::Red } else { Color::Blue };
+            Row::new(vec![
+                Cell::from(time),
+                Cell::from(Span::styled(entry.action.clone(), Style::default().fg(color))),
+                Cell::from(entry.session_id[..entry.session_id.len().min(8)].to_string()),
+                Cell::from(Span::styled(entry.detail.clone(), Style::default().fg(Color::DarkGray))),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [Constraint::Length(19), Constraint::Length(8), Constraint::Length(8), Constraint::Min(0)],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(format!(" Retention Journal ({}) ", app.retention_journal.len()))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::DarkGray)),
+    );
+    frame.render_widget(table, area);
+}
+
+/// 削除候補のセッション1件: 何のセッションか、なぜ候補か、会話のプレビュー
+fn draw_cleanup_review(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let inner_chunks = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Min(0),
+    ])
+    .split(area);
+    let Some(candidate) = app.cleanup_review.current() else {
+        return;
+    };
+
+    let session = &candidate.session;
+    let mut about = vec![
+        Span::styled(
+            format!(" {}", &session.session_id[..session.session_id.len().min(8)]),
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(format!("  {}  {} messages", session.timestamp_str(), session.message_count)),
+    ];
+    if !session.git_branch.is_empty() {
+        about.push(Span::styled(format!("  {}", session.git_branch), Style::default().fg(Color::DarkGray)));
+    }
+    let info = vec![
+        Line::from(about),
+        Line::from(Span::styled(format!(" Candidate: {}", candidate.reason), Style::default().fg(Color::Yellow))),
+    ];
+    frame.render_widget(Paragraph::new(info), inner_chunks[0]);
+
+    let mut lines = Vec::new();
+    for msg in &app.cleanup_preview {
+        let mut header = vec![Span::styled(
+            msg.role_label(),
+            Style::default().fg(role_color(&msg.role)).add_modifier(Modifier::BOLD),
+        )];
+        let ts = msg.timestamp_str();
+        if !ts.is_empty() {
+            header.push(Span::styled(format!(" {}", ts), Style::default().fg(Color::DarkGray)));
+        }
+        lines.push(Line::from(header));
+        lines.extend(msg.text.lines().map(|l| Line::from(l.to_string())));
+        lines.push(Line::from(""));
+    }
+    if lines.is_empty() {
+        lines.push(Line::from(Span::styled("(no messages)", Style::default().fg(Color::DarkGray))));
+    }
+
+    let paragraph = Paragraph::new(lines)
+        .scroll((app.cleanup_scroll as u16, 0))
+        .block(
+            Block::default()
+                .title(format!(" Review {}/{} ", app.cleanup_review.done() + 1, app.cleanup_review.total))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan)),
+        );
+    frame.render_widget(paragraph, inner_chunks[1]);
 }
 
 fn build_match_snippet<'a>(prompt: &str, indices: &[usize], max_width: usize) -> Line<'a> {
@@ -430,7 +2718,7 @@ fn build_match_snippet<'a>(prompt: &str, indices: &[usize], max_width: usize) ->
     let mut current_text = String::new();
     let mut current_is_match = false;
 
-    for i in start..end {
+    for (i, &ch) in chars.iter().enumerate().take(end).skip(start) {
         let is_match = match_set.contains(&i);
         if is_match != current_is_match && !current_text.is_empty() {
             let style = if current_is_match {
@@ -442,7 +2730,7 @@ fn build_match_snippet<'a>(prompt: &str, indices: &[usize], max_width: usize) ->
             };
             spans.push(Span::styled(std::mem::take(&mut current_text), style));
         }
-        current_text.push(chars[i]);
+        current_text.push(ch);
         current_is_match = is_match;
     }
 
@@ -464,11 +2752,11 @@ fn build_match_snippet<'a>(prompt: &str, indices: &[usize], max_width: usize) ->
     Line::from(spans)
 }
 
-pub fn format_relative_time(iso: &str) -> String {
+pub fn format_relative_time(secs: i64) -> String {
     use chrono::{DateTime, Utc};
-    let dt: DateTime<Utc> = match iso.parse() {
-        Ok(d) => d,
-        Err(_) => return iso.to_string(),
+    let dt: DateTime<Utc> = match DateTime::from_timestamp(secs, 0) {
+        Some(d) if secs > 0 => d,
+        _ => return String::new(),
     };
     let now = Utc::now();
     let dur = now.signed_duration_since(dt);
@@ -79,3 +79,29 @@ fn incremental_index_update() {
     let index = cc_sessions_viewer::index::SessionIndex::open(&db_path).unwrap();
     assert_eq!(index.search_all().unwrap().len(), 2);
 }
+
+#[test]
+fn index_synthetic_corpus() {
+    use cc_sessions_viewer::corpus::{self, CorpusSpec};
+
+    let tmp = TempDir::new().unwrap();
+    let projects_dir = tmp.path().join("projects");
+    let db_path = tmp.path().join("index.db");
+    let spec = CorpusSpec::parse("3x4x12", 7).unwrap();
+    corpus::generate(&projects_dir, &spec).unwrap();
+
+    let progress = cc_sessions_viewer::indexer::build_index(&db_path, &projects_dir).unwrap();
+    assert_eq!(progress.sessions_added, spec.total_sessions());
+    assert_eq!(progress.bad_lines, 0);
+
+    let index = cc_sessions_viewer::index::SessionIndex::open(&db_path).unwrap();
+    let sessions = index.search_all().unwrap();
+    assert_eq!(sessions.len(), 12);
+    // 4件ごとに1件のユーザープロンプト
+    assert!(sessions.iter().all(|s| s.prompts.len() == 3));
+    assert_eq!(index.search_project(&corpus::project_dir_name(1)).unwrap().len(), 4);
+
+    // 何も変わっていなければ再索引で解析し直さない
+    let again = cc_sessions_viewer::indexer::build_index(&db_path, &projects_dir).unwrap();
+    assert_eq!(again.sessions_updated, 0);
+}
@@ -0,0 +1,289 @@
+//! Drives the whole TUI with scripted key sequences against fixture
+//! transcripts under a throwaway home directory, and checks what is on the
+//! screen after each step.
+
+use cc_sessions_viewer::app::{self, App, Screen};
+use cc_sessions_viewer::ui;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{Terminal, backend::TestBackend};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+const WIDTH: u16 = 120;
+const HEIGHT: u16 = 30;
+
+/// A home directory with two projects, shared by every test in this file
+/// since the viewer reads `~/.claude` once per process.
+fn fixture_home() -> &'static PathBuf {
+    static HOME: OnceLock<PathBuf> = OnceLock::new();
+    HOME.get_or_init(|| {
+        let home = std::env::temp_dir().join(format!("cc-sessions-viewer-tui-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+        let projects = home.join(".claude").join("projects");
+
+        let auth = projects.join("-home-me-auth");
+        fs::create_dir_all(&auth).unwrap();
+        fs::write(
+            auth.join("sess-login.jsonl"),
+            r#"{"type":"user","uuid":"u1","timestamp":"2026-01-15T10:00:00Z","cwd":"/home/me/auth","gitBranch":"feat/login","message":{"role":"user","content":"fix the login redirect bug"}}
+{"type":"assistant","uuid":"a1","parentUuid":"u1","timestamp":"2026-01-15T10:00:05Z","cwd":"/home/me/auth","message":{"role":"assistant","model":"claude-sonnet-4-5","content":[{"type":"text","text":"Let me look at the handler."},{"type":"tool_use","id":"t1","name":"Read","input":{"file_path":"src/login.rs"}}]}}
+{"type":"user","uuid":"r1","parentUuid":"a1","timestamp":"2026-01-15T10:00:06Z","cwd":"/home/me/auth","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"fn redirect() {}"}]}}
+{"type":"assistant","uuid":"a2","parentUuid":"r1","timestamp":"2026-01-15T10:00:20Z","cwd":"/home/me/auth","message":{"role":"assistant","model":"claude-sonnet-4-5","content":[{"type":"text","text":"The redirect drops the `next` parameter; fixed."}]}}
+{"type":"user","uuid":"u2","parentUuid":"a2","timestamp":"2026-01-15T10:05:00Z","cwd":"/home/me/auth","message":{"role":"user","content":"now add a regression test"}}
+{"type":"assistant","uuid":"a3","parentUuid":"u2","timestamp":"2026-01-15T10:05:30Z","cwd":"/home/me/auth","message":{"role":"assistant","model":"claude-sonnet-4-5","content":[{"type":"text","text":"Added `redirect_keeps_next`."}]}}
+"#,
+        )
+        .unwrap();
+        fs::write(
+            auth.join("sess-jwt.jsonl"),
+            r#"{"type":"user","uuid":"u1","timestamp":"2026-01-14T09:00:00Z","cwd":"/home/me/auth","message":{"role":"user","content":"rotate the JWT signing key"}}
+{"type":"assistant","uuid":"a1","parentUuid":"u1","timestamp":"2026-01-14T09:00:10Z","cwd":"/home/me/auth","message":{"role":"assistant","content":[{"type":"text","text":"Rotated."}]}}
+"#,
+        )
+        .unwrap();
+
+        let deploy = projects.join("-home-me-deploy");
+        fs::create_dir_all(&deploy).unwrap();
+        fs::write(
+            deploy.join("sess-kamal.jsonl"),
+            r#"{"type":"user","uuid":"u1","timestamp":"2026-01-13T08:00:00Z","cwd":"/home/me/deploy","message":{"role":"user","content":"set up Kamal deployment"}}
+{"type":"assistant","uuid":"a1","parentUuid":"u1","timestamp":"2026-01-13T08:01:00Z","cwd":"/home/me/deploy","message":{"role":"assistant","content":[{"type":"text","text":"Wrote config/deploy.yml."}]}}
+"#,
+        )
+        .unwrap();
+
+        // アーカイブのテスト専用（ほかのテストと同じファイルを動かさない）
+        let notes = projects.join("-home-me-notes");
+        fs::create_dir_all(&notes).unwrap();
+        for (id, day, prompt) in [("sess-draft", 11, "draft the release notes"), ("sess-outline", 10, "outline the changelog")] {
+            fs::write(
+                notes.join(format!("{}.jsonl", id)),
+                format!(
+                    r#"{{"type":"user","uuid":"u1","timestamp":"2026-01-{}T08:00:00Z","cwd":"/home/me/notes","message":{{"role":"user","content":"{}"}}}}
+"#,
+                    day, prompt
+                ),
+            )
+            .unwrap();
+        }
+
+        // SAFETY: set once, before any test reads these, while the other
+        // test threads wait on this OnceLock
+        unsafe {
+            std::env::set_var("HOME", &home);
+            std::env::set_var("XDG_CONFIG_HOME", home.join(".config"));
+            std::env::set_var("XDG_CACHE_HOME", home.join(".cache"));
+            std::env::set_var("XDG_DATA_HOME", home.join(".local/share"));
+        }
+        cc_sessions_viewer::indexer::build_default_index().unwrap();
+        home
+    })
+}
+
+/// The app on a fake terminal: `keys` feeds it input, `screen` draws it.
+struct Harness {
+    app: App,
+    terminal: Terminal<TestBackend>,
+}
+
+impl Harness {
+    fn new() -> Harness {
+        fixture_home();
+        let mut harness = Harness { app: App::new(), terminal: Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap() };
+        harness.screen();
+        harness
+    }
+
+    /// Press keys written like `jj<Enter>/login<Esc><C-n>`: each character is
+    /// a key, `<...>` names a special key or a Ctrl chord. The screen is
+    /// redrawn after every key, as the event loop does.
+    fn keys(&mut self, script: &str) -> &mut Harness {
+        let mut chars = script.chars();
+        while let Some(c) = chars.next() {
+            let key = if c == '<' {
+                let name: String = chars.by_ref().take_while(|&c| c != '>').collect();
+                named_key(&name)
+            } else {
+                KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+            };
+            app::handle_key(&mut self.app, key);
+            self.screen();
+        }
+        self
+    }
+
+    /// Draw the app and return the screen, one string per row.
+    fn screen(&mut self) -> Vec<String> {
+        let app = &mut self.app;
+        self.terminal
+            .draw(|frame| {
+                app.terminal_height = frame.area().height as usize;
+                ui::draw(frame, app);
+            })
+            .unwrap();
+        let buffer = self.terminal.backend().buffer();
+        (0..HEIGHT).map(|y| (0..WIDTH).map(|x| buffer[(x, y)].symbol()).collect()).collect()
+    }
+
+    fn shows(&mut self, text: &str) -> bool {
+        self.screen().iter().any(|row| row.contains(text))
+    }
+
+    /// Let the background re-index started by some screens finish.
+    fn wait_for_indexing(&mut self) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.app.index_rx.is_some() && Instant::now() < deadline {
+            self.app.poll_indexing();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(self.app.index_rx.is_none(), "indexing did not finish");
+    }
+}
+
+fn named_key(name: &str) -> KeyEvent {
+    let code = match name {
+        "Enter" => KeyCode::Enter,
+        "Esc" => KeyCode::Esc,
+        "Tab" => KeyCode::Tab,
+        "BS" => KeyCode::Backspace,
+        "Down" => KeyCode::Down,
+        "Up" => KeyCode::Up,
+        "Space" => KeyCode::Char(' '),
+        _ => match name.strip_prefix("C-").and_then(|c| c.chars().next()) {
+            Some(c) => return KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL),
+            None => panic!("unknown key <{}>", name),
+        },
+    };
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+#[test]
+fn project_to_session_detail_and_back() {
+    let mut h = Harness::new();
+    assert_eq!(h.app.screen, Screen::ProjectList);
+    assert!(h.shows("/home/me/auth"));
+    assert!(h.shows("/home/me/deploy"));
+
+    h.keys("<Enter>");
+    assert_eq!(h.app.screen, Screen::SessionList);
+    assert!(h.shows("fix the login redirect bug"));
+    assert!(h.shows("rotate the JWT signing key"));
+
+    // 新しい順なので先頭はログインのセッション
+    h.keys("<Enter>");
+    assert_eq!(h.app.screen, Screen::SessionDetail);
+    assert!(h.shows("fix the login redirect bug"));
+    assert!(h.shows("The redirect drops the next parameter; fixed."));
+    assert!(h.shows(" message 1/"));
+
+    // 次のプロンプトへ飛び、ツールを隠す
+    h.keys("]");
+    assert_eq!(h.app.messages[h.app.selected_message].text, "now add a regression test");
+    h.keys("t");
+    assert!(h.shows("[hidden: tool calls, tool results]"));
+
+    // 一覧を経由せずに次のセッションへ
+    h.keys("<C-n>");
+    assert!(h.shows("rotate the JWT signing key"));
+    assert_eq!(h.app.current_session_id, "sess-jwt");
+
+    h.keys("<Esc>");
+    assert_eq!(h.app.screen, Screen::SessionList);
+    h.keys("<Esc>");
+    assert_eq!(h.app.screen, Screen::ProjectList);
+    h.keys("q");
+    assert!(h.app.should_quit);
+}
+
+#[test]
+fn filter_lists_with_slash() {
+    let mut h = Harness::new();
+    h.keys("/deploy<Enter>");
+    assert!(h.shows("/home/me/deploy"));
+    assert!(!h.shows("/home/me/auth"));
+
+    h.keys("<Enter>");
+    assert!(h.shows("set up Kamal deployment"));
+    h.keys("<Enter>");
+    assert!(h.shows("Wrote config/deploy.yml."));
+}
+
+#[test]
+fn global_search_opens_the_matching_session() {
+    let mut h = Harness::new();
+    h.keys("s");
+    h.wait_for_indexing();
+    assert_eq!(h.app.screen, Screen::GlobalSearch);
+
+    // j/k/d/u/g は一覧の移動なので、それを含まない語で探す
+    h.keys("rotate");
+    assert_eq!(h.app.global_search_filtered.len(), 1);
+    assert!(h.shows("rotate the JWT signing key"));
+    assert!(!h.shows("Kamal"));
+
+    h.keys("<Enter>");
+    assert_eq!(h.app.screen, Screen::SessionDetail);
+    assert_eq!(h.app.current_session_id, "sess-jwt");
+    assert!(h.shows("\"rotate\""));
+
+    // 詳細からは一覧を経てプロジェクト一覧へ戻る
+    h.keys("<Esc>");
+    assert_eq!(h.app.screen, Screen::SessionList);
+    h.keys("<Esc>");
+    assert_eq!(h.app.screen, Screen::ProjectList);
+}
+
+#[test]
+fn overlays_take_the_keys_until_closed() {
+    let mut h = Harness::new();
+    h.keys("<Enter><Enter>T");
+    assert!(h.app.prompt_toc.is_some());
+    // 一覧が開いている間の j は一覧の中を動く
+    h.keys("j<Enter>");
+    assert!(h.app.prompt_toc.is_none());
+    assert_eq!(h.app.messages[h.app.selected_message].text, "now add a regression test");
+
+    h.keys("?");
+    assert!(h.shows("?: Position  Esc: Back"));
+    h.keys("?");
+    assert!(h.shows(" message "));
+}
+
+#[test]
+fn archived_sessions_move_out_of_the_list_and_back() {
+    let mut h = Harness::new();
+    h.keys("/notes<Enter><Enter>");
+    assert!(h.shows("draft the release notes"));
+
+    h.keys("Z");
+    assert!(h.shows("Archived 1 sessions"));
+    assert!(!h.shows("draft the release notes"));
+    assert!(h.shows("outline the changelog"));
+    assert!(fixture_home().join(".claude/projects/-home-me-notes/archive/sess-draft.jsonl").exists());
+
+    // アーカイブの一覧から開いて、元に戻す
+    h.keys("A");
+    assert!(h.shows("[archived]"));
+    assert!(!h.shows("outline the changelog"));
+    h.keys("<Enter>");
+    assert_eq!(h.app.screen, Screen::SessionDetail);
+    assert!(h.shows("draft the release notes"));
+
+    // 索引し直しても期限切れにならず、全体検索からも開ける
+    h.keys("<Esc><Esc>s");
+    h.wait_for_indexing();
+    h.keys("draft");
+    assert_eq!(h.app.global_search_filtered.len(), 1);
+    assert!(!h.app.global_search_filtered[0].expired);
+    h.keys("<Enter>");
+    assert_eq!(h.app.screen, Screen::SessionDetail);
+    assert!(h.shows("draft the release notes"));
+
+    h.keys("<Esc><Esc>/notes<Enter><Enter>A<Enter><Esc>Z");
+    assert!(h.shows("Restored 1 sessions"));
+    h.keys("A");
+    assert!(h.shows("draft the release notes"));
+    assert!(h.shows("outline the changelog"));
+}